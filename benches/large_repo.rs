@@ -0,0 +1,191 @@
+//! Benchmarks of the operations that get slow in large repositories: status,
+//! loading pages of the log and generating diffs. They run against a
+//! repository generated in the temp directory on first use, so the numbers
+//! are comparable between machines and runs.
+//!
+//!     cargo bench --features bench
+//!
+//! oaf is a binary, so the modules being measured are compiled in directly.
+
+#[macro_use]
+extern crate criterion;
+#[macro_use]
+extern crate log;
+extern crate chrono;
+extern crate git2;
+#[macro_use]
+extern crate lazy_static;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+#[macro_use]
+#[allow(dead_code, unused_imports)]
+#[path = "../src/timer.rs"]
+mod timer;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/trace.rs"]
+mod trace;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/history.rs"]
+mod history;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/mailmap.rs"]
+mod mailmap;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/patch.rs"]
+mod patch;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/status.rs"]
+mod status;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use criterion::Criterion;
+use git2::{Oid, Repository, Signature, Time};
+use history::{LogFilter, LogSort};
+use mailmap::Mailmap;
+use patch::{DiffLimits, PatchStream};
+
+/// The size of the generated repository.
+const FILES: usize = 20_000;
+const COMMITS: usize = 5_000;
+/// How many files each of the generated commits changes.
+const FILES_PER_COMMIT: usize = 10;
+/// How many files are left modified, and how many untracked, for status.
+const MODIFIED: usize = 200;
+const UNTRACKED: usize = 200;
+/// The page size the log view loads.
+const PAGE: usize = 500;
+
+fn file_name(n: usize) -> String {
+    format!("dir{:03}/file{:05}.txt", n % 500, n)
+}
+
+fn file_content(n: usize, version: usize) -> String {
+    (0..20).map(|line| format!("file {} line {} version {}\n", n, line, version)).collect()
+}
+
+/// Opens the benchmark repository, generating it first if it does not exist.
+/// It is kept between runs because generating it takes far longer than the
+/// benchmarks; delete the directory to start again.
+fn large_repo() -> Repository {
+    let dir = env::temp_dir().join(format!("oaf-bench-{}-files-{}-commits", FILES, COMMITS));
+    if let Ok(repo) = Repository::open(&dir) {
+        return repo;
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+    generate(&dir);
+    Repository::open(&dir).unwrap()
+}
+
+fn generate(dir: &Path) {
+    println!("Generating a repository with {} files and {} commits in {}", FILES, COMMITS, dir.display());
+    let repo = Repository::init(dir).unwrap();
+    let write = |n: usize, version: usize| {
+        let path = dir.join(file_name(n));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, file_content(n, version)).unwrap();
+        PathBuf::from(file_name(n))
+    };
+
+    let mut index = repo.index().unwrap();
+    for n in 0..FILES {
+        index.add_path(&write(n, 0)).unwrap();
+    }
+
+    let mut parent: Option<Oid> = None;
+    for c in 0..COMMITS {
+        if c > 0 {
+            for i in 0..FILES_PER_COMMIT {
+                let n = (c * FILES_PER_COMMIT + i) * 7919 % FILES;
+                index.add_path(&write(n, c)).unwrap();
+            }
+        }
+
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::new("Bench", "bench@example.com", &Time::new(1_500_000_000 + 60 * c as i64, 0)).unwrap();
+        let parents: Vec<_> = parent.iter().map(|&p| repo.find_commit(p).unwrap()).collect();
+        let parents: Vec<_> = parents.iter().collect();
+        parent = Some(repo.commit(Some("HEAD"), &signature, &signature, &format!("Commit {}", c), &tree, &parents).unwrap());
+    }
+    index.write().unwrap();
+
+    for n in 0..MODIFIED {
+        write(n * 97 % FILES, COMMITS);
+    }
+    for n in 0..UNTRACKED {
+        fs::write(dir.join(format!("untracked{:04}.txt", n)), "untracked\n").unwrap();
+    }
+}
+
+fn bench_status(c: &mut Criterion) {
+    let repo = large_repo();
+    c.bench_function("status, tracked files only", move |b| {
+        b.iter(|| status::changed_files(&repo, false).unwrap())
+    });
+
+    let repo = large_repo();
+    c.bench_function("status, including untracked", move |b| {
+        b.iter(|| status::changed_files(&repo, true).unwrap())
+    });
+}
+
+fn bench_log(c: &mut Criterion) {
+    let repo = large_repo();
+    c.bench_function("log, first page", move |b| {
+        b.iter(|| history::load(&repo, None, &LogFilter::default(), LogSort::Time, PAGE, &Mailmap::default()).unwrap())
+    });
+
+    // The log view starts each later page from the last commit it has loaded.
+    let repo = large_repo();
+    let start = history::load(&repo, None, &LogFilter::default(), LogSort::Time, PAGE * 5, &Mailmap::default()).unwrap()
+        .last().map(|entry| entry.oid);
+    c.bench_function("log, sixth page", move |b| {
+        b.iter(|| history::load(&repo, start, &LogFilter::default(), LogSort::Time, PAGE, &Mailmap::default()).unwrap())
+    });
+
+    let repo = large_repo();
+    c.bench_function("log, first page, topological", move |b| {
+        b.iter(|| history::load(&repo, None, &LogFilter::default(), LogSort::Topological, PAGE, &Mailmap::default()).unwrap())
+    });
+
+    let repo = large_repo();
+    let filter = LogFilter::parse("", "", "", &file_name(0)).unwrap();
+    c.bench_function("log, first page, filtered by path", move |b| {
+        b.iter(|| history::load(&repo, None, &filter, LogSort::Time, PAGE, &Mailmap::default()).unwrap())
+    });
+}
+
+fn bench_diff(c: &mut Criterion) {
+    let repo = large_repo();
+    let head = repo.head().unwrap().target().unwrap();
+    c.bench_function("diff, commit", move |b| {
+        b.iter(|| patch::commit_patch(&repo, head, DiffLimits::unlimited()).unwrap())
+    });
+
+    // What the diff pane waits for before it shows anything.
+    let repo = large_repo();
+    c.bench_function("diff, commit, first hunk", move |b| {
+        b.iter(|| {
+            let diff = patch::commit_diff(&repo, head, DiffLimits::unlimited()).unwrap();
+            let first = PatchStream::new(&diff, DiffLimits::unlimited()).next();
+            first.map(|chunk| chunk.unwrap().len())
+        })
+    });
+
+    let repo = large_repo();
+    let path = PathBuf::from(file_name(0));
+    c.bench_function("diff, working tree file", move |b| {
+        b.iter(|| patch::working_tree_patch(&repo, &path, DiffLimits::unlimited()).unwrap())
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_status, bench_log, bench_diff
+}
+criterion_main!(benches);
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use chrono::{Datelike, Duration, NaiveDate};
+use git2::{Repository, Sort};
+use cancel::Cancel;
+use error::OafError;
+use history::commit_date;
+use mailmap::Mailmap;
+
+/// Which commits to include when gathering activity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityScope {
+    /// Commits reachable from HEAD.
+    CurrentBranch,
+    /// Commits reachable from any branch, remote-tracking branch or tag.
+    AllRefs,
+}
+
+/// The number of commits made on each day, keyed by the committer's local date.
+pub type DailyCounts = BTreeMap<NaiveDate, usize>;
+
+/// The commits counted for the activity view.
+#[derive(Debug, Default)]
+pub struct Activity {
+    pub days: DailyCounts,
+    /// The number of commits by each author, as 'Name <email>' mapped by the
+    /// mailmap, so that one person's identities are counted together.
+    pub authors: BTreeMap<String, usize>,
+}
+
+/// How many authors the activity view lists.
+const TOP_AUTHORS: usize = 10;
+
+/// Characters used to draw a cell in the heatmap, from no activity to the most.
+const LEVELS: [char; 5] = ['·', '░', '▒', '▓', '█'];
+const DAY_NAMES: [&str; 7] = ["Mon", "   ", "Wed", "   ", "Fri", "   ", "Sun"];
+
+/// Counts commits per day and per author, with authors mapped by `mailmap`.
+/// Only commits made on or after `since` are counted; since the walk is in
+/// time order it stops at the first older commit. The walk also stops, with
+/// `OafError::Cancelled`, once `cancel` is set.
+pub fn gather(repo: &Repository, scope: ActivityScope, since: NaiveDate, mailmap: &Mailmap, cancel: &Cancel) -> Result<Activity, OafError> {
+    let _timer = timer!("activity::gather");
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME);
+    match scope {
+        ActivityScope::CurrentBranch => revwalk.push_head()?,
+        ActivityScope::AllRefs => {
+            revwalk.push_glob("refs/heads")?;
+            revwalk.push_glob("refs/remotes")?;
+            revwalk.push_glob("refs/tags")?;
+        }
+    }
+
+    let mut activity = Activity::default();
+    for oid in revwalk {
+        cancel.check()?;
+        let commit = repo.find_commit(oid?)?;
+        let date = commit_date(&commit.time());
+        if date < since {
+            break;
+        }
+        *activity.days.entry(date).or_insert(0) += 1;
+        *activity.authors.entry(mailmap.resolve_signature(&commit.author())).or_insert(0) += 1;
+    }
+
+    _timer.set_message(format!("{} days with commits by {} authors", activity.days.len(), activity.authors.len()));
+    Ok(activity)
+}
+
+/// Renders the heatmap of `activity` followed by its most active authors.
+pub fn render(activity: &Activity, end: NaiveDate, weeks: usize) -> String {
+    format!("{}\n{}", render_heatmap(&activity.days, end, weeks), render_authors(&activity.authors, TOP_AUTHORS))
+}
+
+/// Renders a contribution-graph style heatmap covering the `weeks` weeks up
+/// to and including `end`. Each column is a week starting on Monday and each
+/// row a day of the week; cells are shaded relative to the busiest day.
+pub fn render_heatmap(counts: &DailyCounts, end: NaiveDate, weeks: usize) -> String {
+    let start = first_day_of_heatmap(end, weeks);
+    let max = counts.range(start..).map(|(_, c)| *c).max().unwrap_or(0);
+
+    let mut text = String::new();
+    text += "    ";
+    text += &month_header(start, weeks);
+    text += "\n";
+
+    for (row, day_name) in DAY_NAMES.iter().enumerate() {
+        text += day_name;
+        text += " ";
+        for week in 0..weeks {
+            let date = start + Duration::days((week * 7 + row) as i64);
+            if date > end {
+                text.push(' ');
+            } else {
+                text.push(LEVELS[level(counts.get(&date).cloned().unwrap_or(0), max)]);
+            }
+        }
+        text += "\n";
+    }
+
+    let total: usize = counts.range(start..).map(|(_, c)| *c).sum();
+    text += &format!("\n{} commits, busiest day had {}.  Less {} More\n",
+                     total, max, LEVELS.iter().collect::<String>());
+    text
+}
+
+/// Lists the `limit` authors with the most commits, busiest first.
+fn render_authors(authors: &BTreeMap<String, usize>, limit: usize) -> String {
+    let mut busiest: Vec<(&String, &usize)> = authors.iter().collect();
+    busiest.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut text = "Most active authors:\n".to_string();
+    for (author, count) in busiest.into_iter().take(limit) {
+        text += &format!("{:>6}  {}\n", count, author);
+    }
+    text
+}
+
+/// Maps a count onto one of the `LEVELS`. Zero is always level 0 and the
+/// busiest day is always the top level.
+fn level(count: usize, max: usize) -> usize {
+    if count == 0 || max == 0 {
+        0
+    } else {
+        (count * (LEVELS.len() - 1) + max - 1) / max
+    }
+}
+
+/// The Monday of the first week shown in a heatmap ending at `end`.
+fn first_day_of_heatmap(end: NaiveDate, weeks: usize) -> NaiveDate {
+    let monday = end - Duration::days(end.weekday().num_days_from_monday() as i64);
+    monday - Duration::weeks(weeks as i64 - 1)
+}
+
+/// Builds a header line with month abbreviations above the first week of each month.
+fn month_header(start: NaiveDate, weeks: usize) -> String {
+    let mut header: Vec<char> = vec![' '; weeks + 3];
+    let mut last_month = 0;
+    for week in 0..weeks {
+        let date = start + Duration::weeks(week as i64);
+        if date.month() != last_month {
+            last_month = date.month();
+            let name = date.format("%b").to_string();
+            for (i, ch) in name.chars().enumerate() {
+                header[week + i] = ch;
+            }
+        }
+    }
+
+    header.into_iter().collect::<String>().trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    #[test]
+    fn level_scales_to_max() {
+        assert_eq!(level(0, 10), 0);
+        assert_eq!(level(1, 10), 1);
+        assert_eq!(level(10, 10), 4);
+        assert_eq!(level(0, 0), 0);
+        assert_eq!(level(1, 1), 4);
+    }
+
+    #[test]
+    fn first_day_of_heatmap_is_a_monday() {
+        // 2018-09-13 was a Thursday.
+        assert_eq!(first_day_of_heatmap(date(2018, 9, 13), 1), date(2018, 9, 10));
+        assert_eq!(first_day_of_heatmap(date(2018, 9, 13), 2), date(2018, 9, 3));
+        assert_eq!(first_day_of_heatmap(date(2018, 9, 10), 1), date(2018, 9, 10));
+    }
+
+    #[test]
+    fn render_heatmap_shades_days() {
+        let mut counts = DailyCounts::new();
+        counts.insert(date(2018, 9, 10), 4);
+        counts.insert(date(2018, 9, 12), 1);
+        let text = render_heatmap(&counts, date(2018, 9, 13), 1);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[1], "Mon █");
+        assert_eq!(lines[2], "    ·");
+        assert_eq!(lines[3], "Wed ░");
+        // Days after the end date are left blank.
+        assert_eq!(lines[5], "Fri  ");
+        assert!(text.contains("5 commits, busiest day had 4."));
+    }
+
+    #[test]
+    fn render_authors_lists_the_busiest_first() {
+        let mut authors = BTreeMap::new();
+        authors.insert("Ann <ann@example.com>".to_string(), 2);
+        authors.insert("Bob <bob@example.com>".to_string(), 5);
+        authors.insert("Cat <cat@example.com>".to_string(), 2);
+        assert_eq!(render_authors(&authors, 2),
+                   "Most active authors:\n     5  Bob <bob@example.com>\n     2  Ann <ann@example.com>\n");
+    }
+}
@@ -0,0 +1,268 @@
+use std::cmp;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use git2::{Oid, Repository};
+use commit_graph::CommitGraph;
+use default_branch;
+use error::OafError;
+use network;
+use remotes::{self, TagFetch};
+
+/// The longest we will wait between attempts after repeated failures, in seconds.
+const MAX_BACKOFF_SECS: u64 = 60 * 60;
+/// How often the worker wakes up to see if any repository is due, in seconds.
+const TICK_SECS: u64 = 15;
+
+/// The outcome of one background fetch of a repository.
+#[derive(Debug, Clone)]
+pub struct FetchReport {
+    /// The path of the repository's .git directory.
+    pub path: PathBuf,
+    pub new_commits: usize,
+    /// Whether the upstream of the current branch moved.
+    pub upstream_changed: bool,
+    /// If the upstream was rewritten (force-pushed), so that its old tip is no
+    /// longer part of its history, the old and new tips' merge base, if any.
+    pub upstream_rewritten: Option<Option<Oid>>,
+    /// How far the current branch is ahead of and behind its upstream.
+    pub ahead_behind: Option<(usize, usize)>,
+    /// How far the current branch is behind the default branch of the main
+    /// remote, which is named, if it is behind at all.
+    pub behind_default: Option<(String, usize)>,
+    /// If the fetch failed, the error and how long until the next attempt.
+    pub error: Option<(String, Duration)>,
+}
+
+/// Tracks when a repository is next due to be fetched. After a failure the
+/// delay doubles, up to an hour, until a fetch succeeds again.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    interval: Duration,
+    failures: u32,
+    next: Instant,
+}
+
+impl Schedule {
+    /// The first fetch is due one interval after `now`.
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Schedule { interval, failures: 0, next: now + interval }
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next
+    }
+
+    pub fn succeeded(&mut self, now: Instant) {
+        self.failures = 0;
+        self.next = now + self.interval;
+    }
+
+    /// Records a failure and returns the delay until the next attempt.
+    pub fn failed(&mut self, now: Instant) -> Duration {
+        self.failures += 1;
+        let delay = backoff(self.interval, self.failures);
+        self.next = now + delay;
+        delay
+    }
+}
+
+/// The delay after `failures` consecutive failures: the interval doubled for
+/// each failure, but never more than an hour (unless the interval itself is
+/// longer than that).
+pub fn backoff(interval: Duration, failures: u32) -> Duration {
+    let max_backoff = Duration::from_secs(MAX_BACKOFF_SECS);
+    let factor = 1u32 << cmp::min(failures, 16);
+    let delay = interval.checked_mul(factor).unwrap_or(max_backoff);
+    cmp::max(interval, cmp::min(delay, max_backoff))
+}
+
+/// Works out how often to fetch a repository. The repository's own git config
+/// setting 'oaf.autofetchMinutes' takes precedence over `default_minutes`.
+/// Returns None if autofetch is turned off, i.e. the number of minutes is 0.
+pub fn interval_for(repo: &Repository, default_minutes: u64) -> Option<Duration> {
+    let minutes = repo.config().ok()
+        .and_then(|c| c.get_i64("oaf.autofetchMinutes").ok())
+        .map(|m| cmp::max(m, 0) as u64)
+        .unwrap_or(default_minutes);
+
+    if minutes == 0 { None } else { Some(Duration::from_secs(minutes * 60)) }
+}
+
+/// Adds repositories to a running autofetch, such as ones opened after it
+/// started.
+#[derive(Debug, Clone)]
+pub struct Autofetcher {
+    sender: Sender<PathBuf>,
+}
+
+impl Autofetcher {
+    /// Starts fetching the repository whose .git directory is `git_dir`, if it
+    /// is not being fetched already.
+    pub fn add(&self, git_dir: &Path) {
+        let _ = self.sender.send(git_dir.to_path_buf());
+    }
+}
+
+/// Starts a background thread that periodically fetches every remote of the
+/// repositories at `paths`, and of any later given to the returned
+/// `Autofetcher`. `on_report` is called on that thread after each attempt.
+/// Repositories for which autofetch is turned off are ignored, and nothing is
+/// fetched while in offline mode.
+pub fn spawn<F>(paths: Vec<PathBuf>, default_minutes: u64, on_report: F) -> Autofetcher
+    where F: Fn(FetchReport) + Send + 'static
+{
+    let (sender, receiver) = mpsc::channel();
+    for path in paths {
+        let _ = sender.send(path);
+    }
+
+    thread::spawn(move || {
+        // Every path seen, including those with autofetch turned off, so
+        // that adding one again does not reopen it.
+        let mut seen: Vec<PathBuf> = Vec::new();
+        let mut schedules: Vec<(PathBuf, Schedule, CommitGraph)> = Vec::new();
+        loop {
+            while let Ok(path) = receiver.try_recv() {
+                if seen.contains(&path) {
+                    continue;
+                }
+                seen.push(path.clone());
+                if let Some(schedule) = schedule_for(path, default_minutes) {
+                    schedules.push(schedule);
+                }
+            }
+
+            thread::sleep(Duration::from_secs(TICK_SECS));
+            if network::is_offline() {
+                continue;
+            }
+
+            let now = Instant::now();
+            for &mut (ref path, ref mut schedule, ref mut graph) in &mut schedules {
+                if !schedule.is_due(now) {
+                    continue;
+                }
+
+                let report = match fetch_repository(path, graph) {
+                    Ok(report) => {
+                        schedule.succeeded(Instant::now());
+                        report
+                    },
+                    Err(e) => {
+                        let delay = schedule.failed(Instant::now());
+                        warn!("Autofetch of '{}' failed, retrying in {}s. Error = {}", path.display(), delay.as_secs(), e);
+                        FetchReport {
+                            path: path.clone(),
+                            new_commits: 0,
+                            upstream_changed: false,
+                            upstream_rewritten: None,
+                            ahead_behind: None,
+                            behind_default: None,
+                            error: Some((e.to_string(), delay)),
+                        }
+                    },
+                };
+
+                on_report(report);
+            }
+        }
+    });
+
+    Autofetcher { sender }
+}
+
+/// Opens the repository at `path` and works out when it is first due to be
+/// fetched, or returns None if it cannot be opened or autofetch is off for it.
+fn schedule_for(path: PathBuf, default_minutes: u64) -> Option<(PathBuf, Schedule, CommitGraph)> {
+    let repo = Repository::open(&path).ok()?;
+    let interval = interval_for(&repo, default_minutes)?;
+    info!("Autofetching '{}' every {} minutes", path.display(), interval.as_secs() / 60);
+    let graph = CommitGraph::new(&repo);
+    Some((path, Schedule::new(interval, Instant::now()), graph))
+}
+
+/// Fetches all the remotes of the repository at `path` and works out how its
+/// current branch now compares with its upstream. `graph` must belong to the
+/// same repository; it is kept between fetches so that generation numbers are
+/// only computed once.
+pub fn fetch_repository(path: &Path, graph: &mut CommitGraph) -> Result<FetchReport, OafError> {
+    let _timer = timer!("autofetch::fetch_repository");
+    let repo = Repository::open(path)?;
+    let upstream_tip = || {
+        let branch = remotes::current_branch_name(&repo)?;
+        let upstream = remotes::upstream_of(&repo, &branch)?;
+        let name = format!("refs/remotes/{}/{}", upstream.remote, upstream.branch);
+        repo.refname_to_id(&name).ok()
+    };
+
+    let before = upstream_tip();
+    let mut new_commits = 0;
+    for remote in remotes::remote_names(&repo) {
+        new_commits += remotes::fetch(&repo, &remote, TagFetch::Auto)?;
+    }
+    let after = upstream_tip();
+
+    let ahead_behind = match (repo.head().ok().and_then(|h| h.target()), after) {
+        (Some(local), Some(upstream)) => graph.ahead_behind(&repo, local, upstream).ok(),
+        _ => None,
+    };
+    let behind_default = default_branch::behind_default(&repo, graph);
+
+    let upstream_rewritten = match (before, after) {
+        (Some(old), Some(new)) if old != new && !graph.is_ancestor(&repo, old, new)? => {
+            Some(graph.merge_base(&repo, old, new)?)
+        },
+        _ => None,
+    };
+
+    _timer.set_message(format!("{} new commits in '{}'", new_commits, path.display()));
+    Ok(FetchReport {
+        path: path.to_path_buf(),
+        new_commits,
+        upstream_changed: before != after,
+        upstream_rewritten,
+        ahead_behind,
+        behind_default,
+        error: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(n: u64) -> Duration {
+        Duration::from_secs(n * 60)
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(backoff(minutes(5), 0), minutes(5));
+        assert_eq!(backoff(minutes(5), 1), minutes(10));
+        assert_eq!(backoff(minutes(5), 2), minutes(20));
+        assert_eq!(backoff(minutes(5), 4), minutes(60));
+        assert_eq!(backoff(minutes(5), 100), minutes(60));
+    }
+
+    #[test]
+    fn backoff_never_shortens_a_long_interval() {
+        assert_eq!(backoff(minutes(120), 3), minutes(120));
+    }
+
+    #[test]
+    fn schedule_resets_after_success() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(minutes(5), now);
+        assert!(!schedule.is_due(now));
+        assert!(schedule.is_due(now + minutes(5)));
+
+        assert_eq!(schedule.failed(now), minutes(10));
+        assert_eq!(schedule.failed(now), minutes(20));
+        assert!(!schedule.is_due(now + minutes(19)));
+
+        schedule.succeeded(now);
+        assert!(schedule.is_due(now + minutes(5)));
+    }
+}
@@ -0,0 +1,206 @@
+//! Backporting: cherry-picking a series of commits onto several branches in
+//! one go, such as a fix onto each maintained release branch. The picks are
+//! made in memory, so the target branches do not need to be checked out.
+
+use std::fmt;
+use std::path::PathBuf;
+use git2::{Error, Oid, Repository, Sort};
+use commit;
+use config::SigningConfig;
+use error::OafError;
+use history;
+use remotes;
+use rewrite;
+use signing::{self, Enforcement, PolicyCheck, SigningSetup};
+
+/// What happened when the commits were picked onto one target branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The branch was moved on to `tip`, after saving a backup unless
+    /// nothing was picked. Commits whose changes the branch already had were
+    /// left out.
+    Picked { tip: Oid, picked: usize, already_there: usize, backup: Option<String> },
+    /// `commit` does not apply cleanly; the branch has not been changed.
+    Conflict { commit: Oid, summary: String, paths: Vec<PathBuf> },
+    /// The branch has not been changed because of some other error.
+    Failed(String),
+}
+
+/// The outcome of backporting onto one branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetReport {
+    pub branch: String,
+    pub outcome: Outcome,
+    /// Why the commits picked onto the branch are not signed although it
+    /// should have signed commits, when the policy only warns about that.
+    pub unsigned_warning: Option<String>,
+}
+
+impl TargetReport {
+    pub fn succeeded(&self) -> bool {
+        match self.outcome {
+            Outcome::Picked { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for TargetReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.outcome {
+            Outcome::Picked { tip, picked, already_there, .. } => {
+                write!(f, "{}: picked {} commits, now at {:.7}", self.branch, picked, tip.to_string())?;
+                if already_there > 0 {
+                    write!(f, " ({} already there)", already_there)?;
+                }
+                Ok(())
+            },
+            Outcome::Conflict { commit, ref summary, ref paths } => {
+                let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "{}: {:.7} '{}' conflicts in {}; not changed", self.branch, commit.to_string(), summary, paths.join(", "))
+            },
+            Outcome::Failed(ref message) => write!(f, "{}: {}; not changed", self.branch, message),
+        }?;
+        match self.unsigned_warning {
+            Some(ref warning) => write!(f, "; {}", warning),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Resolves the commits to backport from `spec`, a list of commits and
+/// ranges separated by spaces, e.g. 'abc1234 def5678' or 'main..fix'. A range
+/// gives its commits oldest first. Merges cannot be picked, so are an error.
+pub fn resolve_commits(repo: &Repository, spec: &str) -> Result<Vec<Oid>, Error> {
+    let mut commits: Vec<Oid> = Vec::new();
+    for token in spec.split_whitespace() {
+        let found = match token.find("..") {
+            Some(i) => range(repo, &token[..i], &token[i + 2..])?,
+            None => vec![history::resolve_commit(repo, token)?],
+        };
+        for oid in found {
+            if !commits.contains(&oid) {
+                commits.push(oid);
+            }
+        }
+    }
+
+    if commits.is_empty() {
+        return Err(Error::from_str("Give the commits to backport."));
+    }
+    for &oid in &commits {
+        let commit = repo.find_commit(oid)?;
+        if commit.parents().len() != 1 {
+            return Err(Error::from_str(&format!("{:.7} is a merge or a root commit, which cannot be backported.", oid.to_string())));
+        }
+    }
+    Ok(commits)
+}
+
+/// The commits in `tip` but not `base`, oldest first. Either may be empty,
+/// meaning HEAD, as for git.
+fn range(repo: &Repository, base: &str, tip: &str) -> Result<Vec<Oid>, Error> {
+    let head_if_empty = |spec: &str| history::resolve_commit(repo, if spec.is_empty() { "HEAD" } else { spec });
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE);
+    revwalk.push(head_if_empty(tip)?)?;
+    revwalk.hide(head_if_empty(base)?)?;
+    revwalk.collect()
+}
+
+/// Picks `commits`, in order, onto each of `targets` in turn. Each target
+/// either gets all the commits or, if one conflicts or something else goes
+/// wrong, is left as it was; either way the other targets are still tried.
+/// A target that is checked out has its working tree updated too, unless
+/// that would overwrite local changes. The commits are signed if the git
+/// config says so, and `policy` is checked for each target as it is for a
+/// commit on HEAD's branch.
+pub fn backport(repo: &Repository, commits: &[Oid], targets: &[String], policy: &SigningConfig) -> Vec<TargetReport> {
+    let _timer = timer!("backport::backport");
+    let setup = SigningSetup::load(repo);
+    let reports: Vec<TargetReport> = targets.iter()
+        .map(|branch| {
+            let setup = match setup {
+                Ok(ref setup) => setup,
+                Err(ref e) => return TargetReport { branch: branch.clone(), outcome: Outcome::Failed(e.to_string()), unsigned_warning: None },
+            };
+            let problem = match signing::check_branch_policy(branch, setup, &policy.required_branches) {
+                PolicyCheck::Unmet { problem, .. } => Some(problem.trim_end_matches('.').to_string()),
+                PolicyCheck::Met { .. } | PolicyCheck::NotRequired => None,
+            };
+            let (outcome, unsigned_warning) = match (problem, policy.enforcement) {
+                (Some(problem), Enforcement::Refuse) => (Outcome::Failed(format!("commits must be signed, but {}", problem)), None),
+                (problem, _) => {
+                    let outcome = pick_onto(repo, setup, commits, branch).unwrap_or_else(|e| Outcome::Failed(e.to_string()));
+                    let picked = match outcome {
+                        Outcome::Picked { picked, .. } => picked > 0,
+                        _ => false,
+                    };
+                    let warning = if picked { problem.map(|problem| format!("commits should be signed, but {}", problem)) } else { None };
+                    (outcome, warning)
+                },
+            };
+            info!("Backport onto '{}': {:?}", branch, outcome);
+            TargetReport { branch: branch.clone(), outcome, unsigned_warning }
+        })
+        .collect();
+    _timer.set_message(format!("Backported {} commits onto {} of {} branches",
+                               commits.len(), reports.iter().filter(|r| r.succeeded()).count(), reports.len()));
+    reports
+}
+
+fn pick_onto(repo: &Repository, setup: &SigningSetup, commits: &[Oid], branch: &str) -> Result<Outcome, OafError> {
+    let full_ref = remotes::full_branch_ref(branch);
+    let committer = repo.signature()?;
+    let mut top = repo.find_commit(repo.refname_to_id(&full_ref)?)?;
+    let mut picked = 0;
+    let mut already_there = 0;
+
+    for &oid in commits {
+        let commit = repo.find_commit(oid)?;
+        let mut merged = repo.merge_trees(&commit.parent(0)?.tree()?, &top.tree()?, &commit.tree()?, None)
+            .map_err(OafError::Merge)?;
+        if merged.has_conflicts() {
+            let mut paths: Vec<PathBuf> = merged.iter()
+                .filter(|e| (e.flags >> 12) & 3 != 0)
+                .map(|e| PathBuf::from(String::from_utf8_lossy(&e.path).into_owned()))
+                .collect();
+            paths.dedup();
+            return Ok(Outcome::Conflict { commit: oid, summary: commit.summary().unwrap_or("").to_string(), paths });
+        }
+
+        let tree = repo.find_tree(merged.write_tree_to(repo)?)?;
+        if tree.id() == top.tree_id() {
+            already_there += 1;
+            continue;
+        }
+        let message = with_origin(&String::from_utf8_lossy(commit.message_bytes()), oid);
+        let new_oid = commit::write_commit(repo, setup, &tree, &[&top], &commit.author(), &committer, &message)?;
+        picked += 1;
+        top = repo.find_commit(new_oid)?;
+    }
+
+    if picked == 0 {
+        return Ok(Outcome::Picked { tip: top.id(), picked, already_there, backup: None });
+    }
+    let backup = rewrite::move_checked_out_branch(repo, &full_ref, top.id(), &format!("backport {} commits onto {}", picked, branch))?;
+    Ok(Outcome::Picked { tip: top.id(), picked, already_there, backup: Some(backup) })
+}
+
+/// Notes where a picked commit came from at the end of its message, as
+/// 'git cherry-pick -x' does.
+fn with_origin(message: &str, original: Oid) -> String {
+    format!("{}\n\n(cherry picked from commit {})\n", message.trim_end(), original)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_origin_appends_the_original_commit() {
+        let oid = Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap();
+        assert_eq!(with_origin("Fix the widget\n\nDetails.\n\n", oid),
+                   "Fix the widget\n\nDetails.\n\n(cherry picked from commit 0123456789abcdef0123456789abcdef01234567)\n");
+    }
+}
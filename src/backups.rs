@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use chrono::{Duration, Local, NaiveDateTime};
+use git2::{Error, Oid, Repository};
+use config::BackupConfig;
+
+/// All backups live under this prefix, one directory per backup named after
+/// the time it was taken, e.g. 'refs/oaf/backup/20181016-142501/master'.
+pub const BACKUP_PREFIX: &str = "refs/oaf/backup/";
+
+const TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// A set of branch tips saved before a destructive operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    /// The directory under `BACKUP_PREFIX`.
+    pub name: String,
+    /// When the backup was taken, if its name is one oaf made.
+    pub time: Option<NaiveDateTime>,
+    /// The short branch names and their saved tips.
+    pub refs: Vec<(String, Oid)>,
+}
+
+impl Backup {
+    /// The full ref name of one of the saved branches.
+    pub fn ref_name(&self, branch: &str) -> String {
+        format!("{}{}/{}", BACKUP_PREFIX, self.name, branch)
+    }
+}
+
+/// Saves the tips of one or more branches, given as (short branch name, tip)
+/// pairs, before they are rewritten. Returns the name of the backup, which is
+/// the directory under `BACKUP_PREFIX` holding the refs.
+pub fn create(repo: &Repository, tips: &[(&str, Oid)], reason: &str) -> Result<String, Error> {
+    let existing: Vec<String> = list(repo)?.into_iter().map(|b| b.name).collect();
+    let stamp = Local::now().format(TIME_FORMAT).to_string();
+    let name = unique_name(&stamp, |candidate| existing.iter().any(|e| e == candidate));
+
+    for &(branch, tip) in tips {
+        repo.reference(&format!("{}{}/{}", BACKUP_PREFIX, name, branch), tip, false, reason)?;
+    }
+
+    info!("Created backup '{}' of {} branches before {}", name, tips.len(), reason);
+    Ok(name)
+}
+
+/// Returns all the backups in the repository, newest first.
+pub fn list(repo: &Repository) -> Result<Vec<Backup>, Error> {
+    let mut grouped: BTreeMap<String, Vec<(String, Oid)>> = BTreeMap::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        let rest = match reference.name() {
+            Some(name) if name.starts_with(BACKUP_PREFIX) => name[BACKUP_PREFIX.len()..].to_string(),
+            _ => continue,
+        };
+
+        let mut parts = rest.splitn(2, '/');
+        let (name, branch, target) = match (parts.next(), parts.next(), reference.target()) {
+            (Some(name), Some(branch), Some(target)) => (name, branch, target),
+            _ => continue,
+        };
+        grouped.entry(name.to_string()).or_insert_with(Vec::new).push((branch.to_string(), target));
+    }
+
+    Ok(grouped.into_iter().rev().map(|(name, refs)| Backup { time: parse_time(&name), name, refs }).collect())
+}
+
+/// Deletes all the refs of the backup `name`.
+pub fn delete(repo: &Repository, name: &str) -> Result<(), Error> {
+    let backup = list(repo)?.into_iter().find(|b| b.name == name)
+        .ok_or_else(|| Error::from_str(&format!("There is no backup called '{}'.", name)))?;
+
+    for &(ref branch, _) in &backup.refs {
+        repo.find_reference(&backup.ref_name(branch))?.delete()?;
+    }
+    info!("Deleted backup '{}'", name);
+    Ok(())
+}
+
+/// Deletes the backups that the retention policy says have expired. Returns
+/// their names.
+pub fn clean_up(repo: &Repository, policy: &BackupConfig) -> Result<Vec<String>, Error> {
+    let _timer = timer!("backups::clean_up");
+    let backups = list(repo)?;
+    let names: Vec<String> = expired(&backups, Local::now().naive_local(), policy)
+        .into_iter().map(|n| n.to_string()).collect();
+    for name in &names {
+        delete(repo, name)?;
+    }
+
+    _timer.set_message(format!("Deleted {} of {} backups", names.len(), backups.len()));
+    Ok(names)
+}
+
+/// Works out which of `backups`, which must be newest first, have expired.
+/// Backups whose time cannot be worked out are never expired.
+fn expired<'a>(backups: &'a [Backup], now: NaiveDateTime, policy: &BackupConfig) -> Vec<&'a str> {
+    let cutoff = now - Duration::days(policy.keep_days as i64);
+    backups.iter()
+        .skip(policy.keep_at_least)
+        .filter(|b| b.time.map_or(false, |t| t < cutoff))
+        .map(|b| b.name.as_str())
+        .collect()
+}
+
+fn parse_time(name: &str) -> Option<NaiveDateTime> {
+    let stamp = name.get(..15)?;
+    NaiveDateTime::parse_from_str(stamp, TIME_FORMAT).ok()
+}
+
+/// Two backups in the same second get '-2', '-3'... suffixes.
+fn unique_name<F>(stamp: &str, exists: F) -> String
+    where F: Fn(&str) -> bool
+{
+    let mut name = stamp.to_string();
+    let mut n = 1;
+    while exists(&name) {
+        n += 1;
+        name = format!("{}-{}", stamp, n);
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_name_adds_suffix() {
+        assert_eq!(unique_name("20181016-142501", |_| false), "20181016-142501");
+        let taken = ["20181016-142501", "20181016-142501-2"];
+        assert_eq!(unique_name("20181016-142501", |n| taken.contains(&n)), "20181016-142501-3");
+    }
+
+    #[test]
+    fn parse_time_works() {
+        let expected = NaiveDateTime::parse_from_str("2018-10-16 14:25:01", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(parse_time("20181016-142501"), Some(expected));
+        assert_eq!(parse_time("20181016-142501-2"), Some(expected));
+        assert_eq!(parse_time("my-backup"), None);
+    }
+
+    fn backup(name: &str) -> Backup {
+        Backup { name: name.to_string(), time: parse_time(name), refs: Vec::new() }
+    }
+
+    #[test]
+    fn expired_applies_policy() {
+        let now = parse_time("20181031-120000").unwrap();
+        let backups = vec![backup("20181030-120000"), backup("20181001-120000"), backup("mine"),
+                           backup("20180901-120000"), backup("20180801-120000")];
+
+        let policy = BackupConfig { keep_days: 7, keep_at_least: 1 };
+        assert_eq!(expired(&backups, now, &policy), vec!["20181001-120000", "20180901-120000", "20180801-120000"]);
+
+        let policy = BackupConfig { keep_days: 7, keep_at_least: 4 };
+        assert_eq!(expired(&backups, now, &policy), vec!["20180801-120000"]);
+
+        let policy = BackupConfig { keep_days: 365, keep_at_least: 0 };
+        assert!(expired(&backups, now, &policy).is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use error::OafError;
+
+/// Lets the user stop a long operation running on another thread. Clones
+/// share the same flag, so the UI keeps one and the operation another; the
+/// operation checks it between steps and stops at the next safe point.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Cancel {
+    pub fn new() -> Self {
+        Cancel::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns `OafError::Cancelled` if the operation has been cancelled, for
+    /// use with `?` between steps.
+    pub fn check(&self) -> Result<(), OafError> {
+        if self.is_cancelled() { Err(OafError::Cancelled) } else { Ok(()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_the_flag() {
+        let cancel = Cancel::new();
+        let job = cancel.clone();
+        assert!(job.check().is_ok());
+
+        cancel.cancel();
+        assert!(job.is_cancelled());
+        match job.check() {
+            Err(OafError::Cancelled) => {},
+            other => panic!("Expected Cancelled, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use chrono::NaiveDate;
+use git2::{Oid, Repository, Sort};
+use config::{ChangelogConfig, ChangelogGroup};
+use history;
+
+/// The parts of a Conventional Commit summary line, such as
+/// 'feat(log)!: Show tags in the log'.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conventional {
+    pub kind: String,
+    pub scope: Option<String>,
+    /// Marked with '!' after the type or scope, or by a 'BREAKING CHANGE:'
+    /// footer.
+    pub breaking: bool,
+    pub description: String,
+}
+
+/// Parses `message` as a Conventional Commit. Returns None if its summary
+/// line does not start with a type followed by ':'.
+pub fn parse(message: &str) -> Option<Conventional> {
+    let summary = message.lines().next().unwrap_or("");
+    let colon = summary.find(": ")?;
+    let mut prefix = &summary[..colon];
+    let description = summary[colon + 2..].trim();
+
+    let mut breaking = prefix.ends_with('!');
+    if breaking {
+        prefix = &prefix[..prefix.len() - 1];
+    }
+
+    let (kind, scope) = match prefix.find('(') {
+        Some(open) if prefix.ends_with(')') => (&prefix[..open], Some(prefix[open + 1..prefix.len() - 1].trim())),
+        Some(_) => return None,
+        None => (prefix, None),
+    };
+
+    if kind.is_empty() || description.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+
+    breaking |= message.lines().skip(1)
+        .any(|l| l.starts_with("BREAKING CHANGE:") || l.starts_with("BREAKING-CHANGE:"));
+
+    Some(Conventional {
+        kind: kind.to_lowercase(),
+        scope: scope.filter(|s| !s.is_empty()).map(|s| s.to_string()),
+        breaking,
+        description: description.to_string(),
+    })
+}
+
+/// Splits a range given as 'FROM..TO' or 'FROM TO' into its two revisions.
+pub fn parse_range(spec: &str) -> Option<(&str, &str)> {
+    let parts: Vec<&str> = if spec.contains("...") {
+        return None;
+    } else if spec.contains("..") {
+        spec.splitn(2, "..").map(|s| s.trim()).collect()
+    } else {
+        spec.split_whitespace().collect()
+    };
+
+    if parts.len() == 2 && parts.iter().all(|p| !p.is_empty()) {
+        Some((parts[0], parts[1]))
+    } else {
+        None
+    }
+}
+
+/// The groups used when none are configured: the common Conventional
+/// Commit types that are of interest to users.
+pub fn default_groups() -> Vec<ChangelogGroup> {
+    let group = |title: &str, kind: &str| ChangelogGroup {
+        title: title.to_string(),
+        types: vec![kind.to_string()],
+        patterns: Vec::new(),
+    };
+
+    vec![
+        group("Features", "feat"),
+        group("Bug Fixes", "fix"),
+        group("Performance", "perf"),
+        group("Refactoring", "refactor"),
+        group("Documentation", "docs"),
+        group("Reverts", "revert"),
+    ]
+}
+
+/// A commit to be listed in a changelog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangelogCommit {
+    pub oid: Oid,
+    pub summary: String,
+    pub author: String,
+    pub conventional: Option<Conventional>,
+}
+
+impl ChangelogCommit {
+    pub fn new(oid: Oid, message: &str, author: &str) -> Self {
+        ChangelogCommit {
+            oid,
+            summary: message.lines().next().unwrap_or("").trim().to_string(),
+            author: author.to_string(),
+            conventional: parse(message),
+        }
+    }
+
+    fn is_in(&self, group: &ChangelogGroup) -> bool {
+        let kind = self.conventional.as_ref().map(|c| c.kind.as_str());
+        group.types.iter().any(|t| Some(t.to_lowercase().as_str()) == kind)
+            || group.patterns.iter().any(|p| wildcard_match(p, &self.summary))
+    }
+
+    fn entry(&self, template: &str) -> String {
+        let oid = self.oid.to_string();
+        let (kind, scope, description) = match self.conventional {
+            Some(ref c) => (c.kind.as_str(), c.scope.as_ref().map(|s| format!("{}: ", s)), c.description.as_str()),
+            None => ("", None, self.summary.as_str()),
+        };
+
+        template
+            .replace("{description}", description)
+            .replace("{summary}", &self.summary)
+            .replace("{type}", kind)
+            .replace("{scope}", &scope.unwrap_or_default())
+            .replace("{short_id}", &oid[..7])
+            .replace("{id}", &oid)
+            .replace("{author}", &self.author)
+    }
+}
+
+/// Matches `text` against `pattern`, in which '*' matches any run of
+/// characters. Case is ignored.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Loads the non-merge commits in `from..to`, newest first. If `from` is
+/// None that is all the commits reachable from `to`.
+pub fn commits(repo: &Repository, from: Option<Oid>, to: Oid) -> Result<Vec<ChangelogCommit>, ::git2::Error> {
+    let _timer = timer!("changelog::commits");
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME);
+    revwalk.push(to)?;
+    if let Some(from) = from {
+        revwalk.hide(from)?;
+    }
+
+    let mut commits = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parents().len() > 1 {
+            continue;
+        }
+        let author = String::from_utf8_lossy(commit.author().name_bytes()).into_owned();
+        commits.push(ChangelogCommit::new(commit.id(), &String::from_utf8_lossy(commit.message_bytes()), &author));
+    }
+
+    _timer.set_message(format!("{} commits", commits.len()));
+    Ok(commits)
+}
+
+/// Generates the changelog for the commits in `from..to`, which are
+/// revision expressions such as tag names.
+pub fn generate(repo: &Repository, from: &str, to: &str, config: &ChangelogConfig) -> Result<String, ::git2::Error> {
+    let from_oid = history::resolve_commit(repo, from)?;
+    let to_oid = history::resolve_commit(repo, to)?;
+    let date = history::commit_date(&repo.find_commit(to_oid)?.time());
+    let commits = commits(repo, Some(from_oid), to_oid)?;
+    Ok(render(from, to, date, &commits, config))
+}
+
+/// Lays out `commits` as Markdown, grouped as `config` says. Groups with no
+/// commits are left out.
+pub fn render(from: &str, to: &str, date: NaiveDate, commits: &[ChangelogCommit], config: &ChangelogConfig) -> String {
+    let groups = if config.groups.is_empty() { default_groups() } else { config.groups.clone() };
+    let mut sections: Vec<(&str, Vec<&ChangelogCommit>)> = Vec::new();
+
+    if !config.breaking.is_empty() {
+        let breaking = commits.iter().filter(|c| c.conventional.as_ref().map_or(false, |c| c.breaking)).collect();
+        sections.push((&config.breaking, breaking));
+    }
+
+    let mut grouped: Vec<Vec<&ChangelogCommit>> = vec![Vec::new(); groups.len()];
+    let mut other = Vec::new();
+    for commit in commits {
+        match groups.iter().position(|g| commit.is_in(g)) {
+            Some(i) => grouped[i].push(commit),
+            None => other.push(commit),
+        }
+    }
+    sections.extend(groups.iter().map(|g| g.title.as_str()).zip(grouped));
+    if !config.other.is_empty() {
+        sections.push((&config.other, other));
+    }
+
+    let mut text = config.heading
+        .replace("{version}", to)
+        .replace("{from}", from)
+        .replace("{date}", &date.format("%Y-%m-%d").to_string());
+    text.push('\n');
+
+    for (title, commits) in sections.into_iter().filter(|&(_, ref commits)| !commits.is_empty()) {
+        text.push('\n');
+        text.push_str(&config.group_heading.replace("{title}", title));
+        text.push_str("\n\n");
+        for commit in commits {
+            text.push_str(&commit.entry(&config.entry));
+            text.push('\n');
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(n: u8, message: &str) -> ChangelogCommit {
+        ChangelogCommit::new(Oid::from_bytes(&[n; 20]).unwrap(), message, "Ann")
+    }
+
+    #[test]
+    fn parse_works() {
+        assert_eq!(parse("feat(log)!: Show tags\n\nMore detail"), Some(Conventional {
+            kind: "feat".to_string(),
+            scope: Some("log".to_string()),
+            breaking: true,
+            description: "Show tags".to_string(),
+        }));
+        assert_eq!(parse("Fix: a typo").map(|c| (c.kind, c.scope, c.breaking)), Some(("fix".to_string(), None, false)));
+        assert!(parse("fix: Drop the flag\n\nBREAKING CHANGE: it is gone").unwrap().breaking);
+        assert_eq!(parse("Fix the widget"), None);
+        assert_eq!(parse("Note: see below"), Some(parse("note: see below").unwrap()));
+        assert_eq!(parse("WIP on master: 1234567"), None);
+        assert_eq!(parse("feat(log: Oops"), None);
+        assert_eq!(parse("feat: "), None);
+    }
+
+    #[test]
+    fn parse_range_works() {
+        assert_eq!(parse_range("v1.0..v1.1"), Some(("v1.0", "v1.1")));
+        assert_eq!(parse_range(" v1.0  HEAD "), Some(("v1.0", "HEAD")));
+        assert_eq!(parse_range("v1.0..."), None);
+        assert_eq!(parse_range("a...b"), None);
+        assert_eq!(parse_range("v1.0"), None);
+    }
+
+    #[test]
+    fn wildcard_match_works() {
+        assert!(wildcard_match("Fix*", "fix the widget"));
+        assert!(wildcard_match("*typo*", "Correct a typo in the README"));
+        assert!(wildcard_match("a*b*c", "abc"));
+        assert!(wildcard_match("Release", "release"));
+        assert!(!wildcard_match("Release", "Release 1.0"));
+        assert!(!wildcard_match("a*a", "a"));
+    }
+
+    #[test]
+    fn entry_fills_in_the_template() {
+        let oid = Oid::from_bytes(&[0xab; 20]).unwrap();
+        let commit = ChangelogCommit::new(oid, "feat(log): Show tags", "Ann");
+        assert_eq!(commit.entry("- {scope}{description} ({short_id}, {author})"), "- log: Show tags (abababa, Ann)");
+        assert_eq!(commit.entry("{type} {summary}"), "feat feat(log): Show tags");
+        let commit = ChangelogCommit::new(oid, "Fix the widget", "Ann");
+        assert_eq!(commit.entry("- {scope}{description}"), "- Fix the widget");
+    }
+
+    #[test]
+    fn render_groups_the_commits() {
+        let commits = vec![
+            commit(1, "feat!: Drop the old format"),
+            commit(2, "fix(diff): Handle empty files"),
+            commit(3, "chore: Update dependencies"),
+            commit(4, "feat: Show tags"),
+        ];
+        let date = NaiveDate::from_ymd(2018, 10, 1);
+        assert_eq!(render("v1.0", "v1.1", date, &commits, &ChangelogConfig::default()), "\
+## v1.1 (2018-10-01)
+
+### Breaking Changes
+
+- Drop the old format (0101010)
+
+### Features
+
+- Drop the old format (0101010)
+- Show tags (0404040)
+
+### Bug Fixes
+
+- diff: Handle empty files (0202020)
+
+### Other Changes
+
+- Update dependencies (0303030)
+");
+    }
+
+    #[test]
+    fn render_uses_the_configured_groups() {
+        let config = ChangelogConfig {
+            groups: vec![ChangelogGroup { title: "Fixes".to_string(), types: Vec::new(), patterns: vec!["fix*".to_string()] }],
+            other: String::new(),
+            breaking: String::new(),
+            heading: "# {from} to {version}".to_string(),
+            group_heading: "{title}:".to_string(),
+            entry: "* {summary}".to_string(),
+        };
+        let commits = vec![commit(1, "Fix the widget"), commit(2, "Add a gadget"), commit(3, "fix!: Break it")];
+        let date = NaiveDate::from_ymd(2018, 10, 1);
+        assert_eq!(render("a", "b", date, &commits, &config), "# a to b\n\nFixes:\n\n* Fix the widget\n* fix!: Break it\n");
+        assert_eq!(render("a", "b", date, &[], &config), "# a to b\n");
+    }
+}
@@ -0,0 +1,44 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// The programs tried, in order, to put text on the system clipboard.
+const PROGRAMS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("clip", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+
+/// Copies `text` to the system clipboard using the first of the usual
+/// clipboard programs that is installed.
+pub fn copy(text: &str) -> io::Result<()> {
+    for &(program, args) in PROGRAMS {
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        info!("Copying {} bytes to the clipboard using {}", text.len(), program);
+        if let Some(ref mut stdin) = child.stdin {
+            stdin.write_all(text.as_bytes())?;
+        }
+        drop(child.stdin.take());
+        let status = child.wait()?;
+        return if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::Other, format!("{} failed with {}", program, status)))
+        };
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound, "No clipboard program (pbcopy, clip, wl-copy, xclip or xsel) is installed"))
+}
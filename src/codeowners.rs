@@ -0,0 +1,138 @@
+//! The owners of files, from a CODEOWNERS file as used by GitHub and GitLab.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use ignore_rules;
+
+/// Where a CODEOWNERS file can be, relative to the root of the working
+/// tree, in the order the forges look.
+const LOCATIONS: &[&str] = &[".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS", ".gitlab/CODEOWNERS"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnerRule {
+    pub line: usize,
+    pub pattern: String,
+    /// e.g. '@org/team' or 'someone@example.com'. A rule with no owners
+    /// means the files have none.
+    pub owners: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeOwners {
+    /// The file the rules came from, relative to the working tree.
+    pub source: PathBuf,
+    pub rules: Vec<OwnerRule>,
+}
+
+impl CodeOwners {
+    /// Reads the first CODEOWNERS file found in the working tree, if any.
+    pub fn load(workdir: &Path) -> Option<CodeOwners> {
+        LOCATIONS.iter().find_map(|location| {
+            let text = fs::read_to_string(workdir.join(location)).ok()?;
+            Some(CodeOwners { source: PathBuf::from(location), rules: parse(&text) })
+        })
+    }
+
+    /// The rule that decides who owns `path`. As in .gitignore, the last
+    /// matching rule wins.
+    pub fn rule_for(&self, path: &Path) -> Option<&OwnerRule> {
+        let path = path.to_string_lossy().replace('\\', "/");
+        self.rules.iter().rev().find(|rule| matches(&rule.pattern, &path))
+    }
+
+    /// Groups `paths` by owner, for knowing who needs to review a change. A
+    /// file with several owners is listed under each.
+    pub fn summarise(&self, paths: &[PathBuf]) -> OwnerSummary {
+        let mut summary = OwnerSummary::default();
+        for path in paths {
+            match self.rule_for(path) {
+                Some(rule) if !rule.owners.is_empty() => for owner in &rule.owners {
+                    summary.by_owner.entry(owner.clone()).or_insert_with(Vec::new).push(path.clone());
+                },
+                _ => summary.unowned.push(path.clone()),
+            }
+        }
+        summary
+    }
+}
+
+/// Changed files grouped by their owners.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnerSummary {
+    pub by_owner: BTreeMap<String, Vec<PathBuf>>,
+    pub unowned: Vec<PathBuf>,
+}
+
+impl OwnerSummary {
+    pub fn description(&self) -> String {
+        let mut text = String::new();
+        for (owner, paths) in &self.by_owner {
+            text += &format!("{} ({} files)\n", owner, paths.len());
+            for path in paths {
+                text += &format!("    {}\n", path.display());
+            }
+        }
+        if !self.unowned.is_empty() {
+            text += &format!("No owner ({} files)\n", self.unowned.len());
+            for path in &self.unowned {
+                text += &format!("    {}\n", path.display());
+            }
+        }
+        text
+    }
+}
+
+fn parse(text: &str) -> Vec<OwnerRule> {
+    text.lines().enumerate().filter_map(|(n, line)| {
+        let line = line.split(" #").next().unwrap_or(line).trim();
+        // GitLab's '[Section]' headers are not rules.
+        if line.is_empty() || line.starts_with('#') || line.starts_with('[') || line.starts_with("^[") {
+            return None;
+        }
+        let mut words = line.split_whitespace();
+        let pattern = words.next()?.to_string();
+        Some(OwnerRule { line: n + 1, pattern, owners: words.map(|w| w.to_string()).collect() })
+    }).collect()
+}
+
+/// CODEOWNERS patterns follow .gitignore rules, so a pattern that matches a
+/// directory owns everything in it.
+fn matches(pattern: &str, path: &str) -> bool {
+    let mut candidates: Vec<(&str, bool)> = path.match_indices('/').map(|(i, _)| (&path[..i], true)).collect();
+    candidates.push((path, false));
+    candidates.iter().any(|&(candidate, is_dir)| ignore_rules::pattern_matches(pattern, candidate, is_dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owners_of(owners: &CodeOwners, path: &str) -> Vec<String> {
+        owners.rule_for(Path::new(path)).map(|r| r.owners.clone()).unwrap_or_default()
+    }
+
+    #[test]
+    fn the_last_matching_rule_wins() {
+        let owners = CodeOwners {
+            source: PathBuf::from("CODEOWNERS"),
+            rules: parse("# Owners\n*  @org/everyone\n/src/net/ @net-team alice@example.com # networking\n*.md @docs\n/src/net/generated/\n"),
+        };
+        assert_eq!(owners_of(&owners, "Cargo.toml"), vec!["@org/everyone"]);
+        assert_eq!(owners_of(&owners, "src/net/tcp.rs"), vec!["@net-team", "alice@example.com"]);
+        assert_eq!(owners_of(&owners, "src/net/README.md"), vec!["@docs"]);
+        assert!(owners_of(&owners, "src/net/generated/a.rs").is_empty());
+        assert_eq!(owners.rule_for(Path::new("src/net/tcp.rs")).map(|r| r.line), Some(3));
+    }
+
+    #[test]
+    fn summarise_groups_files_by_owner() {
+        let owners = CodeOwners { source: PathBuf::from("CODEOWNERS"), rules: parse("/src/ @dev @lead\n*.md @docs\n") };
+        let paths: Vec<PathBuf> = ["src/a.rs", "README.md", "build.rs"].iter().map(PathBuf::from).collect();
+        let summary = owners.summarise(&paths);
+        assert_eq!(summary.by_owner.keys().collect::<Vec<_>>(), vec!["@dev", "@docs", "@lead"]);
+        assert_eq!(summary.unowned, vec![PathBuf::from("build.rs")]);
+        assert_eq!(summary.description(), "@dev (1 files)\n    src/a.rs\n@docs (1 files)\n    README.md\n\
+                                           @lead (1 files)\n    src/a.rs\nNo owner (1 files)\n    build.rs\n");
+    }
+}
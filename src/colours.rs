@@ -0,0 +1,74 @@
+use std::env;
+
+/// How many colours the terminal can show, and so which palette is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ColourDepth {
+    /// Work it out from the environment, see `detect`.
+    #[serde(rename = "auto")]
+    Auto,
+    /// The 16 standard colours, which every colour terminal has.
+    #[serde(rename = "16")]
+    Basic,
+    /// The xterm 256 colour palette.
+    #[serde(rename = "256")]
+    Extended,
+    /// Any RGB colour.
+    #[serde(rename = "truecolor")]
+    TrueColour,
+}
+
+impl ColourDepth {
+    /// Resolves Auto by looking at the environment.
+    pub fn resolve(self) -> ColourDepth {
+        if self != ColourDepth::Auto {
+            return self;
+        }
+
+        let term = env::var("TERM").ok();
+        let colorterm = env::var("COLORTERM").ok();
+        detect(term.as_ref().map(|s| s.as_str()), colorterm.as_ref().map(|s| s.as_str()))
+    }
+}
+
+/// Works out the colour depth from the TERM and COLORTERM environment
+/// variables. COLORTERM is the de facto way for a terminal to say it has
+/// true colour; terminfo names say whether there are 256 colours. Anything
+/// unrecognised is assumed to have the basic colours only, as choosing
+/// colours the terminal does not have can make things unreadable.
+pub fn detect(term: Option<&str>, colorterm: Option<&str>) -> ColourDepth {
+    let term = term.unwrap_or("");
+    match colorterm {
+        Some("truecolor") | Some("24bit") => return ColourDepth::TrueColour,
+        _ => {},
+    }
+
+    if term.ends_with("-direct") {
+        ColourDepth::TrueColour
+    } else if term.contains("256color") {
+        ColourDepth::Extended
+    } else {
+        ColourDepth::Basic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_depth_from_the_environment() {
+        assert_eq!(detect(Some("xterm-256color"), Some("truecolor")), ColourDepth::TrueColour);
+        assert_eq!(detect(Some("xterm"), Some("24bit")), ColourDepth::TrueColour);
+        assert_eq!(detect(Some("xterm-direct"), None), ColourDepth::TrueColour);
+        assert_eq!(detect(Some("screen-256color"), None), ColourDepth::Extended);
+        assert_eq!(detect(Some("xterm"), None), ColourDepth::Basic);
+        assert_eq!(detect(Some("linux"), Some("")), ColourDepth::Basic);
+        assert_eq!(detect(None, None), ColourDepth::Basic);
+    }
+
+    #[test]
+    fn explicit_depths_are_not_detected() {
+        assert_eq!(ColourDepth::Extended.resolve(), ColourDepth::Extended);
+        assert_ne!(ColourDepth::Auto.resolve(), ColourDepth::Auto);
+    }
+}
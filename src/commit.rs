@@ -0,0 +1,145 @@
+//! Committing the changes that have been staged in the index.
+
+use std::path::PathBuf;
+use git2::{Commit, Error, Index, ObjectType, Oid, Repository, RepositoryState, Signature, Status, Tree};
+use error::OafError;
+use history;
+use rewrite;
+use signing::{self, SigningSetup};
+use staging;
+use status;
+
+/// Commits the index on HEAD with `message`, tidied as 'git commit' does. On
+/// an unborn branch, e.g. in a new repository, this makes the root commit,
+/// which creates the branch.
+pub fn commit_index(repo: &Repository, message: &str) -> Result<Oid, OafError> {
+    let mut index = repo.index().map_err(OafError::Index)?;
+    commit_tree_of(repo, &mut index, message)
+}
+
+/// Commits the tree of `index`, which need not have been written.
+fn commit_tree_of(repo: &Repository, index: &mut Index, message: &str) -> Result<Oid, OafError> {
+    let message = check_can_commit(repo, message)?;
+    let tree = repo.find_tree(index.write_tree().map_err(OafError::Index)?)?;
+    let parent = history::head_commit(repo)?;
+    let unchanged = match parent {
+        Some(ref parent) => parent.tree_id() == tree.id(),
+        None => tree.is_empty(),
+    };
+    if unchanged {
+        return Err(OafError::Index(Error::from_str("There are no staged changes to commit.")));
+    }
+
+    let signature = repo.signature()?;
+    let parents: Vec<_> = parent.iter().collect();
+    let setup = SigningSetup::load(repo)?;
+    let oid = if setup.sign_commits {
+        let oid = signed_commit(repo, &setup, &tree, &parents, &signature, &signature, &message)?;
+        move_head(repo, oid, &parents, &message)?;
+        oid
+    } else {
+        repo.commit(Some("HEAD"), &signature, &signature, &message, &tree, &parents)?
+    };
+    info!("Committed {}{}{}", oid, if parents.is_empty() { " (root commit)" } else { "" },
+          if setup.sign_commits { ", signed" } else { "" });
+    Ok(oid)
+}
+
+/// Writes a commit without moving any ref, signed if `setup` says commits
+/// are signed, for commands such as backporting that make commits on
+/// branches other than HEAD's.
+pub fn write_commit(repo: &Repository, setup: &SigningSetup, tree: &Tree, parents: &[&Commit], author: &Signature,
+                    committer: &Signature, message: &str) -> Result<Oid, OafError> {
+    if setup.sign_commits {
+        signed_commit(repo, setup, tree, parents, author, committer, message)
+    } else {
+        Ok(repo.commit(None, author, committer, message, tree, parents)?)
+    }
+}
+
+/// Writes a commit signed with gpg, as 'git commit -S' does.
+fn signed_commit(repo: &Repository, setup: &SigningSetup, tree: &Tree, parents: &[&Commit], author: &Signature,
+                 committer: &Signature, message: &str) -> Result<Oid, OafError> {
+    let mut content = format!("tree {}\n", tree.id());
+    for parent in parents {
+        content.push_str(&format!("parent {}\n", parent.id()));
+    }
+    content.push_str(&format!("author {}\ncommitter {}\n\n{}",
+                              signing::identity_line(author), signing::identity_line(committer), message));
+    let signed = signing::add_commit_signature(&content, &setup.sign(&content)?);
+    Ok(repo.odb()?.write(ObjectType::Commit, signed.as_bytes())?)
+}
+
+/// Moves HEAD on to the commit `oid`, as `Repository::commit` does.
+fn move_head(repo: &Repository, oid: Oid, parents: &[&Commit], message: &str) -> Result<(), OafError> {
+    let reflog = format!("commit{}: {}", if parents.is_empty() { " (initial)" } else { "" }, message.lines().next().unwrap_or(""));
+    let head = repo.find_reference("HEAD")?;
+    match head.symbolic_target() {
+        Some(branch) => { repo.reference(branch, oid, true, &reflog)?; },
+        None => repo.set_head_detached(oid)?,
+    }
+    Ok(())
+}
+
+/// Stages the changes to every tracked file, then commits, as 'git commit
+/// -a' does. Untracked files are left out. The changes are staged in memory
+/// and only written to the index once the commit has been made, so nothing
+/// is left staged if it cannot be.
+pub fn commit_all_tracked(repo: &Repository, message: &str) -> Result<Oid, OafError> {
+    check_can_commit(repo, message)?;
+    let changes = tracked_changes(repo)?;
+    let mut index = repo.index().map_err(OafError::Index)?;
+    let committed = staging::add_to_index(repo, &mut index, &changes)
+        .and_then(|_| commit_tree_of(repo, &mut index, message));
+    match committed {
+        Ok(oid) => {
+            index.write().map_err(OafError::Index)?;
+            Ok(oid)
+        },
+        Err(e) => {
+            // The repository shares one index object, so the staged changes
+            // are dropped by reading the file back in.
+            if let Err(read) = index.read(true) {
+                warn!("Cannot reload the index after a failed commit. Error = {}", read);
+            }
+            Err(e)
+        },
+    }
+}
+
+/// The tidied message, unless a commit cannot be made now.
+fn check_can_commit(repo: &Repository, message: &str) -> Result<String, OafError> {
+    if repo.state() != RepositoryState::Clean {
+        return Err(OafError::Git(Error::from_str("An operation is in progress; continue or abort it first.")));
+    }
+    rewrite::clean_message(message).ok_or_else(|| OafError::Git(Error::from_str("The commit message is empty.")))
+}
+
+/// The tracked files with changes that are not staged. Conflicted files are
+/// left out, since they need resolving first.
+pub fn tracked_changes(repo: &Repository) -> Result<Vec<PathBuf>, Error> {
+    let unstaged = Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE;
+    Ok(status::changed_files(repo, false)?.into_iter()
+        .filter(|&(_, s)| s.intersects(unstaged) && !s.contains(Status::CONFLICTED))
+        .map(|(path, _)| path)
+        .collect())
+}
+
+/// The files `commit_all_tracked` would commit changes to.
+pub fn all_tracked_changes(repo: &Repository) -> Result<Vec<PathBuf>, Error> {
+    let mut files = staged_files(repo)?;
+    files.extend(tracked_changes(repo)?);
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// The files whose staged changes the next commit will include.
+pub fn staged_files(repo: &Repository) -> Result<Vec<PathBuf>, Error> {
+    let head_tree = match history::head_commit(repo)? {
+        Some(commit) => Some(commit.tree()?),
+        None => None,
+    };
+    let diff = repo.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+    Ok(diff.deltas().filter_map(|d| d.new_file().path().or_else(|| d.old_file().path()).map(|p| p.to_path_buf())).collect())
+}
@@ -0,0 +1,569 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs;
+use git2::{Error, Oid, Repository};
+use refs::{RefKind, RefLabel};
+
+const SIGNATURE: &[u8] = b"CGPH";
+const HASH_LEN: usize = 20;
+const CHUNK_OID_FANOUT: &[u8] = b"OIDF";
+const CHUNK_OID_LOOKUP: &[u8] = b"OIDL";
+const CHUNK_COMMIT_DATA: &[u8] = b"CDAT";
+const CHUNK_EXTRA_EDGES: &[u8] = b"EDGE";
+const PARENT_NONE: u32 = 0x7000_0000;
+const EDGE_FLAG: u32 = 0x8000_0000;
+
+/// What the graph queries need to know about a commit. The generation number
+/// is 1 for root commits and otherwise one more than the largest generation
+/// of the commit's parents, so a commit can only reach commits with a lower
+/// generation than its own. This lets walks stop early.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub parents: Vec<Oid>,
+    pub generation: u32,
+}
+
+/// Answers reachability questions about a repository's history. Commits are
+/// looked up in git's commit-graph file when there is one (written by 'git
+/// commit-graph write' or 'git gc' with 'gc.writeCommitGraph'); generation
+/// numbers for other commits are computed on demand and remembered, so a
+/// `CommitGraph` should be kept for as long as the repository is open. The
+/// answers to merge-base and ancestry queries are remembered too; they are
+/// keyed by commit rather than by ref, so they never go stale.
+pub struct CommitGraph {
+    file: Option<GraphFile>,
+    computed: HashMap<Oid, Node>,
+    merge_bases: HashMap<(Oid, Oid), Option<Oid>>,
+    ancestry: HashMap<(Oid, Oid), bool>,
+}
+
+impl CommitGraph {
+    /// Loads the commit-graph file of `repo` if it has a usable one. Split
+    /// commit-graph chains are not supported and are treated as absent.
+    pub fn new(repo: &Repository) -> Self {
+        let path = repo.path().join("objects").join("info").join("commit-graph");
+        let file = match fs::read(&path) {
+            Ok(data) => match GraphFile::parse(data) {
+                Ok(file) => {
+                    info!("Loaded commit-graph '{}' with {} commits", path.display(), file.len());
+                    Some(file)
+                },
+                Err(e) => {
+                    warn!("Ignoring commit-graph '{}': {}", path.display(), e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+
+        CommitGraph {
+            file,
+            computed: HashMap::new(),
+            merge_bases: HashMap::new(),
+            ancestry: HashMap::new(),
+        }
+    }
+
+    /// Looks up a commit, computing its generation number if necessary.
+    pub fn node(&mut self, repo: &Repository, oid: Oid) -> Result<Node, Error> {
+        if let Some(node) = self.lookup(oid) {
+            return Ok(node);
+        }
+
+        // Work out generations bottom-up without recursion, since histories
+        // can be far deeper than the stack.
+        let mut stack = vec![oid];
+        while let Some(&current) = stack.last() {
+            if self.lookup(current).is_some() {
+                stack.pop();
+                continue;
+            }
+
+            let parents: Vec<Oid> = repo.find_commit(current)?.parent_ids().collect();
+            let missing: Vec<Oid> = parents.iter().cloned().filter(|p| self.lookup(*p).is_none()).collect();
+            if missing.is_empty() {
+                let generation = parents.iter()
+                    .filter_map(|p| self.lookup(*p))
+                    .map(|n| n.generation)
+                    .max()
+                    .unwrap_or(0) + 1;
+                self.computed.insert(current, Node { parents, generation });
+                stack.pop();
+            } else {
+                stack.extend(missing);
+            }
+        }
+
+        Ok(self.lookup(oid).expect("generation was just computed"))
+    }
+
+    fn lookup(&self, oid: Oid) -> Option<Node> {
+        self.computed.get(&oid).cloned()
+            .or_else(|| self.file.as_ref().and_then(|f| f.node(oid)))
+    }
+
+    /// Counts the commits reachable from `a` but not `b`, and from `b` but not `a`.
+    pub fn ahead_behind(&mut self, repo: &Repository, a: Oid, b: Oid) -> Result<(usize, usize), Error> {
+        let _timer = timer!("commit_graph::ahead_behind");
+        let mut counts = (0, 0);
+        paint(a, b, |oid| self.node(repo, oid), |_, flags| {
+            match flags {
+                FROM_A => counts.0 += 1,
+                FROM_B => counts.1 += 1,
+                _ => {},
+            }
+            true
+        })?;
+        Ok(counts)
+    }
+
+    /// Finds a best common ancestor of `a` and `b`, i.e. one that is not an
+    /// ancestor of any other common ancestor. If there are several (criss-cross
+    /// merges) the one with the highest generation is returned.
+    pub fn merge_base(&mut self, repo: &Repository, a: Oid, b: Oid) -> Result<Option<Oid>, Error> {
+        let key = if a < b { (a, b) } else { (b, a) };
+        if let Some(&base) = self.merge_bases.get(&key) {
+            return Ok(base);
+        }
+
+        let _timer = timer!("commit_graph::merge_base");
+        let mut base = None;
+        paint(a, b, |oid| self.node(repo, oid), |oid, flags| {
+            if flags == FROM_BOTH {
+                base = Some(oid);
+                false
+            } else {
+                true
+            }
+        })?;
+        self.merge_bases.insert(key, base);
+        Ok(base)
+    }
+
+    /// Whether `ancestor` is reachable from `descendant`; a commit counts as
+    /// its own ancestor.
+    pub fn is_ancestor(&mut self, repo: &Repository, ancestor: Oid, descendant: Oid) -> Result<bool, Error> {
+        if let Some(&answer) = self.ancestry.get(&(ancestor, descendant)) {
+            return Ok(answer);
+        }
+
+        let answer = is_ancestor(ancestor, descendant, |oid| self.node(repo, oid))?;
+        self.ancestry.insert((ancestor, descendant), answer);
+        Ok(answer)
+    }
+
+    /// Returns the local and remote-tracking branches among `labels` whose
+    /// history includes `commit`.
+    pub fn branches_containing(&mut self, repo: &Repository, commit: Oid, labels: &[RefLabel]) -> Result<Vec<RefLabel>, Error> {
+        let _timer = timer!("commit_graph::branches_containing");
+        let mut branches = Vec::new();
+        for label in labels.iter().filter(|l| is_branch(l)) {
+            if self.is_ancestor(repo, commit, label.target)? {
+                branches.push(label.clone());
+            }
+        }
+
+        _timer.set_message(format!("{} branches contain {}", branches.len(), commit));
+        Ok(branches)
+    }
+}
+
+/// A detached HEAD is labelled 'HEAD', which is not a branch.
+fn is_branch(label: &RefLabel) -> bool {
+    match label.kind {
+        RefKind::Head => label.name != "HEAD",
+        RefKind::LocalBranch | RefKind::RemoteBranch => true,
+        RefKind::Tag => false,
+    }
+}
+
+const FROM_A: u8 = 1;
+const FROM_B: u8 = 2;
+const FROM_BOTH: u8 = 3;
+
+/// Walks back from `a` and `b` in decreasing generation order, recording for
+/// each commit which of the two it is reachable from. Because parents always
+/// have lower generations than their children, a commit's flags are final when
+/// it is visited. `visit` is called once per commit and can stop the walk by
+/// returning false. The walk stops by itself once every commit still to be
+/// visited is reachable from both, as nothing further back can be reachable
+/// from only one.
+fn paint<N, V>(a: Oid, b: Oid, mut node: N, mut visit: V) -> Result<(), Error>
+    where N: FnMut(Oid) -> Result<Node, Error>,
+          V: FnMut(Oid, u8) -> bool
+{
+    let mut flags: HashMap<Oid, u8> = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut queue = BinaryHeap::new();
+    // The number of queue entries that were reachable from only one side
+    // when they were queued.
+    let mut interesting = 0;
+
+    for &(oid, flag) in &[(a, FROM_A), (b, FROM_B)] {
+        *flags.entry(oid).or_insert(0) |= flag;
+        queue.push((node(oid)?.generation, oid, true));
+        interesting += 1;
+    }
+
+    while interesting > 0 {
+        let (_, oid, counted) = match queue.pop() {
+            Some(entry) => entry,
+            None => break,
+        };
+        if counted {
+            interesting -= 1;
+        }
+        if !visited.insert(oid) {
+            continue;
+        }
+
+        let current = flags[&oid];
+        if !visit(oid, current) {
+            break;
+        }
+
+        for parent in node(oid)?.parents {
+            let old = flags.get(&parent).cloned().unwrap_or(0);
+            let new = old | current;
+            if new != old {
+                flags.insert(parent, new);
+                let counted = new != FROM_BOTH;
+                if counted {
+                    interesting += 1;
+                }
+                queue.push((node(parent)?.generation, parent, counted));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn is_ancestor<N>(ancestor: Oid, descendant: Oid, mut node: N) -> Result<bool, Error>
+    where N: FnMut(Oid) -> Result<Node, Error>
+{
+    let target_generation = node(ancestor)?.generation;
+    let mut stack = vec![descendant];
+    let mut seen = HashSet::new();
+
+    while let Some(oid) = stack.pop() {
+        if oid == ancestor {
+            return Ok(true);
+        }
+        if !seen.insert(oid) {
+            continue;
+        }
+
+        let current = node(oid)?;
+        // Nothing below the target's generation can reach it.
+        if current.generation > target_generation {
+            stack.extend(current.parents);
+        }
+    }
+
+    Ok(false)
+}
+
+/// A parsed commit-graph file. See Documentation/technical/commit-graph-format.txt
+/// in the git sources for the layout.
+struct GraphFile {
+    data: Vec<u8>,
+    fanout: usize,
+    lookup: usize,
+    commit_data: usize,
+    extra_edges: Option<usize>,
+    count: usize,
+}
+
+impl GraphFile {
+    fn parse(data: Vec<u8>) -> Result<GraphFile, String> {
+        if data.len() < 8 || &data[0..4] != SIGNATURE {
+            return Err("not a commit-graph file".to_string());
+        }
+        if data[4] != 1 {
+            return Err(format!("unsupported version {}", data[4]));
+        }
+        if data[5] != 1 {
+            return Err("unsupported hash algorithm".to_string());
+        }
+
+        let num_chunks = data[6] as usize;
+        let mut chunks = HashMap::new();
+        for i in 0..num_chunks {
+            let entry = 8 + i * 12;
+            let id = data.get(entry..entry + 4).ok_or("truncated chunk table")?;
+            let offset = read_u64(&data, entry + 4).ok_or("truncated chunk table")? as usize;
+            chunks.insert(id.to_vec(), offset);
+        }
+
+        let chunk = |id: &[u8]| chunks.get(id).cloned();
+        let fanout = chunk(CHUNK_OID_FANOUT).ok_or("missing OID fanout chunk")?;
+        let lookup = chunk(CHUNK_OID_LOOKUP).ok_or("missing OID lookup chunk")?;
+        let commit_data = chunk(CHUNK_COMMIT_DATA).ok_or("missing commit data chunk")?;
+        let count = read_u32(&data, fanout + 255 * 4).ok_or("truncated fanout chunk")? as usize;
+
+        if data.len() < lookup + count * HASH_LEN || data.len() < commit_data + count * (HASH_LEN + 16) {
+            return Err("truncated file".to_string());
+        }
+
+        let file = GraphFile { extra_edges: chunk(CHUNK_EXTRA_EDGES), data, fanout, lookup, commit_data, count };
+
+        // Files written by old versions of git have no generation numbers.
+        if file.count > 0 && file.generation(0) == 0 {
+            return Err("the file has no generation numbers".to_string());
+        }
+
+        Ok(file)
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    /// The id of the commit at `index`, or None if the index is out of range,
+    /// as it can be in a corrupt file.
+    fn oid_at(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.count {
+            return None;
+        }
+        let start = self.lookup + index * HASH_LEN;
+        self.data.get(start..start + HASH_LEN)
+    }
+
+    fn find(&self, oid: Oid) -> Option<usize> {
+        let bytes = oid.as_bytes();
+        let first = bytes[0] as usize;
+        let start = if first == 0 { 0 } else { read_u32(&self.data, self.fanout + (first - 1) * 4)? as usize };
+        let end = read_u32(&self.data, self.fanout + first * 4)? as usize;
+        if start > end || end > self.count {
+            return None;
+        }
+
+        let (mut low, mut high) = (start, end);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.oid_at(mid)?.cmp(bytes) {
+                ::std::cmp::Ordering::Less => low = mid + 1,
+                ::std::cmp::Ordering::Greater => high = mid,
+                ::std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    fn generation(&self, index: usize) -> u32 {
+        let record = self.commit_data + index * (HASH_LEN + 16);
+        read_u32(&self.data, record + HASH_LEN + 8).unwrap_or(0) >> 2
+    }
+
+    fn node(&self, oid: Oid) -> Option<Node> {
+        let index = self.find(oid)?;
+        let record = self.commit_data + index * (HASH_LEN + 16);
+        let parent1 = read_u32(&self.data, record + HASH_LEN)?;
+        let parent2 = read_u32(&self.data, record + HASH_LEN + 4)?;
+
+        let mut parent_indexes = Vec::new();
+        if parent1 != PARENT_NONE {
+            parent_indexes.push(parent1);
+        }
+        if parent2 & EDGE_FLAG != 0 {
+            // An octopus merge; the remaining parents are in the extra edges chunk.
+            let edges = self.extra_edges?;
+            let mut i = (parent2 & !EDGE_FLAG) as usize;
+            loop {
+                let edge = read_u32(&self.data, edges + i * 4)?;
+                parent_indexes.push(edge & !EDGE_FLAG);
+                if edge & EDGE_FLAG != 0 {
+                    break;
+                }
+                i += 1;
+            }
+        } else if parent2 != PARENT_NONE {
+            parent_indexes.push(parent2);
+        }
+
+        // A parent index outside the file means it is corrupt; returning None
+        // makes the caller compute the generation from the commits instead.
+        let parents = parent_indexes.into_iter()
+            .map(|i| self.oid_at(i as usize).and_then(|bytes| Oid::from_bytes(bytes).ok()))
+            .collect::<Option<Vec<Oid>>>()?;
+
+        Some(Node { parents, generation: self.generation(index) })
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(bytes.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some(bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oid(n: u8) -> Oid {
+        Oid::from_bytes(&[n; 20]).unwrap()
+    }
+
+    /// Builds nodes from (commit, parents) pairs given children-last, i.e.
+    /// parents must be listed before their children.
+    fn graph(edges: &[(u8, &[u8])]) -> HashMap<Oid, Node> {
+        let mut nodes: HashMap<Oid, Node> = HashMap::new();
+        for &(commit, parents) in edges {
+            let parents: Vec<Oid> = parents.iter().map(|&p| oid(p)).collect();
+            let generation = parents.iter().map(|p| nodes[p].generation).max().unwrap_or(0) + 1;
+            nodes.insert(oid(commit), Node { parents, generation });
+        }
+        nodes
+    }
+
+    // 1 - 2 - 3 - 4 - 6      (6 merges 5)
+    //      \         /
+    //       5 ------
+    //        \
+    //         7 - 8
+    fn sample() -> HashMap<Oid, Node> {
+        graph(&[(1, &[]), (2, &[1]), (3, &[2]), (4, &[3]), (5, &[2]), (6, &[4, 5]), (7, &[5]), (8, &[7])])
+    }
+
+    fn ahead_behind(nodes: &HashMap<Oid, Node>, a: u8, b: u8) -> (usize, usize) {
+        let mut counts = (0, 0);
+        paint(oid(a), oid(b), |o| Ok(nodes[&o].clone()), |_, flags| {
+            match flags {
+                FROM_A => counts.0 += 1,
+                FROM_B => counts.1 += 1,
+                _ => {},
+            }
+            true
+        }).unwrap();
+        counts
+    }
+
+    fn merge_base(nodes: &HashMap<Oid, Node>, a: u8, b: u8) -> Option<Oid> {
+        let mut base = None;
+        paint(oid(a), oid(b), |o| Ok(nodes[&o].clone()), |o, flags| {
+            if flags == FROM_BOTH { base = Some(o); false } else { true }
+        }).unwrap();
+        base
+    }
+
+    #[test]
+    fn ahead_behind_works() {
+        let nodes = sample();
+        assert_eq!(ahead_behind(&nodes, 6, 8), (3, 2));
+        assert_eq!(ahead_behind(&nodes, 4, 4), (0, 0));
+        assert_eq!(ahead_behind(&nodes, 4, 1), (3, 0));
+    }
+
+    #[test]
+    fn merge_base_works() {
+        let nodes = sample();
+        assert_eq!(merge_base(&nodes, 6, 8), Some(oid(5)));
+        assert_eq!(merge_base(&nodes, 4, 7), Some(oid(2)));
+        assert_eq!(merge_base(&nodes, 3, 3), Some(oid(3)));
+    }
+
+    #[test]
+    fn is_ancestor_works() {
+        let nodes = sample();
+        let check = |a, d| is_ancestor(oid(a), oid(d), |o| Ok(nodes[&o].clone())).unwrap();
+        assert!(check(5, 6));
+        assert!(check(1, 8));
+        assert!(check(8, 8));
+        assert!(!check(4, 8));
+        assert!(!check(6, 5));
+    }
+
+    /// Writes a commit-graph file for 4 commits in a line, 0 to 2, plus an
+    /// octopus merge 3 of 1, 2 and 0.
+    fn sample_file() -> Vec<u8> {
+        let oids = [oid(0), oid(1), oid(2), oid(3)];
+        let chunk_ids: [&[u8]; 4] = [CHUNK_OID_FANOUT, CHUNK_OID_LOOKUP, CHUNK_COMMIT_DATA, CHUNK_EXTRA_EDGES];
+        let header_len = 8 + 5 * 12;
+        let sizes = [256 * 4, oids.len() * HASH_LEN, oids.len() * (HASH_LEN + 16), 2 * 4];
+
+        let mut data = vec![b'C', b'G', b'P', b'H', 1, 1, 4, 0];
+        let mut offset = header_len as u64;
+        for (id, size) in chunk_ids.iter().zip(sizes.iter()) {
+            data.extend_from_slice(id);
+            data.extend((0..8).rev().map(|i| (offset >> (i * 8)) as u8));
+            offset += *size as u64;
+        }
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend((0..8).rev().map(|i| (offset >> (i * 8)) as u8));
+
+        let push_u32 = |data: &mut Vec<u8>, v: u32| data.extend((0..4).rev().map(|i| (v >> (i * 8)) as u8));
+        for i in 0..256 {
+            push_u32(&mut data, oids.iter().filter(|o| (o.as_bytes()[0] as usize) <= i).count() as u32);
+        }
+        for o in &oids {
+            data.extend_from_slice(o.as_bytes());
+        }
+        // (parent1, parent2, generation) for commits 0..3.
+        let records = [(PARENT_NONE, PARENT_NONE, 1), (0, PARENT_NONE, 2), (1, PARENT_NONE, 3), (1, EDGE_FLAG, 4)];
+        for &(p1, p2, generation) in &records {
+            data.extend_from_slice(&[0; HASH_LEN]);
+            push_u32(&mut data, p1);
+            push_u32(&mut data, p2);
+            push_u32(&mut data, generation << 2);
+            push_u32(&mut data, 0);
+        }
+        push_u32(&mut data, 2);
+        push_u32(&mut data, EDGE_FLAG);
+        data
+    }
+
+    #[test]
+    fn graph_file_parses() {
+        let file = GraphFile::parse(sample_file()).unwrap();
+        assert_eq!(file.len(), 4);
+        assert_eq!(file.node(oid(0)), Some(Node { parents: vec![], generation: 1 }));
+        assert_eq!(file.node(oid(2)), Some(Node { parents: vec![oid(1)], generation: 3 }));
+        assert_eq!(file.node(oid(3)), Some(Node { parents: vec![oid(1), oid(2), oid(0)], generation: 4 }));
+        assert_eq!(file.node(oid(9)), None);
+    }
+
+    #[test]
+    fn is_branch_works() {
+        let label = |kind, name: &str| RefLabel { kind, name: name.to_string(), target: oid(1) };
+        assert!(is_branch(&label(RefKind::Head, "master")));
+        assert!(!is_branch(&label(RefKind::Head, "HEAD")));
+        assert!(is_branch(&label(RefKind::RemoteBranch, "origin/master")));
+        assert!(!is_branch(&label(RefKind::Tag, "v1.0")));
+    }
+
+    #[test]
+    fn graph_file_ignores_out_of_range_indexes() {
+        let fanout = 8 + 5 * 12;
+        let commit_data = fanout + 256 * 4 + 4 * HASH_LEN;
+        let set_u32 = |data: &mut Vec<u8>, offset: usize, v: u32| {
+            for i in 0..4 {
+                data[offset + i] = (v >> ((3 - i) * 8)) as u8;
+            }
+        };
+
+        // Commit 1's first parent is past the end of the file.
+        let mut data = sample_file();
+        set_u32(&mut data, commit_data + (HASH_LEN + 16) + HASH_LEN, 99);
+        let file = GraphFile::parse(data).unwrap();
+        assert_eq!(file.node(oid(1)), None);
+        assert_eq!(file.node(oid(2)), Some(Node { parents: vec![oid(1)], generation: 3 }));
+
+        // The fanout says there are more commits starting with 2 than the
+        // file holds.
+        let mut data = sample_file();
+        set_u32(&mut data, fanout + 2 * 4, 200);
+        let file = GraphFile::parse(data).unwrap();
+        assert_eq!(file.node(oid(2)), None);
+    }
+
+    #[test]
+    fn graph_file_rejects_garbage() {
+        assert!(GraphFile::parse(b"CGPX\x01\x01\x00\x00".to_vec()).is_err());
+        assert!(GraphFile::parse(b"CGPH\x01\x01\x03\x00".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! Comparing two revisions, as 'git diff A..B' and 'git diff A...B' do.
+
+/// How two revisions are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    /// 'A..B': the difference between the two trees, so changes made on A
+    /// since the two diverged show up too, reversed.
+    TwoDot,
+    /// 'A...B': what B changed since it forked from A, i.e. the diff from
+    /// their merge base to B, as a pull request shows it.
+    ThreeDot,
+}
+
+impl CompareMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            CompareMode::TwoDot => CompareMode::ThreeDot,
+            CompareMode::ThreeDot => CompareMode::TwoDot,
+        }
+    }
+
+    /// The dots between the revisions, as git writes them.
+    pub fn separator(self) -> &'static str {
+        match self {
+            CompareMode::TwoDot => "..",
+            CompareMode::ThreeDot => "...",
+        }
+    }
+}
+
+/// Parses 'FROM..TO', 'FROM...TO' or 'FROM TO'. Without dots the comparison
+/// is three-dot, which is what is usually wanted when reviewing a branch.
+pub fn parse_spec(spec: &str) -> Option<(&str, &str, CompareMode)> {
+    let (parts, mode): (Vec<&str>, _) = if spec.contains("...") {
+        (spec.splitn(2, "...").map(|s| s.trim()).collect(), CompareMode::ThreeDot)
+    } else if spec.contains("..") {
+        (spec.splitn(2, "..").map(|s| s.trim()).collect(), CompareMode::TwoDot)
+    } else {
+        (spec.split_whitespace().collect(), CompareMode::ThreeDot)
+    };
+
+    if parts.len() == 2 && parts.iter().all(|p| !p.is_empty() && !p.contains(char::is_whitespace)) {
+        Some((parts[0], parts[1], mode))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_spec_works() {
+        assert_eq!(parse_spec("master...feature"), Some(("master", "feature", CompareMode::ThreeDot)));
+        assert_eq!(parse_spec("v1.0 .. HEAD"), Some(("v1.0", "HEAD", CompareMode::TwoDot)));
+        assert_eq!(parse_spec(" master  feature "), Some(("master", "feature", CompareMode::ThreeDot)));
+        assert_eq!(parse_spec("master"), None);
+        assert_eq!(parse_spec("master.."), None);
+        assert_eq!(parse_spec("a b c"), None);
+    }
+
+    #[test]
+    fn toggling_switches_between_the_modes() {
+        assert_eq!(CompareMode::TwoDot.toggled(), CompareMode::ThreeDot);
+        assert_eq!(CompareMode::ThreeDot.toggled().separator(), "..");
+    }
+}
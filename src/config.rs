@@ -0,0 +1,1199 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use encoding_rs::Encoding;
+use toml::{self, Value};
+use colours::ColourDepth;
+use dates::{self, DateStyle, DateZone};
+use highlight;
+use maintenance::Task;
+use patch::{self, DiffLimits};
+use signing::Enforcement;
+use text_width::Ellipsis;
+
+/// The user's settings, read from 'config.toml' in the config directory.
+/// Every setting has a default, so the file only needs to mention the
+/// settings the user wants to change, and need not exist at all.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Templates used to turn remote URLs into web URLs. These are tried
+    /// in order before the built-in templates for well-known forges.
+    pub forges: Vec<ForgeTemplate>,
+
+    /// API tokens for forges, keyed by host name, e.g. 'github.com'. These
+    /// are only used when oaf is built with the 'forge-api' feature.
+    pub api_tokens: BTreeMap<String, String>,
+
+    /// Patterns used to find issue references in commit messages. If there
+    /// are none, GitHub-style '#123' references are recognised.
+    pub issue_trackers: Vec<IssueTracker>,
+
+    /// The identities, such as 'work' and 'personal', that commits can be
+    /// made under. Each repository uses the one chosen for it, or else the
+    /// first whose remote patterns match one of its remotes' URLs.
+    pub identities: Vec<IdentityProfile>,
+
+    /// How often, in minutes, to fetch the remotes of open repositories in
+    /// the background. 0 turns autofetch off. A repository can override this
+    /// with the git config setting 'oaf.autofetchMinutes'.
+    pub autofetch_minutes: u64,
+
+    /// Start in offline mode, in which network operations fail immediately.
+    pub offline: bool,
+
+    /// If oaf is already running, open repositories in it rather than
+    /// starting another; the same as the '--single-instance' option.
+    pub single_instance: bool,
+
+    /// Before committing or pushing, show who owns the changed files
+    /// according to the repository's CODEOWNERS file.
+    pub show_owners: bool,
+
+    /// Make 'Commit...' stage the changes to all tracked files first, as
+    /// 'git commit -a' does, rather than commit only what is staged.
+    pub commit_all_tracked: bool,
+
+    /// Branch names ('*' is a wildcard) that the review before a push warns
+    /// about pushing to directly.
+    pub protected_branches: Vec<String>,
+
+    /// When to treat a repository as large, and what to do differently.
+    pub large_repo: LargeRepoConfig,
+
+    /// How long to keep the backups taken before history is rewritten.
+    pub backups: BackupConfig,
+
+    /// Housekeeping of the object database, such as 'git gc'.
+    pub maintenance: MaintenanceConfig,
+
+    /// How the views follow changes made to the repository outside oaf.
+    pub refresh: RefreshConfig,
+
+    /// How fetches and pushes are retried after a network failure that is
+    /// likely to be passing, such as a timeout.
+    pub retry: RetryConfig,
+
+    /// Which branches need signed commits.
+    pub signing: SigningConfig,
+
+    /// How things are shown in the UI.
+    pub display: DisplayConfig,
+
+    /// How commit and tag dates are shown.
+    pub dates: DateConfig,
+
+    /// Limits on how much of a file or diff is loaded.
+    pub limits: LimitsConfig,
+
+    /// How changelogs are generated from the commits between two refs.
+    pub changelog: ChangelogConfig,
+
+    /// The defaults and templates offered when creating a repository.
+    pub new_repo: NewRepoConfig,
+
+    /// How files that are not UTF-8 are read for display.
+    pub encoding: EncodingConfig,
+
+    /// Syntax highlighting of file content and diffs.
+    pub highlighting: HighlightConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            forges: Vec::new(),
+            api_tokens: BTreeMap::new(),
+            issue_trackers: Vec::new(),
+            identities: Vec::new(),
+            autofetch_minutes: 0,
+            offline: false,
+            single_instance: false,
+            show_owners: false,
+            commit_all_tracked: false,
+            protected_branches: vec!["main".to_string(), "master".to_string()],
+            large_repo: LargeRepoConfig::default(),
+            backups: BackupConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            refresh: RefreshConfig::default(),
+            retry: RetryConfig::default(),
+            signing: SigningConfig::default(),
+            display: DisplayConfig::default(),
+            dates: DateConfig::default(),
+            limits: LimitsConfig::default(),
+            changelog: ChangelogConfig::default(),
+            new_repo: NewRepoConfig::default(),
+            encoding: EncodingConfig::default(),
+            highlighting: HighlightConfig::default(),
+        }
+    }
+}
+
+/// Describes how to build web URLs for repositories hosted on a forge.
+/// In the templates, `{base}` is replaced by 'https://<host>/<repo path>',
+/// `{commit}` by a commit hash, `{branch}` by a branch name, `{rev}` by
+/// either of those and `{path}` by a file path relative to the root of the
+/// repository.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ForgeTemplate {
+    /// The host name that identifies the forge, e.g. 'github.com'.
+    pub host: String,
+    pub commit: String,
+    pub branch: String,
+    pub file: String,
+}
+
+/// Describes how to recognise references to an issue tracker and where they
+/// link to. In `pattern`, `{key}` matches a run of upper-case letters such as
+/// a JIRA project key and `{id}` matches a run of digits; everything else is
+/// matched literally, so 'JIRA-{id}' or '{key}-{id}' are typical. In `url`,
+/// `{key}` and `{id}` are replaced by what they matched, `{ref}` by the whole
+/// reference and `{base}` by the web address of the repository's remote.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IssueTracker {
+    pub pattern: String,
+    pub url: String,
+}
+
+/// A name, email and optional signing key to make commits under, called
+/// `name`. `remote_patterns` ('*' is a wildcard) pick the profile for any
+/// repository with a remote whose URL matches, e.g. '*github.com/acme/*'.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityProfile {
+    pub name: String,
+    pub user_name: String,
+    pub email: String,
+    #[serde(default)]
+    pub signing_key: Option<String>,
+    #[serde(default)]
+    pub remote_patterns: Vec<String>,
+}
+
+/// Whether large repository mode is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LargeRepoMode {
+    /// Used for repositories with more than `object_threshold` objects.
+    Auto,
+    Always,
+    Never,
+}
+
+/// Settings for large repository mode. In this mode the untracked files in
+/// the working tree are only scanned on request, the log initially loads
+/// fewer commits and it is not decorated with refs.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LargeRepoConfig {
+    pub mode: LargeRepoMode,
+    /// The estimated number of objects above which a repository is large.
+    pub object_threshold: u64,
+    /// The number of commits the log loads at first in a large repository.
+    pub log_limit: usize,
+}
+
+impl Default for LargeRepoConfig {
+    fn default() -> Self {
+        LargeRepoConfig {
+            mode: LargeRepoMode::Auto,
+            object_threshold: 1_000_000,
+            log_limit: 200,
+        }
+    }
+}
+
+/// Whether to watch the current repository for changes, and how long its
+/// files must stay unchanged before the views are refreshed, so that a build
+/// or test run writing many files causes one refresh rather than dozens.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RefreshConfig {
+    pub watch: bool,
+    pub debounce_millis: u64,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        RefreshConfig {
+            watch: true,
+            debounce_millis: 1000,
+        }
+    }
+}
+
+/// The branch a new repository starts on, and a directory of the user's own
+/// templates for its README, .gitignore and LICENSE. Each file in the
+/// 'readme', 'gitignore' and 'license' directories under `template_dir` is
+/// offered alongside the built-in templates, named after the file.
+///
+/// The other settings are applied to every repository oaf creates or clones,
+/// once it is open: `git_config` is written to its local git config, the
+/// `identity` profile is chosen for it, each file in `hooks_dir` is copied
+/// into its hooks directory and `lfs_install` runs 'git lfs install --local'.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NewRepoConfig {
+    pub initial_branch: String,
+    pub template_dir: Option<PathBuf>,
+    pub git_config: BTreeMap<String, String>,
+    pub identity: Option<String>,
+    pub hooks_dir: Option<PathBuf>,
+    pub lfs_install: bool,
+}
+
+impl Default for NewRepoConfig {
+    fn default() -> Self {
+        NewRepoConfig {
+            initial_branch: "master".to_string(),
+            template_dir: None,
+            git_config: BTreeMap::new(),
+            identity: None,
+            hooks_dir: None,
+            lfs_install: false,
+        }
+    }
+}
+
+/// The encodings that files which are not valid UTF-8 are read in, so that
+/// legacy files show readable text. Encodings are named by their WHATWG
+/// labels, e.g. 'windows-1252', 'shift_jis' or 'euc-kr'. The first entry in
+/// `paths` whose gitignore-style pattern matches a file wins; other files
+/// use `fallback`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncodingConfig {
+    pub fallback: String,
+    pub paths: Vec<PathEncoding>,
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        EncodingConfig {
+            fallback: "windows-1252".to_string(),
+            paths: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathEncoding {
+    pub pattern: String,
+    pub encoding: String,
+}
+
+/// Colours file previews and diffs by language, chosen by file extension.
+/// This is only done when oaf is built with the 'syntax-highlighting'
+/// feature, and not on terminals with only the basic colours. `theme` is one
+/// of syntect's built-in themes, e.g. 'base16-ocean.dark',
+/// 'base16-ocean.light', 'InspiredGitHub' or 'Solarized (dark)'. Turning it
+/// off makes big files and diffs quicker to show.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighlightConfig {
+    pub enabled: bool,
+    pub theme: String,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            enabled: true,
+            theme: "base16-ocean.dark".to_string(),
+        }
+    }
+}
+
+/// How many times a network operation is retried after a timeout or dropped
+/// connection, and how long to wait before the first retry. The wait doubles
+/// for each retry after that. 0 retries turns retrying off.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub retries: u32,
+    pub backoff_millis: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            retries: 2,
+            backoff_millis: 2000,
+        }
+    }
+}
+
+/// The policy on signed commits. Committing on a branch matching one of
+/// `required_branches` ('*' is a wildcard) when the repository is not set up
+/// to sign commits either warns or, if `enforcement` is "refuse", is refused.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    pub required_branches: Vec<String>,
+    pub enforcement: Enforcement,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        SigningConfig {
+            required_branches: Vec::new(),
+            enforcement: Enforcement::Warn,
+        }
+    }
+}
+
+/// The retention policy for backups, applied when the user cleans them up.
+/// Backups older than `keep_days` are deleted, except that the newest
+/// `keep_at_least` are always kept however old they are.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    pub keep_days: u32,
+    pub keep_at_least: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            keep_days: 30,
+            keep_at_least: 10,
+        }
+    }
+}
+
+/// The maintenance tasks run on each open repository every `interval_days`
+/// days, in the background; 0 turns the schedule off. They are also the
+/// tasks chosen at first in the Maintenance panel. A repository with at
+/// least `loose_refs_hint` loose refs gets a hint to pack them when it is
+/// opened; 0 turns the hint off.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaintenanceConfig {
+    pub interval_days: u32,
+    pub tasks: Vec<Task>,
+    pub loose_refs_hint: u64,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        MaintenanceConfig {
+            interval_days: 0,
+            tasks: vec![Task::Gc],
+            loose_refs_hint: 1000,
+        }
+    }
+}
+
+/// Settings for how things are shown in the UI.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// Where paths and branch names that are too wide for their column are
+    /// cut: "start", "middle" or "end".
+    pub path_ellipsis: Ellipsis,
+
+    /// Render for screen readers; the same as the '--screen-reader' option.
+    pub screen_reader: bool,
+
+    /// How many colours the terminal has: "16", "256", "truecolor", or
+    /// "auto" to work it out from the TERM and COLORTERM variables.
+    pub colours: ColourDepth,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            path_ellipsis: Ellipsis::Start,
+            screen_reader: false,
+            colours: ColourDepth::Auto,
+        }
+    }
+}
+
+/// How dates are shown in the log, where space is short, and in the commit
+/// and tag details. `custom_format` is a strftime format, such as
+/// '%d %b %Y %H:%M', used by the "custom" style.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DateConfig {
+    pub log: DateStyle,
+    pub details: DateStyle,
+    pub timezone: DateZone,
+    pub custom_format: String,
+}
+
+impl Default for DateConfig {
+    fn default() -> Self {
+        DateConfig {
+            log: DateStyle::Short,
+            details: DateStyle::Iso,
+            timezone: DateZone::Author,
+            custom_format: "%Y-%m-%d %H:%M".to_string(),
+        }
+    }
+}
+
+/// Limits that keep oaf from loading a multi-hundred-MB file or diff into
+/// memory. Diffs of files bigger than `max_file_bytes` are not loaded, and
+/// diffs are cut off after `max_diff_lines`; the diff view offers to load
+/// them anyway. 0 means no limit.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_file_bytes: u64,
+    pub max_diff_lines: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_file_bytes: 10 * 1024 * 1024,
+            max_diff_lines: 50_000,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn diff_limits(&self) -> DiffLimits {
+        DiffLimits {
+            max_file_bytes: if self.max_file_bytes == 0 { None } else { Some(self.max_file_bytes) },
+            max_lines: if self.max_diff_lines == 0 { None } else { Some(self.max_diff_lines) },
+            context_lines: patch::DEFAULT_CONTEXT_LINES,
+        }
+    }
+}
+
+/// How a changelog is laid out. Commits are put in the first of `groups`
+/// they belong to; if there are no groups, the usual Conventional Commit
+/// types are used ('feat' under "Features", 'fix' under "Bug Fixes" and so
+/// on). Commits in no group go under `other`, and commits marked as breaking
+/// are also listed under `breaking`; either is left out if its title is
+/// empty.
+///
+/// In `heading`, `{version}` is replaced by the newer ref, `{from}` by the
+/// older one and `{date}` by the date of the newer ref's commit. In
+/// `group_heading`, `{title}` is replaced by the title of the group. In
+/// `entry`, `{description}` is replaced by the description of a Conventional
+/// Commit (or the whole summary line of any other commit), `{summary}` by the
+/// summary line, `{type}` by its type, `{scope}` by its scope followed by
+/// ': ' (or nothing if it has none), `{id}` and `{short_id}` by the commit
+/// hash and `{author}` by the author's name.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChangelogConfig {
+    pub groups: Vec<ChangelogGroup>,
+    pub other: String,
+    pub breaking: String,
+    pub heading: String,
+    pub group_heading: String,
+    pub entry: String,
+}
+
+impl Default for ChangelogConfig {
+    fn default() -> Self {
+        ChangelogConfig {
+            groups: Vec::new(),
+            other: "Other Changes".to_string(),
+            breaking: "Breaking Changes".to_string(),
+            heading: "## {version} ({date})".to_string(),
+            group_heading: "### {title}".to_string(),
+            entry: "- {scope}{description} ({short_id})".to_string(),
+        }
+    }
+}
+
+/// A section of a changelog. A commit belongs to it if it is a Conventional
+/// Commit with one of `types`, or its summary line matches one of
+/// `patterns`. In a pattern '*' matches anything and everything else is
+/// matched literally, ignoring case, so 'Fix*' matches "Fix the widget".
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChangelogGroup {
+    pub title: String,
+    #[serde(default)]
+    pub types: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+lazy_static! {
+    static ref CURRENT: RwLock<Arc<Config>> = RwLock::new(Arc::new(Config::default()));
+}
+
+/// Returns the current configuration.
+pub fn current() -> Arc<Config> {
+    CURRENT.read().unwrap().clone()
+}
+
+/// Replaces the current configuration.
+pub fn set(config: Config) {
+    *CURRENT.write().unwrap() = Arc::new(config);
+}
+
+/// How often the config file is checked for changes, in seconds.
+const WATCH_SECS: u64 = 2;
+
+/// Starts a background thread that reloads the configuration from `path`
+/// whenever the file changes. `on_change` is called on that thread with the
+/// new configuration and its problems. Deleting the file counts as a change
+/// back to the defaults.
+pub fn watch<F>(path: PathBuf, on_change: F)
+    where F: Fn(Config, Vec<Problem>) + Send + 'static
+{
+    thread::spawn(move || {
+        let mut modified = modified_time(&path);
+        loop {
+            thread::sleep(Duration::from_secs(WATCH_SECS));
+            let latest = modified_time(&path);
+            if latest != modified {
+                modified = latest;
+                let (config, problems) = load(&path);
+                on_change(config, problems);
+            }
+        }
+    });
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The settings that differ between `old` and `new` but are only used as
+/// oaf starts, so changing them needs a restart. Everything else is read
+/// each time it is used.
+pub fn needs_restart(old: &Config, new: &Config) -> Vec<&'static str> {
+    let mut settings = Vec::new();
+    if old.autofetch_minutes != new.autofetch_minutes {
+        settings.push("autofetch_minutes");
+    }
+    if old.display.screen_reader != new.display.screen_reader {
+        settings.push("display.screen_reader");
+    }
+    settings
+}
+
+/// A mistake in a settings file. oaf starts anyway, without the setting,
+/// and lists the problems once the UI is up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub file: PathBuf,
+    /// The dotted name of the setting, e.g. 'display.colours', or None if
+    /// the problem is with the file as a whole.
+    pub setting: Option<String>,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new<S: Into<String>>(file: &Path, setting: Option<String>, message: S) -> Problem {
+        Problem { file: file.to_path_buf(), setting, message: message.into() }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.setting {
+            Some(ref setting) => write!(f, "{}: {}", setting, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Reads the configuration from `path`. A missing file is not an error, it
+/// just means all the defaults apply. Settings that are wrong are left at
+/// their defaults and returned as problems, so that one mistake does not
+/// lose the rest of the file.
+pub fn load(path: &Path) -> (Config, Vec<Problem>) {
+    let _timer = timer!("config::load");
+
+    if !path.exists() {
+        _timer.set_message(format!("No config file at '{}', using defaults", path.display()));
+        return (Config::default(), Vec::new());
+    }
+
+    let (config, problems) = match fs::read_to_string(path) {
+        Ok(content) => check(&content, path),
+        Err(e) => (Config::default(), vec![Problem::new(path, None, format!("Cannot read the file: {}", e))]),
+    };
+    _timer.set_message(format!("Loaded config from '{}' with {} problems", path.display(), problems.len()));
+    (config, problems)
+}
+
+/// Parses configuration from a TOML string read from `file`, keeping every
+/// setting that is valid. The others are left at their defaults and
+/// described in the problems returned.
+pub fn check(content: &str, file: &Path) -> (Config, Vec<Problem>) {
+    let mut table = match toml::from_str::<Table>(content) {
+        Ok(table) => table,
+        Err(e) => return (Config::default(), vec![Problem::new(file, None, e.to_string())]),
+    };
+
+    let mut found = Vec::new();
+    prune(&mut table, "", &Value::Table, &mut found);
+    let mut config = Value::Table(table).try_into().unwrap_or_default();
+    validate(&mut config, &mut found);
+
+    let problems = found.into_iter().map(|(setting, message)| Problem::new(file, Some(setting), message)).collect();
+    (config, problems)
+}
+
+type Table = toml::value::Table;
+
+/// Removes the settings in `table` that are not valid, recording why. Each
+/// setting is tried on its own: `wrap` puts it back where `table` sits in
+/// the file, so that it can be deserialized as a Config. `prefix` is the
+/// dotted name of `table`.
+fn prune(table: &mut Table, prefix: &str, wrap: &dyn Fn(Table) -> Value, found: &mut Vec<(String, String)>) {
+    let keys: Vec<String> = table.keys().cloned().collect();
+    for key in keys {
+        let name = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        let mut value = table.remove(&key).unwrap();
+
+        match value {
+            Value::Table(ref mut inner) => {
+                prune(inner, &name, &|t| wrap(single(&key, Value::Table(t))), found);
+            },
+            Value::Array(ref mut items) => {
+                let mut kept = Vec::new();
+                for (i, item) in items.drain(..).enumerate() {
+                    match accepts(wrap(single(&key, Value::Array(vec![item.clone()])))) {
+                        Ok(()) => kept.push(item),
+                        Err(message) => found.push((format!("{} (entry {})", name, i + 1), message)),
+                    }
+                }
+                *items = kept;
+            },
+            _ => {},
+        }
+
+        match accepts(wrap(single(&key, value.clone()))) {
+            Ok(()) => { table.insert(key, value); },
+            Err(message) => found.push((name, message)),
+        }
+    }
+}
+
+fn single(key: &str, value: Value) -> Table {
+    let mut table = Table::new();
+    table.insert(key.to_string(), value);
+    table
+}
+
+fn accepts(value: Value) -> Result<(), String> {
+    value.try_into::<Config>().map(|_| ()).map_err(|e| {
+        // The problem already says which setting it is about.
+        let message = e.to_string();
+        match message.find(" for key `") {
+            Some(i) => message[..i].to_string(),
+            None => message,
+        }
+    })
+}
+
+/// Puts back to their defaults the settings that parse but cannot be used.
+fn validate(config: &mut Config, found: &mut Vec<(String, String)>) {
+    if !dates::is_valid_format(&config.dates.custom_format) {
+        found.push(("dates.custom_format".to_string(),
+            format!("'{}' is not a valid strftime format", config.dates.custom_format)));
+        config.dates.custom_format = DateConfig::default().custom_format;
+    }
+
+    if !highlight::is_known_theme(&config.highlighting.theme) {
+        found.push(("highlighting.theme".to_string(),
+            format!("There is no syntax highlighting theme called '{}'", config.highlighting.theme)));
+        config.highlighting.theme = HighlightConfig::default().theme;
+    }
+
+    let mut trackers = Vec::new();
+    for (i, tracker) in config.issue_trackers.drain(..).enumerate() {
+        if tracker.pattern.contains("{id}") {
+            trackers.push(tracker);
+        } else {
+            found.push((format!("issue_trackers (entry {})", i + 1),
+                format!("The pattern '{}' does not contain {{id}}", tracker.pattern)));
+        }
+    }
+    config.issue_trackers = trackers;
+
+    let mut identities: Vec<IdentityProfile> = Vec::new();
+    for (i, identity) in config.identities.drain(..).enumerate() {
+        if identities.iter().any(|other| other.name == identity.name) {
+            found.push((format!("identities (entry {})", i + 1),
+                format!("There is already an identity called '{}'", identity.name)));
+        } else {
+            identities.push(identity);
+        }
+    }
+    config.identities = identities;
+
+    if let Some(name) = config.new_repo.identity.take() {
+        if config.identities.iter().any(|identity| identity.name == name) {
+            config.new_repo.identity = Some(name);
+        } else {
+            found.push(("new_repo.identity".to_string(), format!("There is no identity called '{}'", name)));
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (i, group) in config.changelog.groups.drain(..).enumerate() {
+        if group.types.is_empty() && group.patterns.is_empty() {
+            found.push((format!("changelog.groups (entry {})", i + 1),
+                format!("The group '{}' has no types or patterns, so no commit can be in it", group.title)));
+        } else {
+            groups.push(group);
+        }
+    }
+    config.changelog.groups = groups;
+
+    if Encoding::for_label(config.encoding.fallback.as_bytes()).is_none() {
+        found.push(("encoding.fallback".to_string(), format!("'{}' is not a known encoding", config.encoding.fallback)));
+        config.encoding.fallback = EncodingConfig::default().fallback;
+    }
+    let mut paths = Vec::new();
+    for (i, path) in config.encoding.paths.drain(..).enumerate() {
+        if Encoding::for_label(path.encoding.as_bytes()).is_some() {
+            paths.push(path);
+        } else {
+            found.push((format!("encoding.paths (entry {})", i + 1), format!("'{}' is not a known encoding", path.encoding)));
+        }
+    }
+    config.encoding.paths = paths;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Config, Vec<Problem>> {
+        match check(content, Path::new("config.toml")) {
+            (config, ref problems) if problems.is_empty() => Ok(config),
+            (_, problems) => Err(problems),
+        }
+    }
+
+    fn settings(problems: &[Problem]) -> Vec<Option<&str>> {
+        problems.iter().map(|p| p.setting.as_ref().map(|s| s.as_str())).collect()
+    }
+
+    #[test]
+    fn parse_empty_gives_defaults() {
+        let config = parse("").unwrap();
+        assert!(config.forges.is_empty());
+        assert!(config.api_tokens.is_empty());
+        assert_eq!(config.autofetch_minutes, 0);
+        assert!(!config.commit_all_tracked);
+    }
+
+    #[test]
+    fn parse_commit_all_tracked() {
+        let config = parse("commit_all_tracked = true").unwrap();
+        assert!(config.commit_all_tracked);
+    }
+
+    #[test]
+    fn parse_forges() {
+        let config = parse(r#"
+            [[forges]]
+            host = "git.example.com"
+            commit = "{base}/c/{commit}"
+            branch = "{base}/b/{branch}"
+            file = "{base}/f/{rev}/{path}"
+        "#).unwrap();
+
+        assert_eq!(config.forges.len(), 1);
+        assert_eq!(config.forges[0].host, "git.example.com");
+        assert_eq!(config.forges[0].commit, "{base}/c/{commit}");
+    }
+
+    #[test]
+    fn parse_api_tokens() {
+        let config = parse(r#"
+            [api_tokens]
+            "github.com" = "abc"
+        "#).unwrap();
+
+        assert_eq!(config.api_tokens.get("github.com").map(|t| t.as_str()), Some("abc"));
+    }
+
+    #[test]
+    fn parse_issue_trackers() {
+        let config = parse(r#"
+            [[issue_trackers]]
+            pattern = "{key}-{id}"
+            url = "https://jira.example.com/browse/{ref}"
+        "#).unwrap();
+
+        assert_eq!(config.issue_trackers[0].pattern, "{key}-{id}");
+    }
+
+    #[test]
+    fn parse_identities() {
+        let result = parse(r#"
+            [[identities]]
+            name = "work"
+            user_name = "Bob Smith"
+            email = "bob@acme.com"
+            signing_key = "1234ABCD"
+            remote_patterns = ["*github.com/acme/*"]
+
+            [[identities]]
+            name = "personal"
+            user_name = "Bob"
+            email = "bob@example.com"
+
+            [[identities]]
+            name = "work"
+            user_name = "Robert"
+            email = "robert@acme.com"
+        "#);
+
+        let problems = result.unwrap_err();
+        assert_eq!(settings(&problems), vec![Some("identities (entry 3)")]);
+        let (config, _) = check(r#"
+            [[identities]]
+            name = "personal"
+            user_name = "Bob"
+            email = "bob@example.com"
+        "#, Path::new("config.toml"));
+        assert_eq!(config.identities[0].signing_key, None);
+        assert!(config.identities[0].remote_patterns.is_empty());
+    }
+
+    #[test]
+    fn parse_large_repo() {
+        let config = parse(r#"
+            [large_repo]
+            mode = "always"
+            log_limit = 50
+        "#).unwrap();
+
+        assert_eq!(config.large_repo.mode, LargeRepoMode::Always);
+        assert_eq!(config.large_repo.log_limit, 50);
+        assert_eq!(config.large_repo.object_threshold, 1_000_000);
+    }
+
+    #[test]
+    fn parse_backups() {
+        let config = parse(r#"
+            [backups]
+            keep_days = 7
+        "#).unwrap();
+
+        assert_eq!(config.backups, BackupConfig { keep_days: 7, keep_at_least: 10 });
+    }
+
+    #[test]
+    fn parse_maintenance() {
+        let config = parse(r#"
+            [maintenance]
+            interval_days = 7
+            tasks = ["repack", "pack-refs"]
+        "#).unwrap();
+
+        assert_eq!(config.maintenance, MaintenanceConfig {
+            interval_days: 7,
+            tasks: vec![Task::Repack, Task::PackRefs],
+            loose_refs_hint: 1000,
+        });
+        assert_eq!(parse("").unwrap().maintenance.tasks, vec![Task::Gc]);
+        assert!(parse(r#"
+            [maintenance]
+            tasks = ["defrag"]
+        "#).is_err());
+    }
+
+    #[test]
+    fn parse_refresh() {
+        let config = parse(r#"
+            [refresh]
+            debounce_millis = 250
+        "#).unwrap();
+
+        assert_eq!(config.refresh, RefreshConfig { watch: true, debounce_millis: 250 });
+    }
+
+    #[test]
+    fn parse_retry() {
+        let config = parse(r#"
+            [retry]
+            retries = 5
+        "#).unwrap();
+
+        assert_eq!(config.retry, RetryConfig { retries: 5, backoff_millis: 2000 });
+    }
+
+    #[test]
+    fn parse_signing() {
+        let config = parse(r#"
+            [signing]
+            required_branches = ["main", "release/*"]
+            enforcement = "refuse"
+        "#).unwrap();
+
+        assert_eq!(config.signing.required_branches, vec!["main".to_string(), "release/*".to_string()]);
+        assert_eq!(config.signing.enforcement, Enforcement::Refuse);
+        assert_eq!(parse("").unwrap().signing.enforcement, Enforcement::Warn);
+    }
+
+    #[test]
+    fn parse_new_repo() {
+        let config = parse(r#"
+            [new_repo]
+            initial_branch = "main"
+            template_dir = "/home/me/templates"
+        "#).unwrap();
+
+        assert_eq!(config.new_repo, NewRepoConfig {
+            initial_branch: "main".to_string(),
+            template_dir: Some(PathBuf::from("/home/me/templates")),
+            ..NewRepoConfig::default()
+        });
+
+        let config = parse(r#"
+            [[identities]]
+            name = "work"
+            user_name = "Bob"
+            email = "bob@acme.com"
+
+            [new_repo]
+            identity = "work"
+            hooks_dir = "~/hooks"
+            lfs_install = true
+
+            [new_repo.git_config]
+            "pull.rebase" = "true"
+        "#).unwrap();
+
+        assert_eq!(config.new_repo.identity, Some("work".to_string()));
+        assert_eq!(config.new_repo.hooks_dir, Some(PathBuf::from("~/hooks")));
+        assert!(config.new_repo.lfs_install);
+        assert_eq!(config.new_repo.git_config.get("pull.rebase"), Some(&"true".to_string()));
+
+        let (config, problems) = check(r#"
+            [new_repo]
+            identity = "nobody"
+        "#, Path::new("config.toml"));
+        assert_eq!(settings(&problems), vec![Some("new_repo.identity")]);
+        assert_eq!(config.new_repo.identity, None);
+    }
+
+    #[test]
+    fn parse_encoding() {
+        let config = parse(r#"
+            [encoding]
+            fallback = "shift_jis"
+
+            [[encoding.paths]]
+            pattern = "legacy/*.txt"
+            encoding = "windows-1251"
+        "#).unwrap();
+
+        assert_eq!(config.encoding, EncodingConfig {
+            fallback: "shift_jis".to_string(),
+            paths: vec![PathEncoding { pattern: "legacy/*.txt".to_string(), encoding: "windows-1251".to_string() }],
+        });
+        assert_eq!(parse("").unwrap().encoding.fallback, "windows-1252");
+    }
+
+    #[test]
+    fn parse_highlighting() {
+        let config = parse(r#"
+            [highlighting]
+            enabled = false
+            theme = "InspiredGitHub"
+        "#).unwrap();
+
+        assert_eq!(config.highlighting, HighlightConfig { enabled: false, theme: "InspiredGitHub".to_string() });
+        assert_eq!(parse("").unwrap().highlighting, HighlightConfig::default());
+    }
+
+    #[test]
+    fn unknown_encodings_are_problems() {
+        let (config, problems) = check(r#"
+            [encoding]
+            fallback = "klingon"
+
+            [[encoding.paths]]
+            pattern = "*.txt"
+            encoding = "ebcdic-nonsense"
+        "#, Path::new("config.toml"));
+
+        assert_eq!(settings(&problems), vec![Some("encoding.fallback"), Some("encoding.paths (entry 1)")]);
+        assert_eq!(config.encoding, EncodingConfig::default());
+    }
+
+    #[test]
+    fn parse_display() {
+        assert_eq!(parse("").unwrap().display.path_ellipsis, Ellipsis::Start);
+        assert_eq!(parse("").unwrap().display.colours, ColourDepth::Auto);
+        let config = parse(r#"
+            [display]
+            path_ellipsis = "middle"
+            colours = "256"
+        "#).unwrap();
+
+        assert_eq!(config.display.path_ellipsis, Ellipsis::Middle);
+        assert_eq!(config.display.colours, ColourDepth::Extended);
+    }
+
+    #[test]
+    fn parse_dates() {
+        let config = parse(r#"
+            [dates]
+            log = "relative"
+            timezone = "local"
+        "#).unwrap();
+
+        assert_eq!(config.dates.log, DateStyle::Relative);
+        assert_eq!(config.dates.details, DateStyle::Iso);
+        assert_eq!(config.dates.timezone, DateZone::Local);
+    }
+
+    #[test]
+    fn parse_limits() {
+        assert_eq!(parse("").unwrap().limits.diff_limits().max_file_bytes, Some(10 * 1024 * 1024));
+        let config = parse(r#"
+            [limits]
+            max_file_bytes = 0
+            max_diff_lines = 1000
+        "#).unwrap();
+
+        assert_eq!(config.limits.diff_limits(), DiffLimits { max_file_bytes: None, max_lines: Some(1000), ..DiffLimits::unlimited() });
+    }
+
+    #[test]
+    fn parse_rejects_invalid_toml() {
+        assert!(parse("forges = 12").is_err());
+    }
+
+    #[test]
+    fn needs_restart_lists_the_settings_only_read_at_startup() {
+        let old = Config::default();
+        let new = parse(r#"
+            autofetch_minutes = 5
+            offline = true
+            [display]
+            screen_reader = true
+            colours = "256"
+            [limits]
+            max_diff_lines = 10
+        "#).unwrap();
+
+        assert!(needs_restart(&old, &old).is_empty());
+        assert_eq!(needs_restart(&old, &new), vec!["autofetch_minutes", "display.screen_reader"]);
+    }
+
+    #[test]
+    fn check_reports_a_syntax_error_and_uses_the_defaults() {
+        let (config, problems) = check("offline = true\n[display", Path::new("config.toml"));
+        assert!(!config.offline);
+        assert_eq!(settings(&problems), vec![None]);
+        assert_eq!(problems[0].file, Path::new("config.toml"));
+    }
+
+    #[test]
+    fn check_keeps_the_valid_settings_and_reports_the_rest() {
+        let (config, problems) = check(r#"
+            offline = true
+            colour = "256"
+            forges = 12
+
+            [display]
+            colours = "lots"
+            screen_reader = true
+
+            [limits]
+            max_diff_lines = -1
+            max_file_bytes = 100
+        "#, Path::new("config.toml"));
+
+        assert!(config.offline);
+        assert!(config.display.screen_reader);
+        assert_eq!(config.display.colours, ColourDepth::Auto);
+        assert_eq!(config.limits.max_file_bytes, 100);
+        assert_eq!(config.limits.max_diff_lines, 50_000);
+        assert_eq!(settings(&problems), vec![Some("colour"), Some("display.colours"), Some("forges"), Some("limits.max_diff_lines")]);
+        assert_eq!(problems[1].to_string(), "display.colours: unknown variant `lots`, expected one of `auto`, `16`, `256`, `truecolor`");
+    }
+
+    #[test]
+    fn check_drops_invalid_entries_in_lists() {
+        let (config, problems) = check(r##"
+            [[issue_trackers]]
+            pattern = "#{id}"
+            url = "{ref}"
+
+            [[issue_trackers]]
+            pattern = "JIRA"
+            url = "{ref}"
+
+            [[issue_trackers]]
+            pattern = "{key}-{id}"
+
+            [[forges]]
+            host = "git.example.com"
+            commit = "{base}/c/{commit}"
+            branch = "{base}/b/{branch}"
+            file = "{base}/f/{rev}/{path}"
+            blame = "{base}/b/{rev}/{path}"
+        "##, Path::new("config.toml"));
+
+        assert!(config.forges.is_empty());
+        assert_eq!(config.issue_trackers.len(), 1);
+        assert_eq!(config.issue_trackers[0].pattern, "#{id}");
+        assert_eq!(settings(&problems), vec![Some("forges (entry 1)"), Some("issue_trackers (entry 3)"), Some("issue_trackers (entry 2)")]);
+    }
+
+    #[test]
+    fn check_drops_changelog_groups_that_match_nothing() {
+        let (config, problems) = check(r#"
+            [changelog]
+            other = ""
+
+            [[changelog.groups]]
+            title = "New"
+            types = ["feat"]
+            patterns = ["Add*"]
+
+            [[changelog.groups]]
+            title = "Empty"
+        "#, Path::new("config.toml"));
+
+        assert_eq!(config.changelog.other, "");
+        assert_eq!(config.changelog.groups.len(), 1);
+        assert_eq!(config.changelog.groups[0].patterns, vec!["Add*"]);
+        assert_eq!(config.changelog.entry, ChangelogConfig::default().entry);
+        assert_eq!(settings(&problems), vec![Some("changelog.groups (entry 2)")]);
+    }
+
+    #[test]
+    fn check_rejects_an_invalid_date_format() {
+        let (config, problems) = check(r#"
+            [dates]
+            custom_format = "%Y-%Q"
+            log = "custom"
+        "#, Path::new("config.toml"));
+
+        assert_eq!(config.dates.custom_format, DateConfig::default().custom_format);
+        assert_eq!(config.dates.log, DateStyle::Custom);
+        assert_eq!(settings(&problems), vec![Some("dates.custom_format")]);
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn check_rejects_an_unknown_highlighting_theme() {
+        let (config, problems) = check(r#"
+            [highlighting]
+            theme = "Solarised"
+        "#, Path::new("config.toml"));
+
+        assert_eq!(config.highlighting, HighlightConfig::default());
+        assert_eq!(settings(&problems), vec![Some("highlighting.theme")]);
+    }
+}
@@ -0,0 +1,274 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use serde_json::{self, Value};
+
+// The error codes defined by JSON-RPC 2.0, and one for a call that failed.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const CALL_FAILED: i64 = 1;
+
+/// The methods a client can call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+    /// 'list_repos': the open repositories, see `RepoInfo`.
+    ListRepos,
+    /// 'current_branch': the branch of the current repository, or null if
+    /// HEAD is detached.
+    CurrentBranch,
+    /// 'refresh': reload the log of the current repository.
+    Refresh,
+    /// 'open' with `{"path": ...}`: open the repository at the path.
+    Open(PathBuf),
+}
+
+/// A repository in the result of 'list_repos'.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepoInfo {
+    pub name: String,
+    /// The path of the repository's .git directory.
+    pub path: String,
+    pub current: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    id: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenParams {
+    path: PathBuf,
+}
+
+fn parse_call(method: &str, params: Value) -> Result<Call, (i64, String)> {
+    match method {
+        "list_repos" => Ok(Call::ListRepos),
+        "current_branch" => Ok(Call::CurrentBranch),
+        "refresh" => Ok(Call::Refresh),
+        "open" => serde_json::from_value::<OpenParams>(params)
+            .map(|p| Call::Open(p.path))
+            .map_err(|e| (INVALID_PARAMS, format!("Invalid params: {}", e))),
+        _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    }
+}
+
+/// Answers one line of JSON-RPC from a client, calling `handler` to carry out
+/// the call. Returns the response to send back, which is None if the request
+/// was a notification, i.e. had no id.
+pub fn respond<F>(line: &str, handler: F) -> Option<String>
+    where F: FnOnce(Call) -> Result<Value, String>
+{
+    let request = match serde_json::from_str::<Value>(line) {
+        Ok(value) => value,
+        Err(e) => return Some(response(Value::Null, Err((PARSE_ERROR, format!("Parse error: {}", e))))),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let request = match serde_json::from_value::<Request>(request) {
+        Ok(ref r) if r.jsonrpc != "2.0" => return Some(response(id, Err((INVALID_REQUEST, "Invalid request: jsonrpc must be \"2.0\"".to_string())))),
+        Ok(r) => r,
+        Err(e) => return Some(response(id, Err((INVALID_REQUEST, format!("Invalid request: {}", e))))),
+    };
+
+    let result = parse_call(&request.method, request.params)
+        .and_then(|call| handler(call).map_err(|message| (CALL_FAILED, message)));
+    request.id.map(|id| response(id, result))
+}
+
+fn response(id: Value, result: Result<Value, (i64, String)>) -> String {
+    let mut response = serde_json::Map::new();
+    response.insert("jsonrpc".to_string(), Value::from("2.0"));
+    match result {
+        Ok(value) => { response.insert("result".to_string(), value); },
+        Err((code, message)) => {
+            let mut error = serde_json::Map::new();
+            error.insert("code".to_string(), Value::from(code));
+            error.insert("message".to_string(), Value::from(message));
+            response.insert("error".to_string(), Value::Object(error));
+        },
+    }
+    response.insert("id".to_string(), id);
+    Value::Object(response).to_string()
+}
+
+/// Listens on a Unix domain socket at `path`, replacing any socket left there
+/// by an oaf that did not exit cleanly, but not one that is in use, nor
+/// anything at `path` that is not a socket. Clients send one JSON-RPC 2.0
+/// request per line and get one response per line. `handler` is called on
+/// the thread serving the client.
+pub fn serve<F>(path: &Path, handler: F) -> io::Result<()>
+    where F: Fn(Call) -> Result<Value, String> + Send + Sync + 'static
+{
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "something other than a socket is there"));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another oaf is listening on it"));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("Listening for control requests on '{}'", path.display());
+
+    let handler = Arc::new(handler);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let handler = handler.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = serve_client(stream, &*handler) {
+                            warn!("Error talking to a control socket client: {}", e);
+                        }
+                    });
+                },
+                Err(e) => warn!("Error accepting a control socket connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn serve_client<F>(stream: UnixStream, handler: &F) -> io::Result<()>
+    where F: Fn(Call) -> Result<Value, String>
+{
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = respond(&line, |call| handler(call)) {
+            writeln!(writer, "{}", response)?;
+        }
+    }
+    Ok(())
+}
+
+/// Asks the oaf listening on `path` to open each of `dirs`, and returns what
+/// it said about each: the name of the repository, or why it could not be
+/// opened. Returns None if no oaf is listening there.
+pub fn forward_open(path: &Path, dirs: &[PathBuf]) -> Option<Vec<Result<String, String>>> {
+    let stream = UnixStream::connect(path).ok()?;
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+
+    Some(dirs.iter().enumerate().map(|(id, dir)| {
+        let dir = dir.to_str().ok_or_else(|| "The path is not valid UTF-8".to_string())?;
+        let mut params = serde_json::Map::new();
+        params.insert("path".to_string(), Value::from(dir));
+        let mut request = serde_json::Map::new();
+        request.insert("jsonrpc".to_string(), Value::from("2.0"));
+        request.insert("method".to_string(), Value::from("open"));
+        request.insert("params".to_string(), Value::Object(params));
+        request.insert("id".to_string(), Value::from(id));
+        writeln!(writer, "{}", Value::Object(request)).map_err(|e| e.to_string())?;
+
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        open_result(&line)
+    }).collect())
+}
+
+fn open_result(response: &str) -> Result<String, String> {
+    let response: Value = serde_json::from_str(response).map_err(|e| format!("Unexpected response: {}", e))?;
+    if let Some(name) = response["result"].as_str() {
+        return Ok(name.to_string());
+    }
+    match response["error"]["message"].as_str() {
+        Some(message) => Err(message.to_string()),
+        None => Err(format!("Unexpected response: {}", response)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(call: Call) -> Result<Value, String> {
+        match call {
+            Call::CurrentBranch => Ok(Value::from("master")),
+            Call::Open(ref path) if path == Path::new("/src/oaf") => Ok(Value::from("oaf")),
+            Call::Open(path) => Err(format!("Cannot open '{}'", path.display())),
+            _ => Ok(Value::Null),
+        }
+    }
+
+    #[test]
+    fn calls_are_answered() {
+        assert_eq!(respond(r#"{"jsonrpc":"2.0","method":"current_branch","id":1}"#, answer),
+                   Some(r#"{"id":1,"jsonrpc":"2.0","result":"master"}"#.to_string()));
+        assert_eq!(respond(r#"{"jsonrpc":"2.0","method":"refresh","params":[],"id":"a"}"#, answer),
+                   Some(r#"{"id":"a","jsonrpc":"2.0","result":null}"#.to_string()));
+    }
+
+    #[test]
+    fn notifications_get_no_response() {
+        assert_eq!(respond(r#"{"jsonrpc":"2.0","method":"refresh"}"#, answer), None);
+    }
+
+    #[test]
+    fn open_takes_a_path() {
+        let mut opened = None;
+        respond(r#"{"jsonrpc":"2.0","method":"open","params":{"path":"/src/oaf"}}"#, |call| {
+            opened = Some(call);
+            Ok(Value::Null)
+        });
+        assert_eq!(opened, Some(Call::Open(PathBuf::from("/src/oaf"))));
+    }
+
+    #[test]
+    fn errors_have_json_rpc_codes() {
+        let code = |line: &str| {
+            let response: Value = serde_json::from_str(&respond(line, answer).unwrap()).unwrap();
+            response["error"]["code"].as_i64()
+        };
+
+        assert_eq!(code("{"), Some(PARSE_ERROR));
+        assert_eq!(code(r#"{"jsonrpc":"1.0","method":"refresh","id":1}"#), Some(INVALID_REQUEST));
+        assert_eq!(code(r#"{"jsonrpc":"2.0","id":1}"#), Some(INVALID_REQUEST));
+        assert_eq!(code(r#"{"jsonrpc":"2.0","method":"quit","id":1}"#), Some(METHOD_NOT_FOUND));
+        assert_eq!(code(r#"{"jsonrpc":"2.0","method":"open","params":{},"id":1}"#), Some(INVALID_PARAMS));
+        assert_eq!(code(r#"{"jsonrpc":"2.0","method":"open","params":{"path":"x"},"id":1}"#), Some(CALL_FAILED));
+    }
+
+    #[test]
+    fn a_running_oaf_answers_over_the_socket() {
+        let path = ::std::env::temp_dir().join(format!("oaf-control-{}.sock", ::std::process::id()));
+        serve(&path, answer).unwrap();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(stream, r#"{{"jsonrpc":"2.0","method":"current_branch","id":7}}"#).unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "{\"id\":7,\"jsonrpc\":\"2.0\",\"result\":\"master\"}\n");
+
+        assert_eq!(forward_open(&path, &[PathBuf::from("/src/oaf"), PathBuf::from("/tmp")]), Some(vec![
+            Ok("oaf".to_string()),
+            Err("Cannot open '/tmp'".to_string()),
+        ]));
+        assert_eq!(serve(&path, answer).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        let _ = fs::remove_file(&path);
+        assert_eq!(forward_open(&path, &[PathBuf::from("/src/oaf")]), None);
+    }
+
+    #[test]
+    fn a_file_that_is_not_a_socket_is_left_alone() {
+        let path = ::std::env::temp_dir().join(format!("oaf-control-{}.txt", ::std::process::id()));
+        fs::write(&path, "keep me").unwrap();
+        assert_eq!(serve(&path, answer).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::{Local, TimeZone};
+use chrono::format::{Item, StrftimeItems};
+use git2::Time;
+use config::DateConfig;
+use history;
+
+/// How commit dates are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateStyle {
+    /// Just the date, e.g. '2018-09-10'.
+    Short,
+    /// The date and time with the timezone offset, e.g. '2018-09-10 14:03:22 +0100'.
+    Iso,
+    /// How long ago, e.g. '3 days ago'.
+    Relative,
+    /// Formatted with the config file's `custom_format`.
+    Custom,
+}
+
+/// Which timezone commit dates are shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateZone {
+    /// The timezone the author or committer was in, as recorded in the commit.
+    Author,
+    /// The timezone of this computer.
+    Local,
+}
+
+/// Formats a commit or tag time in `style`, using the timezone and custom
+/// format from `config`.
+pub fn format(time: &Time, style: DateStyle, config: &DateConfig) -> String {
+    format_at(time, style, config, Local::now().timestamp())
+}
+
+/// Whether chrono understands every specifier in the strftime `pattern`.
+/// Formatting with one it does not understand panics.
+pub fn is_valid_format(pattern: &str) -> bool {
+    StrftimeItems::new(pattern).all(|item| item != Item::Error)
+}
+
+fn format_at(time: &Time, style: DateStyle, config: &DateConfig, now: i64) -> String {
+    const ISO: &str = "%Y-%m-%d %H:%M:%S %z";
+    let pattern = match style {
+        DateStyle::Short => "%Y-%m-%d",
+        DateStyle::Iso => ISO,
+        DateStyle::Relative => return relative(now - time.seconds()),
+        // The config is checked when it is loaded, but one made in code
+        // is not, so a bad format still must not panic.
+        DateStyle::Custom if is_valid_format(&config.custom_format) => &config.custom_format,
+        DateStyle::Custom => ISO,
+    };
+
+    let formatted = match config.timezone {
+        DateZone::Author => history::recorded_time(time).map(|t| t.format(pattern).to_string()),
+        DateZone::Local => Local.timestamp_opt(time.seconds(), 0).single().map(|t| t.format(pattern).to_string()),
+    };
+    formatted.unwrap_or_else(|| raw(time))
+}
+
+/// A time as git records it, '<seconds> <offset>', for one chrono cannot
+/// represent.
+fn raw(time: &Time) -> String {
+    let minutes = time.offset_minutes();
+    let sign = if minutes < 0 { '-' } else { '+' };
+    format!("{} {}{:02}{:02}", time.seconds(), sign, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+/// Describes an age in seconds roughly, in the same steps as git's relative
+/// dates.
+fn relative(age: i64) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+
+    if age < 0 {
+        return "in the future".to_string();
+    }
+
+    let (n, unit) = if age < 90 {
+        (age, "second")
+    } else if age < 90 * MINUTE {
+        ((age + MINUTE / 2) / MINUTE, "minute")
+    } else if age < 36 * HOUR {
+        ((age + HOUR / 2) / HOUR, "hour")
+    } else if age < 14 * DAY {
+        ((age + DAY / 2) / DAY, "day")
+    } else if age < 70 * DAY {
+        ((age + 7 * DAY / 2) / (7 * DAY), "week")
+    } else if age < 365 * DAY {
+        ((age + 15 * DAY) / (30 * DAY), "month")
+    } else {
+        (age / (365 * DAY), "year")
+    };
+
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2018-09-10 13:03:22 UTC, recorded in +01:00.
+    fn time() -> Time {
+        Time::new(1_536_584_602, 60)
+    }
+
+    fn config(timezone: DateZone) -> DateConfig {
+        DateConfig { timezone, custom_format: "%d %b %Y %H:%M".to_string(), ..DateConfig::default() }
+    }
+
+    #[test]
+    fn fixed_formats_use_author_timezone() {
+        let config = config(DateZone::Author);
+        assert_eq!(format(&time(), DateStyle::Short, &config), "2018-09-10");
+        assert_eq!(format(&time(), DateStyle::Iso, &config), "2018-09-10 14:03:22 +0100");
+        assert_eq!(format(&time(), DateStyle::Custom, &config), "10 Sep 2018 14:03");
+    }
+
+    #[test]
+    fn an_invalid_custom_format_falls_back_to_iso() {
+        let config = DateConfig { custom_format: "%Y-%Q".to_string(), ..config(DateZone::Author) };
+        assert!(!is_valid_format(&config.custom_format));
+        assert_eq!(format(&time(), DateStyle::Custom, &config), "2018-09-10 14:03:22 +0100");
+    }
+
+    #[test]
+    fn bogus_offsets_and_timestamps_do_not_panic() {
+        // +9900, as some tools have recorded.
+        let bogus_offset = Time::new(1_536_584_602, 99 * 60);
+        assert_eq!(format(&bogus_offset, DateStyle::Iso, &config(DateZone::Author)), "2018-09-10 13:03:22 +0000");
+        let bogus_time = Time::new(i64::max_value(), 99 * 60);
+        assert_eq!(format(&bogus_time, DateStyle::Iso, &config(DateZone::Author)), format!("{} +9900", i64::max_value()));
+        assert_eq!(format(&bogus_time, DateStyle::Short, &config(DateZone::Local)), format!("{} +9900", i64::max_value()));
+    }
+
+    #[test]
+    fn relative_dates() {
+        let config = config(DateZone::Author);
+        let now = time().seconds();
+        assert_eq!(format_at(&time(), DateStyle::Relative, &config, now + 5), "5 seconds ago");
+        assert_eq!(format_at(&time(), DateStyle::Relative, &config, now + 3600), "60 minutes ago");
+        assert_eq!(format_at(&time(), DateStyle::Relative, &config, now + 3 * 86400), "3 days ago");
+        assert_eq!(format_at(&time(), DateStyle::Relative, &config, now + 21 * 86400), "3 weeks ago");
+        assert_eq!(format_at(&time(), DateStyle::Relative, &config, now + 400 * 86400), "1 year ago");
+        assert_eq!(format_at(&time(), DateStyle::Relative, &config, now - 10), "in the future");
+    }
+}
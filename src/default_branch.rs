@@ -0,0 +1,155 @@
+//! A remote's default branch: the branch its HEAD points at, recorded
+//! locally as the symbolic ref 'refs/remotes/<remote>/HEAD'. A clone records
+//! it, but fetching never updates it, so when the remote's default changes,
+//! say from 'master' to 'main', the local record goes stale. libgit2 cannot
+//! ask a remote what its HEAD points at, so 'git ls-remote' is used for
+//! that.
+
+use git2::{BranchType, Error, Repository};
+use commit_graph::CommitGraph;
+use error::OafError;
+use remotes;
+
+/// What a remote's default branch is recorded as locally and what the
+/// remote says it is now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detected {
+    pub remote: String,
+    /// The recorded default, e.g. 'main', if there is one.
+    pub recorded: Option<String>,
+    /// The remote's default, if it has one, e.g. it is not empty.
+    pub actual: Option<String>,
+}
+
+impl Detected {
+    /// Whether the record is missing or out of date.
+    pub fn changed(&self) -> bool {
+        self.actual.is_some() && self.actual != self.recorded
+    }
+
+    /// Tells the user about a changed default branch, and how to follow it.
+    pub fn advice(&self) -> Option<String> {
+        if !self.changed() {
+            return None;
+        }
+        let actual = self.actual.as_ref().map(|a| a.as_str()).unwrap_or("");
+        Some(match self.recorded {
+            Some(ref recorded) => format!("The default branch of '{}' is now '{}', not '{}'; use Remote > Update Default \
+                                           Branch to follow it.", self.remote, actual, recorded),
+            None => format!("The default branch of '{}' is '{}' but is not recorded; use Remote > Update Default \
+                             Branch to record it.", self.remote, actual),
+        })
+    }
+}
+
+fn head_ref(remote: &str) -> String {
+    format!("refs/remotes/{}/HEAD", remote)
+}
+
+/// The remote whose default branch matters for the current branch: the
+/// remote of its upstream, else 'origin', else the first remote.
+pub fn main_remote(repo: &Repository) -> Option<String> {
+    let names = remotes::remote_names(repo);
+    remotes::current_branch_name(repo)
+        .and_then(|branch| remotes::upstream_of(repo, &branch))
+        .map(|upstream| upstream.remote)
+        .or_else(|| names.iter().find(|n| *n == "origin").cloned())
+        .or_else(|| names.into_iter().next())
+}
+
+/// The default branch of `remote` as recorded locally, e.g. 'main'.
+pub fn recorded(repo: &Repository, remote: &str) -> Option<String> {
+    let reference = repo.find_reference(&head_ref(remote)).ok()?;
+    let target = reference.symbolic_target()?;
+    let prefix = format!("refs/remotes/{}/", remote);
+    if target.starts_with(&prefix) { Some(target[prefix.len()..].to_string()) } else { None }
+}
+
+/// The recorded default branch of the main remote as a remote-tracking
+/// branch name, e.g. 'origin/main', for use as a base to compare with.
+pub fn default_base(repo: &Repository) -> Option<String> {
+    let remote = main_remote(repo)?;
+    recorded(repo, &remote).map(|branch| format!("{}/{}", remote, branch))
+}
+
+/// How far HEAD is behind the main remote's default branch: the branch, e.g.
+/// 'origin/main', and the number of commits. None if there is no default or
+/// HEAD is not behind it.
+pub fn behind_default(repo: &Repository, graph: &mut CommitGraph) -> Option<(String, usize)> {
+    let base = default_base(repo)?;
+    let head = repo.head().ok()?.target()?;
+    let default = repo.find_branch(&base, BranchType::Remote).ok()?.get().target()?;
+    match graph.ahead_behind(repo, head, default) {
+        Ok((_, behind)) if behind > 0 => Some((base, behind)),
+        _ => None,
+    }
+}
+
+/// Asks `remote` what its default branch is, and compares that with the
+/// record.
+pub fn detect(repo: &Repository, remote: &str) -> Result<Detected, OafError> {
+    let _timer = timer!("default_branch::detect");
+    let output = remotes::ls_remote(repo, &["--symref", remote, "HEAD"])?;
+
+    let detected = Detected {
+        remote: remote.to_string(),
+        recorded: recorded(repo, remote),
+        actual: parse_symref(&output),
+    };
+    _timer.set_message(format!("{:?}", detected));
+    Ok(detected)
+}
+
+/// The branch that HEAD points at in the output of 'git ls-remote --symref
+/// <remote> HEAD', whose first line is e.g. 'ref: refs/heads/main\tHEAD'.
+fn parse_symref(output: &str) -> Option<String> {
+    output.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '\t');
+            match (parts.next(), parts.next()) {
+                (Some(target), Some("HEAD")) if target.starts_with("ref: refs/heads/") => {
+                    Some(target["ref: refs/heads/".len()..].to_string())
+                },
+                _ => None,
+            }
+        })
+        .next()
+}
+
+/// Records `branch` as the default branch of `remote`. Its remote-tracking
+/// branch must exist, so fetch first.
+pub fn update(repo: &Repository, remote: &str, branch: &str) -> Result<(), Error> {
+    let target = format!("refs/remotes/{}/{}", remote, branch);
+    if repo.find_reference(&target).is_err() {
+        return Err(Error::from_str(&format!("There is no '{}/{}'; fetch from '{}' first.", remote, branch, remote)));
+    }
+    repo.reference_symbolic(&head_ref(remote), &target, true, &format!("oaf: default branch of {} is {}", remote, branch))?;
+    info!("Recorded '{}' as the default branch of '{}'", branch, remote);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_symref_finds_the_branch_head_points_at() {
+        let output = "ref: refs/heads/main\tHEAD\n0123456789abcdef0123456789abcdef01234567\tHEAD\n";
+        assert_eq!(parse_symref(output), Some("main".to_string()));
+        assert_eq!(parse_symref("0123456789abcdef0123456789abcdef01234567\tHEAD\n"), None);
+        assert_eq!(parse_symref(""), None);
+    }
+
+    #[test]
+    fn advice_only_when_the_default_changed() {
+        let detected = |recorded: Option<&str>, actual: Option<&str>| Detected {
+            remote: "origin".to_string(),
+            recorded: recorded.map(|r| r.to_string()),
+            actual: actual.map(|a| a.to_string()),
+        };
+        assert_eq!(detected(Some("main"), Some("main")).advice(), None);
+        assert_eq!(detected(Some("main"), None).advice(), None);
+        assert!(detected(Some("master"), Some("main")).advice().unwrap().contains("is now 'main', not 'master'"));
+        assert!(detected(None, Some("main")).advice().unwrap().contains("not recorded"));
+    }
+}
@@ -0,0 +1,218 @@
+//! The lines added and removed in each file of a diff, totalled for each
+//! directory, so that a change across a large tree can be reviewed a
+//! directory at a time.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use git2::{self, Delta, Diff};
+
+/// How much changed in one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    pub path: PathBuf,
+    pub added: usize,
+    pub removed: usize,
+    /// Binary files, and those over the size limit, have no lines to count.
+    pub binary: bool,
+}
+
+/// How much changed in a directory and everything below it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub files: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl Totals {
+    fn add(&mut self, file: &FileStat) {
+        self.files += 1;
+        self.added += file.added;
+        self.removed += file.removed;
+    }
+
+    /// E.g. '3 files changed, 120 insertions(+), 40 deletions(-)', as git
+    /// puts it.
+    pub fn description(&self) -> String {
+        let plural = |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+        format!("{} changed, {}(+), {}(-)", plural(self.files, "file", "files"),
+                plural(self.added, "insertion", "insertions"), plural(self.removed, "deletion", "deletions"))
+    }
+}
+
+/// A directory of changed files, with the totals for everything below it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirStat {
+    /// The path relative to the root of the working tree; empty for the root.
+    pub path: PathBuf,
+    pub dirs: Vec<DirStat>,
+    pub files: Vec<FileStat>,
+    pub totals: Totals,
+}
+
+/// A row of the diffstat tree: a directory or a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatRow {
+    /// `depth` is how many directories the row is nested in.
+    Dir { path: PathBuf, totals: Totals, depth: usize, expanded: bool },
+    File { stat: FileStat, depth: usize },
+}
+
+impl StatRow {
+    pub fn path(&self) -> &Path {
+        match *self {
+            StatRow::Dir { ref path, .. } => path,
+            StatRow::File { ref stat, .. } => &stat.path,
+        }
+    }
+
+    /// The lines added and removed, in the directory or file.
+    pub fn changes(&self) -> (usize, usize) {
+        match *self {
+            StatRow::Dir { totals, .. } => (totals.added, totals.removed),
+            StatRow::File { ref stat, .. } => (stat.added, stat.removed),
+        }
+    }
+}
+
+/// Counts the changes in each file of `diff` and builds the tree of them.
+pub fn tree(diff: &Diff) -> Result<DirStat, ::git2::Error> {
+    let _timer = timer!("diffstat::tree");
+    let mut root = DirStat::default();
+    for index in 0..diff.deltas().len() {
+        if let Some(file) = file_stat(diff, index)? {
+            root.add(file);
+        }
+    }
+    Ok(root)
+}
+
+fn file_stat(diff: &Diff, index: usize) -> Result<Option<FileStat>, ::git2::Error> {
+    let delta = match diff.get_delta(index) {
+        Some(delta) => delta,
+        None => return Ok(None),
+    };
+    if delta.status() == Delta::Unmodified {
+        return Ok(None);
+    }
+    let (old_file, new_file) = (delta.old_file(), delta.new_file());
+    let path = match new_file.path().or_else(|| old_file.path()) {
+        Some(path) => path.to_path_buf(),
+        None => return Ok(None),
+    };
+
+    // libgit2 makes no hunks for a binary file, or none at all.
+    let (added, removed, binary) = match git2::Patch::from_diff(diff, index)? {
+        Some(patch) => {
+            let (_, added, removed) = patch.line_stats()?;
+            let binary = patch.num_hunks() == 0 && old_file.id() != new_file.id() && old_file.size().max(new_file.size()) > 0;
+            (added, removed, binary)
+        },
+        None => (0, 0, true),
+    };
+    Ok(Some(FileStat { path, added, removed, binary }))
+}
+
+impl DirStat {
+    fn add(&mut self, file: FileStat) {
+        self.totals.add(&file);
+        let dir = match file.path.strip_prefix(&self.path).ok().and_then(|rest| rest.iter().next()) {
+            Some(first) if self.path.join(first) != file.path => self.path.join(first),
+            _ => return self.files.push(file),
+        };
+
+        match self.dirs.iter_mut().position(|d| d.path == dir) {
+            Some(i) => self.dirs[i].add(file),
+            None => {
+                let mut child = DirStat { path: dir, ..DirStat::default() };
+                child.add(file);
+                self.dirs.push(child);
+            },
+        }
+    }
+
+    /// The rows to show: the directories, then the files. The contents of
+    /// a directory are only shown if it is in `expanded`.
+    pub fn rows(&self, expanded: &HashSet<PathBuf>) -> Vec<StatRow> {
+        let mut rows = Vec::new();
+        self.add_rows(0, expanded, &mut rows);
+        rows
+    }
+
+    fn add_rows(&self, depth: usize, expanded: &HashSet<PathBuf>, rows: &mut Vec<StatRow>) {
+        for dir in &self.dirs {
+            let is_expanded = expanded.contains(&dir.path);
+            rows.push(StatRow::Dir { path: dir.path.clone(), totals: dir.totals, depth, expanded: is_expanded });
+            if is_expanded {
+                dir.add_rows(depth + 1, expanded, rows);
+            }
+        }
+        for file in &self.files {
+            rows.push(StatRow::File { stat: file.clone(), depth });
+        }
+    }
+}
+
+/// The '+++--' graph git draws for a change, scaled so that the biggest of
+/// the changes, `most` lines, takes up `width` characters. Any change at all
+/// gets at least one character.
+pub fn graph(added: usize, removed: usize, most: usize, width: usize) -> (String, String) {
+    let scale = |n: usize| if n == 0 || most <= width { n } else { (n * width / most).max(1) };
+    ("+".repeat(scale(added)), "-".repeat(scale(removed)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, added: usize, removed: usize) -> FileStat {
+        FileStat { path: PathBuf::from(path), added, removed, binary: false }
+    }
+
+    fn tree() -> DirStat {
+        let mut root = DirStat::default();
+        root.add(file("README.md", 1, 0));
+        root.add(file("src/lib.rs", 10, 2));
+        root.add(file("src/tui/view.rs", 5, 5));
+        root.add(file("src/tui/menu.rs", 0, 3));
+        root
+    }
+
+    #[test]
+    fn totals_cover_everything_below_a_directory() {
+        let root = tree();
+        assert_eq!(root.totals, Totals { files: 4, added: 16, removed: 10 });
+        let src = &root.dirs[0];
+        assert_eq!(src.path, Path::new("src"));
+        assert_eq!(src.totals, Totals { files: 3, added: 15, removed: 10 });
+        assert_eq!(src.dirs[0].totals, Totals { files: 2, added: 5, removed: 8 });
+        assert_eq!(root.files, vec![file("README.md", 1, 0)]);
+    }
+
+    #[test]
+    fn rows_show_expanded_directories() {
+        let root = tree();
+        let rows = root.rows(&HashSet::new());
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], StatRow::Dir { path: PathBuf::from("src"), totals: root.dirs[0].totals, depth: 0, expanded: false });
+        assert_eq!(rows[1].path(), Path::new("README.md"));
+
+        let expanded = vec![PathBuf::from("src"), PathBuf::from("src/tui")].into_iter().collect();
+        let paths: Vec<_> = root.rows(&expanded).iter().map(|r| r.path().to_path_buf()).collect();
+        assert_eq!(paths, vec![PathBuf::from("src"), PathBuf::from("src/tui"), PathBuf::from("src/tui/view.rs"),
+                               PathBuf::from("src/tui/menu.rs"), PathBuf::from("src/lib.rs"), PathBuf::from("README.md")]);
+    }
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(tree().totals.description(), "4 files changed, 16 insertions(+), 10 deletions(-)");
+        assert_eq!(Totals { files: 1, added: 1, removed: 0 }.description(), "1 file changed, 1 insertion(+), 0 deletions(-)");
+    }
+
+    #[test]
+    fn graphs_are_scaled_to_the_biggest_change() {
+        assert_eq!(graph(3, 2, 10, 20), ("+++".to_string(), "--".to_string()));
+        assert_eq!(graph(100, 1, 200, 20), ("+".repeat(10), "-".to_string()));
+        assert_eq!(graph(0, 0, 200, 20), (String::new(), String::new()));
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use git2::{build::CheckoutBuilder, Error, Repository, Status, StatusOptions};
+use error::OafError;
+use trash;
+
+/// Returns the files whose working tree content differs from the index.
+pub fn modified_files(repo: &Repository) -> Result<Vec<PathBuf>, OafError> {
+    files_with_status(repo, Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_TYPECHANGE, false)
+}
+
+/// Returns the untracked files, not including ignored ones.
+pub fn untracked_files(repo: &Repository) -> Result<Vec<PathBuf>, OafError> {
+    files_with_status(repo, Status::WT_NEW, true)
+}
+
+fn files_with_status(repo: &Repository, wanted: Status, untracked: bool) -> Result<Vec<PathBuf>, OafError> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(untracked)
+        .recurse_untracked_dirs(untracked)
+        .include_ignored(false)
+        .exclude_submodules(true);
+
+    let statuses = repo.statuses(Some(&mut opts)).map_err(OafError::Index)?;
+    Ok(statuses.iter()
+        .filter(|e| e.status().intersects(wanted))
+        .filter_map(|e| e.path().map(PathBuf::from))
+        .collect())
+}
+
+/// Throws away the working tree changes to `path`, making it match the index
+/// again. Whatever is there now, be it a file, a symlink or a directory that
+/// replaced one, is moved to the trash first so that it can be restored.
+pub fn discard_changes(repo: &Repository, trash_dir: &Path, path: &Path) -> Result<(), OafError> {
+    let workdir = working_tree(repo)?;
+    if fs::symlink_metadata(workdir.join(path)).is_ok() {
+        trash::move_to_trash(trash_dir, workdir, path, "discard changes")?;
+    }
+
+    repo.checkout_index(None, Some(CheckoutBuilder::new().force().path(path))).map_err(OafError::Index)
+}
+
+/// Moves all the untracked files into the trash. Returns how many there were.
+pub fn delete_untracked(repo: &Repository, trash_dir: &Path) -> Result<usize, OafError> {
+    let files = untracked_files(repo)?;
+    delete_files(repo, trash_dir, &files)
+}
+
+/// Moves the given untracked files into the trash, e.g. those in one
+/// directory. Returns how many there were.
+pub fn delete_files(repo: &Repository, trash_dir: &Path, files: &[PathBuf]) -> Result<usize, OafError> {
+    let workdir = working_tree(repo)?;
+    for path in files {
+        trash::move_to_trash(trash_dir, workdir, path, "delete untracked")?;
+    }
+    Ok(files.len())
+}
+
+fn working_tree(repo: &Repository) -> Result<&Path, OafError> {
+    repo.workdir().ok_or_else(|| OafError::Index(Error::from_str("A bare repository has no working tree.")))
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use git2::{self, ErrorClass, ErrorCode};
+use error_report::{ErrorReport, IntoErrorReport};
+
+/// Why a repository or git operation failed. The variant says what kind of
+/// thing went wrong, so that the UI can suggest what to do about it and
+/// tests can check for a particular failure; the git or IO error it wraps
+/// says exactly what.
+#[derive(Debug)]
+pub enum OafError {
+    /// The directory at `path` is not a repository or could not be read.
+    RepoOpen { path: PathBuf, error: git2::Error },
+    /// The repository at the path is open already.
+    AlreadyOpen(PathBuf),
+    /// Talking to a remote failed, or oaf is in offline mode.
+    Network(git2::Error),
+    /// The index or the working tree could not be read or updated.
+    Index(git2::Error),
+    /// Trees could not be merged, or merged with conflicts.
+    Merge(git2::Error),
+    /// A file oaf keeps, such as the MRU list, or a file being moved to the
+    /// trash could not be read or written.
+    Io(io::Error),
+    /// A git config setting could not be read or written, or is not valid.
+    GitConfig(git2::Error),
+    /// Any other failure reported by git.
+    Git(git2::Error),
+    /// The user cancelled the operation before it finished.
+    Cancelled,
+}
+
+impl OafError {
+    /// A short description of the kind of failure, used as the heading of
+    /// error dialogs and in logs.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            OafError::RepoOpen { .. } | OafError::AlreadyOpen(_) => "Repository",
+            OafError::Network(_) => "Network",
+            OafError::Index(_) => "Index",
+            OafError::Merge(_) => "Merge",
+            OafError::Io(_) => "File",
+            OafError::GitConfig(_) => "Git config",
+            OafError::Git(_) => "Git",
+            OafError::Cancelled => "Cancelled",
+        }
+    }
+
+    /// What the user can do about the failure, if there is anything obvious.
+    pub fn hint(&self) -> Option<&'static str> {
+        match *self {
+            OafError::RepoOpen { .. } => Some("Check that the directory exists and is inside a Git repository."),
+            OafError::Network(_) => Some("Check your network connection, proxy settings and credentials, and that offline mode is off."),
+            OafError::Index(_) => Some("Another program may be using the repository; if a stale 'index.lock' file exists, remove it."),
+            OafError::Merge(_) => Some("Resolve the conflicts with git on the command line, then try again."),
+            OafError::GitConfig(_) => Some("Check the repository's git config, e.g. with 'git config --list --show-origin'."),
+            OafError::AlreadyOpen(_) | OafError::Io(_) | OafError::Git(_) | OafError::Cancelled => None,
+        }
+    }
+
+    /// Sorts an error from talking to a remote. Authentication and
+    /// certificate failures are network errors whatever their class; the
+    /// rest are sorted by class, so that e.g. pushing a ref that does not
+    /// exist is not blamed on the network.
+    pub fn from_remote(error: git2::Error) -> Self {
+        match error.code() {
+            ErrorCode::Auth | ErrorCode::Certificate => OafError::Network(error),
+            _ => OafError::from(error),
+        }
+    }
+
+    fn git_error(&self) -> Option<&git2::Error> {
+        match *self {
+            OafError::RepoOpen { ref error, .. } => Some(error),
+            OafError::Network(ref e) | OafError::Index(ref e) | OafError::Merge(ref e) | OafError::GitConfig(ref e) |
+            OafError::Git(ref e) => Some(e),
+            OafError::AlreadyOpen(_) | OafError::Io(_) | OafError::Cancelled => None,
+        }
+    }
+}
+
+impl fmt::Display for OafError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OafError::RepoOpen { ref path, ref error } =>
+                write!(f, "Cannot open a repository at '{}': {}", path.display(), error.message()),
+            OafError::AlreadyOpen(ref path) => write!(f, "The repository at '{}' is already open.", path.display()),
+            OafError::Io(ref e) => write!(f, "{}", e),
+            OafError::Cancelled => write!(f, "The operation was cancelled."),
+            OafError::Network(ref e) | OafError::Index(ref e) | OafError::Merge(ref e) | OafError::GitConfig(ref e) |
+            OafError::Git(ref e) => write!(f, "{}", e.message()),
+        }
+    }
+}
+
+/// Sorts a git error by its class, so that `?` gives the right variant.
+impl From<git2::Error> for OafError {
+    fn from(error: git2::Error) -> Self {
+        let class = error.class();
+        sort(error, class)
+    }
+}
+
+fn sort(error: git2::Error, class: ErrorClass) -> OafError {
+    match class {
+        ErrorClass::Net | ErrorClass::Ssl | ErrorClass::Ssh | ErrorClass::Http => OafError::Network(error),
+        ErrorClass::Index | ErrorClass::Checkout => OafError::Index(error),
+        ErrorClass::Merge | ErrorClass::CherryPick | ErrorClass::Rebase => OafError::Merge(error),
+        ErrorClass::Config => OafError::GitConfig(error),
+        _ => OafError::Git(error),
+    }
+}
+
+impl From<io::Error> for OafError {
+    fn from(error: io::Error) -> Self {
+        OafError::Io(error)
+    }
+}
+
+impl IntoErrorReport for OafError {
+    fn into_report(self, operation: &str) -> ErrorReport {
+        let mut report = ErrorReport::new(operation, self.to_string()).with_detail("Kind", self.kind());
+        if let Some(e) = self.git_error() {
+            report = report
+                .with_detail("Git error class", format!("{:?} ({})", e.class(), e.raw_class()))
+                .with_detail("Git error code", format!("{:?} ({})", e.code(), e.raw_code()));
+        }
+
+        match self {
+            OafError::RepoOpen { ref path, .. } | OafError::AlreadyOpen(ref path) => {
+                report = report.with_detail("Path", path.display());
+            },
+            OafError::Io(ref e) => {
+                report = report.with_detail("IO error kind", format!("{:?}", e.kind()));
+                if let Some(code) = e.raw_os_error() {
+                    report = report.with_detail("OS error code", code);
+                }
+            },
+            _ => {},
+        }
+
+        match self.hint() {
+            Some(hint) => report.with_detail("Hint", hint),
+            None => report,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn git_errors_are_sorted_by_class() {
+        match sort(git2::Error::from_str("timed out"), ErrorClass::Net) {
+            OafError::Network(e) => assert_eq!(e.message(), "timed out"),
+            other => panic!("Expected a network error, got {:?}", other),
+        }
+
+        assert_eq!(sort(git2::Error::from_str("index.lock exists"), ErrorClass::Index).kind(), "Index");
+        assert_eq!(sort(git2::Error::from_str("conflicts"), ErrorClass::Merge).kind(), "Merge");
+        assert_eq!(OafError::from(git2::Error::from_str("no such ref")).kind(), "Git");
+        assert_eq!(sort(git2::Error::from_str("bad config line"), ErrorClass::Config).kind(), "Git config");
+        assert_eq!(OafError::from_remote(git2::Error::from_str("src refspec does not match")).kind(), "Git");
+    }
+
+    #[test]
+    fn reports_include_path_and_hint() {
+        let error = OafError::RepoOpen { path: PathBuf::from("/src/x"), error: git2::Error::from_str("not a repository") };
+        assert_eq!(error.to_string(), "Cannot open a repository at '/src/x': not a repository");
+        let report = error.into_report("Open repository");
+        assert!(report.details.contains(&("Path".to_string(), "/src/x".to_string())));
+        assert!(report.details.iter().any(|&(ref name, _)| name == "Hint"));
+    }
+}
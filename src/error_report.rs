@@ -0,0 +1,95 @@
+use std::fmt;
+use std::io;
+use git2;
+
+/// Everything known about a failed operation: a short message for the user
+/// and the details that help work out what went wrong, such as the git error
+/// class and code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorReport {
+    /// What was being done, e.g. 'Push tag'.
+    pub operation: String,
+    pub message: String,
+    pub details: Vec<(String, String)>,
+}
+
+impl ErrorReport {
+    pub fn new<O, M>(operation: O, message: M) -> Self
+        where O: Into<String>, M: Into<String>
+    {
+        ErrorReport { operation: operation.into(), message: message.into(), details: Vec::new() }
+    }
+
+    pub fn with_detail<N, V>(mut self, name: N, value: V) -> Self
+        where N: Into<String>, V: ToString
+    {
+        self.details.push((name.into(), value.to_string()));
+        self
+    }
+
+    /// The details as 'Name: value' lines, starting with the operation. This
+    /// is what is shown when the details are expanded and what is copied.
+    pub fn details_text(&self) -> String {
+        let mut text = format!("Operation: {}\nMessage: {}\n", self.operation, self.message);
+        for &(ref name, ref value) in &self.details {
+            text.push_str(&format!("{}: {}\n", name, value));
+        }
+        text
+    }
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} failed: {}", self.operation, self.message)
+    }
+}
+
+/// Converts an error into a report for `operation`.
+pub trait IntoErrorReport {
+    fn into_report(self, operation: &str) -> ErrorReport;
+}
+
+impl IntoErrorReport for git2::Error {
+    fn into_report(self, operation: &str) -> ErrorReport {
+        ErrorReport::new(operation, self.message())
+            .with_detail("Git error class", format!("{:?} ({})", self.class(), self.raw_class()))
+            .with_detail("Git error code", format!("{:?} ({})", self.code(), self.raw_code()))
+    }
+}
+
+impl IntoErrorReport for io::Error {
+    fn into_report(self, operation: &str) -> ErrorReport {
+        let report = ErrorReport::new(operation, self.to_string())
+            .with_detail("IO error kind", format!("{:?}", self.kind()));
+        match self.raw_os_error() {
+            Some(code) => report.with_detail("OS error code", code),
+            None => report,
+        }
+    }
+}
+
+impl IntoErrorReport for String {
+    fn into_report(self, operation: &str) -> ErrorReport {
+        ErrorReport::new(operation, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn git_errors_record_class_and_code() {
+        let report = git2::Error::from_str("bad ref").into_report("Push tag");
+        assert_eq!(report.to_string(), "Push tag failed: bad ref");
+        assert_eq!(report.details_text(), "Operation: Push tag\nMessage: bad ref\n\
+                                           Git error class: None (0)\nGit error code: GenericError (-1)\n");
+    }
+
+    #[test]
+    fn io_errors_record_kind_and_os_code() {
+        let report = io::Error::from_raw_os_error(2).into_report("Empty trash");
+        assert_eq!(report.details[0], ("IO error kind".to_string(), "NotFound".to_string()));
+        assert_eq!(report.details[1], ("OS error code".to_string(), "2".to_string()));
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use error::OafError;
+use text_width;
+
+/// The formats the content of a view can be exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Aligned columns, for reading.
+    Text,
+    Csv,
+    /// An array with an object for each row.
+    Json,
+}
+
+impl Format {
+    /// The format for a file: CSV or JSON if its extension says so, else
+    /// plain text.
+    pub fn for_path(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) {
+            Some(ref e) if e == "csv" => Format::Csv,
+            Some(ref e) if e == "json" => Format::Json,
+            _ => Format::Text,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Format::Text => "text",
+            Format::Csv => "CSV",
+            Format::Json => "JSON",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The content of a view, as rows of named columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(columns: Vec<&'static str>) -> Self {
+        Table { columns, rows: Vec::new() }
+    }
+
+    /// Adds a row, which must have a value for each column.
+    pub fn add_row(&mut self, row: Vec<String>) {
+        debug_assert_eq!(row.len(), self.columns.len());
+        self.rows.push(row);
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.to_text(),
+            Format::Csv => self.to_csv(),
+            Format::Json => self.to_json(),
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let mut widths: Vec<usize> = self.columns.iter().map(|c| text_width::width(c)).collect();
+        for row in &self.rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(text_width::width(value));
+            }
+        }
+
+        let line = |values: Vec<&str>| {
+            let last = values.len() - 1;
+            let cells: Vec<String> = values.iter().enumerate()
+                .map(|(i, v)| if i == last { v.to_string() } else { text_width::pad(v, widths[i]) })
+                .collect();
+            cells.join("  ").trim_end().to_string() + "\n"
+        };
+
+        let mut text = line(self.columns.clone());
+        for row in &self.rows {
+            text.push_str(&line(row.iter().map(|v| v.as_str()).collect()));
+        }
+        text
+    }
+
+    fn to_csv(&self) -> String {
+        let line = |values: Vec<&str>| values.iter().map(|v| csv_field(v)).collect::<Vec<_>>().join(",") + "\n";
+        let mut text = line(self.columns.clone());
+        for row in &self.rows {
+            text.push_str(&line(row.iter().map(|v| v.as_str()).collect()));
+        }
+        text
+    }
+
+    fn to_json(&self) -> String {
+        let objects: Vec<String> = self.rows.iter()
+            .map(|row| {
+                let fields: Vec<String> = self.columns.iter().zip(row)
+                    .map(|(c, v)| format!("{}: {}", json_string(c), json_string(v)))
+                    .collect();
+                format!("  {{{}}}", fields.join(", "))
+            })
+            .collect();
+
+        if objects.is_empty() {
+            "[]\n".to_string()
+        } else {
+            format!("[\n{}\n]\n", objects.join(",\n"))
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Writes `table` to `path`, in the format its extension asks for. Returns
+/// the format used.
+pub fn write(path: &Path, table: &Table) -> Result<Format, OafError> {
+    let format = Format::for_path(path);
+    fs::write(path, table.render(format))?;
+    info!("Exported {} rows to '{}' as {}", table.rows.len(), path.display(), format);
+    Ok(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        let mut table = Table::new(vec!["status", "path"]);
+        table.add_row(vec!["M".to_string(), "src/main.rs".to_string()]);
+        table.add_row(vec!["??".to_string(), "notes, \"draft\".txt".to_string()]);
+        table
+    }
+
+    #[test]
+    fn format_comes_from_the_extension() {
+        assert_eq!(Format::for_path(Path::new("log.CSV")), Format::Csv);
+        assert_eq!(Format::for_path(Path::new("log.json")), Format::Json);
+        assert_eq!(Format::for_path(Path::new("log.txt")), Format::Text);
+        assert_eq!(Format::for_path(Path::new("log")), Format::Text);
+    }
+
+    #[test]
+    fn text_is_aligned() {
+        assert_eq!(table().render(Format::Text), "status  path\n\
+                                                  M       src/main.rs\n\
+                                                  ??      notes, \"draft\".txt\n");
+    }
+
+    #[test]
+    fn csv_quotes_when_needed() {
+        assert_eq!(table().render(Format::Csv), "status,path\nM,src/main.rs\n??,\"notes, \"\"draft\"\".txt\"\n");
+    }
+
+    #[test]
+    fn json_has_an_object_per_row() {
+        assert_eq!(table().render(Format::Json), "[\n  {\"status\": \"M\", \"path\": \"src/main.rs\"},\n  \
+                                                  {\"status\": \"??\", \"path\": \"notes, \\\"draft\\\".txt\"}\n]\n");
+        assert_eq!(Table::new(vec!["path"]).render(Format::Json), "[]\n");
+        assert_eq!(json_string("a\\b\n\u{1}"), "\"a\\\\b\\n\\u0001\"");
+    }
+}
@@ -0,0 +1,264 @@
+//! Turning the bytes of a file into text for display. Files are decoded as
+//! UTF-8, or UTF-16 if they start with its byte order mark, and anything
+//! that is not valid UTF-8 is read in the fallback encoding configured for
+//! its path, Windows-1252 by default, so that every byte shows as
+//! something. The text keeps its original line endings; `display` gives it
+//! with plain newlines.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+use encoding_rs::{self, Encoding as Fallback};
+use config::{self, EncodingConfig};
+use ignore_rules;
+
+/// How far into a file to look for a NUL byte, as git does, when deciding
+/// whether it is binary.
+const BINARY_CHECK_BYTES: usize = 8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// The fallback used because the file is not valid UTF-8.
+    Legacy(&'static Fallback),
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Legacy(fallback) => fallback.name(),
+        }
+    }
+}
+
+/// The encoding to read the file at `path`, relative to the working tree,
+/// in if it is not UTF-8.
+pub fn fallback_for(config: &EncodingConfig, path: &Path) -> &'static Fallback {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let label = config.paths.iter()
+        .find(|p| ignore_rules::pattern_matches(&p.pattern, &path, false))
+        .map_or(&config.fallback, |p| &p.encoding);
+    // The config is checked when it is loaded, so this only falls back for
+    // a config made in code.
+    Fallback::for_label(label.as_bytes()).unwrap_or(encoding_rs::WINDOWS_1252)
+}
+
+/// How many lines end in each of the three ways.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineEndings {
+    pub lf: usize,
+    pub crlf: usize,
+    /// A carriage return on its own, as on classic Mac OS.
+    pub cr: usize,
+}
+
+impl LineEndings {
+    pub fn count(text: &str) -> Self {
+        let mut endings = LineEndings::default();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                    endings.crlf += 1;
+                },
+                '\r' => endings.cr += 1,
+                '\n' => endings.lf += 1,
+                _ => {},
+            }
+        }
+        endings
+    }
+
+    /// True if the lines do not all end the same way.
+    pub fn is_mixed(&self) -> bool {
+        [self.lf, self.crlf, self.cr].iter().filter(|&&n| n > 0).count() > 1
+    }
+
+    pub fn description(&self) -> &'static str {
+        match (self.lf > 0, self.crlf > 0, self.cr > 0) {
+            _ if self.is_mixed() => "mixed line endings",
+            (true, _, _) => "LF",
+            (_, true, _) => "CRLF",
+            (_, _, true) => "CR",
+            _ => "no line endings",
+        }
+    }
+}
+
+/// A file's content decoded to text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileText {
+    /// The text, without any byte order mark, with its line endings as they
+    /// are in the file.
+    pub text: String,
+    pub encoding: Encoding,
+    pub bom: bool,
+    pub line_endings: LineEndings,
+}
+
+impl FileText {
+    /// The text with every line ending made a plain newline, since a
+    /// carriage return would garble the terminal.
+    pub fn display(&self) -> Cow<str> {
+        if self.line_endings.crlf == 0 && self.line_endings.cr == 0 {
+            Cow::Borrowed(&self.text)
+        } else {
+            Cow::Owned(self.text.replace("\r\n", "\n").replace('\r', "\n"))
+        }
+    }
+
+    /// The encoding and line endings, e.g. 'UTF-8, CRLF'.
+    pub fn description(&self) -> String {
+        let bom = if self.bom && self.encoding == Encoding::Utf8 { " with BOM" } else { "" };
+        format!("{}{}, {}", self.encoding.name(), bom, self.line_endings.description())
+    }
+}
+
+/// True if `bytes` look like a binary file: one with a NUL near the start
+/// that is not UTF-16, which is full of them.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    utf16_bom(bytes).is_none() && bytes[..bytes.len().min(BINARY_CHECK_BYTES)].contains(&0)
+}
+
+/// Decodes `bytes` as text, or None if they are binary.
+pub fn decode(bytes: &[u8], fallback: &'static Fallback) -> Option<FileText> {
+    if is_binary(bytes) {
+        return None;
+    }
+
+    let (text, encoding, bom) = if let Some(encoding) = utf16_bom(bytes) {
+        (decode_utf16(&bytes[2..], encoding), encoding, true)
+    } else if bytes.starts_with(b"\xef\xbb\xbf") {
+        let (text, encoding) = decode_8bit(&bytes[3..], fallback);
+        (text, encoding, true)
+    } else {
+        let (text, encoding) = decode_8bit(bytes, fallback);
+        (text, encoding, false)
+    };
+
+    let line_endings = LineEndings::count(&text);
+    Some(FileText { text, encoding, bom, line_endings })
+}
+
+/// Decodes one line of a diff, which git has already split from the rest
+/// of the file, without its line ending.
+pub fn display_line(bytes: &[u8], fallback: &'static Fallback) -> String {
+    let (text, _) = decode_8bit(bytes, fallback);
+    text.trim_end_matches('\n').trim_end_matches('\r').to_string()
+}
+
+/// Decodes one line of a diff of the file at `path`, in the fallback
+/// encoding configured for it. A `patch::LineDecoder`.
+pub fn display_diff_line(bytes: &[u8], path: &Path) -> String {
+    display_line(bytes, fallback_for(&config::current().encoding, path))
+}
+
+/// Reads and decodes the file at `path`. None if it is binary or bigger than
+/// `max_bytes`.
+pub fn read(path: &Path, max_bytes: Option<u64>, fallback: &'static Fallback) -> io::Result<Option<FileText>> {
+    if max_bytes.map_or(false, |max| fs::metadata(path).map(|m| m.len() > max).unwrap_or(false)) {
+        return Ok(None);
+    }
+    Ok(decode(&fs::read(path)?, fallback))
+}
+
+fn utf16_bom(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.starts_with(b"\xff\xfe") {
+        Some(Encoding::Utf16Le)
+    } else if bytes.starts_with(b"\xfe\xff") {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+fn decode_utf16(bytes: &[u8], encoding: Encoding) -> String {
+    let units: Vec<u16> = bytes.chunks(2).map(|pair| {
+        let (first, second) = (pair[0] as u16, *pair.get(1).unwrap_or(&0) as u16);
+        if encoding == Encoding::Utf16Le { first | (second << 8) } else { (first << 8) | second }
+    }).collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// UTF-8 if it is valid, otherwise the fallback. Bytes that mean nothing in
+/// the fallback become replacement characters.
+fn decode_8bit(bytes: &[u8], fallback: &'static Fallback) -> (String, Encoding) {
+    match ::std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), Encoding::Utf8),
+        Err(_) => {
+            let (text, _) = fallback.decode_without_bom_handling(bytes);
+            (text.into_owned(), Encoding::Legacy(fallback))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::PathEncoding;
+
+    fn decode(bytes: &[u8]) -> Option<FileText> {
+        super::decode(bytes, encoding_rs::WINDOWS_1252)
+    }
+
+    #[test]
+    fn decodes_utf8_utf16_and_the_fallback() {
+        let text = decode(b"caf\xc3\xa9\n").unwrap();
+        assert_eq!((text.text.as_str(), text.encoding, text.bom), ("café\n", Encoding::Utf8, false));
+
+        let text = decode(b"\xef\xbb\xbfhi").unwrap();
+        assert_eq!((text.text.as_str(), text.description()), ("hi", "UTF-8 with BOM, no line endings".to_string()));
+
+        let text = decode(b"\xff\xfeh\0i\0\r\0\n\0").unwrap();
+        assert_eq!((text.text.as_str(), text.encoding), ("hi\r\n", Encoding::Utf16Le));
+        let text = decode(b"\xfe\xff\0h\0i").unwrap();
+        assert_eq!((text.text.as_str(), text.encoding), ("hi", Encoding::Utf16Be));
+
+        let text = decode(b"caf\xe9 \x80\n").unwrap();
+        assert_eq!((text.text.as_str(), text.encoding.name()), ("café €\n", "windows-1252"));
+
+        let text = super::decode(b"\x93\xfa\x96\x7b", encoding_rs::SHIFT_JIS).unwrap();
+        assert_eq!((text.text.as_str(), text.encoding.name()), ("日本", "Shift_JIS"));
+
+        assert!(decode(b"\x7fELF\0\0\0").is_none());
+    }
+
+    #[test]
+    fn fallback_is_chosen_by_path() {
+        let config = EncodingConfig {
+            fallback: "windows-1252".to_string(),
+            paths: vec![
+                PathEncoding { pattern: "legacy/*.txt".to_string(), encoding: "shift_jis".to_string() },
+                PathEncoding { pattern: "*.txt".to_string(), encoding: "koi8-r".to_string() },
+            ],
+        };
+        assert_eq!(fallback_for(&config, Path::new("legacy/notes.txt")), encoding_rs::SHIFT_JIS);
+        assert_eq!(fallback_for(&config, Path::new("docs/notes.txt")), encoding_rs::KOI8_R);
+        assert_eq!(fallback_for(&config, Path::new("src/main.c")), encoding_rs::WINDOWS_1252);
+    }
+
+    #[test]
+    fn line_endings_are_counted_and_normalised_for_display() {
+        let text = decode(b"one\r\ntwo\nthree\rfour").unwrap();
+        assert_eq!(text.line_endings, LineEndings { lf: 1, crlf: 1, cr: 1 });
+        assert!(text.line_endings.is_mixed());
+        assert_eq!(text.display(), "one\ntwo\nthree\nfour");
+        assert_eq!(text.text, "one\r\ntwo\nthree\rfour");
+
+        let text = decode(b"one\r\ntwo\r\n").unwrap();
+        assert_eq!((text.line_endings.is_mixed(), text.line_endings.description()), (false, "CRLF"));
+    }
+
+    #[test]
+    fn display_line_drops_the_line_ending() {
+        assert_eq!(display_line(b"text\r\n", encoding_rs::WINDOWS_1252), "text");
+        assert_eq!(display_line(b"caf\xe9\n", encoding_rs::WINDOWS_1252), "café");
+    }
+}
@@ -0,0 +1,160 @@
+//! Facts about one file for the file info panel: its size and mode, the last
+//! commit that changed it, the .gitattributes that apply and its owners.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use git2::Repository;
+use codeowners::{CodeOwners, OwnerRule};
+use history::{self, LogEntry, LogFilter, LogSort};
+use ignore_rules;
+use mailmap::Mailmap;
+
+/// A line of a .gitattributes file that applies to the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeRule {
+    pub source: PathBuf,
+    pub line: usize,
+    pub pattern: String,
+    pub attributes: String,
+}
+
+pub struct FileInfo {
+    pub path: PathBuf,
+    /// The size in the working tree, if the file is there.
+    pub size: Option<u64>,
+    /// The mode recorded in the index, e.g. '100755', if the file is tracked.
+    pub mode: Option<u32>,
+    pub last_commit: Option<LogEntry>,
+    /// Lowest precedence first, as git applies them.
+    pub attributes: Vec<AttributeRule>,
+    pub owners: Option<(PathBuf, OwnerRule)>,
+}
+
+/// Gathers the information about `path`, relative to the working tree. The
+/// author of the last commit is mapped by `mailmap`.
+pub fn load(repo: &Repository, path: &Path, mailmap: &Mailmap) -> Result<FileInfo, ::git2::Error> {
+    let workdir = repo.workdir().ok_or_else(|| ::git2::Error::from_str("A bare repository has no working tree."))?;
+    let size = fs::metadata(workdir.join(path)).ok().filter(|m| m.is_file()).map(|m| m.len());
+    let mode = repo.index()?.get_path(path, 0).map(|e| e.mode);
+
+    let filter = LogFilter { paths: vec![path.to_string_lossy().into_owned()], ..LogFilter::default() };
+    let last_commit = if history::unborn_branch(repo).is_some() {
+        None
+    } else {
+        history::load(repo, None, &filter, LogSort::Time, 1, mailmap)?.into_iter().next()
+    };
+
+    let owners = CodeOwners::load(workdir)
+        .and_then(|owners| owners.rule_for(path).cloned().map(|rule| (owners.source, rule)));
+
+    Ok(FileInfo { path: path.to_path_buf(), size, mode, last_commit, attributes: attributes(repo, workdir, path), owners })
+}
+
+impl FileInfo {
+    /// The information as text for the panel.
+    pub fn description(&self) -> String {
+        let mut text = format!("Path:        {}\n", self.path.display());
+        text += &format!("Size:        {}\n", self.size.map_or("not in the working tree".to_string(), |s| format!("{} bytes", s)));
+        text += &format!("Mode:        {}\n", self.mode.map_or("untracked".to_string(), |m| format!("{:o} ({})", m, describe_mode(m))));
+        text += &match self.last_commit {
+            Some(ref c) => format!("Last commit: {:.7} {} ({}, {})\n", c.oid.to_string(), c.summary, c.author, history::commit_date(&c.time)),
+            None => "Last commit: none\n".to_string(),
+        };
+
+        text += &match self.owners {
+            Some((ref source, ref rule)) if rule.owners.is_empty() =>
+                format!("Owners:      none ('{}', line {} of {})\n", rule.pattern, rule.line, source.display()),
+            Some((ref source, ref rule)) =>
+                format!("Owners:      {} ('{}', line {} of {})\n", rule.owners.join(" "), rule.pattern, rule.line, source.display()),
+            None => "Owners:      no CODEOWNERS rule\n".to_string(),
+        };
+
+        text += "\nAttributes:\n";
+        if self.attributes.is_empty() {
+            text += "  none\n";
+        }
+        for rule in &self.attributes {
+            text += &format!("  {} {}   ({}:{})\n", rule.pattern, rule.attributes, rule.source.display(), rule.line);
+        }
+        text
+    }
+}
+
+fn describe_mode(mode: u32) -> &'static str {
+    match mode {
+        0o100755 => "executable",
+        0o120000 => "symbolic link",
+        0o160000 => "submodule",
+        _ => "regular file",
+    }
+}
+
+/// The .gitattributes lines that match `path`: the root file, then those in
+/// each directory down to the file's, then '.git/info/attributes'.
+fn attributes(repo: &Repository, workdir: &Path, path: &Path) -> Vec<AttributeRule> {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut sources = vec![(PathBuf::from(".gitattributes"), workdir.join(".gitattributes"), String::new())];
+    let dirs: Vec<&str> = path.split('/').collect();
+    let mut base = String::new();
+    for dir in &dirs[..dirs.len().saturating_sub(1)] {
+        base = if base.is_empty() { dir.to_string() } else { format!("{}/{}", base, dir) };
+        let source = PathBuf::from(&base).join(".gitattributes");
+        sources.push((source.clone(), workdir.join(&source), base.clone()));
+    }
+    sources.push((PathBuf::from(".git/info/attributes"), repo.path().join("info").join("attributes"), String::new()));
+
+    let mut rules = Vec::new();
+    for (source, file, base) in sources {
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let relative = if base.is_empty() { path.as_str() } else { &path[base.len() + 1..] };
+        rules.extend(matching_lines(&text, relative).into_iter().map(|(line, pattern, attributes)| {
+            AttributeRule { source: source.clone(), line, pattern, attributes }
+        }));
+    }
+    rules
+}
+
+fn matching_lines(text: &str, path: &str) -> Vec<(usize, String, String)> {
+    text.lines().enumerate().filter_map(|(n, line)| {
+        let line = line.trim();
+        // Macros ('[attr]name ...') define attributes rather than apply them.
+        if line.is_empty() || line.starts_with('#') || line.starts_with("[attr]") {
+            return None;
+        }
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let pattern = parts.next()?;
+        let attributes = parts.next().unwrap_or("").trim();
+        // Unlike .gitignore, a pattern naming a directory does not apply to
+        // the files in it.
+        if ignore_rules::pattern_matches(pattern, path, false) {
+            Some((n + 1, pattern.to_string(), attributes.to_string()))
+        } else {
+            None
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_lines_works() {
+        let text = "# Attributes\n* text=auto\n*.png binary\n/docs/*.md linguist-documentation\n[attr]rust text diff=rust\n";
+        let lines = matching_lines(text, "docs/guide.md");
+        assert_eq!(lines, vec![
+            (2, "*".to_string(), "text=auto".to_string()),
+            (4, "/docs/*.md".to_string(), "linguist-documentation".to_string()),
+        ]);
+        assert_eq!(matching_lines(text, "img/a.png").len(), 2);
+    }
+
+    #[test]
+    fn describe_mode_works() {
+        assert_eq!(describe_mode(0o100755), "executable");
+        assert_eq!(describe_mode(0o100644), "regular file");
+    }
+}
@@ -0,0 +1,163 @@
+//! A file's content as of any commit, for stepping back and forth through
+//! the revisions that changed it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use git2::{DiffOptions, ErrorCode, ObjectType, Oid, Patch, Repository};
+use encoding_rs::Encoding;
+use config;
+use file_content::{self, FileText};
+use history::{self, LogEntry, LogFilter, LogSort};
+use mailmap::Mailmap;
+use range_diff::DiffLine;
+
+/// No more than this many revisions of a file are listed.
+pub const MAX_REVISIONS: usize = 1000;
+
+/// What a file held at one revision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Content {
+    Text(FileText),
+    /// Binary, or bigger than the size limit, with its size in bytes.
+    Binary(usize),
+    /// The file does not exist at that revision.
+    Missing,
+}
+
+impl Content {
+    fn lines(&self) -> Vec<String> {
+        match *self {
+            Content::Text(ref text) => text.display().lines().map(|l| l.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The commits that changed a file, newest first, starting from a chosen
+/// commit. Renames are not followed. Each revision's content is read from
+/// its blob when first asked for and then kept, so that moving back and
+/// forth is quick; a blob shared by several revisions is only read once.
+pub struct FileRevisions {
+    pub path: PathBuf,
+    pub revisions: Vec<LogEntry>,
+    max_bytes: Option<u64>,
+    fallback: &'static Encoding,
+    blobs: HashMap<Oid, Option<Oid>>,
+    contents: HashMap<Oid, Rc<Content>>,
+}
+
+impl FileRevisions {
+    /// Lists the revisions of `path` reachable from `start`. If `start` did
+    /// not change the file itself it still comes first, so that the file can
+    /// be seen as it was at any commit. Files over `max_bytes` are treated as
+    /// binary. A file that is not UTF-8 is read in the encoding configured
+    /// for its path. Authors are mapped by `mailmap`.
+    pub fn load(repo: &Repository, path: &Path, start: Oid, max_bytes: Option<u64>, mailmap: &Mailmap) -> Result<Self, ::git2::Error> {
+        let _timer = timer!("file_revisions::load");
+        let filter = LogFilter { paths: vec![path.to_string_lossy().into_owned()], ..LogFilter::default() };
+        let mut revisions = history::load(repo, Some(start), &filter, LogSort::Topological, MAX_REVISIONS, mailmap)?;
+        if revisions.first().map_or(true, |r| r.oid != start) {
+            revisions.insert(0, history::entries(repo, &[start], mailmap)?.remove(0));
+            revisions.truncate(MAX_REVISIONS);
+        }
+
+        let fallback = file_content::fallback_for(&config::current().encoding, path);
+        Ok(FileRevisions { path: path.to_path_buf(), revisions, max_bytes, fallback, blobs: HashMap::new(), contents: HashMap::new() })
+    }
+
+    /// The file as it was at the revision at `index`.
+    pub fn content(&mut self, repo: &Repository, index: usize) -> Result<Rc<Content>, ::git2::Error> {
+        let commit = self.revisions[index].oid;
+        let blob_id = match self.blobs.get(&commit) {
+            Some(&blob_id) => blob_id,
+            None => {
+                let blob_id = blob_at(repo, commit, &self.path)?;
+                self.blobs.insert(commit, blob_id);
+                blob_id
+            },
+        };
+
+        let blob_id = match blob_id {
+            Some(blob_id) => blob_id,
+            None => return Ok(Rc::new(Content::Missing)),
+        };
+        if let Some(content) = self.contents.get(&blob_id) {
+            return Ok(content.clone());
+        }
+
+        let blob = repo.find_blob(blob_id)?;
+        let size = blob.content().len();
+        let decoded = if self.max_bytes.map_or(false, |max| size as u64 > max) { None } else { file_content::decode(blob.content(), self.fallback) };
+        let content = match decoded {
+            Some(text) => Content::Text(text),
+            None => Content::Binary(size),
+        };
+        let content = Rc::new(content);
+        self.contents.insert(blob_id, content.clone());
+        Ok(content)
+    }
+
+    /// How the file changed at the revision at `index`: the whole of it, with
+    /// the lines removed since the previous, older, revision and those added.
+    /// The oldest revision is compared with nothing, so is all added. None if
+    /// either side is binary.
+    pub fn diff_with_previous(&mut self, repo: &Repository, index: usize) -> Result<Option<Vec<DiffLine>>, ::git2::Error> {
+        let new = self.content(repo, index)?;
+        let old = if index + 1 < self.revisions.len() {
+            self.content(repo, index + 1)?
+        } else {
+            Rc::new(Content::Missing)
+        };
+
+        match (&*old, &*new) {
+            (&Content::Binary(_), _) | (_, &Content::Binary(_)) => Ok(None),
+            _ => diff_lines(&old.lines(), &new.lines()).map(Some),
+        }
+    }
+}
+
+/// Diffs two versions of a file with git's own diff, which copes with files
+/// of any length. The decoded text is diffed, so that the lines match those
+/// shown whatever the file's encoding.
+fn diff_lines(old: &[String], new: &[String]) -> Result<Vec<DiffLine>, ::git2::Error> {
+    let joined = |lines: &[String]| lines.iter().map(|l| format!("{}\n", l)).collect::<String>();
+    let mut options = DiffOptions::new();
+    options.context_lines(0);
+    let patch = Patch::from_buffers(joined(old).as_bytes(), None, joined(new).as_bytes(), None, Some(&mut options))?;
+
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    let mut next_old = 0;
+    for hunk in 0..patch.num_hunks() {
+        let (header, count) = patch.hunk(hunk)?;
+        // A hunk that only adds lines starts after its old line, not at it.
+        let start = header.old_start() as usize - if header.old_lines() == 0 { 0 } else { 1 };
+        lines.extend(old[next_old..start].iter().map(|l| DiffLine::Same(l.clone())));
+        next_old = start;
+        for n in 0..count {
+            let line = patch.line_in_hunk(hunk, n)?;
+            match (line.origin(), line.old_lineno(), line.new_lineno()) {
+                ('-', Some(number), _) => {
+                    lines.push(DiffLine::Removed(old[number as usize - 1].clone()));
+                    next_old = number as usize;
+                },
+                ('+', _, Some(number)) => lines.push(DiffLine::Added(new[number as usize - 1].clone())),
+                _ => {},
+            }
+        }
+    }
+    lines.extend(old[next_old..].iter().map(|l| DiffLine::Same(l.clone())));
+    Ok(lines)
+}
+
+/// The id of the blob at `path` in the commit's tree, if there is one
+/// there. A directory at `path` counts as no file.
+fn blob_at(repo: &Repository, commit: Oid, path: &Path) -> Result<Option<Oid>, ::git2::Error> {
+    let tree = repo.find_commit(commit)?.tree()?;
+    match tree.get_path(path) {
+        Ok(ref entry) if entry.kind() == Some(ObjectType::Blob) => Ok(Some(entry.id())),
+        Ok(_) => Ok(None),
+        Err(ref e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,149 @@
+//! Builds throwaway git repositories for tests. Each `TestRepo` lives in its
+//! own directory under the system temp directory, which is deleted when it
+//! is dropped, so tests can run in parallel and leave nothing behind.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use git2::build::CheckoutBuilder;
+use git2::{BranchType, IndexAddOption, Oid, Repository, RepositoryInitOptions, Signature, Time};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Commits are made one minute apart from this time, so that the order of
+/// a log does not depend on how fast the test runs.
+const FIRST_COMMIT_TIME: i64 = 1_536_584_602;
+
+pub struct TestRepo {
+    dir: PathBuf,
+    pub repo: Repository,
+    commits: AtomicUsize,
+}
+
+impl TestRepo {
+    /// Creates an empty repository whose first branch is 'master'. `name`
+    /// is only used to make the directory easy to recognise.
+    pub fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("oaf-fixture-{}-{}-{}", name, ::std::process::id(),
+                                               NEXT_ID.fetch_add(1, Ordering::SeqCst)));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut opts = RepositoryInitOptions::new();
+        opts.initial_head("master");
+        let repo = Repository::init_opts(&dir, &opts).unwrap();
+        {
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Test User").unwrap();
+            config.set_str("user.email", "test@example.com").unwrap();
+            // Whatever the user's global config says, commits are not signed.
+            config.set_bool("commit.gpgsign", false).unwrap();
+        }
+
+        TestRepo { dir, repo, commits: AtomicUsize::new(0) }
+    }
+
+    /// The working tree directory.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes `content` to the file at `path`, relative to the working tree,
+    /// creating any directories needed. Nothing is staged.
+    pub fn write(&self, path: &str, content: &str) -> &Self {
+        let path = self.dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        self
+    }
+
+    /// Stages everything in the working tree, including deletions.
+    pub fn stage_all(&self) -> &Self {
+        let mut index = self.repo.index().unwrap();
+        index.add_all(&["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.update_all(&["*"], None).unwrap();
+        index.write().unwrap();
+        self
+    }
+
+    /// Stages everything and commits it on the current branch.
+    pub fn commit(&self, message: &str) -> Oid {
+        self.stage_all();
+        let mut index = self.repo.index().unwrap();
+        let tree = self.repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = self.signature();
+        let parent = self.repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        self.repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents).unwrap()
+    }
+
+    /// Writes one file and commits it.
+    pub fn commit_file(&self, path: &str, content: &str, message: &str) -> Oid {
+        self.write(path, content);
+        self.commit(message)
+    }
+
+    /// Creates a branch at HEAD without checking it out.
+    pub fn branch(&self, name: &str) -> &Self {
+        let head = self.repo.head().unwrap().peel_to_commit().unwrap();
+        self.repo.branch(name, &head, false).unwrap();
+        self
+    }
+
+    /// Checks out an existing local branch, updating the working tree.
+    pub fn checkout(&self, name: &str) -> &Self {
+        let branch = self.repo.find_branch(name, BranchType::Local).unwrap();
+        let refname = branch.get().name().unwrap().to_string();
+        let tree = branch.get().peel_to_tree().unwrap();
+        self.repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().force())).unwrap();
+        self.repo.set_head(&refname).unwrap();
+        self
+    }
+
+    /// Makes 'master' and a new branch `branch` both change the same line of
+    /// `path`, so that merging one into the other conflicts. Leaves `branch`
+    /// checked out and returns the tips of master and `branch`.
+    pub fn conflict(&self, branch: &str, path: &str) -> (Oid, Oid) {
+        self.commit_file(path, "base\n", &format!("Add {}", path));
+        self.branch(branch);
+        let master = self.commit_file(path, "master\n", &format!("Change {} on master", path));
+        self.checkout(branch);
+        let tip = self.commit_file(path, &format!("{}\n", branch), &format!("Change {} on {}", path, branch));
+        (master, tip)
+    }
+
+    /// Runs git in the working tree, for what libgit2 cannot do, such as
+    /// starting a rebase. Returns whether it succeeded.
+    pub fn git(&self, args: &[&str]) -> bool {
+        Command::new("git").arg("-C").arg(&self.dir).args(args).output().unwrap().status.success()
+    }
+
+    /// Adds `other` as a submodule at `path`, checked out at `other`'s HEAD,
+    /// and commits it. `other` must have a commit.
+    pub fn add_submodule(&self, other: &TestRepo, path: &str) -> Oid {
+        let url = other.path().to_str().unwrap();
+        let head = other.repo.head().unwrap().target().unwrap();
+        let mut submodule = self.repo.submodule(url, Path::new(path), true).unwrap();
+        {
+            let sub_repo = submodule.open().unwrap();
+            sub_repo.find_remote("origin").unwrap().fetch(&["refs/heads/*:refs/remotes/origin/*"], None, None).unwrap();
+            sub_repo.set_head_detached(head).unwrap();
+            sub_repo.checkout_head(Some(CheckoutBuilder::new().force())).unwrap();
+        }
+        submodule.add_finalize().unwrap();
+        self.commit(&format!("Add submodule {}", path))
+    }
+
+    fn signature(&self) -> Signature<'static> {
+        let n = self.commits.fetch_add(1, Ordering::SeqCst) as i64;
+        Signature::new("Test User", "test@example.com", &Time::new(FIRST_COMMIT_TIME + 60 * n, 0)).unwrap()
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
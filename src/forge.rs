@@ -0,0 +1,189 @@
+use std::io;
+use std::process::{Command, Stdio};
+use config::ForgeTemplate;
+
+/// What the user wants to look at on the forge's website.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebTarget {
+    Commit(String),
+    Branch(String),
+    /// A file at a revision, which may be a branch name or a commit hash.
+    File { rev: String, path: String },
+}
+
+/// The parts of a remote URL needed to build web URLs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteLocation {
+    pub host: String,
+    /// The path of the repository on the host, e.g. 'owner/repo'.
+    pub path: String,
+}
+
+impl RemoteLocation {
+    /// The web address of the repository's home page.
+    pub fn base_url(&self) -> String {
+        format!("https://{}/{}", self.host, self.path)
+    }
+}
+
+/// Parses the common forms of remote URL: 'https://host/path.git',
+/// 'ssh://user@host:port/path.git', 'git://host/path' and the scp-like
+/// 'user@host:path.git'. Returns None for local paths.
+pub fn parse_remote_url(url: &str) -> Option<RemoteLocation> {
+    let url = url.trim();
+
+    let (host_part, path) = if let Some(pos) = url.find("://") {
+        let rest = &url[pos + 3..];
+        let slash = rest.find('/')?;
+        (&rest[..slash], &rest[slash + 1..])
+    } else {
+        // scp-like syntax. A colon after a slash means this is really a path.
+        let colon = url.find(':')?;
+        if url[..colon].contains('/') {
+            return None;
+        }
+        (&url[..colon], &url[colon + 1..])
+    };
+
+    let host = match host_part.rfind('@') {
+        Some(at) => &host_part[at + 1..],
+        None => host_part,
+    };
+    let host = match host.find(':') {
+        Some(colon) => &host[..colon],
+        None => host,
+    };
+
+    let path = path.trim_matches('/');
+    let path = if path.ends_with(".git") { &path[..path.len() - 4] } else { path };
+
+    if host.is_empty() || path.is_empty() {
+        return None;
+    }
+
+    Some(RemoteLocation { host: host.to_lowercase(), path: path.to_string() })
+}
+
+/// Templates for the well-known public forges.
+pub fn builtin_templates() -> Vec<ForgeTemplate> {
+    let make = |host: &str, commit: &str, branch: &str, file: &str| ForgeTemplate {
+        host: host.to_string(),
+        commit: commit.to_string(),
+        branch: branch.to_string(),
+        file: file.to_string(),
+    };
+
+    vec![
+        make("github.com", "{base}/commit/{commit}", "{base}/tree/{branch}", "{base}/blob/{rev}/{path}"),
+        make("gitlab.com", "{base}/-/commit/{commit}", "{base}/-/tree/{branch}", "{base}/-/blob/{rev}/{path}"),
+        make("bitbucket.org", "{base}/commits/{commit}", "{base}/branch/{branch}", "{base}/src/{rev}/{path}"),
+    ]
+}
+
+/// Builds the web URL for `target` in the repository at `remote_url`. The
+/// user's templates are tried before the built-in ones. Returns None if the
+/// URL cannot be parsed or no template matches its host.
+pub fn web_url(user_templates: &[ForgeTemplate], remote_url: &str, target: &WebTarget) -> Option<String> {
+    let location = parse_remote_url(remote_url)?;
+    let builtins = builtin_templates();
+    let template = user_templates.iter()
+        .chain(builtins.iter())
+        .find(|t| host_matches(&location.host, &t.host))?;
+
+    let url = match *target {
+        WebTarget::Commit(ref commit) => template.commit.replace("{commit}", commit),
+        WebTarget::Branch(ref branch) => template.branch.replace("{branch}", branch),
+        WebTarget::File { ref rev, ref path } => template.file
+            .replace("{rev}", rev)
+            .replace("{branch}", rev)
+            .replace("{path}", path),
+    };
+
+    Some(url.replace("{base}", &location.base_url()))
+}
+
+/// Opens `url` in the user's web browser, without waiting for it to close.
+pub fn open_in_browser(url: &str) -> io::Result<()> {
+    info!("Opening '{}' in the browser", url);
+
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        // Not 'cmd /C start', as cmd treats the '&' in query strings as the
+        // end of the command.
+        let mut cmd = Command::new("rundll32");
+        cmd.arg("url.dll,FileProtocolHandler");
+        cmd
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command.arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+}
+
+/// A remote host matches a template host if it is the same or a subdomain.
+fn host_matches(host: &str, template_host: &str) -> bool {
+    let template_host = template_host.to_lowercase();
+    host == template_host || host.ends_with(&format!(".{}", template_host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(host: &str, path: &str) -> Option<RemoteLocation> {
+        Some(RemoteLocation { host: host.to_string(), path: path.to_string() })
+    }
+
+    #[test]
+    fn parse_remote_url_https() {
+        assert_eq!(parse_remote_url("https://github.com/PhilipDaniels/oaf.git"), location("github.com", "PhilipDaniels/oaf"));
+        assert_eq!(parse_remote_url("https://user@GitHub.com/PhilipDaniels/oaf/"), location("github.com", "PhilipDaniels/oaf"));
+    }
+
+    #[test]
+    fn parse_remote_url_ssh() {
+        assert_eq!(parse_remote_url("ssh://git@gitlab.com:2222/group/sub/proj.git"), location("gitlab.com", "group/sub/proj"));
+        assert_eq!(parse_remote_url("git@github.com:PhilipDaniels/oaf.git"), location("github.com", "PhilipDaniels/oaf"));
+    }
+
+    #[test]
+    fn parse_remote_url_rejects_local_paths() {
+        assert_eq!(parse_remote_url("/home/phil/repos/oaf"), None);
+        assert_eq!(parse_remote_url("../oaf"), None);
+        assert_eq!(parse_remote_url("./a:b"), None);
+    }
+
+    #[test]
+    fn web_url_uses_builtin_templates() {
+        let url = "git@github.com:PhilipDaniels/oaf.git";
+        assert_eq!(web_url(&[], url, &WebTarget::Commit("abc123".to_string())),
+                   Some("https://github.com/PhilipDaniels/oaf/commit/abc123".to_string()));
+        assert_eq!(web_url(&[], url, &WebTarget::Branch("master".to_string())),
+                   Some("https://github.com/PhilipDaniels/oaf/tree/master".to_string()));
+        assert_eq!(web_url(&[], url, &WebTarget::File { rev: "master".to_string(), path: "src/main.rs".to_string() }),
+                   Some("https://github.com/PhilipDaniels/oaf/blob/master/src/main.rs".to_string()));
+    }
+
+    #[test]
+    fn web_url_prefers_user_templates() {
+        let templates = vec![ForgeTemplate {
+            host: "github.com".to_string(),
+            commit: "{base}/c/{commit}".to_string(),
+            branch: "{base}/b/{branch}".to_string(),
+            file: "{base}/f/{rev}/{path}".to_string(),
+        }];
+        assert_eq!(web_url(&templates, "https://github.com/a/b", &WebTarget::Commit("abc".to_string())),
+                   Some("https://github.com/a/b/c/abc".to_string()));
+    }
+
+    #[test]
+    fn web_url_for_unknown_host_is_none() {
+        assert_eq!(web_url(&[], "https://example.com/a/b", &WebTarget::Commit("abc".to_string())), None);
+    }
+}
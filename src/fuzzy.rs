@@ -0,0 +1,158 @@
+/// A fuzzy match of a pattern against some text: the characters of the
+/// pattern appear in the text in order, but not necessarily together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Higher is better. Only comparable between matches of the same pattern.
+    pub score: i64,
+    /// The char indices in the text of the characters that matched, for
+    /// highlighting.
+    pub positions: Vec<usize>,
+}
+
+const MATCH_SCORE: i64 = 16;
+/// For a character that follows the previous match directly.
+const CONSECUTIVE_BONUS: i64 = 8;
+/// For a character at the start of a word, e.g. the 'm' of 'src/main.rs'.
+const BOUNDARY_BONUS: i64 = 10;
+
+/// Matches `pattern` against `text`, returning None if it does not match.
+/// Matching ignores case unless the pattern has an upper-case letter. An
+/// empty pattern matches everything.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<Match> {
+    let pattern: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    let text: Vec<char> = text.chars().collect();
+    if pattern.is_empty() {
+        return Some(Match { score: 0, positions: Vec::new() });
+    }
+
+    let case_sensitive = pattern.iter().any(|c| c.is_uppercase());
+    let eq = |p: char, t: char| if case_sensitive { p == t } else { fold(p) == fold(t) };
+
+    // Find where the earliest match ends, then work back from there to find
+    // the shortest stretch of text that contains the pattern. This keeps
+    // the matched characters close together, as a user expects.
+    let mut end = None;
+    let mut p = 0;
+    for (i, &c) in text.iter().enumerate() {
+        if eq(pattern[p], c) {
+            p += 1;
+            if p == pattern.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    let mut positions = vec![0; pattern.len()];
+    let mut p = pattern.len();
+    for i in (0..end + 1).rev() {
+        if eq(pattern[p - 1], text[i]) {
+            p -= 1;
+            positions[p] = i;
+            if p == 0 {
+                break;
+            }
+        }
+    }
+
+    let score = score(&text, &positions);
+    Some(Match { score, positions })
+}
+
+/// Matches `pattern` against each item, returning the index and match of
+/// those that match, best first. Items that score the same stay in order.
+pub fn filter<T, F>(pattern: &str, items: &[T], text: F) -> Vec<(usize, Match)>
+    where F: Fn(&T) -> String
+{
+    let mut matches: Vec<(usize, Match)> = items.iter()
+        .enumerate()
+        .filter_map(|(i, item)| fuzzy_match(pattern, &text(item)).map(|m| (i, m)))
+        .collect();
+    matches.sort_by(|a, b| b.1.score.cmp(&a.1.score).then(a.0.cmp(&b.0)));
+    matches
+}
+
+fn score(text: &[char], positions: &[usize]) -> i64 {
+    let mut score = 0;
+    for (n, &pos) in positions.iter().enumerate() {
+        score += MATCH_SCORE;
+        if n > 0 {
+            let gap = pos - positions[n - 1] - 1;
+            if gap == 0 {
+                score += CONSECUTIVE_BONUS;
+            } else {
+                score -= gap as i64;
+            }
+        }
+        if is_boundary(text, pos) {
+            score += BOUNDARY_BONUS;
+        }
+    }
+
+    // Prefer matches near the start, and shorter texts.
+    score - positions[0].min(10) as i64 - (text.len() / 16) as i64
+}
+
+fn is_boundary(text: &[char], pos: usize) -> bool {
+    if pos == 0 {
+        return true;
+    }
+
+    let (prev, c) = (text[pos - 1], text[pos]);
+    !prev.is_alphanumeric() && c.is_alphanumeric()
+        || prev.is_lowercase() && c.is_uppercase()
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(pattern: &str, text: &str) -> Option<Vec<usize>> {
+        fuzzy_match(pattern, text).map(|m| m.positions)
+    }
+
+    #[test]
+    fn matches_characters_in_order() {
+        assert_eq!(positions("mrs", "src/main.rs"), Some(vec![4, 9, 10]));
+        assert_eq!(positions("srm", "src/main.rs"), Some(vec![0, 1, 4]));
+        assert_eq!(positions("xyz", "src/main.rs"), None);
+        assert_eq!(positions("", "anything"), Some(vec![]));
+    }
+
+    #[test]
+    fn ignores_case_unless_the_pattern_has_capitals() {
+        assert!(fuzzy_match("readme", "README.md").is_some());
+        assert!(fuzzy_match("ReadMe", "README.md").is_none());
+        assert!(fuzzy_match("RM", "README.md").is_some());
+    }
+
+    #[test]
+    fn prefers_the_shortest_stretch_of_text() {
+        // The first 'f' is not used, as the 'fo' of 'foo' is tighter.
+        assert_eq!(positions("fo", "f/foo"), Some(vec![2, 3]));
+    }
+
+    #[test]
+    fn consecutive_and_word_start_matches_score_higher() {
+        let score = |pattern, text| fuzzy_match(pattern, text).unwrap().score;
+        assert!(score("main", "src/main.rs") > score("main", "src/mxaxixn.rs"));
+        assert!(score("fb", "feature/bar") > score("fb", "feature/xbar"));
+        assert!(score("fb", "fooBar") > score("fb", "foobar"));
+    }
+
+    #[test]
+    fn filter_sorts_best_first_and_keeps_ties_in_order() {
+        let items = vec!["origin/feature", "feature", "fixture", "master", "feature2"];
+        let indices: Vec<usize> = filter("feat", &items, |s| s.to_string()).into_iter().map(|(i, _)| i).collect();
+        assert_eq!(indices, vec![1, 4, 0]);
+
+        let all: Vec<usize> = filter("", &items, |s| s.to_string()).into_iter().map(|(i, _)| i).collect();
+        assert_eq!(all, vec![0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use git2::{Error, Repository};
+use error::OafError;
+use scaffold::Template;
+
+/// The built-in .gitignore templates, named after the language, tool or
+/// operating system whose files they ignore.
+pub const CATALOGUE: &[(&str, &str)] = &[
+    ("Rust", "/target\n**/*.rs.bk\n"),
+    ("Node", "node_modules/\nnpm-debug.log*\nyarn-debug.log*\nyarn-error.log*\n.npm\ndist/\ncoverage/\n.env\n"),
+    ("Python", "__pycache__/\n*.py[cod]\n*$py.class\n*.egg-info/\n.eggs/\nbuild/\ndist/\n.venv/\nvenv/\n.pytest_cache/\n.mypy_cache/\n.coverage\n"),
+    ("Go", "/bin/\n*.exe\n*.test\n*.out\nvendor/\n"),
+    ("Java", "*.class\n*.jar\n*.war\n*.ear\ntarget/\n.gradle/\nbuild/\nhs_err_pid*\n"),
+    ("C", "*.o\n*.obj\n*.a\n*.lib\n*.so\n*.dylib\n*.dll\n*.exe\n*.out\n*.d\n"),
+    ("C++", "*.o\n*.obj\n*.a\n*.lib\n*.so\n*.dylib\n*.dll\n*.exe\n*.out\n*.d\n*.gch\n*.pch\nCMakeFiles/\nCMakeCache.txt\n"),
+    ("VisualStudio", ".vs/\n[Bb]in/\n[Oo]bj/\n*.user\n*.suo\n*.userprefs\n[Dd]ebug/\n[Rr]elease/\n"),
+    ("JetBrains", ".idea/\n*.iml\nout/\n"),
+    ("VisualStudioCode", ".vscode/*\n!.vscode/settings.json\n!.vscode/tasks.json\n!.vscode/launch.json\n!.vscode/extensions.json\n"),
+    ("Vim", "[._]*.s[a-v][a-z]\n[._]*.sw[a-p]\nSession.vim\n*~\ntags\n"),
+    ("Emacs", "*~\n\\#*\\#\n.\\#*\nauto-save-list\n"),
+    ("macOS", ".DS_Store\n.AppleDouble\n.LSOverride\n._*\n"),
+    ("Windows", "Thumbs.db\nehthumbs.db\nDesktop.ini\n$RECYCLE.BIN/\n"),
+    ("Linux", "*~\n.fuse_hidden*\n.directory\n.Trash-*\n"),
+];
+
+/// What appending a template to a .gitignore would do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Append {
+    /// The text to add to the end of the file, including a comment naming
+    /// the template. Empty if there is nothing new to add.
+    pub text: String,
+    /// The template's rules that are already in the file and are left out.
+    pub skipped: Vec<String>,
+}
+
+/// Works out what to add to a .gitignore containing `existing` to get the
+/// rules of `template`. Rules the file already has are left out, so a
+/// template can be applied twice, or after one that overlaps it, without
+/// repeating anything.
+pub fn plan_append(existing: &str, template: &Template) -> Append {
+    let present: Vec<&str> = existing.lines().map(|l| l.trim()).collect();
+    let mut added = Vec::new();
+    let mut skipped = Vec::new();
+    for rule in template.text.lines().map(|l| l.trim_end()).filter(|l| !l.trim().is_empty()) {
+        if present.contains(&rule.trim()) || added.contains(&rule) {
+            skipped.push(rule.to_string());
+        } else {
+            added.push(rule);
+        }
+    }
+
+    if added.is_empty() {
+        return Append { text: String::new(), skipped };
+    }
+
+    // Start on a new line, with a blank line between this and what is there.
+    let mut text = String::new();
+    if !existing.is_empty() {
+        if !existing.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push('\n');
+    }
+    text.push_str(&format!("# {}\n", template.name));
+    for rule in added {
+        text.push_str(rule);
+        text.push('\n');
+    }
+    Append { text, skipped }
+}
+
+/// The current contents of the .gitignore at the root of the working tree,
+/// or an empty string if there is none.
+pub fn read(repo: &Repository) -> Result<String, OafError> {
+    let path = repo.workdir().ok_or_else(|| Error::from_str("A bare repository has no .gitignore."))?.join(".gitignore");
+    if path.exists() { Ok(fs::read_to_string(path)?) } else { Ok(String::new()) }
+}
+
+/// Appends the rules of `template` that the .gitignore at the root of the
+/// working tree does not already have, creating it if need be.
+pub fn append(repo: &Repository, template: &Template) -> Result<Append, OafError> {
+    let plan = plan_append(&read(repo)?, template);
+    if !plan.text.is_empty() {
+        let path = repo.workdir().ok_or_else(|| Error::from_str("A bare repository has no .gitignore."))?.join(".gitignore");
+        OpenOptions::new().create(true).append(true).open(&path)?.write_all(plan.text.as_bytes())?;
+        info!("Appended the '{}' template to {}", template.name, path.display());
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(name: &str, text: &str) -> Template {
+        Template { name: name.to_string(), text: text.to_string() }
+    }
+
+    #[test]
+    fn plan_append_adds_a_commented_section() {
+        let plan = plan_append("*.log", &template("Rust", "/target\n\n**/*.rs.bk\n"));
+        assert_eq!(plan.text, "\n\n# Rust\n/target\n**/*.rs.bk\n");
+        assert!(plan.skipped.is_empty());
+        assert_eq!(plan_append("", &template("Rust", "/target\n")).text, "# Rust\n/target\n");
+    }
+
+    #[test]
+    fn plan_append_skips_rules_already_present() {
+        let plan = plan_append("/target\n", &template("Rust", "/target\n**/*.rs.bk\n**/*.rs.bk\n"));
+        assert_eq!(plan.text, "\n# Rust\n**/*.rs.bk\n");
+        assert_eq!(plan.skipped, vec!["/target".to_string(), "**/*.rs.bk".to_string()]);
+
+        let plan = plan_append("/target\n", &template("Rust", "/target\n"));
+        assert_eq!(plan.text, "");
+    }
+}
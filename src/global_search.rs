@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+use git2::{ObjectType, Oid, Repository, RepositoryOpenFlags, Sort, TreeWalkMode, TreeWalkResult};
+use cancel::Cancel;
+use config;
+use error::OafError;
+use file_content;
+
+/// No more than this many hits are collected from one repository, so that a
+/// common word does not bury the other repositories' results.
+pub const MAX_HITS_PER_REPO: usize = 100;
+
+/// What a search across repositories looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// The messages of the commits on every local branch.
+    Messages,
+    /// The files in the tree HEAD points at.
+    Content,
+}
+
+impl SearchMode {
+    pub fn all() -> &'static [SearchMode] {
+        &[SearchMode::Messages, SearchMode::Content]
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            SearchMode::Messages => "Commit messages on all branches",
+            SearchMode::Content => "File contents at HEAD",
+        }
+    }
+}
+
+/// Something that matched: a commit whose message matched, or a line of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchHit {
+    Commit { oid: Oid, summary: String },
+    Line { path: PathBuf, line: usize, text: String },
+}
+
+/// The hits in one repository, or why it could not be searched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoResults {
+    pub path: PathBuf,
+    pub hits: Vec<SearchHit>,
+    /// True if there were more than `MAX_HITS_PER_REPO` hits.
+    pub truncated: bool,
+    pub error: Option<String>,
+}
+
+/// Searches each of the repositories at `paths` for `query`, ignoring case.
+/// Each repository is opened just for the search and nothing in it is
+/// changed. A repository that cannot be searched gets an error rather than
+/// stopping the search. Only the repositories with hits or errors are
+/// returned, in the order of `paths`. `on_repo` is called before each is
+/// searched.
+pub fn search<F>(paths: &[PathBuf], mode: SearchMode, query: &str, cancel: &Cancel, mut on_repo: F) -> Result<Vec<RepoResults>, OafError>
+    where F: FnMut(&Path)
+{
+    let _timer = timer!("global_search::search");
+    let query = query.to_lowercase();
+    let mut results = Vec::new();
+    for path in paths {
+        cancel.check()?;
+        on_repo(path);
+        let mut hits = Vec::new();
+        let searched = Repository::open_ext(path, RepositoryOpenFlags::NO_SEARCH, Vec::<&Path>::new())
+            .map_err(OafError::from)
+            .and_then(|repo| match mode {
+                SearchMode::Messages => search_messages(&repo, &query, cancel, &mut hits),
+                SearchMode::Content => search_content(&repo, &query, cancel, &mut hits),
+            });
+
+        let error = match searched {
+            Err(OafError::Cancelled) => return Err(OafError::Cancelled),
+            Err(e) => Some(e.to_string()),
+            Ok(()) => None,
+        };
+        if !hits.is_empty() || error.is_some() {
+            let truncated = hits.len() > MAX_HITS_PER_REPO;
+            hits.truncate(MAX_HITS_PER_REPO);
+            results.push(RepoResults { path: path.clone(), hits, truncated, error });
+        }
+    }
+
+    _timer.set_message(format!("Searched {} repositories for '{}', {} had hits", paths.len(), query, results.len()));
+    Ok(results)
+}
+
+/// Collects up to one more hit than the limit, so that the caller can tell
+/// that there were more.
+fn search_messages(repo: &Repository, query: &str, cancel: &Cancel, hits: &mut Vec<SearchHit>) -> Result<(), OafError> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME);
+    revwalk.push_glob("refs/heads/*")?;
+    for oid in revwalk {
+        let oid = oid?;
+        let commit = repo.find_commit(oid)?;
+        if String::from_utf8_lossy(commit.message_bytes()).to_lowercase().contains(query) {
+            hits.push(SearchHit::Commit { oid, summary: commit.summary().unwrap_or("").to_string() });
+            if hits.len() > MAX_HITS_PER_REPO {
+                break;
+            }
+        }
+        cancel.check()?;
+    }
+    Ok(())
+}
+
+fn search_content(repo: &Repository, query: &str, cancel: &Cancel, hits: &mut Vec<SearchHit>) -> Result<(), OafError> {
+    let tree = match repo.head() {
+        Ok(head) => head.peel_to_tree()?,
+        // An empty repository has nothing to search.
+        Err(_) => return Ok(()),
+    };
+
+    let config = config::current();
+    let max_bytes = config.limits.max_file_bytes;
+    let mut failure = None;
+    let walked = tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if cancel.is_cancelled() || hits.len() > MAX_HITS_PER_REPO {
+            return TreeWalkResult::Abort;
+        }
+        if entry.kind() != Some(ObjectType::Blob) {
+            return TreeWalkResult::Ok;
+        }
+        let blob = match repo.find_blob(entry.id()) {
+            Ok(blob) => blob,
+            Err(e) => {
+                failure = Some(e);
+                return TreeWalkResult::Abort;
+            },
+        };
+        if max_bytes > 0 && blob.content().len() as u64 > max_bytes {
+            return TreeWalkResult::Ok;
+        }
+        let path = Path::new(dir).join(entry.name().unwrap_or(""));
+        let text = match file_content::decode(blob.content(), file_content::fallback_for(&config.encoding, &path)) {
+            Some(text) => text,
+            None => return TreeWalkResult::Ok,
+        };
+
+        for (i, line) in text.display().lines().enumerate() {
+            if line.to_lowercase().contains(query) {
+                hits.push(SearchHit::Line { path: path.clone(), line: i + 1, text: line.trim().to_string() });
+            }
+        }
+        TreeWalkResult::Ok
+    });
+
+    // Stopping the walk early makes it return an error, which is expected
+    // when cancelled or when there are enough hits.
+    cancel.check()?;
+    match failure {
+        Some(e) => Err(e.into()),
+        None if hits.len() > MAX_HITS_PER_REPO => Ok(()),
+        None => Ok(walked?),
+    }
+}
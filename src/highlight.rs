@@ -0,0 +1,142 @@
+//! Syntax highlighting of file content, with the language chosen by the
+//! file's extension. The highlighting itself is done by syntect, which is
+//! only built in with the 'syntax-highlighting' feature; without it nothing
+//! is ever highlighted.
+
+use std::path::Path;
+use config::HighlightConfig;
+
+/// A colour chosen by the theme, as red, green and blue.
+pub type Rgb = (u8, u8, u8);
+
+/// Highlights the lines of one file, which must be given in order since
+/// the colour of a line can depend on those before it, e.g. in a block
+/// comment.
+#[cfg_attr(not(feature = "syntax-highlighting"), allow(dead_code))]
+pub struct Highlighter {
+    #[cfg(feature = "syntax-highlighting")]
+    lines: engine::HighlightLines<'static>,
+}
+
+/// A highlighter for the file at `path`, or None if highlighting is turned
+/// off, oaf was built without it, or the file's language is not known.
+pub fn for_path(path: &Path, config: &HighlightConfig) -> Option<Highlighter> {
+    if !config.enabled {
+        return None;
+    }
+    engine::for_path(path, &config.theme)
+}
+
+/// Whether `name` is one of syntect's themes. Without the highlighting built
+/// in any name will do, since it is never used.
+pub fn is_known_theme(name: &str) -> bool {
+    engine::is_known_theme(name)
+}
+
+impl Highlighter {
+    /// Splits `line`, which should not end in a newline, into runs of text
+    /// of the same colour. If the line cannot be highlighted it is returned
+    /// as it is, with no colour.
+    pub fn line(&mut self, line: &str) -> Vec<(Option<Rgb>, String)> {
+        #[cfg(feature = "syntax-highlighting")]
+        {
+            if let Some(runs) = engine::highlight(&mut self.lines, line) {
+                return runs;
+            }
+        }
+        vec![(None, line.to_string())]
+    }
+}
+
+#[cfg(feature = "syntax-highlighting")]
+mod engine {
+    use std::path::Path;
+    use syntect::highlighting::{Theme, ThemeSet};
+    use syntect::parsing::SyntaxSet;
+    pub use syntect::easy::HighlightLines;
+    use super::{Highlighter, Rgb};
+
+    /// Used when the configured theme is not one of syntect's.
+    const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+    lazy_static! {
+        static ref SYNTAXES: SyntaxSet = SyntaxSet::load_defaults_nonewlines();
+        static ref THEMES: ThemeSet = ThemeSet::load_defaults();
+    }
+
+    pub fn for_path(path: &Path, theme: &str) -> Option<Highlighter> {
+        let by_extension = path.extension().and_then(|ext| SYNTAXES.find_syntax_by_extension(&ext.to_string_lossy()));
+        // Files such as 'Makefile' are known by their whole name.
+        let syntax = by_extension
+            .or_else(|| path.file_name().and_then(|name| SYNTAXES.find_syntax_by_extension(&name.to_string_lossy())))?;
+        Some(Highlighter { lines: HighlightLines::new(syntax, find_theme(theme)) })
+    }
+
+    pub fn is_known_theme(name: &str) -> bool {
+        THEMES.themes.contains_key(name)
+    }
+
+    fn find_theme(name: &str) -> &'static Theme {
+        match THEMES.themes.get(name) {
+            Some(theme) => theme,
+            None => {
+                warn!("There is no syntax highlighting theme called '{}', using '{}'", name, DEFAULT_THEME);
+                &THEMES.themes[DEFAULT_THEME]
+            },
+        }
+    }
+
+    pub fn highlight(lines: &mut HighlightLines<'static>, line: &str) -> Option<Vec<(Option<Rgb>, String)>> {
+        let runs = lines.highlight_line(line, &SYNTAXES).ok()?;
+        Some(runs.into_iter()
+            .map(|(style, text)| (Some((style.foreground.r, style.foreground.g, style.foreground.b)), text.to_string()))
+            .collect())
+    }
+}
+
+#[cfg(not(feature = "syntax-highlighting"))]
+mod engine {
+    use std::path::Path;
+    use super::Highlighter;
+
+    pub fn for_path(_path: &Path, _theme: &str) -> Option<Highlighter> {
+        None
+    }
+
+    pub fn is_known_theme(_name: &str) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_is_highlighted_when_turned_off() {
+        let config = HighlightConfig { enabled: false, ..HighlightConfig::default() };
+        assert!(for_path(Path::new("src/main.rs"), &config).is_none());
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn languages_are_chosen_by_extension() {
+        let config = HighlightConfig::default();
+        let mut highlighter = for_path(Path::new("src/main.rs"), &config).unwrap();
+        let runs = highlighter.line("fn main() {}");
+        assert!(runs.len() > 1);
+        assert!(runs.iter().all(|&(rgb, _)| rgb.is_some()));
+        assert_eq!(runs.iter().map(|&(_, ref text)| text.as_str()).collect::<String>(), "fn main() {}");
+
+        assert!(for_path(Path::new("Makefile"), &config).is_some());
+        assert!(for_path(Path::new("notes.unknown-extension"), &config).is_none());
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn themes_are_known_by_name() {
+        assert!(is_known_theme(&HighlightConfig::default().theme));
+        assert!(is_known_theme("InspiredGitHub"));
+        assert!(!is_known_theme("no-such-theme"));
+    }
+}
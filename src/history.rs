@@ -0,0 +1,301 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone};
+use git2::{Commit, DiffOptions, ErrorCode, Oid, Repository, Sort, Time};
+use mailmap::Mailmap;
+
+/// The information about a commit needed to display one line of the log.
+#[derive(Clone)]
+pub struct LogEntry {
+    pub oid: Oid,
+    pub summary: String,
+    pub author: String,
+    pub time: Time,
+}
+
+impl LogEntry {
+    /// The entry for `commit`, whose author, once mapped, is `author`.
+    fn new(commit: &Commit, author: String) -> Self {
+        LogEntry {
+            oid: commit.id(),
+            summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or(b"")).into_owned(),
+            author,
+            time: commit.time(),
+        }
+    }
+}
+
+/// The name and email of the author of `commit`, mapped by `mailmap`.
+fn author_identity(commit: &Commit, mailmap: &Mailmap) -> (String, String) {
+    let author = commit.author();
+    mailmap.resolve(&String::from_utf8_lossy(author.name_bytes()), &String::from_utf8_lossy(author.email_bytes()))
+}
+
+/// The order commits are shown in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSort {
+    /// Newest first by commit time.
+    Time,
+    /// Children before their parents, as 'git log --topo-order'.
+    Topological,
+}
+
+impl LogSort {
+    fn revwalk_sort(&self) -> Sort {
+        match *self {
+            LogSort::Time => Sort::TIME,
+            LogSort::Topological => Sort::TOPOLOGICAL | Sort::TIME,
+        }
+    }
+}
+
+/// Restricts the commits shown in the log. All the criteria must match for a
+/// commit to be shown; an empty filter matches everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogFilter {
+    /// Case-insensitive substring of the author's name or email.
+    pub author: Option<String>,
+    /// Only commits made on or after this date.
+    pub since: Option<NaiveDate>,
+    /// Only commits made on or before this date.
+    pub until: Option<NaiveDate>,
+    /// Only commits that touch a path matching one of these globs.
+    pub paths: Vec<String>,
+}
+
+impl LogFilter {
+    /// Builds a filter from the text a user typed into the filter dialog.
+    /// Empty strings mean 'no restriction'; paths are separated by spaces.
+    /// On Windows '\' is taken to separate directories, as it does in the
+    /// shell, and turned into the '/' that git expects.
+    pub fn parse(author: &str, since: &str, until: &str, paths: &str) -> Result<Self, String> {
+        Ok(LogFilter {
+            author: Some(author.trim().to_string()).filter(|a| !a.is_empty()),
+            since: parse_date(since)?,
+            until: parse_date(until)?,
+            paths: paths.split_whitespace()
+                .map(|p| if cfg!(windows) { p.replace('\\', "/") } else { p.to_string() })
+                .collect(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == LogFilter::default()
+    }
+
+    /// A one-line description of the filter, suitable for a view header.
+    pub fn description(&self) -> String {
+        if self.is_empty() {
+            return "No filter".to_string();
+        }
+
+        let mut parts = Vec::new();
+        if let Some(ref author) = self.author {
+            parts.push(format!("author ~ '{}'", author));
+        }
+        if let Some(since) = self.since {
+            parts.push(format!("since {}", since));
+        }
+        if let Some(until) = self.until {
+            parts.push(format!("until {}", until));
+        }
+        if !self.paths.is_empty() {
+            parts.push(format!("paths {}", self.paths.join(" ")));
+        }
+
+        format!("Filter: {}", parts.join(", "))
+    }
+
+    fn matches_author(&self, name: &str, email: &str) -> bool {
+        match self.author {
+            Some(ref author) => {
+                let author = author.to_lowercase();
+                name.to_lowercase().contains(&author) || email.to_lowercase().contains(&author)
+            },
+            None => true,
+        }
+    }
+}
+
+/// Resolves a revision expression such as a branch or tag name, a (possibly
+/// abbreviated) hash or 'HEAD~3' to the commit it refers to.
+pub fn resolve_commit(repo: &Repository, spec: &str) -> Result<Oid, ::git2::Error> {
+    let object = repo.revparse_single(spec)?;
+    let commit = object.peel_to_commit()?;
+    Ok(commit.id())
+}
+
+/// Loads the log entries for `oids`, in the same order, with authors mapped
+/// by `mailmap`.
+pub fn entries(repo: &Repository, oids: &[Oid], mailmap: &Mailmap) -> Result<Vec<LogEntry>, ::git2::Error> {
+    oids.iter().map(|&oid| repo.find_commit(oid).map(|c| LogEntry::new(&c, author_identity(&c, mailmap).0))).collect()
+}
+
+/// The branch HEAD is on if it has no commits yet, as in a new repository or
+/// after 'git checkout --orphan', e.g. 'master'.
+pub fn unborn_branch(repo: &Repository) -> Option<String> {
+    match repo.head() {
+        Err(ref e) if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound => {
+            let head = repo.find_reference("HEAD").ok()?;
+            let target = head.symbolic_target()?;
+            Some(target.trim_start_matches("refs/heads/").to_string())
+        },
+        _ => None,
+    }
+}
+
+/// The commit HEAD points at, or None if the branch is unborn. A commit made
+/// on an unborn branch is a root commit, with no parents.
+pub fn head_commit(repo: &Repository) -> Result<Option<Commit>, ::git2::Error> {
+    if unborn_branch(repo).is_some() {
+        return Ok(None);
+    }
+    repo.head()?.peel_to_commit().map(Some)
+}
+
+/// Loads up to `limit` commits reachable from `start` (or HEAD) that match
+/// `filter`, in `sort` order. The filter is applied during the walk with the
+/// cheap checks first: the date bounds, then the author, and only then the
+/// path check, which needs a tree diff. When the walk is in time order it
+/// stops as soon as it reaches a commit older than the `since` date. An
+/// unborn HEAD has no history, so gives no commits. Authors are mapped by
+/// `mailmap`, both for the filter and in the entries.
+pub fn load(repo: &Repository, start: Option<Oid>, filter: &LogFilter, sort: LogSort, limit: usize, mailmap: &Mailmap)
+    -> Result<Vec<LogEntry>, ::git2::Error>
+{
+    let _timer = timer!("log::load");
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(sort.revwalk_sort());
+    match start {
+        Some(oid) => revwalk.push(oid)?,
+        None if unborn_branch(repo).is_some() => return Ok(Vec::new()),
+        None => revwalk.push_head()?,
+    }
+
+    let mut diff_opts = DiffOptions::new();
+    for path in &filter.paths {
+        diff_opts.pathspec(path.as_str());
+    }
+
+    let mut entries = Vec::new();
+    let mut examined = 0;
+    for oid in revwalk {
+        if entries.len() >= limit {
+            break;
+        }
+
+        examined += 1;
+        let commit = repo.find_commit(oid?)?;
+        let date = commit_date(&commit.time());
+        if filter.since.map_or(false, |since| date < since) {
+            if sort == LogSort::Time {
+                break;
+            }
+            continue;
+        }
+        if filter.until.map_or(false, |until| date > until) {
+            continue;
+        }
+
+        let (name, email) = author_identity(&commit, mailmap);
+        if !filter.matches_author(&name, &email) {
+            continue;
+        }
+
+        if !filter.paths.is_empty() && !touches_paths(repo, &commit, &mut diff_opts)? {
+            continue;
+        }
+
+        entries.push(LogEntry::new(&commit, name));
+    }
+
+    _timer.set_message(format!("Loaded {} of {} examined commits, {}", entries.len(), examined, filter.description()));
+    Ok(entries)
+}
+
+/// Returns true if the commit changes any path matched by the pathspec in
+/// `diff_opts`. Merge commits are compared against their first parent.
+fn touches_paths(repo: &Repository, commit: &Commit, diff_opts: &mut DiffOptions) -> Result<bool, ::git2::Error> {
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(diff_opts))?;
+    Ok(diff.deltas().len() > 0)
+}
+
+/// The local date on which something happened, in its own timezone. A time
+/// chrono cannot represent is taken to be at the Unix epoch.
+pub fn commit_date(time: &Time) -> NaiveDate {
+    recorded_time(time).map_or_else(|| NaiveDate::from_ymd(1970, 1, 1), |t| t.date().naive_local())
+}
+
+/// The moment `time` records, in the timezone it was recorded in. git
+/// accepts any offset and timestamp in a commit and real histories have
+/// bogus ones, so an offset chrono cannot represent, a day or more either
+/// way, is taken as UTC, and a timestamp it cannot represent gives None.
+pub fn recorded_time(time: &Time) -> Option<DateTime<FixedOffset>> {
+    let offset = time.offset_minutes().checked_mul(60)
+        .and_then(FixedOffset::east_opt)
+        .unwrap_or_else(|| FixedOffset::east(0));
+    offset.timestamp_opt(time.seconds(), 0).single()
+}
+
+fn parse_date(text: &str) -> Result<Option<NaiveDate>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| format!("'{}' is not a valid date, please use YYYY-MM-DD", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_empty_strings_gives_empty_filter() {
+        let filter = LogFilter::parse("", "  ", "", " ").unwrap();
+        assert!(filter.is_empty());
+        assert_eq!(filter.description(), "No filter");
+    }
+
+    #[test]
+    fn parse_all_fields() {
+        let filter = LogFilter::parse(" bob ", "2018-01-01", "2018-12-31", "src/*.rs  README.md").unwrap();
+        assert_eq!(filter.author, Some("bob".to_string()));
+        assert_eq!(filter.since, Some(NaiveDate::from_ymd(2018, 1, 1)));
+        assert_eq!(filter.until, Some(NaiveDate::from_ymd(2018, 12, 31)));
+        assert_eq!(filter.paths, vec!["src/*.rs".to_string(), "README.md".to_string()]);
+        assert_eq!(filter.description(),
+                   "Filter: author ~ 'bob', since 2018-01-01, until 2018-12-31, paths src/*.rs README.md");
+    }
+
+    #[test]
+    fn parse_rejects_bad_dates() {
+        assert!(LogFilter::parse("", "yesterday", "", "").is_err());
+        assert!(LogFilter::parse("", "", "2018-13-01", "").is_err());
+    }
+
+    #[test]
+    fn bogus_offsets_and_timestamps_do_not_panic() {
+        let time = Time::new(1_536_584_602, 99 * 60);
+        assert_eq!(recorded_time(&time).unwrap().offset().local_minus_utc(), 0);
+        assert_eq!(commit_date(&time), NaiveDate::from_ymd(2018, 9, 10));
+        assert_eq!(recorded_time(&Time::new(i64::max_value(), 0)), None);
+        assert_eq!(commit_date(&Time::new(i64::max_value(), 0)), NaiveDate::from_ymd(1970, 1, 1));
+    }
+
+    #[test]
+    fn matches_author_is_case_insensitive_on_name_and_email() {
+        let filter = LogFilter::parse("BOB", "", "", "").unwrap();
+        assert!(filter.matches_author("Bobby Tables", "x@example.com"));
+        assert!(filter.matches_author("Robert", "bob@example.com"));
+        assert!(!filter.matches_author("Alice", "alice@example.com"));
+        assert!(LogFilter::default().matches_author("Anyone", "any@example.com"));
+    }
+}
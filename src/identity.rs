@@ -0,0 +1,142 @@
+//! Identity profiles, such as 'work' and 'personal', each a name, email and
+//! signing key to commit under. A profile is chosen for a repository, or
+//! picked automatically from its remote URLs, so that commits are not made
+//! under the wrong identity.
+
+use git2::{ConfigLevel, Error, ErrorCode, Repository};
+use changelog;
+use config::IdentityProfile;
+use remotes;
+use signing;
+
+/// The repository's git config setting that names its chosen profile.
+const SETTING: &str = "oaf.identity";
+/// The profile last applied to the repository's git config, which shows
+/// that its 'user.name' and 'user.email' were set by oaf.
+const APPLIED: &str = "oaf.identityApplied";
+
+/// The profile a repository's commits are made under, and why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection<'a> {
+    /// Chosen for the repository with `choose`.
+    Chosen(&'a IdentityProfile),
+    /// One of its `remote_patterns` matches the URL of a remote.
+    Matched(&'a IdentityProfile),
+    /// No profile applies, so commits use whatever identity git is set up
+    /// with.
+    NoProfile,
+}
+
+impl<'a> Selection<'a> {
+    pub fn profile(&self) -> Option<&'a IdentityProfile> {
+        match *self {
+            Selection::Chosen(profile) | Selection::Matched(profile) => Some(profile),
+            Selection::NoProfile => None,
+        }
+    }
+}
+
+/// Finds the profile for `repo`: the one chosen for it, or else the first
+/// whose remote patterns match one of its remotes' URLs.
+pub fn select<'a>(repo: &Repository, profiles: &'a [IdentityProfile]) -> Selection<'a> {
+    if let Some(name) = chosen(repo) {
+        match profiles.iter().find(|p| p.name == name) {
+            Some(profile) => return Selection::Chosen(profile),
+            None => warn!("'{}' names the identity profile '{}', which does not exist, ignoring it", SETTING, name),
+        }
+    }
+
+    let urls: Vec<String> = remotes::remote_names(repo).iter()
+        .filter_map(|name| repo.find_remote(name).ok().and_then(|r| r.url().map(|u| u.to_string())))
+        .collect();
+    profiles.iter()
+        .find(|p| p.remote_patterns.iter().any(|pattern| urls.iter().any(|url| changelog::wildcard_match(pattern, url))))
+        .map_or(Selection::NoProfile, Selection::Matched)
+}
+
+/// The name of the profile chosen for `repo`, if any.
+pub fn chosen(repo: &Repository) -> Option<String> {
+    repo.config().and_then(|c| c.get_string(SETTING)).ok()
+}
+
+/// Chooses the profile called `name` for `repo`, or with None goes back to
+/// picking one from the remote URLs.
+pub fn choose(repo: &Repository, name: Option<&str>) -> Result<(), Error> {
+    let mut config = repo.config()?;
+    match name {
+        Some(name) => config.set_str(SETTING, name)?,
+        None => remove(&mut config, SETTING)?,
+    }
+    info!("Chose identity profile {:?} for '{}'", name, repo.path().display());
+    Ok(())
+}
+
+/// What `apply` did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Applied {
+    /// The repository was already set up for the profile.
+    Unchanged,
+    Changed,
+    /// The repository has its own 'user.name' or 'user.email', set by hand
+    /// rather than by oaf, which was kept. Says who commits are made as.
+    KeptLocal(String),
+}
+
+/// Sets the repository's own 'user.name', 'user.email' and 'user.signingKey'
+/// to those of `profile`, so that commits are made under it, by git as well
+/// as oaf. A profile without a key removes the repository's own key, so
+/// git's usual key applies. Only the repository's own settings are looked
+/// at and changed. A name or email set there by hand is kept rather than
+/// overwritten.
+pub fn apply(repo: &Repository, profile: &IdentityProfile) -> Result<Applied, Error> {
+    let mut config = repo.config()?.open_level(ConfigLevel::Local)?;
+    let (name, email, key, applied) = {
+        let local = |name: &str| config.get_string(name).ok();
+        (local("user.name"), local("user.email"), local("user.signingkey"), local(APPLIED))
+    };
+
+    let matches = name.as_ref() == Some(&profile.user_name) && email.as_ref() == Some(&profile.email);
+    if applied.is_none() && !matches && (name.is_some() || email.is_some()) {
+        let own = format!("{} <{}>", name.unwrap_or_default(), email.unwrap_or_default());
+        warn!("'{}' has its own identity {}, not applying profile '{}'", repo.path().display(), own, profile.name);
+        return Ok(Applied::KeptLocal(own));
+    }
+    if matches && key == profile.signing_key && applied.as_ref() == Some(&profile.name) {
+        return Ok(Applied::Unchanged);
+    }
+
+    config.set_str("user.name", &profile.user_name)?;
+    config.set_str("user.email", &profile.email)?;
+    match profile.signing_key {
+        Some(ref key) => config.set_str("user.signingkey", key)?,
+        None => remove(&mut config, "user.signingkey")?,
+    }
+    config.set_str(APPLIED, &profile.name)?;
+    info!("Applied identity profile '{}' to '{}'", profile.name, repo.path().display());
+    Ok(Applied::Changed)
+}
+
+/// Says who commits in `repo` will be made by, for the header of the log.
+/// Empty when no profiles are set up, since then there is nothing to get
+/// wrong.
+pub fn description(repo: &Repository, profiles: &[IdentityProfile]) -> String {
+    if profiles.is_empty() {
+        return String::new();
+    }
+    match select(repo, profiles) {
+        Selection::Chosen(p) => format!("Committing as {} <{}> ({}). ", p.user_name, p.email, p.name),
+        Selection::Matched(p) => format!("Committing as {} <{}> ({}, from remote URL). ", p.user_name, p.email, p.name),
+        Selection::NoProfile => match repo.signature() {
+            Ok(signature) => format!("Committing as {} (no identity profile). ", signing::identity(&signature)),
+            Err(_) => "No identity profile, and no git identity set up. ".to_string(),
+        },
+    }
+}
+
+/// Removes `name` from the config, if it is there.
+fn remove(config: &mut ::git2::Config, name: &str) -> Result<(), Error> {
+    match config.remove(name) {
+        Err(ref e) if e.code() == ErrorCode::NotFound => Ok(()),
+        result => result,
+    }
+}
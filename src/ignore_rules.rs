@@ -0,0 +1,262 @@
+//! Finds which ignore rule applies to a path, as 'git check-ignore -v'
+//! does. libgit2 can say whether a path is ignored, but not why.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use git2::Repository;
+
+/// The rule that decides whether a path is ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreMatch {
+    /// The file the rule is in, e.g. 'src/.gitignore' or '.git/info/exclude'.
+    pub source: PathBuf,
+    /// The line of the rule in `source`, from 1.
+    pub line: usize,
+    pub pattern: String,
+    /// The path the rule matched: the path asked about, or a directory above
+    /// it, which ignores everything inside it.
+    pub matched: PathBuf,
+    /// False if the rule is a negation ('!pattern'), which un-ignores the path.
+    pub ignored: bool,
+}
+
+impl IgnoreMatch {
+    pub fn description(&self, path: &Path) -> String {
+        let rule = format!("'{}' (line {} of {})", self.pattern, self.line, self.source.display());
+        match (self.ignored, self.matched == path) {
+            (true, true) => format!("'{}' is ignored by {}.", path.display(), rule),
+            (true, false) => format!("'{}' is ignored because its directory '{}' is ignored by {}.",
+                                     path.display(), self.matched.display(), rule),
+            (false, _) => format!("'{}' is not ignored: {} un-ignores it.", path.display(), rule),
+        }
+    }
+}
+
+struct Rule {
+    source: PathBuf,
+    line: usize,
+    /// The directory the rule's file is in, relative to the working tree,
+    /// which the pattern is relative to. Empty for the root and for the
+    /// global and repository exclude files.
+    base: String,
+    pattern: String,
+}
+
+/// Explains whether `path` (relative to the working tree) is ignored and by
+/// which rule. None means no rule matches it.
+pub fn check(repo: &Repository, path: &Path) -> Option<IgnoreMatch> {
+    let workdir = repo.workdir()?;
+    let path_text = path.to_string_lossy().replace('\\', "/").trim_matches('/').to_string();
+    let is_dir = workdir.join(path).is_dir();
+
+    // Ancestor directories first: once a directory is ignored nothing
+    // inside it can be un-ignored.
+    let mut candidates: Vec<(&str, bool)> = path_text.match_indices('/').map(|(i, _)| (&path_text[..i], true)).collect();
+    candidates.push((&path_text, is_dir));
+
+    let rules = load_rules(repo, workdir, &path_text);
+    for (n, &(candidate, candidate_is_dir)) in candidates.iter().enumerate() {
+        let found = rules.iter().rev().find(|rule| rule_matches(rule, candidate, candidate_is_dir));
+        if let Some(rule) = found {
+            let ignored = !rule.pattern.starts_with('!');
+            if ignored || n == candidates.len() - 1 {
+                return Some(IgnoreMatch {
+                    source: rule.source.clone(),
+                    line: rule.line,
+                    pattern: rule.pattern.clone(),
+                    matched: PathBuf::from(candidate),
+                    ignored,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// The rules that can apply to `path`, lowest precedence first: the global
+/// excludes file, '.git/info/exclude', then each '.gitignore' from the root
+/// down to the directory containing `path`.
+fn load_rules(repo: &Repository, workdir: &Path, path: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    let global = repo.config().ok()
+        .and_then(|c| c.get_path("core.excludesFile").ok())
+        .or_else(default_excludes_file);
+    if let Some(global) = global {
+        add_rules(&mut rules, &global, global.clone(), "");
+    }
+    add_rules(&mut rules, &repo.path().join("info").join("exclude"), PathBuf::from(".git/info/exclude"), "");
+
+    let mut base = String::new();
+    add_rules(&mut rules, &workdir.join(".gitignore"), PathBuf::from(".gitignore"), "");
+    let dirs: Vec<&str> = path.split('/').collect();
+    for dir in &dirs[..dirs.len().saturating_sub(1)] {
+        base = if base.is_empty() { dir.to_string() } else { format!("{}/{}", base, dir) };
+        let source = PathBuf::from(&base).join(".gitignore");
+        add_rules(&mut rules, &workdir.join(&source), source, &base);
+    }
+    rules
+}
+
+/// Where git looks for global ignore rules when 'core.excludesFile' is not
+/// set.
+fn default_excludes_file() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(config_home.join("git").join("ignore"))
+}
+
+fn add_rules(rules: &mut Vec<Rule>, file: &Path, source: PathBuf, base: &str) {
+    let text = match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    for (n, line) in text.lines().enumerate() {
+        let pattern = line.trim_end();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            continue;
+        }
+        rules.push(Rule { source: source.clone(), line: n + 1, base: base.to_string(), pattern: pattern.to_string() });
+    }
+}
+
+fn rule_matches(rule: &Rule, path: &str, is_dir: bool) -> bool {
+    let relative = if rule.base.is_empty() {
+        path
+    } else if path.starts_with(&format!("{}/", rule.base)) {
+        &path[rule.base.len() + 1..]
+    } else {
+        return false;
+    };
+    pattern_matches(rule.pattern.trim_start_matches('!'), relative, is_dir)
+}
+
+/// Whether a gitignore-style pattern matches `path`, itself rather than
+/// through one of its directories. A pattern with a '/' other than at the
+/// end is anchored to the start of the path; otherwise it matches the last
+/// component. A trailing '/' only matches directories.
+pub fn pattern_matches(pattern: &str, path: &str, is_dir: bool) -> bool {
+    let pattern = pattern.trim_start_matches('\\');
+    let dir_only = pattern.ends_with('/');
+    if dir_only && !is_dir {
+        return false;
+    }
+    let pattern = pattern.trim_end_matches('/');
+    if pattern.contains('/') {
+        glob(pattern.trim_start_matches('/').as_bytes(), path.as_bytes())
+    } else {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        glob(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+/// Matches '*' (within a directory), '**' (across directories), '?' and
+/// classes such as '[abc]', '[a-z]' and '[!0-9]' ('^' negates too).
+pub fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = pattern[2..].strip_prefix(b"/").unwrap_or(&pattern[2..]);
+            (0..text.len() + 1).any(|i| glob(rest, &text[i..]))
+        },
+        Some(b'*') => (0..text.len() + 1)
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob(&pattern[1..], &text[i..])),
+        Some(b'?') => !text.is_empty() && text[0] != b'/' && glob(&pattern[1..], &text[1..]),
+        Some(b'[') => match class(&pattern[1..], text.first().cloned()) {
+            Some((matched, len)) => matched && glob(&pattern[1 + len..], &text[1..]),
+            None => text.first() == Some(&b'[') && glob(&pattern[1..], &text[1..]),
+        },
+        Some(&c) => text.first() == Some(&c) && glob(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Matches `c` against the class at the start of `pattern`, just after its
+/// '['. Gives whether it matched and the length of the class up to and
+/// including its ']', or None if there is no ']', when the '[' is an
+/// ordinary character. A ']' first in the class is part of it, as is a '-'
+/// first or last. No class matches a '/' or the end of the text.
+fn class(pattern: &[u8], c: Option<u8>) -> Option<(bool, usize)> {
+    let negated = match pattern.first() {
+        Some(b'!') | Some(b'^') => true,
+        _ => false,
+    };
+    let start = if negated { 1 } else { 0 };
+    let mut matched = false;
+    let mut i = start;
+    while i < pattern.len() {
+        if pattern[i] == b']' && i > start {
+            let matched = match c {
+                Some(b'/') | None => false,
+                Some(_) => matched != negated,
+            };
+            return Some((matched, i + 1));
+        }
+        let low = pattern[i];
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some(b'-'), Some(&high)) if high != b']' => {
+                matched |= c.map_or(false, |c| low <= c && c <= high);
+                i += 3;
+            },
+            _ => {
+                matched |= c == Some(low);
+                i += 1;
+            },
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(base: &str, pattern: &str) -> Rule {
+        Rule { source: PathBuf::from(".gitignore"), line: 1, base: base.to_string(), pattern: pattern.to_string() }
+    }
+
+    #[test]
+    fn pattern_matches_works() {
+        assert!(pattern_matches("*.o", "src/a.o", false));
+        assert!(pattern_matches("/build/", "build", true));
+        assert!(!pattern_matches("/build/", "build", false));
+        assert!(!pattern_matches("/build", "src/build", true));
+        assert!(pattern_matches("doc/*.txt", "doc/a.txt", false));
+        assert!(!pattern_matches("doc/*.txt", "doc/x/a.txt", false));
+        assert!(pattern_matches("**/target", "a/b/target", true));
+        assert!(pattern_matches("[Bb]in/", "src/bin", true));
+    }
+
+    #[test]
+    fn rules_are_relative_to_their_directory() {
+        assert!(rule_matches(&rule("src", "/gen/"), "src/gen", true));
+        assert!(!rule_matches(&rule("src", "/gen/"), "gen", true));
+        assert!(rule_matches(&rule("src", "!keep.o"), "src/x/keep.o", false));
+    }
+
+    #[test]
+    fn glob_works() {
+        assert!(glob(b"*.rs", b"main.rs"));
+        assert!(!glob(b"*.rs", b"src/main.rs"));
+        assert!(glob(b"src/**/*.rs", b"src/a/b/main.rs"));
+        assert!(glob(b"src/**/*.rs", b"src/main.rs"));
+        assert!(glob(b"a?c", b"abc"));
+    }
+
+    #[test]
+    fn glob_matches_classes() {
+        assert!(glob(b"*.py[cod]", b"x.pyc"));
+        assert!(glob(b"*.py[cod]", b"x.pyd"));
+        assert!(!glob(b"*.py[cod]", b"x.pyx"));
+        assert!(!glob(b"*.py[cod]", b"x.py"));
+        assert!(glob(b"[Bb]in", b"Bin"));
+        assert!(glob(b"[._]*.s[a-v][a-z]", b".main.suo"));
+        assert!(!glob(b"[._]*.s[a-v][a-z]", b".main.sxp"));
+        assert!(glob(b"[!0-9]x", b"ax"));
+        assert!(!glob(b"[^0-9]x", b"5x"));
+        assert!(glob(b"[]a]", b"]"));
+        assert!(glob(b"[a-]", b"-"));
+        assert!(!glob(b"a[/]b", b"a/b"));
+        assert!(glob(b"a[b", b"a[b"));
+    }
+}
@@ -0,0 +1,439 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use git2::{build::CheckoutBuilder, Error, Index, Oid, Repository, RepositoryState, ResetType};
+use backups;
+use error::OafError;
+use remotes;
+use rewrite;
+
+/// An operation that git started but did not finish, usually because of
+/// conflicts, leaving state files in the .git directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Merge,
+    Revert,
+    CherryPick,
+    Rebase,
+    Bisect,
+    ApplyMailbox,
+}
+
+impl Operation {
+    fn from_state(state: RepositoryState) -> Option<Operation> {
+        match state {
+            RepositoryState::Clean => None,
+            RepositoryState::Merge => Some(Operation::Merge),
+            RepositoryState::Revert | RepositoryState::RevertSequence => Some(Operation::Revert),
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => Some(Operation::CherryPick),
+            RepositoryState::Bisect => Some(Operation::Bisect),
+            RepositoryState::Rebase | RepositoryState::RebaseInteractive | RepositoryState::RebaseMerge
+                | RepositoryState::ApplyMailboxOrRebase => Some(Operation::Rebase),
+            RepositoryState::ApplyMailbox => Some(Operation::ApplyMailbox),
+        }
+    }
+
+    /// The git command that started the operation.
+    pub fn command(&self) -> &'static str {
+        match *self {
+            Operation::Merge => "merge",
+            Operation::Revert => "revert",
+            Operation::CherryPick => "cherry-pick",
+            Operation::Rebase => "rebase",
+            Operation::Bisect => "bisect",
+            Operation::ApplyMailbox => "am",
+        }
+    }
+
+    /// Whether oaf can finish the operation by committing the index. A
+    /// rebase or 'git am' has further commits to apply, which only git can
+    /// do, and a bisect is finished by marking commits good or bad.
+    pub fn can_continue(&self) -> bool {
+        match *self {
+            Operation::Merge | Operation::Revert | Operation::CherryPick => true,
+            Operation::Rebase | Operation::Bisect | Operation::ApplyMailbox => false,
+        }
+    }
+
+    /// What aborting is called, e.g. for a button.
+    pub fn abort_label(&self) -> &'static str {
+        match *self {
+            Operation::Bisect => "Reset",
+            _ => "Abort",
+        }
+    }
+}
+
+/// What became of the changes a rebase started with '--autostash' put
+/// aside, once the rebase is aborted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Autostash {
+    /// Nothing was put aside.
+    None,
+    /// The changes are back in the working tree.
+    Applied,
+    /// The changes conflicted with the working tree, so they were kept as a
+    /// stash entry instead.
+    Stored(Oid),
+}
+
+impl Autostash {
+    /// What to tell the user once the operation has been aborted.
+    pub fn message(&self) -> String {
+        match *self {
+            Autostash::None => "Done.".to_string(),
+            Autostash::Applied => "Done. The changes the rebase stashed have been applied again.".to_string(),
+            Autostash::Stored(oid) => format!("Done. The changes the rebase stashed conflicted when applied, so \
+                                               they are kept in the stash as {:.7}; use 'git stash pop' to get them \
+                                               back.", oid.to_string()),
+        }
+    }
+}
+
+/// The operation in progress in a repository, described for the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InProgress {
+    pub operation: Operation,
+    /// What is being merged, picked or rebased, e.g. "rebasing 'topic' onto
+    /// 1234567, step 2 of 5".
+    pub detail: String,
+    /// The number of files with unresolved conflicts.
+    pub conflicts: usize,
+}
+
+impl InProgress {
+    /// A one line description for the banner.
+    pub fn banner(&self) -> String {
+        let mut text = format!("A {} is in progress: {}.", self.operation.command(), self.detail);
+        if self.conflicts > 0 {
+            text += &format!(" {} files have conflicts.", self.conflicts);
+        }
+        text
+    }
+
+    /// How to carry on with git, for what oaf cannot do itself.
+    pub fn command_line_hint(&self) -> String {
+        match self.operation {
+            Operation::Bisect => "Mark commits with 'git bisect good' or 'git bisect bad'.".to_string(),
+            op => format!("Resolve any conflicts, stage the files, then run 'git {} --continue'.", op.command()),
+        }
+    }
+}
+
+/// Finds out whether an operation is in progress in the repository, from
+/// the state files git leaves in the .git directory.
+pub fn detect(repo: &Repository) -> Result<Option<InProgress>, Error> {
+    let operation = match Operation::from_state(repo.state()) {
+        Some(operation) => operation,
+        None => return Ok(None),
+    };
+
+    let conflicts = conflicted_files(&repo.index()?);
+    let detail = match operation {
+        Operation::Merge => first_line(&repo.message().unwrap_or_default())
+            .unwrap_or_else(|| "merging".to_string()),
+        Operation::CherryPick => commit_detail(repo, "picking", "CHERRY_PICK_HEAD"),
+        Operation::Revert => commit_detail(repo, "reverting", "REVERT_HEAD"),
+        Operation::Rebase => rebase_detail(repo),
+        Operation::Bisect => match state_file(repo, "BISECT_START") {
+            Some(start) => format!("bisecting, started from '{}'", remotes::short_branch_name(&start)),
+            None => "bisecting".to_string(),
+        },
+        Operation::ApplyMailbox => "applying patches from a mailbox".to_string(),
+    };
+
+    Ok(Some(InProgress { operation, detail, conflicts }))
+}
+
+/// The number of paths in the index with conflict entries, i.e. entries at
+/// a stage other than 0.
+fn conflicted_files(index: &Index) -> usize {
+    if !index.has_conflicts() {
+        return 0;
+    }
+    let mut paths: Vec<Vec<u8>> = index.iter().filter(|e| (e.flags >> 12) & 3 != 0).map(|e| e.path).collect();
+    paths.dedup();
+    paths.len()
+}
+
+fn first_line(text: &str) -> Option<String> {
+    text.lines().next().map(|l| l.trim().to_string()).filter(|l| !l.is_empty())
+}
+
+/// Reads one of git's state files, e.g. 'MERGE_HEAD' or
+/// 'rebase-merge/head-name', trimmed.
+fn state_file(repo: &Repository, name: &str) -> Option<String> {
+    fs::read_to_string(repo.path().join(name)).ok().map(|s| s.trim().to_string())
+}
+
+fn state_oid(repo: &Repository, name: &str) -> Option<Oid> {
+    state_file(repo, name).and_then(|s| Oid::from_str(&s).ok())
+}
+
+fn commit_detail(repo: &Repository, verb: &str, head_file: &str) -> String {
+    let commit = state_oid(repo, head_file).and_then(|oid| repo.find_commit(oid).ok());
+    match commit {
+        Some(commit) => format!("{} {:.7} {}", verb, commit.id().to_string(), commit.summary().unwrap_or("")),
+        None => verb.to_string(),
+    }
+}
+
+/// The directory a rebase keeps its state in: 'rebase-merge' for the merge
+/// and interactive backends, 'rebase-apply' for the older one.
+fn rebase_dir(repo: &Repository) -> &'static str {
+    if repo.path().join("rebase-merge").is_dir() { "rebase-merge" } else { "rebase-apply" }
+}
+
+fn rebase_detail(repo: &Repository) -> String {
+    let dir = rebase_dir(repo);
+    let file = |name: &str| state_file(repo, &format!("{}/{}", dir, name));
+    let branch = file("head-name").map_or("HEAD".to_string(), |h| remotes::short_branch_name(&h).to_string());
+    let onto = file("onto").map_or(String::new(), |o| format!(" onto {:.7}", o));
+
+    let (step, total) = if dir == "rebase-merge" { (file("msgnum"), file("end")) } else { (file("next"), file("last")) };
+    match (step, total) {
+        (Some(step), Some(total)) => format!("rebasing '{}'{}, step {} of {}", branch, onto, step, total),
+        _ => format!("rebasing '{}'{}", branch, onto),
+    }
+}
+
+/// Whether a cherry-pick or revert of several commits is in progress. git
+/// keeps the commits still to do in '.git/sequencer', which only git reads,
+/// so oaf leaves these to it.
+fn is_sequence(repo: &Repository) -> bool {
+    match repo.state() {
+        RepositoryState::CherryPickSequence | RepositoryState::RevertSequence => true,
+        _ => false,
+    }
+}
+
+fn sequence_error(repo: &Repository, action: &str) -> OafError {
+    let command = if repo.state() == RepositoryState::RevertSequence { "revert" } else { "cherry-pick" };
+    OafError::Git(Error::from_str(&format!(
+        "A {} of several commits is in progress; run 'git {} --{}' so the rest of them are dealt with too.",
+        command, command, action)))
+}
+
+/// Finishes a merge, revert or cherry-pick by committing the index with the
+/// message git prepared, as 'git <command> --continue' does. All conflicts
+/// must have been resolved. A cherry-pick or revert of several commits is
+/// refused, since the commits after this one would be dropped.
+pub fn continue_operation(repo: &Repository) -> Result<Oid, OafError> {
+    if is_sequence(repo) {
+        return Err(sequence_error(repo, "continue"));
+    }
+    let state = match detect(repo)? {
+        Some(ref state) if state.operation.can_continue() => state.clone(),
+        Some(state) => return Err(OafError::Git(Error::from_str(&state.command_line_hint()))),
+        None => return Err(OafError::Git(Error::from_str("There is no operation in progress."))),
+    };
+    if state.conflicts > 0 {
+        return Err(OafError::Merge(Error::from_str(&format!(
+            "{} files still have conflicts; resolve and stage them first.", state.conflicts))));
+    }
+
+    let mut index = repo.index().map_err(OafError::Index)?;
+    let tree = repo.find_tree(index.write_tree().map_err(OafError::Index)?)?;
+    let mut parents = vec![repo.head()?.peel_to_commit()?];
+    if state.operation == Operation::Merge {
+        for line in state_file(repo, "MERGE_HEAD").unwrap_or_default().lines() {
+            parents.push(repo.find_commit(Oid::from_str(line.trim())?)?);
+        }
+    }
+
+    let committer = repo.signature()?;
+    let author = match state_oid(repo, "CHERRY_PICK_HEAD") {
+        Some(oid) if state.operation == Operation::CherryPick => repo.find_commit(oid)?.author().to_owned(),
+        _ => committer.clone(),
+    };
+    let prepared: String = repo.message().unwrap_or_default().lines()
+        .filter(|l| !l.starts_with('#'))
+        .map(|l| format!("{}\n", l))
+        .collect();
+    let message = rewrite::clean_message(&prepared).unwrap_or_else(|| format!("Finish {}\n", state.operation.command()));
+
+    let parent_refs: Vec<_> = parents.iter().collect();
+    let oid = repo.commit(Some("HEAD"), &author, &committer, &message, &tree, &parent_refs)?;
+    repo.cleanup_state()?;
+    info!("Finished the {} in progress with commit {}", state.operation.command(), oid);
+    Ok(oid)
+}
+
+/// Abandons the operation in progress, putting HEAD and the working tree
+/// back as they were before it started, as 'git <command> --abort' (or 'git
+/// bisect reset') does. Changes made while resolving conflicts are lost, but
+/// changes to files the merge, revert or cherry-pick did not touch are kept.
+/// The tip of a rebase is backed up first, since its commits are no longer
+/// on a branch afterwards, and changes the rebase stashed are put back.
+pub fn abort(repo: &Repository) -> Result<Autostash, OafError> {
+    let operation = match Operation::from_state(repo.state()) {
+        Some(operation) => operation,
+        None => return Err(OafError::Git(Error::from_str("There is no operation in progress."))),
+    };
+    if is_sequence(repo) {
+        return Err(sequence_error(repo, "abort"));
+    }
+
+    let autostash = match operation {
+        Operation::Merge | Operation::Revert | Operation::CherryPick => {
+            reset_merge(repo)?;
+            repo.cleanup_state()?;
+            Autostash::None
+        },
+        Operation::Rebase | Operation::ApplyMailbox => abort_rebase(repo)?,
+        Operation::Bisect => {
+            reset_bisect(repo)?;
+            Autostash::None
+        },
+    };
+
+    info!("Aborted the {} in progress, autostash {:?}", operation.command(), autostash);
+    Ok(autostash)
+}
+
+/// Puts back the files the operation changed in the index, as 'git reset
+/// --merge' does: those that differ between HEAD and the index, and those
+/// with conflicts. Other files, and any changes to them in the working
+/// tree, are left alone.
+fn reset_merge(repo: &Repository) -> Result<(), OafError> {
+    let head = repo.head()?.peel_to_commit()?;
+    let index = repo.index().map_err(OafError::Index)?;
+    let mut paths: Vec<PathBuf> = index.iter()
+        .filter(|e| (e.flags >> 12) & 3 != 0)
+        .map(|e| PathBuf::from(String::from_utf8_lossy(&e.path).into_owned()))
+        .collect();
+    let diff = repo.diff_tree_to_index(Some(&head.tree()?), Some(&index), None)?;
+    for delta in diff.deltas() {
+        if let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) {
+            paths.push(path.to_path_buf());
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    repo.reset_default(Some(head.as_object()), paths.iter().map(|p| p.as_path())).map_err(OafError::Index)?;
+    // Files the operation added are not in HEAD, so they are removed.
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force().remove_untracked(true);
+    for path in &paths {
+        checkout.path(path.as_path());
+    }
+    repo.checkout_head(Some(&mut checkout)).map_err(OafError::Index)?;
+    Ok(())
+}
+
+fn abort_rebase(repo: &Repository) -> Result<Autostash, OafError> {
+    let dir = rebase_dir(repo);
+    // The state directory holds the only reference to the autostash commit,
+    // so it must be dealt with before the directory goes.
+    let autostash = state_oid(repo, &format!("{}/autostash", dir));
+    let orig_head = state_oid(repo, &format!("{}/orig-head", dir))
+        .or_else(|| state_oid(repo, "ORIG_HEAD"))
+        .ok_or_else(|| Error::from_str("Cannot find where the rebase started."))?;
+    let head_name = state_file(repo, &format!("{}/head-name", dir)).filter(|h| h.starts_with("refs/heads/"));
+
+    if let Ok(tip) = repo.head().and_then(|h| h.peel_to_commit()) {
+        if tip.id() != orig_head {
+            let branch = head_name.as_ref().map_or("HEAD", |h| remotes::short_branch_name(h));
+            backups::create(repo, &[(branch, tip.id())], "abort rebase")?;
+        }
+    }
+
+    if let Some(ref head_name) = head_name {
+        repo.reference(head_name, orig_head, true, "rebase: aborting")?;
+        repo.set_head(head_name)?;
+    } else {
+        repo.set_head_detached(orig_head)?;
+    }
+    let commit = repo.find_commit(orig_head)?;
+    repo.reset(commit.as_object(), ResetType::Hard, None).map_err(OafError::Index)?;
+    let autostash = match autostash {
+        Some(oid) => apply_autostash(repo, oid)?,
+        None => Autostash::None,
+    };
+    fs::remove_dir_all(repo.path().join(dir))?;
+    Ok(autostash)
+}
+
+/// Applies the changes a rebase stashed, as git does when a rebase ends. If
+/// they conflict they are stored as a stash entry instead, so they are not
+/// lost. libgit2 cannot apply a stash commit that is not in the stash list,
+/// so git does both.
+fn apply_autostash(repo: &Repository, oid: Oid) -> Result<Autostash, OafError> {
+    let workdir = repo.workdir().ok_or_else(|| Error::from_str("A rebase needs a working tree."))?;
+    let oid_text = oid.to_string();
+    if git(workdir, &["stash", "apply", &oid_text])?.status.success() {
+        return Ok(Autostash::Applied);
+    }
+
+    let output = git(workdir, &["stash", "store", "-m", "autostash", &oid_text])?;
+    if !output.status.success() {
+        return Err(OafError::Git(Error::from_str(&format!(
+            "Could not apply or store the changes the rebase stashed, {}: {}",
+            oid_text, String::from_utf8_lossy(&output.stderr).trim()))));
+    }
+    warn!("The autostash {} conflicted and was stored in the stash", oid);
+    Ok(Autostash::Stored(oid))
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<Output, OafError> {
+    Ok(Command::new("git").arg("-C").arg(dir).args(args).output()?)
+}
+
+fn reset_bisect(repo: &Repository) -> Result<(), OafError> {
+    if let Some(start) = state_file(repo, "BISECT_START") {
+        let branch_ref = remotes::full_branch_ref(&start);
+        if repo.find_reference(&branch_ref).is_ok() {
+            repo.set_head(&branch_ref)?;
+        } else {
+            repo.set_head_detached(Oid::from_str(&start)?)?;
+        }
+        repo.checkout_head(Some(CheckoutBuilder::new().force())).map_err(OafError::Index)?;
+    }
+
+    for reference in repo.references_glob("refs/bisect/*")? {
+        reference?.delete()?;
+    }
+    for name in &["BISECT_START", "BISECT_LOG", "BISECT_TERMS", "BISECT_NAMES", "BISECT_EXPECTED_REV", "BISECT_ANCESTORS_OK", "BISECT_RUN"] {
+        let path = repo.path().join(name);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_map_to_operations() {
+        assert_eq!(Operation::from_state(RepositoryState::Clean), None);
+        assert_eq!(Operation::from_state(RepositoryState::RebaseInteractive), Some(Operation::Rebase));
+        assert_eq!(Operation::from_state(RepositoryState::CherryPickSequence), Some(Operation::CherryPick));
+    }
+
+    #[test]
+    fn banner_mentions_conflicts() {
+        let state = InProgress { operation: Operation::Merge, detail: "Merge branch 'feature'".to_string(), conflicts: 2 };
+        assert_eq!(state.banner(), "A merge is in progress: Merge branch 'feature'. 2 files have conflicts.");
+        assert_eq!(state.command_line_hint(), "Resolve any conflicts, stage the files, then run 'git merge --continue'.");
+    }
+
+    #[test]
+    fn only_single_commit_operations_can_be_continued() {
+        assert!(Operation::CherryPick.can_continue());
+        assert!(!Operation::Rebase.can_continue());
+        assert_eq!(Operation::Bisect.abort_label(), "Reset");
+    }
+
+    #[test]
+    fn a_stored_autostash_says_how_to_get_it_back() {
+        assert_eq!(Autostash::None.message(), "Done.");
+        assert!(Autostash::Stored(Oid::zero()).message().contains("'git stash pop'"));
+    }
+}
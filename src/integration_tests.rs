@@ -0,0 +1,1365 @@
+//! End-to-end tests of the repository layer against real repositories built
+//! by `fixtures`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use git2::{BranchType, Direction, ObjectType, Signature};
+use activity::{self, ActivityScope};
+use backport::{self, Outcome};
+use backups;
+use cancel::Cancel;
+use codeowners::CodeOwners;
+use commit_graph::CommitGraph;
+use commit;
+use default_branch;
+use config::{ChangelogConfig, IdentityProfile, NewRepoConfig, SigningConfig};
+use diffstat::{self, Totals};
+use error::OafError;
+use file_revisions::{Content, FileRevisions};
+use fixtures::TestRepo;
+use gitignore_catalogue;
+use global_search::{self, SearchHit, SearchMode};
+use history::{self, LogFilter, LogSort};
+use identity::{self, Applied, Selection};
+use ignore_rules;
+use mailmap::Mailmap;
+use in_progress::{self, Operation};
+use inspect;
+use maintenance::{self, Task};
+use mru_list::MruList;
+use patch::{self, DiffLimits, PatchLine, PatchStream};
+use patch_stack::{self, Stack};
+use push_review::{self, Warning};
+use range_diff::DiffLine;
+use recent_commits::RecentCommits;
+use ref_editor::{self, NoBackup};
+use refresh;
+use refspecs::{self, Refspec};
+use release::{self, Bump};
+use remotes::{self, TagFetch, UpstreamSpec};
+use repo_setup::{self, Step};
+use repositories::{self, Repositories};
+use review_marks::{self, ReviewMarks, ReviewState};
+use rewrite::{self, SplitPart};
+use sandbox::{self, Operation as SandboxOperation, Outcome as SandboxOutcome};
+use scaffold::{self, Scaffold, TemplateKind};
+use script::{self, State};
+use signatures::{self, SignatureStatus};
+use signing::{self, Enforcement, PolicyCheck, SigningSetup};
+use sparse;
+use staging;
+use status;
+use untracked;
+use discard;
+
+fn repositories(fixture: &TestRepo) -> Repositories {
+    Repositories::new(MruList::new(fixture.path().join(".git").join("oaf-test-mru"), 20))
+}
+
+#[test]
+fn open_finds_the_repository_from_a_subdirectory() {
+    let fixture = TestRepo::new("open");
+    fixture.commit_file("src/main.rs", "fn main() {}\n", "Initial commit");
+
+    let mut repos = repositories(&fixture);
+    let repo = repositories::open_repository(&fixture.path().join("src")).unwrap();
+    assert_eq!(repo.workdir().map(|p| p.canonicalize().unwrap()), Some(fixture.path().canonicalize().unwrap()));
+    assert!(repos.finish_open(fixture.path(), Ok(repo)).is_ok());
+    assert_eq!(repos.iter().count(), 1);
+}
+
+#[test]
+fn open_reports_failure_modes() {
+    let fixture = TestRepo::new("open-twice");
+    fixture.commit_file("a.txt", "a\n", "Initial commit");
+    let mut repos = repositories(&fixture);
+
+    let first = repositories::open_repository(fixture.path());
+    assert!(repos.finish_open(fixture.path(), first).is_ok());
+    let second = repositories::open_repository(fixture.path());
+    match repos.finish_open(fixture.path(), second) {
+        Err(OafError::AlreadyOpen(ref path)) => assert_eq!(path, fixture.path()),
+        other => panic!("Expected AlreadyOpen, got {:?}", other.map(|r| r.path().to_path_buf())),
+    }
+
+    let not_a_repo = PathBuf::from("/");
+    match repositories::open_repository(&not_a_repo) {
+        Err(OafError::RepoOpen { ref path, .. }) => assert_eq!(path, &not_a_repo),
+        other => panic!("Expected RepoOpen, got {:?}", other.map(|r| r.path().to_path_buf())),
+    }
+}
+
+#[test]
+fn status_lists_modified_and_untracked_files() {
+    let fixture = TestRepo::new("status");
+    fixture.commit_file("tracked.txt", "one\n", "Initial commit");
+    fixture.write("tracked.txt", "two\n").write("new.txt", "new\n");
+
+    let mut changed: Vec<(PathBuf, String)> = status::changed_files(&fixture.repo, true).unwrap()
+        .into_iter()
+        .map(|(path, s)| (path, status::short_code(s)))
+        .collect();
+    changed.sort();
+    assert_eq!(changed, vec![
+        (PathBuf::from("new.txt"), "??".to_string()),
+        (PathBuf::from("tracked.txt"), " M".to_string()),
+    ]);
+
+    assert_eq!(status::changed_files(&fixture.repo, false).unwrap().len(), 1);
+}
+
+#[test]
+fn untracked_directories_can_be_staged_and_ignored() {
+    let fixture = TestRepo::new("status-tree");
+    fixture.commit_file("tracked.txt", "one\n", "Initial commit");
+    fixture.write("src/new/a.rs", "a\n").write("src/new/b.rs", "b\n").write("build/out.o", "o\n");
+
+    let tree = status::StatusTree::new(status::changed_files(&fixture.repo, true).unwrap());
+    let src = tree.untracked.find(Path::new("src")).unwrap();
+    untracked::stage(&fixture.repo, &src.all_files()).unwrap();
+    untracked::ignore(&fixture.repo, Path::new("build"), true).unwrap();
+
+    let mut changed: Vec<(PathBuf, String)> = status::changed_files(&fixture.repo, true).unwrap()
+        .into_iter()
+        .map(|(path, s)| (path, status::short_code(s)))
+        .collect();
+    changed.sort();
+    assert_eq!(changed, vec![
+        (PathBuf::from(".gitignore"), "??".to_string()),
+        (PathBuf::from("src/new/a.rs"), "A ".to_string()),
+        (PathBuf::from("src/new/b.rs"), "A ".to_string()),
+    ]);
+    assert_eq!(fs::read_to_string(fixture.path().join(".gitignore")).unwrap(), "/build/\n");
+}
+
+#[test]
+fn unborn_branches_have_no_history_until_the_root_commit() {
+    let fixture = TestRepo::new("unborn");
+    fixture.write("a.txt", "a\n");
+    assert_eq!(history::unborn_branch(&fixture.repo), Some("master".to_string()));
+    assert!(history::load(&fixture.repo, None, &LogFilter::default(), LogSort::Time, 10, &Mailmap::default()).unwrap().is_empty());
+    assert_eq!(status::changed_files(&fixture.repo, true).unwrap().len(), 1);
+
+    untracked::stage(&fixture.repo, &[PathBuf::from("a.txt")]).unwrap();
+    let root = fixture.repo.find_commit(commit::commit_index(&fixture.repo, "First\n").unwrap()).unwrap();
+    assert_eq!(root.parents().len(), 0);
+    assert_eq!(history::unborn_branch(&fixture.repo), None);
+    assert_eq!(history::load(&fixture.repo, None, &LogFilter::default(), LogSort::Time, 10, &Mailmap::default()).unwrap().len(), 1);
+
+    fixture.repo.set_head("refs/heads/orphan").unwrap();
+    assert_eq!(history::unborn_branch(&fixture.repo), Some("orphan".to_string()));
+    assert!(history::load(&fixture.repo, None, &LogFilter::default(), LogSort::Time, 10, &Mailmap::default()).unwrap().is_empty());
+    let orphan = fixture.repo.find_commit(commit::commit_index(&fixture.repo, "Orphan\n").unwrap()).unwrap();
+    assert_eq!((orphan.parents().len(), orphan.tree_id()), (0, root.tree_id()));
+}
+
+#[test]
+fn sparse_checkout_removes_and_restores_files() {
+    let fixture = TestRepo::new("sparse");
+    fixture.write("README", "r\n").write("src/app/main.rs", "m\n").write("docs/guide.md", "g\n");
+    fixture.commit("Initial commit");
+    fixture.write("docs/guide.md", "changed\n");
+
+    let applied = sparse::save(&fixture.repo, &sparse::from_edit_text("src/app\n", true)).unwrap();
+    assert_eq!((applied.removed, applied.kept), (0, vec![PathBuf::from("docs/guide.md")]));
+    fixture.write("docs/guide.md", "g\n");
+    let applied = sparse::apply(&fixture.repo, sparse::SparseCheckout::load(&fixture.repo).as_ref()).unwrap();
+    assert_eq!(applied.removed, 1);
+    assert!(!fixture.path().join("docs").exists());
+    assert!(fixture.path().join("src/app/main.rs").exists() && fixture.path().join("README").exists());
+    assert!(status::changed_files(&fixture.repo, true).unwrap().is_empty());
+
+    let applied = sparse::disable(&fixture.repo).unwrap();
+    assert_eq!(applied.restored, 1);
+    assert_eq!(fs::read_to_string(fixture.path().join("docs/guide.md")).unwrap(), "g\n");
+    assert_eq!(sparse::SparseCheckout::load(&fixture.repo), None);
+}
+
+#[test]
+fn ignore_rules_explain_why_a_path_is_ignored() {
+    let fixture = TestRepo::new("ignore-rules");
+    fixture.write(".gitignore", "# Build output\n*.o\n/build/\n")
+        .write("src/.gitignore", "!keep.o\n")
+        .write("build/out/a.txt", "a\n")
+        .write(".git/info/exclude", "secret.txt\n");
+
+    let found = ignore_rules::check(&fixture.repo, Path::new("src/a.o")).unwrap();
+    assert_eq!((found.source, found.line, found.ignored), (PathBuf::from(".gitignore"), 2, true));
+    let found = ignore_rules::check(&fixture.repo, Path::new("src/keep.o")).unwrap();
+    assert_eq!((found.source, found.pattern.as_str(), found.ignored), (PathBuf::from("src/.gitignore"), "!keep.o", false));
+    let found = ignore_rules::check(&fixture.repo, Path::new("build/out/a.txt")).unwrap();
+    assert_eq!((found.matched, found.line), (PathBuf::from("build"), 3));
+    let found = ignore_rules::check(&fixture.repo, Path::new("docs/secret.txt")).unwrap();
+    assert_eq!(found.source, PathBuf::from(".git/info/exclude"));
+    assert_eq!(ignore_rules::check(&fixture.repo, Path::new("src/main.rs")), None);
+}
+
+#[test]
+fn owners_cover_staged_and_outgoing_files() {
+    let fixture = TestRepo::new("owners");
+    fixture.write(".github/CODEOWNERS", "* @everyone\n/src/ @dev\n");
+    fixture.commit("Initial commit");
+    let first = fixture.commit_file("src/a.rs", "a\n", "Add a");
+    let second = fixture.commit_file("README", "r\n", "Add readme");
+    fixture.write("src/b.rs", "b\n");
+    untracked::stage(&fixture.repo, &[PathBuf::from("src/b.rs")]).unwrap();
+
+    let owners = CodeOwners::load(fixture.path()).unwrap();
+    let staged = commit::staged_files(&fixture.repo).unwrap();
+    assert_eq!(owners.summarise(&staged).by_owner.keys().collect::<Vec<_>>(), vec!["@dev"]);
+
+    // Nothing has been pushed, so every commit is outgoing.
+    let outgoing = remotes::outgoing_commits(&fixture.repo, "master", &UpstreamSpec::new("origin", "master")).unwrap();
+    assert_eq!(outgoing.len(), 3);
+    assert!(outgoing.contains(&first) && outgoing.contains(&second));
+    let files = remotes::files_changed_by(&fixture.repo, &[first, second]).unwrap();
+    let summary = owners.summarise(&files);
+    assert_eq!(summary.by_owner["@dev"], vec![PathBuf::from("src/a.rs")]);
+    assert_eq!(summary.by_owner["@everyone"], vec![PathBuf::from("README")]);
+}
+
+#[test]
+fn push_review_summarises_outgoing_commits_and_warns() {
+    let fixture = TestRepo::new("push-review");
+    fixture.commit_file("a.txt", "1\n", "Initial commit");
+    let pushed = fixture.commit_file("a.txt", "1\n2\n", "Second");
+    fixture.repo.reference("refs/remotes/origin/main", pushed, false, "fake fetch").unwrap();
+    fixture.commit_file("a.txt", "1\n2\n3\n4\n", "Third");
+
+    let review = push_review::review(&fixture.repo, "master", &UpstreamSpec::new("origin", "main"), &["ma*".to_string()]).unwrap();
+    assert_eq!(review.commits.iter().map(|c| c.summary.as_str()).collect::<Vec<_>>(), vec!["Third"]);
+    assert_eq!((review.files_changed, review.insertions, review.deletions), (1, 2, 0));
+    assert_eq!(review.warnings, vec![Warning::Protected { pattern: "ma*".to_string() }]);
+
+    let review = push_review::review(&fixture.repo, "master", &UpstreamSpec::new("origin", "topic"), &[]).unwrap();
+    assert_eq!(review.warnings, vec![Warning::NewBranch]);
+
+    // A change undone by a later commit is not counted.
+    fixture.commit_file("a.txt", "1\n2\n3\n", "Fourth");
+    let review = push_review::review(&fixture.repo, "master", &UpstreamSpec::new("origin", "main"), &[]).unwrap();
+    assert_eq!(review.commits.len(), 2);
+    assert_eq!((review.files_changed, review.insertions, review.deletions), (1, 1, 0));
+
+    let theirs = fixture.repo.commit(None, &fixture.repo.signature().unwrap(), &fixture.repo.signature().unwrap(), "Theirs",
+                                     &fixture.repo.find_commit(pushed).unwrap().tree().unwrap(),
+                                     &[&fixture.repo.find_commit(pushed).unwrap()]).unwrap();
+    fixture.repo.reference("refs/remotes/origin/main", theirs, true, "fake fetch").unwrap();
+    let review = push_review::review(&fixture.repo, "master", &UpstreamSpec::new("origin", "main"), &[]).unwrap();
+    assert_eq!(review.warnings, vec![Warning::ForcePush { behind: 1 }]);
+    assert!(review.description().contains("This is a force push"));
+}
+
+#[test]
+fn incoming_commits_are_those_on_the_fetched_upstream_only() {
+    let fixture = TestRepo::new("incoming");
+    let base = fixture.commit_file("a.txt", "1\n", "Initial commit");
+    let upstream = UpstreamSpec::new("origin", "master");
+    assert!(remotes::incoming_commits(&fixture.repo, "master", &upstream).unwrap().is_empty());
+
+    let theirs = fixture.commit_file("b.txt", "b\n", "Theirs");
+    fixture.repo.reference("refs/remotes/origin/master", theirs, false, "fake fetch").unwrap();
+    fixture.repo.reference("refs/heads/master", base, true, "rewind").unwrap();
+    let ours = fixture.commit_file("c.txt", "c\n", "Ours");
+
+    assert_eq!(remotes::incoming_commits(&fixture.repo, "master", &upstream).unwrap(), vec![theirs]);
+    assert_eq!(remotes::outgoing_commits(&fixture.repo, "master", &upstream).unwrap(), vec![ours]);
+}
+
+#[test]
+fn log_shows_commits_newest_first() {
+    let fixture = TestRepo::new("log");
+    let first = fixture.commit_file("a.txt", "1\n", "First");
+    let second = fixture.commit_file("a.txt", "2\n", "Second");
+
+    let entries = history::load(&fixture.repo, None, &LogFilter::default(), LogSort::Time, 10, &Mailmap::default()).unwrap();
+    let summaries: Vec<_> = entries.iter().map(|e| (e.oid, e.summary.as_str())).collect();
+    assert_eq!(summaries, vec![(second, "Second"), (first, "First")]);
+}
+
+#[test]
+fn the_log_and_activity_map_authors_with_the_mailmap() {
+    let fixture = TestRepo::new("log-mailmap");
+    fixture.commit_file(".mailmap", "Proper Name <proper@example.com> <test@example.com>\n", "Add a mailmap");
+    let mailmap = Mailmap::load(&fixture.repo);
+
+    let entries = history::load(&fixture.repo, None, &LogFilter::default(), LogSort::Time, 10, &mailmap).unwrap();
+    assert_eq!(entries[0].author, "Proper Name");
+    let raw = history::load(&fixture.repo, None, &LogFilter::default(), LogSort::Time, 10, &Mailmap::default()).unwrap();
+    assert_eq!(raw[0].author, "Test User");
+
+    let by = |author: &str| LogFilter::parse(author, "", "", "").unwrap();
+    assert_eq!(history::load(&fixture.repo, None, &by("proper@"), LogSort::Time, 10, &mailmap).unwrap().len(), 1);
+    assert!(history::load(&fixture.repo, None, &by("test user"), LogSort::Time, 10, &mailmap).unwrap().is_empty());
+
+    let since = ::chrono::NaiveDate::from_ymd(1970, 1, 1);
+    let activity = activity::gather(&fixture.repo, ActivityScope::CurrentBranch, since, &mailmap, &Cancel::new()).unwrap();
+    assert_eq!(activity.authors.keys().collect::<Vec<_>>(), vec!["Proper Name <proper@example.com>"]);
+}
+
+#[test]
+fn reword_replays_later_commits_keeping_their_committer_dates() {
+    let fixture = TestRepo::new("reword");
+    fixture.commit_file("a.txt", "1\n", "Initial commit");
+    let target = fixture.commit_file("a.txt", "2\n", "Secnod");
+    let later = fixture.commit_file("b.txt", "b\n", "Later");
+    let original_later = fixture.repo.find_commit(later).unwrap();
+
+    let mut graph = CommitGraph::new(&fixture.repo);
+    let reworded = rewrite::reword(&fixture.repo, &mut graph, target, "Second\n").unwrap();
+    assert_eq!(fixture.repo.find_commit(reworded).unwrap().message(), Some("Second\n"));
+
+    let head = fixture.repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.parent_id(0).unwrap(), reworded);
+    assert_eq!(head.message(), Some("Later"));
+    assert_eq!(head.tree_id(), original_later.tree_id());
+    assert_eq!(head.committer().when(), original_later.committer().when());
+    assert_eq!(head.author().when(), original_later.author().when());
+    assert_eq!(fixture.repo.head().unwrap().name(), Some("refs/heads/master"));
+}
+
+#[test]
+fn reword_refuses_pushed_commits() {
+    let fixture = TestRepo::new("reword-pushed");
+    fixture.commit_file("a.txt", "1\n", "Initial commit");
+    let pushed = fixture.commit_file("a.txt", "2\n", "Pushed");
+    fixture.repo.reference("refs/remotes/origin/master", pushed, false, "fake fetch").unwrap();
+    let tip = fixture.commit_file("b.txt", "b\n", "Not pushed");
+
+    let mut graph = CommitGraph::new(&fixture.repo);
+    assert!(rewrite::reword(&fixture.repo, &mut graph, pushed, "Reworded").is_err());
+    assert_eq!(fixture.repo.refname_to_id("refs/heads/master").unwrap(), tip);
+}
+
+#[test]
+fn split_divides_a_commit_by_file_and_keeps_the_final_tree() {
+    let fixture = TestRepo::new("split");
+    fixture.commit_file("a.txt", "1\n", "Initial commit");
+    fixture.write("a.txt", "2\n").write("b.txt", "b\n").write("c.txt", "c\n");
+    let target = fixture.commit("Everything");
+    let later = fixture.commit_file("d.txt", "d\n", "Later");
+    let original_target = fixture.repo.find_commit(target).unwrap();
+    let original_later = fixture.repo.find_commit(later).unwrap();
+
+    let mut graph = CommitGraph::new(&fixture.repo);
+    let parts = [SplitPart { paths: vec![PathBuf::from("b.txt")], message: "Add b".to_string() }];
+    let commits = rewrite::split(&fixture.repo, &mut graph, target, &parts).unwrap();
+    assert_eq!(commits.len(), 2);
+
+    let first = fixture.repo.find_commit(commits[0]).unwrap();
+    assert_eq!(first.parent_id(0).unwrap(), original_target.parent_id(0).unwrap());
+    assert_eq!(first.message(), Some("Add b\n"));
+    assert_eq!(rewrite::changed_paths(&fixture.repo, commits[0]).unwrap(), vec![PathBuf::from("b.txt")]);
+
+    let second = fixture.repo.find_commit(commits[1]).unwrap();
+    assert_eq!(second.parent_id(0).unwrap(), commits[0]);
+    assert_eq!(second.message(), Some("Everything\n"));
+    assert_eq!(rewrite::changed_paths(&fixture.repo, commits[1]).unwrap(),
+               vec![PathBuf::from("a.txt"), PathBuf::from("c.txt")]);
+    assert_eq!(second.tree_id(), original_target.tree_id());
+
+    let head = fixture.repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.parent_id(0).unwrap(), commits[1]);
+    assert_eq!(head.tree_id(), original_later.tree_id());
+    assert_eq!(head.committer().when(), original_later.committer().when());
+}
+
+#[test]
+fn split_refuses_pushed_commits() {
+    let fixture = TestRepo::new("split-pushed");
+    fixture.commit_file("a.txt", "1\n", "Initial commit");
+    fixture.write("a.txt", "2\n").write("b.txt", "b\n");
+    let pushed = fixture.commit("Pushed");
+    fixture.repo.reference("refs/remotes/origin/master", pushed, false, "fake fetch").unwrap();
+    let tip = fixture.commit_file("c.txt", "c\n", "Not pushed");
+
+    let mut graph = CommitGraph::new(&fixture.repo);
+    let parts = [SplitPart { paths: vec![PathBuf::from("a.txt")], message: "Just a".to_string() }];
+    assert!(rewrite::split(&fixture.repo, &mut graph, pushed, &parts).is_err());
+    assert_eq!(fixture.repo.refname_to_id("refs/heads/master").unwrap(), tip);
+}
+
+#[test]
+fn squash_onto_a_moved_base_merges_the_changes() {
+    let fixture = TestRepo::new("squash");
+    fixture.commit_file("base.txt", "base\n", "Base");
+    fixture.branch("feature");
+    let master = fixture.commit_file("master.txt", "master\n", "On master");
+    fixture.checkout("feature");
+    fixture.commit_file("feature.txt", "1\n", "Feature 1");
+    fixture.commit_file("feature.txt", "2\n", "Feature 2");
+
+    let squashed = rewrite::squash_branch(&fixture.repo, master, "Feature").unwrap();
+    let commit = fixture.repo.find_commit(squashed.commit).unwrap();
+    assert_eq!(commit.parent_id(0).unwrap(), master);
+    assert!(commit.tree().unwrap().get_name("master.txt").is_some());
+    assert_eq!(fs::read_to_string(fixture.path().join("feature.txt")).unwrap(), "2\n");
+    assert_eq!(fs::read_to_string(fixture.path().join("master.txt")).unwrap(), "master\n");
+    assert_eq!(fixture.repo.head().unwrap().name(), Some("refs/heads/feature"));
+}
+
+#[test]
+fn squash_leaves_the_branch_alone_if_the_checkout_would_lose_local_changes() {
+    let fixture = TestRepo::new("squash-local-changes");
+    fixture.commit_file("base.txt", "base\n", "Base");
+    fixture.branch("feature");
+    let master = fixture.commit_file("master.txt", "master\n", "On master");
+    fixture.checkout("feature");
+    let tip = fixture.commit_file("feature.txt", "1\n", "Feature 1");
+    fixture.write("master.txt", "mine\n");
+
+    assert!(rewrite::squash_branch(&fixture.repo, master, "Feature").is_err());
+    assert_eq!(fixture.repo.head().unwrap().name(), Some("refs/heads/feature"));
+    assert_eq!(fixture.repo.refname_to_id("refs/heads/feature").unwrap(), tip);
+    assert_eq!(fs::read_to_string(fixture.path().join("master.txt")).unwrap(), "mine\n");
+}
+
+#[test]
+fn squash_fails_with_a_merge_error_on_conflict() {
+    let fixture = TestRepo::new("conflict");
+    let (master, _) = fixture.conflict("feature", "shared.txt");
+
+    match rewrite::squash_branch(&fixture.repo, master, "Feature") {
+        Err(OafError::Merge(_)) => {},
+        other => panic!("Expected a merge error, got {:?}", other.map(|s| s.commit)),
+    }
+}
+
+#[test]
+fn a_patch_stack_can_be_reordered_refreshed_and_exported() {
+    let fixture = TestRepo::new("patch-stack");
+    let base = fixture.commit_file("base.txt", "base\n", "Base");
+    fixture.branch("local");
+    let upstream = fixture.commit_file("upstream.txt", "upstream\n", "Upstream");
+    fixture.checkout("local");
+    let first = fixture.commit_file("a.txt", "a\n", "Add a");
+    let second = fixture.commit_file("b.txt", "b\n", "Add b");
+    let summaries = |stack: &Stack| -> Vec<String> {
+        stack.patches.iter().map(|&p| fixture.repo.find_commit(p).unwrap().summary().unwrap().to_string()).collect()
+    };
+
+    let stack = patch_stack::create(&fixture.repo, upstream).unwrap();
+    assert_eq!((stack.base, stack.patches.clone()), (base, vec![first, second]));
+    assert_eq!(patch_stack::load(&fixture.repo, "local").unwrap(), Some(stack));
+
+    let reordered = patch_stack::reorder(&fixture.repo, &[1, 0]).unwrap();
+    assert_eq!(summaries(&reordered.stack), vec!["Add b", "Add a"]);
+    assert_eq!(fixture.repo.head().unwrap().target(), Some(reordered.stack.top()));
+    assert_eq!(patch_stack::load(&fixture.repo, "local").unwrap(), Some(reordered.stack.clone()));
+
+    let refreshed = patch_stack::refresh(&fixture.repo, upstream).unwrap();
+    assert_eq!(summaries(&refreshed.stack), vec!["Add b", "Add a"]);
+    assert_eq!(fixture.repo.find_commit(refreshed.stack.patches[0]).unwrap().parent_id(0).unwrap(), upstream);
+    assert!(refreshed.dropped.is_empty());
+    assert!(fixture.path().join("upstream.txt").exists());
+
+    let dir = fixture.path().join("patches");
+    let written = patch_stack::export(&fixture.repo, &refreshed.stack, &dir).unwrap();
+    assert_eq!(written.len(), 3);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+    assert_eq!(fs::read_to_string(dir.join("series")).unwrap(), "0001-Add-b.patch\n0002-Add-a.patch\n");
+    let text = fs::read_to_string(&written[0]).unwrap();
+    assert!(text.contains("Subject: [PATCH 1/2] Add b\n"));
+    assert!(text.contains("\n+b\n"));
+}
+
+#[test]
+fn a_conflicted_merge_is_detected_and_can_be_aborted_or_continued() {
+    let fixture = TestRepo::new("in-progress");
+    fixture.commit_file("unrelated.txt", "committed\n", "Add unrelated.txt");
+    let (master, tip) = fixture.conflict("feature", "shared.txt");
+    let merge = || {
+        let theirs = fixture.repo.find_annotated_commit(master).unwrap();
+        fixture.repo.merge(&[&theirs], None, None).unwrap();
+    };
+
+    fixture.write("unrelated.txt", "local change\n");
+    merge();
+    let state = in_progress::detect(&fixture.repo).unwrap().unwrap();
+    assert_eq!((state.operation, state.conflicts), (Operation::Merge, 1));
+    match in_progress::continue_operation(&fixture.repo) {
+        Err(OafError::Merge(_)) => {},
+        other => panic!("Expected a merge error, got {:?}", other),
+    }
+
+    in_progress::abort(&fixture.repo).unwrap();
+    assert_eq!(in_progress::detect(&fixture.repo).unwrap(), None);
+    assert_eq!(fixture.repo.head().unwrap().target(), Some(tip));
+    assert_eq!(fs::read_to_string(fixture.path().join("shared.txt")).unwrap(), "feature\n");
+    assert_eq!(fs::read_to_string(fixture.path().join("unrelated.txt")).unwrap(), "local change\n");
+    fixture.write("unrelated.txt", "committed\n");
+
+    merge();
+    fixture.write("shared.txt", "both\n");
+    let mut index = fixture.repo.index().unwrap();
+    index.add_path(Path::new("shared.txt")).unwrap();
+    index.write().unwrap();
+    let merged = fixture.repo.find_commit(in_progress::continue_operation(&fixture.repo).unwrap()).unwrap();
+    assert_eq!(merged.parent_ids().collect::<Vec<_>>(), vec![tip, master]);
+    assert_eq!(in_progress::detect(&fixture.repo).unwrap(), None);
+}
+
+#[test]
+fn aborting_an_autostashed_rebase_puts_the_stashed_changes_back() {
+    let fixture = TestRepo::new("autostash");
+    fixture.commit_file("unrelated.txt", "committed\n", "Add unrelated.txt");
+    let (_, tip) = fixture.conflict("feature", "shared.txt");
+    fixture.write("unrelated.txt", "local change\n");
+
+    assert!(!fixture.git(&["rebase", "--autostash", "master"]));
+    let state = in_progress::detect(&fixture.repo).unwrap().unwrap();
+    assert_eq!(state.operation, Operation::Rebase);
+
+    assert_eq!(in_progress::abort(&fixture.repo).unwrap(), in_progress::Autostash::Applied);
+    assert_eq!(in_progress::detect(&fixture.repo).unwrap(), None);
+    assert_eq!(fixture.repo.head().unwrap().target(), Some(tip));
+    assert_eq!(fs::read_to_string(fixture.path().join("shared.txt")).unwrap(), "feature\n");
+    assert_eq!(fs::read_to_string(fixture.path().join("unrelated.txt")).unwrap(), "local change\n");
+}
+
+#[test]
+fn ssh_signatures_are_verified_against_the_allowed_signers_file() {
+    let fixture = TestRepo::new("ssh-signature");
+    let key = fixture.path().join(".git").join("oaf-test-key");
+    assert!(Command::new("ssh-keygen").args(&["-q", "-t", "ed25519", "-N", "", "-C", "test", "-f"]).arg(&key).status().unwrap().success());
+    fixture.repo.config().unwrap().set_str("gpg.format", "ssh").unwrap();
+    fixture.repo.config().unwrap().set_str("user.signingkey", &key.to_string_lossy()).unwrap();
+    fixture.write("a.txt", "a\n");
+    assert!(fixture.git(&["add", "a.txt"]));
+    assert!(fixture.git(&["commit", "-q", "-S", "-m", "Signed"]));
+    let head = fixture.repo.head().unwrap().target().unwrap();
+
+    match signatures::verify_commit(&fixture.repo, head) {
+        SignatureStatus::Unverified { .. } => {},
+        other => panic!("Expected an unverified signature, got {:?}", other),
+    }
+
+    let public = fs::read_to_string(key.with_extension("pub")).unwrap();
+    let allowed = fixture.path().join(".git").join("oaf-test-allowed-signers");
+    fs::write(&allowed, format!("test@example.com {}\n", public.split_whitespace().take(2).collect::<Vec<_>>().join(" "))).unwrap();
+    fixture.repo.config().unwrap().set_str("gpg.ssh.allowedSignersFile", &allowed.to_string_lossy()).unwrap();
+    assert_eq!(signatures::verify_commit(&fixture.repo, head), SignatureStatus::Good { signer: "test@example.com".to_string() });
+}
+
+#[test]
+fn discard_restores_the_file_and_keeps_a_copy() {
+    let fixture = TestRepo::new("discard");
+    fixture.commit_file("a.txt", "committed\n", "Initial commit");
+    fixture.write("a.txt", "changed\n");
+    let trash_dir = fixture.path().join(".git").join("oaf-test-trash");
+
+    discard::discard_changes(&fixture.repo, &trash_dir, Path::new("a.txt")).unwrap();
+    assert_eq!(fs::read_to_string(fixture.path().join("a.txt")).unwrap(), "committed\n");
+    assert_eq!(::trash::list(&trash_dir).unwrap().len(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn discard_keeps_a_copy_of_a_changed_symlink() {
+    use std::os::unix::fs::symlink;
+    let fixture = TestRepo::new("discard-symlink");
+    fixture.write("a.txt", "a\n").write("b.txt", "b\n");
+    symlink("a.txt", fixture.path().join("link")).unwrap();
+    fixture.commit("Initial commit");
+    fs::remove_file(fixture.path().join("link")).unwrap();
+    symlink("b.txt", fixture.path().join("link")).unwrap();
+    let trash_dir = fixture.path().join(".git").join("oaf-test-trash");
+
+    discard::discard_changes(&fixture.repo, &trash_dir, Path::new("link")).unwrap();
+    assert_eq!(fs::read_link(fixture.path().join("link")).unwrap(), PathBuf::from("a.txt"));
+    let trashed = ::trash::list(&trash_dir).unwrap();
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(fs::read_link(trash_dir.join(&trashed[0].id).join("content")).unwrap(), PathBuf::from("b.txt"));
+}
+
+#[test]
+fn submodules_are_recorded() {
+    let library = TestRepo::new("library");
+    let library_head = library.commit_file("lib.rs", "pub fn f() {}\n", "Library");
+    let fixture = TestRepo::new("superproject");
+    fixture.commit_file("README", "readme\n", "Initial commit");
+
+    fixture.add_submodule(&library, "vendor/library");
+    let submodules = fixture.repo.submodules().unwrap();
+    assert_eq!(submodules.len(), 1);
+    assert_eq!(submodules[0].path(), Path::new("vendor/library"));
+    assert_eq!(submodules[0].head_id(), Some(library_head));
+    assert!(status::changed_files(&fixture.repo, true).unwrap().is_empty());
+}
+
+#[test]
+fn diff_limits_skip_big_files_and_cut_off_long_diffs() {
+    let fixture = TestRepo::new("diff-limits");
+    fixture.commit_file("small.txt", "1\n", "Initial commit");
+    let long: String = (0..100).map(|n| format!("line {}\n", n)).collect();
+    let oid = fixture.commit_file("big.txt", &long, "Add a big file");
+
+    let limited = patch::commit_patch(&fixture.repo, oid, DiffLimits { max_file_bytes: Some(100), ..DiffLimits::unlimited() }).unwrap();
+    assert_eq!(limited.skipped, vec![(PathBuf::from("big.txt"), long.len() as u64)]);
+
+    let truncated = patch::commit_patch(&fixture.repo, oid, DiffLimits { max_lines: Some(10), ..DiffLimits::unlimited() }).unwrap();
+    assert!(truncated.truncated);
+    assert_eq!(truncated.lines.len(), 10);
+
+    let full = patch::commit_patch(&fixture.repo, oid, DiffLimits::unlimited()).unwrap();
+    assert!(!full.truncated && full.skipped.is_empty());
+    assert!(full.lines.len() > 100);
+}
+
+#[test]
+fn patch_stream_yields_a_file_header_then_each_hunk() {
+    let fixture = TestRepo::new("patch-stream");
+    let lines: String = (0..40).map(|n| format!("{}\n", n)).collect();
+    fixture.commit_file("a.txt", &lines, "Initial commit");
+    let changed = lines.replacen("1\n", "one\n", 1).replacen("30\n", "thirty\n", 1);
+    let oid = fixture.commit_file("a.txt", &changed, "Change two far apart lines");
+
+    let diff = patch::commit_diff(&fixture.repo, oid, DiffLimits::unlimited()).unwrap();
+    let chunks: Vec<Vec<PatchLine>> = PatchStream::new(&diff, DiffLimits::unlimited()).map(|c| c.unwrap()).collect();
+    assert_eq!(chunks.len(), 3);
+    assert!(chunks[0].iter().all(|l| match *l { PatchLine::File(_) => true, _ => false }));
+    assert!(chunks[1][0].text().starts_with("@@ -1,"));
+    assert!(chunks[2].contains(&PatchLine::Added("+thirty".to_string())));
+}
+
+#[test]
+fn patch_stream_reads_the_new_version_of_each_file() {
+    let fixture = TestRepo::new("patch-stream-new-lines");
+    let lines: String = (0..40).map(|n| format!("{}\n", n)).collect();
+    fixture.commit_file("a.txt", &lines, "Initial commit");
+    let changed = lines.replacen("1\n", "one\n", 1);
+    let oid = fixture.commit_file("a.txt", &changed, "Change a line");
+    fixture.write("a.txt", &format!("{}40\n", changed));
+
+    let new_lines = |diff: &::git2::Diff| {
+        let mut stream = PatchStream::new(diff, DiffLimits::unlimited());
+        stream.next().unwrap().unwrap();
+        stream.current_new_lines(&fixture.repo)
+    };
+
+    let committed = new_lines(&patch::commit_diff(&fixture.repo, oid, DiffLimits::unlimited()).unwrap());
+    assert_eq!(committed.len(), 40);
+    assert_eq!(committed[1], "one");
+
+    let uncommitted = new_lines(&patch::working_tree_diff(&fixture.repo, Path::new("a.txt"), DiffLimits::unlimited()).unwrap());
+    assert_eq!(uncommitted.len(), 41);
+    assert_eq!(uncommitted[40], "40");
+}
+
+#[test]
+fn three_dot_comparisons_leave_out_changes_made_on_the_other_side() {
+    let fixture = TestRepo::new("compare");
+    fixture.commit_file("base.txt", "base\n", "Base");
+    fixture.branch("feature");
+    let master = fixture.commit_file("master.txt", "master\n", "On master");
+    fixture.checkout("feature");
+    let feature = fixture.commit_file("feature.txt", "feature\n", "On feature");
+
+    let lines = |from, to| {
+        let diff = patch::compare_diff(&fixture.repo, from, to, DiffLimits::unlimited()).unwrap();
+        PatchStream::new(&diff, DiffLimits::unlimited()).collect_patch().unwrap().lines
+    };
+
+    let two_dot = lines(master, feature);
+    assert!(two_dot.contains(&PatchLine::Added("+feature".to_string())));
+    assert!(two_dot.contains(&PatchLine::Removed("-master".to_string())));
+
+    let base = fixture.repo.merge_base(master, feature).unwrap();
+    let three_dot = lines(base, feature);
+    assert!(three_dot.contains(&PatchLine::Added("+feature".to_string())));
+    assert!(!three_dot.contains(&PatchLine::Removed("-master".to_string())));
+}
+
+#[test]
+fn diffstat_totals_uncommitted_changes_by_directory() {
+    let fixture = TestRepo::new("diffstat");
+    fixture.write("README.md", "readme\n").write("src/lib.rs", "a\nb\nc\n").write("src/tui/view.rs", "view\n");
+    fixture.commit("Initial commit");
+    fixture.write("src/lib.rs", "a\nB\nc\nd\n").write("src/tui/new.rs", "1\n2\n");
+    fs::remove_file(fixture.path().join("src/tui/view.rs")).unwrap();
+
+    let diff = patch::uncommitted_diff(&fixture.repo, DiffLimits::unlimited()).unwrap();
+    let root = diffstat::tree(&diff).unwrap();
+    assert_eq!(root.totals, Totals { files: 3, added: 4, removed: 2 });
+    assert!(root.files.is_empty());
+    let tui = &root.dirs[0].dirs[0];
+    assert_eq!(tui.path, Path::new("src/tui"));
+    assert_eq!(tui.totals, Totals { files: 2, added: 2, removed: 1 });
+}
+
+#[test]
+fn hunks_can_be_staged_and_unstaged_one_at_a_time() {
+    let fixture = TestRepo::new("stage-hunks");
+    let lines: String = (0..40).map(|n| format!("{}\n", n)).collect();
+    fixture.commit_file("a.txt", &lines, "Initial commit");
+    let changed = lines.replacen("1\n", "one\n", 1).replacen("30\n", "thirty\n", 1);
+    fixture.write("a.txt", &changed);
+    let path = Path::new("a.txt");
+    let in_index = || {
+        let entry = fixture.repo.index().unwrap().get_path(path, 0).unwrap();
+        String::from_utf8(fixture.repo.find_blob(entry.id).unwrap().content().to_vec()).unwrap()
+    };
+
+    staging::stage_hunk(&fixture.repo, path, 1, patch::DEFAULT_CONTEXT_LINES).unwrap();
+    assert_eq!(in_index(), lines.replacen("30\n", "thirty\n", 1));
+    let codes: Vec<String> = status::changed_files(&fixture.repo, true).unwrap().into_iter().map(|(_, s)| status::short_code(s)).collect();
+    assert_eq!(codes, vec!["MM".to_string()]);
+
+    staging::unstage_hunk(&fixture.repo, path, 0, patch::DEFAULT_CONTEXT_LINES).unwrap();
+    assert_eq!(in_index(), lines);
+    assert_eq!(fs::read_to_string(fixture.path().join("a.txt")).unwrap(), changed);
+}
+
+#[test]
+fn files_can_be_staged_and_unstaged_whole() {
+    let fixture = TestRepo::new("stage-files");
+    fixture.commit_file("a.txt", "a\n", "Initial commit");
+    fixture.write("new.txt", "new\n");
+    fs::remove_file(fixture.path().join("a.txt")).unwrap();
+    let codes = || -> Vec<String> {
+        status::changed_files(&fixture.repo, true).unwrap().into_iter().map(|(_, s)| status::short_code(s)).collect()
+    };
+
+    staging::stage_file(&fixture.repo, Path::new("new.txt")).unwrap();
+    staging::stage_file(&fixture.repo, Path::new("a.txt")).unwrap();
+    assert_eq!(codes(), vec!["D ".to_string(), "A ".to_string()]);
+
+    staging::unstage_file(&fixture.repo, Path::new("new.txt")).unwrap();
+    staging::unstage_file(&fixture.repo, Path::new("a.txt")).unwrap();
+    assert_eq!(codes(), vec![" D".to_string(), "??".to_string()]);
+}
+
+#[test]
+fn commit_all_tracked_leaves_untracked_files_out() {
+    let fixture = TestRepo::new("commit-all-tracked");
+    fixture.write("a.txt", "a\n").write("b.txt", "b\n");
+    fixture.commit("Initial commit");
+    fixture.write("a.txt", "a\nmore\n").write("new.txt", "new\n");
+    fs::remove_file(fixture.path().join("b.txt")).unwrap();
+
+    assert_eq!(commit::tracked_changes(&fixture.repo).unwrap(), vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")]);
+    assert!(commit::commit_all_tracked(&fixture.repo, "  \n").is_err());
+    assert!(commit::staged_files(&fixture.repo).unwrap().is_empty());
+
+    // Failing after the changes have been staged leaves nothing staged either.
+    let mut config = fixture.repo.config().unwrap();
+    config.set_bool("commit.gpgsign", true).unwrap();
+    config.set_str("gpg.program", "/nonexistent/gpg").unwrap();
+    assert!(commit::commit_all_tracked(&fixture.repo, "Signed commit").is_err());
+    assert!(commit::staged_files(&fixture.repo).unwrap().is_empty());
+    assert!(fixture.repo.index().unwrap().get_path(Path::new("b.txt"), 0).is_some());
+    config.set_bool("commit.gpgsign", false).unwrap();
+
+    commit::commit_all_tracked(&fixture.repo, "Quick commit").unwrap();
+    let codes: Vec<(PathBuf, String)> = status::changed_files(&fixture.repo, true).unwrap().into_iter()
+        .map(|(path, s)| (path, status::short_code(s)))
+        .collect();
+    assert_eq!(codes, vec![(PathBuf::from("new.txt"), "??".to_string())]);
+    let head = fixture.repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("Quick commit\n"));
+}
+
+#[test]
+fn signing_policy_applies_to_the_branch_being_committed_on() {
+    let fixture = TestRepo::new("signing-policy");
+    fixture.commit_file("a.txt", "a\n", "Initial commit");
+    let required = vec!["release/*".to_string()];
+    let check = || signing::check_policy(&fixture.repo, &SigningSetup::load(&fixture.repo).unwrap(), &required);
+
+    assert_eq!(check(), PolicyCheck::NotRequired);
+    fixture.branch("release/1.0").checkout("release/1.0");
+    match check() {
+        PolicyCheck::Unmet { ref branch, .. } => assert_eq!(branch, "release/1.0"),
+        other => panic!("Expected the policy to be unmet, got {:?}", other),
+    }
+
+    {
+        let mut config = fixture.repo.config().unwrap();
+        config.set_bool("commit.gpgsign", true).unwrap();
+        config.set_str("user.signingkey", "1234ABCD").unwrap();
+    }
+    assert_eq!(check(), PolicyCheck::Met { branch: "release/1.0".to_string() });
+    assert_eq!(SigningSetup::load(&fixture.repo).unwrap().key_description(), "key 1234ABCD (user.signingKey)");
+}
+
+#[test]
+fn identity_profiles_are_picked_by_remote_url_unless_one_is_chosen() {
+    let fixture = TestRepo::new("identity");
+    fixture.commit_file("a.txt", "a\n", "Initial commit");
+    fixture.repo.remote("origin", "https://github.com/acme/widgets.git").unwrap();
+    let profile = |name: &str, email: &str, patterns: &[&str]| IdentityProfile {
+        name: name.to_string(),
+        user_name: "Bob".to_string(),
+        email: email.to_string(),
+        signing_key: None,
+        remote_patterns: patterns.iter().map(|p| p.to_string()).collect(),
+    };
+    let profiles = vec![profile("personal", "bob@example.com", &["*github.com/bob/*"]),
+                        profile("work", "bob@acme.com", &["*github.com/acme/*"])];
+
+    assert_eq!(identity::select(&fixture.repo, &profiles), Selection::Matched(&profiles[1]));
+    identity::choose(&fixture.repo, Some("personal")).unwrap();
+    assert_eq!(identity::select(&fixture.repo, &profiles), Selection::Chosen(&profiles[0]));
+    assert_eq!(identity::description(&fixture.repo, &profiles), "Committing as Bob <bob@example.com> (personal). ");
+    identity::choose(&fixture.repo, None).unwrap();
+    assert_eq!(identity::select(&fixture.repo, &profiles[..1]), Selection::NoProfile);
+
+    // The fixture's own identity was set by hand, so it is kept.
+    assert_eq!(identity::apply(&fixture.repo, &profiles[1]).unwrap(),
+               Applied::KeptLocal("Test User <test@example.com>".to_string()));
+    assert_eq!(fixture.repo.config().unwrap().get_string("user.email").unwrap(), "test@example.com");
+    {
+        let mut config = fixture.repo.config().unwrap();
+        config.remove("user.name").unwrap();
+        config.remove("user.email").unwrap();
+    }
+
+    assert_eq!(identity::apply(&fixture.repo, &profiles[1]).unwrap(), Applied::Changed);
+    assert_eq!(identity::apply(&fixture.repo, &profiles[1]).unwrap(), Applied::Unchanged);
+    fixture.write("b.txt", "b\n").stage_all();
+    let oid = commit::commit_index(&fixture.repo, "As work").unwrap();
+    assert_eq!(fixture.repo.find_commit(oid).unwrap().author().email(), Some("bob@acme.com"));
+}
+
+#[test]
+fn backport_picks_onto_each_branch_and_leaves_conflicting_ones_alone() {
+    let fixture = TestRepo::new("backport");
+    fixture.write("a.txt", "1\n2\n3\n").write("b.txt", "b\n");
+    fixture.commit("Initial commit");
+    fixture.branch("release-1").branch("release-2");
+    let fix = fixture.commit_file("a.txt", "1\ntwo\n3\n", "Fix two");
+    let docs = fixture.commit_file("b.txt", "b\nmore\n", "Document b");
+    fixture.checkout("release-2");
+    let release_2 = fixture.commit_file("a.txt", "1\nII\n3\n", "Roman numerals");
+    fixture.checkout("master");
+
+    let commits = backport::resolve_commits(&fixture.repo, &format!("{}..master", fix)).unwrap();
+    assert_eq!(commits, vec![docs]);
+    let commits = backport::resolve_commits(&fixture.repo, &format!("{} {}", fix, docs)).unwrap();
+    let reports = backport::backport(&fixture.repo, &commits, &["release-1".to_string(), "release-2".to_string()],
+                                     &SigningConfig::default());
+
+    match reports[0].outcome {
+        Outcome::Picked { tip, picked, already_there, ref backup } => {
+            assert_eq!((picked, already_there), (2, 0));
+            assert!(backup.is_some());
+            let tip = fixture.repo.find_commit(tip).unwrap();
+            assert!(tip.message().unwrap().ends_with(&format!("(cherry picked from commit {})\n", docs)));
+            assert_eq!(fixture.repo.refname_to_id("refs/heads/release-1").unwrap(), tip.id());
+        },
+        ref other => panic!("Expected release-1 to be picked onto, got {:?}", other),
+    }
+    match reports[1].outcome {
+        Outcome::Conflict { commit, ref paths, .. } => {
+            assert_eq!(commit, fix);
+            assert_eq!(paths, &vec![PathBuf::from("a.txt")]);
+        },
+        ref other => panic!("Expected release-2 to conflict, got {:?}", other),
+    }
+    assert_eq!(fixture.repo.refname_to_id("refs/heads/release-2").unwrap(), release_2);
+
+    // Picking again finds the changes already there.
+    let reports = backport::backport(&fixture.repo, &commits, &["release-1".to_string()], &SigningConfig::default());
+    assert!(reports[0].to_string().contains("picked 0 commits"));
+}
+
+#[test]
+fn backport_applies_the_signing_policy_of_each_target() {
+    let fixture = TestRepo::new("backport-signing");
+    fixture.commit_file("a.txt", "a\n", "Initial commit");
+    fixture.branch("release-1").branch("release-2");
+    let fix = fixture.commit_file("a.txt", "fixed\n", "Fix a");
+    let release_1 = fixture.repo.refname_to_id("refs/heads/release-1").unwrap();
+    let targets = ["release-1".to_string(), "release-2".to_string()];
+    let mut policy = SigningConfig { required_branches: vec!["release-1".to_string()], enforcement: Enforcement::Refuse };
+
+    let reports = backport::backport(&fixture.repo, &[fix], &targets, &policy);
+    match reports[0].outcome {
+        Outcome::Failed(ref message) => assert!(message.starts_with("commits must be signed")),
+        ref other => panic!("Expected release-1 to be refused, got {:?}", other),
+    }
+    assert_eq!(fixture.repo.refname_to_id("refs/heads/release-1").unwrap(), release_1);
+    assert!(reports[1].succeeded());
+    assert_eq!(reports[1].unsigned_warning, None);
+
+    policy.enforcement = Enforcement::Warn;
+    let reports = backport::backport(&fixture.repo, &[fix], &targets[..1], &policy);
+    assert!(reports[0].succeeded());
+    assert!(reports[0].to_string().contains("commits should be signed"));
+}
+
+#[test]
+fn sandboxed_merges_and_rebases_leave_the_working_tree_alone() {
+    let fixture = TestRepo::new("sandbox");
+    fixture.write("a.txt", "1\n2\n3\n").write("b.txt", "b\n");
+    fixture.commit("Initial commit");
+    fixture.branch("feature");
+    fixture.commit_file("a.txt", "one\n2\n3\n", "Main change");
+    fixture.checkout("feature");
+    fixture.commit_file("b.txt", "bee\n", "Feature change");
+    fixture.checkout("master");
+    fixture.write("untracked.txt", "mine\n");
+
+    let outcome = sandbox::run(&fixture.repo, "feature", &SandboxOperation::Rebase("master".to_string())).unwrap();
+    let new_tip = match outcome {
+        SandboxOutcome::Done { new_tip, .. } => new_tip,
+        other => panic!("Expected the rebase to finish, got {:?}", other),
+    };
+    let rebased = fixture.repo.find_commit(new_tip).unwrap();
+    assert_eq!(rebased.parent_id(0).unwrap(), fixture.repo.refname_to_id("refs/heads/master").unwrap());
+    assert_eq!(fs::read_to_string(fixture.path().join("b.txt")).unwrap(), "b\n");
+    assert_eq!(fs::read_to_string(fixture.path().join("untracked.txt")).unwrap(), "mine\n");
+    assert_eq!(fixture.repo.head().unwrap().shorthand(), Some("master"));
+    assert_eq!(sandbox::run(&fixture.repo, "feature", &SandboxOperation::Merge("master".to_string())).unwrap(),
+               SandboxOutcome::Unchanged);
+
+    fixture.commit_file("b.txt", "B\n", "Conflicting change");
+    let master = fixture.repo.refname_to_id("refs/heads/master").unwrap();
+    match sandbox::run(&fixture.repo, "master", &SandboxOperation::Merge("feature".to_string())).unwrap() {
+        SandboxOutcome::Conflicts { paths, .. } => assert_eq!(paths, vec![PathBuf::from("b.txt")]),
+        other => panic!("Expected the merge to conflict, got {:?}", other),
+    }
+    assert_eq!(fixture.repo.refname_to_id("refs/heads/master").unwrap(), master);
+    assert_eq!(fs::read_to_string(fixture.path().join("b.txt")).unwrap(), "B\n");
+}
+
+#[test]
+fn maintenance_packs_loose_objects_and_records_the_run() {
+    let fixture = TestRepo::new("maintenance");
+    fixture.commit_file("a.txt", "a\n", "Initial commit");
+    fixture.commit_file("b.txt", "b\n", "Second commit");
+    fixture.branch("feature");
+    let before = maintenance::measure(fixture.repo.path());
+    assert!(before.loose_objects >= 6, "{:?}", before);
+    assert_eq!(before.packs, 0);
+    assert_eq!((before.loose_refs, before.packed_refs), (2, 0));
+    assert_eq!(maintenance::last_run(&fixture.repo), None);
+
+    let report = maintenance::run(fixture.repo.path(), &[Task::PackRefs, Task::Repack], &Cancel::new(), &|_| {}).unwrap();
+    assert_eq!(report.tasks, vec![(Task::Repack, Ok(())), (Task::PackRefs, Ok(()))]);
+    assert_eq!(report.before, before);
+    assert_eq!(report.after.loose_objects, 0);
+    assert_eq!(report.after.packs, 1);
+    assert_eq!((report.after.loose_refs, report.after.packed_refs), (0, 2));
+    assert!(maintenance::last_run(&fixture.repo).is_some());
+    assert_eq!(fixture.repo.find_branch("feature", BranchType::Local).unwrap().get().target(), fixture.repo.head().unwrap().target());
+}
+
+#[test]
+fn inspect_shows_commits_trees_and_blobs_raw() {
+    let fixture = TestRepo::new("inspect");
+    fixture.write("bin.dat", "PNG\0data").write("src/a.txt", "hello\n");
+    let commit = fixture.commit("Initial commit");
+
+    let inspection = inspect::inspect(&fixture.repo, &commit.to_string()[..7], None).unwrap();
+    assert_eq!(inspection.kind, ObjectType::Commit);
+    assert!(inspection.content.starts_with("tree "), "{}", inspection.content);
+    assert!(inspection.content.contains("\nauthor Test User <test@example.com> "), "{}", inspection.content);
+    assert!(inspection.content.ends_with("\n\nInitial commit"), "{}", inspection.content);
+
+    let tree = inspect::inspect(&fixture.repo, "HEAD^{tree}", None).unwrap();
+    let lines: Vec<&str> = tree.content.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("100644 blob ") && lines[0].ends_with("\tbin.dat"), "{}", lines[0]);
+    assert!(lines[1].starts_with("040000 tree ") && lines[1].ends_with("\tsrc"), "{}", lines[1]);
+
+    let text = inspect::inspect(&fixture.repo, "HEAD:src/a.txt", None).unwrap();
+    assert_eq!((text.kind, text.size, text.content.as_str()), (ObjectType::Blob, 6, "hello\n"));
+    assert_eq!(inspect::inspect(&fixture.repo, "HEAD:src/a.txt", Some(2)).unwrap().content, "he\n... and 4 more bytes\n");
+    assert!(inspect::inspect(&fixture.repo, "HEAD:bin.dat", None).unwrap().content.starts_with("00000000  50 4e 47 00"));
+    assert!(inspect::inspect(&fixture.repo, "no-such-thing", None).is_err());
+}
+
+#[test]
+fn ref_editor_lists_hidden_refs_and_backs_up_what_it_changes() {
+    let fixture = TestRepo::new("ref-editor");
+    let first = fixture.commit_file("a.txt", "a\n", "Initial commit");
+    let second = fixture.commit_file("a.txt", "b\n", "Second commit");
+    backups::create(&fixture.repo, &[("master", first)], "test").unwrap();
+
+    let names: Vec<String> = ref_editor::list(&fixture.repo).unwrap().into_iter().map(|r| r.name).collect();
+    assert_eq!(names[0], "HEAD");
+    assert!(names.iter().any(|n| n.starts_with("refs/oaf/backup/")), "{:?}", names);
+    assert!(ref_editor::create(&fixture.repo, "rescue", "HEAD").is_err());
+    assert_eq!(ref_editor::create(&fixture.repo, "refs/rescue/tree", "HEAD^{tree}").unwrap(),
+               fixture.repo.head().unwrap().peel_to_tree().unwrap().id());
+    assert!(ref_editor::create(&fixture.repo, "refs/rescue/tree", "HEAD").is_err());
+
+    let backup = ref_editor::point_at(&fixture.repo, "refs/heads/master", &first.to_string()).unwrap().unwrap();
+    assert_eq!(fixture.repo.refname_to_id("refs/heads/master").unwrap(), first);
+    assert_eq!(fixture.repo.refname_to_id(&format!("refs/oaf/backup/{}/master", backup)).unwrap(), second);
+
+    let backup = ref_editor::delete(&fixture.repo, "refs/rescue/tree").unwrap().unwrap();
+    assert!(fixture.repo.find_reference("refs/rescue/tree").is_err());
+    assert!(fixture.repo.find_reference(&format!("refs/oaf/backup/{}/rescue/tree", backup)).is_ok());
+    assert!(ref_editor::delete(&fixture.repo, "HEAD").is_err());
+
+    let backup_ref = format!("refs/oaf/backup/{}/rescue/tree", backup);
+    assert_eq!(ref_editor::no_backup(&fixture.repo, &backup_ref).unwrap(), Some(NoBackup::IsBackup));
+    assert_eq!(ref_editor::no_backup(&fixture.repo, "HEAD").unwrap(), Some(NoBackup::Symbolic));
+    assert_eq!(ref_editor::no_backup(&fixture.repo, "refs/heads/master").unwrap(), None);
+    assert_eq!(ref_editor::point_at(&fixture.repo, &backup_ref, &first.to_string()).unwrap(), None);
+}
+
+#[test]
+fn clones_are_set_up_from_the_new_repo_settings() {
+    let origin = TestRepo::new("setup-origin");
+    origin.commit_file("a.txt", "a\n", "Initial commit");
+    let hooks = TestRepo::new("setup-hooks");
+    hooks.write("pre-commit", "#!/bin/sh\nexit 0\n");
+
+    let target = origin.path().with_file_name(format!("{}-clone", origin.path().file_name().unwrap().to_string_lossy()));
+    let clone = remotes::clone_cancellable(&origin.path().to_string_lossy(), &target, &Cancel::new(), |_| {}).unwrap();
+    assert!(target.join("a.txt").is_file());
+
+    let mut config = NewRepoConfig::default();
+    config.git_config.insert("pull.rebase".to_string(), "true".to_string());
+    config.identity = Some("work".to_string());
+    config.hooks_dir = Some(hooks.path().to_path_buf());
+    let profiles = vec![IdentityProfile {
+        name: "work".to_string(),
+        user_name: "Bob".to_string(),
+        email: "bob@acme.com".to_string(),
+        signing_key: None,
+        remote_patterns: Vec::new(),
+    }];
+
+    let reports = repo_setup::run(&clone, &config, &profiles);
+    assert_eq!(reports.iter().map(|r| r.step).collect::<Vec<_>>(), vec![Step::GitConfig, Step::Identity, Step::Hooks]);
+    assert!(reports.iter().all(|r| r.result.is_ok()), "{:?}", reports);
+    let git_config = clone.config().unwrap();
+    assert_eq!(git_config.get_string("pull.rebase").unwrap(), "true");
+    assert_eq!(git_config.get_string("user.email").unwrap(), "bob@acme.com");
+    assert_eq!(identity::chosen(&clone), Some("work".to_string()));
+    assert!(clone.path().join("hooks/pre-commit").is_file());
+    assert!(!clone.path().join("hooks/.git").exists());
+
+    drop(clone);
+    fs::remove_dir_all(&target).unwrap();
+}
+
+#[test]
+fn stale_tracking_refs_are_found_with_ls_remote_and_pruned() {
+    let origin = TestRepo::new("prune-origin");
+    origin.commit_file("a.txt", "a\n", "Initial commit");
+    origin.branch("gone").branch("kept");
+    let target = origin.path().with_file_name(format!("{}-clone", origin.path().file_name().unwrap().to_string_lossy()));
+    let clone = remotes::clone_cancellable(&origin.path().to_string_lossy(), &target, &Cancel::new(), |_| {}).unwrap();
+    assert!(remotes::stale_tracking_refs(&clone, "origin").unwrap().is_empty());
+
+    origin.repo.find_branch("gone", BranchType::Local).unwrap().delete().unwrap();
+    let stale = remotes::stale_tracking_refs(&clone, "origin").unwrap();
+    assert_eq!(stale, vec!["refs/remotes/origin/gone".to_string()]);
+    remotes::prune_refs(&clone, &stale).unwrap();
+    assert_eq!(remotes::remote_tracking_branches(&clone).unwrap(), vec!["origin/kept".to_string(), "origin/master".to_string()]);
+
+    drop(clone);
+    fs::remove_dir_all(&target).unwrap();
+}
+
+#[test]
+fn default_branch_is_detected_and_followed_when_the_remote_changes_it() {
+    let origin = TestRepo::new("default-origin");
+    origin.commit_file("a.txt", "a\n", "Initial commit");
+    origin.branch("main");
+    let target = origin.path().with_file_name(format!("{}-clone", origin.path().file_name().unwrap().to_string_lossy()));
+    let clone = remotes::clone_cancellable(&origin.path().to_string_lossy(), &target, &Cancel::new(), |_| {}).unwrap();
+    assert_eq!(default_branch::main_remote(&clone), Some("origin".to_string()));
+    assert_eq!(default_branch::recorded(&clone, "origin"), Some("master".to_string()));
+    assert!(!default_branch::detect(&clone, "origin").unwrap().changed());
+
+    origin.checkout("main");
+    origin.commit_file("b.txt", "b\n", "On main");
+    remotes::fetch(&clone, "origin", TagFetch::Auto).unwrap();
+    let detected = default_branch::detect(&clone, "origin").unwrap();
+    assert_eq!((detected.recorded.as_ref().map(|r| r.as_str()), detected.actual.as_ref().map(|a| a.as_str())),
+               (Some("master"), Some("main")));
+    assert!(default_branch::update(&clone, "origin", "no-such-branch").is_err());
+    default_branch::update(&clone, "origin", "main").unwrap();
+    assert_eq!(default_branch::default_base(&clone), Some("origin/main".to_string()));
+    assert_eq!(default_branch::behind_default(&clone, &mut CommitGraph::new(&clone)), Some(("origin/main".to_string(), 1)));
+
+    drop(clone);
+    fs::remove_dir_all(&target).unwrap();
+}
+
+#[test]
+fn script_checks_out_a_branch_and_reports() {
+    let fixture = TestRepo::new("script");
+    fixture.commit_file("a.txt", "1\n", "First");
+    fixture.branch("feature");
+    fixture.write("b.txt", "new\n");
+
+    let script = format!("open {}\ncheckout feature\nreport\n", fixture.path().display());
+    let mut out = Vec::new();
+    script::run(&script, &mut State::default(), &mut out).unwrap();
+
+    assert_eq!(fixture.repo.head().unwrap().shorthand(), Some("feature"));
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("Checked out 'feature'"));
+    assert!(out.contains("on 'feature', which has no upstream"));
+    assert!(out.contains("0 staged, 0 unstaged, 1 untracked"));
+}
+
+#[test]
+fn release_suggests_the_next_version_and_tags_head() {
+    let fixture = TestRepo::new("release");
+    fixture.commit_file("a.txt", "a\n", "Initial commit");
+    release::create_tag(&fixture.repo, "v1.2.3", "First release\n", false).unwrap();
+    fixture.commit_file("a.txt", "b\n", "fix: Handle b");
+    fixture.commit_file("b.txt", "b\n", "feat(b): Add b");
+
+    let plan = release::plan(&fixture.repo, &ChangelogConfig::default()).unwrap();
+    assert_eq!(plan.previous.map(|p| p.tag), Some("v1.2.3".to_string()));
+    assert_eq!((plan.commits, plan.bump, plan.tag.as_str()), (2, Bump::Minor, "v1.3.0"));
+    assert!(plan.message.contains("### Features\n\n- b: Add b ("));
+
+    release::create_tag(&fixture.repo, &plan.tag, &plan.message, false).unwrap();
+    let plan = release::plan(&fixture.repo, &ChangelogConfig::default()).unwrap();
+    assert_eq!((plan.previous.map(|p| p.tag), plan.commits), (Some("v1.3.0".to_string()), 0));
+}
+
+#[test]
+fn fingerprint_changes_with_the_index_and_refs_but_not_the_working_tree() {
+    let fixture = TestRepo::new("fingerprint");
+    fixture.commit_file("a.txt", "a\n", "Initial commit");
+
+    let before = refresh::fingerprint(fixture.repo.path());
+    fixture.write("new.txt", "new\n");
+    assert_eq!(refresh::fingerprint(fixture.repo.path()), before);
+
+    fixture.branch("feature");
+    let branched = refresh::fingerprint(fixture.repo.path());
+    assert_ne!(branched, before);
+
+    let mut index = fixture.repo.index().unwrap();
+    index.add_path(Path::new("new.txt")).unwrap();
+    index.write().unwrap();
+    assert_ne!(refresh::fingerprint(fixture.repo.path()), branched);
+}
+
+#[test]
+fn cancelled_operations_stop_without_changing_the_repository() {
+    let upstream = TestRepo::new("cancel-upstream");
+    upstream.commit_file("a.txt", "a\n", "Initial commit");
+    let fixture = TestRepo::new("cancel");
+    fixture.commit_file("b.txt", "b\n", "Initial commit");
+    fixture.repo.remote("origin", &upstream.path().display().to_string()).unwrap();
+
+    let cancel = Cancel::new();
+    cancel.cancel();
+    match remotes::fetch_cancellable(&fixture.repo, "origin", TagFetch::Auto, &cancel, |_| {}) {
+        Err(OafError::Cancelled) => {},
+        other => panic!("Expected Cancelled, got {:?}", other),
+    }
+    assert!(remotes::tracking_tips(&fixture.repo, "origin").unwrap().is_empty());
+
+    let since = ::chrono::NaiveDate::from_ymd(1970, 1, 1);
+    match activity::gather(&fixture.repo, ActivityScope::CurrentBranch, since, &Mailmap::default(), &cancel) {
+        Err(OafError::Cancelled) => {},
+        other => panic!("Expected Cancelled, got {:?}", other),
+    }
+    assert_eq!(activity::gather(&fixture.repo, ActivityScope::CurrentBranch, since, &Mailmap::default(), &Cancel::new()).unwrap().days.len(), 1);
+}
+
+#[test]
+fn refspecs_are_replaced_and_reloaded() {
+    let fixture = TestRepo::new("refspecs");
+    fixture.commit_file("a.txt", "a\n", "Initial commit");
+    fixture.repo.remote("origin", "https://example.com/a.git").unwrap();
+    assert_eq!(refspecs::load(&fixture.repo, "origin", Direction::Fetch).unwrap(), vec!["+refs/heads/*:refs/remotes/origin/*"]);
+
+    let specs = refspecs::parse_lines("refs/heads/master:refs/remotes/origin/master\n+refs/tags/v*:refs/tags/v*\n", Direction::Fetch).unwrap();
+    refspecs::save(&fixture.repo, "origin", Direction::Fetch, &specs).unwrap();
+    assert_eq!(refspecs::load(&fixture.repo, "origin", Direction::Fetch).unwrap(),
+               vec!["refs/heads/master:refs/remotes/origin/master", "+refs/tags/v*:refs/tags/v*"]);
+    assert!(refspecs::load(&fixture.repo, "origin", Direction::Push).unwrap().is_empty());
+
+    let push = refspecs::parse_lines("refs/heads/master:refs/heads/main\n", Direction::Push).unwrap();
+    refspecs::save(&fixture.repo, "origin", Direction::Push, &push).unwrap();
+    assert_eq!(refspecs::load(&fixture.repo, "origin", Direction::Push).unwrap(), vec!["refs/heads/master:refs/heads/main"]);
+    refspecs::save(&fixture.repo, "origin", Direction::Push, &[]).unwrap();
+    assert!(refspecs::load(&fixture.repo, "origin", Direction::Push).unwrap().is_empty());
+    refspecs::save(&fixture.repo, "origin", Direction::Push, &[]).unwrap();
+
+    // Nothing is written if any refspec is bad.
+    let bad = vec![specs[0].clone(), Refspec { force: false, src: "a..b".to_string(), dst: None }];
+    assert!(refspecs::save(&fixture.repo, "origin", Direction::Fetch, &bad).is_err());
+    assert_eq!(refspecs::load(&fixture.repo, "origin", Direction::Fetch).unwrap().len(), 2);
+}
+
+#[test]
+fn a_new_repository_is_scaffolded_and_committed() {
+    let fixture = TestRepo::new("scaffold");
+    let path = fixture.path().join("widget");
+    let readme = scaffold::templates(TemplateKind::Readme, None).remove(0);
+    let mit = scaffold::templates(TemplateKind::License, None).into_iter().find(|t| t.name == "MIT").unwrap();
+    let scaffold = Scaffold {
+        initial_branch: "main".to_string(),
+        files: vec![(TemplateKind::Readme, readme), (TemplateKind::License, mit)],
+        author: "Ann".to_string(),
+    };
+
+    let (repo, written) = scaffold::create(&path, &scaffold).unwrap();
+    assert_eq!(written, vec![PathBuf::from("README.md"), PathBuf::from("LICENSE")]);
+    assert_eq!(fs::read_to_string(path.join("README.md")).unwrap(), "# widget\n");
+    assert!(fs::read_to_string(path.join("LICENSE")).unwrap().contains(" Ann\n"));
+
+    let signature = Signature::now("Test User", "test@example.com").unwrap();
+    scaffold::initial_commit(&repo, &written, &signature).unwrap();
+    let head = repo.head().unwrap();
+    assert_eq!(head.name(), Some("refs/heads/main"));
+    assert_eq!(head.peel_to_commit().unwrap().tree().unwrap().len(), 2);
+
+    assert!(scaffold::create(&path, &scaffold).is_err());
+}
+
+#[test]
+fn templates_in_the_template_dir_replace_built_in_ones() {
+    let fixture = TestRepo::new("templates");
+    fixture.write("templates/gitignore/Rust", "/target\n/local\n");
+    fixture.write("templates/gitignore/Go", "/bin\n");
+
+    let templates = scaffold::templates(TemplateKind::Gitignore, Some(&fixture.path().join("templates")));
+    let rust = templates.iter().find(|t| t.name == "Rust").unwrap();
+    assert_eq!(rust.text, "/target\n/local\n");
+    assert_eq!(templates.iter().filter(|t| t.name == "Rust").count(), 1);
+    assert!(templates.iter().any(|t| t.name == "Go"));
+}
+
+#[test]
+fn a_gitignore_template_is_appended_once() {
+    let fixture = TestRepo::new("gitignore-template");
+    fixture.write(".gitignore", "*.log");
+    let rust = scaffold::templates(TemplateKind::Gitignore, None).into_iter().find(|t| t.name == "Rust").unwrap();
+
+    let appended = gitignore_catalogue::append(&fixture.repo, &rust).unwrap();
+    assert!(appended.skipped.is_empty());
+    assert_eq!(fs::read_to_string(fixture.path().join(".gitignore")).unwrap(), "*.log\n\n# Rust\n/target\n**/*.rs.bk\n");
+    assert!(fixture.repo.is_path_ignored(Path::new("target/debug")).unwrap());
+
+    let again = gitignore_catalogue::append(&fixture.repo, &rust).unwrap();
+    assert_eq!((again.text.as_str(), again.skipped.len()), ("", 2));
+}
+
+#[test]
+fn search_across_repositories_groups_hits_by_repository() {
+    let first = TestRepo::new("global-search-1");
+    let wanted = first.commit_file("notes.txt", "nothing here\nthe Frobnicator lives here\n", "Add the frobnicator");
+    let second = TestRepo::new("global-search-2");
+    second.commit_file("other.txt", "unrelated\n", "Something else");
+    let paths = vec![first.path().to_path_buf(), second.path().to_path_buf(), first.path().join("missing")];
+
+    let results = global_search::search(&paths, SearchMode::Messages, "FROBNICATOR", &Cancel::new(), |_| {}).unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].hits, vec![SearchHit::Commit { oid: wanted, summary: "Add the frobnicator".to_string() }]);
+    assert!(results[1].error.is_some());
+
+    let results = global_search::search(&paths[..2], SearchMode::Content, "frobnicator", &Cancel::new(), |_| {}).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].hits, vec![SearchHit::Line {
+        path: PathBuf::from("notes.txt"), line: 2, text: "the Frobnicator lives here".to_string() }]);
+}
+
+#[test]
+fn recently_viewed_commits_are_kept_between_sessions() {
+    let fixture = TestRepo::new("recent-commits");
+    let first = fixture.commit_file("a.txt", "a\n", "First");
+    let second = fixture.commit_file("b.txt", "b\n", "Second");
+    let file = fixture.path().join(".git").join("oaf-test-recent");
+
+    let mut recent = RecentCommits::load(&file, 100);
+    recent.record(fixture.repo.path(), first, "First", 100);
+    recent.record(fixture.repo.path(), second, "Second", 101);
+    recent.record(fixture.repo.path(), first, "First", 102);
+    recent.save().unwrap();
+
+    let mut recent = RecentCommits::load(&file, 200);
+    assert!(recent.this_session().is_empty());
+    let earlier: Vec<_> = recent.earlier().iter().map(|c| c.oid).collect();
+    assert_eq!(earlier, vec![first, second]);
+
+    recent.record(fixture.repo.path(), second, "Second", 201);
+    assert_eq!(recent.this_session().iter().map(|c| c.oid).collect::<Vec<_>>(), vec![second]);
+    assert_eq!(recent.earlier().iter().map(|c| c.oid).collect::<Vec<_>>(), vec![first]);
+}
+
+#[test]
+fn review_marks_are_kept_outside_the_repository() {
+    let fixture = TestRepo::new("review-marks");
+    let first = fixture.commit_file("a.txt", "a\n", "First");
+    let second = fixture.commit_file("b.txt", "b\n", "Second");
+    let file = fixture.path().join(".git").join("oaf-test-reviews.toml");
+
+    let mut marks = ReviewMarks::load(&file);
+    marks.set(fixture.repo.path(), second, "Second", ReviewState::NeedsWork, "Add a test.\n", 2).unwrap();
+    marks.set(fixture.repo.path(), first, "First", ReviewState::NeedsWork, "Explain why.", 1).unwrap();
+    marks.set(fixture.repo.path(), first, "First", ReviewState::Reviewed, "", 3).unwrap();
+
+    let mut marks = ReviewMarks::load(&file);
+    let states = marks.states(fixture.repo.path());
+    assert_eq!((states.len(), states[&first], states[&second]), (2, ReviewState::Reviewed, ReviewState::NeedsWork));
+    let pending = marks.pending(fixture.repo.path());
+    assert_eq!(review_marks::format_pending(&pending), format!("## {:.7} Second\n\nAdd a test.\n", second.to_string()));
+
+    assert!(marks.clear(fixture.repo.path(), second).unwrap());
+    assert!(!marks.clear(fixture.repo.path(), second).unwrap());
+    assert!(ReviewMarks::load(&file).pending(fixture.repo.path()).is_empty());
+
+    fs::write(&file, "[[mark]]\nrepo = ").unwrap();
+    let mut broken = ReviewMarks::load(&file);
+    assert!(broken.load_error().is_some());
+    assert!(broken.set(fixture.repo.path(), first, "First", ReviewState::Reviewed, "", 4).is_err());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "[[mark]]\nrepo = ");
+    assert_eq!(fs::read_to_string(file.with_extension("toml.broken")).unwrap(), "[[mark]]\nrepo = ");
+}
+
+#[test]
+fn a_file_can_be_seen_at_each_revision() {
+    let fixture = TestRepo::new("file-revisions");
+    let first = fixture.commit_file("notes.txt", "one\ntwo\n", "Add notes");
+    fixture.commit_file("other.txt", "x\n", "Unrelated");
+    let second = fixture.commit_file("notes.txt", "one\n2\n", "Change notes");
+    let head = fixture.commit_file("other.txt", "y\n", "Unrelated again");
+
+    let mut revisions = FileRevisions::load(&fixture.repo, Path::new("notes.txt"), head, None, &Mailmap::default()).unwrap();
+    let oids: Vec<_> = revisions.revisions.iter().map(|r| r.oid).collect();
+    assert_eq!(oids, vec![head, second, first]);
+    assert_eq!(text(&revisions.content(&fixture.repo, 2).unwrap()), "one\ntwo\n");
+    assert_eq!(text(&revisions.content(&fixture.repo, 0).unwrap()), "one\n2\n");
+
+    let diff = revisions.diff_with_previous(&fixture.repo, 1).unwrap().unwrap();
+    assert_eq!(diff, vec![DiffLine::Same("one".to_string()), DiffLine::Removed("two".to_string()), DiffLine::Added("2".to_string())]);
+    let diff = revisions.diff_with_previous(&fixture.repo, 2).unwrap().unwrap();
+    assert_eq!(diff.len(), 2);
+
+    let mut missing = FileRevisions::load(&fixture.repo, Path::new("notes.txt"), first, None, &Mailmap::default()).unwrap();
+    assert_eq!(missing.revisions.len(), 1);
+    let mut other = FileRevisions::load(&fixture.repo, Path::new("other.txt"), first, None, &Mailmap::default()).unwrap();
+    assert_eq!(*other.content(&fixture.repo, 0).unwrap(), Content::Missing);
+    assert_eq!(text(&missing.content(&fixture.repo, 0).unwrap()), "one\ntwo\n");
+
+    fn text(content: &Content) -> String {
+        match *content {
+            Content::Text(ref text) => text.text.clone(),
+            _ => panic!("{:?} is not text", content),
+        }
+    }
+}
+
+#[test]
+fn a_long_file_is_diffed_line_by_line() {
+    let fixture = TestRepo::new("file-revisions-long");
+    let lines: Vec<String> = (0..5000).map(|n| format!("line {}\n", n)).collect();
+    fixture.commit_file("long.txt", &lines.concat(), "Add a long file");
+    let mut changed = lines.clone();
+    changed[2500] = "changed\n".to_string();
+    let head = fixture.commit_file("long.txt", &changed.concat(), "Change one line");
+
+    let mut revisions = FileRevisions::load(&fixture.repo, Path::new("long.txt"), head, None, &Mailmap::default()).unwrap();
+    let diff = revisions.diff_with_previous(&fixture.repo, 0).unwrap().unwrap();
+    assert_eq!(diff.len(), 5001);
+    assert_eq!(diff[2500], DiffLine::Removed("line 2500".to_string()));
+    assert_eq!(diff[2501], DiffLine::Added("changed".to_string()));
+    assert_eq!(diff[2502], DiffLine::Same("line 2501".to_string()));
+}
@@ -0,0 +1,190 @@
+use config::IssueTracker;
+
+/// A reference to an issue found in a commit message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueRef {
+    /// The byte range of the reference within the message.
+    pub start: usize,
+    pub end: usize,
+    /// The text of the reference, e.g. '#123' or 'JIRA-456'.
+    pub text: String,
+    /// The issue tracker page for the issue. This is None if the tracker's
+    /// URL needs the repository's web address and it has no forge remote.
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Key,
+    Id,
+}
+
+/// The tracker used when none are configured: GitHub-style '#123'.
+pub fn default_trackers() -> Vec<IssueTracker> {
+    vec![IssueTracker { pattern: "#{id}".to_string(), url: "{base}/issues/{id}".to_string() }]
+}
+
+/// Finds all the issue references in `message`, in order. Where references
+/// from different trackers overlap the earliest, then longest, wins. `base`
+/// is the web address of the repository, used to expand '{base}' in URLs.
+pub fn find_refs(message: &str, trackers: &[IssueTracker], base: Option<&str>) -> Vec<IssueRef> {
+    let compiled: Vec<(Vec<Token>, &IssueTracker)> = trackers.iter()
+        .map(|t| (parse_pattern(&t.pattern), t))
+        .filter(|&(ref tokens, _)| !tokens.is_empty())
+        .collect();
+
+    let mut refs = Vec::new();
+    let mut pos = 0;
+    while pos < message.len() {
+        if !message.is_char_boundary(pos) || !at_word_start(message, pos) {
+            pos += 1;
+            continue;
+        }
+
+        let best = compiled.iter()
+            .filter_map(|&(ref tokens, tracker)| match_at(tokens, message, pos).map(|m| (m, tracker)))
+            .max_by_key(|&((end, _, _), _)| end);
+
+        match best {
+            Some(((end, key, id), tracker)) => {
+                let text = message[pos..end].to_string();
+                let url = expand_url(&tracker.url, &text, &key, &id, base);
+                refs.push(IssueRef { start: pos, end, text, url });
+                pos = end;
+            },
+            None => pos += 1,
+        }
+    }
+
+    refs
+}
+
+fn expand_url(template: &str, text: &str, key: &str, id: &str, base: Option<&str>) -> Option<String> {
+    let url = template.replace("{ref}", text).replace("{key}", key).replace("{id}", id);
+    if url.contains("{base}") {
+        base.map(|b| url.replace("{base}", b))
+    } else {
+        Some(url)
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = pattern;
+    while !rest.is_empty() {
+        let next = [("{key}", Token::Key), ("{id}", Token::Id)].iter()
+            .filter_map(|&(placeholder, ref token)| rest.find(placeholder).map(|i| (i, placeholder, token.clone())))
+            .min_by_key(|&(i, _, _)| i);
+
+        match next {
+            Some((i, placeholder, token)) => {
+                if i > 0 {
+                    tokens.push(Token::Literal(rest[..i].to_string()));
+                }
+                tokens.push(token);
+                rest = &rest[i + placeholder.len()..];
+            },
+            None => {
+                tokens.push(Token::Literal(rest.to_string()));
+                rest = "";
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Tries to match `tokens` at `start`, returning the end of the match and
+/// the text matched by `{key}` and `{id}`. The match must end at a word boundary.
+fn match_at(tokens: &[Token], text: &str, start: usize) -> Option<(usize, String, String)> {
+    let mut pos = start;
+    let mut key = String::new();
+    let mut id = String::new();
+
+    for token in tokens {
+        let rest = &text[pos..];
+        match *token {
+            Token::Literal(ref literal) => {
+                if !rest.starts_with(literal.as_str()) {
+                    return None;
+                }
+                pos += literal.len();
+            },
+            Token::Key => {
+                let n = rest.bytes().take_while(|b| b.is_ascii_uppercase()).count();
+                if n == 0 {
+                    return None;
+                }
+                key = rest[..n].to_string();
+                pos += n;
+            },
+            Token::Id => {
+                let n = rest.bytes().take_while(|b| b.is_ascii_digit()).count();
+                if n == 0 {
+                    return None;
+                }
+                id = rest[..n].to_string();
+                pos += n;
+            },
+        }
+    }
+
+    let ends_word = text[pos..].chars().next().map_or(true, |c| !c.is_alphanumeric());
+    if pos > start && ends_word { Some((pos, key, id)) } else { None }
+}
+
+fn at_word_start(text: &str, pos: usize) -> bool {
+    text[..pos].chars().next_back().map_or(true, |c| !c.is_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(pattern: &str, url: &str) -> IssueTracker {
+        IssueTracker { pattern: pattern.to_string(), url: url.to_string() }
+    }
+
+    fn texts(refs: &[IssueRef]) -> Vec<&str> {
+        refs.iter().map(|r| r.text.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_pattern_works() {
+        assert_eq!(parse_pattern("#{id}"), vec![Token::Literal("#".to_string()), Token::Id]);
+        assert_eq!(parse_pattern("{key}-{id}"), vec![Token::Key, Token::Literal("-".to_string()), Token::Id]);
+        assert_eq!(parse_pattern(""), vec![]);
+    }
+
+    #[test]
+    fn finds_github_refs() {
+        let refs = find_refs("Fix #12 and #345, not a#1 or #x.", &default_trackers(), Some("https://github.com/a/b"));
+        assert_eq!(texts(&refs), vec!["#12", "#345"]);
+        assert_eq!(refs[0].start, 4);
+        assert_eq!(refs[0].end, 7);
+        assert_eq!(refs[0].url, Some("https://github.com/a/b/issues/12".to_string()));
+    }
+
+    #[test]
+    fn finds_jira_refs() {
+        let trackers = vec![tracker("{key}-{id}", "https://jira/browse/{key}-{id}")];
+        let refs = find_refs("JIRA-456: fix OAF-7, but not abcJIRA-1 or UTF-8x", &trackers, None);
+        assert_eq!(texts(&refs), vec!["JIRA-456", "OAF-7"]);
+        assert_eq!(refs[1].url, Some("https://jira/browse/OAF-7".to_string()));
+    }
+
+    #[test]
+    fn url_needing_base_without_one_is_none() {
+        let refs = find_refs("#1", &default_trackers(), None);
+        assert_eq!(texts(&refs), vec!["#1"]);
+        assert_eq!(refs[0].url, None);
+    }
+
+    #[test]
+    fn longest_match_wins() {
+        let trackers = vec![tracker("#{id}", "{ref}"), tracker("#{id}-{id}", "{ref}")];
+        let refs = find_refs("see #1-2", &trackers, None);
+        assert_eq!(texts(&refs), vec!["#1-2"]);
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use git2::Repository;
+use config::{LargeRepoConfig, LargeRepoMode};
+
+/// Size of a version 2 pack index header plus its fan-out table.
+const IDX_HEADER_LEN: usize = 8 + 256 * 4;
+const IDX_V2_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+
+/// Decides whether `repo` should be treated as a large repository.
+pub fn is_large(repo: &Repository, config: &LargeRepoConfig) -> bool {
+    match config.mode {
+        LargeRepoMode::Always => true,
+        LargeRepoMode::Never => false,
+        LargeRepoMode::Auto => {
+            let _timer = timer!("large_repo::is_large");
+            let count = estimate_object_count(repo);
+            let large = count > config.object_threshold;
+            _timer.set_message(format!("{} objects, large = {}", count, large));
+            large
+        }
+    }
+}
+
+/// Estimates the number of objects in the repository without reading them:
+/// the counts recorded in the pack indexes plus the number of loose objects.
+/// Objects that appear in more than one pack are counted more than once.
+pub fn estimate_object_count(repo: &Repository) -> u64 {
+    let objects = repo.path().join("objects");
+    packed_object_count(&objects.join("pack")) + loose_object_count(&objects)
+}
+
+fn packed_object_count(pack_dir: &Path) -> u64 {
+    let entries = match fs::read_dir(pack_dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries.filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map_or(false, |ext| ext == "idx"))
+        .filter_map(|p| {
+            let mut header = Vec::with_capacity(IDX_HEADER_LEN);
+            File::open(&p).ok()?.take(IDX_HEADER_LEN as u64).read_to_end(&mut header).ok()?;
+            idx_object_count(&header)
+        })
+        .sum()
+}
+
+/// Loose objects live in directories named after the first two hex digits
+/// of their id.
+fn loose_object_count(objects_dir: &Path) -> u64 {
+    let entries = match fs::read_dir(objects_dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries.filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            name.len() == 2 && name.chars().all(|c| c.is_digit(16))
+        })
+        .map(|e| fs::read_dir(e.path()).map(|d| d.count() as u64).unwrap_or(0))
+        .sum()
+}
+
+/// Reads the object count from the header of a pack index file. The last
+/// entry of the fan-out table is the total number of objects in the pack.
+fn idx_object_count(header: &[u8]) -> Option<u64> {
+    let fanout_start = if header.starts_with(&IDX_V2_MAGIC) { 8 } else { 0 };
+    let last = fanout_start + 255 * 4;
+    let bytes = header.get(last..last + 4)?;
+    Some(bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fanout(total: u32) -> Vec<u8> {
+        let mut table = vec![0u8; 255 * 4];
+        table.extend_from_slice(&[(total >> 24) as u8, (total >> 16) as u8, (total >> 8) as u8, total as u8]);
+        table
+    }
+
+    #[test]
+    fn idx_object_count_v2() {
+        let mut header = IDX_V2_MAGIC.to_vec();
+        header.extend_from_slice(&[0, 0, 0, 2]);
+        header.extend(fanout(70_000));
+        assert_eq!(idx_object_count(&header), Some(70_000));
+    }
+
+    #[test]
+    fn idx_object_count_v1() {
+        assert_eq!(idx_object_count(&fanout(12)), Some(12));
+    }
+
+    #[test]
+    fn idx_object_count_truncated() {
+        assert_eq!(idx_object_count(&[0xff, b't', b'O', b'c', 0, 0]), None);
+    }
+}
@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::Path;
+use git2::Repository;
+
+/// A parsed `.mailmap` file, used to map the names and email addresses
+/// recorded in commits to a person's canonical identity.
+/// See 'git help check-mailmap' for the file format.
+#[derive(Debug, Default, Clone)]
+pub struct Mailmap {
+    entries: Vec<MailmapEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MailmapEntry {
+    proper_name: Option<String>,
+    proper_email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+impl Mailmap {
+    /// Loads the mailmap for a repository, from '.mailmap' in the working
+    /// directory and from the file named by the 'mailmap.file' setting.
+    /// Missing files are not an error, they just result in an empty mailmap.
+    pub fn load(repo: &Repository) -> Self {
+        let mut mailmap = Mailmap::default();
+
+        if let Some(workdir) = repo.workdir() {
+            mailmap.add_file(&workdir.join(".mailmap"));
+        }
+
+        if let Ok(path) = repo.config().and_then(|c| c.get_path("mailmap.file")) {
+            mailmap.add_file(&path);
+        }
+
+        mailmap
+    }
+
+    fn add_file(&mut self, path: &Path) {
+        if !path.exists() {
+            return;
+        }
+
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                self.add_content(&content);
+                info!("Loaded mailmap from '{}', now have {} entries", path.display(), self.entries.len());
+            },
+            Err(e) => warn!("Cannot read mailmap file '{}', ignoring. Error = {}", path.display(), e),
+        }
+    }
+
+    fn add_content(&mut self, content: &str) {
+        self.entries.extend(content.lines().filter_map(parse_line));
+    }
+
+    /// Maps a name and email as recorded in a commit to the canonical pair.
+    /// Entries which specify the commit name as well as the email take
+    /// priority; when nothing matches the inputs are returned unchanged.
+    pub fn resolve(&self, name: &str, email: &str) -> (String, String) {
+        let by_email = |e: &&MailmapEntry| e.commit_email.eq_ignore_ascii_case(email);
+
+        let entry = self.entries.iter()
+            .filter(by_email)
+            .find(|e| e.commit_name.as_ref().map_or(false, |n| n.eq_ignore_ascii_case(name)))
+            .or_else(|| self.entries.iter().filter(by_email).find(|e| e.commit_name.is_none()));
+
+        match entry {
+            Some(entry) => (
+                entry.proper_name.clone().unwrap_or_else(|| name.to_string()),
+                entry.proper_email.clone().unwrap_or_else(|| email.to_string())
+            ),
+            None => (name.to_string(), email.to_string()),
+        }
+    }
+
+    /// Convenience function that resolves a signature and formats it
+    /// as 'Name <email>'.
+    pub fn resolve_signature(&self, sig: &::git2::Signature) -> String {
+        let (name, email) = self.resolve(&String::from_utf8_lossy(sig.name_bytes()),
+                                         &String::from_utf8_lossy(sig.email_bytes()));
+        format!("{} <{}>", name, email)
+    }
+}
+
+/// Parses one line of a mailmap file. Each line consists of up to two
+/// 'Name <email>' pairs, where the names are optional; the first pair is
+/// the proper identity and the second, if present, the one in the commit.
+fn parse_line(line: &str) -> Option<MailmapEntry> {
+    let line = match line.find('#') {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+
+    let mut pairs = Vec::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('<') {
+        let close = open + rest[open..].find('>')?;
+        let name = rest[..open].trim();
+        let email = rest[open + 1..close].trim();
+        pairs.push((non_empty(name), email.to_string()));
+        rest = &rest[close + 1..];
+    }
+
+    match pairs.len() {
+        1 => {
+            let (name, email) = pairs.remove(0);
+            Some(MailmapEntry { proper_name: name, proper_email: None, commit_name: None, commit_email: email })
+        },
+        2 => {
+            let (commit_name, commit_email) = pairs.remove(1);
+            let (proper_name, proper_email) = pairs.remove(0);
+            Some(MailmapEntry { proper_name, proper_email: Some(proper_email), commit_name, commit_email })
+        },
+        _ => None,
+    }
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    if s.is_empty() { None } else { Some(s.to_string()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Mailmap {
+        fn parse(content: &str) -> Self {
+            let mut mailmap = Mailmap::default();
+            mailmap.add_content(content);
+            mailmap
+        }
+    }
+
+    fn resolve(mailmap: &Mailmap, name: &str, email: &str) -> (String, String) {
+        mailmap.resolve(name, email)
+    }
+
+    fn pair(name: &str, email: &str) -> (String, String) {
+        (name.to_string(), email.to_string())
+    }
+
+    #[test]
+    fn parse_ignores_comments_and_blank_lines() {
+        let mm = Mailmap::parse("# A comment\n\n   \nBob <bob@example.com> # trailing\n");
+        assert_eq!(mm.entries.len(), 1);
+    }
+
+    #[test]
+    fn resolve_replaces_name_only() {
+        let mm = Mailmap::parse("Robert Smith <bob@example.com>");
+        assert_eq!(resolve(&mm, "bob", "bob@example.com"), pair("Robert Smith", "bob@example.com"));
+    }
+
+    #[test]
+    fn resolve_replaces_email_only() {
+        let mm = Mailmap::parse("<robert@example.com> <bob@example.com>");
+        assert_eq!(resolve(&mm, "bob", "bob@example.com"), pair("bob", "robert@example.com"));
+    }
+
+    #[test]
+    fn resolve_replaces_name_and_email() {
+        let mm = Mailmap::parse("Robert Smith <robert@example.com> <bob@example.com>");
+        assert_eq!(resolve(&mm, "bob", "BOB@example.com"), pair("Robert Smith", "robert@example.com"));
+    }
+
+    #[test]
+    fn resolve_prefers_entries_matching_commit_name() {
+        let mm = Mailmap::parse("Other <other@example.com> <shared@example.com>\n\
+                                 Robert <robert@example.com> bob <shared@example.com>");
+        assert_eq!(resolve(&mm, "bob", "shared@example.com"), pair("Robert", "robert@example.com"));
+        assert_eq!(resolve(&mm, "alice", "shared@example.com"), pair("Other", "other@example.com"));
+    }
+
+    #[test]
+    fn resolve_with_no_match_returns_input() {
+        let mm = Mailmap::parse("Robert <robert@example.com> <bob@example.com>");
+        assert_eq!(resolve(&mm, "alice", "alice@example.com"), pair("alice", "alice@example.com"));
+    }
+
+    #[test]
+    fn parse_line_rejects_malformed_lines() {
+        assert_eq!(parse_line("Bob <bob@example.com"), None);
+        assert_eq!(parse_line("just a name"), None);
+    }
+}
@@ -8,6 +8,23 @@ extern crate directories;
 #[macro_use]
 extern crate lazy_static;
 extern crate cursive;
+extern crate chrono;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+extern crate unicode_width;
+extern crate encoding_rs;
+#[cfg(unix)]
+extern crate signal_hook;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(any(feature = "forge-api", feature = "control-socket"))]
+extern crate serde_json;
+#[cfg(feature = "syntax-highlighting")]
+extern crate syntect;
+#[cfg(test)]
+extern crate crossbeam_channel;
 
 // Crates in my workspace.
 extern crate path_encoding;
@@ -15,16 +32,96 @@ extern crate path_encoding;
 use structopt::StructOpt;
 use std::path::{Path, PathBuf};
 use std::env;
+use std::fs;
+use std::io;
+use std::process;
+use log4rs::file::RawConfig;
 
 // If some of my modules export macros, they must be imported before they are used
 // (order matters where macros are concerned).
 #[macro_use] mod timer;
+mod trace;
 mod mru_list;
 use mru_list::MruList;
 mod utils;
 mod paths;
+mod config;
+mod dates;
+mod default_branch;
+mod diffstat;
+mod discard;
+mod error;
+mod error_report;
+mod export;
+mod file_content;
+mod file_info;
+mod file_revisions;
+#[cfg(test)]
+mod fixtures;
+mod forge;
+mod fuzzy;
+mod gitignore_catalogue;
+mod global_search;
+mod highlight;
 mod repositories;
+mod activity;
+mod autofetch;
+mod backport;
+mod backups;
+mod cancel;
+mod changelog;
+mod clipboard;
+mod colours;
+mod codeowners;
+mod commit;
+mod commit_graph;
+mod compare;
+#[cfg(all(unix, feature = "control-socket"))]
+mod control_socket;
+mod history;
+mod identity;
+mod ignore_rules;
+mod in_progress;
+mod inspect;
+#[cfg(test)]
+mod integration_tests;
+mod issues;
+mod large_repo;
+mod mailmap;
+mod maintenance;
+mod network;
+mod patch;
+mod patch_stack;
+mod refresh;
+mod refspecs;
+mod refs;
+mod release;
+#[cfg(feature = "forge-api")]
+mod pull_requests;
+mod push_review;
+mod range_diff;
+mod recent_commits;
+mod ref_editor;
+mod remotes;
+mod repo_setup;
+mod review_marks;
+mod rewrite;
+mod sandbox;
+mod scaffold;
+mod script;
+mod session;
+mod short_path;
+mod signatures;
+mod signing;
+mod sparse;
+mod staging;
+mod startup_error;
+mod status;
+mod text_width;
+mod trash;
+mod untracked;
 use repositories::{Repositories, RepositoryExtensions};
+use startup_error::StartupError;
 mod tui;//::default;
 
 // This produces various constants about the build environment which can be referred to using ::PKG_... syntax.
@@ -34,14 +131,60 @@ pub mod built_info {
 
 #[derive(StructOpt, Debug)]
 struct Arguments {
+    /// Read settings from this directory instead of the usual one. The
+    /// OAF_CONFIG_DIR environment variable does the same.
+    #[structopt(long = "config-dir", parse(from_os_str))]
+    config_dir: Option<PathBuf>,
+
+    /// Keep the MRU list, session, trash and caches in this directory instead
+    /// of the usual one. The OAF_DATA_DIR environment variable does the same.
+    #[structopt(long = "data-dir", parse(from_os_str))]
+    data_dir: Option<PathBuf>,
+
     /// Turn off loading of all config files, use compiled-in defaults for all settings.
     #[structopt(long = "no-config")]
     no_config: bool,
 
+    /// Start in offline mode; network operations such as fetch and push are disabled.
+    #[structopt(long = "offline")]
+    offline: bool,
+
+    /// Render for screen readers: plain linear text, without borders or
+    /// side by side panes.
+    #[structopt(long = "screen-reader")]
+    screen_reader: bool,
+
+    /// The terminal library to draw with: ncurses or termion. Only those oaf
+    /// was built with are available; the default is ncurses if it is.
+    #[structopt(long = "backend")]
+    backend: Option<tui::Backend>,
+
+    /// Listen on a Unix domain socket at this path for JSON-RPC requests from
+    /// other programs, such as editor plugins. oaf must be built with the
+    /// control-socket feature.
+    #[structopt(long = "control-socket", parse(from_os_str))]
+    control_socket: Option<PathBuf>,
+
+    /// If oaf is already running, ask it to open the directories instead of
+    /// starting another. oaf must be built with the control-socket feature.
+    #[structopt(long = "single-instance")]
+    single_instance: bool,
+
     /// Turn off all logging.
     #[structopt(long = "no-logging")]
     no_logging: bool,  
 
+    /// Run the commands in this file instead of starting the UI, printing
+    /// what each one did. Each line is one of 'open <directory>',
+    /// 'fetch [remote]', 'checkout <branch>' or 'report'; '#' starts a comment.
+    #[structopt(long = "script", parse(from_os_str))]
+    script: Option<PathBuf>,
+
+    /// Record every timed operation and write them to this file on exit, in
+    /// the Chrome trace format (load it in chrome://tracing).
+    #[structopt(long = "trace-file", parse(from_os_str))]
+    trace_file: Option<PathBuf>,
+
     /// Optional list of directories to open. The directories are expected to be
     /// git repositories. If no directory is passed, the current directory is assumed.
     #[structopt(parse(from_os_str))]
@@ -49,20 +192,73 @@ struct Arguments {
 }
 
 lazy_static! {
-    static ref PATHS: paths::WellKnownPaths = { paths::WellKnownPaths::new() };
+    static ref PATHS: paths::WellKnownPaths = {
+        paths::WellKnownPaths::new().unwrap_or_else(|e| startup_error::exit(&e))
+    };
 }
 
 fn main() {
     std::env::set_var("IN_OAF", "1");
     let mut args = Arguments::from_args();
+    tui::install_panic_hook();
+    // The options override the environment variables, which is where PATHS
+    // looks for them.
+    if let Some(ref dir) = args.config_dir {
+        env::set_var(paths::CONFIG_DIR_VAR, dir);
+    }
+    if let Some(ref dir) = args.data_dir {
+        env::set_var(paths::DATA_DIR_VAR, dir);
+    }
+    // Find the well-known paths up front so that if that fails the user gets
+    // a clear message now rather than a panic at some random later point.
+    lazy_static::initialize(&PATHS);
+    if (args.control_socket.is_some() || args.single_instance) && !cfg!(all(unix, feature = "control-socket")) {
+        startup_error::exit(&StartupError::NoControlSocket);
+    }
 
     // Configure logging as early as possible (because, obviously, we want to log
     // in the rest of the initialization phase).
+    // Mistakes in the settings files don't stop oaf starting; they are shown
+    // once the UI is up.
+    let mut problems = Vec::new();
     if !args.no_logging {
-        configure_logging(PATHS.logging_config_file());
+        problems.extend(configure_logging(PATHS.logging_config_file()));
         log_built_info();
     }
 
+    if let Some(ref file) = args.trace_file {
+        trace::start(file);
+    }
+
+    if !args.no_config {
+        let (cfg, config_problems) = config::load(PATHS.config_file());
+        config::set(cfg);
+        problems.extend(config_problems);
+    }
+
+    for problem in &problems {
+        warn!("Problem in settings file '{}', ignoring the setting. {}", problem.file.display(), problem);
+    }
+
+    if args.offline || config::current().offline {
+        network::set_offline(true);
+    }
+
+    if let Some(ref file) = args.script {
+        let result = script::run_file(file, &mut io::stdout());
+        if let Err(e) = trace::finish() {
+            warn!("Error writing trace file, error = {}", e);
+        }
+        if let Err(e) = result {
+            eprintln!("The script '{}' stopped. {}", file.display(), e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    PATHS.migrate();
+    session::load(PATHS.session_file());
+
     let mut mru = MruList::new(PATHS.mru_file(), 20);
     if let Err(e) = mru.read_from_file() {
         warn!("Error reading from MRU file '{}', ignoring. Error = {}", PATHS.mru_file().display(), e);
@@ -72,23 +268,114 @@ fn main() {
     // were specified) and try and open them all. This also validates paths
     // and ascends the directory to tree to try and find a valid repo.
     verify_directories(&mut args.directories);
+
+    // A single instance listens on the well-known socket, unless told to use
+    // another, and the first thing to do is see if one is running already.
+    let single_instance = args.single_instance || config::current().single_instance;
+    let control_socket = match args.control_socket.clone() {
+        Some(socket) => Some(socket),
+        None if single_instance => Some(PATHS.control_socket_file().to_path_buf()),
+        None => None,
+    };
+    if single_instance {
+        if let Some(ref socket) = control_socket {
+            forward_to_running_instance(socket, &args.directories);
+        }
+    }
     let mut repos = Repositories::new(mru);
 
+    // They are opened in the background once the UI is up.
     for dir in &args.directories {
-        let _ = repos.open(dir);
+        repos.add_pending(dir);
     }
 
-    tui::run_cursive(repos);
+    let screen_reader = args.screen_reader || config::current().display.screen_reader;
+    let backend = args.backend.or_else(tui::Backend::default_available)
+        .expect("oaf must be built with the ncurses-backend or termion-backend feature");
+    tui::run_cursive(repos, tui::Options {
+        backend,
+        screen_reader,
+        problems,
+        watch_config: !args.no_config,
+        control_socket,
+    });
+
+    if let Err(e) = trace::finish() {
+        warn!("Error writing trace file, error = {}", e);
+    }
 }
 
 
 
-fn configure_logging(logging_config_file: &Path) {
-    if logging_config_file.exists() {
-        log4rs::init_file(&logging_config_file, Default::default()).expect("Cannot configure logging.");
+/// Asks the oaf listening on `socket`, if there is one, to open `directories`,
+/// and exits. Otherwise returns, so that this oaf starts and listens there.
+#[cfg(all(unix, feature = "control-socket"))]
+fn forward_to_running_instance(socket: &Path, directories: &[PathBuf]) {
+    let results = match control_socket::forward_open(socket, directories) {
+        Some(results) => results,
+        None => return,
+    };
+
+    let mut failed = false;
+    for (dir, result) in directories.iter().zip(results) {
+        match result {
+            Ok(name) => println!("Opened '{}' in the oaf that is already running.", name),
+            Err(e) => {
+                eprintln!("The oaf that is already running cannot open '{}': {}", dir.display(), e);
+                failed = true;
+            },
+        }
+    }
+    process::exit(if failed { 1 } else { 0 });
+}
+
+#[cfg(not(all(unix, feature = "control-socket")))]
+fn forward_to_running_instance(_socket: &Path, _directories: &[PathBuf]) {
+    warn!("The single_instance setting needs the control-socket feature, ignoring it.");
+}
+
+/// Configures logging from `logging_config_file`, if there is one. The
+/// parts of the file that are wrong are left out and returned as problems;
+/// if the file cannot be parsed at all, logging stays off.
+fn configure_logging(logging_config_file: &Path) -> Vec<config::Problem> {
+    if !logging_config_file.exists() {
+        return Vec::new();
+    }
+
+    let problem = |message: String| config::Problem::new(logging_config_file, None, message);
+    let raw = match fs::read_to_string(logging_config_file) {
+        Ok(content) => toml::from_str::<RawConfig>(&content).map_err(|e| e.to_string()),
+        Err(e) => Err(format!("Cannot read the file: {}", e)),
+    };
+    let raw = match raw {
+        Ok(raw) => raw,
+        Err(message) => return vec![problem(format!("{}. Logging is off.", message))],
+    };
+
+    let (appenders, appender_errors) = raw.appenders_lossy(&Default::default());
+    let (log_config, config_errors) = log4rs::config::Config::builder()
+        .appenders(appenders)
+        .loggers(raw.loggers())
+        .build_lossy(raw.root());
+    let mut problems: Vec<_> = appender_errors.iter().map(|e| problem(e.to_string()))
+        .chain(config_errors.iter().map(|e| problem(e.to_string())))
+        .collect();
+
+    // init_file is only used for a file without mistakes, because it prints
+    // them over the terminal. Unlike init_config, it reloads the file when
+    // it changes if the file asks for that.
+    let result = if problems.is_empty() {
+        log4rs::init_file(logging_config_file, Default::default()).map_err(|e| e.to_string())
+    } else {
+        log4rs::init_config(log_config).map(|_| ()).map_err(|e| e.to_string())
+    };
+
+    match result {
         // Use a messge that makes it very easy to find the start of one run in a log file.
-        info!("========== Logging initialized using file at {:?} ==========", logging_config_file);
+        Ok(()) => info!("========== Logging initialized using file at {:?} ==========", logging_config_file),
+        Err(message) => problems.push(problem(format!("{}. Logging is off.", message))),
     }
+    problems
 }
 
 fn log_built_info() {
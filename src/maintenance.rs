@@ -0,0 +1,353 @@
+//! Housekeeping of a repository's object database and refs: the equivalents
+//! of 'git gc', 'git repack', 'git prune' and 'git pack-refs', run on demand
+//! or every so many days in the background. libgit2 cannot repack, prune or
+//! pack refs, so the tasks run the git program; the size statistics shown
+//! before and after are measured directly from the .git directory.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use git2::{Error, Repository};
+use cancel::Cancel;
+use config;
+use error::OafError;
+use patch;
+
+/// The repository's git config setting that records when maintenance last
+/// ran, in seconds since the Unix epoch.
+const LAST_RUN_SETTING: &str = "oaf.maintenanceLast";
+/// How often the background worker looks for repositories that are due, in
+/// seconds.
+const TICK_SECS: u64 = 10 * 60;
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// The maintenance tasks, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Task {
+    /// 'git gc': everything below, as git sees fit.
+    Gc,
+    /// 'git repack -a -d': all objects into one pack, removing redundant
+    /// packs and loose objects.
+    Repack,
+    /// 'git prune': loose objects that are unreachable and more than two
+    /// weeks old, so that those of an operation in progress are kept.
+    Prune,
+    /// 'git pack-refs --all': loose refs into the packed-refs file.
+    PackRefs,
+}
+
+impl Task {
+    pub fn all() -> &'static [Task] {
+        const ALL: [Task; 4] = [Task::Gc, Task::Repack, Task::Prune, Task::PackRefs];
+        &ALL
+    }
+
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Task::Gc => "Collect garbage (git gc)",
+            Task::Repack => "Repack objects (git repack)",
+            Task::Prune => "Prune unreachable objects (git prune)",
+            Task::PackRefs => "Pack refs (git pack-refs)",
+        }
+    }
+
+    fn args(&self) -> &'static [&'static str] {
+        match *self {
+            Task::Gc => &["gc", "--quiet"],
+            Task::Repack => &["repack", "-a", "-d", "--quiet"],
+            Task::Prune => &["prune", "--expire=2.weeks.ago"],
+            Task::PackRefs => &["pack-refs", "--all", "--prune"],
+        }
+    }
+}
+
+/// The size of a repository's object database and how many of its refs are
+/// loose files and how many are in the packed-refs file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub loose_objects: u64,
+    pub loose_size: u64,
+    pub packs: u64,
+    pub pack_size: u64,
+    pub loose_refs: u64,
+    pub packed_refs: u64,
+}
+
+impl Stats {
+    pub fn total_size(&self) -> u64 {
+        self.loose_size + self.pack_size
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} loose objects ({}), {} packs ({}), {} loose and {} packed refs; {} in all",
+               self.loose_objects, patch::format_size(self.loose_size),
+               self.packs, patch::format_size(self.pack_size),
+               self.loose_refs, self.packed_refs, patch::format_size(self.total_size()))
+    }
+}
+
+/// The statistics before and after a run, and how each task went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub before: Stats,
+    pub after: Stats,
+    pub tasks: Vec<(Task, Result<(), String>)>,
+}
+
+impl Report {
+    pub fn succeeded(&self) -> bool {
+        self.tasks.iter().all(|&(_, ref result)| result.is_ok())
+    }
+
+    /// One line saying how much space was saved, e.g. 'Freed 1.2 MB, from
+    /// 3.4 MB to 2.2 MB.'
+    pub fn summary(&self) -> String {
+        let (before, after) = (self.before.total_size(), self.after.total_size());
+        let (before_text, after_text) = (patch::format_size(before), patch::format_size(after));
+        if after < before {
+            format!("Freed {}, from {} to {}.", patch::format_size(before - after), before_text, after_text)
+        } else {
+            format!("Freed nothing, from {} to {}.", before_text, after_text)
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(task, ref result) in &self.tasks {
+            match *result {
+                Ok(()) => writeln!(f, "{}: done", task.description())?,
+                Err(ref message) => writeln!(f, "{}: FAILED, {}", task.description(), message)?,
+            }
+        }
+        writeln!(f, "\nBefore: {}", self.before)?;
+        writeln!(f, "After:  {}", self.after)?;
+        write!(f, "\n{}", self.summary())
+    }
+}
+
+/// Measures the object database and refs of the repository whose .git
+/// directory is `git_dir`, by looking at the files; nothing is read.
+pub fn measure(git_dir: &Path) -> Stats {
+    let objects = git_dir.join("objects");
+    let mut stats = Stats::default();
+
+    for dir in read_dir(&objects) {
+        let name = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        if name.len() == 2 && name.chars().all(|c| c.is_digit(16)) {
+            for object in read_dir(&dir) {
+                stats.loose_objects += 1;
+                stats.loose_size += file_size(&object);
+            }
+        }
+    }
+
+    for file in read_dir(&objects.join("pack")) {
+        if file.extension().map_or(false, |ext| ext == "pack") {
+            stats.packs += 1;
+        }
+        stats.pack_size += file_size(&file);
+    }
+
+    stats.loose_refs = loose_ref_count(git_dir);
+    stats.packed_refs = fs::read_to_string(git_dir.join("packed-refs")).map(|c| packed_ref_count(&c)).unwrap_or(0);
+    stats
+}
+
+/// The number of refs stored as files under 'refs', rather than in the
+/// packed-refs file.
+pub fn loose_ref_count(git_dir: &Path) -> u64 {
+    count_files(&git_dir.join("refs"))
+}
+
+/// The number of refs in the content of a packed-refs file. Lines starting
+/// '#' are the header and those starting '^' are the peeled targets of the
+/// annotated tag on the line before.
+fn packed_ref_count(content: &str) -> u64 {
+    content.lines().filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('^')).count() as u64
+}
+
+/// Suggests packing the refs if there are at least `threshold` loose ones,
+/// since each is a file that many operations have to read. 0 turns the hint
+/// off.
+pub fn pack_refs_hint(loose_refs: u64, threshold: u64) -> Option<String> {
+    if threshold == 0 || loose_refs < threshold {
+        return None;
+    }
+    Some(format!("There are {} loose refs, which slows down many operations. Packing them \
+                  (Repository > Maintenance) would help.", loose_refs))
+}
+
+fn read_dir(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default()
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn count_files(dir: &Path) -> u64 {
+    read_dir(dir).iter()
+        .map(|path| if path.is_dir() { count_files(path) } else { 1 })
+        .sum()
+}
+
+/// Runs `tasks` in order on the repository whose .git directory is
+/// `git_dir`, calling `on_progress` before each. A task that fails does not
+/// stop the others. The time of the run is recorded for the schedule.
+pub fn run(git_dir: &Path, tasks: &[Task], cancel: &Cancel, on_progress: &dyn Fn(String)) -> Result<Report, OafError> {
+    let _timer = timer!("maintenance::run");
+    let repo = Repository::open(git_dir)?;
+    let before = measure(repo.path());
+
+    let mut results = Vec::new();
+    for &task in Task::all().iter().filter(|t| tasks.contains(t)) {
+        cancel.check()?;
+        on_progress(format!("{}...", task.description()));
+        let result = run_task(repo.path(), task).map_err(|e| e.to_string());
+        info!("Maintenance of '{}': {:?} gave {:?}", repo.path().display(), task, result);
+        results.push((task, result));
+    }
+
+    record_run(&repo, now())?;
+    let report = Report { before, after: measure(repo.path()), tasks: results };
+    _timer.set_message(format!("'{}': {}", repo.path().display(), report.summary()));
+    Ok(report)
+}
+
+fn run_task(git_dir: &Path, task: Task) -> Result<(), OafError> {
+    let output = Command::new("git").arg("-C").arg(git_dir).args(task.args()).output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(OafError::Git(Error::from_str(&format!("git {} failed: {}", task.args()[0],
+                                                   String::from_utf8_lossy(&output.stderr).trim()))))
+    }
+}
+
+/// When maintenance last ran on `repo`, in seconds since the Unix epoch.
+pub fn last_run(repo: &Repository) -> Option<i64> {
+    repo.config().and_then(|c| c.get_i64(LAST_RUN_SETTING)).ok()
+}
+
+fn record_run(repo: &Repository, time: i64) -> Result<(), Error> {
+    repo.config()?.set_i64(LAST_RUN_SETTING, time)
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// Whether maintenance is due at `now`, given when it last ran. A repository
+/// that has never had it is due straight away; 0 days turns it off.
+pub fn is_due(last_run: Option<i64>, interval_days: u32, now: i64) -> bool {
+    match (interval_days, last_run) {
+        (0, _) => false,
+        (_, None) => true,
+        (days, Some(last)) => now - last >= days as i64 * DAY_SECS,
+    }
+}
+
+/// Adds repositories to a running maintenance schedule, such as ones opened
+/// after it started.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    sender: Sender<PathBuf>,
+}
+
+impl Scheduler {
+    /// Starts maintaining the repository whose .git directory is `git_dir`,
+    /// if it is not maintained already.
+    pub fn add(&self, git_dir: &Path) {
+        let _ = self.sender.send(git_dir.to_path_buf());
+    }
+}
+
+/// Starts a background thread that runs the configured maintenance tasks on
+/// each of the repositories at `paths`, and any later given to the returned
+/// `Scheduler`, once it is due. The settings are read afresh each time, so
+/// turning the schedule on or off needs no restart. `on_report` is called on
+/// that thread after each run.
+pub fn spawn<F>(paths: Vec<PathBuf>, on_report: F) -> Scheduler
+    where F: Fn(PathBuf, Result<Report, OafError>) + Send + 'static
+{
+    let (sender, receiver) = mpsc::channel();
+    for path in paths {
+        let _ = sender.send(path);
+    }
+
+    thread::spawn(move || {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        loop {
+            while let Ok(path) = receiver.try_recv() {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+
+            thread::sleep(Duration::from_secs(TICK_SECS));
+            let config = config::current();
+            for path in &paths {
+                let last = match Repository::open(path) {
+                    Ok(repo) => last_run(&repo),
+                    Err(_) => continue,
+                };
+                if is_due(last, config.maintenance.interval_days, now()) {
+                    info!("Scheduled maintenance of '{}' is due", path.display());
+                    on_report(path.clone(), run(path, &config.maintenance.tasks, &Cancel::new(), &|_| {}));
+                }
+            }
+        }
+    });
+
+    Scheduler { sender }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_due_after_the_interval_or_if_never_run() {
+        let now = 100 * DAY_SECS;
+        assert!(!is_due(None, 0, now));
+        assert!(is_due(None, 7, now));
+        assert!(!is_due(Some(now - 6 * DAY_SECS), 7, now));
+        assert!(is_due(Some(now - 7 * DAY_SECS), 7, now));
+    }
+
+    #[test]
+    fn packed_ref_count_skips_the_header_and_peeled_lines() {
+        let content = "# pack-refs with: peeled fully-peeled sorted \n\
+                       0123456789abcdef0123456789abcdef01234567 refs/heads/master\n\
+                       1123456789abcdef0123456789abcdef01234567 refs/tags/v1.0\n\
+                       ^2123456789abcdef0123456789abcdef01234567\n";
+        assert_eq!(packed_ref_count(content), 2);
+        assert_eq!(packed_ref_count(""), 0);
+    }
+
+    #[test]
+    fn pack_refs_hint_needs_enough_loose_refs() {
+        assert!(pack_refs_hint(1500, 1000).unwrap().starts_with("There are 1500 loose refs"));
+        assert_eq!(pack_refs_hint(1500, 2000), None);
+        assert_eq!(pack_refs_hint(1500, 0), None);
+    }
+
+    #[test]
+    fn summary_gives_the_space_freed() {
+        let stats = |loose_size, pack_size| Stats { loose_size, pack_size, ..Stats::default() };
+        let report = Report { before: stats(3072, 1024), after: stats(0, 2048), tasks: Vec::new() };
+        assert_eq!(report.summary(), "Freed 2.0 KB, from 4.0 KB to 2.0 KB.");
+        let report = Report { before: stats(0, 2048), after: stats(0, 2048), tasks: Vec::new() };
+        assert_eq!(report.summary(), "Freed nothing, from 2.0 KB to 2.0 KB.");
+    }
+}
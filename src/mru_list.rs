@@ -6,6 +6,62 @@ use path_encoding;
 use paths;
 use std::slice;
 
+/// The in-memory part of an MRU list, for any kind of item. New items are
+/// added at the front; adding an item that is already in the list moves it
+/// to the front instead. The list never holds more than its maximum size,
+/// the least recently used items falling off the end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mru<T> {
+    items: Vec<T>,
+    max_items: usize,
+}
+
+impl<T: PartialEq> Mru<T> {
+    pub fn new(max_items: usize) -> Self {
+        Mru { items: Vec::with_capacity(max_items), max_items }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Makes `item` the first in the list.
+    pub fn insert(&mut self, item: T) {
+        self.remove(&item);
+        self.items.insert(0, item);
+        self.items.truncate(self.max_items);
+    }
+
+    /// Removes `item` from the list if it is there.
+    pub fn remove(&mut self, item: &T) {
+        if let Some(pos) = self.items.iter().position(|x| x == item) {
+            self.items.remove(pos);
+        }
+    }
+
+    /// Puts the items back in the reverse order, for when a list has been
+    /// loaded by inserting its items most recent first.
+    pub fn reverse(&mut self) {
+        self.items.reverse();
+    }
+
+    pub fn iter(&self) -> slice::Iter<T> {
+        self.items.iter()
+    }
+}
+
+impl<T> Index<usize> for Mru<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.items[index]
+    }
+}
+
 /// A simple MRU-list data structure. Create a list of the appropriate
 /// maximum size (which can be changed later) then use `insert` to add new
 /// items. New items are always added at the front of the list. Adding
@@ -21,7 +77,7 @@ use std::slice;
 /// but writes them out to disk in their friendlier '~' form.
 pub struct MruList {
     filename: PathBuf,
-    items: Vec<PathBuf>,
+    items: Mru<PathBuf>,
     max_items: usize,
 }
 
@@ -31,7 +87,7 @@ impl MruList {
     {
         Self {
             filename: filename.as_ref().to_path_buf(),
-            items: Vec::with_capacity(max_items),
+            items: Mru::new(max_items),
             max_items: max_items,
         }
     }
@@ -58,9 +114,7 @@ impl MruList {
 
     fn insert_impl(&mut self, path: PathBuf)
     {
-        self.remove(&path);
-        self.items.insert(0, path);
-        self.items.truncate(self.max_items);
+        self.items.insert(path);
     }
 
     /// Removes a path from the MRUList if it exists. A no-op if it doesn't.
@@ -73,9 +127,7 @@ impl MruList {
 
     fn remove_impl(&mut self, path: &Path)
     {
-        if let Some(pos) = self.items.iter().position(|x| *x == *path) {
-            self.items.remove(pos);
-        }
+        self.items.remove(&path.to_path_buf());
     }
 
     pub fn write_to_file(&mut self) -> io::Result<()> {
@@ -84,7 +136,7 @@ impl MruList {
         let file = File::create(&self.filename)?;
         let mut writer = BufWriter::new(file);
 
-        for pbuf in &self.items {
+        for pbuf in self.items.iter() {
             let p = paths::to_canon(pbuf);
             let encoded_path = path_encoding::encode_path(&p);
             writeln!(writer, "{}", encoded_path);
@@ -223,4 +275,13 @@ mod tests {
         assert_eq!(Some(&PathBuf::from("c")), it.next());
         assert_eq!(None, it.next());
     }
+
+    #[test]
+    fn mru_moves_an_existing_item_to_the_front() {
+        let mut mru = Mru::new(3);
+        for item in &[1, 2, 3, 1, 4] {
+            mru.insert(*item);
+        }
+        assert_eq!(mru.iter().cloned().collect::<Vec<_>>(), vec![4, 1, 3]);
+    }
 }
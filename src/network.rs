@@ -0,0 +1,281 @@
+use std::cmp;
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use git2::{Config, Error, ProxyOptions};
+use cancel::Cancel;
+use config::RetryConfig;
+use error::OafError;
+
+/// Parts of the messages of network failures that are likely to be passing.
+const TRANSIENT_MESSAGES: [&str; 8] = [
+    "timed out", "timeout", "connection reset", "connection was reset", "connection aborted",
+    "broken pipe", "early eof", "unexpected eof",
+];
+
+/// How often a wait between retries checks whether it has been cancelled.
+const RETRY_TICK_MILLIS: u64 = 100;
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the user has asked oaf not to touch the network.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::SeqCst)
+}
+
+pub fn set_offline(offline: bool) {
+    info!("Offline mode is now {}", if offline { "on" } else { "off" });
+    OFFLINE.store(offline, Ordering::SeqCst);
+}
+
+/// Call this before any network operation so that it fails straight away,
+/// rather than timing out, when in offline mode.
+pub fn check_online() -> Result<(), OafError> {
+    check_online_when(is_offline())
+}
+
+/// As `check_online`, given whether oaf is offline, so that tests need not
+/// change the setting for every other test running at the same time.
+fn check_online_when(offline: bool) -> Result<(), OafError> {
+    if offline {
+        Err(OafError::Network(Error::from_str("oaf is in offline mode. Turn offline mode off (Remote menu) to use the network.")))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether `error` is a network failure that is likely to be passing, such as
+/// a timeout or a dropped connection, rather than bad credentials or a
+/// missing repository, so that trying again may work.
+pub fn is_transient(error: &OafError) -> bool {
+    match *error {
+        OafError::Network(ref e) => {
+            let message = e.message().to_lowercase();
+            TRANSIENT_MESSAGES.iter().any(|m| message.contains(m))
+        },
+        _ => false,
+    }
+}
+
+/// A retry of a network operation that is about to happen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retry {
+    /// Which retry this is, from 1.
+    pub retry: u32,
+    pub retries: u32,
+    pub delay: Duration,
+    /// The failure that caused the retry.
+    pub error: String,
+}
+
+impl fmt::Display for Retry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} Retrying in {:.1} seconds (retry {} of {}).", self.error,
+               self.delay.as_secs() as f64 + f64::from(self.delay.subsec_millis()) / 1000.0, self.retry, self.retries)
+    }
+}
+
+/// The delay before retry number `retry` (from 1): `backoff_millis` doubled
+/// for each retry after the first.
+pub fn retry_delay(config: &RetryConfig, retry: u32) -> Duration {
+    let factor = 1u64 << cmp::min(retry.saturating_sub(1), 16);
+    Duration::from_millis(config.backoff_millis.saturating_mul(factor))
+}
+
+/// Runs `operation`, running it again after a transient failure up to
+/// `config.retries` times. `on_retry` is told about each retry before the
+/// delay that precedes it. Cancelling stops the delay and returns
+/// `OafError::Cancelled`; any other failure is returned as it is.
+pub fn with_retries<T, F, R>(config: &RetryConfig, cancel: &Cancel, mut operation: F, mut on_retry: R) -> Result<T, OafError>
+    where F: FnMut() -> Result<T, OafError>,
+          R: FnMut(&Retry)
+{
+    let mut retry = 0;
+    loop {
+        match operation() {
+            Err(ref e) if retry < config.retries && is_transient(e) => {
+                retry += 1;
+                let delay = retry_delay(config, retry);
+                warn!("Network operation failed, retry {} of {} in {:?}: {}", retry, config.retries, delay, e);
+                on_retry(&Retry { retry, retries: config.retries, delay, error: e.to_string() });
+                sleep_unless_cancelled(delay, cancel)?;
+            },
+            result => return result,
+        }
+    }
+}
+
+fn sleep_unless_cancelled(delay: Duration, cancel: &Cancel) -> Result<(), OafError> {
+    let tick = Duration::from_millis(RETRY_TICK_MILLIS);
+    let mut waited = Duration::from_millis(0);
+    while waited < delay {
+        cancel.check()?;
+        let step = cmp::min(tick, delay - waited);
+        thread::sleep(step);
+        waited += step;
+    }
+    cancel.check()
+}
+
+/// Builds the proxy options for talking to the remote `remote_name` at `url`.
+/// The proxy is taken from git's 'remote.<name>.proxy' or 'http.proxy'
+/// settings, then from the usual environment variables. If none of those
+/// are set libgit2 is left to detect a proxy itself.
+pub fn proxy_options(config: &Config, remote_name: &str, url: &str) -> ProxyOptions<'static> {
+    let mut options = ProxyOptions::new();
+    match proxy_for(config, remote_name, url) {
+        Some(proxy) => {
+            debug!("Using proxy '{}' for '{}'", proxy, url);
+            options.url(&proxy);
+        },
+        None => { options.auto(); },
+    }
+    options
+}
+
+fn proxy_for(config: &Config, remote_name: &str, url: &str) -> Option<String> {
+    let configured = config.get_string(&format!("remote.{}.proxy", remote_name)).ok()
+        .or_else(|| config.get_string("http.proxy").ok());
+
+    match configured {
+        // An empty setting explicitly turns the proxy off.
+        Some(ref proxy) if proxy.is_empty() => None,
+        Some(proxy) => Some(proxy),
+        None => proxy_from_env(url, |name| env::var(name).ok()),
+    }
+}
+
+/// Picks the proxy for `url` from the environment the way curl does: the
+/// scheme-specific variable, then 'all_proxy', with lower-case names taking
+/// precedence. Hosts listed in 'no_proxy' get no proxy. SSH remotes never
+/// use an HTTP proxy.
+fn proxy_from_env<F>(url: &str, get_env: F) -> Option<String>
+    where F: Fn(&str) -> Option<String>
+{
+    let var = |name: &str| get_env(name).or_else(|| get_env(&name.to_uppercase())).filter(|v| !v.is_empty());
+
+    let scheme_var = if url.starts_with("https://") {
+        "https_proxy"
+    } else if url.starts_with("http://") {
+        "http_proxy"
+    } else {
+        return None;
+    };
+
+    if let Some(no_proxy) = var("no_proxy") {
+        let host = url_host(url);
+        let excluded = no_proxy.split(',').map(|h| h.trim().trim_start_matches('.')).any(|h| {
+            h == "*" || (!h.is_empty() && (host == h || host.ends_with(&format!(".{}", h))))
+        });
+        if excluded {
+            return None;
+        }
+    }
+
+    var(scheme_var).or_else(|| var("all_proxy"))
+}
+
+fn url_host(url: &str) -> &str {
+    let rest = url.splitn(2, "://").nth(1).unwrap_or(url);
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    host.split(':').next().unwrap_or(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env_of(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn proxy(url: &str, vars: &[(&str, &str)]) -> Option<String> {
+        let env = env_of(vars);
+        proxy_from_env(url, |name| env.get(name).cloned())
+    }
+
+    #[test]
+    fn proxy_from_env_uses_scheme_variable() {
+        let vars = [("https_proxy", "http://s:1"), ("http_proxy", "http://p:1")];
+        assert_eq!(proxy("https://github.com/a/b", &vars), Some("http://s:1".to_string()));
+        assert_eq!(proxy("http://github.com/a/b", &vars), Some("http://p:1".to_string()));
+    }
+
+    #[test]
+    fn proxy_from_env_falls_back() {
+        assert_eq!(proxy("https://h/a", &[("HTTPS_PROXY", "http://u:1")]), Some("http://u:1".to_string()));
+        assert_eq!(proxy("https://h/a", &[("all_proxy", "http://a:1")]), Some("http://a:1".to_string()));
+        assert_eq!(proxy("https://h/a", &[]), None);
+    }
+
+    #[test]
+    fn proxy_from_env_ignores_ssh() {
+        assert_eq!(proxy("git@github.com:a/b.git", &[("all_proxy", "http://a:1")]), None);
+        assert_eq!(proxy("ssh://git@github.com/a/b.git", &[("all_proxy", "http://a:1")]), None);
+    }
+
+    #[test]
+    fn proxy_from_env_honours_no_proxy() {
+        let vars = [("https_proxy", "http://s:1"), ("no_proxy", "localhost, .corp.com")];
+        assert_eq!(proxy("https://git.corp.com/a", &vars), None);
+        assert_eq!(proxy("https://user@localhost:8443/a", &vars), None);
+        assert_eq!(proxy("https://github.com/a", &vars), Some("http://s:1".to_string()));
+    }
+
+    #[test]
+    fn only_timeouts_and_dropped_connections_are_transient() {
+        assert!(is_transient(&OafError::Network(Error::from_str("Operation timed out"))));
+        assert!(is_transient(&OafError::Network(Error::from_str("recv failure: Connection reset by peer"))));
+        assert!(!is_transient(&OafError::Network(Error::from_str("authentication required"))));
+        assert!(!is_transient(&OafError::Git(Error::from_str("timed out"))));
+        assert!(!is_transient(&OafError::Cancelled));
+    }
+
+    #[test]
+    fn retry_delay_doubles() {
+        let config = RetryConfig { retries: 3, backoff_millis: 500 };
+        assert_eq!(retry_delay(&config, 1), Duration::from_millis(500));
+        assert_eq!(retry_delay(&config, 3), Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn with_retries_retries_transient_failures_only() {
+        let config = RetryConfig { retries: 2, backoff_millis: 0 };
+        let cancel = Cancel::new();
+        let mut attempts = 0;
+        let mut retries = Vec::new();
+        let result = with_retries(&config, &cancel, || {
+            attempts += 1;
+            Err::<(), _>(OafError::Network(Error::from_str("timed out")))
+        }, |r| retries.push(r.retry));
+        assert!(result.is_err());
+        assert_eq!((attempts, retries), (3, vec![1, 2]));
+
+        let mut attempts = 0;
+        let result = with_retries(&config, &cancel, || {
+            attempts += 1;
+            if attempts == 1 { Err(OafError::Network(Error::from_str("timed out"))) } else { Ok(attempts) }
+        }, |_| {});
+        assert_eq!(result.unwrap(), 2);
+
+        let mut attempts = 0;
+        let _ = with_retries(&config, &cancel, || {
+            attempts += 1;
+            Err::<(), _>(OafError::Network(Error::from_str("authentication required")))
+        }, |_| {});
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn check_online_fails_when_offline() {
+        match check_online_when(true) {
+            Err(OafError::Network(_)) => {},
+            other => panic!("Expected a network error, got {:?}", other),
+        }
+        assert!(check_online_when(false).is_ok());
+    }
+}
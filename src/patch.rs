@@ -0,0 +1,459 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use git2::{self, Delta, Diff, DiffFile, DiffOptions, Oid, Repository};
+
+/// A line of a patch, classified so that it can be coloured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchLine {
+    /// A 'diff --git' header line, naming the file.
+    File(String),
+    /// A hunk header, e.g. '@@ -1,3 +1,4 @@'.
+    Hunk(String),
+    Context(String),
+    Added(String),
+    Removed(String),
+    /// Says how many unchanged lines lie between two hunks. PatchStream
+    /// never produces these; views add them.
+    Unchanged(String),
+}
+
+impl PatchLine {
+    fn new(origin: char, content: &str) -> Vec<PatchLine> {
+        let content = content.trim_end_matches('\n');
+        match origin {
+            'F' => content.lines().map(|l| PatchLine::File(l.to_string())).collect(),
+            'H' => vec![PatchLine::Hunk(content.to_string())],
+            '+' => vec![PatchLine::Added(format!("+{}", content))],
+            '-' => vec![PatchLine::Removed(format!("-{}", content))],
+            ' ' => vec![PatchLine::Context(format!(" {}", content))],
+            // The 'no newline at end of file' markers.
+            _ => vec![PatchLine::Context(content.to_string())],
+        }
+    }
+
+    /// A note of `count` unchanged lines.
+    pub fn unchanged(count: usize) -> PatchLine {
+        let lines = if count == 1 { "line" } else { "lines" };
+        PatchLine::Unchanged(format!("... {} unchanged {} ...", count, lines))
+    }
+
+    pub fn text(&self) -> &str {
+        match *self {
+            PatchLine::File(ref s) | PatchLine::Hunk(ref s) | PatchLine::Context(ref s)
+                | PatchLine::Added(ref s) | PatchLine::Removed(ref s) | PatchLine::Unchanged(ref s) => s,
+        }
+    }
+}
+
+/// Limits that stop a huge file or diff from being loaded into memory in
+/// full. `None` means no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffLimits {
+    /// Files bigger than this are not read, and are listed in
+    /// `Patch::skipped` instead.
+    pub max_file_bytes: Option<u64>,
+    /// The patch stops after this many lines and is marked as truncated.
+    pub max_lines: Option<usize>,
+    /// How many unchanged lines are shown around each change. Not a limit as
+    /// such, but it goes wherever the limits go.
+    pub context_lines: u32,
+}
+
+/// The context git shows around each change.
+pub const DEFAULT_CONTEXT_LINES: u32 = 3;
+
+impl DiffLimits {
+    /// No limits, for when the user asks to load a diff anyway.
+    pub fn unlimited() -> Self {
+        DiffLimits { max_file_bytes: None, max_lines: None, context_lines: DEFAULT_CONTEXT_LINES }
+    }
+}
+
+/// The lines of a patch, and what was left out of it because of the limits.
+/// The UI streams patches instead; this is for tests and benchmarks.
+#[cfg(any(test, feature = "bench"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Patch {
+    pub lines: Vec<PatchLine>,
+    /// True if the patch was cut off at `DiffLimits::max_lines`.
+    pub truncated: bool,
+    /// The files that were not diffed because they are too big, with the
+    /// size of the bigger side.
+    pub skipped: Vec<(PathBuf, u64)>,
+}
+
+/// The patch introduced by a commit, relative to its first parent. A root
+/// commit is shown as adding all its files.
+#[cfg(any(test, feature = "bench"))]
+pub fn commit_patch(repo: &Repository, oid: Oid, limits: DiffLimits) -> Result<Patch, ::git2::Error> {
+    let diff = commit_diff(repo, oid, limits)?;
+    PatchStream::new(&diff, limits).collect_patch()
+}
+
+/// The uncommitted changes to `path`, staged or not, relative to HEAD. An
+/// untracked file is shown as being added.
+#[cfg(any(test, feature = "bench"))]
+pub fn working_tree_patch(repo: &Repository, path: &Path, limits: DiffLimits) -> Result<Patch, ::git2::Error> {
+    let diff = working_tree_diff(repo, path, limits)?;
+    PatchStream::new(&diff, limits).collect_patch()
+}
+
+/// The diff behind `commit_patch`, for streaming with a `PatchStream`.
+pub fn commit_diff(repo: &Repository, oid: Oid, limits: DiffLimits) -> Result<Diff, ::git2::Error> {
+    let commit = repo.find_commit(oid)?;
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+
+    let mut opts = options(limits);
+    repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut opts))
+}
+
+/// The diff from the tree of commit `from` to that of commit `to`, for
+/// streaming with a `PatchStream`. For a three-dot comparison `from` is the
+/// merge base.
+pub fn compare_diff(repo: &Repository, from: Oid, to: Oid, limits: DiffLimits) -> Result<Diff, ::git2::Error> {
+    let old_tree = repo.find_commit(from)?.tree()?;
+    let new_tree = repo.find_commit(to)?.tree()?;
+    let mut opts = options(limits);
+    repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), Some(&mut opts))
+}
+
+/// The diff behind `working_tree_patch`, for streaming with a `PatchStream`.
+pub fn working_tree_diff<'repo>(repo: &'repo Repository, path: &Path, limits: DiffLimits) -> Result<Diff<'repo>, ::git2::Error> {
+    head_to_working_tree(repo, Some(path), limits)
+}
+
+/// All the uncommitted changes, staged or not, relative to HEAD, including
+/// untracked files.
+pub fn uncommitted_diff(repo: &Repository, limits: DiffLimits) -> Result<Diff, ::git2::Error> {
+    head_to_working_tree(repo, None, limits)
+}
+
+/// The staged changes to `path`: HEAD against the index.
+pub fn staged_diff<'repo>(repo: &'repo Repository, path: &Path, limits: DiffLimits) -> Result<Diff<'repo>, ::git2::Error> {
+    let head_tree = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(_) => None,
+    };
+
+    let mut opts = options(limits);
+    opts.pathspec(path);
+    repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut opts))
+}
+
+/// The changes to `path` that are not staged: the index against the
+/// working tree. An untracked file is shown as being added.
+pub fn unstaged_diff<'repo>(repo: &'repo Repository, path: &Path, limits: DiffLimits) -> Result<Diff<'repo>, ::git2::Error> {
+    let mut opts = options(limits);
+    opts.pathspec(path)
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+    repo.diff_index_to_workdir(None, Some(&mut opts))
+}
+
+fn head_to_working_tree<'repo>(repo: &'repo Repository, path: Option<&Path>, limits: DiffLimits) -> Result<Diff<'repo>, ::git2::Error> {
+    let head_tree = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(_) => None,
+    };
+
+    let mut opts = options(limits);
+    if let Some(path) = path {
+        opts.pathspec(path);
+    }
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+    repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut opts))
+}
+
+/// libgit2 does not read files over `max_size`; it treats them as binary.
+/// Zero would mean its own default of 512 MB, so no limit is the largest
+/// size instead.
+fn options(limits: DiffLimits) -> DiffOptions {
+    let mut opts = DiffOptions::new();
+    opts.context_lines(limits.context_lines);
+    opts.max_size(limits.max_file_bytes.map_or(i64::max_value(), |bytes| bytes.min(i64::max_value() as u64).max(1) as i64));
+    opts
+}
+
+/// Produces the lines of a patch a hunk at a time, only generating each
+/// file's patch when it is reached. This lets the start of a huge diff be
+/// shown straight away, and the rest be generated as it is scrolled to.
+pub struct PatchStream<'a, 'repo: 'a> {
+    diff: &'a Diff<'repo>,
+    limits: DiffLimits,
+    decode: LineDecoder,
+    /// The next file to generate.
+    delta: usize,
+    /// The patch of the current file, and the next hunk in it.
+    current: Option<(git2::Patch, usize)>,
+    lines: usize,
+    /// True if the stream stopped at `DiffLimits::max_lines`.
+    pub truncated: bool,
+    /// The files that were not diffed because they are too big.
+    pub skipped: Vec<(PathBuf, u64)>,
+}
+
+impl<'a, 'repo> PatchStream<'a, 'repo> {
+    pub fn new(diff: &'a Diff<'repo>, limits: DiffLimits) -> Self {
+        PatchStream { diff, limits, decode: lossy_line, delta: 0, current: None, lines: 0, truncated: false, skipped: Vec::new() }
+    }
+
+    /// Decodes the lines of each file with `decode` rather than as UTF-8, so
+    /// that the caller can choose the encoding by path.
+    pub fn with_decoder(mut self, decode: LineDecoder) -> Self {
+        self.decode = decode;
+        self
+    }
+
+    /// Generates the rest of the patch.
+    #[cfg(any(test, feature = "bench"))]
+    pub fn collect_patch(mut self) -> Result<Patch, ::git2::Error> {
+        let mut lines = Vec::new();
+        while let Some(chunk) = self.next() {
+            lines.extend(chunk?);
+        }
+        Ok(Patch { lines, truncated: self.truncated, skipped: self.skipped })
+    }
+
+    /// The file the last chunk was from, unless it was binary.
+    pub fn current_path(&self) -> Option<PathBuf> {
+        self.current.as_ref().and_then(|&(ref patch, _)| {
+            let delta = patch.delta();
+            delta.new_file().path().or_else(|| delta.old_file().path()).map(|p| p.to_path_buf())
+        })
+    }
+
+    /// The lines of the new version of the file the last chunk was from,
+    /// decoded as the patch's lines are: its blob or, in a diff against the
+    /// working tree, the file itself. Empty if the file was deleted or
+    /// cannot be read.
+    pub fn current_new_lines(&self, repo: &Repository) -> Vec<String> {
+        let patch = match self.current {
+            Some((ref patch, _)) => patch,
+            None => return Vec::new(),
+        };
+        let delta = patch.delta();
+        let file = delta.new_file();
+        let path = match file.path() {
+            Some(path) if delta.status() != Delta::Deleted => path,
+            _ => return Vec::new(),
+        };
+        let content = repo.find_blob(file.id()).map(|blob| blob.content().to_vec()).ok()
+            .or_else(|| repo.workdir().and_then(|dir| fs::read(dir.join(path)).ok()))
+            .unwrap_or_default();
+        if content.is_empty() {
+            return Vec::new();
+        }
+        let content = if content.ends_with(b"\n") { &content[..content.len() - 1] } else { &content[..] };
+        content.split(|&b| b == b'\n').map(|line| (self.decode)(line, path)).collect()
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<Vec<PatchLine>>, ::git2::Error> {
+        loop {
+            if let Some((ref patch, ref mut hunk)) = self.current {
+                if *hunk < patch.num_hunks() {
+                    *hunk += 1;
+                    return hunk_lines(patch, *hunk - 1, self.decode).map(Some);
+                }
+            }
+            self.current = None;
+
+            if self.delta >= self.diff.deltas().len() {
+                return Ok(None);
+            }
+            let index = self.delta;
+            self.delta += 1;
+
+            let header = match git2::Patch::from_diff(self.diff, index)? {
+                Some(mut patch) => {
+                    let header = self.file_header(&mut patch)?;
+                    self.current = Some((patch, 0));
+                    header
+                },
+                // libgit2 makes no patch for some binary files.
+                None => self.binary_header(index),
+            };
+            if !header.is_empty() {
+                return Ok(Some(header));
+            }
+        }
+    }
+
+    /// The lines before the first hunk: the 'diff --git' header and, for a
+    /// binary file, the line saying it differs.
+    fn file_header(&mut self, patch: &mut git2::Patch) -> Result<Vec<PatchLine>, ::git2::Error> {
+        let mut header = Vec::new();
+        let mut binary = false;
+        let mut reached_hunks = false;
+        let result = patch.print(&mut |_, hunk, line| {
+            if hunk.is_some() {
+                reached_hunks = true;
+                return false;
+            }
+            binary |= line.origin() == 'B';
+            header.extend(PatchLine::new(line.origin(), &String::from_utf8_lossy(line.content())));
+            true
+        });
+        // Stopping the print at the first hunk makes it return an error.
+        if !reached_hunks {
+            result?;
+        }
+
+        if binary {
+            let delta = patch.delta();
+            self.check_size(&delta.old_file(), &delta.new_file());
+        }
+        Ok(header)
+    }
+
+    fn binary_header(&mut self, index: usize) -> Vec<PatchLine> {
+        let delta = match self.diff.get_delta(index) {
+            Some(delta) => delta,
+            None => return Vec::new(),
+        };
+        if delta.status() == Delta::Unmodified {
+            return Vec::new();
+        }
+
+        let (old_file, new_file) = (delta.old_file(), delta.new_file());
+        self.check_size(&old_file, &new_file);
+        let old = old_file.path().unwrap_or_else(|| Path::new("")).display();
+        let new = new_file.path().unwrap_or_else(|| Path::new("")).display();
+        vec![PatchLine::File(format!("diff --git a/{} b/{}", old, new)),
+             PatchLine::Context(format!("Binary files a/{} and b/{} differ", old, new))]
+    }
+
+    /// A file over the size limit comes through as binary; records it if it
+    /// is one.
+    fn check_size(&mut self, old_file: &DiffFile, new_file: &DiffFile) {
+        let size = old_file.size().max(new_file.size());
+        if self.limits.max_file_bytes.map_or(false, |max| size > max) {
+            let path = new_file.path().or_else(|| old_file.path()).unwrap_or_else(|| Path::new(""));
+            self.skipped.push((path.to_path_buf(), size));
+        }
+    }
+}
+
+impl<'a, 'repo> Iterator for PatchStream<'a, 'repo> {
+    type Item = Result<Vec<PatchLine>, ::git2::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.truncated {
+            return None;
+        }
+
+        let mut chunk = match self.next_chunk() {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => return None,
+            Err(e) => {
+                // Don't keep generating after an error.
+                self.delta = self.diff.deltas().len();
+                self.current = None;
+                return Some(Err(e));
+            },
+        };
+
+        if let Some(max) = self.limits.max_lines {
+            if self.lines >= max {
+                self.truncated = true;
+                return None;
+            }
+            if self.lines + chunk.len() > max {
+                chunk.truncate(max - self.lines);
+                self.truncated = true;
+            }
+        }
+        self.lines += chunk.len();
+        Some(Ok(chunk))
+    }
+}
+
+/// Turns the bytes of a line of the file at the given path into text,
+/// without its line ending.
+pub type LineDecoder = fn(&[u8], &Path) -> String;
+
+/// Decodes a line as UTF-8, replacing anything invalid.
+fn lossy_line(bytes: &[u8], _path: &Path) -> String {
+    String::from_utf8_lossy(bytes).trim_end_matches('\n').trim_end_matches('\r').to_string()
+}
+
+fn hunk_lines(patch: &git2::Patch, hunk: usize, decode: LineDecoder) -> Result<Vec<PatchLine>, ::git2::Error> {
+    let (header, count) = patch.hunk(hunk)?;
+    let mut lines = PatchLine::new('H', &String::from_utf8_lossy(header.header()));
+    let delta = patch.delta();
+    let path = delta.new_file().path().or_else(|| delta.old_file().path()).unwrap_or_else(|| Path::new(""));
+    for n in 0..count {
+        let line = patch.line_in_hunk(hunk, n)?;
+        lines.extend(PatchLine::new(line.origin(), &decode(line.content(), path)));
+    }
+    Ok(lines)
+}
+
+/// The lines of the new file that a hunk covers, from its header, e.g.
+/// '@@ -1,3 +2,4 @@': the first, counting from 1, and how many. A hunk that
+/// only removes lines covers none, and its header names the line before it.
+pub fn hunk_new_lines(header: &str) -> Option<(usize, usize)> {
+    let range = header.split_whitespace().nth(2)?.trim_start_matches('+');
+    let mut parts = range.splitn(2, ',');
+    let start = parts.next()?.parse().ok()?;
+    let count = match parts.next() {
+        Some(count) => count.parse().ok()?,
+        None => 1,
+    };
+    Some(if count == 0 { (start + 1, 0) } else { (start, count) })
+}
+
+/// Formats a size in bytes for messages, e.g. '312.5 MB'.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_lines_by_origin() {
+        assert_eq!(PatchLine::new('F', "diff --git a/x b/x\nindex 1..2\n"),
+                   vec![PatchLine::File("diff --git a/x b/x".into()), PatchLine::File("index 1..2".into())]);
+        assert_eq!(PatchLine::new('H', "@@ -1 +1 @@\n"), vec![PatchLine::Hunk("@@ -1 +1 @@".into())]);
+        assert_eq!(PatchLine::new('+', "new\n"), vec![PatchLine::Added("+new".into())]);
+        assert_eq!(PatchLine::new('-', "old\n"), vec![PatchLine::Removed("-old".into())]);
+        assert_eq!(PatchLine::new(' ', "same\n")[0].text(), " same");
+    }
+
+    #[test]
+    fn hunk_new_lines_reads_the_header() {
+        assert_eq!(hunk_new_lines("@@ -1,3 +2,4 @@ fn main()"), Some((2, 4)));
+        assert_eq!(hunk_new_lines("@@ -1 +1 @@"), Some((1, 1)));
+        assert_eq!(hunk_new_lines("@@ -6 +5,0 @@"), Some((6, 0)));
+        assert_eq!(hunk_new_lines("Binary files differ"), None);
+        assert_eq!(PatchLine::unchanged(240).text(), "... 240 unchanged lines ...");
+    }
+
+    #[test]
+    fn format_size_picks_a_readable_unit() {
+        assert_eq!(format_size(0), "0 bytes");
+        assert_eq!(format_size(1023), "1023 bytes");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(300 * 1024 * 1024), "300.0 MB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
+}
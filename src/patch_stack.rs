@@ -0,0 +1,363 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{FixedOffset, TimeZone};
+use git2::{Error, Oid, Repository};
+use error::OafError;
+use history;
+use patch::{self, DiffLimits};
+use remotes;
+use rewrite;
+
+/// Stacks live under this prefix, one directory per branch, holding the
+/// upstream commit the patches apply to and the patches in order, e.g.
+/// 'refs/oaf/stack/master/base' and 'refs/oaf/stack/master/0001'.
+pub const STACK_PREFIX: &str = "refs/oaf/stack/";
+
+const BASE: &str = "base";
+
+/// A series of local patches kept on top of an upstream commit, in the
+/// manner of quilt. The patches are the commits of the branch after the base.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stack {
+    /// The short name of the branch.
+    pub branch: String,
+    pub base: Oid,
+    /// The patches, bottom (applied first) to top.
+    pub patches: Vec<Oid>,
+}
+
+impl Stack {
+    /// The commit at the top of the stack, which the branch should point at.
+    pub fn top(&self) -> Oid {
+        self.patches.last().cloned().unwrap_or(self.base)
+    }
+
+    fn ref_name(&self, name: &str) -> String {
+        format!("{}{}", directory(&self.branch), name)
+    }
+}
+
+/// The outcome of rebuilding a stack.
+#[derive(Debug, Clone)]
+pub struct Rebuilt {
+    pub stack: Stack,
+    /// Patches left out because the new base already contains their changes.
+    pub dropped: Vec<Oid>,
+    /// The name of the backup holding the branch's original tip.
+    pub backup: String,
+}
+
+fn directory(branch: &str) -> String {
+    format!("{}{}/", STACK_PREFIX, branch)
+}
+
+/// The refs of the stack of `branch`, as the part of their name after the
+/// branch's directory and their target.
+fn stack_refs(repo: &Repository, branch: &str) -> Result<Vec<(String, Oid)>, Error> {
+    let prefix = directory(branch);
+    let mut refs = Vec::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        match (reference.name(), reference.target()) {
+            // Names with a '/' belong to the stacks of branches nested below
+            // this one, such as 'master/x'.
+            (Some(name), Some(target)) if name.starts_with(&prefix) && !name[prefix.len()..].contains('/') =>
+                refs.push((name[prefix.len()..].to_string(), target)),
+            _ => {},
+        }
+    }
+    Ok(refs)
+}
+
+/// Loads the stack of the local branch `branch`, a short name, if it has one.
+pub fn load(repo: &Repository, branch: &str) -> Result<Option<Stack>, Error> {
+    let mut base = None;
+    let mut numbered = Vec::new();
+    for (name, target) in stack_refs(repo, branch)? {
+        if name == BASE {
+            base = Some(target);
+        } else if let Ok(number) = name.parse::<usize>() {
+            numbered.push((number, target));
+        }
+    }
+
+    numbered.sort();
+    Ok(base.map(|base| Stack { branch: branch.to_string(), base, patches: numbered.into_iter().map(|(_, oid)| oid).collect() }))
+}
+
+/// Writes the refs of `stack`, replacing any it had before. The new refs are
+/// written over the old ones before the left over ones are deleted, so the
+/// stack never goes missing part way through.
+fn save(repo: &Repository, stack: &Stack, reason: &str) -> Result<(), Error> {
+    let old = stack_refs(repo, &stack.branch)?;
+    let mut names = vec![BASE.to_string()];
+    for (i, &oid) in stack.patches.iter().enumerate() {
+        let name = format!("{:04}", i + 1);
+        repo.reference(&stack.ref_name(&name), oid, true, reason)?;
+        names.push(name);
+    }
+    repo.reference(&stack.ref_name(BASE), stack.base, true, reason)?;
+
+    for (name, _) in old.into_iter().filter(|&(ref name, _)| !names.contains(name)) {
+        repo.find_reference(&stack.ref_name(&name))?.delete()?;
+    }
+    Ok(())
+}
+
+/// Deletes the stack of `branch`, if it has one. The branch is not touched.
+pub fn delete(repo: &Repository, branch: &str) -> Result<(), Error> {
+    for (name, _) in stack_refs(repo, branch)? {
+        repo.find_reference(&format!("{}{}", directory(branch), name))?.delete()?;
+    }
+    Ok(())
+}
+
+/// Makes the commits of the current branch since it diverged from `base`
+/// into its patch stack, replacing any stack it had.
+pub fn create(repo: &Repository, base: Oid) -> Result<Stack, OafError> {
+    let branch = rewrite::head_branch(repo)?;
+    let tip = repo.refname_to_id(&branch)?;
+    let fork_point = repo.merge_base(base, tip)?;
+    let patches = rewrite::linear_range(repo, fork_point, tip)?;
+    if patches.is_empty() {
+        return Err(Error::from_str("The branch has no commits that are not already in the base.").into());
+    }
+
+    let stack = Stack { branch: remotes::short_branch_name(&branch).to_string(), base: fork_point, patches };
+    save(repo, &stack, "oaf: create patch stack")?;
+    info!("Created a stack of {} patches on {} for {}", stack.patches.len(), fork_point, stack.branch);
+    Ok(stack)
+}
+
+/// Loads the stack of the current branch, which must not have moved since
+/// the stack was made, because then the stack no longer describes it.
+pub fn current(repo: &Repository) -> Result<Option<Stack>, Error> {
+    let branch = rewrite::head_branch(repo)?;
+    let stack = match load(repo, remotes::short_branch_name(&branch))? {
+        Some(stack) => stack,
+        None => return Ok(None),
+    };
+
+    if repo.refname_to_id(&branch)? != stack.top() {
+        return Err(Error::from_str(&format!(
+            "'{}' has moved since its patch stack was made; make the stack again.", stack.branch)));
+    }
+    Ok(Some(stack))
+}
+
+fn require_current(repo: &Repository) -> Result<Stack, Error> {
+    current(repo)?.ok_or_else(|| Error::from_str("The current branch has no patch stack."))
+}
+
+/// Reapplies the patches of the current branch's stack in a new order,
+/// given as the positions of the patches in the old order.
+pub fn reorder(repo: &Repository, order: &[usize]) -> Result<Rebuilt, OafError> {
+    let stack = require_current(repo)?;
+    check_order(stack.patches.len(), order).map_err(|e| Error::from_str(&e))?;
+    let patches: Vec<Oid> = order.iter().map(|&i| stack.patches[i]).collect();
+    let base = stack.base;
+    rebuild(repo, &stack, &patches, base, "reorder patch stack")
+}
+
+/// Reapplies the patches of the current branch's stack on top of
+/// `new_base`, typically the latest upstream. Patches whose changes the new
+/// base already has are dropped.
+pub fn refresh(repo: &Repository, new_base: Oid) -> Result<Rebuilt, OafError> {
+    let stack = require_current(repo)?;
+    let patches = stack.patches.clone();
+    rebuild(repo, &stack, &patches, new_base, &format!("refresh patch stack onto {:.7}", new_base.to_string()))
+}
+
+/// Applies `patches` one by one onto `base`, like a cherry-pick but in
+/// memory, then moves the branch to the result and checks it out. Nothing
+/// is changed if a patch conflicts or the checkout would overwrite local
+/// changes. Authors and messages are kept; the current user is the committer.
+fn rebuild(repo: &Repository, stack: &Stack, patches: &[Oid], base: Oid, operation: &str) -> Result<Rebuilt, OafError> {
+    let _timer = timer!("patch_stack::rebuild");
+    let committer = repo.signature()?;
+    let mut top = repo.find_commit(base)?;
+    let mut applied = Vec::new();
+    let mut dropped = Vec::new();
+
+    for &oid in patches {
+        let commit = repo.find_commit(oid)?;
+        let mut merged = repo.merge_trees(&commit.parent(0)?.tree()?, &top.tree()?, &commit.tree()?, None)
+            .map_err(OafError::Merge)?;
+        if merged.has_conflicts() {
+            return Err(OafError::Merge(Error::from_str(&format!(
+                "Patch {:.7} '{}' does not apply cleanly; nothing has been changed.",
+                oid.to_string(), commit.summary().unwrap_or("")))));
+        }
+
+        let tree = repo.find_tree(merged.write_tree_to(repo)?)?;
+        if tree.id() == top.tree_id() {
+            dropped.push(oid);
+            continue;
+        }
+        let message = String::from_utf8_lossy(commit.message_bytes());
+        let new_oid = repo.commit(None, &commit.author(), &committer, &message, &tree, &[&top])?;
+        applied.push(new_oid);
+        top = repo.find_commit(new_oid)?;
+    }
+
+    let backup = rewrite::move_checked_out_branch(repo, &remotes::full_branch_ref(&stack.branch), top.id(), operation)?;
+    let rebuilt = Stack { branch: stack.branch.clone(), base, patches: applied };
+    save(repo, &rebuilt, &format!("oaf: {}", operation))?;
+
+    _timer.set_message(format!("Rebuilt {} with {} patches, dropped {}", stack.branch, rebuilt.patches.len(), dropped.len()));
+    Ok(Rebuilt { stack: rebuilt, dropped, backup })
+}
+
+/// Checks that `order` mentions each of `count` positions exactly once.
+fn check_order(count: usize, order: &[usize]) -> Result<(), String> {
+    let mut seen = vec![false; count];
+    for &i in order {
+        match seen.get_mut(i) {
+            Some(seen) if *seen => return Err(format!("Patch {} is listed more than once.", i + 1)),
+            Some(seen) => *seen = true,
+            None => return Err(format!("There is no patch {}.", i + 1)),
+        }
+    }
+    match seen.iter().position(|s| !s) {
+        Some(missing) => Err(format!("Patch {} is missing; patches can be reordered but not removed.", missing + 1)),
+        None => Ok(()),
+    }
+}
+
+/// Reads a new order for `patches` from `text`, one patch per line starting
+/// with its id or an abbreviation of it, as written by `order_text`. Blank
+/// lines are ignored. Returns the positions of the patches in the old order.
+pub fn parse_order(patches: &[Oid], text: &str) -> Result<Vec<usize>, String> {
+    let ids: Vec<String> = patches.iter().map(|p| p.to_string()).collect();
+    let mut order = Vec::new();
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let prefix = line.split_whitespace().next().unwrap_or("");
+        let matches: Vec<usize> = ids.iter().enumerate().filter(|&(_, id)| id.starts_with(prefix)).map(|(i, _)| i).collect();
+        match matches.len() {
+            1 => order.push(matches[0]),
+            0 => return Err(format!("'{}' is not one of the patches.", prefix)),
+            _ => return Err(format!("'{}' could be more than one patch.", prefix)),
+        }
+    }
+
+    check_order(patches.len(), &order)?;
+    Ok(order)
+}
+
+/// Lists the patches of `stack` one per line, bottom first, as an abbreviated
+/// id and summary, for editing into a new order.
+pub fn order_text(repo: &Repository, stack: &Stack) -> Result<String, Error> {
+    let mut text = String::new();
+    for &oid in &stack.patches {
+        let commit = repo.find_commit(oid)?;
+        text.push_str(&format!("{:.10} {}\n", oid.to_string(), commit.summary().unwrap_or("")));
+    }
+    Ok(text)
+}
+
+/// Writes the patches of `stack` to `dir` as numbered mail-style patch files,
+/// as 'git format-patch' does, plus a quilt 'series' file listing them in
+/// order. Each file is written in full before it replaces one of the same
+/// name. Returns the paths of the files written.
+pub fn export(repo: &Repository, stack: &Stack, dir: &Path) -> Result<Vec<PathBuf>, OafError> {
+    fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    let mut series = String::new();
+    for (i, &oid) in stack.patches.iter().enumerate() {
+        let commit = repo.find_commit(oid)?;
+        let name = format!("{:04}-{}.patch", i + 1, file_slug(commit.summary().unwrap_or("")));
+        let path = dir.join(&name);
+        write_replacing(&path, &format_patch(repo, oid, i + 1, stack.patches.len())?)?;
+        series.push_str(&name);
+        series.push('\n');
+        written.push(path);
+    }
+
+    let series_path = dir.join("series");
+    write_replacing(&series_path, &series)?;
+    written.push(series_path);
+    info!("Exported {} patches of {} to {}", stack.patches.len(), stack.branch, dir.display());
+    Ok(written)
+}
+
+/// Writes `contents` to a temporary file beside `path` and renames it over
+/// `path`, so an interrupted export never leaves a half written file.
+fn write_replacing(path: &Path, contents: &str) -> Result<(), OafError> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// The patch for one commit, with a mail header that 'git am' understands.
+fn format_patch(repo: &Repository, oid: Oid, number: usize, total: usize) -> Result<String, Error> {
+    let commit = repo.find_commit(oid)?;
+    let author = commit.author();
+    let when = author.when();
+    let date = history::recorded_time(&when).unwrap_or_else(|| FixedOffset::east(0).timestamp(0, 0)).to_rfc2822();
+    let message = String::from_utf8_lossy(commit.message_bytes()).into_owned();
+    let mut parts = message.splitn(2, '\n');
+    let summary = parts.next().unwrap_or("").trim();
+    let body = parts.next().unwrap_or("").trim();
+
+    let mut text = format!("From {} Mon Sep 17 00:00:00 2001\nFrom: {} <{}>\nDate: {}\nSubject: [PATCH {}/{}] {}\n\n",
+                           oid, author.name().unwrap_or(""), author.email().unwrap_or(""), date, number, total, summary);
+    if !body.is_empty() {
+        text.push_str(body);
+        text.push('\n');
+    }
+    text.push_str("---\n");
+    for line in patch::commit_patch(repo, oid, DiffLimits::unlimited())?.lines {
+        text.push_str(line.text());
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Makes a commit summary usable as part of a file name, as 'git
+/// format-patch' does: runs of other characters become a single '-', and
+/// the result is cut to a sensible length.
+fn file_slug(summary: &str) -> String {
+    const MAX_LEN: usize = 52;
+    let mut slug = String::new();
+    for c in summary.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.truncate(MAX_LEN);
+    let slug = slug.trim_matches(|c| c == '-' || c == '.').to_string();
+    if slug.is_empty() { "patch".to_string() } else { slug }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_slug_works() {
+        assert_eq!(file_slug("Fix the log: don't crash!"), "Fix-the-log-don-t-crash");
+        assert_eq!(file_slug("  ...  "), "patch");
+        assert_eq!(file_slug(&"x".repeat(80)).len(), 52);
+    }
+
+    #[test]
+    fn parse_order_accepts_a_permutation() {
+        let patches = vec![Oid::from_str("aaaa1111").unwrap(), Oid::from_str("bbbb2222").unwrap(), Oid::from_str("cccc3333").unwrap()];
+        assert_eq!(parse_order(&patches, "cccc Third\n\naaaa1 First\nbbbb Second\n"), Ok(vec![2, 0, 1]));
+        assert!(parse_order(&patches, "cccc\naaaa\n").is_err());
+        assert!(parse_order(&patches, "cccc\naaaa\naaaa\nbbbb\n").is_err());
+        assert!(parse_order(&patches, "dddd\naaaa\nbbbb\n").is_err());
+    }
+
+    #[test]
+    fn check_order_rejects_out_of_range() {
+        assert!(check_order(2, &[1, 0]).is_ok());
+        assert!(check_order(2, &[0, 2]).is_err());
+    }
+}
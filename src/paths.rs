@@ -1,36 +1,85 @@
 use directories;
 use built_info;
+use startup_error::StartupError;
+use std::env;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use trash;
+
+const MRU_FILE: &str = "mru.txt";
+
+/// Environment variables that replace the config and data directories, as
+/// the '--config-dir' and '--data-dir' options do. They make it possible to
+/// keep separate profiles, or to run oaf without touching the real ones.
+pub const CONFIG_DIR_VAR: &str = "OAF_CONFIG_DIR";
+pub const DATA_DIR_VAR: &str = "OAF_DATA_DIR";
 
 /// The set of well-known directories and filenames that the program needs
-/// to refer to. We expect to have a valid HOME directory, we can't run
+/// to refer to. They follow the platform's conventions, which on Linux are
+/// the XDG ones: settings the user edits are in the config directory
+/// (~/.config/oaf), files oaf keeps for itself in the data directory
+/// (~/.local/share/oaf) and files that can be thrown away in the cache
+/// directory (~/.cache/oaf). We expect to have a valid HOME directory, we can't run
 /// otherwise (though at the time of writing this is only really because
 /// we are doing '~' expansion and contraction in the mru file.
+/// On Windows the home directory is the profile folder (USERPROFILE) and the
+/// files are kept under the roaming AppData folder, e.g.
+/// 'C:\Users\bob\AppData\Roaming\oaf\config\config.toml'.
 #[derive(Debug)]
 pub struct WellKnownPaths {
     home_dir: PathBuf,
     config_dir: PathBuf,
+    cache_dir: PathBuf,
     logging_config_file: PathBuf,
+    config_file: PathBuf,
     mru_file: PathBuf,
+    trash_dir: PathBuf,
+    session_file: PathBuf,
+    recent_commits_file: PathBuf,
+    reviews_file: PathBuf,
+    control_socket_file: PathBuf,
 }
 
 impl WellKnownPaths {
-    pub fn new() -> Self {
-        let bd = directories::BaseDirs::new()
-            .expect("Cannot determine location of HOME directory, terminating.");
-        let pd = directories::ProjectDirs::from("", "", built_info::PKG_NAME)
-            .expect("Cannot determine location of HOME directory, terminating.");
-
-        let home_dir = bd.home_dir().to_path_buf();
-        let config_dir = pd.config_dir().to_path_buf();
-        let mru_file = config_dir.join("mru.txt");
+    /// Finds the platform's directories, except where OAF_CONFIG_DIR or
+    /// OAF_DATA_DIR name others. When the data directory is replaced, the
+    /// cache goes in it too, so that nothing is shared with the real one.
+    pub fn new() -> Result<Self, StartupError> {
+        let bd = directories::BaseDirs::new().ok_or(StartupError::NoHomeDirectory)?;
+        let pd = directories::ProjectDirs::from("", "", built_info::PKG_NAME).ok_or(StartupError::NoHomeDirectory)?;
+
+        let config_dir = dir_from_env(CONFIG_DIR_VAR).unwrap_or_else(|| pd.config_dir().to_path_buf());
+        let (data_dir, cache_dir) = match dir_from_env(DATA_DIR_VAR) {
+            Some(dir) => (dir.clone(), dir.join("cache")),
+            None => (pd.data_dir().to_path_buf(), pd.cache_dir().to_path_buf()),
+        };
+
+        Ok(Self::from_dirs(bd.home_dir().to_path_buf(), config_dir, &data_dir, cache_dir))
+    }
+
+    fn from_dirs(home_dir: PathBuf, config_dir: PathBuf, data_dir: &Path, cache_dir: PathBuf) -> Self {
         let logging_config_file = config_dir.join("logging.toml");
+        let config_file = config_dir.join("config.toml");
+        let mru_file = data_dir.join(MRU_FILE);
+        let trash_dir = data_dir.join("trash");
+        let session_file = data_dir.join("session.toml");
+        let recent_commits_file = data_dir.join("recent_commits.txt");
+        let reviews_file = data_dir.join("reviews.toml");
+        let control_socket_file = data_dir.join("control.sock");
 
         WellKnownPaths {
             home_dir,
             config_dir,
+            cache_dir,
             mru_file,
-            logging_config_file
+            logging_config_file,
+            config_file,
+            trash_dir,
+            session_file,
+            recent_commits_file,
+            reviews_file,
+            control_socket_file,
         }
     }
 
@@ -42,13 +91,86 @@ impl WellKnownPaths {
 //        &self.config_dir
 //    }
 
+    /// Where files that can be regenerated are kept. Nothing is cached on
+    /// disk yet.
+    #[allow(dead_code)]
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
     pub fn logging_config_file(&self) -> &Path {
         &self.logging_config_file
     }
 
+    pub fn config_file(&self) -> &Path {
+        &self.config_file
+    }
+
     pub fn mru_file(&self) -> &Path {
         &self.mru_file
     }
+
+    /// Where discarded working tree files are kept so that they can be restored.
+    pub fn trash_dir(&self) -> &Path {
+        &self.trash_dir
+    }
+
+    /// Where the UI layout is saved between runs.
+    pub fn session_file(&self) -> &Path {
+        &self.session_file
+    }
+
+    /// Where the commits whose details were shown most recently are kept.
+    pub fn recent_commits_file(&self) -> &Path {
+        &self.recent_commits_file
+    }
+
+    /// Where the review marks on commits are kept. They are the user's own
+    /// notes, so they are never written into the repositories.
+    pub fn reviews_file(&self) -> &Path {
+        &self.reviews_file
+    }
+
+    /// Where a single instance of oaf listens for requests to open
+    /// repositories.
+    pub fn control_socket_file(&self) -> &Path {
+        &self.control_socket_file
+    }
+
+    /// Moves files that older versions kept in the config directory to where
+    /// they now belong. A file is left where it is if there is already one
+    /// in the new place. Failures are logged and otherwise ignored, as none
+    /// of the files is essential.
+    pub fn migrate(&self) {
+        let moves = [(self.config_dir.join(MRU_FILE), &self.mru_file)];
+        for &(ref old, new) in &moves {
+            match migrate_file(old, new) {
+                Ok(true) => info!("Moved '{}' to '{}'", old.display(), new.display()),
+                Ok(false) => {},
+                Err(e) => warn!("Error moving '{}' to '{}', ignoring. Error = {}", old.display(), new.display(), e),
+            }
+        }
+    }
+}
+
+/// The directory named by the environment variable `var`, if it is set and
+/// not empty.
+fn dir_from_env(var: &str) -> Option<PathBuf> {
+    env::var_os(var).filter(|dir| !dir.is_empty()).map(PathBuf::from)
+}
+
+/// Moves `old` to `new` if `old` exists and `new` does not. Returns whether
+/// it was moved.
+fn migrate_file(old: &Path, new: &Path) -> io::Result<bool> {
+    if !old.exists() || new.exists() {
+        return Ok(false);
+    }
+
+    if let Some(dir) = new.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    trash::move_file(old, new)?;
+    Ok(true)
 }
 
 // TODO: Can we use Cow here?
@@ -63,10 +185,22 @@ pub fn to_canon<P>(path: P) -> PathBuf
     where P: AsRef<Path>
 {
     let path = path.as_ref().to_path_buf();
-    let path = path.canonicalize().unwrap_or(path);
+    let path = path.canonicalize().map(strip_verbatim).unwrap_or(path);
     compress_tilde(path)
 }
 
+/// On Windows, canonicalizing gives a verbatim path such as '\\?\C:\src',
+/// which many programs do not accept and which does not match the home
+/// directory. The prefix is taken off drive and network share paths.
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    let stripped = match path.to_str() {
+        Some(text) if text.starts_with(r"\\?\UNC\") => format!(r"\\{}", &text[8..]),
+        Some(text) if text.starts_with(r"\\?\") && text[4..].chars().nth(1) == Some(':') => text[4..].to_string(),
+        _ => return path,
+    };
+    PathBuf::from(stripped)
+}
+
 /// The inverse of `path_to_canon`. Use this to transform stored paths into
 /// 'proper' paths that the program can use.
 pub fn from_canon<P>(path: P) -> PathBuf
@@ -99,7 +233,7 @@ fn compress_tilde_impl<P, Q>(path: P, home: Q) -> PathBuf
 {
     let home = home.as_ref();
     let path = path.as_ref().to_path_buf();
-    if path.starts_with(home) {
+    if starts_with_dir(&path, home) {
         let mut result = PathBuf::from("~");
         for comp in path.components().skip(home.components().count()) {
             result.push(comp);
@@ -110,6 +244,18 @@ fn compress_tilde_impl<P, Q>(path: P, home: Q) -> PathBuf
     path
 }
 
+/// Whether `path` is `dir` or inside it. File names on Windows are not case
+/// sensitive, and the home directory is not always spelt the same way, so
+/// neither is the comparison there.
+fn starts_with_dir(path: &Path, dir: &Path) -> bool {
+    if cfg!(windows) {
+        let lower = |p: &Path| PathBuf::from(p.to_string_lossy().to_lowercase());
+        lower(path).starts_with(lower(dir))
+    } else {
+        path.starts_with(dir)
+    }
+}
+
 /// Inner helper function to make things testable.
 fn expand_tilde_impl<P, Q>(path: P, home: Q) -> PathBuf
     where P: AsRef<Path>,
@@ -186,6 +332,56 @@ mod tests {
         assert_eq!(expand_tilde_impl("~/", home), PathBuf::from("/home/heart/"));
         assert_eq!(expand_tilde_impl("~/pics", home), PathBuf::from("/home/heart/pics"));
     }
+
+    #[test]
+    fn files_are_split_between_config_and_data_dirs() {
+        let paths = WellKnownPaths::from_dirs(PathBuf::from("/home/heart"), PathBuf::from("/profile/config"),
+                                              Path::new("/profile/data"), PathBuf::from("/profile/data/cache"));
+        assert_eq!(paths.config_file(), Path::new("/profile/config/config.toml"));
+        assert_eq!(paths.logging_config_file(), Path::new("/profile/config/logging.toml"));
+        assert_eq!(paths.mru_file(), Path::new("/profile/data/mru.txt"));
+        assert_eq!(paths.session_file(), Path::new("/profile/data/session.toml"));
+        assert_eq!(paths.recent_commits_file(), Path::new("/profile/data/recent_commits.txt"));
+        assert_eq!(paths.reviews_file(), Path::new("/profile/data/reviews.toml"));
+        assert_eq!(paths.trash_dir(), Path::new("/profile/data/trash"));
+        assert_eq!(paths.control_socket_file(), Path::new("/profile/data/control.sock"));
+    }
+
+    #[test]
+    fn migrate_file_moves_only_when_there_is_nothing_to_overwrite() {
+        let root = ::std::env::temp_dir().join(format!("oaf-paths-test-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (old, new) = (root.join("config/mru.txt"), root.join("data/mru.txt"));
+        fs::create_dir_all(root.join("config")).unwrap();
+
+        assert!(!migrate_file(&old, &new).unwrap());
+        fs::write(&old, "~/repo").unwrap();
+        assert!(migrate_file(&old, &new).unwrap());
+        assert!(!old.exists());
+        assert_eq!(fs::read_to_string(&new).unwrap(), "~/repo");
+
+        fs::write(&old, "older").unwrap();
+        assert!(!migrate_file(&old, &new).unwrap());
+        assert_eq!(fs::read_to_string(&new).unwrap(), "~/repo");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn strip_verbatim_works() {
+        assert_eq!(strip_verbatim(PathBuf::from(r"\\?\C:\src\oaf")), PathBuf::from(r"C:\src\oaf"));
+        assert_eq!(strip_verbatim(PathBuf::from(r"\\?\UNC\server\share\oaf")), PathBuf::from(r"\\server\share\oaf"));
+        assert_eq!(strip_verbatim(PathBuf::from(r"\\?\Volume{1234}\oaf")), PathBuf::from(r"\\?\Volume{1234}\oaf"));
+        assert_eq!(strip_verbatim(PathBuf::from("/home/heart")), PathBuf::from("/home/heart"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn tildes_work_with_windows_paths() {
+        let home = r"C:\Users\Heart";
+        assert_eq!(compress_tilde_impl(r"c:\users\heart\pics", home), PathBuf::from(r"~\pics"));
+        assert_eq!(compress_tilde_impl(r"C:\Users\Bob", home), PathBuf::from(r"C:\Users\Bob"));
+        assert_eq!(expand_tilde_impl(r"~\pics", home), PathBuf::from(r"C:\Users\Heart\pics"));
+    }
 }
 
 
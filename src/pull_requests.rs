@@ -0,0 +1,316 @@
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use git2::{build::CheckoutBuilder, ObjectType, Repository};
+use serde_json;
+use error::OafError;
+use forge::RemoteLocation;
+use network;
+use remotes::{self, TagFetch};
+
+/// The forges whose APIs we know how to talk to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForgeKind {
+    GitHub,
+    GitLab,
+}
+
+impl ForgeKind {
+    /// Works out the kind of forge from its host name. Self-hosted GitLab
+    /// instances are recognised if 'gitlab' appears in the host name.
+    pub fn from_host(host: &str) -> Option<ForgeKind> {
+        if host == "github.com" {
+            Some(ForgeKind::GitHub)
+        } else if host.contains("gitlab") {
+            Some(ForgeKind::GitLab)
+        } else {
+            None
+        }
+    }
+}
+
+/// The combined state of the CI checks on a pull request's head commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CiStatus {
+    Unknown,
+    Pending,
+    Success,
+    Failure,
+}
+
+impl fmt::Display for CiStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CiStatus::Unknown => write!(f, "-"),
+            CiStatus::Pending => write!(f, "pending"),
+            CiStatus::Success => write!(f, "passed"),
+            CiStatus::Failure => write!(f, "failed"),
+        }
+    }
+}
+
+/// An open pull request (GitHub) or merge request (GitLab).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRequest {
+    /// The number shown in the forge's UI, e.g. '#12' or '!12'.
+    pub number: u64,
+    pub title: String,
+    pub author: String,
+    /// The name of the branch the changes are on, in the source repository.
+    pub branch: String,
+    pub head_sha: String,
+    pub ci_status: CiStatus,
+}
+
+/// Lists the open pull requests for the repository at `location`, including
+/// the CI status of each one.
+pub fn list(location: &RemoteLocation, token: &str) -> Result<Vec<PullRequest>, String> {
+    let _timer = timer!("pull_requests::list");
+    network::check_online().map_err(|e| e.to_string())?;
+    let kind = ForgeKind::from_host(&location.host)
+        .ok_or_else(|| format!("Pull requests are not supported for '{}'.", location.host))?;
+
+    let mut requests = match kind {
+        ForgeKind::GitHub => {
+            let url = format!("https://api.github.com/repos/{}/pulls?state=open&per_page=50", location.path);
+            parse_github_pulls(&http_get(kind, &url, token)?)?
+        },
+        ForgeKind::GitLab => {
+            let url = format!("{}/merge_requests?state=opened&per_page=50", gitlab_project_url(location));
+            parse_gitlab_merge_requests(&http_get(kind, &url, token)?)?
+        },
+    };
+
+    for pr in &mut requests {
+        pr.ci_status = match ci_status(kind, location, token, &pr.head_sha) {
+            Ok(status) => status,
+            Err(e) => {
+                warn!("Could not get CI status of {}. Error = {}", pr.head_sha, e);
+                CiStatus::Unknown
+            }
+        };
+    }
+
+    _timer.set_message(format!("{} open pull requests for {}", requests.len(), location.path));
+    Ok(requests)
+}
+
+/// Fetches the head of a pull request from `remote_name` into a local branch
+/// and checks it out. Returns the name of the local branch.
+pub fn checkout(repo: &Repository, remote_name: &str, kind: ForgeKind, number: u64) -> Result<String, OafError> {
+    let (source, branch) = match kind {
+        ForgeKind::GitHub => (format!("refs/pull/{}/head", number), format!("pr/{}", number)),
+        ForgeKind::GitLab => (format!("refs/merge-requests/{}/head", number), format!("mr/{}", number)),
+    };
+
+    let target = remotes::full_branch_ref(&branch);
+    let refspec = format!("+{}:{}", source, target);
+    remotes::fetch_refspecs(repo, remote_name, &[&refspec], TagFetch::Auto)?;
+
+    let commit = repo.find_reference(&target)?.peel(ObjectType::Commit)?;
+    repo.checkout_tree(&commit, Some(CheckoutBuilder::new().safe()))?;
+    repo.set_head(&target)?;
+    Ok(branch)
+}
+
+fn ci_status(kind: ForgeKind, location: &RemoteLocation, token: &str, sha: &str) -> Result<CiStatus, String> {
+    match kind {
+        ForgeKind::GitHub => {
+            let url = format!("https://api.github.com/repos/{}/commits/{}/status", location.path, sha);
+            parse_github_status(&http_get(kind, &url, token)?)
+        },
+        ForgeKind::GitLab => {
+            let url = format!("{}/repository/commits/{}", gitlab_project_url(location), sha);
+            parse_gitlab_commit_status(&http_get(kind, &url, token)?)
+        },
+    }
+}
+
+/// GitLab identifies projects by their URL-encoded path.
+fn gitlab_project_url(location: &RemoteLocation) -> String {
+    format!("https://{}/api/v4/projects/{}", location.host, location.path.replace('/', "%2F"))
+}
+
+/// Performs an HTTP GET by running curl. The authorization header is passed
+/// on stdin so that the token does not appear in the process list.
+fn http_get(kind: ForgeKind, url: &str, token: &str) -> Result<String, String> {
+    debug!("GET {}", url);
+    let header = match kind {
+        ForgeKind::GitHub => format!("Authorization: token {}\n", token),
+        ForgeKind::GitLab => format!("PRIVATE-TOKEN: {}\n", token),
+    };
+
+    let mut child = Command::new("curl")
+        .args(&["--silent", "--show-error", "--fail", "--location", "--header", "@-"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run curl: {}", e))?;
+
+    if let Some(stdin) = child.stdin.as_mut() {
+        let _ = stdin.write_all(header.as_bytes());
+    }
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubHead {
+    #[serde(rename = "ref")]
+    branch: String,
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubPull {
+    number: u64,
+    title: String,
+    user: GitHubUser,
+    head: GitHubHead,
+}
+
+#[derive(Deserialize)]
+struct GitHubStatus {
+    state: String,
+    total_count: u64,
+}
+
+#[derive(Deserialize)]
+struct GitLabUser {
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabMergeRequest {
+    iid: u64,
+    title: String,
+    author: GitLabUser,
+    source_branch: String,
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabPipeline {
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabCommit {
+    last_pipeline: Option<GitLabPipeline>,
+}
+
+fn parse_github_pulls(json: &str) -> Result<Vec<PullRequest>, String> {
+    let pulls: Vec<GitHubPull> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    Ok(pulls.into_iter().map(|p| PullRequest {
+        number: p.number,
+        title: p.title,
+        author: p.user.login,
+        branch: p.head.branch,
+        head_sha: p.head.sha,
+        ci_status: CiStatus::Unknown,
+    }).collect())
+}
+
+fn parse_github_status(json: &str) -> Result<CiStatus, String> {
+    let status: GitHubStatus = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    // GitHub reports 'pending' for commits that have no statuses at all.
+    if status.total_count == 0 {
+        return Ok(CiStatus::Unknown);
+    }
+
+    Ok(match status.state.as_str() {
+        "success" => CiStatus::Success,
+        "pending" => CiStatus::Pending,
+        "failure" | "error" => CiStatus::Failure,
+        _ => CiStatus::Unknown,
+    })
+}
+
+fn parse_gitlab_merge_requests(json: &str) -> Result<Vec<PullRequest>, String> {
+    let requests: Vec<GitLabMergeRequest> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    Ok(requests.into_iter().map(|r| PullRequest {
+        number: r.iid,
+        title: r.title,
+        author: r.author.username,
+        branch: r.source_branch,
+        head_sha: r.sha,
+        ci_status: CiStatus::Unknown,
+    }).collect())
+}
+
+fn parse_gitlab_commit_status(json: &str) -> Result<CiStatus, String> {
+    let commit: GitLabCommit = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    Ok(match commit.last_pipeline.as_ref().map(|p| p.status.as_str()) {
+        Some("success") => CiStatus::Success,
+        Some("created") | Some("waiting_for_resource") | Some("preparing") | Some("pending") | Some("running") => CiStatus::Pending,
+        Some("failed") | Some("canceled") => CiStatus::Failure,
+        _ => CiStatus::Unknown,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_host_works() {
+        assert_eq!(ForgeKind::from_host("github.com"), Some(ForgeKind::GitHub));
+        assert_eq!(ForgeKind::from_host("gitlab.com"), Some(ForgeKind::GitLab));
+        assert_eq!(ForgeKind::from_host("gitlab.example.org"), Some(ForgeKind::GitLab));
+        assert_eq!(ForgeKind::from_host("bitbucket.org"), None);
+    }
+
+    #[test]
+    fn parse_github_pulls_works() {
+        let json = r#"[{"number": 7, "title": "Fix it", "user": {"login": "bob"},
+                        "head": {"ref": "fix-it", "sha": "abc", "repo": null}, "state": "open"}]"#;
+        let pulls = parse_github_pulls(json).unwrap();
+        assert_eq!(pulls.len(), 1);
+        assert_eq!(pulls[0].number, 7);
+        assert_eq!(pulls[0].author, "bob");
+        assert_eq!(pulls[0].branch, "fix-it");
+        assert_eq!(pulls[0].head_sha, "abc");
+    }
+
+    #[test]
+    fn parse_github_status_works() {
+        assert_eq!(parse_github_status(r#"{"state": "success", "total_count": 2}"#), Ok(CiStatus::Success));
+        assert_eq!(parse_github_status(r#"{"state": "error", "total_count": 1}"#), Ok(CiStatus::Failure));
+        assert_eq!(parse_github_status(r#"{"state": "pending", "total_count": 0}"#), Ok(CiStatus::Unknown));
+        assert!(parse_github_status("not json").is_err());
+    }
+
+    #[test]
+    fn parse_gitlab_merge_requests_works() {
+        let json = r#"[{"iid": 3, "title": "Add it", "author": {"username": "sue"},
+                        "source_branch": "add-it", "sha": "def"}]"#;
+        let requests = parse_gitlab_merge_requests(json).unwrap();
+        assert_eq!(requests[0].number, 3);
+        assert_eq!(requests[0].author, "sue");
+        assert_eq!(requests[0].branch, "add-it");
+    }
+
+    #[test]
+    fn parse_gitlab_commit_status_works() {
+        assert_eq!(parse_gitlab_commit_status(r#"{"last_pipeline": {"status": "running"}}"#), Ok(CiStatus::Pending));
+        assert_eq!(parse_gitlab_commit_status(r#"{"last_pipeline": null}"#), Ok(CiStatus::Unknown));
+    }
+
+    #[test]
+    fn gitlab_project_url_encodes_path() {
+        let location = RemoteLocation { host: "gitlab.com".to_string(), path: "group/sub/proj".to_string() };
+        assert_eq!(gitlab_project_url(&location), "https://gitlab.com/api/v4/projects/group%2Fsub%2Fproj");
+    }
+}
@@ -0,0 +1,151 @@
+//! What a push is about to do, for the user to review before it happens.
+
+use std::fmt;
+use git2::{Error, Oid, Repository};
+use changelog;
+use remotes::{self, UpstreamSpec};
+
+/// Something about a push the user should know before confirming it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The remote branch has commits the local branch does not, so this is
+    /// a force push: it would throw those commits away, and is rejected
+    /// unless forced.
+    ForcePush { behind: usize },
+    /// The destination matches one of the 'protected_branches' patterns.
+    Protected { pattern: String },
+    /// 'commit.gpgSign' is set but some commits are not signed.
+    Unsigned { count: usize },
+    /// The branch is new on the remote.
+    NewBranch,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::ForcePush { behind } =>
+                write!(f, "This is a force push: the remote branch has {} commits that are not on yours, which it would \
+                           throw away. The remote will reject it unless it is forced; pull first to keep them.", behind),
+            Warning::Protected { ref pattern } =>
+                write!(f, "The destination is a protected branch (matches '{}').", pattern),
+            Warning::Unsigned { count } =>
+                write!(f, "{} commits are not signed, although commit.gpgSign is set.", count),
+            Warning::NewBranch => write!(f, "This creates a new branch on the remote."),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutgoingCommit {
+    pub oid: Oid,
+    pub summary: String,
+    pub signed: bool,
+}
+
+/// A summary of a push of a local branch to its upstream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Review {
+    pub branch: String,
+    pub upstream: UpstreamSpec,
+    /// Newest first.
+    pub commits: Vec<OutgoingCommit>,
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub warnings: Vec<Warning>,
+}
+
+impl Review {
+    pub fn description(&self) -> String {
+        let mut text = format!("Push '{}' to '{}/{}'\n\n", self.branch, self.upstream.remote, self.upstream.branch);
+        text += &format!("{} commits, {} files changed, {} insertions(+), {} deletions(-)\n",
+                         self.commits.len(), self.files_changed, self.insertions, self.deletions);
+        for commit in &self.commits {
+            text += &format!("  {:.7} {}{}\n", commit.oid.to_string(), commit.summary, if commit.signed { "" } else { " (unsigned)" });
+        }
+        if !self.warnings.is_empty() {
+            text += "\nWarnings:\n";
+            for warning in &self.warnings {
+                text += &format!("  ! {}\n", warning);
+            }
+        }
+        text
+    }
+}
+
+/// Works out what pushing `branch` to `upstream` will send and what could go
+/// wrong. `protected` holds patterns ('*' is a wildcard) of branch names
+/// that should not normally be pushed to directly. The totals are those of
+/// one diff, from where the branch left the remote branch to its tip, so
+/// changes made and then undone by the outgoing commits are not counted.
+pub fn review(repo: &Repository, branch: &str, upstream: &UpstreamSpec, protected: &[String]) -> Result<Review, Error> {
+    let oids = remotes::outgoing_commits(repo, branch, upstream)?;
+    let mut commits = Vec::new();
+    for &oid in &oids {
+        let commit = repo.find_commit(oid)?;
+        commits.push(OutgoingCommit {
+            oid,
+            summary: commit.summary().unwrap_or("").to_string(),
+            signed: repo.extract_signature(&oid, None).is_ok(),
+        });
+    }
+
+    let mut warnings = Vec::new();
+    let local = repo.revparse_single(&remotes::full_branch_ref(branch))?.id();
+    let remote = repo.find_reference(&format!("refs/remotes/{}/{}", upstream.remote, upstream.branch)).ok().and_then(|r| r.target());
+    let base = match remote {
+        Some(remote) => {
+            let (_, behind) = repo.graph_ahead_behind(local, remote)?;
+            if behind > 0 {
+                warnings.push(Warning::ForcePush { behind });
+            }
+            repo.merge_base(local, remote).ok()
+        },
+        None => {
+            warnings.push(Warning::NewBranch);
+            // The oldest outgoing commit's parent is already on the remote.
+            match oids.last() {
+                Some(&oldest) => repo.find_commit(oldest)?.parent_ids().next(),
+                None => Some(local),
+            }
+        },
+    };
+    let base_tree = match base {
+        Some(base) => Some(repo.find_commit(base)?.tree()?),
+        None => None,
+    };
+    let stats = repo.diff_tree_to_tree(base_tree.as_ref(), Some(&repo.find_commit(local)?.tree()?), None)?.stats()?;
+    let (files_changed, insertions, deletions) = (stats.files_changed(), stats.insertions(), stats.deletions());
+
+    if let Some(pattern) = protected.iter().find(|p| changelog::wildcard_match(p, &upstream.branch)) {
+        warnings.push(Warning::Protected { pattern: pattern.clone() });
+    }
+    let unsigned = commits.iter().filter(|c| !c.signed).count();
+    if unsigned > 0 && repo.config().and_then(|c| c.get_bool("commit.gpgSign")).unwrap_or(false) {
+        warnings.push(Warning::Unsigned { count: unsigned });
+    }
+
+    Ok(Review { branch: branch.to_string(), upstream: upstream.clone(), commits, files_changed, insertions, deletions, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description_lists_commits_and_warnings() {
+        let review = Review {
+            branch: "feature".to_string(),
+            upstream: UpstreamSpec::new("origin", "main"),
+            commits: vec![OutgoingCommit { oid: Oid::zero(), summary: "Add a".to_string(), signed: false }],
+            files_changed: 1,
+            insertions: 3,
+            deletions: 0,
+            warnings: vec![Warning::Protected { pattern: "main".to_string() }],
+        };
+        assert_eq!(review.description(), "Push 'feature' to 'origin/main'\n\n\
+                                          1 commits, 1 files changed, 3 insertions(+), 0 deletions(-)\n  \
+                                          0000000 Add a (unsigned)\n\nWarnings:\n  \
+                                          ! The destination is a protected branch (matches 'main').\n");
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashSet;
+use git2::{Commit, DiffFormat, Oid, Repository, Sort};
+
+/// A pair is only matched if the patches differ by less than this percentage
+/// of the cost of removing one and adding the other; git's default.
+const CREATION_FACTOR: usize = 60;
+
+/// Beyond this many cells the line diff is not worth the time and memory, and
+/// the two texts are treated as completely different.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// How a commit in the old series relates to one in the new series. The
+/// markers are the ones 'git range-diff' uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// The patch is identical.
+    Unchanged,
+    /// The commit was rewritten, e.g. after review.
+    Modified,
+    /// The commit is only in the old series.
+    Removed,
+    /// The commit is only in the new series.
+    Added,
+}
+
+impl Change {
+    pub fn marker(&self) -> char {
+        match *self {
+            Change::Unchanged => '=',
+            Change::Modified => '!',
+            Change::Removed => '<',
+            Change::Added => '>',
+        }
+    }
+}
+
+/// A line in the difference between two texts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+impl DiffLine {
+    pub fn text(&self) -> &str {
+        match *self {
+            DiffLine::Same(ref s) | DiffLine::Removed(ref s) | DiffLine::Added(ref s) => s,
+        }
+    }
+}
+
+/// One line of the range-diff: a commit from either or both series.
+#[derive(Debug, Clone)]
+pub struct RangeDiffEntry {
+    /// The 1-based position in the old series and the commit id.
+    pub old: Option<(usize, Oid)>,
+    pub new: Option<(usize, Oid)>,
+    pub change: Change,
+    /// The summary of the newer version of the commit.
+    pub summary: String,
+    /// For modified commits, how the patch changed.
+    pub patch_diff: Vec<DiffLine>,
+}
+
+impl RangeDiffEntry {
+    /// Formats the entry the way 'git range-diff' does, e.g.
+    /// '2: 1234567 ! 2: abcdef0 Fix the widget'.
+    pub fn description(&self) -> String {
+        let side = |commit: Option<(usize, Oid)>| match commit {
+            Some((n, oid)) => format!("{:>2}: {:.7}", n, oid.to_string()),
+            None => " -: -------".to_string(),
+        };
+        format!("{} {} {} {}", side(self.old), self.change.marker(), side(self.new), self.summary)
+    }
+}
+
+struct SeriesCommit {
+    oid: Oid,
+    summary: String,
+    patch: Vec<String>,
+}
+
+/// Compares two versions of a series of commits, the commits in
+/// `old_base..old_tip` with those in `new_base..new_tip`. Commits are matched
+/// up by how similar their patches (including the commit message) are, so
+/// rebased, reordered and reworded commits are still recognised. Merge
+/// commits are ignored.
+pub fn range_diff(repo: &Repository, old_base: Oid, old_tip: Oid, new_base: Oid, new_tip: Oid)
+    -> Result<Vec<RangeDiffEntry>, ::git2::Error>
+{
+    let _timer = timer!("range_diff::range_diff");
+    let old = series(repo, old_base, old_tip)?;
+    let new = series(repo, new_base, new_tip)?;
+
+    let old_patches: Vec<&[String]> = old.iter().map(|c| c.patch.as_slice()).collect();
+    let new_patches: Vec<&[String]> = new.iter().map(|c| c.patch.as_slice()).collect();
+    let pairs = match_series(&old_patches, &new_patches);
+
+    let entries: Vec<RangeDiffEntry> = pairs.into_iter().map(|pair| {
+        match pair {
+            (Some(i), Some(j)) => {
+                let patch_diff = diff_lines(&old[i].patch, &new[j].patch);
+                let change = if patch_diff.iter().all(|l| match *l { DiffLine::Same(_) => true, _ => false }) {
+                    Change::Unchanged
+                } else {
+                    Change::Modified
+                };
+                RangeDiffEntry {
+                    old: Some((i + 1, old[i].oid)),
+                    new: Some((j + 1, new[j].oid)),
+                    change,
+                    summary: new[j].summary.clone(),
+                    patch_diff: if change == Change::Modified { patch_diff } else { Vec::new() },
+                }
+            },
+            (Some(i), None) => RangeDiffEntry {
+                old: Some((i + 1, old[i].oid)),
+                new: None,
+                change: Change::Removed,
+                summary: old[i].summary.clone(),
+                patch_diff: Vec::new(),
+            },
+            (None, Some(j)) => RangeDiffEntry {
+                old: None,
+                new: Some((j + 1, new[j].oid)),
+                change: Change::Added,
+                summary: new[j].summary.clone(),
+                patch_diff: Vec::new(),
+            },
+            (None, None) => unreachable!("every entry has at least one commit"),
+        }
+    }).collect();
+
+    _timer.set_message(format!("{} old and {} new commits", old.len(), new.len()));
+    Ok(entries)
+}
+
+/// Splits a range-diff specification, 'OLD...NEW' or 'OLD NEW', into the
+/// two revisions.
+pub fn parse_spec(spec: &str) -> Option<(&str, &str)> {
+    let parts: Vec<&str> = if spec.contains("...") {
+        spec.splitn(2, "...").map(|s| s.trim()).collect()
+    } else {
+        spec.split_whitespace().collect()
+    };
+
+    if parts.len() == 2 && parts.iter().all(|p| !p.is_empty()) {
+        Some((parts[0], parts[1]))
+    } else {
+        None
+    }
+}
+
+/// Loads the non-merge commits in `base..tip`, oldest first.
+fn series(repo: &Repository, base: Oid, tip: Oid) -> Result<Vec<SeriesCommit>, ::git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE);
+    revwalk.push(tip)?;
+    revwalk.hide(base)?;
+
+    let mut commits = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parents().len() > 1 {
+            continue;
+        }
+
+        commits.push(SeriesCommit {
+            oid: commit.id(),
+            summary: commit.summary().unwrap_or("").to_string(),
+            patch: patch_lines(repo, &commit)?,
+        });
+    }
+
+    Ok(commits)
+}
+
+/// The commit's message, indented, followed by its patch against its parent.
+/// Index lines and hunk line numbers are left out because they change
+/// whenever the commit is rebased, even if the patch itself does not.
+fn patch_lines(repo: &Repository, commit: &Commit) -> Result<Vec<String>, ::git2::Error> {
+    let mut lines: Vec<String> = String::from_utf8_lossy(commit.message_bytes())
+        .lines()
+        .map(|l| format!("    {}", l))
+        .collect();
+    lines.push(String::new());
+
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        let content = String::from_utf8_lossy(line.content());
+        match line.origin() {
+            'F' => lines.extend(content.lines().filter(|l| !l.starts_with("index ")).map(|l| l.to_string())),
+            'H' => lines.push(normalize_hunk_header(content.trim_end())),
+            '+' | '-' | ' ' => lines.push(format!("{}{}", line.origin(), content.trim_end_matches('\n'))),
+            _ => {},
+        }
+        true
+    })?;
+
+    Ok(lines)
+}
+
+/// Turns '@@ -10,7 +10,8 @@ fn main()' into '@@ fn main()'.
+fn normalize_hunk_header(header: &str) -> String {
+    let rest = header.trim_start_matches("@@");
+    match rest.find("@@") {
+        Some(end) => format!("@@{}", rest[end + 2..].trim_end()),
+        None => header.to_string(),
+    }
+}
+
+/// Pairs up the old and new patches, returning the order in which to show
+/// them: the new series in order, with commits that were dropped from the old
+/// series shown where they used to be. Pairs are chosen greedily, cheapest
+/// first, from those that cost less than dropping the old commit and adding
+/// the new one.
+fn match_series(old: &[&[String]], new: &[&[String]]) -> Vec<(Option<usize>, Option<usize>)> {
+    let creation_cost = |patch: &[String]| patch.len() * CREATION_FACTOR / 100;
+
+    let mut candidates = Vec::new();
+    for (i, a) in old.iter().enumerate() {
+        for (j, b) in new.iter().enumerate() {
+            let cost = diff_size(a, b);
+            if cost < creation_cost(a) + creation_cost(b) || cost == 0 {
+                candidates.push((cost, i, j));
+            }
+        }
+    }
+    candidates.sort();
+
+    let mut old_match = vec![None; old.len()];
+    let mut new_match = vec![None; new.len()];
+    for (_, i, j) in candidates {
+        if old_match[i].is_none() && new_match[j].is_none() {
+            old_match[i] = Some(j);
+            new_match[j] = Some(i);
+        }
+    }
+
+    let mut order = Vec::new();
+    let mut shown_old = HashSet::new();
+    let (mut i, mut j) = (0, 0);
+    loop {
+        while i < old.len() && shown_old.contains(&i) {
+            i += 1;
+        }
+
+        if i < old.len() && old_match[i].is_none() {
+            order.push((Some(i), None));
+            i += 1;
+        } else if j < new.len() {
+            if let Some(partner) = new_match[j] {
+                shown_old.insert(partner);
+            }
+            order.push((new_match[j], Some(j)));
+            j += 1;
+        } else {
+            break;
+        }
+    }
+
+    order
+}
+
+fn diff_size(a: &[String], b: &[String]) -> usize {
+    diff_lines(a, b).iter().filter(|l| match **l { DiffLine::Same(_) => false, _ => true }).count()
+}
+
+/// A line-by-line diff of `a` and `b` based on their longest common
+/// subsequence.
+pub fn diff_lines(a: &[String], b: &[String]) -> Vec<DiffLine> {
+    if a.len() * b.len() > MAX_DIFF_CELLS {
+        return a.iter().map(|l| DiffLine::Removed(l.clone()))
+            .chain(b.iter().map(|l| DiffLine::Added(l.clone())))
+            .collect();
+    }
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let width = b.len() + 1;
+    let mut lcs = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i * width + j] = if a[i] == b[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            lines.push(DiffLine::Same(a[i].clone()));
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+            lines.push(DiffLine::Removed(a[i].clone()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(b[j].clone()));
+            j += 1;
+        }
+    }
+    lines.extend(a[i..].iter().map(|l| DiffLine::Removed(l.clone())));
+    lines.extend(b[j..].iter().map(|l| DiffLine::Added(l.clone())));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('|').map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn normalize_hunk_header_drops_line_numbers() {
+        assert_eq!(normalize_hunk_header("@@ -10,7 +10,8 @@ fn main()"), "@@ fn main()");
+        assert_eq!(normalize_hunk_header("@@ -1 +1 @@"), "@@");
+    }
+
+    #[test]
+    fn diff_lines_works() {
+        let diff = diff_lines(&lines("a|b|c"), &lines("a|x|c|d"));
+        assert_eq!(diff, vec![
+            DiffLine::Same("a".to_string()),
+            DiffLine::Removed("b".to_string()),
+            DiffLine::Added("x".to_string()),
+            DiffLine::Same("c".to_string()),
+            DiffLine::Added("d".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn match_series_pairs_similar_patches() {
+        let a = lines("    Add widget||+widget|+more|+lines");
+        let a2 = lines("    Add widget||+widget|+more|+lines|+extra");
+        let b = lines("    Fix bug||-bug|+fix|+comment");
+        let c = lines("    Something else entirely||+1|+2|+3|+4");
+        let old: Vec<&[String]> = vec![&a, &b];
+        let new: Vec<&[String]> = vec![&a2, &c];
+        assert_eq!(match_series(&old, &new), vec![(Some(0), Some(0)), (Some(1), None), (None, Some(1))]);
+    }
+
+    #[test]
+    fn match_series_handles_reordering() {
+        let a = lines("    One||+1|+1|+1");
+        let b = lines("    Two||+2|+2|+2");
+        let old: Vec<&[String]> = vec![&a, &b];
+        let new: Vec<&[String]> = vec![&b, &a];
+        assert_eq!(match_series(&old, &new), vec![(Some(1), Some(0)), (Some(0), Some(1))]);
+    }
+
+    #[test]
+    fn parse_spec_works() {
+        assert_eq!(parse_spec("feature@{1}...feature"), Some(("feature@{1}", "feature")));
+        assert_eq!(parse_spec(" old  new "), Some(("old", "new")));
+        assert_eq!(parse_spec("old"), None);
+        assert_eq!(parse_spec("old..."), None);
+        assert_eq!(parse_spec("a b c"), None);
+    }
+
+    #[test]
+    fn description_works() {
+        let oid = Oid::from_bytes(&[0xab; 20]).unwrap();
+        let entry = RangeDiffEntry {
+            old: None,
+            new: Some((3, oid)),
+            change: Change::Added,
+            summary: "New".to_string(),
+            patch_diff: Vec::new(),
+        };
+        assert_eq!(entry.description(), " -: ------- >  3: abababa New");
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use git2::Oid;
+use mru_list::Mru;
+use path_encoding;
+
+/// How many viewed commits are remembered, across all repositories.
+pub const MAX_RECENT_COMMITS: usize = 50;
+
+/// A commit whose details were shown.
+#[derive(Debug, Clone, Eq)]
+pub struct RecentCommit {
+    /// The repository's .git directory.
+    pub repo: PathBuf,
+    pub oid: Oid,
+    pub summary: String,
+    /// When it was last viewed, in seconds since the epoch.
+    pub viewed: i64,
+}
+
+/// Viewing a commit again moves it to the front rather than adding it twice,
+/// so only the repository and id are compared.
+impl PartialEq for RecentCommit {
+    fn eq(&self, other: &Self) -> bool {
+        self.repo == other.repo && self.oid == other.oid
+    }
+}
+
+impl RecentCommit {
+    /// One line of the file: the time, id, encoded repository path and
+    /// summary, separated by tabs. Encoded paths never contain tabs.
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}", self.viewed, self.oid, path_encoding::encode_path(&self.repo), self.summary)
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, '\t');
+        let viewed = fields.next()?.parse().ok()?;
+        let oid = Oid::from_str(fields.next()?).ok()?;
+        let repo = path_encoding::decode_path(fields.next()?).ok()?;
+        let summary = fields.next().unwrap_or("").to_string();
+        Some(RecentCommit { repo, oid, summary, viewed })
+    }
+}
+
+/// The commits whose details were shown most recently, newest first, kept in
+/// a file so that they are still there next time. Those viewed since oaf
+/// started are this session's.
+pub struct RecentCommits {
+    file: PathBuf,
+    items: Mru<RecentCommit>,
+    session_start: i64,
+}
+
+impl RecentCommits {
+    /// Loads the list from `file`, if it exists. Lines that cannot be read
+    /// are skipped.
+    pub fn load(file: &Path, session_start: i64) -> Self {
+        let mut items = Mru::new(MAX_RECENT_COMMITS);
+        match fs::read_to_string(file) {
+            Ok(text) => {
+                // The file is newest first and each insert goes to the front.
+                for line in text.lines().take(MAX_RECENT_COMMITS) {
+                    match RecentCommit::from_line(line) {
+                        Some(commit) => items.insert(commit),
+                        None => warn!("Skipping unreadable recent commit '{}'", line),
+                    }
+                }
+                items.reverse();
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => warn!("Cannot read recent commits from '{}': {}", file.display(), e),
+        }
+
+        RecentCommits { file: file.to_path_buf(), items, session_start }
+    }
+
+    /// Records that the commit `oid` of the repository at `repo` was viewed
+    /// at `now`.
+    pub fn record(&mut self, repo: &Path, oid: Oid, summary: &str, now: i64) {
+        self.items.insert(RecentCommit { repo: repo.to_path_buf(), oid, summary: summary.to_string(), viewed: now });
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut file = fs::File::create(&self.file)?;
+        for commit in self.items.iter() {
+            writeln!(file, "{}", commit.to_line())?;
+        }
+        Ok(())
+    }
+
+    /// The commits viewed since oaf started, newest first.
+    pub fn this_session(&self) -> Vec<&RecentCommit> {
+        self.items.iter().filter(|c| c.viewed >= self.session_start).collect()
+    }
+
+    /// The commits viewed before oaf started, newest first.
+    pub fn earlier(&self) -> Vec<&RecentCommit> {
+        self.items.iter().filter(|c| c.viewed < self.session_start).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_round_trip() {
+        let commit = RecentCommit {
+            repo: PathBuf::from("/src/oaf/.git"),
+            oid: Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap(),
+            summary: "Fix the\tlog".to_string(),
+            viewed: 1_540_000_000,
+        };
+        let read = RecentCommit::from_line(&commit.to_line()).unwrap();
+        assert_eq!((read.repo, read.oid, read.summary, read.viewed), (commit.repo, commit.oid, commit.summary, commit.viewed));
+        assert!(RecentCommit::from_line("garbage").is_none());
+    }
+}
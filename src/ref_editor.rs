@@ -0,0 +1,162 @@
+//! Editing refs directly by their full names, including the ones oaf and git
+//! keep out of sight such as 'refs/oaf/backup/...', 'refs/stash' and
+//! 'ORIG_HEAD', for recovering from unusual situations. A ref is backed up
+//! before it is moved or deleted, unless it is itself a backup or is
+//! symbolic.
+
+use std::fmt;
+use git2::{Error, Oid, Reference, ReferenceType, Repository};
+use backups::{self, BACKUP_PREFIX};
+
+/// The refs outside 'refs/' that are shown if they exist.
+const PSEUDO_REFS: [&str; 5] = ["HEAD", "ORIG_HEAD", "MERGE_HEAD", "CHERRY_PICK_HEAD", "REVERT_HEAD"];
+
+/// What a ref points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// An object, usually a commit.
+    Object(Oid),
+    /// Another ref, as HEAD points at the current branch.
+    Symbolic(String),
+}
+
+/// Why a ref is not backed up before it is moved or deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoBackup {
+    /// It is a backup itself.
+    IsBackup,
+    /// It points at another ref, so has no object to save.
+    Symbolic,
+}
+
+/// A ref by its full name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawRef {
+    pub name: String,
+    pub target: Target,
+}
+
+impl RawRef {
+    fn from_reference(reference: &Reference) -> Option<RawRef> {
+        let name = reference.name()?.to_string();
+        let target = match reference.kind()? {
+            ReferenceType::Oid => Target::Object(reference.target()?),
+            ReferenceType::Symbolic => Target::Symbolic(reference.symbolic_target()?.to_string()),
+        };
+        Some(RawRef { name, target })
+    }
+}
+
+impl fmt::Display for RawRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.target {
+            Target::Object(oid) => write!(f, "{} {}", oid, self.name),
+            Target::Symbolic(ref target) => write!(f, "{:<40} {} -> {}", "", self.name, target),
+        }
+    }
+}
+
+/// Every ref in the repository, sorted by name, after the pseudo refs such
+/// as HEAD that exist.
+pub fn list(repo: &Repository) -> Result<Vec<RawRef>, Error> {
+    let mut refs: Vec<RawRef> = PSEUDO_REFS.iter()
+        .filter_map(|name| repo.find_reference(name).ok())
+        .filter_map(|r| RawRef::from_reference(&r))
+        .collect();
+
+    let mut under_refs = Vec::new();
+    for reference in repo.references()? {
+        if let Some(raw) = RawRef::from_reference(&reference?) {
+            under_refs.push(raw);
+        }
+    }
+    under_refs.sort_by(|a, b| a.name.cmp(&b.name));
+    refs.extend(under_refs);
+    Ok(refs)
+}
+
+/// Creates the ref `name`, a full name such as 'refs/heads/rescue', pointing
+/// at the object `spec` resolves to, which need not be a commit. Fails if
+/// the ref exists.
+pub fn create(repo: &Repository, name: &str, spec: &str) -> Result<Oid, Error> {
+    if !name.starts_with("refs/") || !Reference::is_valid_name(name) {
+        return Err(Error::from_str(&format!("'{}' is not a valid full ref name, such as 'refs/heads/rescue'.", name)));
+    }
+    let oid = repo.revparse_single(spec)?.id();
+    repo.reference(name, oid, false, &format!("oaf: create {} at {}", name, spec))?;
+    info!("Created ref '{}' at {}", name, oid);
+    Ok(oid)
+}
+
+/// Points the ref `name` at the object `spec` resolves to. A symbolic ref is
+/// turned into a direct one. Returns the backup of the old target, if one
+/// was taken.
+pub fn point_at(repo: &Repository, name: &str, spec: &str) -> Result<Option<String>, Error> {
+    let oid = repo.revparse_single(spec)?.id();
+    let backup = back_up(repo, name, "point at another object")?;
+    repo.reference(name, oid, true, &format!("oaf: point {} at {}", name, spec))?;
+    info!("Pointed ref '{}' at {}", name, oid);
+    Ok(backup)
+}
+
+/// Deletes the ref `name`. HEAD cannot be deleted. Returns the backup of its
+/// old target, if one was taken.
+pub fn delete(repo: &Repository, name: &str) -> Result<Option<String>, Error> {
+    if name == "HEAD" {
+        return Err(Error::from_str("HEAD cannot be deleted; point it at a commit instead."));
+    }
+    let backup = back_up(repo, name, "delete")?;
+    repo.find_reference(name)?.delete()?;
+    info!("Deleted ref '{}'", name);
+    Ok(backup)
+}
+
+/// Why `name` would not be backed up before it is moved or deleted, or
+/// None if it would be.
+pub fn no_backup(repo: &Repository, name: &str) -> Result<Option<NoBackup>, Error> {
+    if name.starts_with(BACKUP_PREFIX) {
+        return Ok(Some(NoBackup::IsBackup));
+    }
+    match repo.find_reference(name)?.kind() {
+        Some(ReferenceType::Symbolic) => Ok(Some(NoBackup::Symbolic)),
+        _ => Ok(None),
+    }
+}
+
+/// Saves what `name` points at as a backup, unless `no_backup` says not to.
+fn back_up(repo: &Repository, name: &str, operation: &str) -> Result<Option<String>, Error> {
+    if no_backup(repo, name)?.is_some() {
+        return Ok(None);
+    }
+    match repo.find_reference(name)?.target() {
+        Some(oid) => {
+            let reason = format!("ref editor: {} {}", operation, name);
+            backups::create(repo, &[(backup_name(name), oid)], &reason).map(Some)
+        },
+        None => Ok(None),
+    }
+}
+
+/// The name a ref is saved under in a backup: branches by their short name,
+/// as elsewhere, and other refs without the 'refs/' prefix.
+fn backup_name(name: &str) -> &str {
+    if name.starts_with("refs/heads/") {
+        &name["refs/heads/".len()..]
+    } else if name.starts_with("refs/") {
+        &name["refs/".len()..]
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_name_shortens_branches_and_drops_refs() {
+        assert_eq!(backup_name("refs/heads/feature/x"), "feature/x");
+        assert_eq!(backup_name("refs/tags/v1.0"), "tags/v1.0");
+        assert_eq!(backup_name("ORIG_HEAD"), "ORIG_HEAD");
+    }
+}
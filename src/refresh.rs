@@ -0,0 +1,174 @@
+use std::cmp;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use config;
+
+/// How often the watched repositories are checked for changes, in milliseconds.
+const POLL_MILLIS: u64 = 500;
+
+/// Waits for a burst of changes to end. Each change restarts the wait, and
+/// once nothing has changed for the quiet period the burst is due, once.
+#[derive(Debug, Clone)]
+pub struct Debounce {
+    last_change: Option<Instant>,
+}
+
+impl Debounce {
+    pub fn new() -> Self {
+        Debounce { last_change: None }
+    }
+
+    pub fn changed(&mut self, now: Instant) {
+        self.last_change = Some(now);
+    }
+
+    /// Returns true if there have been changes and none of them in the last
+    /// `quiet`, and forgets them so that the next call returns false.
+    pub fn take_due(&mut self, now: Instant, quiet: Duration) -> bool {
+        match self.last_change {
+            Some(last) if now >= last + quiet => {
+                self.last_change = None;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// A cheap summary of the state of a repository: the number of files looked
+/// at and the latest time one of them was modified. Adding, deleting or
+/// writing a file changes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Fingerprint {
+    files: usize,
+    latest: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    fn add(&mut self, path: &Path) {
+        if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+            self.files += 1;
+            self.latest = cmp::max(self.latest, Some(modified));
+        }
+    }
+}
+
+/// Takes the fingerprint of the repository whose .git directory is
+/// `git_dir`: its HEAD, index, refs and the state files of operations in
+/// progress. The working tree is not looked at, since walking it often is
+/// too slow; editing a file shows up once something, such as a status
+/// refresh or 'git status', rewrites the index.
+pub fn fingerprint(git_dir: &Path) -> Fingerprint {
+    let mut print = Fingerprint::default();
+    for file in &["HEAD", "index", "packed-refs", "MERGE_HEAD", "REBASE_HEAD", "CHERRY_PICK_HEAD"] {
+        print.add(&git_dir.join(file));
+    }
+    add_dir(&git_dir.join("refs"), &mut print);
+    print
+}
+
+/// Adds the files under `dir` to `print`.
+fn add_dir(dir: &Path, print: &mut Fingerprint) {
+    // Directories count too, since their modified time changes when a file
+    // in them is created or deleted.
+    print.add(dir);
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => add_dir(&path, print),
+            Ok(_) => print.add(&path),
+            Err(_) => {},
+        }
+    }
+}
+
+/// Adds repositories to a running watch, such as ones opened after it
+/// started.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    sender: Sender<PathBuf>,
+}
+
+impl Watcher {
+    /// Starts watching the repository whose .git directory is `git_dir`, if
+    /// it is not watched already.
+    pub fn add(&self, git_dir: &Path) {
+        let _ = self.sender.send(git_dir.to_path_buf());
+    }
+}
+
+/// Starts a background thread that watches the repositories whose .git
+/// directories are at `paths`, and any later given to the returned
+/// `Watcher`. `on_change` is called on that thread with the path of a
+/// repository's .git directory once it has changed and then stayed unchanged
+/// for the 'refresh.debounce_millis' setting, so that a build writing many
+/// files causes one call rather than dozens. Nothing is watched while the
+/// 'refresh.watch' setting is off.
+pub fn watch<F>(paths: Vec<PathBuf>, on_change: F) -> Watcher
+    where F: Fn(PathBuf) + Send + 'static
+{
+    let (sender, receiver) = mpsc::channel();
+    for path in paths {
+        let _ = sender.send(path);
+    }
+
+    thread::spawn(move || {
+        let mut watched: Vec<(PathBuf, Fingerprint, Debounce)> = Vec::new();
+        loop {
+            while let Ok(path) = receiver.try_recv() {
+                if !watched.iter().any(|w| w.0 == path) {
+                    let print = fingerprint(&path);
+                    watched.push((path, print, Debounce::new()));
+                }
+            }
+
+            thread::sleep(Duration::from_millis(POLL_MILLIS));
+            let settings = config::current().refresh.clone();
+            if !settings.watch {
+                continue;
+            }
+
+            let quiet = Duration::from_millis(settings.debounce_millis);
+            for &mut (ref path, ref mut print, ref mut debounce) in &mut watched {
+                let latest = fingerprint(path);
+                let now = Instant::now();
+                if latest != *print {
+                    *print = latest;
+                    debounce.changed(now);
+                }
+                if debounce.take_due(now, quiet) {
+                    on_change(path.clone());
+                }
+            }
+        }
+    });
+
+    Watcher { sender }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debounce_waits_for_the_changes_to_stop() {
+        let start = Instant::now();
+        let quiet = Duration::from_millis(1000);
+        let mut debounce = Debounce::new();
+        assert!(!debounce.take_due(start, quiet));
+
+        debounce.changed(start);
+        debounce.changed(start + Duration::from_millis(600));
+        assert!(!debounce.take_due(start + Duration::from_millis(1200), quiet));
+        assert!(debounce.take_due(start + Duration::from_millis(1600), quiet));
+        assert!(!debounce.take_due(start + Duration::from_millis(5000), quiet));
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fmt;
+use git2::{BranchType, ObjectType, Oid, Repository};
+use git2::build::CheckoutBuilder;
+use remotes;
+
+/// The kinds of reference shown as decorations in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RefKind {
+    /// The branch HEAD is on, or HEAD itself when detached.
+    Head,
+    LocalBranch,
+    RemoteBranch,
+    Tag,
+}
+
+impl fmt::Display for RefKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            RefKind::Head => "head",
+            RefKind::LocalBranch => "branch",
+            RefKind::RemoteBranch => "remote branch",
+            RefKind::Tag => "tag",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A reference pointing at a commit, named in its short form.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RefLabel {
+    pub kind: RefKind,
+    pub name: String,
+    pub target: Oid,
+}
+
+impl fmt::Display for RefLabel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            RefKind::Head if self.name == "HEAD" => write!(f, "HEAD"),
+            RefKind::Head => write!(f, "HEAD -> {}", self.name),
+            RefKind::Tag => write!(f, "tag: {}", self.name),
+            _ => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Checks out the local branch `branch`. Files with changes in the working
+/// tree are not overwritten; the checkout fails instead.
+pub fn checkout_branch(repo: &Repository, branch: &str) -> Result<(), ::git2::Error> {
+    let commit = repo.find_branch(branch, BranchType::Local)?.get().peel(ObjectType::Commit)?;
+    repo.checkout_tree(&commit, Some(CheckoutBuilder::new().safe()))?;
+    repo.set_head(&remotes::full_branch_ref(branch))
+}
+
+/// Maps commits to the references that point at them.
+pub type Decorations = HashMap<Oid, Vec<RefLabel>>;
+
+/// Returns all the branches, remote-tracking branches and tags in the
+/// repository, peeled to the commits they point at. Annotated tags are
+/// labelled with the tagged commit. The result is sorted by kind then name.
+pub fn list_refs(repo: &Repository) -> Result<Vec<RefLabel>, ::git2::Error> {
+    let _timer = timer!("refs::list_refs");
+    let head = repo.head().ok();
+    let head_name = head.as_ref().and_then(|h| if h.is_branch() { h.name().map(|n| n.to_string()) } else { None });
+
+    let mut labels = Vec::new();
+    if let Some(ref head) = head {
+        if !head.is_branch() {
+            if let Ok(commit) = head.peel_to_commit() {
+                labels.push(RefLabel { kind: RefKind::Head, name: "HEAD".to_string(), target: commit.id() });
+            }
+        }
+    }
+
+    for reference in repo.references()? {
+        let reference = reference?;
+        let full_name = match reference.name() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        let kind = match classify(&full_name) {
+            Some(_) if Some(&full_name) == head_name.as_ref() => RefKind::Head,
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        let name = match reference.shorthand() {
+            Some(name) if !name.ends_with("/HEAD") => name.to_string(),
+            _ => continue,
+        };
+
+        if let Ok(commit) = reference.peel_to_commit() {
+            labels.push(RefLabel { kind, name, target: commit.id() });
+        }
+    }
+
+    labels.sort();
+    _timer.set_message(format!("{} refs", labels.len()));
+    Ok(labels)
+}
+
+/// Groups refs by the commit they point at, for decorating the log.
+pub fn decorations(labels: &[RefLabel]) -> Decorations {
+    let mut decorations = Decorations::new();
+    for label in labels {
+        decorations.entry(label.target).or_insert_with(Vec::new).push(label.clone());
+    }
+    decorations
+}
+
+/// Formats the decorations of one commit the way 'git log --decorate' does,
+/// e.g. '(HEAD -> master, origin/master, tag: v1.0)'. Returns an empty
+/// string if there are none.
+pub fn format_decorations(labels: &[RefLabel]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let names: Vec<String> = labels.iter().map(|l| l.to_string()).collect();
+    format!("({})", names.join(", "))
+}
+
+fn classify(full_name: &str) -> Option<RefKind> {
+    if full_name.starts_with("refs/heads/") {
+        Some(RefKind::LocalBranch)
+    } else if full_name.starts_with("refs/remotes/") {
+        Some(RefKind::RemoteBranch)
+    } else if full_name.starts_with("refs/tags/") {
+        Some(RefKind::Tag)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(kind: RefKind, name: &str) -> RefLabel {
+        RefLabel { kind, name: name.to_string(), target: Oid::zero() }
+    }
+
+    #[test]
+    fn format_decorations_empty() {
+        assert_eq!(format_decorations(&[]), "");
+    }
+
+    #[test]
+    fn format_decorations_like_git() {
+        let labels = vec![
+            label(RefKind::Head, "master"),
+            label(RefKind::RemoteBranch, "origin/master"),
+            label(RefKind::Tag, "v1.0"),
+        ];
+        assert_eq!(format_decorations(&labels), "(HEAD -> master, origin/master, tag: v1.0)");
+    }
+
+    #[test]
+    fn format_detached_head() {
+        assert_eq!(label(RefKind::Head, "HEAD").to_string(), "HEAD");
+    }
+
+    #[test]
+    fn classify_works() {
+        assert_eq!(classify("refs/heads/master"), Some(RefKind::LocalBranch));
+        assert_eq!(classify("refs/remotes/origin/master"), Some(RefKind::RemoteBranch));
+        assert_eq!(classify("refs/tags/v1.0"), Some(RefKind::Tag));
+        assert_eq!(classify("refs/stash"), None);
+    }
+
+    #[test]
+    fn decorations_groups_by_target() {
+        let labels = vec![label(RefKind::LocalBranch, "a"), label(RefKind::Tag, "b")];
+        let decorations = decorations(&labels);
+        assert_eq!(decorations.len(), 1);
+        assert_eq!(decorations[&Oid::zero()].len(), 2);
+    }
+}
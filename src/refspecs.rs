@@ -0,0 +1,220 @@
+use std::fmt;
+use git2::{ConfigLevel, Direction, Error, ErrorCode, Reference, Repository};
+use error::OafError;
+
+/// A refspec, such as '+refs/heads/*:refs/remotes/origin/*', which says
+/// which refs a fetch or push reads (`src`) and which it writes (`dst`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refspec {
+    /// Update the destination even if it is not a fast-forward.
+    pub force: bool,
+    /// Empty in a push refspec that deletes `dst` on the remote.
+    pub src: String,
+    /// None for a fetch that only writes FETCH_HEAD, or a push to the ref
+    /// with the same name as `src`.
+    pub dst: Option<String>,
+}
+
+impl Refspec {
+    /// Parses and checks `spec` as git would for a fetch or push refspec.
+    /// The error says what is wrong with it.
+    pub fn parse(spec: &str, direction: Direction) -> Result<Refspec, String> {
+        let spec = spec.trim();
+        let (force, rest) = if spec.starts_with('+') { (true, &spec[1..]) } else { (false, spec) };
+        let mut parts = rest.splitn(2, ':');
+        let src = parts.next().unwrap_or("").to_string();
+        let dst = parts.next().map(|d| d.to_string());
+
+        if rest.is_empty() {
+            return Err("A refspec cannot be empty.".to_string());
+        }
+        if dst.as_ref().map_or(false, |d| d.contains(':')) {
+            return Err(format!("'{}' has more than one ':'.", spec));
+        }
+        match (direction, src.is_empty(), dst.as_ref().map_or(true, |d| d.is_empty())) {
+            (Direction::Fetch, true, _) => return Err(format!("'{}' has no source; a fetch must say what to fetch.", spec)),
+            (Direction::Push, true, true) => return Err(format!("'{}' has neither a source nor a destination.", spec)),
+            _ => {},
+        }
+
+        for side in Some(&src).into_iter().chain(dst.as_ref()).filter(|s| !s.is_empty()) {
+            if side.matches('*').count() > 1 {
+                return Err(format!("'{}' has more than one '*'.", side));
+            }
+            if !is_valid_pattern(side) {
+                return Err(format!("'{}' is not a valid ref name.", side));
+            }
+        }
+        if let Some(ref dst) = dst {
+            if !dst.is_empty() && !src.is_empty() && src.contains('*') != dst.contains('*') {
+                return Err(format!("'{}' must have a '*' on both sides or neither.", spec));
+            }
+        }
+
+        // An empty destination only means something, deletion, in a push.
+        let push = match direction { Direction::Push => true, Direction::Fetch => false };
+        Ok(Refspec { force, src, dst: dst.filter(|d| push || !d.is_empty()) })
+    }
+
+    /// If `name`, a full ref name, matches the source, the ref it would be
+    /// written to: the destination with the part matched by '*' put in.
+    /// A refspec with no destination writes to the same name.
+    pub fn map(&self, name: &str) -> Option<String> {
+        let matched = match self.src.find('*') {
+            Some(star) => {
+                let (prefix, suffix) = (&self.src[..star], &self.src[star + 1..]);
+                if name.len() < prefix.len() + suffix.len() || !name.starts_with(prefix) || !name.ends_with(suffix) {
+                    return None;
+                }
+                &name[prefix.len()..name.len() - suffix.len()]
+            },
+            None if name == self.src || name == format!("refs/heads/{}", self.src) || name == format!("refs/tags/{}", self.src) => "",
+            None => return None,
+        };
+
+        match self.dst {
+            Some(ref dst) => Some(dst.replacen('*', matched, 1)),
+            None => Some(name.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Refspec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.force {
+            write!(f, "+")?;
+        }
+        match self.dst {
+            Some(ref dst) => write!(f, "{}:{}", self.src, dst),
+            None => write!(f, "{}", self.src),
+        }
+    }
+}
+
+/// Whether `pattern` is a valid ref name once its '*' (if any) is filled in.
+/// Short names such as 'master' are allowed, as git allows them.
+fn is_valid_pattern(pattern: &str) -> bool {
+    let name = pattern.replace('*', "x");
+    if name.starts_with("refs/") || name == "HEAD" {
+        Reference::is_valid_name(&name)
+    } else {
+        Reference::is_valid_name(&format!("refs/heads/{}", name))
+    }
+}
+
+/// Parses the refspecs in `text`, one per line, ignoring blank lines.
+/// Returns every problem found, with its line number, if any line is bad.
+pub fn parse_lines(text: &str, direction: Direction) -> Result<Vec<Refspec>, Vec<String>> {
+    let mut specs = Vec::new();
+    let mut problems = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|&(_, l)| !l.trim().is_empty()) {
+        match Refspec::parse(line, direction) {
+            Ok(spec) => specs.push(spec),
+            Err(problem) => problems.push(format!("Line {}: {}", i + 1, problem)),
+        }
+    }
+
+    if problems.is_empty() { Ok(specs) } else { Err(problems) }
+}
+
+/// Pairs each of `names` that one of `specs` matches with the ref it would
+/// be written to, using the first refspec that matches as git does.
+pub fn preview(specs: &[Refspec], names: &[String]) -> Vec<(String, String)> {
+    names.iter()
+        .filter_map(|name| specs.iter().filter_map(|spec| spec.map(name)).next().map(|dst| (name.clone(), dst)))
+        .collect()
+}
+
+/// The configured refspecs of the remote called `remote_name`.
+pub fn load(repo: &Repository, remote_name: &str, direction: Direction) -> Result<Vec<String>, Error> {
+    let remote = repo.find_remote(remote_name)?;
+    let specs = match direction {
+        Direction::Fetch => remote.fetch_refspecs()?,
+        Direction::Push => remote.push_refspecs()?,
+    };
+    Ok(specs.iter().filter_map(|s| s).map(|s| s.to_string()).collect())
+}
+
+/// Replaces the refspecs of the remote called `remote_name` in the
+/// repository's own config. Every refspec is checked before any is written.
+/// The first replaces all the old values at once and the rest are added
+/// after it, so the remote is never left with none unless `specs` is empty.
+pub fn save(repo: &Repository, remote_name: &str, direction: Direction, specs: &[Refspec]) -> Result<(), OafError> {
+    let values: Vec<String> = specs.iter().map(|s| s.to_string()).collect();
+    for value in &values {
+        Refspec::parse(value, direction).map_err(|problem| Error::from_str(&problem))?;
+    }
+
+    let setting = format!("remote.{}.{}", remote_name, setting_suffix(direction));
+    let mut config = repo.config()?.open_level(ConfigLevel::Local)?;
+    match values.split_first() {
+        Some((first, rest)) => {
+            config.set_multivar(&setting, ".*", first)?;
+            for value in rest {
+                // '^$' matches no refspec, so each value is added.
+                config.set_multivar(&setting, "^$", value)?;
+            }
+        },
+        None => match config.remove_multivar(&setting, ".*") {
+            Err(ref e) if e.code() == ErrorCode::NotFound => {},
+            result => result?,
+        },
+    }
+    info!("Set {} to {:?}", setting, values);
+    Ok(())
+}
+
+fn setting_suffix(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Fetch => "fetch",
+        Direction::Push => "push",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetch(spec: &str) -> Result<Refspec, String> {
+        Refspec::parse(spec, Direction::Fetch)
+    }
+
+    #[test]
+    fn parse_accepts_usual_refspecs() {
+        assert_eq!(fetch("+refs/heads/*:refs/remotes/origin/*").unwrap(), Refspec {
+            force: true, src: "refs/heads/*".to_string(), dst: Some("refs/remotes/origin/*".to_string()) });
+        assert_eq!(fetch("master").unwrap().dst, None);
+        assert_eq!(Refspec::parse(":refs/heads/old", Direction::Push).unwrap().src, "");
+        assert_eq!(fetch("+refs/heads/*:refs/remotes/origin/*").unwrap().to_string(), "+refs/heads/*:refs/remotes/origin/*");
+    }
+
+    #[test]
+    fn parse_rejects_bad_refspecs() {
+        assert!(fetch("").is_err());
+        assert!(fetch(":refs/heads/x").is_err());
+        assert!(fetch("refs/heads/*:refs/remotes/origin/x").is_err());
+        assert!(fetch("refs/*/*:refs/remotes/*/*").is_err());
+        assert!(fetch("refs/heads/a..b:refs/remotes/origin/x").is_err());
+        assert!(fetch("a:b:c").is_err());
+        assert!(Refspec::parse(":", Direction::Push).is_err());
+    }
+
+    #[test]
+    fn map_fills_in_the_wildcard() {
+        let spec = fetch("+refs/heads/*:refs/remotes/origin/*").unwrap();
+        assert_eq!(spec.map("refs/heads/feature/x"), Some("refs/remotes/origin/feature/x".to_string()));
+        assert_eq!(spec.map("refs/tags/v1"), None);
+        assert_eq!(fetch("master:refs/remotes/origin/main").unwrap().map("refs/heads/master"), Some("refs/remotes/origin/main".to_string()));
+    }
+
+    #[test]
+    fn preview_uses_the_first_matching_refspec() {
+        let specs = parse_lines("refs/heads/main:refs/remotes/up/trunk\n\nrefs/heads/*:refs/remotes/up/*\n", Direction::Fetch).unwrap();
+        let names = vec!["refs/heads/main".to_string(), "refs/heads/dev".to_string(), "refs/tags/v1".to_string()];
+        assert_eq!(preview(&specs, &names), vec![
+            ("refs/heads/main".to_string(), "refs/remotes/up/trunk".to_string()),
+            ("refs/heads/dev".to_string(), "refs/remotes/up/dev".to_string()),
+        ]);
+        assert_eq!(parse_lines("ok\n:bad\n", Direction::Fetch).unwrap_err().len(), 1);
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt;
+use git2::{Error, ObjectType, Oid, Repository};
+use changelog::{self, ChangelogCommit};
+use config::ChangelogConfig;
+use history;
+use signing::{self, SigningSetup};
+
+/// A semantic version, as found in release tags such as 'v1.2.3'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    /// Parses a tag name of the form 'MAJOR.MINOR.PATCH', optionally with a
+    /// 'v' in front. Pre-release tags such as '1.0.0-rc1' are not releases,
+    /// so they give None.
+    pub fn parse(tag: &str) -> Option<Version> {
+        let numbers = tag.trim_start_matches(|c| c == 'v' || c == 'V');
+        let parts: Vec<u64> = numbers.split('.').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+        match parts[..] {
+            [major, minor, patch] => Some(Version { major, minor, patch }),
+            _ => None,
+        }
+    }
+
+    /// The next version after this one. Before 1.0.0 breaking changes only
+    /// bump the minor version, as Cargo and most tools expect.
+    pub fn bumped(&self, bump: Bump) -> Version {
+        match bump {
+            Bump::Major if self.major > 0 => Version { major: self.major + 1, minor: 0, patch: 0 },
+            Bump::Major | Bump::Minor => Version { major: self.major, minor: self.minor + 1, patch: 0 },
+            Bump::Patch => Version { major: self.major, minor: self.minor, patch: self.patch + 1 },
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Which part of the version a release bumps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bump {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl Bump {
+    /// The bump the commits in a release call for: major if any are
+    /// breaking, minor if any are features ('feat') and patch otherwise.
+    pub fn for_commits(commits: &[ChangelogCommit]) -> Bump {
+        let conventional = || commits.iter().filter_map(|c| c.conventional.as_ref());
+        if conventional().any(|c| c.breaking) {
+            Bump::Major
+        } else if conventional().any(|c| c.kind == "feat") {
+            Bump::Minor
+        } else {
+            Bump::Patch
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match *self {
+            Bump::Major => "there are breaking changes",
+            Bump::Minor => "there are new features",
+            Bump::Patch => "there are only fixes and other changes",
+        }
+    }
+}
+
+/// The release tag with the highest version that HEAD contains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastRelease {
+    pub tag: String,
+    pub version: Version,
+    pub commit: Oid,
+}
+
+/// Finds the latest release before `head`. Tags on other branches, and tags
+/// whose names are not versions, are ignored.
+pub fn last_release(repo: &Repository, head: Oid) -> Result<Option<LastRelease>, Error> {
+    let mut latest: Option<LastRelease> = None;
+    for tag in repo.tag_names(None)?.iter().filter_map(|t| t) {
+        let version = match Version::parse(tag) {
+            Some(version) => version,
+            None => continue,
+        };
+        if latest.as_ref().map_or(false, |l| l.version >= version) {
+            continue;
+        }
+
+        let commit = repo.revparse_single(&format!("refs/tags/{}", tag))?.peel_to_commit()?.id();
+        if commit == head || repo.graph_descendant_of(head, commit)? {
+            latest = Some(LastRelease { tag: tag.to_string(), version, commit });
+        }
+    }
+    Ok(latest)
+}
+
+/// A proposed release of HEAD.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub previous: Option<LastRelease>,
+    /// The number of commits since the previous release.
+    pub commits: usize,
+    pub bump: Bump,
+    /// The name of the new tag, with the same 'v' prefix (or none) as the
+    /// previous one.
+    pub tag: String,
+    /// The changelog of the release, for the tag's message.
+    pub message: String,
+}
+
+/// Works out the next release of HEAD from the commits since the last one.
+/// If there has been no release the first is 0.1.0.
+pub fn plan(repo: &Repository, config: &ChangelogConfig) -> Result<Plan, Error> {
+    let head = history::head_commit(repo)?.ok_or_else(|| Error::from_str("There are no commits to release yet."))?;
+    let previous = last_release(repo, head.id())?;
+    let commits = changelog::commits(repo, previous.as_ref().map(|p| p.commit), head.id())?;
+    let bump = Bump::for_commits(&commits);
+
+    let tag = match previous {
+        Some(ref p) => {
+            let prefix = &p.tag[..p.tag.len() - p.tag.trim_start_matches(|c| c == 'v' || c == 'V').len()];
+            format!("{}{}", prefix, p.version.bumped(bump))
+        },
+        None => "v0.1.0".to_string(),
+    };
+
+    let from = previous.as_ref().map_or("", |p| p.tag.as_str());
+    let date = history::commit_date(&head.time());
+    let message = changelog::render(from, &tag, date, &commits, config);
+    Ok(Plan { previous, commits: commits.len(), bump, tag, message })
+}
+
+/// Whether the repository's 'tag.gpgSign' setting asks for tags to be
+/// signed.
+pub fn signs_tags(repo: &Repository) -> bool {
+    repo.config().and_then(|c| c.get_bool("tag.gpgSign")).unwrap_or(false)
+}
+
+/// Creates an annotated tag called `name` on HEAD. If `sign` is set the tag
+/// is signed with gpg, using the key in 'user.signingKey' or else the one
+/// for the committer's identity, as 'git tag -s' does.
+pub fn create_tag(repo: &Repository, name: &str, message: &str, sign: bool) -> Result<Oid, Error> {
+    let target = repo.head()?.peel(ObjectType::Commit)?;
+    let tagger = repo.signature()?;
+    if !sign {
+        return repo.tag(name, &target, &tagger, message, false);
+    }
+
+    let mut content = tag_content(target.id(), name, &signing::identity_line(&tagger), message);
+    content.push_str(&SigningSetup::load(repo)?.sign(&content)?);
+    let oid = repo.odb()?.write(ObjectType::Tag, content.as_bytes())?;
+    repo.reference(&format!("refs/tags/{}", name), oid, false, &format!("tag: {}", name))?;
+    info!("Created signed tag '{}' {}", name, oid);
+    Ok(oid)
+}
+
+/// The raw content of an annotated tag object, which is what gets signed.
+fn tag_content(target: Oid, name: &str, tagger: &str, message: &str) -> String {
+    format!("object {}\ntype commit\ntag {}\ntagger {}\n\n{}\n", target, name, tagger, message.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Signature, Time};
+
+    fn version(major: u64, minor: u64, patch: u64) -> Version {
+        Version { major, minor, patch }
+    }
+
+    #[test]
+    fn parse_works() {
+        assert_eq!(Version::parse("v1.2.3"), Some(version(1, 2, 3)));
+        assert_eq!(Version::parse("0.10.0"), Some(version(0, 10, 0)));
+        assert_eq!(Version::parse("v1.0.0-rc1"), None);
+        assert_eq!(Version::parse("v1.2"), None);
+        assert_eq!(Version::parse("release-1"), None);
+        assert!(version(0, 10, 0) > version(0, 9, 9));
+    }
+
+    #[test]
+    fn bumped_works() {
+        assert_eq!(version(1, 2, 3).bumped(Bump::Major), version(2, 0, 0));
+        assert_eq!(version(1, 2, 3).bumped(Bump::Minor), version(1, 3, 0));
+        assert_eq!(version(1, 2, 3).bumped(Bump::Patch), version(1, 2, 4));
+        assert_eq!(version(0, 2, 3).bumped(Bump::Major), version(0, 3, 0));
+    }
+
+    #[test]
+    fn bump_for_commits_works() {
+        let commits = |messages: &[&str]| -> Vec<ChangelogCommit> {
+            messages.iter().map(|m| ChangelogCommit::new(Oid::zero(), m, "Ann")).collect()
+        };
+        assert_eq!(Bump::for_commits(&commits(&["fix: A", "feat: B", "Tidy up"])), Bump::Minor);
+        assert_eq!(Bump::for_commits(&commits(&["fix: A", "refactor!: B"])), Bump::Major);
+        assert_eq!(Bump::for_commits(&commits(&["fix: A", "Tidy up"])), Bump::Patch);
+        assert_eq!(Bump::for_commits(&[]), Bump::Patch);
+    }
+
+    #[test]
+    fn tag_content_is_what_git_writes() {
+        let tagger = Signature::new("Ann", "ann@example.com", &Time::new(1538400000, -330)).unwrap();
+        let content = tag_content(Oid::zero(), "v1.0.0", &signing::identity_line(&tagger), "## v1.0.0\n\n- Ship it\n\n");
+        assert_eq!(content, "object 0000000000000000000000000000000000000000\ntype commit\ntag v1.0.0\n\
+                             tagger Ann <ann@example.com> 1538400000 -0530\n\n## v1.0.0\n\n- Ship it\n");
+    }
+}
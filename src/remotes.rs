@@ -0,0 +1,717 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use cancel::Cancel;
+use error::OafError;
+use network;
+use git2::build::RepoBuilder;
+use git2::{AutotagOption, BranchType, Config, Cred, CredentialType, Error, FetchOptions, Oid,
+           PushOptions, RemoteCallbacks, Repository, Sort};
+
+/// The remote and remote branch that a local branch tracks. This is what
+/// git stores in the `branch.<name>.remote` and `branch.<name>.merge` config
+/// entries; `branch` here is the short name, i.e. without 'refs/heads/'.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamSpec {
+    pub remote: String,
+    pub branch: String,
+}
+
+impl UpstreamSpec {
+    pub fn new<S, T>(remote: S, branch: T) -> Self
+        where S: Into<String>,
+              T: Into<String>
+    {
+        UpstreamSpec {
+            remote: remote.into(),
+            branch: branch.into(),
+        }
+    }
+
+    /// The full name of the ref on the remote, e.g. 'refs/heads/master'.
+    pub fn merge_ref(&self) -> String {
+        full_branch_ref(&self.branch)
+    }
+}
+
+/// Controls which tags are downloaded by a fetch. These correspond to
+/// plain 'git fetch', 'git fetch --tags' and 'git fetch --no-tags'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagFetch {
+    /// Fetch tags that point at commits that are being fetched anyway.
+    Auto,
+    /// Fetch all tags.
+    All,
+    /// Do not fetch any tags beyond those matched by the refspecs.
+    None,
+}
+
+impl TagFetch {
+    pub fn all() -> &'static [TagFetch] {
+        &[TagFetch::Auto, TagFetch::All, TagFetch::None]
+    }
+
+    /// A description suitable for showing in a menu.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            TagFetch::Auto => "Tags on fetched commits (default)",
+            TagFetch::All => "All tags (--tags)",
+            TagFetch::None => "No tags (--no-tags)",
+        }
+    }
+
+    fn autotag_option(&self) -> AutotagOption {
+        match *self {
+            TagFetch::Auto => AutotagOption::Auto,
+            TagFetch::All => AutotagOption::All,
+            TagFetch::None => AutotagOption::None,
+        }
+    }
+}
+
+/// Returns the short name of the branch that HEAD points to, or None if
+/// HEAD is detached or the branch is unborn.
+pub fn current_branch_name(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+
+    head.shorthand().map(|s| s.to_string())
+}
+
+/// Returns the names of all the remotes configured in the repository.
+pub fn remote_names(repo: &Repository) -> Vec<String> {
+    match repo.remotes() {
+        Ok(names) => names.iter().filter_map(|n| n).map(|n| n.to_string()).collect(),
+        Err(e) => {
+            warn!("Could not list remotes, assuming there are none. Error = {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Returns the fetch URL of the remote called `remote_name`.
+pub fn remote_url(repo: &Repository, remote_name: &str) -> Result<String, Error> {
+    let remote = repo.find_remote(remote_name)?;
+    match remote.url() {
+        Some(url) => Ok(url.to_string()),
+        None => Err(Error::from_str(&format!("The URL of remote '{}' is not valid UTF-8.", remote_name))),
+    }
+}
+
+/// Reads the configured upstream of `local_branch`, if it has one.
+pub fn upstream_of(repo: &Repository, local_branch: &str) -> Option<UpstreamSpec> {
+    let config = repo.config().ok()?;
+    let remote = config.get_string(&format!("branch.{}.remote", local_branch)).ok()?;
+    let merge = config.get_string(&format!("branch.{}.merge", local_branch)).ok()?;
+    Some(UpstreamSpec::new(remote, short_branch_name(&merge)))
+}
+
+/// Works out a sensible upstream for a branch that does not yet have one.
+/// The remote is chosen from 'branch.<name>.pushRemote', 'remote.pushDefault',
+/// 'origin' or the first remote, in that order. The remote branch has the
+/// same name as the local branch.
+pub fn default_upstream(repo: &Repository, local_branch: &str) -> Option<UpstreamSpec> {
+    let remotes = remote_names(repo);
+    let configured = repo.config().ok().and_then(|config| {
+        config.get_string(&format!("branch.{}.pushRemote", local_branch))
+            .or_else(|_| config.get_string("remote.pushDefault"))
+            .ok()
+    });
+
+    choose_remote(&remotes, configured.as_ref().map(|s| s.as_str()))
+        .map(|remote| UpstreamSpec::new(remote, local_branch))
+}
+
+/// Persists `upstream` as the upstream of `local_branch`. The config is written
+/// directly rather than via `Branch::set_upstream` because the latter requires
+/// the remote-tracking branch to already exist, which it won't before the
+/// first push.
+pub fn set_upstream(repo: &Repository, local_branch: &str, upstream: &UpstreamSpec) -> Result<(), Error> {
+    let mut config = repo.config()?;
+    config.set_str(&format!("branch.{}.remote", local_branch), &upstream.remote)?;
+    config.set_str(&format!("branch.{}.merge", local_branch), &upstream.merge_ref())?;
+    info!("Set upstream of branch '{}' to '{}/{}'", local_branch, upstream.remote, upstream.branch);
+    Ok(())
+}
+
+/// The remotes that 'Push to Several Remotes' pushes to, from the
+/// repository's git config setting 'oaf.pushRemotes', a list of remote names
+/// separated by commas or spaces such as 'origin, mirror'. Names that are
+/// not remotes of the repository are left out, with a warning in the log.
+pub fn push_targets(repo: &Repository) -> Vec<String> {
+    let setting = match repo.config().and_then(|c| c.get_string("oaf.pushRemotes")) {
+        Ok(setting) => setting,
+        Err(_) => return Vec::new(),
+    };
+    let remotes = remote_names(repo);
+    parse_push_targets(&setting).into_iter()
+        .filter(|name| {
+            let exists = remotes.contains(name);
+            if !exists {
+                warn!("'oaf.pushRemotes' names '{}', which is not a remote, ignoring it", name);
+            }
+            exists
+        })
+        .collect()
+}
+
+fn parse_push_targets(setting: &str) -> Vec<String> {
+    let mut targets: Vec<String> = Vec::new();
+    for name in setting.split(|c: char| c == ',' || c.is_whitespace()).filter(|n| !n.is_empty()) {
+        if !targets.iter().any(|t| t == name) {
+            targets.push(name.to_string());
+        }
+    }
+    targets
+}
+
+/// Describes the outcome of pushing `branch` to several remotes, one line
+/// per remote, starting with how many succeeded.
+pub fn describe_push_results(branch: &str, results: &[(String, Result<(), OafError>)]) -> String {
+    let pushed = results.iter().filter(|&&(_, ref r)| r.is_ok()).count();
+    let mut text = format!("Pushed '{}' to {} of {} remotes.\n", branch, pushed, results.len());
+    for &(ref remote, ref result) in results {
+        match *result {
+            Ok(()) => text.push_str(&format!("\n{}: pushed", remote)),
+            Err(ref e) => text.push_str(&format!("\n{}: failed: {}", remote, e)),
+        }
+    }
+    text
+}
+
+/// Pushes `local_branch` to the branch described by `upstream`.
+pub fn push_branch(repo: &Repository, local_branch: &str, upstream: &UpstreamSpec) -> Result<(), OafError> {
+    let _timer = timer!("push_branch");
+    let refspec = format!("{}:{}", full_branch_ref(local_branch), upstream.merge_ref());
+    push_refspecs(repo, &upstream.remote, &[&refspec])?;
+    _timer.set_message(format!("Pushed '{}' to '{}'", refspec, upstream.remote));
+    Ok(())
+}
+
+/// The commits on `local_branch` that pushing it to `upstream` would send:
+/// those not on the upstream branch or, if that has not been fetched, not on
+/// any branch of the remote.
+pub fn outgoing_commits(repo: &Repository, local_branch: &str, upstream: &UpstreamSpec) -> Result<Vec<Oid>, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME);
+    revwalk.push_ref(&full_branch_ref(local_branch))?;
+    let tracking = format!("refs/remotes/{}/{}", upstream.remote, upstream.branch);
+    if repo.find_reference(&tracking).is_ok() {
+        revwalk.hide_ref(&tracking)?;
+    } else {
+        revwalk.hide_glob(&format!("refs/remotes/{}/*", upstream.remote))?;
+    }
+    revwalk.collect()
+}
+
+/// The commits on `upstream`, as last fetched, that `local_branch` does not
+/// have, i.e. what a pull would bring in. Empty if the upstream branch has
+/// not been fetched.
+pub fn incoming_commits(repo: &Repository, local_branch: &str, upstream: &UpstreamSpec) -> Result<Vec<Oid>, git2::Error> {
+    let tracking = format!("refs/remotes/{}/{}", upstream.remote, upstream.branch);
+    if repo.find_reference(&tracking).is_err() {
+        return Ok(Vec::new());
+    }
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME);
+    revwalk.push_ref(&tracking)?;
+    revwalk.hide_ref(&full_branch_ref(local_branch))?;
+    revwalk.collect()
+}
+
+/// The files changed by `commits`, each compared with its first parent.
+pub fn files_changed_by(repo: &Repository, commits: &[Oid]) -> Result<Vec<PathBuf>, git2::Error> {
+    let mut files = BTreeSet::new();
+    for &oid in commits {
+        let commit = repo.find_commit(oid)?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        files.extend(diff.deltas().filter_map(|d| d.new_file().path().or_else(|| d.old_file().path()).map(|p| p.to_path_buf())));
+    }
+    Ok(files.into_iter().collect())
+}
+
+/// Pushes an arbitrary set of refspecs to the named remote. The server can
+/// reject individual refs, e.g. because they are not fast-forwards, without
+/// the push as a whole failing; such rejections are turned into an error.
+pub fn push_refspecs(repo: &Repository, remote_name: &str, refspecs: &[&str]) -> Result<(), OafError> {
+    network::check_online()?;
+    let config = repo.config()?;
+    let mut remote = repo.find_remote(remote_name)?;
+    let proxy = network::proxy_options(&config, remote_name, remote.url().unwrap_or(""));
+    let mut rejections = Vec::new();
+    {
+        let mut callbacks = make_callbacks(&config);
+        callbacks.push_update_reference(|name, status| {
+            if let Some(status) = status {
+                rejections.push(format!("{} ({})", name, status));
+            }
+            Ok(())
+        });
+        let mut opts = PushOptions::new();
+        opts.remote_callbacks(callbacks);
+        opts.proxy_options(proxy);
+        remote.push(refspecs, Some(&mut opts)).map_err(OafError::from_remote)?;
+    }
+
+    if rejections.is_empty() {
+        Ok(())
+    } else {
+        Err(OafError::Git(Error::from_str(&format!("Push to '{}' rejected: {}", remote_name, rejections.join(", ")))))
+    }
+}
+
+/// Fetches from the named remote using its configured refspecs. Returns the
+/// number of commits that were new to the remote-tracking branches.
+pub fn fetch(repo: &Repository, remote_name: &str, tags: TagFetch) -> Result<usize, OafError> {
+    fetch_cancellable(repo, remote_name, tags, &Cancel::new(), |_| {})
+}
+
+/// As `fetch`, reporting to `on_progress` as objects arrive. Once `cancel` is
+/// set the download stops and `OafError::Cancelled` is returned. The
+/// remote-tracking branches are only updated after everything has been
+/// downloaded, so a cancelled fetch leaves them as they were.
+pub fn fetch_cancellable<F>(repo: &Repository, remote_name: &str, tags: TagFetch, cancel: &Cancel, on_progress: F) -> Result<usize, OafError>
+    where F: FnMut(TransferProgress)
+{
+    let _timer = timer!("fetch");
+    let before = tracking_tips(repo, remote_name)?;
+    fetch_with(repo, remote_name, &[], tags, cancel, on_progress)?;
+    let after = tracking_tips(repo, remote_name)?;
+    let new_commits = count_new_commits(repo, &before, &after)?;
+    _timer.set_message(format!("Fetched {} new commits from '{}' with tag option {:?}", new_commits, remote_name, tags));
+    Ok(new_commits)
+}
+
+/// Fetches an arbitrary set of refspecs from the named remote. An empty
+/// list means the remote's configured refspecs.
+pub fn fetch_refspecs(repo: &Repository, remote_name: &str, refspecs: &[&str], tags: TagFetch) -> Result<(), OafError> {
+    fetch_with(repo, remote_name, refspecs, tags, &Cancel::new(), |_| {})
+}
+
+fn fetch_with<F>(repo: &Repository, remote_name: &str, refspecs: &[&str], tags: TagFetch, cancel: &Cancel, on_progress: F) -> Result<(), OafError>
+    where F: FnMut(TransferProgress)
+{
+    cancel.check()?;
+    network::check_online()?;
+    let config = repo.config()?;
+    let mut remote = repo.find_remote(remote_name)?;
+    let mut callbacks = make_callbacks(&config);
+    report_transfer(&mut callbacks, cancel, on_progress);
+    let mut opts = FetchOptions::new();
+    opts.remote_callbacks(callbacks);
+    opts.proxy_options(network::proxy_options(&config, remote_name, remote.url().unwrap_or("")));
+    opts.download_tags(tags.autotag_option());
+    match remote.fetch(refspecs, Some(&mut opts), None) {
+        Err(_) if cancel.is_cancelled() => Err(OafError::Cancelled),
+        result => result.map_err(OafError::from_remote),
+    }
+}
+
+/// Clones the repository at `url` into the directory `path`, which must not
+/// exist or be empty, checking out the remote's default branch. Credentials
+/// and proxies come from the user's global git config.
+pub fn clone_cancellable<F>(url: &str, path: &Path, cancel: &Cancel, on_progress: F) -> Result<Repository, OafError>
+    where F: FnMut(TransferProgress)
+{
+    let _timer = timer!("clone");
+    cancel.check()?;
+    network::check_online()?;
+    let config = Config::open_default()?;
+    let mut callbacks = make_callbacks(&config);
+    report_transfer(&mut callbacks, cancel, on_progress);
+    let mut opts = FetchOptions::new();
+    opts.remote_callbacks(callbacks);
+    opts.proxy_options(network::proxy_options(&config, "origin", url));
+    let result = match RepoBuilder::new().fetch_options(opts).clone(url, path) {
+        Err(_) if cancel.is_cancelled() => Err(OafError::Cancelled),
+        result => result.map_err(OafError::from_remote),
+    };
+    _timer.set_message(format!("Cloned '{}' into '{}'", url, path.display()));
+    result
+}
+
+/// Passes the progress of a download to `on_progress`, and abandons the
+/// download once `cancel` is set: returning false from the callback makes
+/// libgit2 stop.
+fn report_transfer<'a, F>(callbacks: &mut RemoteCallbacks<'a>, cancel: &'a Cancel, mut on_progress: F)
+    where F: FnMut(TransferProgress) + 'a
+{
+    callbacks.transfer_progress(move |progress| {
+        on_progress(TransferProgress {
+            received_objects: progress.received_objects(),
+            total_objects: progress.total_objects(),
+            received_bytes: progress.received_bytes(),
+        });
+        !cancel.is_cancelled()
+    });
+}
+
+/// How much of a fetch has been downloaded so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferProgress {
+    pub received_objects: usize,
+    pub total_objects: usize,
+    pub received_bytes: usize,
+}
+
+impl fmt::Display for TransferProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Received {} of {} objects ({} KiB).", self.received_objects, self.total_objects, self.received_bytes / 1024)
+    }
+}
+
+/// Returns the commits that the remote-tracking branches of `remote_name`
+/// point at, keyed by full ref name.
+pub fn tracking_tips(repo: &Repository, remote_name: &str) -> Result<HashMap<String, Oid>, Error> {
+    let mut tips = HashMap::new();
+    for reference in repo.references_glob(&format!("refs/remotes/{}/*", remote_name))? {
+        let reference = reference?;
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            tips.insert(name.to_string(), target);
+        }
+    }
+
+    Ok(tips)
+}
+
+/// Counts the commits reachable from the `after` tips that were not reachable
+/// from the `before` tips.
+pub fn count_new_commits(repo: &Repository, before: &HashMap<String, Oid>, after: &HashMap<String, Oid>) -> Result<usize, Error> {
+    let mut revwalk = repo.revwalk()?;
+    let mut any = false;
+    for (name, oid) in after {
+        if before.get(name) != Some(oid) {
+            revwalk.push(*oid)?;
+            any = true;
+        }
+    }
+
+    if !any {
+        return Ok(0);
+    }
+
+    for oid in before.values() {
+        // The old tip may have been garbage collected after a forced update.
+        let _ = revwalk.hide(*oid);
+    }
+
+    Ok(revwalk.count())
+}
+
+/// Returns the names of all tags in the repository, sorted.
+pub fn tag_names(repo: &Repository) -> Result<Vec<String>, Error> {
+    let tags = repo.tag_names(None)?;
+    let mut names: Vec<String> = tags.iter().filter_map(|t| t).map(|t| t.to_string()).collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Pushes a single tag to the named remote.
+pub fn push_tag(repo: &Repository, remote_name: &str, tag: &str) -> Result<(), OafError> {
+    let refspec = format!("refs/tags/{0}:refs/tags/{0}", tag);
+    push_refspecs(repo, remote_name, &[&refspec])
+}
+
+/// Pushes every tag to the named remote, i.e. 'git push <remote> --tags'.
+pub fn push_all_tags(repo: &Repository, remote_name: &str) -> Result<(), OafError> {
+    push_refspecs(repo, remote_name, &["refs/tags/*:refs/tags/*"])
+}
+
+/// Deletes `branch` on the named remote by pushing an empty source to it,
+/// i.e. the equivalent of 'git push <remote> :<branch>'. The local
+/// remote-tracking branch is removed as well.
+pub fn delete_remote_branch(repo: &Repository, remote_name: &str, branch: &str) -> Result<(), OafError> {
+    let _timer = timer!("delete_remote_branch");
+    let refspec = format!(":{}", full_branch_ref(branch));
+    push_refspecs(repo, remote_name, &[&refspec])?;
+
+    let tracking_name = format!("{}/{}", remote_name, branch);
+    if let Ok(mut tracking) = repo.find_branch(&tracking_name, BranchType::Remote) {
+        tracking.delete()?;
+    }
+
+    _timer.set_message(format!("Deleted '{}' on '{}'", branch, remote_name));
+    Ok(())
+}
+
+/// Returns the local branches, by short name.
+pub fn local_branches(repo: &Repository) -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let Some(name) = branch.name()? {
+            names.push(name.to_string());
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the remote-tracking branches, in short 'remote/branch' form.
+pub fn remote_tracking_branches(repo: &Repository) -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+    for branch in repo.branches(Some(BranchType::Remote))? {
+        let (branch, _) = branch?;
+        if let Some(name) = branch.name()? {
+            if !name.ends_with("/HEAD") {
+                names.push(name.to_string());
+            }
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+/// Splits a short remote-tracking branch name such as 'origin/feature/x'
+/// into the remote and the branch name on that remote. The remote is
+/// matched against the configured remotes because both parts may contain '/'.
+pub fn split_tracking_name(repo: &Repository, tracking_name: &str) -> Option<(String, String)> {
+    split_tracking_name_impl(&remote_names(repo), tracking_name)
+}
+
+/// Works out which of the remote-tracking refs for `remote_name` no longer
+/// exist on the remote. This asks the remote but does not fetch, so
+/// the result can be shown to the user before anything is deleted.
+pub fn stale_tracking_refs(repo: &Repository, remote_name: &str) -> Result<Vec<String>, OafError> {
+    let _timer = timer!("stale_tracking_refs");
+    let remote_heads = remote_heads(repo, remote_name)?;
+
+    let prefix = format!("refs/remotes/{}/", remote_name);
+    let mut stale = Vec::new();
+    for reference in repo.references_glob(&format!("{}*", prefix))? {
+        let reference = reference?;
+        if let Some(name) = reference.name() {
+            let branch = &name[prefix.len()..];
+            if branch != "HEAD" && !remote_heads.contains(&full_branch_ref(branch)) {
+                stale.push(name.to_string());
+            }
+        }
+    }
+
+    _timer.set_message(format!("Found {} stale refs for '{}'", stale.len(), remote_name));
+    Ok(stale)
+}
+
+/// Lists the full names of the refs on the named remote, like 'git
+/// ls-remote'. This asks the remote but does not fetch.
+pub fn remote_heads(repo: &Repository, remote_name: &str) -> Result<Vec<String>, OafError> {
+    let output = ls_remote(repo, &[remote_name])?;
+    Ok(parse_ls_remote(&output))
+}
+
+/// Runs 'git ls-remote' with `args`, which name the remote, and returns
+/// what it prints. git is used rather than libgit2 because libgit2 cannot
+/// give credentials or a proxy when only connecting, so it fails for most
+/// real remotes. git runs in the background, where it must not stop to ask
+/// for a password or to accept a host key.
+pub fn ls_remote(repo: &Repository, args: &[&str]) -> Result<String, OafError> {
+    network::check_online()?;
+    let output = Command::new("git").arg("-C").arg(repo.path())
+        .arg("ls-remote").args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes")
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(OafError::Network(Error::from_str(&format!("git ls-remote failed: {}", String::from_utf8_lossy(&output.stderr).trim()))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The ref names in the output of 'git ls-remote', whose lines are e.g.
+/// '<oid>\trefs/heads/main'. The peeled '^{}' entries of annotated tags are
+/// left out.
+fn parse_ls_remote(output: &str) -> Vec<String> {
+    output.lines()
+        .filter_map(|line| line.splitn(2, '\t').nth(1))
+        .filter(|name| !name.ends_with("^{}"))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Deletes the given refs, typically the result of `stale_tracking_refs`.
+pub fn prune_refs(repo: &Repository, refs: &[String]) -> Result<(), Error> {
+    for name in refs {
+        repo.find_reference(name)?.delete()?;
+        info!("Pruned stale ref '{}'", name);
+    }
+
+    Ok(())
+}
+
+/// Builds a set of remote callbacks that can supply credentials, trying the
+/// ssh-agent for SSH urls and the configured credential helpers otherwise.
+/// libgit2 asks again each time the server rejects what it was given, so
+/// each method is only tried once; after that the connection fails rather
+/// than asking forever.
+pub fn make_callbacks<'a>(config: &'a Config) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut tried = CredentialType::empty();
+    callbacks.credentials(move |url, username_from_url, allowed| {
+        match next_credential(allowed, &mut tried) {
+            Some(kind) if kind == CredentialType::SSH_KEY => Cred::ssh_key_from_agent(username_from_url.unwrap_or("git")),
+            Some(kind) if kind == CredentialType::USER_PASS_PLAINTEXT => Cred::credential_helper(config, url, username_from_url),
+            Some(_) => Cred::default(),
+            None => Err(Error::from_str(&format!("Authentication to '{}' failed; the credentials available were all rejected.", url))),
+        }
+    });
+
+    callbacks
+}
+
+/// The kind of credential to offer next, out of those `allowed`, leaving out
+/// those already `tried` and adding it to them. None once every kind that
+/// can be offered has been tried.
+fn next_credential(allowed: CredentialType, tried: &mut CredentialType) -> Option<CredentialType> {
+    let next = [CredentialType::SSH_KEY, CredentialType::USER_PASS_PLAINTEXT, CredentialType::DEFAULT].iter()
+        .cloned()
+        .find(|&kind| allowed.contains(kind) && !tried.contains(kind))?;
+    tried.insert(next);
+    Some(next)
+}
+
+/// Converts a short branch name into a full ref name, e.g. 'master' becomes
+/// 'refs/heads/master'. Names that are already full are returned unchanged.
+pub fn full_branch_ref(branch: &str) -> String {
+    if branch.starts_with("refs/") {
+        branch.to_string()
+    } else {
+        format!("refs/heads/{}", branch)
+    }
+}
+
+/// The inverse of `full_branch_ref`.
+pub fn short_branch_name(reference: &str) -> &str {
+    if reference.starts_with("refs/heads/") {
+        &reference["refs/heads/".len()..]
+    } else {
+        reference
+    }
+}
+
+/// Inner helper function to make things testable.
+fn choose_remote(remotes: &[String], configured: Option<&str>) -> Option<String> {
+    if let Some(configured) = configured {
+        if remotes.iter().any(|r| r == configured) {
+            return Some(configured.to_string());
+        }
+    }
+
+    if remotes.iter().any(|r| r == "origin") {
+        return Some("origin".to_string());
+    }
+
+    remotes.first().cloned()
+}
+
+/// Inner helper function to make things testable.
+fn split_tracking_name_impl(remotes: &[String], tracking_name: &str) -> Option<(String, String)> {
+    // Prefer the longest matching remote, in case one remote's name is a
+    // prefix of another's, e.g. 'origin' and 'origin/mirror'.
+    remotes.iter()
+        .filter(|r| tracking_name.len() > r.len() + 1
+                    && tracking_name.starts_with(r.as_str())
+                    && tracking_name.as_bytes()[r.len()] == b'/')
+        .max_by_key(|r| r.len())
+        .map(|r| (r.clone(), tracking_name[r.len() + 1..].to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn next_credential_tries_each_kind_once() {
+        let mut tried = CredentialType::empty();
+        let allowed = CredentialType::SSH_KEY | CredentialType::USER_PASS_PLAINTEXT;
+        assert_eq!(next_credential(allowed, &mut tried), Some(CredentialType::SSH_KEY));
+        assert_eq!(next_credential(allowed, &mut tried), Some(CredentialType::USER_PASS_PLAINTEXT));
+        assert_eq!(next_credential(allowed, &mut tried), None);
+        assert_eq!(next_credential(CredentialType::DEFAULT, &mut tried), Some(CredentialType::DEFAULT));
+        assert_eq!(next_credential(CredentialType::DEFAULT, &mut tried), None);
+    }
+
+    #[test]
+    fn parse_ls_remote_lists_the_ref_names() {
+        let output = "0123456789abcdef0123456789abcdef01234567\tHEAD\n\
+                      0123456789abcdef0123456789abcdef01234567\trefs/heads/main\n\
+                      89abcdef0123456789abcdef0123456789abcdef\trefs/tags/v1.0\n\
+                      0123456789abcdef0123456789abcdef01234567\trefs/tags/v1.0^{}\n";
+        assert_eq!(parse_ls_remote(output), names(&["HEAD", "refs/heads/main", "refs/tags/v1.0"]));
+        assert!(parse_ls_remote("").is_empty());
+    }
+
+    #[test]
+    fn full_branch_ref_works() {
+        assert_eq!(full_branch_ref("master"), "refs/heads/master");
+        assert_eq!(full_branch_ref("feature/x"), "refs/heads/feature/x");
+        assert_eq!(full_branch_ref("refs/heads/master"), "refs/heads/master");
+    }
+
+    #[test]
+    fn short_branch_name_works() {
+        assert_eq!(short_branch_name("refs/heads/master"), "master");
+        assert_eq!(short_branch_name("refs/heads/feature/x"), "feature/x");
+        assert_eq!(short_branch_name("master"), "master");
+    }
+
+    #[test]
+    fn choose_remote_prefers_configured_remote() {
+        let remotes = names(&["origin", "upstream"]);
+        assert_eq!(choose_remote(&remotes, Some("upstream")), Some("upstream".to_string()));
+    }
+
+    #[test]
+    fn choose_remote_ignores_configured_remote_that_does_not_exist() {
+        let remotes = names(&["upstream", "origin"]);
+        assert_eq!(choose_remote(&remotes, Some("fork")), Some("origin".to_string()));
+    }
+
+    #[test]
+    fn choose_remote_falls_back_to_first_remote() {
+        let remotes = names(&["upstream", "fork"]);
+        assert_eq!(choose_remote(&remotes, None), Some("upstream".to_string()));
+        assert_eq!(choose_remote(&[], None), None);
+    }
+
+    #[test]
+    fn parse_push_targets_splits_and_dedups() {
+        assert_eq!(parse_push_targets("origin, mirror  backup,origin"), names(&["origin", "mirror", "backup"]));
+        assert!(parse_push_targets(" , ").is_empty());
+    }
+
+    #[test]
+    fn describe_push_results_lists_each_remote() {
+        let results = vec![
+            ("origin".to_string(), Ok(())),
+            ("mirror".to_string(), Err(OafError::Network(Error::from_str("timed out")))),
+        ];
+        assert_eq!(describe_push_results("main", &results),
+                   "Pushed 'main' to 1 of 2 remotes.\n\norigin: pushed\nmirror: failed: timed out");
+    }
+
+    #[test]
+    fn split_tracking_name_impl_works() {
+        let remotes = names(&["origin", "origin/mirror", "fork"]);
+        assert_eq!(split_tracking_name_impl(&remotes, "origin/master"),
+                   Some(("origin".to_string(), "master".to_string())));
+        assert_eq!(split_tracking_name_impl(&remotes, "origin/mirror/feature/x"),
+                   Some(("origin/mirror".to_string(), "feature/x".to_string())));
+        assert_eq!(split_tracking_name_impl(&remotes, "upstream/master"), None);
+        assert_eq!(split_tracking_name_impl(&remotes, "fork"), None);
+        assert_eq!(split_tracking_name_impl(&remotes, "fork/"), None);
+    }
+}
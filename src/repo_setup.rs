@@ -0,0 +1,178 @@
+//! The setup applied to every repository oaf creates or clones, from the
+//! 'new_repo' settings. Once the repository is open it is run through a
+//! pipeline of steps: git settings, the identity profile, hooks, and 'git lfs
+//! install'. A step that fails is reported but does not stop the ones after
+//! it, since a repository with some of its setup is more use than none.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use git2::{Error, Repository};
+use config::{IdentityProfile, NewRepoConfig};
+use error::OafError;
+use identity::{self, Applied};
+use paths;
+
+/// The steps of the pipeline, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    GitConfig,
+    Identity,
+    Hooks,
+    LfsInstall,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Step::GitConfig => "Git settings",
+            Step::Identity => "Identity",
+            Step::Hooks => "Hooks",
+            Step::LfsInstall => "Git LFS",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What one step did, or why it failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepReport {
+    pub step: Step,
+    pub result: Result<String, String>,
+}
+
+impl fmt::Display for StepReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.result {
+            Ok(ref done) => write!(f, "{}: {}", self.step, done),
+            Err(ref message) => write!(f, "{}: FAILED, {}", self.step, message),
+        }
+    }
+}
+
+/// The steps `config` asks for; those with nothing to do are left out.
+pub fn steps(config: &NewRepoConfig) -> Vec<Step> {
+    let mut steps = Vec::new();
+    if !config.git_config.is_empty() {
+        steps.push(Step::GitConfig);
+    }
+    if config.identity.is_some() {
+        steps.push(Step::Identity);
+    }
+    if config.hooks_dir.is_some() {
+        steps.push(Step::Hooks);
+    }
+    if config.lfs_install {
+        steps.push(Step::LfsInstall);
+    }
+    steps
+}
+
+/// Runs each step that `config` asks for on the newly opened `repo`. An
+/// empty result means there was nothing to do.
+pub fn run(repo: &Repository, config: &NewRepoConfig, profiles: &[IdentityProfile]) -> Vec<StepReport> {
+    let _timer = timer!("repo_setup::run");
+    let reports: Vec<StepReport> = steps(config).into_iter()
+        .map(|step| {
+            let result = match step {
+                Step::GitConfig => set_git_config(repo, config),
+                Step::Identity => set_identity(repo, config, profiles),
+                Step::Hooks => install_hooks(repo, config),
+                Step::LfsInstall => lfs_install(repo),
+            };
+            let report = StepReport { step, result: result.map_err(|e| e.to_string()) };
+            info!("Repository setup of '{}': {}", repo.path().display(), report);
+            report
+        })
+        .collect();
+    _timer.set_message(format!("{} steps for '{}'", reports.len(), repo.path().display()));
+    reports
+}
+
+fn set_git_config(repo: &Repository, config: &NewRepoConfig) -> Result<String, OafError> {
+    let mut git_config = repo.config()?;
+    for (name, value) in &config.git_config {
+        git_config.set_str(name, value)?;
+    }
+    Ok(format!("set {} settings", config.git_config.len()))
+}
+
+fn set_identity(repo: &Repository, config: &NewRepoConfig, profiles: &[IdentityProfile]) -> Result<String, OafError> {
+    let name = config.identity.as_ref().map(|n| n.as_str()).unwrap_or("");
+    let profile = profiles.iter().find(|p| p.name == name)
+        .ok_or_else(|| Error::from_str(&format!("there is no identity profile called '{}'", name)))?;
+    identity::choose(repo, Some(name))?;
+    match identity::apply(repo, profile)? {
+        Applied::KeptLocal(own) => Ok(format!("kept the repository's own identity, {}, over '{}'", own, name)),
+        _ => Ok(format!("committing as {} <{}> ({})", profile.user_name, profile.email, name)),
+    }
+}
+
+/// Copies each file in the hooks directory into the repository's hooks
+/// directory, replacing any hook of the same name, and makes it executable.
+fn install_hooks(repo: &Repository, config: &NewRepoConfig) -> Result<String, OafError> {
+    let source = config.hooks_dir.as_ref().map(paths::expand_tilde).unwrap_or_default();
+    let target = repo.path().join("hooks");
+    fs::create_dir_all(&target)?;
+
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&source)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let destination = target.join(entry.file_name());
+        fs::copy(entry.path(), &destination)?;
+        make_executable(&destination)?;
+        names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+
+    names.sort();
+    match names.len() {
+        0 => Ok(format!("'{}' has no hooks in it", source.display())),
+        _ => Ok(format!("installed {}", names.join(", "))),
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), OafError> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    Ok(fs::set_permissions(path, permissions)?)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), OafError> {
+    Ok(())
+}
+
+/// libgit2 knows nothing of LFS, so this runs the git program, which needs
+/// git-lfs to be installed.
+fn lfs_install(repo: &Repository) -> Result<String, OafError> {
+    let dir = repo.workdir().unwrap_or_else(|| repo.path());
+    let output = Command::new("git").arg("-C").arg(dir).args(&["lfs", "install", "--local"]).output()?;
+    if output.status.success() {
+        Ok("installed the LFS hooks and filters".to_string())
+    } else {
+        Err(OafError::Git(Error::from_str(&format!("git lfs install failed: {}", String::from_utf8_lossy(&output.stderr).trim()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn steps_leaves_out_those_with_nothing_to_do() {
+        assert!(steps(&NewRepoConfig::default()).is_empty());
+
+        let mut config = NewRepoConfig::default();
+        config.hooks_dir = Some(PathBuf::from("/hooks"));
+        config.lfs_install = true;
+        config.git_config.insert("pull.rebase".to_string(), "true".to_string());
+        assert_eq!(steps(&config), vec![Step::GitConfig, Step::Hooks, Step::LfsInstall]);
+    }
+}
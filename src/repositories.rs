@@ -1,7 +1,11 @@
-use std::path::Path;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
 use std::ops::Index;
 use std::slice;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use git2::{Repository, RepositoryOpenFlags};
+use error::OafError;
 use mru_list::MruList;
 use paths;
 
@@ -11,7 +15,9 @@ pub trait RepositoryExtensions {
 
 impl RepositoryExtensions for Repository {
     fn display_name(&self) -> String {
-        let path = self.workdir().unwrap_or(self.path());
+        // libgit2 gives paths with '/' even on Windows; rebuilding the path
+        // from its components uses the platform's separator.
+        let path: PathBuf = self.workdir().unwrap_or(self.path()).components().collect();
         let compressed_path = paths::compress_tilde(path);
         compressed_path.display().to_string()
     }
@@ -19,14 +25,51 @@ impl RepositoryExtensions for Repository {
 
 pub struct Repositories {
     pub mru: MruList,
-    pub repos: Vec<Repository>
+    pub repos: Vec<Repository>,
+    current: usize,
+    // Directories that are still being opened in the background.
+    pending: Vec<PathBuf>,
 }
 
 impl Repositories {
     pub fn new(mru: MruList) -> Self {
         Repositories {
             mru: mru,
-            repos: Vec::new()
+            repos: Vec::new(),
+            current: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns the repository the user is currently working with, if any
+    /// repositories are open.
+    pub fn current(&self) -> Option<&Repository> {
+        self.repos.get(self.current)
+    }
+
+    /// Makes the next repository current, wrapping around at the end.
+    pub fn select_next(&mut self) {
+        if !self.repos.is_empty() {
+            self.current = (self.current + 1) % self.repos.len();
+        }
+    }
+
+    /// Makes the previous repository current, wrapping around at the start.
+    pub fn select_previous(&mut self) {
+        if !self.repos.is_empty() {
+            self.current = (self.current + self.repos.len() - 1) % self.repos.len();
+        }
+    }
+
+    /// Makes the repository at `path`, its .git directory or working tree,
+    /// current. Returns false if it is not open.
+    pub fn select<P>(&mut self, path: P) -> bool
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        match self.repos.iter().position(|repo| repo.path() == path || repo.workdir() == Some(path)) {
+            Some(index) => { self.current = index; true },
+            None => false,
         }
     }
 
@@ -41,40 +84,77 @@ impl Repositories {
         self.repos.iter().any(|repo| repo.path() == path || repo.workdir() == Some(path))
     }
 
-    pub fn open<P>(&mut self, path: P) -> Option<&Repository>
+    /// Records that the directory at `path` is going to be opened in the
+    /// background, so that it can be shown as a placeholder meanwhile.
+    pub fn add_pending<P>(&mut self, path: P)
+        where P: AsRef<Path>
+    {
+        self.pending.push(path.as_ref().to_path_buf());
+    }
+
+    /// The directories still being opened, in the order they were added.
+    pub fn pending(&self) -> &[PathBuf] {
+        &self.pending
+    }
+
+    /// Takes the result of opening the directory at `path`, which need not
+    /// have been pending, and adds the repository unless it is already open.
+    /// The MRU list is updated but not saved, see `save_mru`.
+    pub fn finish_open<P>(&mut self, path: P, result: Result<Repository, OafError>) -> Result<&Repository, OafError>
         where P: AsRef<Path>
     {
         // Do not allow a repository to be opened more than once. This is not
         // actually sufficient, because we may search up for the actual path
         // (i.e. we may start oaf in a subdirectory of the repository).
         let path = path.as_ref();
-        if self.repo_is_open(path) {
-            warn!("The repository at path '{}' is already open, ignoring.", path.display());
-            return None;
+        self.pending.retain(|p| p != path);
+        let repo = result?;
+        if self.repo_is_open(path) || self.repo_is_open(repo.path()) {
+            return Err(OafError::AlreadyOpen(path.to_path_buf()));
         }
 
-        match Repository::open_ext(path, RepositoryOpenFlags::empty(), vec![::PATHS.home_dir()]) {
-            Ok(repo) => {
-                if self.repo_is_open(repo.path()) {
-                    warn!("The repository at path '{}' is already open, ignoring.", path.display());
-                    return None;
-                }
-
-                info!("Successfully opened Git repository at '{}'", repo.path().display());
-                self.repos.push(repo);
-                self.mru.insert(path);
-                if let Err(e) = self.mru.write_to_file() {
-                    warn!("Error writing to MRU file '{}', ignoring. Error = {}", self.mru.filename().display(), e);
-                }
-
-                return Some(&self.repos[self.repos.len() - 1]);
-            },
-            Err(e) => {
-                warn!("Failed to initialize repository, ignoring: {}", e);
-            }
-        }
+        info!("Successfully opened Git repository at '{}'", repo.path().display());
+        self.repos.push(repo);
+        self.mru.insert(path);
+        Ok(&self.repos[self.repos.len() - 1])
+    }
+
+    /// Writes the MRU list to its file.
+    pub fn save_mru(&mut self) -> Result<(), OafError> {
+        Ok(self.mru.write_to_file()?)
+    }
+}
+
+/// Opens the repository containing `path`, searching up from it but not
+/// beyond the home directory. This can take a while on a slow file system,
+/// so the time taken is logged.
+pub fn open_repository(path: &Path) -> Result<Repository, OafError> {
+    let _timer = timer!("repositories::open_repository");
+    _timer.set_message(path.display().to_string());
+    Repository::open_ext(path, RepositoryOpenFlags::empty(), vec![::PATHS.home_dir()])
+        .map_err(|error| OafError::RepoOpen { path: path.to_path_buf(), error })
+}
+
+/// Opens the repositories at `paths` on up to `workers` background threads,
+/// calling `on_open` with each result as it completes.
+pub fn open_in_background<F>(paths: Vec<PathBuf>, workers: usize, on_open: F)
+    where F: Fn(PathBuf, Result<Repository, OafError>) + Send + Sync + 'static
+{
+    let workers = workers.min(paths.len());
+    let queue = Arc::new(Mutex::new(paths.into_iter().collect::<VecDeque<_>>()));
+    let on_open = Arc::new(on_open);
 
-        None
+    for _ in 0..workers {
+        let queue = queue.clone();
+        let on_open = on_open.clone();
+        thread::spawn(move || loop {
+            let path = match queue.lock().unwrap().pop_front() {
+                Some(path) => path,
+                None => break,
+            };
+            let result = open_repository(&path);
+            on_open(path, result);
+        });
     }
 }
 
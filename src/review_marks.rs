@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use git2::Oid;
+use toml;
+
+/// What the reviewer thought of a commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReviewState {
+    Reviewed,
+    NeedsWork,
+}
+
+impl ReviewState {
+    pub fn all() -> &'static [ReviewState] {
+        &[ReviewState::Reviewed, ReviewState::NeedsWork]
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ReviewState::Reviewed => "Reviewed",
+            ReviewState::NeedsWork => "Needs work",
+        }
+    }
+
+    /// The character shown against the commit in the log view.
+    pub fn indicator(self) -> char {
+        match self {
+            ReviewState::Reviewed => '+',
+            ReviewState::NeedsWork => '!',
+        }
+    }
+}
+
+/// A mark on one commit. Marks are the user's own notes and are never
+/// written into the repository.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewMark {
+    /// The repository's .git directory.
+    pub repo: PathBuf,
+    /// The commit's full id. Oids cannot be serialized, so it is kept as text.
+    pub commit: String,
+    /// The commit's summary when it was marked, so that a mark can still be
+    /// described after the commit has been rewritten away.
+    pub summary: String,
+    pub state: ReviewState,
+    pub comment: String,
+    /// When it was marked, in seconds since the epoch.
+    pub time: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct ReviewFile {
+    mark: Vec<ReviewMark>,
+}
+
+/// The review marks on commits in every repository, kept in 'reviews.toml'
+/// in the data directory. Each change is saved straight away, since a lost
+/// comment cannot be got back.
+pub struct ReviewMarks {
+    file: PathBuf,
+    marks: Vec<ReviewMark>,
+    /// Why the file could not be loaded, if it could not. The marks are not
+    /// saved then, since that would overwrite the ones in the file.
+    load_error: Option<String>,
+}
+
+impl ReviewMarks {
+    /// Loads the marks from `file`, if it exists. A file that cannot be read
+    /// or parsed gives no marks, is copied to '<file>.broken' if it can be,
+    /// and is never overwritten: `save` fails until oaf is restarted.
+    pub fn load(file: &Path) -> Self {
+        let (marks, load_error) = match fs::read_to_string(file) {
+            Ok(text) => match toml::from_str::<ReviewFile>(&text) {
+                Ok(review_file) => (review_file.mark, None),
+                Err(e) => {
+                    warn!("Error reading review marks from '{}', ignoring them. Error = {}", file.display(), e);
+                    (Vec::new(), Some(Self::keep_broken(file, &e.to_string())))
+                },
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (Vec::new(), None),
+            Err(e) => {
+                warn!("Cannot read review marks from '{}': {}", file.display(), e);
+                (Vec::new(), Some(format!("Cannot read '{}': {}.", file.display(), e)))
+            },
+        };
+
+        ReviewMarks { file: file.to_path_buf(), marks, load_error }
+    }
+
+    /// Copies a file that cannot be parsed out of the way, and describes the
+    /// problem.
+    fn keep_broken(file: &Path, error: &str) -> String {
+        let mut copy = file.as_os_str().to_owned();
+        copy.push(".broken");
+        let copy = PathBuf::from(copy);
+        match fs::copy(file, &copy) {
+            Ok(_) => format!("Cannot read '{}': {}. A copy has been saved as '{}'.", file.display(), error, copy.display()),
+            Err(e) => {
+                warn!("Cannot copy '{}' to '{}': {}", file.display(), copy.display(), e);
+                format!("Cannot read '{}': {}.", file.display(), error)
+            },
+        }
+    }
+
+    /// Why the marks could not be loaded, if they could not.
+    pub fn load_error(&self) -> Option<&str> {
+        self.load_error.as_ref().map(|e| e.as_str())
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(ref error) = self.load_error {
+            return Err(io::Error::new(io::ErrorKind::Other, format!(
+                "{} Review marks are not saved until it is fixed or removed and oaf is restarted.", error)));
+        }
+        let text = toml::to_string(&ReviewFile { mark: self.marks.clone() })
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.file, text)
+    }
+
+    pub fn get(&self, repo: &Path, oid: Oid) -> Option<&ReviewMark> {
+        let commit = oid.to_string();
+        self.marks.iter().find(|m| m.repo == repo && m.commit == commit)
+    }
+
+    /// Marks the commit `oid` of the repository at `repo`, replacing any
+    /// earlier mark, and saves the marks.
+    pub fn set(&mut self, repo: &Path, oid: Oid, summary: &str, state: ReviewState, comment: &str, now: i64) -> io::Result<()> {
+        self.remove(repo, oid);
+        self.marks.push(ReviewMark {
+            repo: repo.to_path_buf(),
+            commit: oid.to_string(),
+            summary: summary.to_string(),
+            state,
+            comment: comment.trim().to_string(),
+            time: now,
+        });
+        self.save()
+    }
+
+    /// Removes the mark on a commit, if it has one, and saves the marks.
+    pub fn clear(&mut self, repo: &Path, oid: Oid) -> io::Result<bool> {
+        if !self.remove(repo, oid) {
+            return Ok(false);
+        }
+        self.save().map(|_| true)
+    }
+
+    fn remove(&mut self, repo: &Path, oid: Oid) -> bool {
+        let commit = oid.to_string();
+        let before = self.marks.len();
+        self.marks.retain(|m| !(m.repo == repo && m.commit == commit));
+        self.marks.len() != before
+    }
+
+    /// The state of each marked commit in the repository at `repo`.
+    pub fn states(&self, repo: &Path) -> HashMap<Oid, ReviewState> {
+        self.marks.iter()
+            .filter(|m| m.repo == repo)
+            .filter_map(|m| Oid::from_str(&m.commit).ok().map(|oid| (oid, m.state)))
+            .collect()
+    }
+
+    /// The commits in the repository at `repo` that need work, oldest mark first.
+    pub fn pending(&self, repo: &Path) -> Vec<&ReviewMark> {
+        let mut pending: Vec<_> = self.marks.iter()
+            .filter(|m| m.repo == repo && m.state == ReviewState::NeedsWork)
+            .collect();
+        pending.sort_by_key(|m| m.time);
+        pending
+    }
+}
+
+/// Writes out the comments on commits that need work as Markdown, a section
+/// for each commit, ready to be pasted into a review or an email.
+pub fn format_pending(pending: &[&ReviewMark]) -> String {
+    let mut text = String::new();
+    for mark in pending {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(&format!("## {:.7} {}\n\n", mark.commit, mark.summary));
+        if mark.comment.is_empty() {
+            text.push_str("Needs work.\n");
+        } else {
+            text.push_str(&mark.comment);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mark(commit: &str, state: ReviewState, comment: &str, time: i64) -> ReviewMark {
+        ReviewMark {
+            repo: PathBuf::from("/src/oaf/.git"),
+            commit: commit.to_string(),
+            summary: format!("Summary of {}", commit),
+            state,
+            comment: comment.to_string(),
+            time,
+        }
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let file = ReviewFile { mark: vec![
+            mark("0123456789abcdef0123456789abcdef01234567", ReviewState::NeedsWork, "Split this up.\nAnd add a test.", 1),
+            mark("1123456789abcdef0123456789abcdef01234567", ReviewState::Reviewed, "", 2),
+        ]};
+        let text = toml::to_string(&file).unwrap();
+        assert!(text.contains("state = \"needs-work\""));
+        assert_eq!(toml::from_str::<ReviewFile>(&text).unwrap().mark, file.mark);
+    }
+
+    #[test]
+    fn pending_comments_are_formatted_as_markdown() {
+        let first = mark("0123456789abcdef0123456789abcdef01234567", ReviewState::NeedsWork, "Split this up.", 1);
+        let second = mark("1123456789abcdef0123456789abcdef01234567", ReviewState::NeedsWork, "", 2);
+        assert_eq!(format_pending(&[&first, &second]),
+                   "## 0123456 Summary of 0123456789abcdef0123456789abcdef01234567\n\nSplit this up.\n\n\
+                    ## 1123456 Summary of 1123456789abcdef0123456789abcdef01234567\n\nNeeds work.\n");
+        assert_eq!(format_pending(&[]), "");
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use git2::{build::CheckoutBuilder, Commit, Error, Index, Oid, Repository, Sort};
+use backups;
+use commit_graph::CommitGraph;
+use error::OafError;
+use refs::{self, RefKind};
+use remotes;
+
+/// Fails if `commit` can be reached from any remote-tracking branch, since
+/// rewriting it would rewrite history that others may already have.
+pub fn check_unpushed(repo: &Repository, graph: &mut CommitGraph, commit: Oid) -> Result<(), Error> {
+    for label in refs::list_refs(repo)?.iter().filter(|l| l.kind == RefKind::RemoteBranch) {
+        if graph.is_ancestor(repo, commit, label.target)? {
+            return Err(Error::from_str(&format!(
+                "Commit {:.7} has already been pushed to '{}'; oaf will not rewrite published history.",
+                commit.to_string(), label.name)));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name of the branch HEAD is on, e.g. 'refs/heads/master'.
+/// History can only be rewritten on a branch.
+pub fn head_branch(repo: &Repository) -> Result<String, Error> {
+    let head = repo.head()?;
+    match head.name() {
+        Some(name) if head.is_branch() => Ok(name.to_string()),
+        _ => Err(Error::from_str("HEAD is detached; check out a branch first.")),
+    }
+}
+
+/// Returns the commits from `ancestor` (exclusive) to `tip` (inclusive),
+/// oldest first. The history in between must be a straight line, since
+/// rewriting merges is not supported.
+pub fn linear_range(repo: &Repository, ancestor: Oid, tip: Oid) -> Result<Vec<Oid>, Error> {
+    let mut commits = Vec::new();
+    let mut current = repo.find_commit(tip)?;
+    while current.id() != ancestor {
+        if current.parents().len() != 1 {
+            return Err(Error::from_str(&format!(
+                "Commit {:.7} is a merge or a root commit; only linear history can be rewritten.",
+                current.id().to_string())));
+        }
+        commits.push(current.id());
+        current = current.parent(0)?;
+    }
+
+    commits.reverse();
+    Ok(commits)
+}
+
+/// Points `branch` (a full ref name) at `new_tip`, first saving its current
+/// tip in a backup. `operation` describes what is being done, for the backup
+/// and the reflog. Returns the name of the backup.
+pub fn move_branch(repo: &Repository, branch: &str, new_tip: Oid, operation: &str) -> Result<String, Error> {
+    let old_tip = repo.refname_to_id(branch)?;
+    let backup = backups::create(repo, &[(remotes::short_branch_name(branch), old_tip)], operation)?;
+    repo.reference(branch, new_tip, true, &format!("oaf: {}", operation))?;
+    Ok(backup)
+}
+
+/// Moves `branch` (a full ref name) as `move_branch` does and then, if it is
+/// checked out, updates the index and working tree to match. If that fails,
+/// say because it would overwrite local changes, the branch is put back
+/// where it was and the error returned. Returns the name of the backup.
+pub fn move_checked_out_branch(repo: &Repository, branch: &str, new_tip: Oid, operation: &str) -> Result<String, Error> {
+    let checked_out = repo.head().ok().and_then(|h| h.name().map(|n| n == branch)).unwrap_or(false);
+    if !checked_out {
+        return move_branch(repo, branch, new_tip, operation);
+    }
+
+    // The checkout compares the working tree with HEAD, so HEAD is left at
+    // the old tip while the branch moves.
+    let old_tip = repo.refname_to_id(branch)?;
+    repo.set_head_detached(old_tip)?;
+    let moved = move_branch(repo, branch, new_tip, operation).and_then(|backup| {
+        let checkout = repo.find_object(new_tip, None)
+            .and_then(|target| repo.checkout_tree(&target, Some(CheckoutBuilder::new().safe())));
+        match checkout {
+            Ok(()) => Ok(backup),
+            Err(e) => {
+                repo.reference(branch, old_tip, true, &format!("oaf: undo {}", operation))?;
+                Err(e)
+            },
+        }
+    });
+    let reattached = repo.set_head(branch);
+    let backup = moved?;
+    reattached?;
+    Ok(backup)
+}
+
+/// Recreates `commits` (oldest first) on top of `new_parent`, keeping their
+/// trees, messages, authors and committers, including the committer dates.
+/// Because each commit records a snapshot rather than a change, this is only
+/// correct when the new parent has the same tree as the old parent of the
+/// first commit. Returns the id of the last new commit, or `new_parent` if
+/// there are no commits.
+pub fn replay(repo: &Repository, commits: &[Oid], new_parent: Oid) -> Result<Oid, Error> {
+    let mut parent = new_parent;
+    for &oid in commits {
+        let commit = repo.find_commit(oid)?;
+        parent = copy_commit(repo, &commit, &repo.find_commit(parent)?)?;
+    }
+    Ok(parent)
+}
+
+/// Creates a copy of `commit` with a new parent.
+fn copy_commit(repo: &Repository, commit: &Commit, parent: &Commit) -> Result<Oid, Error> {
+    let message = String::from_utf8_lossy(commit.message_bytes());
+    repo.commit(None, &commit.author(), &commit.committer(), &message, &commit.tree()?, &[parent])
+}
+
+/// Changes the message of `commit`, which must be on the current branch and
+/// not pushed, and replays the commits after it. The reworded commit gets the
+/// current user as committer; the replayed commits keep their original
+/// committers and dates. The working tree and index are not touched, as no
+/// tree changes. Returns the id of the reworded commit.
+pub fn reword(repo: &Repository, graph: &mut CommitGraph, commit: Oid, message: &str) -> Result<Oid, Error> {
+    let _timer = timer!("rewrite::reword");
+    let message = clean_message(message).ok_or_else(|| Error::from_str("The commit message is empty."))?;
+    let branch = head_branch(repo)?;
+    let head = repo.refname_to_id(&branch)?;
+    check_unpushed(repo, graph, commit)?;
+
+    if commit != head && !repo.graph_descendant_of(head, commit)? {
+        return Err(Error::from_str(&format!("Commit {:.7} is not on the current branch.", commit.to_string())));
+    }
+
+    let original = repo.find_commit(commit)?;
+    if original.parents().len() > 1 {
+        return Err(Error::from_str("Merge commits cannot be reworded."));
+    }
+    let parent = original.parent(0)
+        .map_err(|_| Error::from_str("The root commit cannot be reworded."))?;
+    let later = linear_range(repo, commit, head)?;
+
+    let reworded = repo.commit(None, &original.author(), &repo.signature()?, &message, &original.tree()?, &[&parent])?;
+    let new_head = replay(repo, &later, reworded)?;
+    move_branch(repo, &branch, new_head, &format!("reword {:.7}", commit.to_string()))?;
+
+    _timer.set_message(format!("Reworded {} as {}, replayed {} commits", commit, reworded, later.len()));
+    Ok(reworded)
+}
+
+/// One of the commits a commit is split into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitPart {
+    pub paths: Vec<PathBuf>,
+    pub message: String,
+}
+
+/// Returns the paths changed by `commit` relative to its first parent.
+pub fn changed_paths(repo: &Repository, commit: Oid) -> Result<Vec<PathBuf>, Error> {
+    let commit = repo.find_commit(commit)?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+
+    Ok(diff.deltas()
+        .filter_map(|d| d.new_file().path().or_else(|| d.old_file().path()).map(|p| p.to_path_buf()))
+        .collect())
+}
+
+/// Checks the parts a commit changing `changed` is to be split into. Any
+/// changed paths not in a part are put into a final part with
+/// `remainder_message`. Returns the complete list of parts.
+pub fn plan_split(changed: &[PathBuf], parts: &[SplitPart], remainder_message: &str) -> Result<Vec<SplitPart>, String> {
+    let changed_set: HashSet<&PathBuf> = changed.iter().collect();
+    let mut used = HashSet::new();
+    for (i, part) in parts.iter().enumerate() {
+        if part.paths.is_empty() {
+            return Err(format!("Commit {} of the split has no files.", i + 1));
+        }
+        if clean_message(&part.message).is_none() {
+            return Err(format!("Commit {} of the split has no message.", i + 1));
+        }
+        for path in &part.paths {
+            if !changed_set.contains(path) {
+                return Err(format!("'{}' is not changed by the commit.", path.display()));
+            }
+            if !used.insert(path) {
+                return Err(format!("'{}' is in more than one commit.", path.display()));
+            }
+        }
+    }
+
+    let mut plan = parts.to_vec();
+    let remainder: Vec<PathBuf> = changed.iter().filter(|p| !used.contains(p)).cloned().collect();
+    if !remainder.is_empty() {
+        plan.push(SplitPart { paths: remainder, message: remainder_message.to_string() });
+    }
+
+    if plan.len() < 2 {
+        return Err("Splitting needs at least two commits; leave some files for the last one.".to_string());
+    }
+    Ok(plan)
+}
+
+/// Replaces `commit`, which must be on the current branch and not pushed,
+/// with a series of commits, each containing the changes to some of its
+/// files, then replays the commits after it. Files are not divided: all of a
+/// file's changes go into the same commit. The trees are built in memory, so
+/// the working tree and index are not touched; the final tree is the same as
+/// the original commit's. Files not in any of `parts` go into a final commit
+/// with the original message, as for `plan_split`. Returns the ids of the new
+/// commits.
+pub fn split(repo: &Repository, graph: &mut CommitGraph, commit: Oid, parts: &[SplitPart]) -> Result<Vec<Oid>, Error> {
+    let _timer = timer!("rewrite::split");
+    let branch = head_branch(repo)?;
+    let head = repo.refname_to_id(&branch)?;
+    check_unpushed(repo, graph, commit)?;
+    if commit != head && !repo.graph_descendant_of(head, commit)? {
+        return Err(Error::from_str(&format!("Commit {:.7} is not on the current branch.", commit.to_string())));
+    }
+
+    let original = repo.find_commit(commit)?;
+    if original.parents().len() != 1 {
+        return Err(Error::from_str("Only commits with a single parent can be split."));
+    }
+    let parent = original.parent(0)?;
+    let original_message = String::from_utf8_lossy(original.message_bytes()).into_owned();
+    let plan = plan_split(&changed_paths(repo, commit)?, parts, &original_message)
+        .map_err(|e| Error::from_str(&e))?;
+    let later = linear_range(repo, commit, head)?;
+
+    let mut target = Index::new()?;
+    target.read_tree(&original.tree()?)?;
+    let mut index = Index::new()?;
+    index.read_tree(&parent.tree()?)?;
+
+    let committer = repo.signature()?;
+    let mut new_commits = Vec::new();
+    let mut previous = parent;
+    for part in &plan {
+        for path in &part.paths {
+            match target.get_path(path, 0) {
+                Some(entry) => index.add(&entry)?,
+                None => index.remove(path, 0)?,
+            }
+        }
+
+        let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+        let message = clean_message(&part.message).unwrap_or_else(|| original_message.clone());
+        let oid = repo.commit(None, &original.author(), &committer, &message, &tree, &[&previous])?;
+        new_commits.push(oid);
+        previous = repo.find_commit(oid)?;
+    }
+
+    let new_head = replay(repo, &later, previous.id())?;
+    move_branch(repo, &branch, new_head, &format!("split {:.7}", commit.to_string()))?;
+
+    _timer.set_message(format!("Split {} into {} commits, replayed {} commits", commit, new_commits.len(), later.len()));
+    Ok(new_commits)
+}
+
+/// The outcome of squashing a branch.
+#[derive(Debug, Clone)]
+pub struct Squashed {
+    pub commit: Oid,
+    /// The name of the backup holding the branch's original tip.
+    pub backup: String,
+}
+
+/// Builds the default message for squashing the current branch onto `base`:
+/// the messages of the commits being squashed, oldest first.
+pub fn squash_message(repo: &Repository, base: Oid) -> Result<String, Error> {
+    let tip = repo.refname_to_id(&head_branch(repo)?)?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE);
+    revwalk.push(tip)?;
+    revwalk.hide(base)?;
+
+    let mut messages = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        messages.push(String::from_utf8_lossy(commit.message_bytes()).into_owned());
+    }
+    Ok(aggregate_messages(&messages))
+}
+
+/// Joins commit messages with a blank line between each.
+fn aggregate_messages(messages: &[String]) -> String {
+    messages.iter().filter_map(|m| clean_message(m)).collect::<Vec<_>>().join("\n")
+}
+
+/// Replaces the current branch with a single commit on top of `base` that
+/// contains all the branch's changes since it diverged from `base`. If
+/// `base` has moved on since then the changes are merged onto it, and the
+/// squash fails if they conflict. The working tree is updated, unless that
+/// would overwrite local changes, in which case nothing is done. The
+/// original tip is kept in a backup.
+pub fn squash_branch(repo: &Repository, base: Oid, message: &str) -> Result<Squashed, OafError> {
+    let _timer = timer!("rewrite::squash_branch");
+    let message = clean_message(message).ok_or_else(|| Error::from_str("The commit message is empty."))?;
+    let branch = head_branch(repo)?;
+    let tip = repo.refname_to_id(&branch)?;
+    let fork_point = repo.merge_base(base, tip)?;
+    if fork_point == tip {
+        return Err(Error::from_str("The branch has no commits that are not already in the base.").into());
+    }
+
+    let base_commit = repo.find_commit(base)?;
+    let tip_commit = repo.find_commit(tip)?;
+    let tree = if fork_point == base {
+        tip_commit.tree()?
+    } else {
+        let ancestor = repo.find_commit(fork_point)?.tree()?;
+        let mut merged = repo.merge_trees(&ancestor, &base_commit.tree()?, &tip_commit.tree()?, None)
+            .map_err(OafError::Merge)?;
+        if merged.has_conflicts() {
+            return Err(OafError::Merge(Error::from_str("The branch's changes conflict with the base; rebase the branch first.")));
+        }
+        repo.find_tree(merged.write_tree_to(repo)?)?
+    };
+
+    let signature = repo.signature()?;
+    let commit = repo.commit(None, &signature, &signature, &message, &tree, &[&base_commit])?;
+    let backup = move_checked_out_branch(repo, &branch, commit, &format!("squash onto {:.7}", base.to_string()))?;
+
+    _timer.set_message(format!("Squashed {} onto {} as {}", branch, base, commit));
+    Ok(Squashed { commit, backup })
+}
+
+/// Tidies a commit message the way 'git commit' does by default: trailing
+/// whitespace is removed from each line, leading and trailing blank lines are
+/// dropped and the message ends with a single newline. Returns None if
+/// nothing is left.
+pub fn clean_message(message: &str) -> Option<String> {
+    let lines: Vec<&str> = message.lines().map(|l| l.trim_end()).collect();
+    let start = lines.iter().position(|l| !l.is_empty())?;
+    let end = lines.iter().rposition(|l| !l.is_empty())?;
+
+    let mut cleaned = String::new();
+    let mut previous_blank = false;
+    for line in &lines[start..end + 1] {
+        // Runs of blank lines collapse to one.
+        if line.is_empty() && previous_blank {
+            continue;
+        }
+        previous_blank = line.is_empty();
+        cleaned.push_str(line);
+        cleaned.push('\n');
+    }
+    Some(cleaned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_message_works() {
+        assert_eq!(clean_message("\n\nSummary  \n\n\n\nBody\n\n"), Some("Summary\n\nBody\n".to_string()));
+        assert_eq!(clean_message("One line"), Some("One line\n".to_string()));
+    }
+
+    #[test]
+    fn aggregate_messages_works() {
+        let messages = vec!["First\n\nBody\n".to_string(), "  \n".to_string(), "Second".to_string()];
+        assert_eq!(aggregate_messages(&messages), "First\n\nBody\n\nSecond\n");
+    }
+
+    fn part(paths: &[&str], message: &str) -> SplitPart {
+        SplitPart { paths: paths.iter().map(PathBuf::from).collect(), message: message.to_string() }
+    }
+
+    #[test]
+    fn plan_split_adds_remainder() {
+        let changed: Vec<PathBuf> = ["a", "b", "c"].iter().map(PathBuf::from).collect();
+        let plan = plan_split(&changed, &[part(&["b"], "First")], "Rest").unwrap();
+        assert_eq!(plan, vec![part(&["b"], "First"), part(&["a", "c"], "Rest")]);
+    }
+
+    #[test]
+    fn plan_split_rejects_bad_parts() {
+        let changed: Vec<PathBuf> = ["a", "b"].iter().map(PathBuf::from).collect();
+        assert!(plan_split(&changed, &[part(&[], "Empty")], "Rest").is_err());
+        assert!(plan_split(&changed, &[part(&["a"], "  ")], "Rest").is_err());
+        assert!(plan_split(&changed, &[part(&["x"], "Unknown")], "Rest").is_err());
+        assert!(plan_split(&changed, &[part(&["a"], "One"), part(&["a"], "Two")], "Rest").is_err());
+        assert!(plan_split(&changed, &[part(&["a", "b"], "All")], "Rest").is_err());
+        assert!(plan_split(&changed, &[], "Rest").is_err());
+    }
+
+    #[test]
+    fn clean_message_rejects_blank() {
+        assert_eq!(clean_message(""), None);
+        assert_eq!(clean_message("  \n\t\n"), None);
+    }
+}
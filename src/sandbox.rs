@@ -0,0 +1,129 @@
+//! Merging and rebasing in a temporary linked worktree, made with 'git
+//! worktree add', so that the main working tree is never disturbed. The
+//! branch is only moved once the operation has finished cleanly; if it
+//! stops with conflicts the worktree is thrown away and nothing changes.
+//! libgit2 cannot make worktrees or run a rebase with git's hooks and merge
+//! drivers, so the git program does the work.
+
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use git2::{Error, Oid, Repository};
+use error::OafError;
+use history;
+use remotes;
+use rewrite;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The operations that can be run in a sandbox.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// Merge the commit named by the revision into the branch.
+    Merge(String),
+    /// Rebase the branch onto the commit named by the revision.
+    Rebase(String),
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operation::Merge(ref rev) => write!(f, "merge '{}'", rev),
+            Operation::Rebase(ref rev) => write!(f, "rebase onto '{}'", rev),
+        }
+    }
+}
+
+/// How a sandboxed operation ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The branch was moved to `new_tip`, after saving a backup.
+    Done { new_tip: Oid, backup: String },
+    /// The operation made no change, e.g. the branch was already up to date.
+    Unchanged,
+    /// The operation stopped with conflicts in `paths`; the branch has not
+    /// been changed. `output` is what git said.
+    Conflicts { paths: Vec<PathBuf>, output: String },
+}
+
+/// Runs `operation` on `branch` in a temporary worktree, then moves the
+/// branch to the result if it finished cleanly. A merge fast-forwards the
+/// branch; a rebase rewrites it, so a backup is taken first either way. If
+/// the branch is checked out its working tree is updated, unless that would
+/// overwrite local changes, in which case nothing changes.
+pub fn run(repo: &Repository, branch: &str, operation: &Operation) -> Result<Outcome, OafError> {
+    let _timer = timer!("sandbox::run");
+    let full_ref = remotes::full_branch_ref(branch);
+    let old_tip = repo.refname_to_id(&full_ref)?;
+    let target = match *operation {
+        Operation::Merge(ref rev) | Operation::Rebase(ref rev) => history::resolve_commit(repo, rev)?,
+    };
+
+    let dir = env::temp_dir().join(format!("oaf-sandbox-{}-{}", ::std::process::id(), NEXT_ID.fetch_add(1, Ordering::SeqCst)));
+    checked(git(repo.path(), &["worktree", "add", "--detach", &path_arg(&dir), &old_tip.to_string()])?, "git worktree add")?;
+    info!("Running {} on '{}' in the sandbox '{}'", operation, branch, dir.display());
+    let result = run_in(&dir, operation, target);
+    if let Err(e) = git(repo.path(), &["worktree", "remove", "--force", &path_arg(&dir)]) {
+        warn!("Cannot remove the sandbox worktree '{}', ignoring. Error = {}", dir.display(), e);
+    }
+
+    let new_tip = match result? {
+        Ok(new_tip) => new_tip,
+        Err(conflicts) => return Ok(conflicts),
+    };
+    if new_tip == old_tip {
+        return Ok(Outcome::Unchanged);
+    }
+    if repo.refname_to_id(&full_ref)? != old_tip {
+        return Err(OafError::Git(Error::from_str(&format!("'{}' moved while the {} was running; nothing has been changed.", branch, operation))));
+    }
+
+    let backup = rewrite::move_checked_out_branch(repo, &full_ref, new_tip, &format!("{} (sandboxed)", operation))?;
+    _timer.set_message(format!("{} on '{}' gave {}", operation, branch, new_tip));
+    Ok(Outcome::Done { new_tip, backup })
+}
+
+/// Runs the operation in the worktree at `dir`, giving the new tip, or the
+/// conflicts it stopped with.
+fn run_in(dir: &Path, operation: &Operation, target: Oid) -> Result<Result<Oid, Outcome>, OafError> {
+    let target = target.to_string();
+    let output = match *operation {
+        Operation::Merge(ref rev) => git(dir, &["merge", "--no-edit", "-m", &format!("Merge '{}'", rev), &target])?,
+        Operation::Rebase(_) => git(dir, &["rebase", &target])?,
+    };
+    if !output.status.success() {
+        let conflicted = checked(git(dir, &["diff", "--name-only", "--diff-filter=U"])?, "git diff")?;
+        let paths: Vec<PathBuf> = conflicted.lines().map(PathBuf::from).collect();
+        if paths.is_empty() {
+            return Err(failure(&output, &operation.to_string()));
+        }
+        let output = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        return Ok(Err(Outcome::Conflicts { paths, output }));
+    }
+
+    let head = checked(git(dir, &["rev-parse", "HEAD"])?, "git rev-parse")?;
+    Ok(Ok(Oid::from_str(head.trim())?))
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<Output, OafError> {
+    Ok(Command::new("git").arg("-C").arg(dir).args(args).output()?)
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// The standard output of a git command that must succeed.
+fn checked(output: Output, what: &str) -> Result<String, OafError> {
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(failure(&output, what))
+    }
+}
+
+fn failure(output: &Output, what: &str) -> OafError {
+    OafError::Git(Error::from_str(&format!("{} failed: {}", what, String::from_utf8_lossy(&output.stderr).trim())))
+}
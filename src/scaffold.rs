@@ -0,0 +1,224 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{Datelike, Local};
+use git2::{Error, Reference, Repository, RepositoryInitOptions, Signature};
+use error::OafError;
+use gitignore_catalogue;
+
+/// The kinds of file a new repository can be started with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+    Readme,
+    Gitignore,
+    License,
+}
+
+impl TemplateKind {
+    pub fn all() -> &'static [TemplateKind] {
+        &[TemplateKind::Readme, TemplateKind::Gitignore, TemplateKind::License]
+    }
+
+    /// The file the template is written to, in the root of the working tree.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            TemplateKind::Readme => "README.md",
+            TemplateKind::Gitignore => ".gitignore",
+            TemplateKind::License => "LICENSE",
+        }
+    }
+
+    /// The directory under the configured template directory that holds
+    /// the user's own templates of this kind.
+    fn dir_name(self) -> &'static str {
+        match self {
+            TemplateKind::Readme => "readme",
+            TemplateKind::Gitignore => "gitignore",
+            TemplateKind::License => "license",
+        }
+    }
+}
+
+/// The text of a file to start a repository with. `{name}` is replaced by
+/// the name of the repository's directory, `{year}` by the current year and
+/// `{author}` by the user's name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pub name: String,
+    pub text: String,
+}
+
+/// Returns the templates of `kind`: the built-in ones (for .gitignore, the
+/// catalogue), then those in `template_dir`, each named after its file. One
+/// of the user's templates replaces a built-in one with the same name.
+pub fn templates(kind: TemplateKind, template_dir: Option<&Path>) -> Vec<Template> {
+    let built_in: Vec<(&str, &str)> = match kind {
+        TemplateKind::Gitignore => gitignore_catalogue::CATALOGUE.to_vec(),
+        _ => BUILT_IN.iter().filter(|&&(k, _, _)| k == kind).map(|&(_, name, text)| (name, text)).collect(),
+    };
+    let mut templates: Vec<Template> = built_in.into_iter()
+        .map(|(name, text)| Template { name: name.to_string(), text: text.to_string() })
+        .collect();
+
+    let dir = match template_dir {
+        Some(dir) => dir.join(kind.dir_name()),
+        None => return templates,
+    };
+    let mut own: Vec<Template> = match fs::read_dir(&dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file())
+            .filter_map(|p| {
+                let name = p.file_name()?.to_string_lossy().into_owned();
+                fs::read_to_string(&p).ok().map(|text| Template { name, text })
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Cannot read templates from {}: {}", dir.display(), e);
+            Vec::new()
+        },
+    };
+
+    own.sort_by(|a, b| a.name.cmp(&b.name));
+    templates.retain(|t| !own.iter().any(|o| o.name == t.name));
+    templates.extend(own);
+    templates
+}
+
+/// Fills in the placeholders in a template.
+pub fn fill(text: &str, name: &str, author: &str) -> String {
+    text.replace("{name}", name)
+        .replace("{year}", &Local::now().year().to_string())
+        .replace("{author}", author)
+}
+
+/// What to put in a new repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scaffold {
+    /// The name of the branch HEAD starts on, e.g. 'main'.
+    pub initial_branch: String,
+    /// The templates to write, at most one of each kind.
+    pub files: Vec<(TemplateKind, Template)>,
+    /// Who `{author}` is in the templates.
+    pub author: String,
+}
+
+/// Creates a repository at `path`, creating the directory if need be, and
+/// writes the scaffold's files into it. Nothing is created if the directory
+/// is already a repository or a file would overwrite one already there.
+/// Returns the repository and the paths of the files written, relative to
+/// the working tree.
+pub fn create(path: &Path, scaffold: &Scaffold) -> Result<(Repository, Vec<PathBuf>), OafError> {
+    if Repository::open(path).is_ok() {
+        return Err(Error::from_str(&format!("'{}' is already a repository.", path.display())).into());
+    }
+    if !Reference::is_valid_name(&format!("refs/heads/{}", scaffold.initial_branch)) {
+        return Err(Error::from_str(&format!("'{}' is not a valid branch name.", scaffold.initial_branch)).into());
+    }
+    for &(kind, _) in &scaffold.files {
+        if path.join(kind.file_name()).exists() {
+            return Err(Error::from_str(&format!("'{}' already has a {}.", path.display(), kind.file_name())).into());
+        }
+    }
+
+    let mut opts = RepositoryInitOptions::new();
+    opts.initial_head(&scaffold.initial_branch);
+    let repo = Repository::init_opts(path, &opts)?;
+
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut written = Vec::new();
+    for &(kind, ref template) in &scaffold.files {
+        fs::write(path.join(kind.file_name()), fill(&template.text, &name, &scaffold.author))?;
+        written.push(PathBuf::from(kind.file_name()));
+    }
+
+    info!("Created a repository at {} on '{}' with {:?}", path.display(), scaffold.initial_branch, written);
+    Ok((repo, written))
+}
+
+/// Commits `files` as the first commit of the new repository `repo`.
+pub fn initial_commit(repo: &Repository, files: &[PathBuf], signature: &Signature) -> Result<(), Error> {
+    let mut index = repo.index()?;
+    for file in files {
+        index.add_path(file)?;
+    }
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    repo.commit(Some("HEAD"), signature, signature, "Initial commit", &tree, &[])?;
+    Ok(())
+}
+
+const BUILT_IN: &[(TemplateKind, &str, &str)] = &[
+    (TemplateKind::Readme, "Title only", "# {name}\n"),
+    (TemplateKind::Readme, "Sections", "# {name}\n\n## Building\n\n## Usage\n\n## License\n"),
+    (TemplateKind::License, "MIT", MIT),
+    (TemplateKind::License, "BSD-2-Clause", BSD_2_CLAUSE),
+];
+
+const MIT: &str = "MIT License
+
+Copyright (c) {year} {author}
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the \"Software\"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED \"AS IS\", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+";
+
+const BSD_2_CLAUSE: &str = "BSD 2-Clause License
+
+Copyright (c) {year}, {author}
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this
+   list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS \"AS IS\"
+AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_kind_has_built_in_templates() {
+        for &kind in TemplateKind::all() {
+            assert!(!templates(kind, None).is_empty(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn fill_replaces_placeholders() {
+        let text = fill("# {name} by {author}, {year}", "oaf", "Ann");
+        assert!(text.starts_with("# oaf by Ann, 2"));
+        assert!(!text.contains('{'));
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use git2::{BranchType, Repository};
+use paths;
+use refs;
+use remotes::{self, TagFetch};
+use repositories::{self, RepositoryExtensions};
+use status;
+
+/// A command that can be run by name, with its arguments as words.
+pub struct Command {
+    pub name: &'static str,
+    /// How it is used, for error messages.
+    pub usage: &'static str,
+    /// The fewest and most arguments it takes.
+    arguments: (usize, usize),
+    run: fn(&mut State, &[&str]) -> Result<String, String>,
+}
+
+/// The commands oaf understands. 'open' opens a repository for the commands
+/// after it; 'fetch' fetches from the named remote, or from all of them;
+/// 'report' describes the current branch and the working tree.
+pub const COMMANDS: &[Command] = &[
+    Command { name: "open", usage: "open <directory>", arguments: (1, 1), run: open },
+    Command { name: "fetch", usage: "fetch [remote]", arguments: (0, 1), run: fetch },
+    Command { name: "checkout", usage: "checkout <branch>", arguments: (1, 1), run: checkout },
+    Command { name: "report", usage: "report", arguments: (0, 0), run: report },
+];
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+/// What the commands in a script share: the repository they act on.
+#[derive(Default)]
+pub struct State {
+    repo: Option<Repository>,
+}
+
+impl State {
+    fn repo(&self) -> Result<&Repository, String> {
+        self.repo.as_ref().ok_or_else(|| "No repository is open; use 'open <directory>' first".to_string())
+    }
+}
+
+/// Why a script stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    /// The line of the script, counting from 1, or 0 if it could not be read.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "Line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+/// Runs the script in `file`, writing what each command did to `out`.
+pub fn run_file<W: Write>(file: &Path, out: &mut W) -> Result<(), ScriptError> {
+    let script = fs::read_to_string(file)
+        .map_err(|e| ScriptError { line: 0, message: format!("Cannot read '{}': {}", file.display(), e) })?;
+    run(&script, &mut State::default(), out)
+}
+
+/// Runs a script: one command per line, with its arguments separated by
+/// spaces. Blank lines and lines starting with '#' are ignored. The script
+/// stops at the first command that fails.
+pub fn run<W: Write>(script: &str, state: &mut State, out: &mut W) -> Result<(), ScriptError> {
+    for (i, line) in script.lines().enumerate() {
+        let error = |message: String| ScriptError { line: i + 1, message };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() || words[0].starts_with('#') {
+            continue;
+        }
+
+        let command = find(words[0]).ok_or_else(|| error(format!("Unknown command '{}'", words[0])))?;
+        let (min, max) = command.arguments;
+        if words.len() - 1 < min || words.len() - 1 > max {
+            return Err(error(format!("Expected '{}'", command.usage)));
+        }
+        info!("Running script command '{}'", line.trim());
+        let output = (command.run)(state, &words[1..]).map_err(&error)?;
+        if !output.is_empty() {
+            writeln!(out, "{}", output).map_err(|e| error(e.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+fn open(state: &mut State, args: &[&str]) -> Result<String, String> {
+    let dir = paths::expand_tilde(Path::new(args[0]));
+    let repo = repositories::open_repository(&dir).map_err(|e| e.to_string())?;
+    let message = format!("Opened '{}'", repo.display_name());
+    state.repo = Some(repo);
+    Ok(message)
+}
+
+fn fetch(state: &mut State, args: &[&str]) -> Result<String, String> {
+    let repo = state.repo()?;
+    let remotes = match args.first() {
+        Some(remote) => vec![remote.to_string()],
+        None => remotes::remote_names(repo),
+    };
+
+    let mut lines = Vec::new();
+    for remote in remotes {
+        let new_commits = remotes::fetch(repo, &remote, TagFetch::Auto).map_err(|e| e.to_string())?;
+        lines.push(format!("Fetched {} new commits from '{}'", new_commits, remote));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn checkout(state: &mut State, args: &[&str]) -> Result<String, String> {
+    let branch = args[0];
+    refs::checkout_branch(state.repo()?, branch).map_err(|e| e.to_string())?;
+    Ok(format!("Checked out '{}'", branch))
+}
+
+fn report(state: &mut State, _args: &[&str]) -> Result<String, String> {
+    let repo = state.repo()?;
+    let branch = match remotes::current_branch_name(repo) {
+        Some(branch) => branch_description(repo, &branch).map_err(|e| e.to_string())?,
+        None => "HEAD is detached".to_string(),
+    };
+    let status = status::summary(repo, true).map_err(|e| e.to_string())?;
+    Ok(format!("{}: {}\n  {}", repo.display_name(), branch, status.description()))
+}
+
+fn branch_description(repo: &Repository, branch: &str) -> Result<String, ::git2::Error> {
+    let local = repo.find_branch(branch, BranchType::Local)?;
+    let upstream = match local.upstream() {
+        Ok(upstream) => upstream,
+        Err(_) => return Ok(format!("on '{}', which has no upstream", branch)),
+    };
+
+    let upstream_name = upstream.name()?.unwrap_or("").to_string();
+    match (local.get().target(), upstream.get().target()) {
+        (Some(a), Some(b)) => {
+            let (ahead, behind) = repo.graph_ahead_behind(a, b)?;
+            Ok(format!("on '{}', {} ahead and {} behind '{}'", branch, ahead, behind, upstream_name))
+        },
+        _ => Ok(format!("on '{}', tracking '{}'", branch, upstream_name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_script(script: &str) -> (Result<(), ScriptError>, String) {
+        let mut out = Vec::new();
+        let result = run(script, &mut State::default(), &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn blank_lines_and_comments_are_ignored() {
+        assert_eq!(run_script("\n# Nothing to do\n   \n"), (Ok(()), String::new()));
+    }
+
+    #[test]
+    fn stops_at_an_unknown_command() {
+        let (result, _) = run_script("# Demo\nlist branches\nreport");
+        assert_eq!(result, Err(ScriptError { line: 2, message: "Unknown command 'list'".to_string() }));
+    }
+
+    #[test]
+    fn commands_that_need_a_repository_fail_without_one() {
+        let (result, _) = run_script("fetch origin");
+        assert_eq!(result.unwrap_err().to_string(), "Line 1: No repository is open; use 'open <directory>' first");
+    }
+
+    #[test]
+    fn checks_the_arguments() {
+        let (result, _) = run_script("checkout");
+        assert_eq!(result.unwrap_err().message, "Expected 'checkout <branch>'");
+        let (result, _) = run_script("report now");
+        assert_eq!(result.unwrap_err().message, "Expected 'report'");
+    }
+
+    #[test]
+    fn every_command_can_be_found() {
+        for command in COMMANDS {
+            assert!(find(command.name).is_some());
+            assert!(command.usage.starts_with(command.name));
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use toml;
+use history::LogSort;
+
+/// UI state that is remembered between runs, such as the layout choices
+/// made in each view. Unlike the config file this is written by oaf, so it
+/// is kept in the data directory as 'session.toml'.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub status_layout: StatusLayout,
+    // Tables must come after plain values for the toml serializer.
+    pub log_view: LogViewLayout,
+    pub split: SplitLayout,
+}
+
+/// How the status view lists the changed files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusLayout {
+    /// One list, with each file's staged and unstaged status.
+    Combined,
+    /// The staged files in one list, above the unstaged files in another.
+    Index,
+}
+
+impl Default for StatusLayout {
+    fn default() -> Self {
+        StatusLayout::Combined
+    }
+}
+
+/// The columns that can be shown for each commit in the log view. The
+/// summary is always shown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogColumns {
+    pub hash: bool,
+    pub date: bool,
+    pub author: bool,
+    /// The branches and tags pointing at the commit.
+    pub refs: bool,
+}
+
+impl Default for LogColumns {
+    fn default() -> Self {
+        LogColumns { hash: true, date: true, author: true, refs: true }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogViewLayout {
+    /// The width of the refs panel; 0 hides it.
+    pub refs_panel_width: usize,
+    pub sort: LogSort,
+    // Tables must come after plain values for the toml serializer.
+    pub columns: LogColumns,
+}
+
+impl Default for LogViewLayout {
+    fn default() -> Self {
+        LogViewLayout {
+            refs_panel_width: 30,
+            sort: LogSort::Time,
+            columns: LogColumns::default(),
+        }
+    }
+}
+
+/// Which way the split view divides the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitOrientation {
+    /// Side by side.
+    Horizontal,
+    /// One above the other.
+    Vertical,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitLayout {
+    pub orientation: SplitOrientation,
+    /// The share of the screen given to the first pane, as a percentage.
+    pub percent: usize,
+}
+
+impl Default for SplitLayout {
+    fn default() -> Self {
+        SplitLayout { orientation: SplitOrientation::Horizontal, percent: 40 }
+    }
+}
+
+lazy_static! {
+    static ref CURRENT: RwLock<Arc<Session>> = RwLock::new(Arc::new(Session::default()));
+    static ref FILE: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Returns the current session state.
+pub fn current() -> Arc<Session> {
+    CURRENT.read().unwrap().clone()
+}
+
+/// Changes the session state and saves it, if it was loaded from a file.
+/// Failing to save is logged but otherwise ignored, since the worst that can
+/// happen is that the layout is not remembered.
+pub fn update<F>(f: F)
+    where F: FnOnce(&mut Session)
+{
+    let mut session = (*current()).clone();
+    f(&mut session);
+    *CURRENT.write().unwrap() = Arc::new(session);
+    flush();
+}
+
+/// Saves the session state, if it was loaded from a file.
+pub fn flush() {
+    if let Some(ref path) = *FILE.read().unwrap() {
+        if let Err(e) = save(path, &current()) {
+            warn!("Error saving session file '{}'. Error = {}", path.display(), e);
+        }
+    }
+}
+
+/// As `flush`, but gives up rather than waiting if the state is locked, as
+/// it may be by the update a panic interrupted.
+pub fn try_flush() {
+    let path = match FILE.try_read() {
+        Ok(file) => file.clone(),
+        Err(_) => return warn!("The session file is locked, not saving it"),
+    };
+    let session = match CURRENT.try_read() {
+        Ok(session) => session.clone(),
+        Err(_) => return warn!("The session is locked, not saving it"),
+    };
+    if let Some(path) = path {
+        if let Err(e) = save(&path, &session) {
+            warn!("Error saving session file '{}'. Error = {}", path.display(), e);
+        }
+    }
+}
+
+/// Reads the session state from `path` and makes it current; later updates
+/// are saved back to the same file. A missing or unreadable file gives the
+/// default state.
+pub fn load(path: &Path) {
+    *FILE.write().unwrap() = Some(path.to_path_buf());
+
+    let session = match fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text).unwrap_or_else(|e| {
+            warn!("Error reading session file '{}', using defaults. Error = {}", path.display(), e);
+            Session::default()
+        }),
+        Err(_) => Session::default(),
+    };
+
+    *CURRENT.write().unwrap() = Arc::new(session);
+}
+
+fn save(path: &Path, session: &Session) -> io::Result<()> {
+    let text = toml::to_string(session).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut session = Session::default();
+        session.log_view.refs_panel_width = 0;
+        session.log_view.columns.author = false;
+        session.log_view.sort = LogSort::Topological;
+        session.split.orientation = SplitOrientation::Vertical;
+        session.status_layout = StatusLayout::Index;
+
+        let text = toml::to_string(&session).unwrap();
+        assert_eq!(toml::from_str::<Session>(&text).unwrap(), session);
+    }
+
+    #[test]
+    fn missing_settings_get_defaults() {
+        let session: Session = toml::from_str("[log_view]\nsort = \"topological\"\n").unwrap();
+        assert_eq!(session.log_view.sort, LogSort::Topological);
+        assert_eq!(session.log_view.refs_panel_width, 30);
+        assert!(session.log_view.columns.hash);
+    }
+}
@@ -0,0 +1,136 @@
+//! Shortening repository paths to fit narrow columns. Rather than cutting
+//! the text at one end, whole directories are taken out of the middle, so
+//! that 'src/tui/views/log/main.rs' becomes 'src/…/log/main.rs'. Among a set
+//! of paths, a path is only shortened in a way that no other path in the set
+//! could also have been shortened to.
+
+use std::collections::HashMap;
+use text_width::{self, Ellipsis};
+
+const ELIDED: &str = "…";
+
+/// Shortens paths from a set so that each stays distinguishable from the
+/// others where there is room.
+pub struct PathShortener {
+    /// The paths in the set, split into components, by file name. Only paths
+    /// with the same file name can be confused once shortened.
+    by_name: HashMap<String, Vec<Vec<String>>>,
+}
+
+impl PathShortener {
+    pub fn new<I, S>(paths: I) -> Self
+        where I: IntoIterator<Item = S>,
+              S: AsRef<str>
+    {
+        let mut by_name = HashMap::new();
+        for path in paths {
+            let parts = components(path.as_ref());
+            let name = parts.last().cloned().unwrap_or_default();
+            by_name.entry(name).or_insert_with(Vec::new).push(parts);
+        }
+        PathShortener { by_name }
+    }
+
+    /// Shortens `path` to at most `max_width` columns. The widest shortening
+    /// that fits and cannot be mistaken for another path in the set is used;
+    /// failing that, the widest that fits.
+    pub fn shorten(&self, path: &str, max_width: usize) -> String {
+        if text_width::width(path) <= max_width {
+            return path.to_string();
+        }
+
+        let parts = components(path);
+        let others: Vec<&Vec<String>> = parts.last()
+            .and_then(|name| self.by_name.get(name))
+            .map(|paths| paths.iter().filter(|p| **p != parts).collect())
+            .unwrap_or_default();
+
+        let fitting: Vec<(usize, usize, String)> = elisions(&parts)
+            .into_iter()
+            .filter(|&(_, _, ref text)| text_width::width(text) <= max_width)
+            .collect();
+        let unambiguous = fitting.iter()
+            .find(|&&(start, end, _)| !others.iter().any(|other| could_be(other, &parts, start, end)));
+
+        match unambiguous.or_else(|| fitting.first()) {
+            Some(&(_, _, ref text)) => text.clone(),
+            None => text_width::truncate(path, max_width, Ellipsis::Start),
+        }
+    }
+}
+
+/// Shortens a single path to at most `max_width` columns.
+pub fn shorten(path: &str, max_width: usize) -> String {
+    PathShortener::new(&[path]).shorten(path, max_width)
+}
+
+fn components(path: &str) -> Vec<String> {
+    path.split('/').filter(|c| !c.is_empty()).map(|c| c.to_string()).collect()
+}
+
+/// Every way of taking a run of directories out of `parts`, as the start and
+/// end of the run and the text that results, widest first. Of two as wide,
+/// the one that takes out fewer directories, then the one that keeps those
+/// nearest the file, comes first.
+fn elisions(parts: &[String]) -> Vec<(usize, usize, String)> {
+    let dirs = parts.len().saturating_sub(1);
+    let mut result = Vec::new();
+    for start in 0..dirs {
+        for end in start + 1..dirs + 1 {
+            let mut kept: Vec<&str> = parts[..start].iter().map(|s| s.as_str()).collect();
+            kept.push(ELIDED);
+            kept.extend(parts[end..].iter().map(|s| s.as_str()));
+            result.push((start, end, kept.join("/")));
+        }
+    }
+
+    result.sort_by(|a, b| text_width::width(&b.2).cmp(&text_width::width(&a.2))
+        .then((a.1 - a.0).cmp(&(b.1 - b.0)))
+        .then(a.0.cmp(&b.0)));
+    result
+}
+
+/// Whether `other` would also be shown as `parts` with the directories from
+/// `start` to `end` taken out.
+fn could_be(other: &[String], parts: &[String], start: usize, end: usize) -> bool {
+    let tail = parts.len() - end;
+    other.len() > start + tail
+        && other[..start] == parts[..start]
+        && other[other.len() - tail..] == parts[end..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_that_fit_are_unchanged() {
+        assert_eq!(shorten("src/main.rs", 11), "src/main.rs");
+    }
+
+    #[test]
+    fn directories_are_taken_from_the_middle() {
+        assert_eq!(shorten("src/tui/views/log/main.rs", 17), "src/…/log/main.rs");
+        assert_eq!(shorten("src/tui/views/log/main.rs", 13), "…/log/main.rs");
+        assert_eq!(shorten("src/tui/views/log/main.rs", 11), "…/main.rs");
+    }
+
+    #[test]
+    fn very_narrow_columns_keep_the_end_of_the_file_name() {
+        assert_eq!(shorten("src/tui/main.rs", 9), "…/main.rs");
+        assert_eq!(shorten("src/tui/main.rs", 6), "…in.rs");
+        assert_eq!(shorten("a_very_long_file_name.rs", 10), "…e_name.rs");
+    }
+
+    #[test]
+    fn paths_in_a_set_stay_distinct() {
+        let paths = ["src/tui/log/mod.rs", "src/tui/diff/mod.rs", "src/core/log/mod.rs"];
+        let shortener = PathShortener::new(&paths);
+        assert_eq!(shortener.shorten("src/tui/log/mod.rs", 16), "…/tui/log/mod.rs");
+        assert_eq!(shortener.shorten("src/core/log/mod.rs", 17), "…/core/log/mod.rs");
+        // 'src/tui/…/mod.rs' could be either of the tui modules.
+        assert_eq!(shortener.shorten("src/tui/diff/mod.rs", 16), "…/diff/mod.rs");
+        // Nothing that fits is unambiguous, so the widest is used.
+        assert_eq!(shortener.shorten("src/core/log/mod.rs", 16), "src/…/log/mod.rs");
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use git2::{Oid, Repository};
+
+const PGP_SIGNATURE_START: &str = "-----BEGIN PGP SIGNATURE-----";
+const SSH_SIGNATURE_START: &str = "-----BEGIN SSH SIGNATURE-----";
+
+static NEXT_SIGNATURE_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// The result of verifying the signature on a commit or tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// The object carries no signature.
+    Unsigned,
+    /// The signature is valid; `signer` is whatever the verifier reported.
+    Good { signer: String },
+    /// The signature matches the signed data, but whose key made it has not
+    /// been checked, as for SSH signatures when no allowed signers file
+    /// names the key, which are then only checked against the key they
+    /// carry.
+    Unverified { detail: String },
+    /// The signature is valid but the key has expired or been revoked.
+    Expired { signer: String },
+    /// The signature does not match the signed data.
+    Bad { signer: String },
+    /// The key needed to check the signature is not available.
+    UnknownKey { key_id: String },
+    /// The verifier could not be run, or produced output we do not understand.
+    Error(String),
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignatureStatus::Unsigned => write!(f, "Not signed"),
+            SignatureStatus::Good { ref signer } => write!(f, "Good signature from {}", signer),
+            SignatureStatus::Unverified { ref detail } => write!(f, "Intact signature, signer not verified ({})", detail),
+            SignatureStatus::Expired { ref signer } => write!(f, "Good signature from {} (expired or revoked key)", signer),
+            SignatureStatus::Bad { ref signer } => write!(f, "BAD signature from {}", signer),
+            SignatureStatus::UnknownKey { ref key_id } => write!(f, "Cannot verify, unknown key {}", key_id),
+            SignatureStatus::Error(ref msg) => write!(f, "Cannot verify: {}", msg),
+        }
+    }
+}
+
+/// The results of verifying signatures on commits and tags. Running an
+/// external program is slow, so results are kept per object id; objects are
+/// immutable, so the cache never needs invalidating.
+#[derive(Debug, Default)]
+pub struct SignatureCache {
+    cache: HashMap<Oid, SignatureStatus>,
+}
+
+impl SignatureCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The status of the signature of the commit or tag `oid`, if it has
+    /// been verified.
+    pub fn get(&self, oid: Oid) -> Option<SignatureStatus> {
+        self.cache.get(&oid).cloned()
+    }
+
+    pub fn insert(&mut self, oid: Oid, status: SignatureStatus) {
+        self.cache.insert(oid, status);
+    }
+}
+
+/// Verifies the signature of the commit `oid` by handing it to gpg, or to
+/// ssh-keygen for SSH signatures. This runs an external program, so should
+/// not be done on the UI thread.
+pub fn verify_commit(repo: &Repository, oid: Oid) -> SignatureStatus {
+    match repo.extract_signature(&oid, None) {
+        Ok((signature, signed_data)) => verify(repo, oid, &signature, &signed_data),
+        // libgit2 reports a missing signature as an error.
+        Err(_) => SignatureStatus::Unsigned,
+    }
+}
+
+/// Verifies the signature of the annotated tag `oid`, as `verify_commit`
+/// does. For tags the signature is simply appended to the message.
+pub fn verify_tag(repo: &Repository, oid: Oid) -> SignatureStatus {
+    match repo.odb() {
+        Ok(odb) => match odb.read(oid) {
+            Ok(obj) => match split_tag_signature(obj.data()) {
+                Some((signed_data, signature)) => verify(repo, oid, signature, signed_data),
+                None => SignatureStatus::Unsigned,
+            },
+            Err(e) => SignatureStatus::Error(e.to_string()),
+        },
+        Err(e) => SignatureStatus::Error(e.to_string()),
+    }
+}
+
+/// Splits the raw content of a tag object into the signed part and the
+/// signature, or returns None if the tag is not signed. As in git, the
+/// signature starts at the last line that begins with a marker, so a message
+/// that quotes a signature block is not taken for the signature.
+fn split_tag_signature(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let starts_signature = |pos: usize| {
+        (pos == 0 || data[pos - 1] == b'\n')
+            && [PGP_SIGNATURE_START, SSH_SIGNATURE_START].iter().any(|marker| data[pos..].starts_with(marker.as_bytes()))
+    };
+    (0..data.len()).rev()
+        .find(|&pos| starts_signature(pos))
+        .map(|pos| (&data[..pos], &data[pos..]))
+}
+
+fn verify(repo: &Repository, oid: Oid, signature: &[u8], signed_data: &[u8]) -> SignatureStatus {
+    let _timer = timer!("verify_signature");
+    let sig_file = match write_signature_file(signature) {
+        Ok(sig_file) => sig_file,
+        Err(e) => return SignatureStatus::Error(format!("cannot write temporary file: {}", e)),
+    };
+
+    let config = repo.config().ok();
+    let setting = |name: &str, default: &str| {
+        config.as_ref()
+            .and_then(|c| c.get_string(name).ok())
+            .unwrap_or_else(|| default.to_string())
+    };
+
+    let status = if signature.starts_with(SSH_SIGNATURE_START.as_bytes()) {
+        let allowed_signers = config.as_ref().and_then(|c| c.get_path("gpg.ssh.allowedSignersFile").ok());
+        verify_ssh(&setting("gpg.ssh.program", "ssh-keygen"), &sig_file, signed_data, allowed_signers.as_ref().map(|p| p.as_path()))
+    } else {
+        let mut command = Command::new(setting("gpg.program", "gpg"));
+        command.args(&["--status-fd=1", "--keyid-format=long", "--verify"]).arg(&sig_file).arg("-");
+        match run_verifier(&mut command, signed_data) {
+            Ok(output) => parse_gpg_status(&String::from_utf8_lossy(&output.stdout)),
+            Err(e) => SignatureStatus::Error(e),
+        }
+    };
+
+    let _ = fs::remove_file(&sig_file);
+    _timer.set_message(format!("{} => {}", oid, status));
+    status
+}
+
+/// Verifies an SSH signature as 'git verify-commit' does. The signer is
+/// looked up by key in the allowed signers file, if there is one, and the
+/// signature verified as theirs. Otherwise it can only be checked against
+/// the key it carries, which says nothing about who signed.
+fn verify_ssh(program: &str, sig_file: &Path, signed_data: &[u8], allowed_signers: Option<&Path>) -> SignatureStatus {
+    if let Some(allowed_signers) = allowed_signers {
+        let mut find = Command::new(program);
+        find.args(&["-Y", "find-principals", "-f"]).arg(allowed_signers).arg("-s").arg(sig_file);
+        let principals = match run_verifier(&mut find, b"") {
+            Ok(ref output) if output.status.success() => parse_principals(&String::from_utf8_lossy(&output.stdout)),
+            _ => Vec::new(),
+        };
+
+        for principal in &principals {
+            let mut verify = Command::new(program);
+            verify.args(&["-Y", "verify", "-n", "git", "-f"]).arg(allowed_signers)
+                .arg("-I").arg(principal)
+                .arg("-s").arg(sig_file);
+            match run_verifier(&mut verify, signed_data) {
+                Ok(ref output) if output.status.success() => return SignatureStatus::Good { signer: principal.clone() },
+                Ok(_) => {},
+                Err(e) => return SignatureStatus::Error(e),
+            }
+        }
+        if let Some(principal) = principals.into_iter().next() {
+            return SignatureStatus::Bad { signer: principal };
+        }
+    }
+
+    let mut check = Command::new(program);
+    check.args(&["-Y", "check-novalidate", "-n", "git", "-s"]).arg(sig_file);
+    match run_verifier(&mut check, signed_data) {
+        Ok(ref output) if output.status.success() => {
+            let detail = match allowed_signers {
+                Some(_) => "the key is not in the allowed signers file".to_string(),
+                None => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            };
+            SignatureStatus::Unverified { detail }
+        },
+        Ok(output) => SignatureStatus::Bad { signer: String::from_utf8_lossy(&output.stderr).trim().to_string() },
+        Err(e) => SignatureStatus::Error(e),
+    }
+}
+
+/// The principals printed by 'ssh-keygen -Y find-principals', one per line.
+fn parse_principals(output: &str) -> Vec<String> {
+    output.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).map(|line| line.to_string()).collect()
+}
+
+/// Writes `signature` to a new file in the temporary directory. The file is
+/// made with `create_new`, so a file or link already at the path, perhaps
+/// put there by another user, is never written through.
+fn write_signature_file(signature: &[u8]) -> io::Result<PathBuf> {
+    const ATTEMPTS: usize = 100;
+    for _ in 0..ATTEMPTS {
+        let id = NEXT_SIGNATURE_FILE.fetch_add(1, Ordering::SeqCst);
+        let path = env::temp_dir().join(format!("oaf-{}-{}.sig", ::std::process::id(), id));
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        if let Err(e) = file.write_all(signature) {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        return Ok(path);
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "no unused temporary file name"))
+}
+
+/// Runs a verifier, feeding it `signed_data`, and returns what it printed.
+fn run_verifier(command: &mut Command, signed_data: &[u8]) -> Result<Output, String> {
+    let mut child = command.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run verifier: {}", e))?;
+
+    if let Some(stdin) = child.stdin.as_mut() {
+        let _ = stdin.write_all(signed_data);
+    }
+
+    child.wait_with_output().map_err(|e| e.to_string())
+}
+
+/// Interprets the machine-readable output of 'gpg --status-fd'.
+/// See doc/DETAILS in the GnuPG distribution for the format.
+fn parse_gpg_status(output: &str) -> SignatureStatus {
+    for line in output.lines() {
+        let mut words = line.splitn(4, ' ');
+        if words.next() != Some("[GNUPG:]") {
+            continue;
+        }
+
+        let keyword = words.next().unwrap_or("");
+        let key_id = words.next().unwrap_or("").to_string();
+        let signer = words.next().unwrap_or("").to_string();
+
+        match keyword {
+            "GOODSIG" => return SignatureStatus::Good { signer },
+            "EXPSIG" | "EXPKEYSIG" | "REVKEYSIG" => return SignatureStatus::Expired { signer },
+            "BADSIG" => return SignatureStatus::Bad { signer },
+            "ERRSIG" | "NO_PUBKEY" => return SignatureStatus::UnknownKey { key_id },
+            _ => {}
+        }
+    }
+
+    SignatureStatus::Error("no recognisable status from gpg".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_signature_file_makes_a_new_file_each_time() {
+        let first = write_signature_file(b"one").unwrap();
+        let second = write_signature_file(b"two").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::read(&first).unwrap(), b"one");
+        assert_eq!(fs::read(&second).unwrap(), b"two");
+        let _ = fs::remove_file(first);
+        let _ = fs::remove_file(second);
+    }
+
+    #[test]
+    fn parse_gpg_status_good() {
+        let output = "[GNUPG:] NEWSIG\n[GNUPG:] KEY_CONSIDERED ABCD 0\n[GNUPG:] GOODSIG 1234ABCD Bob <bob@example.com>\n[GNUPG:] VALIDSIG X";
+        assert_eq!(parse_gpg_status(output), SignatureStatus::Good { signer: "Bob <bob@example.com>".to_string() });
+    }
+
+    #[test]
+    fn parse_gpg_status_bad() {
+        let output = "[GNUPG:] BADSIG 1234ABCD Bob <bob@example.com>";
+        assert_eq!(parse_gpg_status(output), SignatureStatus::Bad { signer: "Bob <bob@example.com>".to_string() });
+    }
+
+    #[test]
+    fn parse_gpg_status_missing_key() {
+        let output = "[GNUPG:] ERRSIG 1234ABCD 1 8 00 1536000000 9\n[GNUPG:] NO_PUBKEY 1234ABCD";
+        assert_eq!(parse_gpg_status(output), SignatureStatus::UnknownKey { key_id: "1234ABCD".to_string() });
+    }
+
+    #[test]
+    fn parse_gpg_status_garbage() {
+        match parse_gpg_status("gpg: command not found") {
+            SignatureStatus::Error(_) => {},
+            other => panic!("Unexpected status {:?}", other),
+        }
+    }
+
+    #[test]
+    fn split_tag_signature_finds_pgp_signature() {
+        let data = b"object abc\ntype commit\n\nmessage\n-----BEGIN PGP SIGNATURE-----\nxyz\n";
+        let (signed, sig) = split_tag_signature(data).unwrap();
+        assert_eq!(signed, &b"object abc\ntype commit\n\nmessage\n"[..]);
+        assert!(sig.starts_with(b"-----BEGIN PGP SIGNATURE-----"));
+    }
+
+    #[test]
+    fn split_tag_signature_takes_the_last_marker_that_starts_a_line() {
+        let data = b"object abc\n\nQuoting:\n-----BEGIN PGP SIGNATURE-----\nold\n\
+                     and inline -----BEGIN PGP SIGNATURE-----\n-----BEGIN SSH SIGNATURE-----\nxyz\n";
+        let (signed, sig) = split_tag_signature(data).unwrap();
+        assert!(signed.ends_with(b"and inline -----BEGIN PGP SIGNATURE-----\n"));
+        assert_eq!(sig, &b"-----BEGIN SSH SIGNATURE-----\nxyz\n"[..]);
+        assert_eq!(split_tag_signature(b"object abc\n\nsee -----BEGIN PGP SIGNATURE-----\n"), None);
+    }
+
+    #[test]
+    fn parse_principals_skips_blank_lines() {
+        assert_eq!(parse_principals("bob@example.com\n\n ann@example.com \n"),
+                   vec!["bob@example.com".to_string(), "ann@example.com".to_string()]);
+    }
+
+    #[test]
+    fn split_tag_signature_unsigned() {
+        assert_eq!(split_tag_signature(b"object abc\n\nmessage\n"), None);
+    }
+}
@@ -0,0 +1,355 @@
+//! Signing commits and tags with gpg, as 'git commit -S' and 'git tag -s'
+//! do, and the policy on which branches need signed commits.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use git2::{Error, Repository, Signature};
+use changelog;
+use history;
+use remotes;
+
+/// What to do about a commit that would not be signed on a branch that
+/// needs signed commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Enforcement {
+    /// Say so, but let the commit be made.
+    Warn,
+    /// Refuse to make the commit.
+    Refuse,
+}
+
+/// How a repository's git config says commits and tags are signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningSetup {
+    /// Whether 'commit.gpgSign' asks for commits to be signed.
+    pub sign_commits: bool,
+    /// The key to sign with: 'user.signingKey', or else the committer's
+    /// identity, which gpg looks up in its keyring.
+    pub key: String,
+    /// Whether the key came from 'user.signingKey'.
+    pub explicit_key: bool,
+    program: String,
+    /// 'gpg.format', if it asks for something other than gpg, which is the
+    /// only kind of signing supported.
+    unsupported_format: Option<String>,
+}
+
+impl SigningSetup {
+    pub fn load(repo: &Repository) -> Result<Self, Error> {
+        let identity = identity(&repo.signature()?);
+        let config = repo.config()?;
+        let explicit_key = config.get_string("user.signingkey").ok();
+        let unsupported_format = match config.get_string("gpg.format") {
+            Ok(ref format) if format == "openpgp" => None,
+            Ok(format) => Some(format),
+            Err(_) => None,
+        };
+
+        Ok(SigningSetup {
+            sign_commits: config.get_bool("commit.gpgsign").unwrap_or(false),
+            explicit_key: explicit_key.is_some(),
+            key: explicit_key.unwrap_or(identity),
+            program: config.get_string("gpg.program").unwrap_or_else(|_| "gpg".to_string()),
+            unsupported_format,
+        })
+    }
+
+    /// E.g. "key 1234ABCD (user.signingKey)", for showing before signing.
+    pub fn key_description(&self) -> String {
+        if self.explicit_key {
+            format!("key {} (user.signingKey)", self.key)
+        } else {
+            format!("the default key for '{}'", self.key)
+        }
+    }
+
+    /// Why commits will not be signed, or None if they will be.
+    pub fn commit_problem(&self) -> Option<String> {
+        if !self.sign_commits {
+            return Some("commit signing is not configured; set 'commit.gpgSign' to true.".to_string());
+        }
+        self.unsupported_format.as_ref()
+            .map(|format| format!("'gpg.format' asks for '{}' signing, which is not supported; only gpg is.", format))
+    }
+
+    /// Signs `content`, giving the ASCII-armoured detached signature.
+    pub fn sign(&self, content: &str) -> Result<String, Error> {
+        if let Some(ref format) = self.unsupported_format {
+            return Err(Error::from_str(&format!("Only gpg signing is supported; 'gpg.format' asks for '{}'.", format)));
+        }
+        gpg_sign(&self.program, &self.key, content)
+    }
+}
+
+/// Whether the next commit on HEAD's branch meets the policy on signed
+/// commits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyCheck {
+    /// The branch does not need signed commits, or HEAD is detached.
+    NotRequired,
+    /// The branch needs signed commits, and they will be signed.
+    Met { branch: String },
+    /// The branch needs signed commits, but they will not be signed.
+    Unmet { branch: String, problem: String },
+}
+
+/// Checks whether commits on HEAD's branch need signing, according to
+/// `required` (branch names, '*' is a wildcard), and if so whether they will
+/// be signed.
+pub fn check_policy(repo: &Repository, setup: &SigningSetup, required: &[String]) -> PolicyCheck {
+    match remotes::current_branch_name(repo).or_else(|| history::unborn_branch(repo)) {
+        Some(branch) => check_branch_policy(&branch, setup, required),
+        None => PolicyCheck::NotRequired,
+    }
+}
+
+/// As `check_policy`, for commits made on `branch`, a short name, whether
+/// or not it is checked out.
+pub fn check_branch_policy(branch: &str, setup: &SigningSetup, required: &[String]) -> PolicyCheck {
+    if !required.iter().any(|pattern| changelog::wildcard_match(pattern, branch)) {
+        return PolicyCheck::NotRequired;
+    }
+    let branch = branch.to_string();
+    match setup.commit_problem() {
+        Some(problem) => PolicyCheck::Unmet { branch, problem },
+        None => PolicyCheck::Met { branch },
+    }
+}
+
+/// The name, email and time of `signature` as they appear in the header of
+/// a raw commit or tag object, e.g. 'Bob <bob@example.com> 1500000000 +0100'.
+pub fn identity_line(signature: &Signature) -> String {
+    let time = signature.when();
+    let offset = time.offset_minutes();
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!("{} {} {}{:02}{:02}", identity(signature), time.seconds(), sign, offset.abs() / 60, offset.abs() % 60)
+}
+
+/// Adds `signature` to the raw content of a commit as a 'gpgsig' header,
+/// which goes last among the headers, each line after the first indented.
+pub fn add_commit_signature(content: &str, signature: &str) -> String {
+    let header = format!("gpgsig {}\n", signature.trim_end().replace('\n', "\n "));
+    match content.find("\n\n") {
+        Some(end) => format!("{}{}{}", &content[..end + 1], header, &content[end + 1..]),
+        None => format!("{}{}", content, header),
+    }
+}
+
+/// E.g. 'Bob <bob@example.com>'.
+pub fn identity(signature: &Signature) -> String {
+    format!("{} <{}>", String::from_utf8_lossy(signature.name_bytes()), String::from_utf8_lossy(signature.email_bytes()))
+}
+
+/// How gpg is given the terminal when it may need to ask for a passphrase.
+/// pinentry-curses draws on the terminal, so while the UI is running it
+/// must let go of the terminal first and take it back afterwards.
+pub struct TerminalHandoff {
+    pub release: Box<dyn Fn() + Send>,
+    pub reclaim: Box<dyn Fn() + Send>,
+}
+
+lazy_static! {
+    static ref HANDOFF: Mutex<Option<TerminalHandoff>> = Mutex::new(None);
+}
+
+/// Sets how the terminal is handed to gpg, for as long as the UI runs.
+pub fn set_terminal_handoff(handoff: Option<TerminalHandoff>) {
+    *HANDOFF.lock().unwrap() = handoff;
+}
+
+fn gpg_sign(program: &str, key: &str, content: &str) -> Result<String, Error> {
+    let handoff = HANDOFF.lock().unwrap();
+    sign_allowing_prompt(handoff.as_ref(), |may_prompt| run_gpg(program, key, content, may_prompt))
+}
+
+/// Why gpg did not sign.
+struct GpgFailure {
+    error: Error,
+    /// Whether gpg's status output shows that it needed a passphrase, or
+    /// pinentry, which it was not allowed to ask for.
+    needs_passphrase: bool,
+}
+
+impl From<Error> for GpgFailure {
+    fn from(error: Error) -> Self {
+        GpgFailure { error, needs_passphrase: false }
+    }
+}
+
+/// Signs with `attempt`, which is told whether gpg may ask for a passphrase.
+/// With a terminal handoff, the first attempt may not ask, so that signing
+/// with a key that has no passphrase, or one gpg-agent has cached, does not
+/// disturb the screen. gpg only gets the terminal if that attempt failed
+/// for want of a passphrase; any other failure, such as a missing key, is
+/// reported as it is.
+fn sign_allowing_prompt<F>(handoff: Option<&TerminalHandoff>, mut attempt: F) -> Result<String, Error>
+    where F: FnMut(bool) -> Result<String, GpgFailure>
+{
+    let handoff = match handoff {
+        Some(handoff) => handoff,
+        None => return attempt(true).map_err(|f| f.error),
+    };
+    match attempt(false) {
+        Ok(signature) => Ok(signature),
+        Err(GpgFailure { ref error, needs_passphrase: true }) => {
+            info!("Signing needs a passphrase, trying again with the terminal released: {}", error);
+            (handoff.release)();
+            let result = attempt(true);
+            (handoff.reclaim)();
+            result.map_err(|f| f.error)
+        },
+        Err(failure) => Err(failure.error),
+    }
+}
+
+/// Whether gpg's '--status-fd' output shows that it stopped because it
+/// needed a passphrase: it asked for one, or failed with 'No pinentry' (85),
+/// a pinentry error (86) or a bad passphrase (11), the low 16 bits of the
+/// error code being the libgpg-error code.
+fn needs_passphrase(status: &str) -> bool {
+    const PASSPHRASE_ERRORS: [u32; 3] = [11, 85, 86];
+    status.lines()
+        .filter_map(|line| line.trim().strip_prefix("[GNUPG:] "))
+        .any(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.first() {
+                Some(&"NEED_PASSPHRASE") | Some(&"BAD_PASSPHRASE") | Some(&"PINENTRY_LAUNCHED") => true,
+                Some(&"FAILURE") | Some(&"ERROR") => words.get(2)
+                    .and_then(|code| code.parse::<u32>().ok())
+                    .map_or(false, |code| PASSPHRASE_ERRORS.contains(&(code & 0xFFFF))),
+                _ => false,
+            }
+        })
+}
+
+fn run_gpg(program: &str, key: &str, content: &str, may_prompt: bool) -> Result<String, GpgFailure> {
+    let mut command = Command::new(program);
+    command.args(&["--status-fd=2", "-bsau", key]);
+    if !may_prompt {
+        command.args(&["--pinentry-mode", "error"]);
+    }
+    set_gpg_tty(&mut command);
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::from_str(&format!("Cannot run '{}': {}", program, e)))?;
+
+    if let Some(stdin) = child.stdin.as_mut() {
+        let _ = stdin.write_all(content.as_bytes());
+    }
+
+    let output = child.wait_with_output().map_err(|e| Error::from_str(&e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(GpgFailure {
+            error: Error::from_str(&format!("gpg failed to sign: {}", stderr.trim())),
+            needs_passphrase: needs_passphrase(&stderr),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Tells pinentry-curses which terminal to use, if the user's environment
+/// does not, as gpg's documentation asks shells to with 'GPG_TTY=$(tty)'.
+/// gpg's own standard streams are pipes, so it cannot work it out.
+#[cfg(unix)]
+fn set_gpg_tty(command: &mut Command) {
+    if ::std::env::var_os("GPG_TTY").is_some() {
+        return;
+    }
+    let tty = unsafe { ::libc::ttyname(0) };
+    if !tty.is_null() {
+        let tty = unsafe { ::std::ffi::CStr::from_ptr(tty) };
+        command.env("GPG_TTY", tty.to_string_lossy().into_owned());
+    }
+}
+
+#[cfg(not(unix))]
+fn set_gpg_tty(_command: &mut Command) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Time;
+
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counting_handoff() -> (TerminalHandoff, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let (released, reclaimed) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (r, c) = (released.clone(), reclaimed.clone());
+        let handoff = TerminalHandoff {
+            release: Box::new(move || { r.fetch_add(1, Ordering::SeqCst); }),
+            reclaim: Box::new(move || { c.fetch_add(1, Ordering::SeqCst); }),
+        };
+        (handoff, released, reclaimed)
+    }
+
+    #[test]
+    fn sign_allowing_prompt_only_releases_the_terminal_when_needed() {
+        let (handoff, released, reclaimed) = counting_handoff();
+        let signature = sign_allowing_prompt(Some(&handoff), |may_prompt| {
+            assert!(!may_prompt);
+            Ok("cached".to_string())
+        });
+        assert_eq!(signature.unwrap(), "cached");
+        assert_eq!(released.load(Ordering::SeqCst), 0);
+
+        let mut attempts = Vec::new();
+        let signature = sign_allowing_prompt(Some(&handoff), |may_prompt| {
+            attempts.push(may_prompt);
+            if may_prompt {
+                Ok("prompted".to_string())
+            } else {
+                Err(GpgFailure { error: Error::from_str("no pinentry"), needs_passphrase: true })
+            }
+        });
+        assert_eq!(signature.unwrap(), "prompted");
+        assert_eq!(attempts, vec![false, true]);
+        assert_eq!((released.load(Ordering::SeqCst), reclaimed.load(Ordering::SeqCst)), (1, 1));
+
+        let mut attempts = Vec::new();
+        let signature = sign_allowing_prompt(Some(&handoff), |may_prompt| {
+            attempts.push(may_prompt);
+            Err(GpgFailure::from(Error::from_str("no secret key")))
+        });
+        assert_eq!(signature.unwrap_err().message(), "no secret key");
+        assert_eq!(attempts, vec![false]);
+        assert_eq!(released.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn sign_allowing_prompt_without_a_handoff_lets_gpg_prompt() {
+        let mut attempts = Vec::new();
+        let _ = sign_allowing_prompt(None, |may_prompt| { attempts.push(may_prompt); Err(Error::from_str("failed").into()) });
+        assert_eq!(attempts, vec![true]);
+    }
+
+    #[test]
+    fn needs_passphrase_reads_the_status_output() {
+        assert!(needs_passphrase("[GNUPG:] KEY_CONSIDERED ABCD 2\n[GNUPG:] PINENTRY_LAUNCHED 123 curses\n"));
+        assert!(needs_passphrase("gpg: signing failed: No pinentry\n[GNUPG:] FAILURE sign 67108949\n"));
+        assert!(!needs_passphrase("gpg: skipped \"x\": No secret key\n[GNUPG:] INV_SGNR 9 x\n[GNUPG:] FAILURE sign 17\n"));
+        assert!(!needs_passphrase("gpg: signing failed: No pinentry\n"));
+    }
+
+    #[test]
+    fn identity_line_formats_the_time_zone() {
+        let signature = Signature::new("Bob", "bob@example.com", &Time::new(1500000000, -90)).unwrap();
+        assert_eq!(identity_line(&signature), "Bob <bob@example.com> 1500000000 -0130");
+    }
+
+    #[test]
+    fn add_commit_signature_goes_after_the_headers() {
+        let content = "tree abc\nauthor A\ncommitter C\n\nSummary\n\nBody\n";
+        let signature = "-----BEGIN PGP SIGNATURE-----\n\nxyz\n-----END PGP SIGNATURE-----\n";
+        assert_eq!(add_commit_signature(content, signature),
+                   "tree abc\nauthor A\ncommitter C\n\
+                    gpgsig -----BEGIN PGP SIGNATURE-----\n \n xyz\n -----END PGP SIGNATURE-----\n\
+                    \nSummary\n\nBody\n");
+    }
+}
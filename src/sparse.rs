@@ -0,0 +1,283 @@
+//! Sparse checkouts, in which only some of the tracked files are in the
+//! working tree. libgit2 does not support them, so the patterns are read
+//! from '.git/info/sparse-checkout' and applied to the index here, the way
+//! 'git sparse-checkout' does.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use git2::{build::CheckoutBuilder, Error, Repository, Status};
+use error::OafError;
+use ignore_rules;
+use status::SKIP_WORKTREE;
+
+/// The sparse checkout settings of a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseCheckout {
+    /// In cone mode the patterns only name directories: everything under
+    /// them is included, along with the files (but not the subdirectories)
+    /// of the directories above them.
+    pub cone: bool,
+    pub patterns: Vec<String>,
+}
+
+impl SparseCheckout {
+    /// Reads the sparse checkout settings, or gives None if the repository
+    /// does not use a sparse checkout.
+    pub fn load(repo: &Repository) -> Option<SparseCheckout> {
+        let config = repo.config().ok()?;
+        if !config.get_bool("core.sparseCheckout").unwrap_or(false) {
+            return None;
+        }
+        let cone = config.get_bool("core.sparseCheckoutCone").unwrap_or(false);
+        let text = fs::read_to_string(patterns_file(repo)).unwrap_or_default();
+        Some(SparseCheckout::parse(&text, cone))
+    }
+
+    fn parse(text: &str, cone: bool) -> SparseCheckout {
+        let patterns = text.lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| l.to_string())
+            .collect();
+        SparseCheckout { cone, patterns }
+    }
+
+    /// The directories included in cone mode, e.g. 'src/app'. The parents
+    /// of those directories, which have their subdirectories excluded again
+    /// with '!/parent/*/', are not included.
+    pub fn directories(&self) -> Vec<String> {
+        self.patterns.iter()
+            .filter(|p| !p.starts_with('!') && p.ends_with('/'))
+            .filter(|p| !self.patterns.contains(&format!("!{}*/", p)))
+            .map(|p| p.trim_matches('/').to_string())
+            .filter(|d| !d.is_empty() && !d.contains('*'))
+            .collect()
+    }
+
+    /// Whether `path`, relative to the root of the working tree, is in the
+    /// working tree.
+    pub fn includes(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().replace('\\', "/");
+        if self.cone {
+            in_cone(&self.directories(), &path)
+        } else {
+            // As with .gitignore, the last pattern that matches wins.
+            self.patterns.iter().rev()
+                .filter_map(|p| if p.starts_with('!') { matches(&p[1..], &path).then_some(false) } else { matches(p, &path).then_some(true) })
+                .next()
+                .unwrap_or(false)
+        }
+    }
+
+    /// The text to edit the patterns as: the directories in cone mode, the
+    /// patterns themselves otherwise.
+    pub fn edit_text(&self) -> String {
+        let lines = if self.cone { self.directories() } else { self.patterns.clone() };
+        lines.iter().map(|l| format!("{}\n", l)).collect()
+    }
+}
+
+/// Parses the text from `SparseCheckout::edit_text` after it has been edited.
+pub fn from_edit_text(text: &str, cone: bool) -> SparseCheckout {
+    let lines: Vec<String> = text.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect();
+    if cone {
+        SparseCheckout { cone, patterns: cone_patterns(&lines) }
+    } else {
+        SparseCheckout { cone, patterns: lines }
+    }
+}
+
+fn in_cone(directories: &[String], path: &str) -> bool {
+    let parent = match path.rfind('/') {
+        Some(i) => &path[..i],
+        None => return true,
+    };
+    directories.iter().any(|d| {
+        path.starts_with(&format!("{}/", d)) || d == parent || d.starts_with(&format!("{}/", parent))
+    })
+}
+
+/// The patterns git writes for a cone, which include each directory and,
+/// for its parents, only the files directly in them.
+fn cone_patterns(directories: &[String]) -> Vec<String> {
+    let mut directories: Vec<String> = directories.iter().map(|d| d.trim_matches('/').to_string()).filter(|d| !d.is_empty()).collect();
+    directories.sort();
+    directories.dedup();
+
+    let mut patterns = vec!["/*".to_string(), "!/*/".to_string()];
+    let mut parents: Vec<String> = Vec::new();
+    for dir in &directories {
+        let parts: Vec<&str> = dir.split('/').collect();
+        for n in 1..parts.len() {
+            let parent = parts[..n].join("/");
+            if !parents.contains(&parent) && !directories.contains(&parent) {
+                parents.push(parent);
+            }
+        }
+    }
+    parents.sort();
+    for parent in &parents {
+        patterns.push(format!("/{}/", parent));
+        patterns.push(format!("!/{}/*/", parent));
+    }
+    for dir in &directories {
+        patterns.push(format!("/{}/", dir));
+    }
+    patterns
+}
+
+/// Whether a gitignore-style pattern matches `path` or one of the
+/// directories above it.
+fn matches(pattern: &str, path: &str) -> bool {
+    let mut candidates: Vec<(&str, bool)> = path.match_indices('/').map(|(i, _)| (&path[..i], true)).collect();
+    candidates.push((path, false));
+    candidates.iter().any(|&(candidate, is_dir)| ignore_rules::pattern_matches(pattern, candidate, is_dir))
+}
+
+fn patterns_file(repo: &Repository) -> PathBuf {
+    repo.path().join("info").join("sparse-checkout")
+}
+
+/// Turns on a sparse checkout with the given patterns, or changes its
+/// patterns, and applies them to the working tree.
+pub fn save(repo: &Repository, sparse: &SparseCheckout) -> Result<Applied, OafError> {
+    let file = patterns_file(repo);
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text: String = sparse.patterns.iter().map(|p| format!("{}\n", p)).collect();
+    fs::write(&file, text)?;
+
+    let mut config = repo.config()?;
+    config.set_bool("core.sparseCheckout", true)?;
+    config.set_bool("core.sparseCheckoutCone", sparse.cone)?;
+    info!("Saved {} sparse checkout patterns", sparse.patterns.len());
+    apply(repo, Some(sparse))
+}
+
+/// Turns the sparse checkout off, putting every file back in the working
+/// tree. The patterns are kept for next time.
+pub fn disable(repo: &Repository) -> Result<Applied, OafError> {
+    repo.config().and_then(|mut c| c.set_bool("core.sparseCheckout", false))?;
+    apply(repo, None)
+}
+
+/// What applying the patterns did.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Applied {
+    pub removed: usize,
+    pub restored: usize,
+    /// Files outside the patterns that have changes, so were left alone.
+    pub kept: Vec<PathBuf>,
+}
+
+impl Applied {
+    pub fn description(&self) -> String {
+        let mut text = format!("Removed {} files from the working tree and restored {}.", self.removed, self.restored);
+        if !self.kept.is_empty() {
+            text += &format!(" {} changed files outside the sparse checkout were kept.", self.kept.len());
+        }
+        text
+    }
+}
+
+/// Makes the working tree match the patterns, as 'git sparse-checkout
+/// reapply' does: tracked files outside them are removed and marked
+/// skip-worktree in the index, and files inside them that had been skipped
+/// are checked out again. `None` includes every file. Files with changes are
+/// never removed.
+pub fn apply(repo: &Repository, sparse: Option<&SparseCheckout>) -> Result<Applied, OafError> {
+    let workdir = repo.workdir().ok_or_else(|| OafError::Index(Error::from_str("A bare repository has no working tree.")))?.to_path_buf();
+    let mut index = repo.index().map_err(OafError::Index)?;
+    let mut applied = Applied::default();
+    let mut restore = Vec::new();
+
+    let entries: Vec<_> = index.iter().filter(|e| (e.flags >> 12) & 3 == 0).collect();
+    for mut entry in entries {
+        let path = PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned());
+        let include = sparse.map_or(true, |s| s.includes(&path));
+        let skipped = entry.flags_extended & SKIP_WORKTREE != 0;
+
+        if include && skipped {
+            entry.flags_extended &= !SKIP_WORKTREE;
+            index.add(&entry).map_err(OafError::Index)?;
+            restore.push(path);
+        } else if !include && !skipped {
+            let changed = repo.status_file(&path).map(|s| s != Status::CURRENT).unwrap_or(false);
+            if changed {
+                applied.kept.push(path);
+                continue;
+            }
+            let file = workdir.join(&path);
+            if file.exists() {
+                fs::remove_file(&file)?;
+                remove_empty_parents(&workdir, &file);
+            }
+            entry.flags_extended |= SKIP_WORKTREE;
+            index.add(&entry).map_err(OafError::Index)?;
+            applied.removed += 1;
+        }
+    }
+    index.write().map_err(OafError::Index)?;
+
+    if !restore.is_empty() {
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force();
+        for path in &restore {
+            checkout.path(path);
+        }
+        repo.checkout_index(Some(&mut index), Some(&mut checkout)).map_err(OafError::Index)?;
+        applied.restored = restore.len();
+    }
+
+    info!("Applied the sparse checkout: {:?}", applied);
+    Ok(applied)
+}
+
+fn remove_empty_parents(workdir: &Path, file: &Path) {
+    let mut dir = file.parent();
+    while let Some(d) = dir {
+        if d == workdir || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn included(sparse: &SparseCheckout, path: &str) -> bool {
+        sparse.includes(Path::new(path))
+    }
+
+    #[test]
+    fn cone_includes_directories_and_the_files_above_them() {
+        let sparse = from_edit_text("src/app\ndocs\n", true);
+        assert_eq!(sparse.patterns, vec!["/*", "!/*/", "/src/", "!/src/*/", "/docs/", "/src/app/"]);
+        assert_eq!(SparseCheckout::parse(&sparse.patterns.join("\n"), true).directories(), vec!["docs", "src/app"]);
+
+        assert!(included(&sparse, "README.md"));
+        assert!(included(&sparse, "src/lib.rs"));
+        assert!(included(&sparse, "src/app/deep/main.rs"));
+        assert!(included(&sparse, "docs/guide/intro.md"));
+        assert!(!included(&sparse, "src/other/mod.rs"));
+        assert!(!included(&sparse, "tests/a.rs"));
+    }
+
+    #[test]
+    fn patterns_use_the_last_match() {
+        let sparse = from_edit_text("/*\n!/vendor/\n/vendor/keep/\n*.md\n", false);
+        assert!(included(&sparse, "Cargo.toml"));
+        assert!(included(&sparse, "src/main.rs"));
+        assert!(!included(&sparse, "vendor/lib/a.c"));
+        assert!(included(&sparse, "vendor/keep/a.c"));
+        assert!(included(&sparse, "vendor/lib/README.md"));
+        assert!(!from_edit_text("", false).includes(Path::new("a")));
+    }
+}
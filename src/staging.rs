@@ -0,0 +1,205 @@
+//! Moving changes between the working tree and the index, a whole file or
+//! a single hunk at a time, as 'git add -p' and 'git reset -p' do.
+
+use std::path::{Path, PathBuf};
+use git2::{self, Delta, Error, Index, IndexTime, Repository};
+use error::OafError;
+use history;
+use patch::{self, DiffLimits};
+
+/// Stages all the changes to `path`, including its deletion.
+pub fn stage_file(repo: &Repository, path: &Path) -> Result<(), OafError> {
+    stage_files(repo, &[path.to_path_buf()])
+}
+
+/// Stages all the changes to each of `paths`, including deletions.
+pub fn stage_files(repo: &Repository, paths: &[PathBuf]) -> Result<(), OafError> {
+    let mut index = repo.index().map_err(OafError::Index)?;
+    add_to_index(repo, &mut index, paths)?;
+    index.write().map_err(OafError::Index)
+}
+
+/// Stages `paths` in `index` in memory only, leaving the caller to write
+/// the index, or to read it back in to undo the changes.
+pub fn add_to_index(repo: &Repository, index: &mut Index, paths: &[PathBuf]) -> Result<(), OafError> {
+    let workdir = repo.workdir().ok_or_else(|| OafError::Index(Error::from_str("A bare repository has no working tree.")))?;
+    for path in paths {
+        if workdir.join(path).exists() {
+            index.add_path(path).map_err(OafError::Index)?;
+        } else {
+            index.remove_path(path).map_err(OafError::Index)?;
+        }
+    }
+    Ok(())
+}
+
+/// Unstages all the changes to `path`, making its index entry match HEAD
+/// again. The working tree is left alone.
+pub fn unstage_file(repo: &Repository, path: &Path) -> Result<(), OafError> {
+    // With no commits yet the file is simply taken out of the index.
+    let head = history::head_commit(repo)?;
+    repo.reset_default(head.as_ref().map(|c| c.as_object()), vec![path]).map_err(OafError::Index)
+}
+
+/// Stages one hunk of the unstaged changes to `path`. Hunks are counted
+/// from 0 in the diff made with `context_lines` of context, so that they
+/// are the hunks the user was shown. A new or deleted file is staged whole.
+pub fn stage_hunk(repo: &Repository, path: &Path, hunk: usize, context_lines: u32) -> Result<(), OafError> {
+    let limits = DiffLimits { context_lines, ..DiffLimits::unlimited() };
+    let diff = patch::unstaged_diff(repo, path, limits)?;
+    let delta = diff.get_delta(0).ok_or_else(|| no_changes(path, "unstaged"))?;
+    if delta.status() != Delta::Modified {
+        return stage_file(repo, path);
+    }
+
+    let patch = git2::Patch::from_diff(&diff, 0)?.ok_or_else(|| binary(path))?;
+    let index_blob = repo.find_blob(delta.old_file().id())?;
+    let content = apply_hunk(index_blob.content(), &patch, hunk, false)?;
+    write_to_index(repo, path, &content)
+}
+
+/// Unstages one hunk of the staged changes to `path`, leaving the working
+/// tree alone. Hunks are counted as for `stage_hunk`. A file added or
+/// deleted in the index is unstaged whole.
+pub fn unstage_hunk(repo: &Repository, path: &Path, hunk: usize, context_lines: u32) -> Result<(), OafError> {
+    let limits = DiffLimits { context_lines, ..DiffLimits::unlimited() };
+    let diff = patch::staged_diff(repo, path, limits)?;
+    let delta = diff.get_delta(0).ok_or_else(|| no_changes(path, "staged"))?;
+    if delta.status() != Delta::Modified {
+        return unstage_file(repo, path);
+    }
+
+    let patch = git2::Patch::from_diff(&diff, 0)?.ok_or_else(|| binary(path))?;
+    let index_blob = repo.find_blob(delta.new_file().id())?;
+    let content = apply_hunk(index_blob.content(), &patch, hunk, true)?;
+    write_to_index(repo, path, &content)
+}
+
+fn no_changes(path: &Path, kind: &str) -> OafError {
+    OafError::Index(Error::from_str(&format!("'{}' has no {} changes.", path.display(), kind)))
+}
+
+fn binary(path: &Path) -> OafError {
+    OafError::Index(Error::from_str(&format!("'{}' is binary, so can only be staged or unstaged whole.", path.display())))
+}
+
+/// Applies one hunk of `patch` to `base`, the old side of the patch; or if
+/// `reverse`, takes it back out of `base`, the new side.
+fn apply_hunk(base: &[u8], patch: &git2::Patch, hunk: usize, reverse: bool) -> Result<Vec<u8>, OafError> {
+    if hunk >= patch.num_hunks() {
+        return Err(OafError::Index(Error::from_str("The hunk is no longer there; the diff may have changed.")));
+    }
+    let (header, count) = patch.hunk(hunk)?;
+    let mut expected = Vec::new();
+    let mut replacement = Vec::new();
+    for n in 0..count {
+        let line = patch.line_in_hunk(hunk, n)?;
+        // The 'no newline at end of file' markers are not content; a line
+        // without a newline just comes without one.
+        let (from_base, to_result) = match (line.origin(), reverse) {
+            (' ', _) => (true, true),
+            ('-', false) | ('+', true) => (true, false),
+            ('+', false) | ('-', true) => (false, true),
+            _ => (false, false),
+        };
+        if from_base {
+            expected.push(line.content().to_vec());
+        }
+        if to_result {
+            replacement.push(line.content().to_vec());
+        }
+    }
+
+    let (start, lines) = if reverse { (header.new_start(), header.new_lines()) } else { (header.old_start(), header.old_lines()) };
+    splice(base, start as usize, lines as usize, &expected, &replacement)
+        .ok_or_else(|| OafError::Index(Error::from_str("The hunk no longer applies; the file may have changed.")))
+}
+
+/// Replaces the `lines` lines of `base` from line `start`, counting from 1,
+/// with `replacement`, if they are the `expected` lines. As in a hunk
+/// header, when no lines are replaced `start` is the line before the place
+/// the replacement goes.
+fn splice(base: &[u8], start: usize, lines: usize, expected: &[Vec<u8>], replacement: &[Vec<u8>]) -> Option<Vec<u8>> {
+    let base_lines = split_lines(base);
+    let first = if lines == 0 { start } else { start.checked_sub(1)? };
+    if first + lines > base_lines.len() || expected.len() != lines {
+        return None;
+    }
+    if base_lines[first..first + lines].iter().zip(expected).any(|(&line, expected)| line != &expected[..]) {
+        return None;
+    }
+
+    let mut result = Vec::with_capacity(base.len());
+    for line in &base_lines[..first] {
+        result.extend_from_slice(line);
+    }
+    for line in replacement {
+        result.extend_from_slice(line);
+    }
+    for line in &base_lines[first + lines..] {
+        result.extend_from_slice(line);
+    }
+    Some(result)
+}
+
+/// The lines of `content`, each with its line ending if it has one.
+fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, &byte) in content.iter().enumerate() {
+        if byte == b'\n' {
+            lines.push(&content[start..i + 1]);
+            start = i + 1;
+        }
+    }
+    if start < content.len() {
+        lines.push(&content[start..]);
+    }
+    lines
+}
+
+/// Points the index entry for `path` at a new blob of `content`. The entry's
+/// timestamps are cleared so that git compares the working tree file with
+/// it again rather than trusting the file's unchanged size and time.
+fn write_to_index(repo: &Repository, path: &Path, content: &[u8]) -> Result<(), OafError> {
+    let mut index = repo.index().map_err(OafError::Index)?;
+    let mut entry = index.get_path(path, 0)
+        .ok_or_else(|| OafError::Index(Error::from_str(&format!("'{}' is not in the index.", path.display()))))?;
+    entry.id = repo.blob(content)?;
+    entry.file_size = content.len() as u32;
+    entry.ctime = IndexTime::new(0, 0);
+    entry.mtime = IndexTime::new(0, 0);
+    index.add(&entry).map_err(OafError::Index)?;
+    index.write().map_err(OafError::Index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &[&str]) -> Vec<Vec<u8>> {
+        text.iter().map(|l| l.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn split_lines_keeps_line_endings() {
+        assert_eq!(split_lines(b"a\nb\r\nc"), vec![&b"a\n"[..], &b"b\r\n"[..], &b"c"[..]]);
+        assert!(split_lines(b"").is_empty());
+    }
+
+    #[test]
+    fn splice_replaces_the_expected_lines() {
+        let base = b"1\n2\n3\n4\n";
+        assert_eq!(splice(base, 2, 2, &lines(&["2\n", "3\n"]), &lines(&["two\n"])), Some(b"1\ntwo\n4\n".to_vec()));
+        // Nothing replaced: the new lines go after line 4.
+        assert_eq!(splice(base, 4, 0, &[], &lines(&["5"])), Some(b"1\n2\n3\n4\n5".to_vec()));
+        assert_eq!(splice(base, 0, 0, &[], &lines(&["0\n"])), Some(b"0\n1\n2\n3\n4\n".to_vec()));
+    }
+
+    #[test]
+    fn splice_refuses_lines_that_have_changed() {
+        let base = b"1\n2\n3\n";
+        assert_eq!(splice(base, 2, 1, &lines(&["changed\n"]), &lines(&["two\n"])), None);
+        assert_eq!(splice(base, 3, 2, &lines(&["3\n", "4\n"]), &[]), None);
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt;
+use std::process;
+
+/// Something that stops oaf starting. These are found before the UI is up,
+/// so they are reported on the terminal with a hint about how to fix them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupError {
+    /// The home directory, under which the config and data directories
+    /// live, could not be found.
+    NoHomeDirectory,
+    /// '--control-socket' or '--single-instance' was given, but oaf was
+    /// built without the control-socket feature.
+    NoControlSocket,
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StartupError::NoHomeDirectory => write!(f,
+                "Cannot find your home directory, which is where oaf keeps its settings.\n\
+                 Set the HOME environment variable to an existing directory and try again."),
+            StartupError::NoControlSocket => write!(f,
+                "This oaf was built without the control socket, so --control-socket and --single-instance cannot be used.\n\
+                 Build it with '--features control-socket'; it is only available on Unix."),
+        }
+    }
+}
+
+/// Prints `error` and ends the program.
+pub fn exit(error: &StartupError) -> ! {
+    eprintln!("oaf cannot start.\n\n{}", error);
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_say_how_to_fix_the_problem() {
+        assert!(StartupError::NoHomeDirectory.to_string().contains("HOME"));
+        assert!(StartupError::NoControlSocket.to_string().contains("--features control-socket"));
+    }
+}
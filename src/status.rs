@@ -0,0 +1,399 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use git2::{Repository, Status, StatusOptions};
+
+/// Counts of the changes in the index and working tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusSummary {
+    pub staged: usize,
+    pub unstaged: usize,
+    pub conflicted: usize,
+    /// None if untracked files were not scanned.
+    pub untracked: Option<usize>,
+}
+
+impl StatusSummary {
+    pub fn description(&self) -> String {
+        let untracked = match self.untracked {
+            Some(n) => format!("{} untracked", n),
+            None => "untracked not scanned".to_string(),
+        };
+
+        let mut text = format!("{} staged, {} unstaged, {}", self.staged, self.unstaged, untracked);
+        if self.conflicted > 0 {
+            text += &format!(", {} conflicted", self.conflicted);
+        }
+        text
+    }
+}
+
+/// Summarises the status of the working tree. Scanning for untracked files
+/// means walking the whole working tree, which is slow in large repositories,
+/// so it is optional. Ignored files are never scanned.
+pub fn summary(repo: &Repository, scan_untracked: bool) -> Result<StatusSummary, ::git2::Error> {
+    let _timer = timer!("status::summary");
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(scan_untracked)
+        .recurse_untracked_dirs(scan_untracked)
+        .include_ignored(false)
+        .exclude_submodules(true);
+
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let mut summary = StatusSummary {
+        untracked: if scan_untracked { Some(0) } else { None },
+        ..StatusSummary::default()
+    };
+
+    let (staged, unstaged) = (staged_flags(), unstaged_flags());
+    for entry in statuses.iter() {
+        let status = entry.status();
+        if status.contains(Status::CONFLICTED) {
+            summary.conflicted += 1;
+            continue;
+        }
+        if status.intersects(staged) {
+            summary.staged += 1;
+        }
+        if status.intersects(unstaged) {
+            summary.unstaged += 1;
+        }
+        if status.contains(Status::WT_NEW) {
+            summary.untracked = summary.untracked.map(|n| n + 1);
+        }
+    }
+
+    _timer.set_message(summary.description());
+    Ok(summary)
+}
+
+fn staged_flags() -> Status {
+    Status::INDEX_NEW | Status::INDEX_MODIFIED | Status::INDEX_DELETED | Status::INDEX_RENAMED | Status::INDEX_TYPECHANGE
+}
+
+fn unstaged_flags() -> Status {
+    Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE
+}
+
+/// Splits the files `changed_files` returns into those with staged changes
+/// and those with changes not staged, including untracked and conflicted
+/// files. A file with both kinds of change is in both lists.
+pub fn split_by_index(files: Vec<(PathBuf, Status)>) -> (Vec<(PathBuf, Status)>, Vec<(PathBuf, Status)>) {
+    let unstaged = unstaged_flags() | Status::WT_NEW | Status::CONFLICTED;
+    let mut staged_files = Vec::new();
+    let mut unstaged_files = Vec::new();
+    for (path, status) in files {
+        if status.intersects(staged_flags()) && !status.contains(Status::CONFLICTED) {
+            staged_files.push((path.clone(), status));
+        }
+        if status.intersects(unstaged) {
+            unstaged_files.push((path, status));
+        }
+    }
+    (staged_files, unstaged_files)
+}
+
+/// Set in an index entry's extended flags when the file is deliberately not
+/// in the working tree, as in a sparse checkout.
+pub const SKIP_WORKTREE: u16 = 1 << 14;
+
+/// Lists the files with uncommitted changes, with their status, in path
+/// order. Untracked files are only included if `scan_untracked` is set.
+/// Files left out of a sparse checkout are not reported as deleted, since
+/// libgit2 does not know about sparse checkouts.
+pub fn changed_files(repo: &Repository, scan_untracked: bool) -> Result<Vec<(PathBuf, Status)>, ::git2::Error> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(scan_untracked)
+        .recurse_untracked_dirs(scan_untracked)
+        .include_ignored(false)
+        .exclude_submodules(true);
+
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let mut files: Vec<_> = statuses.iter()
+        .filter_map(|e| e.path().map(|p| (PathBuf::from(p), e.status())))
+        .collect();
+    if files.iter().any(|&(_, s)| s == Status::WT_DELETED) {
+        let skipped = skip_worktree_paths(repo)?;
+        files.retain(|&(ref path, s)| s != Status::WT_DELETED || !skipped.contains(path));
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+/// The paths marked skip-worktree in the index, which are deliberately not
+/// in the working tree.
+fn skip_worktree_paths(repo: &Repository) -> Result<HashSet<PathBuf>, ::git2::Error> {
+    Ok(repo.index()?.iter()
+        .filter(|e| e.flags_extended & SKIP_WORKTREE != 0)
+        .map(|e| PathBuf::from(String::from_utf8_lossy(&e.path).into_owned()))
+        .collect())
+}
+
+/// The changed files in the working tree as the status view shows them,
+/// with the untracked files grouped by directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusTree {
+    /// The files git tracks that have changes, including conflicted files.
+    pub tracked: Vec<(PathBuf, Status)>,
+    /// The root of the untracked files; its path is empty.
+    pub untracked: UntrackedDir,
+}
+
+/// A directory containing untracked files, somewhere below it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UntrackedDir {
+    /// The path relative to the root of the working tree.
+    pub path: PathBuf,
+    pub dirs: Vec<UntrackedDir>,
+    pub files: Vec<PathBuf>,
+}
+
+impl UntrackedDir {
+    fn add(&mut self, file: PathBuf) {
+        let dir = match file.strip_prefix(&self.path).ok().and_then(|rest| rest.iter().next()) {
+            Some(first) if self.path.join(first) != file => self.path.join(first),
+            _ => return self.files.push(file),
+        };
+
+        match self.dirs.iter_mut().position(|d| d.path == dir) {
+            Some(i) => self.dirs[i].add(file),
+            None => {
+                let mut child = UntrackedDir { path: dir, dirs: Vec::new(), files: Vec::new() };
+                child.add(file);
+                self.dirs.push(child);
+            },
+        }
+    }
+
+    /// All the untracked files in this directory and below it.
+    pub fn all_files(&self) -> Vec<PathBuf> {
+        let mut files = self.files.clone();
+        for dir in &self.dirs {
+            files.extend(dir.all_files());
+        }
+        files
+    }
+
+    /// Finds the directory at `path`, which may be this one.
+    pub fn find(&self, path: &Path) -> Option<&UntrackedDir> {
+        if self.path == path {
+            return Some(self);
+        }
+        self.dirs.iter().filter(|d| path.starts_with(&d.path)).filter_map(|d| d.find(path)).next()
+    }
+
+    fn add_rows(&self, depth: usize, expanded: &HashSet<PathBuf>, rows: &mut Vec<StatusRow>) {
+        for dir in &self.dirs {
+            let is_expanded = expanded.contains(&dir.path);
+            rows.push(StatusRow::Dir { path: dir.path.clone(), files: dir.all_files().len(), depth, expanded: is_expanded });
+            if is_expanded {
+                dir.add_rows(depth + 1, expanded, rows);
+            }
+        }
+        for file in &self.files {
+            rows.push(StatusRow::File { path: file.clone(), status: Status::WT_NEW, depth });
+        }
+    }
+}
+
+/// A row of the status view: a file, or a directory of untracked files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatusRow {
+    /// `depth` is how many directories the file is nested in; it is always
+    /// 0 for tracked files.
+    File { path: PathBuf, status: Status, depth: usize },
+    /// `files` is the number of untracked files in the directory and below.
+    Dir { path: PathBuf, files: usize, depth: usize, expanded: bool },
+}
+
+impl StatusRow {
+    pub fn path(&self) -> &Path {
+        match *self {
+            StatusRow::File { ref path, .. } | StatusRow::Dir { ref path, .. } => path,
+        }
+    }
+
+    pub fn is_untracked(&self) -> bool {
+        match *self {
+            StatusRow::File { status, .. } => status.contains(Status::WT_NEW),
+            StatusRow::Dir { .. } => true,
+        }
+    }
+}
+
+impl StatusTree {
+    /// Builds the tree from the files `changed_files` returns.
+    pub fn new(files: Vec<(PathBuf, Status)>) -> Self {
+        let mut tree = StatusTree::default();
+        for (path, status) in files {
+            if status.contains(Status::WT_NEW) {
+                tree.untracked.add(path);
+            } else {
+                tree.tracked.push((path, status));
+            }
+        }
+        tree
+    }
+
+    /// The rows to show: the tracked files, then the untracked directories
+    /// and files. The contents of a directory are only shown if it is in
+    /// `expanded`.
+    pub fn rows(&self, expanded: &HashSet<PathBuf>) -> Vec<StatusRow> {
+        let mut rows: Vec<StatusRow> = self.tracked.iter()
+            .map(|&(ref path, status)| StatusRow::File { path: path.clone(), status, depth: 0 })
+            .collect();
+        self.untracked.add_rows(0, expanded, &mut rows);
+        rows
+    }
+
+    /// The untracked files a row stands for: the file itself, or everything
+    /// in the directory.
+    pub fn untracked_files(&self, row: &StatusRow) -> Vec<PathBuf> {
+        match *row {
+            StatusRow::File { ref path, status, .. } if status.contains(Status::WT_NEW) => vec![path.clone()],
+            StatusRow::File { .. } => Vec::new(),
+            StatusRow::Dir { ref path, .. } => self.untracked.find(path).map(|d| d.all_files()).unwrap_or_default(),
+        }
+    }
+}
+
+/// The two-letter code 'git status --short' uses for a file: the index
+/// status then the working tree status.
+pub fn short_code(status: Status) -> String {
+    if status.contains(Status::CONFLICTED) {
+        return "UU".to_string();
+    }
+    if status.contains(Status::WT_NEW) {
+        return "??".to_string();
+    }
+
+    let index = if status.contains(Status::INDEX_NEW) { 'A' }
+        else if status.contains(Status::INDEX_MODIFIED) { 'M' }
+        else if status.contains(Status::INDEX_DELETED) { 'D' }
+        else if status.contains(Status::INDEX_RENAMED) { 'R' }
+        else if status.contains(Status::INDEX_TYPECHANGE) { 'T' }
+        else { ' ' };
+    let worktree = if status.contains(Status::WT_MODIFIED) { 'M' }
+        else if status.contains(Status::WT_DELETED) { 'D' }
+        else if status.contains(Status::WT_RENAMED) { 'R' }
+        else if status.contains(Status::WT_TYPECHANGE) { 'T' }
+        else { ' ' };
+    format!("{}{}", index, worktree)
+}
+
+/// Describes a file's status in words, e.g. 'staged new file, unstaged
+/// changes', for when the short code is not clear enough.
+pub fn describe(status: Status) -> String {
+    if status.contains(Status::CONFLICTED) {
+        return "conflicted".to_string();
+    }
+    if status.contains(Status::WT_NEW) {
+        return "untracked".to_string();
+    }
+
+    let index = if status.contains(Status::INDEX_NEW) { Some("new file") }
+        else if status.contains(Status::INDEX_MODIFIED) { Some("changes") }
+        else if status.contains(Status::INDEX_DELETED) { Some("deletion") }
+        else if status.contains(Status::INDEX_RENAMED) { Some("rename") }
+        else if status.contains(Status::INDEX_TYPECHANGE) { Some("type change") }
+        else { None };
+    let worktree = if status.contains(Status::WT_MODIFIED) { Some("changes") }
+        else if status.contains(Status::WT_DELETED) { Some("deletion") }
+        else if status.contains(Status::WT_RENAMED) { Some("rename") }
+        else if status.contains(Status::WT_TYPECHANGE) { Some("type change") }
+        else { None };
+
+    let parts: Vec<String> = index.map(|s| format!("staged {}", s)).into_iter()
+        .chain(worktree.map(|s| format!("unstaged {}", s)))
+        .collect();
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description_when_untracked_not_scanned() {
+        let summary = StatusSummary { staged: 1, unstaged: 2, conflicted: 0, untracked: None };
+        assert_eq!(summary.description(), "1 staged, 2 unstaged, untracked not scanned");
+    }
+
+    #[test]
+    fn description_with_conflicts() {
+        let summary = StatusSummary { staged: 0, unstaged: 0, conflicted: 3, untracked: Some(4) };
+        assert_eq!(summary.description(), "0 staged, 0 unstaged, 4 untracked, 3 conflicted");
+    }
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(describe(Status::WT_NEW), "untracked");
+        assert_eq!(describe(Status::INDEX_NEW | Status::WT_MODIFIED), "staged new file, unstaged changes");
+        assert_eq!(describe(Status::WT_DELETED), "unstaged deletion");
+    }
+
+    fn tree() -> StatusTree {
+        StatusTree::new(vec![
+            (PathBuf::from("a.txt"), Status::WT_MODIFIED),
+            (PathBuf::from("build/out/x.o"), Status::WT_NEW),
+            (PathBuf::from("build/y.o"), Status::WT_NEW),
+            (PathBuf::from("notes.txt"), Status::WT_NEW),
+        ])
+    }
+
+    #[test]
+    fn untracked_files_are_grouped_by_directory() {
+        let tree = tree();
+        assert_eq!(tree.tracked, vec![(PathBuf::from("a.txt"), Status::WT_MODIFIED)]);
+        assert_eq!(tree.untracked.files, vec![PathBuf::from("notes.txt")]);
+        let build = &tree.untracked.dirs[0];
+        assert_eq!(build.path, Path::new("build"));
+        assert_eq!(build.files, vec![PathBuf::from("build/y.o")]);
+        assert_eq!(build.dirs[0].path, Path::new("build/out"));
+        assert_eq!(build.all_files(), vec![PathBuf::from("build/y.o"), PathBuf::from("build/out/x.o")]);
+    }
+
+    #[test]
+    fn rows_show_expanded_directories() {
+        let tree = tree();
+        let dir = |path: &str, files, depth, expanded| StatusRow::Dir { path: PathBuf::from(path), files, depth, expanded };
+        let file = |path: &str, status, depth| StatusRow::File { path: PathBuf::from(path), status, depth };
+
+        assert_eq!(tree.rows(&HashSet::new()), vec![
+            file("a.txt", Status::WT_MODIFIED, 0),
+            dir("build", 2, 0, false),
+            file("notes.txt", Status::WT_NEW, 0),
+        ]);
+
+        let expanded = vec![PathBuf::from("build"), PathBuf::from("build/out")].into_iter().collect();
+        let rows = tree.rows(&expanded);
+        assert_eq!(rows[1..5].to_vec(), vec![
+            dir("build", 2, 0, true),
+            dir("build/out", 1, 1, true),
+            file("build/out/x.o", Status::WT_NEW, 2),
+            file("build/y.o", Status::WT_NEW, 1),
+        ]);
+        assert_eq!(tree.untracked_files(&rows[2]), vec![PathBuf::from("build/out/x.o")]);
+        assert!(tree.untracked_files(&rows[0]).is_empty());
+    }
+
+    #[test]
+    fn files_are_split_into_staged_and_unstaged() {
+        let (staged, unstaged) = split_by_index(vec![
+            (PathBuf::from("both.txt"), Status::INDEX_MODIFIED | Status::WT_MODIFIED),
+            (PathBuf::from("conflict.txt"), Status::CONFLICTED | Status::INDEX_MODIFIED),
+            (PathBuf::from("new.txt"), Status::WT_NEW),
+            (PathBuf::from("staged.txt"), Status::INDEX_NEW),
+        ]);
+        let paths = |files: Vec<(PathBuf, Status)>| files.into_iter().map(|(p, _)| p).collect::<Vec<_>>();
+        assert_eq!(paths(staged), vec![PathBuf::from("both.txt"), PathBuf::from("staged.txt")]);
+        assert_eq!(paths(unstaged), vec![PathBuf::from("both.txt"), PathBuf::from("conflict.txt"), PathBuf::from("new.txt")]);
+    }
+
+    #[test]
+    fn short_codes() {
+        assert_eq!(short_code(Status::WT_NEW), "??");
+        assert_eq!(short_code(Status::INDEX_MODIFIED | Status::WT_MODIFIED), "MM");
+        assert_eq!(short_code(Status::INDEX_NEW), "A ");
+        assert_eq!(short_code(Status::WT_DELETED), " D");
+    }
+}
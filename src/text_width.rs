@@ -0,0 +1,134 @@
+use unicode_width::UnicodeWidthChar;
+
+const ELLIPSIS: char = '…';
+
+/// Where text that is too wide is cut, and so where the ellipsis goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ellipsis {
+    /// Keep the end, e.g. '…/src/main.rs'. Good for paths, where the file
+    /// name matters most.
+    Start,
+    /// Keep both ends, e.g. 'src/…/main.rs'.
+    Middle,
+    /// Keep the start, e.g. 'Fix the bu…'.
+    End,
+}
+
+/// The number of terminal columns `text` takes up. CJK characters take two
+/// columns and combining characters none.
+pub fn width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// Shortens `text` to at most `max_width` columns, replacing what is cut
+/// with an ellipsis. Combining characters stay with the character they
+/// modify.
+pub fn truncate(text: &str, max_width: usize, ellipsis: Ellipsis) -> String {
+    if width(text) <= max_width {
+        return text.to_string();
+    }
+    if max_width == 0 {
+        return String::new();
+    }
+
+    let available = max_width - 1;
+    match ellipsis {
+        Ellipsis::End => format!("{}{}", head(text, available), ELLIPSIS),
+        Ellipsis::Start => format!("{}{}", ELLIPSIS, tail(text, available)),
+        Ellipsis::Middle => {
+            let head_width = (available + 1) / 2;
+            format!("{}{}{}", head(text, head_width), ELLIPSIS, tail(text, available - head_width))
+        },
+    }
+}
+
+/// Pads `text` with spaces on the right to `min_width` columns.
+pub fn pad(text: &str, min_width: usize) -> String {
+    let mut padded = text.to_string();
+    for _ in width(text)..min_width {
+        padded.push(' ');
+    }
+    padded
+}
+
+/// Makes `text` exactly `columns` wide, truncating or padding as needed, for
+/// lining up columns in lists.
+pub fn fit(text: &str, columns: usize, ellipsis: Ellipsis) -> String {
+    pad(&truncate(text, columns, ellipsis), columns)
+}
+
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// The longest start of `text` that fits in `max_width` columns, including
+/// any combining characters after the last character that fits.
+fn head(text: &str, max_width: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        used += char_width(c);
+        if used > max_width {
+            return &text[..i];
+        }
+    }
+    text
+}
+
+/// The longest end of `text` that fits in `max_width` columns. It never
+/// starts with a combining character, since that would be cut off from the
+/// character it modifies.
+fn tail(text: &str, max_width: usize) -> &str {
+    let mut used = 0;
+    let mut start = text.len();
+    for (i, c) in text.char_indices().rev() {
+        let w = char_width(c);
+        if used + w > max_width {
+            break;
+        }
+        used += w;
+        if w > 0 {
+            start = i;
+        }
+    }
+    &text[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_counts_columns() {
+        assert_eq!(width("abc"), 3);
+        assert_eq!(width("日本"), 4);
+        assert_eq!(width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn short_text_is_unchanged() {
+        assert_eq!(truncate("abc", 3, Ellipsis::End), "abc");
+        assert_eq!(fit("abc", 5, Ellipsis::End), "abc  ");
+    }
+
+    #[test]
+    fn truncates_at_each_position() {
+        assert_eq!(truncate("src/tui/main.rs", 8, Ellipsis::End), "src/tui…");
+        assert_eq!(truncate("src/tui/main.rs", 8, Ellipsis::Start), "…main.rs");
+        assert_eq!(truncate("src/tui/main.rs", 8, Ellipsis::Middle), "src/….rs");
+    }
+
+    #[test]
+    fn wide_characters_are_not_split() {
+        // Each character is two columns, so only two fit before the ellipsis.
+        assert_eq!(truncate("日本語です", 6, Ellipsis::End), "日本…");
+        assert_eq!(width(&fit("日本語です", 6, Ellipsis::End)), 6);
+        assert_eq!(truncate("日本語です", 6, Ellipsis::Start), "…です");
+    }
+
+    #[test]
+    fn combining_characters_stay_with_their_base() {
+        assert_eq!(truncate("e\u{301}e\u{301}e\u{301}", 2, Ellipsis::End), "e\u{301}…");
+        assert_eq!(truncate("e\u{301}e\u{301}e\u{301}", 2, Ellipsis::Start), "…e\u{301}");
+    }
+}
@@ -7,6 +7,7 @@ use log;
 /// This module implements a Timer which allow blocks of code to be timed.
 /// A message is logged when the Timer is dropped. The message can be extended
 /// with extra information. Two macros can simplify the creation of timers.
+/// When a trace is being recorded, every timer is also recorded as a span.
 #[derive(Debug, Clone)]
 pub struct Timer<'a> {
     name: &'a str,
@@ -118,6 +119,10 @@ impl<'a> Timer<'a> {
 
 impl<'a> Drop for Timer<'a> {
     fn drop(&mut self) {
+        if ::trace::is_enabled() {
+            ::trace::record(self.name, self.message.borrow().clone(), self.start_time, self.elapsed());
+        }
+
         if self.log_on_drop {
             log!(self.log_level, "{}", self);
         }
@@ -0,0 +1,208 @@
+/// This module records the spans measured by Timers so that a session can be
+/// inspected in chrome://tracing (or any viewer of the Trace Event Format).
+/// Recording is off unless `start` is called, which oaf does when it is run
+/// with `--trace-file`; the file is written by `finish` as oaf exits.
+use std::cell::Cell;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Checked before taking the lock, so that timers cost nothing extra when
+/// tracing is off.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(1);
+
+lazy_static! {
+    static ref RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+}
+
+thread_local! {
+    static THREAD_ID: Cell<usize> = Cell::new(0);
+}
+
+/// One completed span, with times in microseconds since the recording started.
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    name: String,
+    message: Option<String>,
+    thread: usize,
+    start: u64,
+    duration: u64,
+}
+
+#[derive(Debug)]
+struct Recorder {
+    file: PathBuf,
+    epoch: Instant,
+    spans: Vec<Span>,
+    /// Thread ids and names, so that the viewer can label each row.
+    threads: Vec<(usize, String)>,
+}
+
+impl Recorder {
+    fn new(file: PathBuf) -> Self {
+        Recorder { file, epoch: Instant::now(), spans: Vec::new(), threads: Vec::new() }
+    }
+
+    fn add(&mut self, name: &str, message: Option<String>, thread: usize, start: Instant, duration: Duration) {
+        if start < self.epoch {
+            // Started before the recording did; it would be drawn at a
+            // negative time.
+            return;
+        }
+
+        if !self.threads.iter().any(|&(id, _)| id == thread) {
+            let name = thread::current().name().map_or_else(|| format!("thread {}", thread), |n| n.to_string());
+            self.threads.push((thread, name));
+        }
+
+        self.spans.push(Span {
+            name: name.to_string(),
+            message,
+            thread,
+            start: micros(start - self.epoch),
+            duration: micros(duration),
+        });
+    }
+
+    /// The recording as a JSON object in the Trace Event Format. Each span is
+    /// a "complete" event (ph X); the thread names are metadata events.
+    fn to_json(&self, pid: u32) -> String {
+        let mut events = Vec::with_capacity(self.threads.len() + self.spans.len());
+        for &(id, ref name) in &self.threads {
+            events.push(format!(r#"{{"name":"thread_name","ph":"M","pid":{},"tid":{},"args":{{"name":{}}}}}"#,
+                                pid, id, quote(name)));
+        }
+        for span in &self.spans {
+            let mut event = format!(r#"{{"name":{},"cat":"oaf","ph":"X","pid":{},"tid":{},"ts":{},"dur":{}"#,
+                                    quote(&span.name), pid, span.thread, span.start, span.duration);
+            if let Some(ref message) = span.message {
+                let _ = write!(event, r#","args":{{"message":{}}}"#, quote(message));
+            }
+            event.push('}');
+            events.push(event);
+        }
+
+        format!("{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n", events.join(",\n"))
+    }
+}
+
+/// Starts recording spans, to be written to `file` by `finish`.
+pub fn start(file: &Path) {
+    *RECORDER.lock().unwrap() = Some(Recorder::new(file.to_path_buf()));
+    ENABLED.store(true, Ordering::SeqCst);
+    info!("Recording a trace to {}", file.display());
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records a span that began at `start` and lasted `duration`. Does nothing
+/// unless recording has been started.
+pub fn record(name: &str, message: Option<String>, start: Instant, duration: Duration) {
+    if !is_enabled() {
+        return;
+    }
+
+    let thread = current_thread_id();
+    if let Ok(mut recorder) = RECORDER.lock() {
+        if let Some(ref mut recorder) = *recorder {
+            recorder.add(name, message, thread, start, duration);
+        }
+    }
+}
+
+/// Stops recording and writes the trace file. Does nothing if recording was
+/// never started.
+pub fn finish() -> io::Result<()> {
+    ENABLED.store(false, Ordering::SeqCst);
+    let recorder = match RECORDER.lock() {
+        Ok(mut recorder) => recorder.take(),
+        Err(_) => None,
+    };
+
+    match recorder {
+        Some(recorder) => {
+            info!("Writing {} spans to trace file {}", recorder.spans.len(), recorder.file.display());
+            fs::write(&recorder.file, recorder.to_json(process::id()))
+        },
+        None => Ok(()),
+    }
+}
+
+/// Small sequential ids are easier to read in the viewer than the OS ids.
+fn current_thread_id() -> usize {
+    THREAD_ID.with(|id| {
+        if id.get() == 0 {
+            id.set(NEXT_THREAD_ID.fetch_add(1, Ordering::SeqCst));
+        }
+        id.get()
+    })
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + (duration.subsec_nanos() / 1_000) as u64
+}
+
+/// Quotes `s` as a JSON string.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(quoted, "\\u{:04x}", c as u32); },
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_escapes_json_specials() {
+        assert_eq!(quote("plain"), r#""plain""#);
+        assert_eq!(quote("a \"b\" c\\d"), r#""a \"b\" c\\d""#);
+        assert_eq!(quote("line\nbreak\u{1}"), r#""line\nbreak\u0001""#);
+    }
+
+    #[test]
+    fn spans_are_written_as_complete_events_relative_to_the_start() {
+        let mut recorder = Recorder::new(PathBuf::from("trace.json"));
+        let epoch = recorder.epoch;
+        recorder.add("load log", None, 1, epoch + Duration::from_millis(5), Duration::from_micros(1500));
+        recorder.add("status", Some("12 files".to_string()), 2, epoch + Duration::from_secs(1), Duration::from_millis(2));
+
+        assert_eq!(recorder.spans[0], Span { name: "load log".to_string(), message: None, thread: 1, start: 5000, duration: 1500 });
+        let json = recorder.to_json(42);
+        assert!(json.starts_with("{\"traceEvents\":["));
+        assert!(json.contains(r#"{"name":"load log","cat":"oaf","ph":"X","pid":42,"tid":1,"ts":5000,"dur":1500}"#));
+        assert!(json.contains(r#""ts":1000000,"dur":2000,"args":{"message":"12 files"}}"#));
+        assert!(json.contains(r#""name":"thread_name","ph":"M","pid":42,"tid":1"#));
+    }
+
+    #[test]
+    fn spans_that_started_before_recording_are_dropped() {
+        let before = Instant::now();
+        thread::sleep(Duration::from_millis(1));
+        let mut recorder = Recorder::new(PathBuf::from("trace.json"));
+        recorder.add("early", None, 1, before, Duration::from_millis(3));
+        assert!(recorder.spans.is_empty());
+    }
+}
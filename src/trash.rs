@@ -0,0 +1,216 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::Local;
+use toml;
+
+const CONTENT_FILE: &str = "content";
+const INFO_FILE: &str = "info.toml";
+
+/// A working tree file that oaf deleted or overwrote, kept so that it can be
+/// put back. Each entry is a directory in the trash holding the file's
+/// content and an 'info.toml' describing where it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// The name of the entry's directory in the trash.
+    #[serde(skip)]
+    pub id: String,
+    /// The working tree the file was in.
+    pub workdir: PathBuf,
+    /// The file's path relative to `workdir`.
+    pub path: PathBuf,
+    /// When it was trashed, as 'YYYY-MM-DD HH:MM:SS'.
+    pub trashed: String,
+    /// What oaf was doing at the time, e.g. 'discard changes'.
+    pub reason: String,
+}
+
+impl TrashEntry {
+    pub fn original_path(&self) -> PathBuf {
+        self.workdir.join(&self.path)
+    }
+}
+
+/// Moves the file at `path`, relative to `workdir`, into the trash. A
+/// symlink is trashed as the link itself.
+pub fn move_to_trash(trash_dir: &Path, workdir: &Path, path: &Path, reason: &str) -> io::Result<TrashEntry> {
+    let now = Local::now();
+    let stamp = now.format("%Y%m%d-%H%M%S").to_string();
+    let mut id = stamp.clone();
+    let mut n = 1;
+    while trash_dir.join(&id).exists() {
+        n += 1;
+        id = format!("{}-{}", stamp, n);
+    }
+
+    let entry = TrashEntry {
+        id,
+        workdir: workdir.to_path_buf(),
+        path: path.to_path_buf(),
+        trashed: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+        reason: reason.to_string(),
+    };
+
+    let entry_dir = trash_dir.join(&entry.id);
+    fs::create_dir_all(&entry_dir)?;
+    let info = toml::to_string(&entry).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    fs::write(entry_dir.join(INFO_FILE), info)?;
+    move_file(&entry.original_path(), &entry_dir.join(CONTENT_FILE))?;
+
+    info!("Moved '{}' to the trash as '{}'", entry.original_path().display(), entry.id);
+    Ok(entry)
+}
+
+/// Lists the entries in the trash, newest first. Entries that cannot be read
+/// are skipped.
+pub fn list(trash_dir: &Path) -> io::Result<Vec<TrashEntry>> {
+    let dirs = match fs::read_dir(trash_dir) {
+        Ok(dirs) => dirs,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut entries: Vec<TrashEntry> = dirs.filter_map(|d| d.ok())
+        .filter_map(|d| {
+            let text = fs::read_to_string(d.path().join(INFO_FILE)).ok()?;
+            let mut entry: TrashEntry = toml::from_str(&text).ok()?;
+            entry.id = d.file_name().to_string_lossy().into_owned();
+            Some(entry)
+        })
+        .collect();
+
+    entries.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(entries)
+}
+
+/// Puts a trashed file back where it came from. If a file is there now it
+/// is only replaced if `overwrite` is true, and is itself trashed first.
+pub fn restore(trash_dir: &Path, entry: &TrashEntry, overwrite: bool) -> io::Result<()> {
+    let target = entry.original_path();
+    if fs::symlink_metadata(&target).is_ok() {
+        if !overwrite {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("'{}' already exists.", target.display())));
+        }
+        move_to_trash(trash_dir, &entry.workdir, &entry.path, "overwritten by restore")?;
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let entry_dir = trash_dir.join(&entry.id);
+    move_file(&entry_dir.join(CONTENT_FILE), &target)?;
+    fs::remove_dir_all(&entry_dir)?;
+
+    info!("Restored '{}' from the trash", target.display());
+    Ok(())
+}
+
+/// Permanently deletes everything in the trash.
+pub fn empty(trash_dir: &Path) -> io::Result<usize> {
+    let entries = list(trash_dir)?;
+    for entry in &entries {
+        fs::remove_dir_all(trash_dir.join(&entry.id))?;
+    }
+    info!("Emptied the trash of {} entries", entries.len());
+    Ok(entries.len())
+}
+
+/// Renames `from` to `to`, falling back to copying then deleting when they
+/// are on different file systems. `from` may be a file, a directory or a
+/// symlink; a symlink is moved as a link, not as what it points to.
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_all(from, to)?;
+    if fs::symlink_metadata(from)?.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+/// Copies `from` to `to`, recreating symlinks rather than following them and
+/// copying directories with everything in them.
+fn copy_all(from: &Path, to: &Path) -> io::Result<()> {
+    let file_type = fs::symlink_metadata(from)?.file_type();
+    if file_type.is_symlink() {
+        copy_symlink(from, to)
+    } else if file_type.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_all(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    ::std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other,
+                       format!("'{}' is a symlink on another drive and cannot be moved.", from.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("oaf-trash-test-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn trash_and_restore_round_trip() {
+        let root = scratch_dir("round-trip");
+        let (trash_dir, workdir) = (root.join("trash"), root.join("work"));
+        fs::create_dir_all(workdir.join("src")).unwrap();
+        fs::write(workdir.join("src/a.txt"), "hello").unwrap();
+
+        let entry = move_to_trash(&trash_dir, &workdir, Path::new("src/a.txt"), "test").unwrap();
+        assert!(!workdir.join("src/a.txt").exists());
+        assert_eq!(list(&trash_dir).unwrap(), vec![entry.clone()]);
+
+        fs::write(workdir.join("src/a.txt"), "new").unwrap();
+        assert_eq!(restore(&trash_dir, &entry, false).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        restore(&trash_dir, &entry, true).unwrap();
+        assert_eq!(fs::read_to_string(workdir.join("src/a.txt")).unwrap(), "hello");
+
+        // The file that was overwritten is now in the trash.
+        let remaining = list(&trash_dir).unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].reason, "overwritten by restore");
+        assert_eq!(empty(&trash_dir).unwrap(), 1);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copying_keeps_symlinks_and_directories() {
+        let root = scratch_dir("copy-all");
+        fs::create_dir_all(root.join("from/sub")).unwrap();
+        fs::write(root.join("from/sub/a.txt"), "a").unwrap();
+        ::std::os::unix::fs::symlink("sub/a.txt", root.join("from/link")).unwrap();
+
+        copy_all(&root.join("from"), &root.join("to")).unwrap();
+        assert_eq!(fs::read_to_string(root.join("to/sub/a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_link(root.join("to/link")).unwrap(), PathBuf::from("sub/a.txt"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn list_of_missing_trash_is_empty() {
+        assert!(list(Path::new("/no/such/oaf/trash")).unwrap().is_empty());
+    }
+}
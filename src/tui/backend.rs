@@ -0,0 +1,98 @@
+use std::fmt;
+use std::str::FromStr;
+use cursive::Cursive;
+
+/// The terminal libraries cursive can draw with. Which of them oaf can use
+/// depends on the cargo features it was built with: 'ncurses-backend' (the
+/// default) and 'termion-backend', which is pure Rust and needs no curses
+/// library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Ncurses,
+    Termion,
+}
+
+const ALL: [Backend; 2] = [Backend::Ncurses, Backend::Termion];
+
+impl Backend {
+    /// Whether oaf was built with this backend.
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::Ncurses => cfg!(feature = "ncurses-backend"),
+            Backend::Termion => cfg!(feature = "termion-backend"),
+        }
+    }
+
+    /// The backend used when none is asked for: the first that oaf was
+    /// built with.
+    pub fn default_available() -> Option<Backend> {
+        ALL.iter().cloned().find(|b| b.is_available())
+    }
+
+    fn feature(self) -> &'static str {
+        match self {
+            Backend::Ncurses => "ncurses-backend",
+            Backend::Termion => "termion-backend",
+        }
+    }
+
+    /// Starts cursive with this backend, which must be available.
+    pub fn create(self) -> Cursive {
+        info!("Starting the UI with the {} backend", self);
+        match self {
+            #[cfg(feature = "ncurses-backend")]
+            Backend::Ncurses => Cursive::ncurses(),
+            #[cfg(feature = "termion-backend")]
+            Backend::Termion => Cursive::termion(),
+            #[allow(unreachable_patterns)]
+            _ => panic!("oaf was built without the {} feature", self.feature()),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Backend::Ncurses => "ncurses",
+            Backend::Termion => "termion",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parses a backend name, as given to '--backend'. Backends that oaf was not
+/// built with are an error, so that asking for one fails before the UI
+/// starts.
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let backend = match s.to_lowercase().as_str() {
+            "ncurses" => Backend::Ncurses,
+            "termion" => Backend::Termion,
+            "crossterm" => return Err("The version of cursive oaf uses has no crossterm backend".to_string()),
+            _ => return Err(format!("Unknown backend '{}', expected ncurses or termion", s)),
+        };
+
+        if backend.is_available() {
+            Ok(backend)
+        } else {
+            Err(format!("oaf was built without the {} backend; build it with '--features {}'", backend, backend.feature()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_backend_names() {
+        assert!("crossterm".parse::<Backend>().is_err());
+        assert!("curses".parse::<Backend>().unwrap_err().starts_with("Unknown backend"));
+        for backend in &ALL {
+            let parsed = backend.to_string().to_uppercase().parse::<Backend>();
+            assert_eq!(parsed.is_ok(), backend.is_available());
+        }
+    }
+}
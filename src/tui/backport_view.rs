@@ -0,0 +1,78 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{Checkbox, Dialog, LinearLayout, TextView};
+use git2::Oid;
+use backport;
+use config;
+use remotes;
+
+/// Asks for the commits to backport, then the branches to pick them onto.
+pub fn show(siv: &mut Cursive) {
+    show_with(siv, "");
+}
+
+/// As `show`, starting with `initial` as the commits, e.g. the commit
+/// selected in the log.
+pub fn show_with(siv: &mut Cursive, initial: &str) {
+    super::dialogs::prompt(siv, "Backport Commits (e.g. 'abc1234 def5678' or 'main..fix')", initial, |s, spec| {
+        let found = super::with_current_repo(s, |repo| {
+            backport::resolve_commits(repo, spec)
+                .and_then(|commits| remotes::local_branches(repo).map(|branches| (commits, branches)))
+        });
+        match found {
+            Some(Ok((commits, branches))) => choose_targets(s, commits, branches),
+            Some(Err(e)) => super::report_result::<::git2::Error>(s, "Backport", Err(e)),
+            None => {},
+        }
+    });
+}
+
+fn checkbox_id(i: usize) -> String {
+    format!("backport_target_{}", i)
+}
+
+/// Shows the local branches with a checkbox each, to choose where the
+/// commits go.
+fn choose_targets(siv: &mut Cursive, commits: Vec<Oid>, branches: Vec<String>) {
+    let mut list = LinearLayout::vertical();
+    for (i, branch) in branches.iter().enumerate() {
+        list.add_child(LinearLayout::horizontal()
+            .child(Checkbox::new().with_id(checkbox_id(i)))
+            .child(TextView::new(format!(" {}", branch))));
+    }
+
+    let layout = LinearLayout::vertical()
+        .child(TextView::new(format!("Choose the branches to pick the {} commits onto. Each branch gets all of them, \
+                                      or is left alone if one conflicts.\n", commits.len())))
+        .child(list.scrollable().max_height(15));
+
+    siv.add_layer(Dialog::around(layout.max_width(80))
+        .title("Backport Onto")
+        .button("Backport", move |s| {
+            let targets: Vec<String> = branches.iter().enumerate()
+                .filter(|&(i, _)| s.call_on_id(&checkbox_id(i), |c: &mut Checkbox| c.is_checked()).unwrap_or(false))
+                .map(|(_, branch)| branch.clone())
+                .collect();
+            if targets.is_empty() {
+                return s.add_layer(Dialog::info("Choose at least one branch."));
+            }
+            s.pop_layer();
+            run(s, &commits, &targets);
+        })
+        .dismiss_button("Cancel"));
+}
+
+fn run(siv: &mut Cursive, commits: &[Oid], targets: &[String]) {
+    let policy = config::current().signing.clone();
+    let reports = match super::with_current_repo(siv, |repo| backport::backport(repo, commits, targets, &policy)) {
+        Some(reports) => reports,
+        None => return,
+    };
+    super::refresh_views(siv);
+
+    let done = reports.iter().filter(|r| r.succeeded()).count();
+    let text = reports.iter().map(|r| r.to_string()).collect::<Vec<_>>().join("\n");
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(20).max_width(100))
+        .title(format!("Backport - {} of {} Branches Done", done, reports.len()))
+        .dismiss_button("Close"));
+}
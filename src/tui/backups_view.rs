@@ -0,0 +1,82 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{Dialog, SelectView};
+use git2::Oid;
+use backups;
+use config;
+use text_width::{self, Ellipsis};
+
+const LIST_ID: &str = "backups_list";
+
+/// Lists the backups taken before history was rewritten, one line per saved
+/// branch. Choosing one shows it in the log view.
+pub fn show(siv: &mut Cursive) {
+    let backups = match super::with_current_repo(siv, backups::list) {
+        Some(Ok(backups)) => backups,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "List backups", Err(e)),
+        None => return,
+    };
+
+    if backups.is_empty() {
+        return siv.add_layer(Dialog::info("There are no backups."));
+    }
+
+    let ellipsis = config::current().display.path_ellipsis;
+    let mut list = SelectView::<(String, Oid)>::new();
+    for backup in &backups {
+        for &(ref branch, tip) in &backup.refs {
+            let label = format!("{} {} {:.7}", text_width::fit(&backup.name, 20, Ellipsis::End),
+                                text_width::fit(branch, 30, ellipsis), tip.to_string());
+            list.add_item(label, (backup.name.clone(), tip));
+        }
+    }
+    list.set_on_submit(|s, &(_, tip): &(String, Oid)| {
+        s.pop_layer();
+        super::log_view::show(s);
+        super::log_view::goto_commit(s, tip);
+    });
+
+    siv.add_layer(Dialog::around(list.with_id(LIST_ID).scrollable().max_height(20))
+        .title("Backups")
+        .button("Delete", delete_selected)
+        .button("Clean Up", |s| {
+            s.pop_layer();
+            clean_up(s);
+        })
+        .dismiss_button("Close"));
+}
+
+fn delete_selected(siv: &mut Cursive) {
+    let name = match siv.call_on_id(LIST_ID, |v: &mut SelectView<(String, Oid)>| v.selection()) {
+        Some(Some(selection)) => selection.0.clone(),
+        _ => return,
+    };
+
+    super::dialogs::confirm(siv, "Delete Backup", format!("Delete backup '{}'?", name), move |s| {
+        let result = super::with_current_repo(s, |repo| {
+            backups::delete(repo, &name).map(|_| format!("Deleted backup '{}'.", name))
+        });
+        if let Some(result) = result {
+            s.pop_layer();
+            super::report_result::<::git2::Error>(s, "Delete backup", result);
+            show(s);
+        }
+    });
+}
+
+/// Deletes the backups that have expired under the retention policy in the
+/// config file.
+pub fn clean_up(siv: &mut Cursive) {
+    let policy = config::current().backups.clone();
+    let text = format!("Delete backups older than {} days, keeping at least the newest {}?",
+                       policy.keep_days, policy.keep_at_least);
+
+    super::dialogs::confirm(siv, "Clean Up Backups", text, move |s| {
+        let result = super::with_current_repo(s, |repo| {
+            backups::clean_up(repo, &policy).map(|deleted| format!("Deleted {} backups.", deleted.len()))
+        });
+        if let Some(result) = result {
+            super::report_result::<::git2::Error>(s, "Clean up backups", result);
+        }
+    });
+}
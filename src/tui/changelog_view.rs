@@ -0,0 +1,44 @@
+use std::fs;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, TextView};
+use changelog;
+use config;
+use paths;
+
+/// Asks for a range of commits, e.g. 'v1.0..v1.1', and shows the changelog
+/// for it.
+pub fn show(siv: &mut Cursive) {
+    super::dialogs::prompt(siv, "Changelog (FROM..TO)", "", |s, spec| {
+        match changelog::parse_range(spec) {
+            Some((from, to)) => show_range(s, from, to),
+            None => s.add_layer(Dialog::info("Enter two revisions, as 'FROM..TO' or 'FROM TO'.")),
+        }
+    });
+}
+
+/// Shows the changelog for the commits in `from..to`, as Markdown, with the
+/// option of saving it to a file.
+pub fn show_range(siv: &mut Cursive, from: &str, to: &str) {
+    let config = config::current();
+    let text = match super::with_current_repo(siv, |repo| changelog::generate(repo, from, to, &config.changelog)) {
+        Some(Ok(text)) => text,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Changelog", Err(e)),
+        None => return,
+    };
+
+    let for_save = text.clone();
+    let view = Dialog::around(TextView::new(text).scrollable().max_height(30).max_width(120))
+        .title(format!("Changelog - {}..{}", from, to))
+        .button("Save...", move |s| save(s, for_save.clone()))
+        .dismiss_button("Close");
+    super::navigation::open(siv, &format!("Changelog {}..{}", from, to), view, false);
+}
+
+fn save(siv: &mut Cursive, text: String) {
+    super::dialogs::prompt(siv, "Save the Changelog to", "changelog.md", move |s, file| {
+        let path = paths::expand_tilde(file);
+        let result = fs::write(&path, &text).map(|_| format!("Saved the changelog to '{}'.", path.display()));
+        super::report_result(s, "Save changelog", result);
+    });
+}
@@ -0,0 +1,186 @@
+//! Compares two revisions in full screen, either directly (two-dot) or from
+//! where they diverged (three-dot), with a key to switch between the two.
+
+use std::cell::RefCell;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable};
+use cursive::views::{Dialog, IdView, LinearLayout, OnEventView, TextView};
+use git2::{Oid, Repository};
+use compare::{self, CompareMode};
+use config;
+use default_branch;
+use history;
+use patch::{self, DiffLimits};
+use super::diff_view::{self, DiffSource, DiffTitle, DiffView};
+use super::overview::Overview;
+
+const DIFF_ID: &str = "compare_view_diff";
+const OVERVIEW_ID: &str = "compare_view_overview";
+const TITLE_ID: &str = "compare_view_title";
+const EXPLANATION_ID: &str = "compare_view_explanation";
+
+/// The two revisions being compared, as entered and as resolved.
+#[derive(Clone)]
+struct Comparison {
+    from: String,
+    to: String,
+    from_oid: Oid,
+    to_oid: Oid,
+    mode: CompareMode,
+    context: u32,
+}
+
+thread_local! {
+    static COMPARISON: RefCell<Option<Comparison>> = RefCell::new(None);
+}
+
+/// Asks for two revisions, e.g. 'master...feature' to see a branch as a
+/// pull request would show it, and shows the diff between them. HEAD against
+/// the remote's default branch is offered to start with.
+pub fn show(siv: &mut Cursive) {
+    let initial = super::with_current_repo(siv, default_branch::default_base)
+        .and_then(|base| base)
+        .map(|base| format!("{}...HEAD", base))
+        .unwrap_or_default();
+    super::dialogs::prompt(siv, "Compare (FROM...TO or FROM..TO)", &initial, |s, spec| {
+        let (from, to, mode) = match compare::parse_spec(spec) {
+            Some(parsed) => parsed,
+            None => return s.add_layer(Dialog::info("Enter two revisions, as 'FROM...TO', 'FROM..TO' or 'FROM TO'.")),
+        };
+
+        let oids = super::with_current_repo(s, |repo| -> Result<_, ::git2::Error> {
+            Ok((history::resolve_commit(repo, from)?, history::resolve_commit(repo, to)?))
+        });
+        let (from_oid, to_oid) = match oids {
+            Some(Ok(oids)) => oids,
+            Some(Err(e)) => return super::report_result::<::git2::Error>(s, "Compare", Err(e)),
+            None => return,
+        };
+
+        let comparison = Comparison {
+            from: from.to_string(),
+            to: to.to_string(),
+            from_oid,
+            to_oid,
+            mode,
+            context: patch::DEFAULT_CONTEXT_LINES,
+        };
+        // Check there is a merge base before opening the view.
+        if let Err(e) = endpoints(s, &comparison) {
+            return super::report_result::<::git2::Error>(s, "Compare", Err(e));
+        }
+        COMPARISON.with(|c| *c.borrow_mut() = Some(comparison));
+        open(s, &format!("{}{}{}", from, mode.separator(), to));
+    });
+}
+
+fn open(siv: &mut Cursive, title: &str) {
+    let diff_view = DiffView::new();
+    let marks = diff_view.marks();
+    let header = format!("{} > Compare {}\n[t] two-dot/three-dot  [[] []] previous/next hunk  [+] [-] context  [x] expand unchanged  [q] close",
+                         super::navigation::breadcrumb(siv), title);
+    let view = OnEventView::new(LinearLayout::vertical()
+            .child(TextView::new(header))
+            .child(TextView::new("").with_id(EXPLANATION_ID))
+            .child(DiffTitle::new(marks.clone()).with_id(TITLE_ID))
+            .child(Overview::new(diff_view.with_id(DIFF_ID), marks).with_id(OVERVIEW_ID).full_screen()))
+        .on_event('t', toggle_mode)
+        .on_event('[', |s| jump_to_hunk(s, false))
+        .on_event(']', |s| jump_to_hunk(s, true))
+        .on_event('+', |s| change_context(s, true))
+        .on_event('-', |s| change_context(s, false))
+        .on_event('x', |s| { s.call_on_id(DIFF_ID, |v: &mut DiffView| v.expand_gap()); })
+        .on_event('L', |s| show_diff(s, DiffLimits::unlimited()))
+        .on_event('q', |s| { s.pop_layer(); });
+
+    super::navigation::open(siv, &format!("Compare {}", title), view, true);
+    show_diff(siv, config::current().limits.diff_limits());
+}
+
+/// Switches between two-dot and three-dot. Revisions with no history in
+/// common can only be compared directly, so stay two-dot.
+fn toggle_mode(siv: &mut Cursive) {
+    let mut comparison = match current() {
+        Some(comparison) => comparison,
+        None => return,
+    };
+    comparison.mode = comparison.mode.toggled();
+    if let Err(e) = endpoints(siv, &comparison) {
+        return super::report_result::<::git2::Error>(siv, "Compare", Err(e));
+    }
+    COMPARISON.with(|c| *c.borrow_mut() = Some(comparison));
+    show_diff(siv, config::current().limits.diff_limits());
+}
+
+fn jump_to_hunk(siv: &mut Cursive, forward: bool) {
+    siv.call_on_id(OVERVIEW_ID, |v: &mut Overview<IdView<DiffView>>| v.jump(forward));
+}
+
+/// Shows more, or less, of the unchanged lines around each change.
+fn change_context(siv: &mut Cursive, more: bool) {
+    COMPARISON.with(|c| {
+        if let Some(ref mut c) = *c.borrow_mut() {
+            c.context = diff_view::step_context(c.context, more);
+        }
+    });
+    show_diff(siv, config::current().limits.diff_limits());
+}
+
+fn current() -> Option<Comparison> {
+    COMPARISON.with(|c| c.borrow().clone())
+}
+
+/// The commits whose trees are diffed: for three-dot, the merge base of the
+/// two revisions and the second of them.
+fn endpoints(siv: &mut Cursive, comparison: &Comparison) -> Result<(Oid, Oid), ::git2::Error> {
+    if comparison.mode == CompareMode::TwoDot {
+        return Ok((comparison.from_oid, comparison.to_oid));
+    }
+    let (from, to) = (comparison.from_oid, comparison.to_oid);
+    let base = super::with_current_repo(siv, |repo: &Repository| {
+        super::with_commit_graph(repo, |graph| graph.merge_base(repo, from, to))
+    });
+    match base {
+        Some(Ok(Some(base))) => Ok((base, to)),
+        Some(Ok(None)) => Err(::git2::Error::from_str(&format!(
+            "'{}' and '{}' have no history in common, so can only be compared two-dot.", comparison.from, comparison.to))),
+        Some(Err(e)) => Err(e),
+        None => Err(::git2::Error::from_str("No repository is open.")),
+    }
+}
+
+/// What the current mode compares, in words.
+fn explanation(comparison: &Comparison, from: Oid) -> String {
+    let spec = format!("{}{}{}", comparison.from, comparison.mode.separator(), comparison.to);
+    match comparison.mode {
+        CompareMode::TwoDot => format!("{}: the difference between the trees of '{}' and '{}', including changes made on '{}' since they diverged.",
+                                       spec, comparison.from, comparison.to, comparison.from),
+        CompareMode::ThreeDot => format!("{}: the changes made on '{}' since it forked from '{}' at {:.7}.",
+                                         spec, comparison.to, comparison.from, from.to_string()),
+    }
+}
+
+/// Starts streaming the diff for the current comparison and mode.
+fn show_diff(siv: &mut Cursive, limits: DiffLimits) {
+    let comparison = match current() {
+        Some(comparison) => comparison,
+        None => return,
+    };
+    let (from, to) = match endpoints(siv, &comparison) {
+        Ok(endpoints) => endpoints,
+        Err(e) => return super::report_result::<::git2::Error>(siv, "Compare", Err(e)),
+    };
+    let repo_path = match super::with_current_repo(siv, |repo| repo.path().to_path_buf()) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let source = DiffSource::Compare(from, to);
+    let sink = siv.cb_sink().clone();
+    let wake = move || { let _ = sink.send(Box::new(|_: &mut Cursive| {})); };
+    let limits = DiffLimits { context_lines: comparison.context, ..limits };
+    let title = Some(source.clone());
+    siv.call_on_id(EXPLANATION_ID, |v: &mut TextView| v.set_content(explanation(&comparison, from)));
+    siv.call_on_id(TITLE_ID, |v: &mut DiffTitle| v.set_source(title));
+    siv.call_on_id(DIFF_ID, |v: &mut DiffView| v.show(repo_path, source, limits, wake));
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use cursive::Cursive;
+use serde_json::{self, Value};
+use control_socket::{self, Call, RepoInfo};
+use error::OafError;
+use paths;
+use remotes;
+use repositories::{self, RepositoryExtensions};
+use super::notifications::{self, Level};
+use super::{log_view, repo_tabs, with_repos};
+
+/// Starts listening for requests from other programs on the socket at
+/// `path`. Each call is carried out on the UI thread, as if the user had done
+/// it, and the client waits for the answer.
+pub fn start(siv: &mut Cursive, path: &Path) {
+    let sink = siv.cb_sink().clone();
+    let result = control_socket::serve(path, move |call| {
+        let (tx, rx) = mpsc::channel();
+        let _ = sink.send(Box::new(move |s: &mut Cursive| {
+            let _ = tx.send(answer(s, call));
+        }));
+        rx.recv().unwrap_or_else(|_| Err("oaf is exiting".to_string()))
+    });
+
+    if let Err(e) = result {
+        notifications::notify(siv, Level::Error, format!("Cannot listen on the control socket '{}': {}", path.display(), e));
+    }
+}
+
+/// Removes the socket, as oaf exits.
+pub fn stop(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("Error removing the control socket '{}', ignoring. Error = {}", path.display(), e);
+    }
+}
+
+fn answer(siv: &mut Cursive, call: Call) -> Result<Value, String> {
+    info!("Control socket call {:?}", call);
+    match call {
+        Call::ListRepos => {
+            let repos = with_repos(|repos| {
+                let current = repos.current().map(|r| r.path().to_path_buf());
+                repos.iter().map(|r| RepoInfo {
+                    name: r.display_name(),
+                    path: r.path().display().to_string(),
+                    current: current.as_ref().map(|p| p.as_path()) == Some(r.path()),
+                }).collect::<Vec<_>>()
+            }).unwrap_or_default();
+            serde_json::to_value(repos).map_err(|e| e.to_string())
+        },
+        Call::CurrentBranch => {
+            match with_repos(|repos| repos.current().map(remotes::current_branch_name)) {
+                Some(Some(branch)) => Ok(branch.map_or(Value::Null, Value::from)),
+                _ => Err("No repository is open".to_string()),
+            }
+        },
+        Call::Refresh => {
+            log_view::refresh(siv);
+            repo_tabs::refresh(siv);
+            Ok(Value::Null)
+        },
+        Call::Open(path) => open(siv, path).map(Value::from),
+    }
+}
+
+/// Opens the repository at `path` and makes it current. It is not an error
+/// if it is open already, it is just made current.
+fn open(siv: &mut Cursive, path: PathBuf) -> Result<String, String> {
+    let result = repositories::open_repository(&path);
+    let git_dir = result.as_ref().ok().map(|r| r.path().to_path_buf());
+    let opened = with_repos(|repos| {
+        let opened = match repos.finish_open(&path, result) {
+            Ok(repo) => Ok(repo.display_name()),
+            Err(OafError::AlreadyOpen(_)) => Ok(paths::compress_tilde(&path).display().to_string()),
+            Err(e) => Err(e),
+        };
+        if opened.is_ok() {
+            repos.select(git_dir.as_ref().unwrap_or(&path));
+        }
+        opened.and_then(|name| repos.save_mru().map(|_| name))
+    });
+    super::add_open_repositories_to_background_work();
+    repo_tabs::refresh(siv);
+    log_view::refresh(siv);
+
+    match opened {
+        Some(result) => result.map_err(|e| e.to_string()),
+        None => Err("oaf is not ready".to_string()),
+    }
+}
@@ -0,0 +1,224 @@
+use cursive::Cursive;
+use cursive::theme::Effect;
+use cursive::traits::*;
+use cursive::utils::markup::StyledString;
+use cursive::views::{Dialog, LinearLayout, SelectView, TextView};
+use git2::{Oid, Repository, Signature};
+use std::path::Path;
+use std::thread;
+use config;
+use dates;
+use file_info;
+use forge;
+use issues::{self, IssueRef};
+use mailmap::Mailmap;
+use remotes;
+use signatures::{self, SignatureStatus};
+
+/// An object whose signature is shown in a details dialog.
+#[derive(Debug, Clone, Copy)]
+enum Signed {
+    Commit(Oid),
+    Tag(Oid),
+}
+
+impl Signed {
+    fn oid(&self) -> Oid {
+        match *self {
+            Signed::Commit(oid) | Signed::Tag(oid) => oid,
+        }
+    }
+
+    fn verify(&self, repo: &Repository) -> SignatureStatus {
+        match *self {
+            Signed::Commit(oid) => signatures::verify_commit(repo, oid),
+            Signed::Tag(oid) => signatures::verify_tag(repo, oid),
+        }
+    }
+}
+
+/// The text of a commit or tag dialog. The signature line sits between the
+/// header and the message in a view of its own, so that it can be filled in
+/// once the signature has been verified.
+struct Details {
+    header: String,
+    signed: Signed,
+    message: String,
+}
+
+/// Shows a dialog with the details of a commit, including its signature status.
+/// Identities are shown as mapped by `mailmap`; pass an empty mailmap to see
+/// them exactly as recorded.
+pub fn show_commit(siv: &mut Cursive, repo: &Repository, oid: Oid, mailmap: &Mailmap) {
+    let details = match commit_details(repo, oid, mailmap) {
+        Ok(details) => details,
+        Err(e) => return show_text(siv, "Commit", &format!("Commit {:.7}", oid.to_string()), format!("Cannot read commit {}: {}", oid, e)),
+    };
+
+    let config = config::current();
+    let trackers = if config.issue_trackers.is_empty() { issues::default_trackers() } else { config.issue_trackers.clone() };
+    let base = web_base(repo);
+    let refs: Vec<IssueRef> = issues::find_refs(&details.message, &trackers, base.as_ref().map(|b| b.as_str()));
+
+    let mut links = SelectView::<String>::new();
+    for issue in &refs {
+        if let Some(ref url) = issue.url {
+            links.add_item(format!("{}  {}", issue.text, url), url.clone());
+        }
+    }
+    links.set_on_submit(|s, url: &String| {
+        if let Err(e) = forge::open_in_browser(url) {
+            s.add_layer(Dialog::info(format!("Error: {}", e)));
+        }
+    });
+
+    let text = details_view(siv, repo, &details, highlight_refs(&details.message, &refs));
+    let mut layout = LinearLayout::vertical().child(text.scrollable().max_height(24).max_width(100));
+    if !links.is_empty() {
+        layout.add_child(TextView::new("\nIssues (Enter to open in browser):"));
+        layout.add_child(links.scrollable().max_height(5));
+    }
+
+    let view = Dialog::around(layout)
+        .title("Commit")
+        .dismiss_button("Close");
+    super::navigation::open(siv, &format!("Commit {:.7}", oid.to_string()), view, false);
+}
+
+/// Shows a dialog with the details of an annotated tag, including its
+/// signature status. Lightweight tags are shown as their target commit.
+pub fn show_tag(siv: &mut Cursive, repo: &Repository, tag_name: &str, mailmap: &Mailmap) {
+    let crumb = format!("Tag {}", tag_name);
+    let details = match tag_details(repo, tag_name, mailmap) {
+        Ok(details) => details,
+        Err(e) => return show_text(siv, "Tag", &crumb, format!("Cannot read tag {}: {}", tag_name, e)),
+    };
+
+    let message = StyledString::plain(details.message.as_str());
+    let text = details_view(siv, repo, &details, message);
+    let view = Dialog::around(text.scrollable().max_height(30).max_width(100))
+        .title("Tag")
+        .dismiss_button("Close");
+    super::navigation::open(siv, &crumb, view, false);
+}
+
+/// Shows a panel with the size and mode of a file, the last commit that
+/// changed it, the .gitattributes that apply to it and its owners.
+pub fn show_file(siv: &mut Cursive, repo: &Repository, path: &Path) {
+    let text = match file_info::load(repo, path, &super::identity_mailmap(repo)) {
+        Ok(info) => info.description(),
+        Err(e) => format!("Cannot read the information for {}: {}", path.display(), e),
+    };
+    show_text(siv, "File Info", &format!("Info {}", path.display()), text);
+}
+
+fn show_text(siv: &mut Cursive, title: &str, crumb: &str, text: String) {
+    let view = Dialog::around(TextView::new(text).scrollable().max_height(30).max_width(100))
+        .title(title)
+        .dismiss_button("Close");
+    super::navigation::open(siv, crumb, view, false);
+}
+
+/// The header, signature line and message of `details`, one above the
+/// other. A signature that has not been verified before is verified in the
+/// background, since that runs gpg or ssh-keygen, and filled in when the
+/// result arrives.
+fn details_view(siv: &mut Cursive, repo: &Repository, details: &Details, message: StyledString) -> LinearLayout {
+    let signed = details.signed;
+    let id = format!("details_signature_{}", signed.oid());
+    let cached = super::SIGNATURES.with(|c| c.borrow().get(signed.oid()));
+    let status = match cached {
+        Some(status) => signature_line(&status),
+        None => {
+            verify_in_background(siv, repo, signed, id.clone());
+            "Signature: checking...".to_string()
+        },
+    };
+
+    LinearLayout::vertical()
+        .child(TextView::new(details.header.as_str()))
+        .child(TextView::new(status).with_id(id))
+        .child(TextView::new(message))
+}
+
+fn verify_in_background(siv: &mut Cursive, repo: &Repository, signed: Signed, id: String) {
+    let path = repo.path().to_path_buf();
+    let sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let status = match Repository::open(&path) {
+            Ok(repo) => signed.verify(&repo),
+            Err(e) => SignatureStatus::Error(e.to_string()),
+        };
+        let _ = sink.send(Box::new(move |s: &mut Cursive| {
+            let line = signature_line(&status);
+            super::SIGNATURES.with(|c| c.borrow_mut().insert(signed.oid(), status));
+            s.call_on_id(&id, |v: &mut TextView| v.set_content(line));
+        }));
+    });
+}
+
+fn signature_line(status: &SignatureStatus) -> String {
+    format!("Signature: {}", status)
+}
+
+fn commit_details(repo: &Repository, oid: Oid, mailmap: &Mailmap) -> Result<Details, ::git2::Error> {
+    let commit = repo.find_commit(oid)?;
+    let mut header = String::new();
+    header += &format!("Commit:    {}\n", commit.id());
+    for parent in commit.parent_ids() {
+        header += &format!("Parent:    {}\n", parent);
+    }
+    header += &format!("Author:    {}\n", format_signature(&commit.author(), mailmap));
+    header += &format!("Committer: {}", format_signature(&commit.committer(), mailmap));
+    let message = format!("\n{}", String::from_utf8_lossy(commit.message_bytes()));
+    Ok(Details { header, signed: Signed::Commit(oid), message })
+}
+
+fn tag_details(repo: &Repository, tag_name: &str, mailmap: &Mailmap) -> Result<Details, ::git2::Error> {
+    let reference = repo.find_reference(&format!("refs/tags/{}", tag_name))?;
+    let tag = match reference.peel_to_tag() {
+        Ok(tag) => tag,
+        Err(_) => {
+            let commit = reference.peel_to_commit()?;
+            let mut details = commit_details(repo, commit.id(), mailmap)?;
+            details.header = format!("Tag:       {} (lightweight)\n{}", tag_name, details.header);
+            return Ok(details);
+        }
+    };
+
+    let mut header = String::new();
+    header += &format!("Tag:       {}\n", tag_name);
+    header += &format!("Object:    {}\n", tag.id());
+    header += &format!("Target:    {}", tag.target_id());
+    if let Some(tagger) = tag.tagger() {
+        header += &format!("\nTagger:    {}", format_signature(&tagger, mailmap));
+    }
+    let message = format!("\n{}", tag.message().unwrap_or(""));
+    Ok(Details { header, signed: Signed::Tag(tag.id()), message })
+}
+
+/// Underlines the issue references, which must be in order, in `text`.
+fn highlight_refs(text: &str, refs: &[IssueRef]) -> StyledString {
+    let mut styled = StyledString::new();
+    let mut pos = 0;
+    for issue in refs {
+        styled.append_plain(&text[pos..issue.start]);
+        styled.append_styled(&text[issue.start..issue.end], Effect::Underline);
+        pos = issue.end;
+    }
+    styled.append_plain(&text[pos..]);
+    styled
+}
+
+/// The web address of the repository on 'origin', or the first remote.
+fn web_base(repo: &Repository) -> Option<String> {
+    let names = remotes::remote_names(repo);
+    let name = names.iter().find(|n| *n == "origin").or_else(|| names.first())?;
+    let url = remotes::remote_url(repo, name).ok()?;
+    forge::parse_remote_url(&url).map(|l| l.base_url())
+}
+
+fn format_signature(sig: &Signature, mailmap: &Mailmap) -> String {
+    let config = config::current();
+    format!("{} {}", mailmap.resolve_signature(sig), dates::format(&sig.when(), config.dates.details, &config.dates))
+}
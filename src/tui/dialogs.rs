@@ -0,0 +1,134 @@
+use cursive::Cursive;
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, TextArea, TextView};
+use text_width;
+use super::fuzzy_filter::FilterView;
+
+const PROMPT_ID: &str = "dialogs_prompt";
+const EDIT_TEXT_ID: &str = "dialogs_edit_text";
+const CONFIRM_TYPED_ID: &str = "dialogs_confirm_typed";
+
+/// Shows a Yes/No question. `on_yes` is called after the dialog is closed,
+/// nothing happens if the user says no.
+pub fn confirm<S, F>(siv: &mut Cursive, title: &str, text: S, on_yes: F)
+    where S: Into<String>,
+          F: Fn(&mut Cursive) + 'static
+{
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable())
+        .title(title)
+        .button("Yes", move |s| {
+            s.pop_layer();
+            on_yes(s);
+        })
+        .dismiss_button("No"));
+}
+
+/// Asks the user to confirm something dangerous by typing `expected`, such
+/// as the name of what is about to be deleted. `on_yes` is called after the
+/// dialog is closed, and only if what they typed matches exactly.
+pub fn confirm_typed<S, F>(siv: &mut Cursive, title: &str, text: S, expected: &str, on_yes: F)
+    where S: Into<String>,
+          F: Fn(&mut Cursive) + 'static
+{
+    let expected = expected.to_string();
+    let layout = LinearLayout::vertical()
+        .child(TextView::new(text))
+        .child(TextView::new(format!("\nType '{}' to go ahead:", expected)))
+        .child(EditView::new().with_id(CONFIRM_TYPED_ID).min_width(40));
+
+    siv.add_layer(Dialog::around(layout.scrollable())
+        .title(title)
+        .button("Confirm", move |s| {
+            let typed = s.call_on_id(CONFIRM_TYPED_ID, |v: &mut EditView| v.get_content()).unwrap_or_default();
+            if typed.trim() != expected {
+                return s.add_layer(Dialog::info(format!("That is not '{}'; nothing has been done.", expected)));
+            }
+            s.pop_layer();
+            on_yes(s);
+        })
+        .dismiss_button("Cancel"));
+}
+
+/// Shows a list of items for the user to pick one from. `on_choose` is called
+/// with the chosen item after the dialog is closed. Typing narrows the list
+/// down.
+pub fn choose<F>(siv: &mut Cursive, title: &str, items: Vec<String>, on_choose: F)
+    where F: Fn(&mut Cursive, &str) + 'static
+{
+    if items.is_empty() {
+        siv.add_layer(Dialog::info(format!("{}: there is nothing to choose from.", title)));
+        return;
+    }
+
+    // The list asks for whatever width it is given, so size the dialog to
+    // the longest item.
+    let width = items.iter().map(|item| text_width::width(item)).max().unwrap_or(0);
+    let width = (width + 2).max(30).min(100);
+
+    let mut select = FilterView::new(|item: &String| item.clone()).always_filtering();
+    select.set_items(items);
+    select.set_on_submit(move |s, item: &String| {
+        s.pop_layer();
+        on_choose(s, item);
+    });
+
+    siv.add_layer(Dialog::around(select.fixed_width(width).max_height(21))
+        .title(title)
+        .dismiss_button("Cancel"));
+}
+
+/// Asks the user to type in a single line of text, pre-filled with `initial`.
+/// `on_ok` is called with the trimmed text after the dialog is closed; it is
+/// not called if the text is empty.
+pub fn prompt<F>(siv: &mut Cursive, title: &str, initial: &str, on_ok: F)
+    where F: Fn(&mut Cursive, &str) + 'static
+{
+    let on_ok = ::std::rc::Rc::new(on_ok);
+    let on_ok2 = on_ok.clone();
+
+    let edit = EditView::new()
+        .content(initial)
+        .on_submit(move |s, text| {
+            let text = text.trim().to_string();
+            s.pop_layer();
+            if !text.is_empty() {
+                on_ok(s, &text);
+            }
+        })
+        .with_id(PROMPT_ID)
+        .min_width(40);
+
+    siv.add_layer(Dialog::around(edit)
+        .title(title)
+        .button("Ok", move |s| {
+            let text = s.call_on_id(PROMPT_ID, |v: &mut EditView| v.get_content()).unwrap_or_default();
+            let text = text.trim().to_string();
+            s.pop_layer();
+            if !text.is_empty() {
+                on_ok2(s, &text);
+            }
+        })
+        .dismiss_button("Cancel"));
+}
+
+/// Lets the user edit several lines of text, such as a commit message,
+/// pre-filled with `initial`. `on_save` is called with the text, untrimmed,
+/// after the dialog is closed.
+pub fn edit_text<F>(siv: &mut Cursive, title: &str, initial: &str, on_save: F)
+    where F: Fn(&mut Cursive, &str) + 'static
+{
+    let area = TextArea::new()
+        .content(initial)
+        .with_id(EDIT_TEXT_ID)
+        .min_width(72)
+        .min_height(10);
+
+    siv.add_layer(Dialog::around(area)
+        .title(title)
+        .button("Save", move |s| {
+            let text = s.call_on_id(EDIT_TEXT_ID, |v: &mut TextArea| v.get_content().to_string()).unwrap_or_default();
+            s.pop_layer();
+            on_save(s, &text);
+        })
+        .dismiss_button("Cancel"));
+}
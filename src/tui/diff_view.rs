@@ -0,0 +1,641 @@
+//! The diff pane. A diff is generated on a background thread a hunk at a time
+//! and appended to the view as it arrives, so the first screenful of a huge
+//! diff appears straight away. The thread stops once it is a page ahead of
+//! what has been scrolled to, and carries on as the view is scrolled down.
+//! The unchanged lines between hunks are noted by how many there are, and
+//! each note can be expanded to show them.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use cursive::Printer;
+use cursive::theme::Effect;
+use cursive::utils::markup::StyledString;
+use cursive::vec::Vec2;
+use cursive::view::{View, ViewWrapper};
+use cursive::views::{TextContent, TextView};
+use git2::{Oid, Repository};
+use file_content;
+use highlight::Highlighter;
+use patch::{self, DiffLimits, PatchLine, PatchStream};
+use short_path;
+use super::overview::{Mark, Marks, SharedMarks};
+use super::palette;
+
+/// How many lines are generated ahead of the bottom of the view.
+const PAGE_LINES: usize = 500;
+
+/// How close to the end of what has been generated the view can be scrolled
+/// before more is asked for.
+const MARGIN_ROWS: usize = 100;
+
+/// How many more lines of context each press of '+' shows.
+pub const CONTEXT_STEP: u32 = 10;
+
+/// The context around each change after a press of '+', if `more`, or of
+/// '-': a step more or less, but never less than git's usual context.
+pub fn step_context(context: u32, more: bool) -> u32 {
+    if more {
+        context.saturating_add(CONTEXT_STEP)
+    } else {
+        context.saturating_sub(CONTEXT_STEP).max(patch::DEFAULT_CONTEXT_LINES)
+    }
+}
+
+/// What the pane is showing a diff of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffSource {
+    Commit(Oid),
+    /// The uncommitted changes to a file.
+    File(PathBuf),
+    /// From the tree of one commit to that of another.
+    Compare(Oid, Oid),
+    /// The changes to a file that are staged.
+    Staged(PathBuf),
+    /// The changes to a file that are not staged.
+    Unstaged(PathBuf),
+}
+
+impl DiffSource {
+    fn describe(&self) -> String {
+        match *self {
+            DiffSource::Commit(oid) => format!("commit {}", oid),
+            DiffSource::File(ref path) => format!("'{}'", path.display()),
+            DiffSource::Compare(from, to) => format!("{}..{}", from, to),
+            DiffSource::Staged(ref path) => format!("the staged changes to '{}'", path.display()),
+            DiffSource::Unstaged(ref path) => format!("the unstaged changes to '{}'", path.display()),
+        }
+    }
+}
+
+/// A run of unchanged lines left out of a file's patch, known by the file
+/// and where the run starts in its new version, counting from 1.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Gap {
+    path: PathBuf,
+    first: usize,
+}
+
+/// The gaps noted in a diff, in order, as the overview's gaps are.
+type SharedGaps = Arc<Mutex<Vec<Gap>>>;
+
+/// Shared between a view and the thread generating its diff: how many lines
+/// the view wants and how many have been generated.
+struct Demand {
+    wanted: Mutex<usize>,
+    changed: Condvar,
+    produced: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Demand {
+    fn new(wanted: usize) -> Self {
+        Demand { wanted: Mutex::new(wanted), changed: Condvar::new(), produced: AtomicUsize::new(0), cancelled: AtomicBool::new(false) }
+    }
+
+    /// Asks for another page beyond what has been generated.
+    fn more(&self) {
+        let target = self.produced.load(Ordering::SeqCst) + PAGE_LINES;
+        let mut wanted = self.wanted.lock().unwrap();
+        if target > *wanted {
+            *wanted = target;
+            self.changed.notify_all();
+        }
+    }
+
+    fn cancel(&self) {
+        let _wanted = self.wanted.lock().unwrap();
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.changed.notify_all();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until more lines are wanted than have been generated. Returns
+    /// false if the view no longer wants anything.
+    fn wait(&self, produced: usize) -> bool {
+        self.produced.store(produced, Ordering::SeqCst);
+        let mut wanted = self.wanted.lock().unwrap();
+        while *wanted <= produced && !self.is_cancelled() {
+            wanted = self.changed.wait(wanted).unwrap();
+        }
+        !self.is_cancelled()
+    }
+}
+
+/// A TextView showing a diff as it is generated.
+pub struct DiffView {
+    view: TextView,
+    source: Option<DiffSource>,
+    demand: Option<Arc<Demand>>,
+    /// Where the changes are, for an overview beside the view.
+    marks: SharedMarks,
+    gaps: SharedGaps,
+    /// The gaps shown in full rather than noted.
+    expanded: HashSet<Gap>,
+    /// How the diff was generated, for generating it again.
+    generated: Option<(PathBuf, DiffLimits, Arc<dyn Fn() + Send + Sync>)>,
+}
+
+impl DiffView {
+    pub fn new() -> Self {
+        DiffView { view: TextView::new(""), source: None, demand: None, marks: SharedMarks::default(), gaps: SharedGaps::default(),
+                   expanded: HashSet::new(), generated: None }
+    }
+
+    pub fn marks(&self) -> SharedMarks {
+        self.marks.clone()
+    }
+
+    /// What the view is showing a diff of, if anything.
+    pub fn source(&self) -> Option<&DiffSource> {
+        self.source.as_ref()
+    }
+
+    /// Shows a message instead of a diff.
+    pub fn set_text(&mut self, text: StyledString) {
+        self.stop();
+        self.source = None;
+        self.generated = None;
+        self.clear_marks();
+        self.view.set_content(text);
+    }
+
+    /// Starts showing the diff of `source` in the repository at `repo_path`.
+    /// `wake` is called from the background thread each time more of the
+    /// diff has been added, and must get the UI to redraw.
+    pub fn show<F>(&mut self, repo_path: PathBuf, source: DiffSource, limits: DiffLimits, wake: F)
+        where F: Fn() + Send + Sync + 'static
+    {
+        // The gaps move if the context changes.
+        let same = match self.generated {
+            Some((_, ref generated, _)) => self.source.as_ref() == Some(&source) && generated.context_lines == limits.context_lines,
+            None => false,
+        };
+        if !same {
+            self.expanded.clear();
+        }
+        self.start(repo_path, source, limits, Arc::new(wake), PAGE_LINES);
+    }
+
+    /// Shows the lines of the first note of unchanged lines at or below the
+    /// top of the view, generating the diff again as far as the note.
+    pub fn expand_gap(&mut self) {
+        let (index, line) = match self.marks.lock().unwrap().next_gap() {
+            Some(next) => next,
+            None => return,
+        };
+        let gap = match self.gaps.lock().unwrap().get(index) {
+            Some(gap) => gap.clone(),
+            None => return,
+        };
+        if let (Some(source), Some((repo_path, limits, wake))) = (self.source.clone(), self.generated.clone()) {
+            self.expanded.insert(gap);
+            self.start(repo_path, source, limits, wake, line + PAGE_LINES);
+        }
+    }
+
+    fn start(&mut self, repo_path: PathBuf, source: DiffSource, limits: DiffLimits, wake: Arc<dyn Fn() + Send + Sync>, wanted: usize) {
+        self.stop();
+
+        // Each diff gets its own content, so that a thread that has not
+        // noticed it was cancelled cannot write into the next diff.
+        let content = TextContent::new("");
+        self.view = TextView::new_with_content(content.clone());
+        let demand = Arc::new(Demand::new(wanted));
+        self.demand = Some(demand.clone());
+        self.source = Some(source.clone());
+        self.generated = Some((repo_path.clone(), limits, wake.clone()));
+        // The old diff's thread has been cancelled, so adds no more marks.
+        self.clear_marks();
+        let (marks, gaps, expanded) = (self.marks.clone(), self.gaps.clone(), self.expanded.clone());
+
+        thread::spawn(move || {
+            stream(&repo_path, &source, limits, &demand, content, &marks, &gaps, &expanded, &*wake);
+            wake();
+        });
+    }
+
+    /// Clears the marks and the gaps together, under the lock that the
+    /// thread adds them under.
+    fn clear_marks(&mut self) {
+        let mut marks = self.marks.lock().unwrap();
+        marks.clear();
+        self.gaps.lock().unwrap().clear();
+    }
+
+    fn stop(&mut self) {
+        if let Some(demand) = self.demand.take() {
+            demand.cancel();
+        }
+    }
+}
+
+impl Drop for DiffView {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl ViewWrapper for DiffView {
+    type V = TextView;
+
+    fn with_view<F, R>(&self, f: F) -> Option<R>
+        where F: FnOnce(&Self::V) -> R
+    {
+        Some(f(&self.view))
+    }
+
+    fn with_view_mut<F, R>(&mut self, f: F) -> Option<R>
+        where F: FnOnce(&mut Self::V) -> R
+    {
+        Some(f(&mut self.view))
+    }
+
+    fn wrap_draw(&self, printer: &Printer) {
+        self.view.draw(printer);
+
+        // The printer covers the whole text; its output is the part that is
+        // scrolled into view.
+        let bottom = printer.content_offset.y + printer.output_size.y;
+        if bottom + MARGIN_ROWS >= printer.size.y {
+            if let Some(ref demand) = self.demand {
+                demand.more();
+            }
+        }
+    }
+}
+
+/// A line above the diff saying what it is of, and which hunk is at the
+/// top of the view. A long path has directories taken out of the middle to
+/// fit.
+pub struct DiffTitle {
+    source: Option<DiffSource>,
+    marks: SharedMarks,
+}
+
+impl DiffTitle {
+    /// `marks` are those of the DiffView below the title.
+    pub fn new(marks: SharedMarks) -> Self {
+        DiffTitle { source: None, marks }
+    }
+
+    pub fn set_source(&mut self, source: Option<DiffSource>) {
+        self.source = source;
+    }
+}
+
+impl View for DiffTitle {
+    fn draw(&self, printer: &Printer) {
+        let counter = self.marks.lock().unwrap().hunk_counter().unwrap_or_default();
+        let width = printer.size.x.saturating_sub(counter.len() + 2);
+        let title = match self.source {
+            Some(DiffSource::Commit(oid)) => format!("Commit {:.7}", oid.to_string()),
+            Some(DiffSource::File(ref path)) => short_path::shorten(&path.display().to_string(), width),
+            Some(DiffSource::Compare(from, to)) => format!("{:.7}..{:.7}", from.to_string(), to.to_string()),
+            Some(DiffSource::Staged(ref path)) => format!("{} (staged)", short_path::shorten(&path.display().to_string(), width.saturating_sub(9))),
+            Some(DiffSource::Unstaged(ref path)) => format!("{} (unstaged)", short_path::shorten(&path.display().to_string(), width.saturating_sub(11))),
+            None => return,
+        };
+        printer.with_effect(Effect::Bold, |printer| printer.print((0, 0), &title));
+        printer.print((printer.size.x.saturating_sub(counter.len()), 0), &counter);
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        Vec2::new(constraint.x, 1)
+    }
+}
+
+/// Generates the diff, appending each hunk to `content` as long as the view
+/// wants more. The gaps noted in it are added to `gaps`, except those in
+/// `expanded`, which are shown in full.
+fn stream(repo_path: &PathBuf, source: &DiffSource, limits: DiffLimits, demand: &Demand, mut content: TextContent,
+          marks: &SharedMarks, gaps: &SharedGaps, expanded: &HashSet<Gap>, wake: &dyn Fn()) {
+    let _timer = timer!("diff_view::stream");
+    let repo = match Repository::open(repo_path) {
+        Ok(repo) => repo,
+        Err(e) => return content.append(format!("Cannot diff {}: {}", source.describe(), e)),
+    };
+    let diff = match *source {
+        DiffSource::Commit(oid) => patch::commit_diff(&repo, oid, limits),
+        DiffSource::File(ref path) => patch::working_tree_diff(&repo, path, limits),
+        DiffSource::Compare(from, to) => patch::compare_diff(&repo, from, to, limits),
+        DiffSource::Staged(ref path) => patch::staged_diff(&repo, path, limits),
+        DiffSource::Unstaged(ref path) => patch::unstaged_diff(&repo, path, limits),
+    };
+    let diff = match diff {
+        Ok(diff) => diff,
+        Err(e) => return content.append(format!("Cannot diff {}: {}", source.describe(), e)),
+    };
+
+    let mut stream = PatchStream::new(&diff, limits).with_decoder(file_content::display_diff_line);
+    let mut file = None;
+    let mut highlighters = SideHighlighters::new(None);
+    let mut new_side = NewSide::new(None);
+    let mut produced = 0;
+    let mut skipped = 0;
+    while demand.wait(produced) {
+        match stream.next() {
+            Some(Ok(lines)) => {
+                let path = stream.current_path();
+                let (mut filled, mut noted) = (Vec::new(), Vec::new());
+                let mut text = StyledString::new();
+                if path != file {
+                    // The previous file is finished with, so the lines after
+                    // its last hunk can be noted.
+                    let (after, after_noted) = new_side.finish(expanded);
+                    text = patch_text(&after, &mut highlighters);
+                    filled = after;
+                    noted = after_noted;
+                    highlighters = SideHighlighters::new(path.clone());
+                    new_side = NewSide::new(path.clone());
+                    file = path;
+                }
+                if new_side.lines.is_none() && lines.iter().any(|line| match *line { PatchLine::Hunk(_) => true, _ => false }) {
+                    new_side.lines = Some(stream.current_new_lines(&repo));
+                }
+                let (lines, lines_noted) = new_side.fill_gaps(lines, expanded);
+                text.append(patch_text(&lines, &mut highlighters));
+                filled.extend(lines);
+                noted.extend(lines_noted);
+                produced += filled.len();
+                for &(ref path, size) in &stream.skipped[skipped..] {
+                    text.append_styled(format!("'{}' is {}, over the limit of {}; press [L] to load it anyway.\n",
+                                               path.display(), patch::format_size(size),
+                                               patch::format_size(limits.max_file_bytes.unwrap_or(0))), palette::current().notice);
+                }
+                add_marks(marks, demand, |marks| {
+                    push_marks(marks, &filled);
+                    for _ in &stream.skipped[skipped..] {
+                        marks.push(None);
+                    }
+                    gaps.lock().unwrap().extend(noted);
+                });
+                skipped = stream.skipped.len();
+                content.append(text);
+            },
+            Some(Err(e)) => {
+                content.append(format!("Cannot diff {}: {}", source.describe(), e));
+                break;
+            },
+            None => {
+                if stream.truncated {
+                    content.append(StyledString::styled(
+                        format!("The diff was cut off after {} lines; press [L] to load it all.\n", produced), palette::current().notice));
+                } else if produced == 0 {
+                    match *source {
+                        DiffSource::Commit(_) => {},
+                        _ => content.append("No differences to show."),
+                    }
+                } else {
+                    let (after, noted) = new_side.finish(expanded);
+                    produced += after.len();
+                    let text = patch_text(&after, &mut highlighters);
+                    add_marks(marks, demand, |marks| {
+                        push_marks(marks, &after);
+                        gaps.lock().unwrap().extend(noted);
+                    });
+                    content.append(text);
+                }
+                break;
+            },
+        }
+        wake();
+    }
+    add_marks(marks, demand, |marks| marks.complete = true);
+    _timer.set_message(format!("{} lines of {}", produced, source.describe()));
+}
+
+/// Updates the marks, e.g. with lines about to be appended, unless the view
+/// has moved on to another diff. Checking under the lock means a cancelled
+/// thread cannot touch the marks once the view has cleared them.
+fn add_marks<F: FnOnce(&mut Marks)>(marks: &SharedMarks, demand: &Demand, update: F) {
+    let mut marks = marks.lock().unwrap();
+    if !demand.is_cancelled() {
+        update(&mut marks);
+    }
+}
+
+fn push_marks(marks: &mut Marks, lines: &[PatchLine]) {
+    for line in lines {
+        match *line {
+            PatchLine::Hunk(_) => marks.push_hunk(),
+            PatchLine::Added(_) => marks.push(Some(Mark::Added)),
+            PatchLine::Removed(_) => marks.push(Some(Mark::Removed)),
+            PatchLine::Unchanged(_) => marks.push_gap(),
+            _ => marks.push(None),
+        }
+    }
+}
+
+/// The new version of the file being streamed, for noting the unchanged
+/// lines left out of its patch, or showing them once expanded.
+struct NewSide {
+    path: Option<PathBuf>,
+    /// The file's lines, read at its first hunk.
+    lines: Option<Vec<String>>,
+    /// The line after the last hunk so far, counting from 1.
+    next_line: usize,
+}
+
+impl NewSide {
+    fn new(path: Option<PathBuf>) -> Self {
+        NewSide { path, lines: None, next_line: 1 }
+    }
+
+    /// Puts the unchanged lines left out before each hunk into `lines`: a
+    /// note of how many there are or, once the note has been expanded, the
+    /// lines themselves. Also returns the gaps noted, in order.
+    fn fill_gaps(&mut self, lines: Vec<PatchLine>, expanded: &HashSet<Gap>) -> (Vec<PatchLine>, Vec<Gap>) {
+        let mut filled = Vec::with_capacity(lines.len() + 1);
+        let mut noted = Vec::new();
+        for line in lines {
+            if let PatchLine::Hunk(ref header) = line {
+                if let Some((first, count)) = patch::hunk_new_lines(header) {
+                    if first > self.next_line {
+                        self.fill_gap(first - self.next_line, expanded, &mut filled, &mut noted);
+                    }
+                    self.next_line = first + count;
+                }
+            }
+            filled.push(line);
+        }
+        (filled, noted)
+    }
+
+    /// The unchanged lines after the file's last hunk, as `fill_gaps`.
+    fn finish(&mut self, expanded: &HashSet<Gap>) -> (Vec<PatchLine>, Vec<Gap>) {
+        let (mut filled, mut noted) = (Vec::new(), Vec::new());
+        let end = self.lines.as_ref().map_or(0, |lines| lines.len()) + 1;
+        if end > self.next_line {
+            self.fill_gap(end - self.next_line, expanded, &mut filled, &mut noted);
+            self.next_line = end;
+        }
+        (filled, noted)
+    }
+
+    fn fill_gap(&self, count: usize, expanded: &HashSet<Gap>, filled: &mut Vec<PatchLine>, noted: &mut Vec<Gap>) {
+        let gap = Gap { path: self.path.clone().unwrap_or_default(), first: self.next_line };
+        let start = gap.first - 1;
+        match self.lines.as_ref().and_then(|lines| lines.get(start..start + count)) {
+            Some(lines) if expanded.contains(&gap) => filled.extend(lines.iter().map(|line| PatchLine::Context(format!(" {}", line)))),
+            _ => {
+                filled.push(PatchLine::unchanged(count));
+                noted.push(gap);
+            },
+        }
+    }
+}
+
+/// Highlighters for the old and new versions of one file. Removed lines are
+/// only in the old version and added lines only in the new, so each side is
+/// highlighted on its own; context lines are in both. Hunks are not next to
+/// each other in the file, so both start afresh at each hunk.
+struct SideHighlighters {
+    path: Option<PathBuf>,
+    old: Option<Highlighter>,
+    new: Option<Highlighter>,
+}
+
+impl SideHighlighters {
+    fn new(path: Option<PathBuf>) -> Self {
+        let mut highlighters = SideHighlighters { path, old: None, new: None };
+        highlighters.reset();
+        highlighters
+    }
+
+    fn reset(&mut self) {
+        self.old = self.path.as_ref().and_then(|path| palette::highlighter(path));
+        self.new = self.path.as_ref().and_then(|path| palette::highlighter(path));
+    }
+}
+
+/// Colours the lines of a patch. With highlighters the code in the lines is
+/// highlighted, and only the '+' or '-' at the start shows how it changed.
+fn patch_text(lines: &[PatchLine], highlighters: &mut SideHighlighters) -> StyledString {
+    let palette = palette::current();
+    let mut text = StyledString::new();
+    for line in lines {
+        let colour = match *line {
+            PatchLine::File(_) => {
+                text.append_styled(format!("{}\n", line.text()), Effect::Bold);
+                continue;
+            },
+            PatchLine::Hunk(_) => {
+                highlighters.reset();
+                text.append_styled(format!("{}\n", line.text()), palette.hunk);
+                continue;
+            },
+            PatchLine::Unchanged(_) => {
+                text.append_styled(format!("{}\n", line.text()), palette.notice);
+                continue;
+            },
+            PatchLine::Added(_) => Some(palette.added),
+            PatchLine::Removed(_) => Some(palette.removed),
+            PatchLine::Context(_) => None,
+        };
+
+        let removed = match *line {
+            PatchLine::Removed(_) => true,
+            _ => false,
+        };
+        let line = line.text();
+        if line.starts_with(|c: char| c == '+' || c == '-' || c == ' ') {
+            let (prefix, code) = line.split_at(1);
+            if prefix == " " {
+                // Keep the old side in step; only the new side's colours
+                // are shown.
+                if let Some(ref mut old) = highlighters.old {
+                    old.line(code);
+                }
+            }
+            let side = if removed { &mut highlighters.old } else { &mut highlighters.new };
+            palette::append_change(&mut text, side.as_mut(), prefix, colour, code);
+        } else {
+            // The 'no newline at end of file' markers, which are not code.
+            palette::append_change(&mut text, None, "", colour, line);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn demand_waits_until_more_is_wanted() {
+        let demand = Arc::new(Demand::new(PAGE_LINES));
+        assert!(demand.wait(PAGE_LINES - 1));
+
+        let waiter = demand.clone();
+        let handle = thread::spawn(move || waiter.wait(PAGE_LINES));
+        thread::sleep(Duration::from_millis(20));
+        demand.more();
+        assert!(handle.join().unwrap());
+        assert_eq!(*demand.wanted.lock().unwrap(), 2 * PAGE_LINES);
+    }
+
+    #[test]
+    fn unchanged_lines_between_and_after_hunks_are_noted() {
+        let hunk = |header: &str| PatchLine::Hunk(header.to_string());
+        let gap = |first| Gap { path: PathBuf::from("a.txt"), first };
+        let mut side = NewSide::new(Some(PathBuf::from("a.txt")));
+        side.lines = Some((1..301).map(|n| format!("line {}", n)).collect());
+        let expanded = HashSet::new();
+
+        let (lines, noted) = side.fill_gaps(vec![hunk("@@ -1,2 +1,2 @@"), PatchLine::Added("+a".to_string())], &expanded);
+        assert_eq!(lines.len(), 2);
+        assert!(noted.is_empty());
+        assert_eq!(side.next_line, 3);
+
+        let (lines, noted) = side.fill_gaps(vec![hunk("@@ -240,7 +242,7 @@")], &expanded);
+        assert_eq!(lines, vec![PatchLine::unchanged(239), hunk("@@ -240,7 +242,7 @@")]);
+        assert_eq!(noted, vec![gap(3)]);
+        assert_eq!(side.next_line, 249);
+
+        let (lines, noted) = side.finish(&expanded);
+        assert_eq!(lines, vec![PatchLine::unchanged(52)]);
+        assert_eq!(noted, vec![gap(249)]);
+        assert_eq!(side.finish(&expanded), (vec![], vec![]));
+    }
+
+    #[test]
+    fn an_expanded_gap_shows_its_lines() {
+        let mut side = NewSide::new(Some(PathBuf::from("a.txt")));
+        side.lines = Some((1..11).map(|n| format!("line {}", n)).collect());
+        side.next_line = 3;
+        let expanded = vec![Gap { path: PathBuf::from("a.txt"), first: 3 }].into_iter().collect();
+
+        let (lines, noted) = side.fill_gaps(vec![PatchLine::Hunk("@@ -5 +5 @@".to_string())], &expanded);
+        assert_eq!(lines[0], PatchLine::Context(" line 3".to_string()));
+        assert_eq!(lines[1], PatchLine::Context(" line 4".to_string()));
+        assert_eq!(lines.len(), 3);
+        assert!(noted.is_empty());
+
+        let (lines, noted) = side.finish(&expanded);
+        assert_eq!(lines, vec![PatchLine::unchanged(5)]);
+        assert_eq!(noted, vec![Gap { path: PathBuf::from("a.txt"), first: 6 }]);
+    }
+
+    #[test]
+    fn context_steps_stop_at_the_usual_context() {
+        assert_eq!(step_context(patch::DEFAULT_CONTEXT_LINES, true), patch::DEFAULT_CONTEXT_LINES + CONTEXT_STEP);
+        assert_eq!(step_context(patch::DEFAULT_CONTEXT_LINES + CONTEXT_STEP, false), patch::DEFAULT_CONTEXT_LINES);
+        assert_eq!(step_context(patch::DEFAULT_CONTEXT_LINES, false), patch::DEFAULT_CONTEXT_LINES);
+    }
+
+    #[test]
+    fn cancelling_releases_the_thread() {
+        let demand = Arc::new(Demand::new(PAGE_LINES));
+        let waiter = demand.clone();
+        let handle = thread::spawn(move || waiter.wait(PAGE_LINES));
+        demand.cancel();
+        assert!(!handle.join().unwrap());
+    }
+}
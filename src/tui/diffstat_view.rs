@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{Dialog, SelectView};
+use config;
+use diffstat::{self, DirStat, StatRow};
+use patch;
+use text_width;
+
+const LIST_ID: &str = "diffstat_list";
+
+/// The widest the '+++--' graph is drawn.
+const GRAPH_WIDTH: usize = 30;
+
+/// The uncommitted changes being shown, and which directories are expanded.
+#[derive(Default)]
+struct DiffstatState {
+    root: DirStat,
+    expanded: HashSet<PathBuf>,
+}
+
+thread_local! {
+    static DIFFSTAT: RefCell<DiffstatState> = RefCell::new(DiffstatState::default());
+}
+
+/// Shows the uncommitted changes, staged or not, as a tree of directories
+/// with the files changed and lines added and removed in each. Choosing a
+/// directory expands it.
+pub fn show(siv: &mut Cursive) {
+    let limits = config::current().limits.diff_limits();
+    let root = super::with_current_repo(siv, |repo| patch::uncommitted_diff(repo, limits).and_then(|diff| diffstat::tree(&diff)));
+    let root = match root {
+        Some(Ok(root)) => root,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Diffstat", Err(e)),
+        None => return,
+    };
+
+    if root.totals.files == 0 {
+        return siv.add_layer(Dialog::info("There are no uncommitted changes."));
+    }
+
+    let title = format!("Diffstat - {}", root.totals.description());
+    DIFFSTAT.with(|d| *d.borrow_mut() = DiffstatState { root, expanded: HashSet::new() });
+    let mut list = SelectView::<StatRow>::new();
+    list.set_on_submit(toggle_dir);
+
+    let view = Dialog::around(list.with_id(LIST_ID).scrollable().max_height(30))
+        .title(title)
+        .dismiss_button("Close");
+    super::navigation::open(siv, "Diffstat", view.max_width(140), false);
+    update_rows(siv, None);
+}
+
+fn toggle_dir(siv: &mut Cursive, row: &StatRow) {
+    if let StatRow::Dir { ref path, expanded, .. } = *row {
+        DIFFSTAT.with(|d| {
+            let mut d = d.borrow_mut();
+            if expanded {
+                d.expanded.retain(|p| !p.starts_with(path));
+            } else {
+                d.expanded.insert(path.clone());
+            }
+        });
+        update_rows(siv, Some(path));
+    }
+}
+
+/// Lists the rows again, after a directory is expanded or collapsed,
+/// keeping `selected` selected.
+fn update_rows(siv: &mut Cursive, selected: Option<&Path>) {
+    let rows = DIFFSTAT.with(|d| {
+        let d = d.borrow();
+        d.root.rows(&d.expanded)
+    });
+    let screen_reader = super::screen_reader::is_enabled();
+    let names: Vec<String> = rows.iter().map(|row| name(row)).collect();
+    let name_width = names.iter().map(|n| text_width::width(n)).max().unwrap_or(0);
+    let most = rows.iter().map(|row| { let (added, removed) = row.changes(); added + removed }).max().unwrap_or(0);
+    let index = selected.and_then(|path| rows.iter().position(|row| row.path() == path)).unwrap_or(0);
+
+    siv.call_on_id(LIST_ID, |v: &mut SelectView<StatRow>| {
+        v.clear();
+        for (row, name) in rows.into_iter().zip(names) {
+            let label = if screen_reader { spoken_label(&row) } else { row_label(&row, &name, name_width, most) };
+            v.add_item(label, row);
+        }
+        v.set_selection(index);
+    });
+}
+
+/// The indented name of a row, with '+' or '-' before a directory to show
+/// whether it can be expanded or collapsed.
+fn name(row: &StatRow) -> String {
+    let file_name = |path: &Path| path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+    match *row {
+        StatRow::Dir { ref path, depth, expanded, .. } => {
+            format!("{}{} {}/", "  ".repeat(depth), if expanded { "-" } else { "+" }, file_name(path))
+        },
+        StatRow::File { ref stat, depth } => format!("{}  {}", "  ".repeat(depth), file_name(&stat.path)),
+    }
+}
+
+/// E.g. '+ src/       | 12 files |  +120  -40 +++++++---', in the style of
+/// 'git diff --stat'.
+fn row_label(row: &StatRow, name: &str, name_width: usize, most: usize) -> String {
+    let files = match *row {
+        StatRow::Dir { totals, .. } => format!("{} {}", totals.files, if totals.files == 1 { "file" } else { "files" }),
+        StatRow::File { ref stat, .. } if stat.binary => "Bin".to_string(),
+        StatRow::File { .. } => String::new(),
+    };
+    let (added, removed) = row.changes();
+    let (plus, minus) = diffstat::graph(added, removed, most, GRAPH_WIDTH);
+    format!("{} | {:>9} | {:>6} {:>6} {}{}", text_width::pad(name, name_width), files,
+            format!("+{}", added), format!("-{}", removed), plus, minus)
+}
+
+fn spoken_label(row: &StatRow) -> String {
+    let (added, removed) = row.changes();
+    match *row {
+        StatRow::Dir { ref path, totals, expanded, .. } => format!("{}/: {} files, {} lines added, {} removed, {}",
+                                                                path.display(), totals.files, added, removed,
+                                                                if expanded { "expanded" } else { "collapsed" }),
+        StatRow::File { ref stat, .. } if stat.binary => format!("{}: binary", stat.path.display()),
+        StatRow::File { ref stat, .. } => format!("{}: {} lines added, {} removed", stat.path.display(), added, removed),
+    }
+}
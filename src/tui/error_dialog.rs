@@ -0,0 +1,41 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{Dialog, LinearLayout, TextView};
+use clipboard;
+use error_report::ErrorReport;
+use super::notifications::{self, Level};
+
+const DETAILS_ID: &str = "error_dialog_details";
+
+/// Shows a failed operation: the short message, with the details hidden
+/// until the user asks for them and a button to copy them, e.g. into a bug
+/// report. The error is also logged and added to the notification history.
+pub fn show(siv: &mut Cursive, report: ErrorReport) {
+    error!("{}\n{}", report, report.details_text());
+    notifications::record(Level::Error, report.to_string());
+
+    let details = report.details_text();
+    let expanded = Rc::new(Cell::new(false));
+    let details_for_copy = details.clone();
+
+    let content = LinearLayout::vertical()
+        .child(TextView::new(report.message.clone()))
+        .child(TextView::new("").with_id(DETAILS_ID));
+
+    siv.add_layer(Dialog::around(content.scrollable().max_height(20).max_width(80))
+        .title(format!("{} Failed", report.operation))
+        .button("Details", move |s| {
+            expanded.set(!expanded.get());
+            let text = if expanded.get() { format!("\n{}", details) } else { String::new() };
+            s.call_on_id(DETAILS_ID, |v: &mut TextView| v.set_content(text));
+        })
+        .button("Copy Details", move |s| {
+            match clipboard::copy(&details_for_copy) {
+                Ok(()) => notifications::notify(s, Level::Info, "Copied the error details to the clipboard."),
+                Err(e) => notifications::notify(s, Level::Warning, format!("Cannot copy the error details: {}", e)),
+            }
+        })
+        .dismiss_button("Close"));
+}
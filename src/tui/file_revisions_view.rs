@@ -0,0 +1,303 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable};
+use cursive::utils::markup::StyledString;
+use cursive::views::{IdView, LinearLayout, OnEventView, TextView};
+use config;
+use dates;
+use file_revisions::{self, Content, FileRevisions};
+use highlight::Highlighter;
+use history;
+use patch::{self, PatchLine};
+use range_diff::DiffLine;
+use super::diff_view;
+use super::overview::{Mark, Marks, Overview, SharedMarks};
+use super::palette;
+
+const HEADER_ID: &str = "file_revisions_header";
+const CONTENT_ID: &str = "file_revisions_content";
+const OVERVIEW_ID: &str = "file_revisions_overview";
+
+struct State {
+    revisions: FileRevisions,
+    /// The revision shown; 0 is the newest.
+    index: usize,
+    /// Show the changes from the previous revision rather than just the file.
+    diff: bool,
+    /// The text to find, marked in the overview.
+    search: String,
+    /// How many unchanged lines are shown around each change in the diff.
+    context: u32,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = RefCell::new(None);
+}
+
+/// Asks for a file and a revision, then shows the file as it was then.
+pub fn show(siv: &mut Cursive) {
+    super::dialogs::prompt(siv, "File at Revision (path)", "", |s, path| {
+        let path = PathBuf::from(path.trim());
+        super::dialogs::prompt(s, "Revision", "HEAD", move |s, spec| {
+            let start = match super::with_current_repo(s, |repo| history::resolve_commit(repo, spec)) {
+                Some(Ok(oid)) => oid,
+                Some(Err(e)) => return super::report_result::<::git2::Error>(s, "File at revision", Err(e)),
+                None => return,
+            };
+            show_file(s, &path, start);
+        });
+    });
+}
+
+/// Shows `path` as it was at the commit `start`, from where the older
+/// revisions can be stepped through.
+pub fn show_file(siv: &mut Cursive, path: &Path, start: ::git2::Oid) {
+    let max_bytes = config::current().limits.diff_limits().max_file_bytes;
+    let loaded = match super::with_current_repo(siv, |repo| FileRevisions::load(repo, path, start, max_bytes, &super::identity_mailmap(repo))) {
+        Some(Ok(revisions)) => revisions,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "File at revision", Err(e)),
+        None => return,
+    };
+    STATE.with(|st| *st.borrow_mut() = Some(State {
+        revisions: loaded,
+        index: 0,
+        diff: false,
+        search: String::new(),
+        context: patch::DEFAULT_CONTEXT_LINES,
+    }));
+
+    if siv.find_id::<TextView>(HEADER_ID).is_none() {
+        let layout = LinearLayout::vertical()
+            .child(TextView::new("").with_id(HEADER_ID))
+            .child(Overview::new(TextView::new("").with_id(CONTENT_ID), SharedMarks::default()).with_id(OVERVIEW_ID).full_screen());
+
+        let view = OnEventView::new(layout)
+            .on_event('p', |s| step(s, 1))
+            .on_event('n', |s| step(s, -1))
+            .on_event('d', toggle_diff)
+            .on_event('g', prompt_goto)
+            .on_event('/', prompt_find)
+            .on_event('+', |s| change_context(s, true))
+            .on_event('-', |s| change_context(s, false))
+            .on_event('[', |s| jump(s, false))
+            .on_event(']', |s| jump(s, true))
+            .on_event('c', show_commit)
+            .on_event('q', |s| {
+                STATE.with(|st| *st.borrow_mut() = None);
+                s.pop_layer();
+            });
+        super::navigation::open(siv, &format!("{} at revision", path.display()), view, true);
+    }
+
+    render(siv);
+}
+
+/// Moves `by` revisions older, or newer if negative, stopping at the ends.
+fn step(siv: &mut Cursive, by: isize) {
+    STATE.with(|st| {
+        if let Some(ref mut state) = *st.borrow_mut() {
+            let last = state.revisions.revisions.len() as isize - 1;
+            state.index = (state.index as isize + by).max(0).min(last) as usize;
+        }
+    });
+    render(siv);
+}
+
+fn toggle_diff(siv: &mut Cursive) {
+    STATE.with(|st| {
+        if let Some(ref mut state) = *st.borrow_mut() {
+            state.diff = !state.diff;
+        }
+    });
+    render(siv);
+}
+
+/// Reloads the revisions from another commit, keeping the file.
+fn prompt_goto(siv: &mut Cursive) {
+    super::dialogs::prompt(siv, "Go to Revision", "", |s, spec| {
+        let path = STATE.with(|st| st.borrow().as_ref().map(|state| state.revisions.path.clone()));
+        let start = super::with_current_repo(s, |repo| history::resolve_commit(repo, spec));
+        match (path, start) {
+            (Some(path), Some(Ok(oid))) => show_file(s, &path, oid),
+            (_, Some(Err(e))) => super::report_result::<::git2::Error>(s, "Go to revision", Err(e)),
+            _ => {},
+        }
+    });
+}
+
+/// Shows more, or less, of the unchanged lines around each change in the
+/// diff.
+fn change_context(siv: &mut Cursive, more: bool) {
+    STATE.with(|st| {
+        if let Some(ref mut state) = *st.borrow_mut() {
+            state.context = diff_view::step_context(state.context, more);
+        }
+    });
+    render(siv);
+}
+
+/// Marks the lines holding some text, ignoring case, and scrolls to the
+/// first of them.
+fn prompt_find(siv: &mut Cursive) {
+    let search = STATE.with(|st| st.borrow().as_ref().map(|state| state.search.clone())).unwrap_or_default();
+    super::dialogs::prompt(siv, "Find", &search, |s, search| {
+        STATE.with(|st| {
+            if let Some(ref mut state) = *st.borrow_mut() {
+                state.search = search.to_string();
+            }
+        });
+        render(s);
+        s.call_on_id(OVERVIEW_ID, |v: &mut Overview<IdView<TextView>>| v.jump(true));
+    });
+}
+
+/// Scrolls to the next, or previous, change or line found.
+fn jump(siv: &mut Cursive, forward: bool) {
+    siv.call_on_id(OVERVIEW_ID, |v: &mut Overview<IdView<TextView>>| v.jump(forward));
+}
+
+fn show_commit(siv: &mut Cursive) {
+    let oid = STATE.with(|st| st.borrow().as_ref().map(|state| state.revisions.revisions[state.index].oid));
+    if let Some(oid) = oid {
+        super::show_commit(siv, oid);
+    }
+}
+
+fn render(siv: &mut Cursive) {
+    let rendered = super::with_current_repo(siv, |repo| STATE.with(|st| {
+        st.borrow_mut().as_mut().map(|state| -> Result<_, ::git2::Error> {
+            let index = state.index;
+            let content = state.revisions.content(repo, index)?;
+            let diff = if state.diff { state.revisions.diff_with_previous(repo, index)? } else { None };
+            let highlighter = palette::highlighter(&state.revisions.path);
+            let (body, marks) = match diff {
+                Some(lines) => diff_text(&lines, highlighter, &state.search, state.context as usize),
+                None => content_text(&content, highlighter, &state.search),
+            };
+            Ok((header(state, &content), body, marks))
+        })
+    }));
+
+    let (header, body, marks) = match rendered {
+        Some(Some(Ok(rendered))) => rendered,
+        Some(Some(Err(e))) => return super::report_result::<::git2::Error>(siv, "File at revision", Err(e)),
+        _ => return,
+    };
+    siv.call_on_id(HEADER_ID, |v: &mut TextView| v.set_content(header));
+    siv.call_on_id(CONTENT_ID, |v: &mut TextView| v.set_content(body));
+    siv.call_on_id(OVERVIEW_ID, |v: &mut Overview<IdView<TextView>>| v.set_marks(marks));
+}
+
+fn header(state: &State, content: &Content) -> String {
+    let revisions = &state.revisions.revisions;
+    let entry = &revisions[state.index];
+    let config = config::current();
+    let more = if revisions.len() >= file_revisions::MAX_REVISIONS { "+" } else { "" };
+    let mode = match *content {
+        Content::Text(ref text) if state.diff => format!("changes from the previous revision ({})", text.description()),
+        Content::Text(ref text) => format!("content ({})", text.description()),
+        _ => "content".to_string(),
+    };
+    format!("{} at {:.7}, revision {} of {}{}, {}\n{} {} {}\n\
+             [p] previous  [n] next  [d] toggle diff  [g] go to revision  [/] find  [[] []] previous/next mark  [+] [-] context  [c] show commit  [q] close",
+            state.revisions.path.display(), entry.oid.to_string(), revisions.len() - state.index, revisions.len(), more, mode,
+            dates::format(&entry.time, config.dates.log, &config.dates), entry.author, entry.summary)
+}
+
+fn content_text(content: &Content, mut highlighter: Option<Highlighter>, search: &str) -> (StyledString, Marks) {
+    let mut marks = Marks::default();
+    let text = match *content {
+        Content::Text(ref text) => {
+            let text = text.display();
+            let mut styled = if highlighter.is_some() { StyledString::new() } else { StyledString::plain(text.clone().into_owned()) };
+            for line in text.lines() {
+                if highlighter.is_some() {
+                    palette::append_change(&mut styled, highlighter.as_mut(), "", None, line);
+                }
+                marks.push(if is_hit(line, search) { Some(Mark::Hit) } else { None });
+            }
+            styled
+        },
+        Content::Binary(size) => StyledString::plain(format!("Binary or too big to show ({}).", patch::format_size(size as u64))),
+        Content::Missing => StyledString::plain("The file does not exist at this revision."),
+    };
+    (text, marks)
+}
+
+/// The whole file with its changes, except that long runs of unchanged
+/// lines more than `context` lines from a change or a line found are
+/// replaced by a note of how many there are.
+fn diff_text(lines: &[DiffLine], mut highlighter: Option<Highlighter>, search: &str, context: usize) -> (StyledString, Marks) {
+    let palette = palette::current();
+    let shown = shown_lines(lines, search, context);
+    let mut text = StyledString::new();
+    let mut marks = Marks::default();
+    let mut i = 0;
+    while i < lines.len() {
+        let hidden = shown[i..].iter().take_while(|&&s| !s).count();
+        if hidden > 1 {
+            // Keep the highlighting in step, e.g. through a long comment.
+            if let Some(ref mut highlighter) = highlighter {
+                for line in &lines[i..i + hidden] {
+                    highlighter.line(line.text());
+                }
+            }
+            text.append_styled(format!("{}\n", PatchLine::unchanged(hidden).text()), palette.notice);
+            marks.push(None);
+            i += hidden;
+            continue;
+        }
+
+        let (prefix, colour, mark) = match lines[i] {
+            DiffLine::Same(_) => (" ", None, None),
+            DiffLine::Removed(_) => ("-", Some(palette.removed), Some(Mark::Removed)),
+            DiffLine::Added(_) => ("+", Some(palette.added), Some(Mark::Added)),
+        };
+        let line = lines[i].text();
+        palette::append_change(&mut text, highlighter.as_mut(), prefix, colour, line);
+        marks.push(if is_hit(line, search) { Some(Mark::Hit) } else { mark });
+        i += 1;
+    }
+    (text, marks)
+}
+
+/// Which of `lines` to show: the changes and the lines found, with
+/// `context` lines either side of them.
+fn shown_lines(lines: &[DiffLine], search: &str, context: usize) -> Vec<bool> {
+    let mut shown = vec![false; lines.len()];
+    for (i, line) in lines.iter().enumerate() {
+        let keep = match *line {
+            DiffLine::Same(ref l) => is_hit(l, search),
+            _ => true,
+        };
+        if keep {
+            let end = (i + context + 1).min(lines.len());
+            for s in &mut shown[i.saturating_sub(context)..end] {
+                *s = true;
+            }
+        }
+    }
+    shown
+}
+
+fn is_hit(line: &str, search: &str) -> bool {
+    !search.is_empty() && line.to_lowercase().contains(&search.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_lines_far_from_changes_and_hits_are_hidden() {
+        let mut lines: Vec<DiffLine> = (0..20).map(|n| DiffLine::Same(format!("line {}", n))).collect();
+        lines[5] = DiffLine::Added("new".to_string());
+        let shown = shown_lines(&lines, "", 1);
+        assert_eq!(shown.iter().filter(|&&s| s).count(), 3);
+        assert!(shown[4] && shown[5] && shown[6]);
+
+        let shown = shown_lines(&lines, "LINE 15", 0);
+        assert!(shown[5] && shown[15] && !shown[14]);
+    }
+}
@@ -0,0 +1,245 @@
+use std::rc::Rc;
+use cursive::{Cursive, Printer};
+use cursive::direction::Direction;
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::ColorStyle;
+use cursive::vec::Vec2;
+use cursive::view::View;
+use fuzzy;
+use super::virtual_list::VirtualList;
+
+/// An item in the list, with the chars of its label that matched the filter.
+#[derive(Clone)]
+struct Filtered<T> {
+    item: T,
+    positions: Vec<usize>,
+}
+
+/// A list that can be narrowed down by typing part of what is wanted. The
+/// filter is fuzzy: the typed characters must appear in the label in order,
+/// and the best matches are shown first with the matching characters
+/// underlined. The first row shows the filter.
+///
+/// In a view that has its own single key commands, '/' starts typing the
+/// filter and Enter or Esc stops. In a dialog, `always_filtering` sends
+/// every key typed to the filter.
+pub struct FilterView<T> {
+    items: Vec<T>,
+    label: Rc<dyn Fn(&T) -> String>,
+    query: String,
+    editing: bool,
+    always_filtering: bool,
+    list: VirtualList<Filtered<T>>,
+}
+
+impl<T: Clone + 'static> FilterView<T> {
+    /// Creates an empty list whose rows, which are also what the filter
+    /// matches against, are made by `label`.
+    pub fn new<F>(label: F) -> Self
+        where F: Fn(&T) -> String + 'static
+    {
+        let mut view = FilterView {
+            items: Vec::new(),
+            label: Rc::new(label),
+            query: String::new(),
+            editing: false,
+            always_filtering: false,
+            list: VirtualList::new(|_: &Filtered<T>| String::new()),
+        };
+        view.list.set_highlight(|f: &Filtered<T>| f.positions.clone());
+        view.update_label();
+        view
+    }
+
+    /// Sends every character typed to the filter, rather than waiting for
+    /// '/'. Chainable.
+    pub fn always_filtering(mut self) -> Self {
+        self.always_filtering = true;
+        self.editing = true;
+        self
+    }
+
+    pub fn set_label<F>(&mut self, label: F)
+        where F: Fn(&T) -> String + 'static
+    {
+        self.label = Rc::new(label);
+        self.update_label();
+        self.refilter();
+    }
+
+    /// Sets how a row too wide for the list is shortened; see
+    /// `VirtualList::set_fit`.
+    pub fn set_fit<F>(&mut self, fit: F)
+        where F: Fn(&T, usize) -> String + 'static
+    {
+        self.list.set_fit(move |f: &Filtered<T>, width| fit(&f.item, width));
+    }
+
+    /// Replaces the items, keeping the filter.
+    pub fn set_items(&mut self, items: Vec<T>) {
+        self.items = items;
+        self.refilter();
+    }
+
+    /// The items that match the filter, best first.
+    pub fn shown(&self) -> Vec<T> {
+        self.list.items().iter().map(|f| f.item.clone()).collect()
+    }
+
+    pub fn selection(&self) -> Option<T> {
+        self.list.selection().map(|f| f.item.clone())
+    }
+
+    pub fn set_on_select<F>(&mut self, cb: F)
+        where F: Fn(&mut Cursive, &T) + 'static
+    {
+        self.list.set_on_select(move |s, f: &Filtered<T>| cb(s, &f.item));
+    }
+
+    pub fn set_on_submit<F>(&mut self, cb: F)
+        where F: Fn(&mut Cursive, &T) + 'static
+    {
+        self.list.set_on_submit(move |s, f: &Filtered<T>| cb(s, &f.item));
+    }
+
+    fn update_label(&mut self) {
+        let label = self.label.clone();
+        self.list.set_label(move |f: &Filtered<T>| label(&f.item));
+    }
+
+    fn refilter(&mut self) {
+        let label = self.label.clone();
+        let matches = fuzzy::filter(&self.query, &self.items, |item| label(item));
+        let items = &self.items;
+        self.list.set_items(matches.into_iter()
+            .map(|(i, m)| Filtered { item: items[i].clone(), positions: m.positions })
+            .collect());
+    }
+
+    /// Changes the filter and selects the best match.
+    fn set_query(&mut self, query: String) -> EventResult {
+        self.query = query;
+        self.refilter();
+        EventResult::Consumed(Some(self.list.set_selection(0)))
+    }
+
+    fn prompt(&self) -> String {
+        if !self.editing && self.query.is_empty() {
+            return format!("[/] filter {} items", self.items.len());
+        }
+
+        let cursor = if self.editing { "_" } else { "" };
+        format!("Filter: {}{}  ({} of {})", self.query, cursor, self.list.len(), self.items.len())
+    }
+}
+
+impl<T: Clone + 'static> View for FilterView<T> {
+    fn draw(&self, printer: &Printer) {
+        printer.with_color(ColorStyle::secondary(), |printer| printer.print((0, 0), &self.prompt()));
+        self.list.draw(&printer.offset((0, 1)));
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        self.list.layout(size.saturating_sub((0, 1)));
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        self.list.required_size(constraint.saturating_sub((0, 1))) + (0, 1)
+    }
+
+    fn take_focus(&mut self, direction: Direction) -> bool {
+        self.list.take_focus(direction)
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        if !self.editing {
+            return match event {
+                Event::Char('/') => {
+                    self.editing = true;
+                    EventResult::Consumed(None)
+                },
+                event => self.list.on_event(event.relativized((0, 1))),
+            };
+        }
+
+        match event {
+            Event::Char(c) => {
+                let mut query = self.query.clone();
+                query.push(c);
+                self.set_query(query)
+            },
+            Event::Key(Key::Backspace) if !self.query.is_empty() => {
+                let mut query = self.query.clone();
+                query.pop();
+                self.set_query(query)
+            },
+            Event::Key(Key::Esc) if !self.query.is_empty() || !self.always_filtering => {
+                self.editing = self.always_filtering;
+                self.set_query(String::new())
+            },
+            Event::Key(Key::Enter) if !self.always_filtering => {
+                self.editing = false;
+                EventResult::Consumed(None)
+            },
+            event => self.list.on_event(event.relativized((0, 1))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn branches() -> FilterView<String> {
+        let mut view = FilterView::new(|s: &String| s.clone());
+        view.set_items(vec!["master".to_string(), "feature/login".to_string(), "fix/logging".to_string()]);
+        view.layout(Vec2::new(40, 10));
+        view
+    }
+
+    fn type_text(view: &mut FilterView<String>, text: &str) {
+        for c in text.chars() {
+            view.on_event(Event::Char(c));
+        }
+    }
+
+    #[test]
+    fn slash_starts_filtering_and_the_best_match_is_selected() {
+        let mut view = branches();
+        type_text(&mut view, "log");
+        assert_eq!(view.list.len(), 3);
+        assert_eq!(view.query, "");
+
+        type_text(&mut view, "/flog");
+        assert_eq!(view.query, "flog");
+        assert_eq!(view.list.len(), 2);
+        assert_eq!(view.list.selection().map(|f| f.item.as_str()), Some("fix/logging"));
+        assert_eq!(view.prompt(), "Filter: flog_  (2 of 3)");
+
+        view.on_event(Event::Key(Key::Enter));
+        assert!(!view.editing);
+        assert_eq!(view.prompt(), "Filter: flog  (2 of 3)");
+    }
+
+    #[test]
+    fn esc_clears_the_filter() {
+        let mut view = branches().always_filtering();
+        type_text(&mut view, "mas");
+        assert_eq!(view.list.len(), 1);
+        view.on_event(Event::Key(Key::Backspace));
+        assert_eq!(view.query, "ma");
+
+        view.on_event(Event::Key(Key::Esc));
+        assert_eq!(view.query, "");
+        assert_eq!(view.list.len(), 3);
+        assert!(view.editing);
+    }
+
+    #[test]
+    fn replacing_the_items_keeps_the_filter() {
+        let mut view = branches();
+        type_text(&mut view, "/fea");
+        view.set_items(vec!["feature/a".to_string(), "feature/b".to_string(), "main".to_string()]);
+        assert_eq!(view.list.len(), 2);
+    }
+}
@@ -0,0 +1,54 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, TextView};
+use config;
+use error::OafError;
+use gitignore_catalogue;
+use paths;
+use scaffold::{self, Template, TemplateKind};
+
+/// Asks which .gitignore template to add, from the catalogue and the
+/// configured template directory, then shows what would be appended.
+pub fn show(siv: &mut Cursive) {
+    let template_dir = config::current().new_repo.template_dir.as_ref().map(paths::expand_tilde);
+    let templates = scaffold::templates(TemplateKind::Gitignore, template_dir.as_ref().map(|d| d.as_path()));
+    let names = templates.iter().map(|t| t.name.clone()).collect();
+
+    super::dialogs::choose(siv, "Add .gitignore Template", names, move |s, name| {
+        if let Some(template) = templates.iter().find(|t| t.name == name) {
+            preview(s, template.clone());
+        }
+    });
+}
+
+fn preview(siv: &mut Cursive, template: Template) {
+    let plan = super::with_current_repo(siv, |repo| {
+        gitignore_catalogue::read(repo).map(|existing| gitignore_catalogue::plan_append(&existing, &template))
+    });
+    let plan = match plan {
+        Some(Ok(plan)) => plan,
+        Some(Err(e)) => return super::report_result::<OafError>(siv, "Read .gitignore", Err(e)),
+        None => return,
+    };
+    if plan.text.is_empty() {
+        return siv.add_layer(Dialog::info(format!(".gitignore already has all the rules of the '{}' template.", template.name)));
+    }
+
+    let mut text = plan.text.trim_start_matches('\n').to_string();
+    if !plan.skipped.is_empty() {
+        text.push_str(&format!("\n{} rules already in .gitignore are left out.", plan.skipped.len()));
+    }
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(20).min_width(40))
+        .title("Append to .gitignore")
+        .button("Append", move |s| {
+            s.pop_layer();
+            let result = super::with_current_repo(s, |repo| {
+                gitignore_catalogue::append(repo, &template).map(|_| format!("Added the '{}' template to .gitignore.", template.name))
+            });
+            if let Some(result) = result {
+                super::report_result(s, "Append to .gitignore", result);
+                super::split_view::refresh(s);
+            }
+        })
+        .dismiss_button("Cancel"));
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, SelectView};
+use error::OafError;
+use global_search::{self, RepoResults, SearchHit, SearchMode};
+use paths;
+use text_width::{self, Ellipsis};
+
+/// Asks what to search for and where to look, then searches every
+/// repository in the MRU list, open or not, in a progress dialog.
+pub fn show(siv: &mut Cursive) {
+    super::dialogs::prompt(siv, "Search All Repositories", "", |s, query| {
+        let query = query.trim().to_string();
+        if query.is_empty() {
+            return;
+        }
+        let modes = SearchMode::all().iter().map(|m| m.description().to_string()).collect();
+        super::dialogs::choose(s, "Search In", modes, move |s, choice| {
+            let mode = *SearchMode::all().iter().find(|m| m.description() == choice).unwrap();
+            search(s, query.clone(), mode);
+        });
+    });
+}
+
+fn search(siv: &mut Cursive, query: String, mode: SearchMode) {
+    let paths: Vec<PathBuf> = super::with_repos(|repos| repos.mru.iter().map(paths::to_canon).collect()).unwrap_or_default();
+    let title = format!("Searching for '{}'", query);
+    let job_query = query.clone();
+    super::progress_dialog::run(siv, &title, move |cancel, progress| {
+        global_search::search(&paths, mode, &job_query, cancel, |path| progress(format!("Searching {}", path.display())))
+    }, move |s, result: Result<Vec<RepoResults>, OafError>| match result {
+        Ok(results) => show_results(s, &query, &results),
+        Err(e) => super::progress_dialog::report(s, "Search all repositories", Err(e)),
+    });
+}
+
+/// Lists the hits under a line for each repository. Choosing a hit opens its
+/// repository, if need be, and shows the commit or file.
+fn show_results(siv: &mut Cursive, query: &str, results: &[RepoResults]) {
+    if results.is_empty() {
+        return siv.add_layer(Dialog::info(format!("Nothing matches '{}'.", query)));
+    }
+
+    let mut list = SelectView::<Option<(PathBuf, SearchHit)>>::new();
+    for repo in results {
+        list.add_item(paths::compress_tilde(&repo.path).display().to_string(), None);
+        if let Some(ref error) = repo.error {
+            list.add_item(format!("    cannot be searched: {}", error), None);
+        }
+        for hit in &repo.hits {
+            let label = match *hit {
+                SearchHit::Commit { oid, ref summary } => format!("    {:.7} {}", oid.to_string(), summary),
+                SearchHit::Line { ref path, line, ref text } => format!("    {}:{}: {}", path.display(), line, text),
+            };
+            list.add_item(text_width::fit(&label, 100, Ellipsis::End), Some((repo.path.clone(), hit.clone())));
+        }
+        if repo.truncated {
+            list.add_item(format!("    (only the first {} hits are shown)", global_search::MAX_HITS_PER_REPO), None);
+        }
+    }
+    list.set_on_submit(|s, item: &Option<(PathBuf, SearchHit)>| {
+        if let Some((ref path, ref hit)) = *item {
+            s.pop_layer();
+            open_hit(s, path, hit);
+        }
+    });
+
+    let count: usize = results.iter().map(|r| r.hits.len()).sum();
+    siv.add_layer(Dialog::around(list.scrollable().max_height(25))
+        .title(format!("{} Hits for '{}' in {} Repositories", count, query, results.len()))
+        .dismiss_button("Close"));
+}
+
+fn open_hit(siv: &mut Cursive, path: &Path, hit: &SearchHit) {
+    if !super::select_or_open(siv, path) {
+        return;
+    }
+
+    match *hit {
+        SearchHit::Commit { oid, .. } => {
+            super::log_view::show(siv);
+            super::log_view::goto_commit(siv, oid);
+        },
+        SearchHit::Line { ref path, .. } => super::show_file_info(siv, path),
+    }
+}
@@ -0,0 +1,53 @@
+use cursive::Cursive;
+use cursive::views::Dialog;
+use config;
+use identity::{self, Applied};
+use super::notifications::{self, Level};
+
+const AUTOMATIC: &str = "(automatic, from remote URLs)";
+
+/// Asks which identity profile to commit under in the current repository.
+pub fn show(siv: &mut Cursive) {
+    let profiles = config::current().identities.clone();
+    if profiles.is_empty() {
+        return siv.add_layer(Dialog::info("There are no identity profiles. Add them to the 'identities' setting."));
+    }
+
+    let mut names = vec![AUTOMATIC.to_string()];
+    names.extend(profiles.iter().map(|p| p.name.clone()));
+    super::dialogs::choose(siv, "Identity for This Repository", names, |s, name| {
+        let name = if name == AUTOMATIC { None } else { Some(name.to_string()) };
+        let result = super::with_current_repo(s, |repo| {
+            identity::choose(repo, name.as_ref().map(|n| n.as_str())).map(|_| match name {
+                Some(ref name) => format!("Commits will be made as '{}'.", name),
+                None => "Commits will be made as the profile matching a remote URL, if any.".to_string(),
+            })
+        });
+        if let Some(result) = result {
+            super::report_result(s, "Choose identity", result);
+            super::log_view::refresh(s);
+        }
+    });
+}
+
+/// Applies the current repository's identity profile, if it has one, before
+/// a commit is made. Returns false if that failed, in which case the commit
+/// should not go ahead.
+pub fn apply(siv: &mut Cursive) -> bool {
+    let profiles = config::current().identities.clone();
+    let result = super::with_current_repo(siv, |repo| match identity::select(repo, &profiles).profile() {
+        Some(profile) => identity::apply(repo, profile),
+        None => Ok(Applied::Unchanged),
+    });
+    match result {
+        Some(Ok(Applied::KeptLocal(own))) => {
+            notifications::notify(siv, Level::Warning, format!(
+                "This repository sets its own user.name and user.email, so committing as {}; unset them to use the \
+                 identity profile.", own));
+            true
+        },
+        Some(Ok(_)) => true,
+        Some(Err(e)) => { super::report_result::<::git2::Error>(siv, "Apply identity profile", Err(e)); false },
+        None => false,
+    }
+}
@@ -0,0 +1,100 @@
+use cursive::Cursive;
+use cursive::traits::Boxable;
+use cursive::views::{Dialog, TextView};
+use in_progress::{self, InProgress};
+
+/// The operation in progress in the current repository, if there is one.
+pub fn current(siv: &mut Cursive) -> Option<InProgress> {
+    super::with_current_repo(siv, |repo| in_progress::detect(repo).ok().and_then(|s| s)).and_then(|s| s)
+}
+
+/// Shows the operation in progress in the current repository, with buttons
+/// to continue or abort it. Returns false if there is nothing in progress.
+pub fn show(siv: &mut Cursive) -> bool {
+    let state = match current(siv) {
+        Some(state) => state,
+        None => return false,
+    };
+
+    let mut text = state.banner();
+    if !state.operation.can_continue() {
+        text += &format!("\n\noaf cannot continue a {}. {}", state.operation.command(), state.command_line_hint());
+    } else if state.conflicts > 0 {
+        text += "\n\nResolve the conflicts and stage the files, then continue.";
+    }
+
+    let mut dialog = Dialog::around(TextView::new(text).max_width(80))
+        .title(format!("{} in Progress", capitalised(state.operation.command())));
+    if state.operation.can_continue() {
+        dialog = dialog.button("Continue", continue_operation);
+    }
+    let label = state.operation.abort_label();
+    siv.add_layer(dialog
+        .button(label, move |s| abort(s, label))
+        .button("Show Status", |s| {
+            s.pop_layer();
+            super::split_view::show_status(s);
+        })
+        .dismiss_button("Close"));
+    true
+}
+
+/// Shows the operation in progress from the menu, saying so if there is none.
+pub fn show_from_menu(siv: &mut Cursive) {
+    if !show(siv) {
+        siv.add_layer(Dialog::info("There is no merge, rebase, cherry-pick, revert or bisect in progress."));
+    }
+}
+
+fn capitalised(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+}
+
+fn continue_operation(siv: &mut Cursive) {
+    let result = super::with_current_repo(siv, |repo| {
+        in_progress::continue_operation(repo).map(|oid| format!("Committed {:.7}.", oid.to_string()))
+    });
+    match result {
+        Some(Ok(message)) => {
+            siv.pop_layer();
+            after_change(siv);
+            super::report_result::<::error::OafError>(siv, "Continue", Ok(message));
+        },
+        Some(Err(e)) => super::report_result::<::error::OafError>(siv, "Continue", Err(e)),
+        None => {},
+    }
+}
+
+fn abort(siv: &mut Cursive, label: &'static str) {
+    let question = "Put HEAD and the working tree back as they were before the operation started? \
+                    Changes made while resolving conflicts will be lost.";
+    super::dialogs::confirm(siv, label, question, move |s| {
+        let result = super::with_current_repo(s, |repo| in_progress::abort(repo).map(|autostash| autostash.message()));
+        match result {
+            Some(Ok(message)) => {
+                s.pop_layer();
+                after_change(s);
+                super::report_result::<::error::OafError>(s, label, Ok(message));
+            },
+            Some(Err(e)) => super::report_result::<::error::OafError>(s, label, Err(e)),
+            None => {},
+        }
+    });
+}
+
+fn after_change(siv: &mut Cursive) {
+    super::repo_tabs::refresh(siv);
+    super::log_view::refresh(siv);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capitalised_works() {
+        assert_eq!(capitalised("cherry-pick"), "Cherry-pick");
+        assert_eq!(capitalised(""), "");
+    }
+}
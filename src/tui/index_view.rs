@@ -0,0 +1,142 @@
+//! The status view laid out the way the index works: the files with staged
+//! changes in one list, above the files with changes not yet staged, so that
+//! changes can be moved from one to the other a file or a hunk at a time.
+
+use std::path::PathBuf;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::utils::markup::StyledString;
+use cursive::views::{LinearLayout, OnEventView, SelectView, TextView};
+use git2::Status;
+use config;
+use large_repo;
+use session::StatusLayout;
+use staging;
+use status;
+use super::diff_view::DiffSource;
+use super::split_view;
+
+const STAGED_ID: &str = "index_view_staged";
+const UNSTAGED_ID: &str = "index_view_unstaged";
+const STAGED_TITLE_ID: &str = "index_view_staged_title";
+const UNSTAGED_TITLE_ID: &str = "index_view_unstaged_title";
+
+type FileList = SelectView<(PathBuf, Status)>;
+
+/// Shows the staged and unstaged files beside the diff of the selected one.
+/// Enter moves a file to the other list; in the diff pane, 's' and 'u'
+/// move the hunk at the top.
+pub fn show(siv: &mut Cursive) {
+    let (staged, unstaged) = match load(siv) {
+        Some(files) => files,
+        None => return,
+    };
+    let first = unstaged.first().map(|&(ref path, _)| DiffSource::Unstaged(path.clone()))
+        .or_else(|| staged.first().map(|&(ref path, _)| DiffSource::Staged(path.clone())));
+
+    let mut staged_list = FileList::new();
+    staged_list.set_on_select(|s, &(ref path, _): &(PathBuf, Status)| split_view::show_source(s, DiffSource::Staged(path.clone())));
+    staged_list.set_on_submit(|s, &(ref path, _): &(PathBuf, Status)| unstage(s, path.clone()));
+    let mut unstaged_list = FileList::new();
+    unstaged_list.set_on_select(|s, &(ref path, _): &(PathBuf, Status)| split_view::show_source(s, DiffSource::Unstaged(path.clone())));
+    unstaged_list.set_on_submit(|s, &(ref path, _): &(PathBuf, Status)| stage(s, path.clone()));
+
+    let lists = LinearLayout::vertical()
+        .child(TextView::new("").with_id(STAGED_TITLE_ID))
+        .child(OnEventView::new(staged_list.with_id(STAGED_ID))
+            .on_event('u', |s| with_selected(s, STAGED_ID, unstage))
+            .scrollable()
+            .full_height())
+        .child(TextView::new("").with_id(UNSTAGED_TITLE_ID))
+        .child(OnEventView::new(unstaged_list.with_id(UNSTAGED_ID))
+            .on_event('s', |s| with_selected(s, UNSTAGED_ID, stage))
+            .scrollable()
+            .full_height());
+    let lists = OnEventView::new(lists)
+        .on_event('l', |s| split_view::switch_status_layout(s, StatusLayout::Combined));
+
+    split_view::show(siv, "Status and Diff",
+                     "  [Enter] stage/unstage  [s] [u] stage/unstage file, or hunk in the diff  [l] single list", lists);
+    set_files(siv, staged, unstaged);
+    match first {
+        Some(source) => split_view::show_source(siv, source),
+        None => split_view::set_diff(siv, StyledString::plain("There are no uncommitted changes.")),
+    }
+}
+
+/// Reloads the lists, if the view is open, keeping the selections where
+/// they were.
+pub fn refresh(siv: &mut Cursive) {
+    if siv.find_id::<FileList>(STAGED_ID).is_none() {
+        return;
+    }
+    if let Some((staged, unstaged)) = load(siv) {
+        set_files(siv, staged, unstaged);
+    }
+}
+
+/// Lists the staged and unstaged files. In a large repository untracked
+/// files are not looked for.
+fn load(siv: &mut Cursive) -> Option<(Vec<(PathBuf, Status)>, Vec<(PathBuf, Status)>)> {
+    let large_config = config::current().large_repo.clone();
+    let files = super::with_current_repo(siv, |repo| status::changed_files(repo, !large_repo::is_large(repo, &large_config)));
+    match files {
+        Some(Ok(files)) => Some(status::split_by_index(files)),
+        Some(Err(e)) => { super::report_result::<::git2::Error>(siv, "Load working tree status", Err(e)); None },
+        None => None,
+    }
+}
+
+fn set_files(siv: &mut Cursive, staged: Vec<(PathBuf, Status)>, unstaged: Vec<(PathBuf, Status)>) {
+    siv.call_on_id(STAGED_TITLE_ID, |v: &mut TextView| v.set_content(format!("Staged ({})", staged.len())));
+    siv.call_on_id(UNSTAGED_TITLE_ID, |v: &mut TextView| v.set_content(format!("Unstaged ({})", unstaged.len())));
+    set_items(siv, STAGED_ID, staged);
+    set_items(siv, UNSTAGED_ID, unstaged);
+}
+
+fn set_items(siv: &mut Cursive, id: &str, files: Vec<(PathBuf, Status)>) {
+    siv.call_on_id(id, |v: &mut FileList| {
+        let selected = v.selected_id().unwrap_or(0);
+        v.clear();
+        for (path, status) in files {
+            v.add_item(format!("{} {}", status::short_code(status), path.display()), (path, status));
+        }
+        if !v.is_empty() {
+            v.set_selection(selected.min(v.len() - 1));
+        }
+    });
+}
+
+fn with_selected<F: Fn(&mut Cursive, PathBuf)>(siv: &mut Cursive, id: &str, f: F) {
+    if let Some(Some(selected)) = siv.call_on_id(id, |v: &mut FileList| v.selection()) {
+        f(siv, selected.0.clone());
+    }
+}
+
+fn stage(siv: &mut Cursive, path: PathBuf) {
+    let result = super::with_current_repo(siv, |repo| {
+        staging::stage_file(repo, &path).map(|_| format!("Staged '{}'.", path.display()))
+    });
+    if let Some(result) = result {
+        let moved = result.is_ok();
+        super::report_result(siv, "Stage file", result);
+        if moved {
+            super::refresh_views(siv);
+            split_view::show_source(siv, DiffSource::Staged(path));
+        }
+    }
+}
+
+fn unstage(siv: &mut Cursive, path: PathBuf) {
+    let result = super::with_current_repo(siv, |repo| {
+        staging::unstage_file(repo, &path).map(|_| format!("Unstaged '{}'.", path.display()))
+    });
+    if let Some(result) = result {
+        let moved = result.is_ok();
+        super::report_result(siv, "Unstage file", result);
+        if moved {
+            super::refresh_views(siv);
+            split_view::show_source(siv, DiffSource::Unstaged(path));
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, TextView};
+use config;
+use inspect::{self, Inspection};
+
+/// Asks for an object id or revspec, then shows the raw object.
+pub fn show(siv: &mut Cursive) {
+    show_with(siv, "HEAD");
+}
+
+/// As `show`, starting with `initial` as the object, e.g. a ref chosen in the
+/// ref editor.
+pub fn show_with(siv: &mut Cursive, initial: &str) {
+    super::dialogs::prompt(siv, "Inspect Object (id or revspec, e.g. 'HEAD^{tree}' or 'main:README.md')", initial, |s, spec| {
+        let max_bytes = config::current().limits.diff_limits().max_file_bytes;
+        match super::with_current_repo(s, |repo| inspect::inspect(repo, spec, max_bytes)) {
+            Some(Ok(inspection)) => show_inspection(s, spec, &inspection),
+            Some(Err(e)) => super::report_result::<::git2::Error>(s, "Inspect object", Err(e)),
+            None => {},
+        }
+    });
+}
+
+fn show_inspection(siv: &mut Cursive, spec: &str, inspection: &Inspection) {
+    let spec = spec.to_string();
+    siv.add_layer(Dialog::around(TextView::new(inspection.to_string()).scrollable().max_height(30).max_width(120))
+        .title(format!("{} {:.7}", inspection.kind, inspection.oid.to_string()))
+        .button("Inspect Another...", move |s| {
+            s.pop_layer();
+            show_with(s, &spec);
+        })
+        .dismiss_button("Close"));
+}
@@ -0,0 +1,256 @@
+//! Tests of views that need a real repository, built by `fixtures` and
+//! shown on the test backend.
+
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use cursive::event::Key;
+use git2::Repository;
+use fixtures::TestRepo;
+use mru_list::MruList;
+use repositories::Repositories;
+use super::REPOS;
+use super::compare_view;
+use super::file_revisions_view;
+use super::index_view;
+use super::split_view;
+use super::test_backend::TestUi;
+
+/// Makes `fixture` the current repository for views on this thread.
+fn open(fixture: &TestRepo) {
+    let mut repos = Repositories::new(MruList::new(fixture.path().join(".git").join("oaf-test-mru"), 5));
+    repos.finish_open(fixture.path(), Ok(Repository::open(fixture.path()).unwrap())).unwrap();
+    REPOS.with(|r| *r.borrow_mut() = Some(repos));
+}
+
+#[test]
+fn status_view_lists_changes_and_shows_the_diff() {
+    let fixture = TestRepo::new("status-view");
+    fixture.commit_file("tracked.txt", "old line\n", "Initial commit");
+    fixture.write("tracked.txt", "new line\n");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 20);
+    split_view::show_status_and_diff(&mut ui.siv);
+    ui.render();
+    assert!(ui.shows(" M tracked.txt"), "{}", ui.screen());
+    assert!(ui.wait_for("+new line"), "{}", ui.screen());
+    assert!(ui.shows("-old line"), "{}", ui.screen());
+
+    ui.press(vec!['q']);
+    assert!(!ui.shows("tracked.txt"), "{}", ui.screen());
+}
+
+#[test]
+fn status_view_says_when_there_is_nothing_to_show() {
+    let fixture = TestRepo::new("status-view-clean");
+    fixture.commit_file("tracked.txt", "line\n", "Initial commit");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 20);
+    split_view::show_status_and_diff(&mut ui.siv);
+    ui.render();
+    assert!(ui.shows("There are no uncommitted changes."), "{}", ui.screen());
+}
+
+#[test]
+fn status_view_flags_files_with_mixed_line_endings() {
+    let fixture = TestRepo::new("status-view-eol");
+    fixture.commit_file("dos.txt", "one\r\n", "Initial commit");
+    fixture.write("dos.txt", "one\r\ntwo\n");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 20);
+    split_view::show_status_and_diff(&mut ui.siv);
+    ui.render();
+    assert!(ui.shows(" M dos.txt (mixed line endings)"), "{}", ui.screen());
+    assert!(ui.wait_for("+two"), "{}", ui.screen());
+}
+
+#[test]
+fn diff_pane_overview_marks_the_changes() {
+    let fixture = TestRepo::new("diff-overview");
+    fixture.commit_file("tracked.txt", "old line\n", "Initial commit");
+    fixture.write("tracked.txt", "new line\n");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 20);
+    split_view::show_status_and_diff(&mut ui.siv);
+    assert!(ui.wait_for("+new line"), "{}", ui.screen());
+    let lines = ui.lines();
+    let row = |text: &str| lines.iter().find(|l| l.contains(text)).unwrap().clone();
+    assert!(row("-old line").ends_with('-'), "{}", ui.screen());
+    assert!(row("+new line").ends_with('+'), "{}", ui.screen());
+}
+
+#[test]
+fn diff_pane_counts_and_jumps_between_hunks() {
+    let fixture = TestRepo::new("diff-hunks");
+    let lines: Vec<String> = (0..200).map(|n| format!("line {:04}\n", n)).collect();
+    fixture.commit_file("long.txt", &lines.concat(), "Add a long file");
+    let mut changed = lines.clone();
+    for &n in &[10, 60, 110, 160] {
+        changed[n] = format!("changed {:04}\n", n);
+    }
+    fixture.write("long.txt", &changed.concat());
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 16);
+    split_view::show_status_and_diff(&mut ui.siv);
+    assert!(ui.wait_for("+changed 0010"), "{}", ui.screen());
+    assert!(ui.wait_for("hunk 1 of 4"), "{}", ui.screen());
+
+    ui.press(vec![Key::Tab]).press(vec![']', ']']);
+    assert!(ui.shows("+changed 0110"), "{}", ui.screen());
+    assert!(ui.shows("hunk 3 of 4"), "{}", ui.screen());
+
+    ui.press(vec!['[']);
+    assert!(ui.shows("+changed 0060"), "{}", ui.screen());
+    assert!(ui.shows("hunk 2 of 4"), "{}", ui.screen());
+}
+
+#[test]
+fn diff_pane_notes_unchanged_lines_and_shows_more_context() {
+    let fixture = TestRepo::new("diff-context");
+    let lines: Vec<String> = (0..100).map(|n| format!("line {:04}\n", n)).collect();
+    fixture.commit_file("long.txt", &lines.concat(), "Add a long file");
+    let mut changed = lines.clone();
+    changed[50] = "changed 0050\n".to_string();
+    fixture.write("long.txt", &changed.concat());
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 30);
+    split_view::show_status_and_diff(&mut ui.siv);
+    assert!(ui.wait_for("... 47 unchanged lines ..."), "{}", ui.screen());
+    assert!(!ui.shows(" line 0045"), "{}", ui.screen());
+
+    ui.press(vec![Key::Tab]).press(vec!['+']);
+    assert!(ui.wait_for("... 37 unchanged lines ..."), "{}", ui.screen());
+    assert!(ui.shows(" line 0045"), "{}", ui.screen());
+}
+
+#[test]
+fn file_revisions_view_jumps_to_lines_found() {
+    let fixture = TestRepo::new("file-revisions-find");
+    let lines: String = (0..100).map(|n| format!("line {:04}\n", n)).collect();
+    let oid = fixture.commit_file("long.txt", &lines, "Add a long file");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 20);
+    file_revisions_view::show_file(&mut ui.siv, Path::new("long.txt"), oid);
+    ui.render();
+    assert!(ui.shows("line 0000"), "{}", ui.screen());
+    assert!(!ui.shows("line 0080"), "{}", ui.screen());
+
+    ui.press(vec!['/']).press("LINE 0080".chars()).press(vec![Key::Enter]);
+    assert!(ui.shows("line 0080"), "{}", ui.screen());
+    assert!(!ui.shows("line 0000"), "{}", ui.screen());
+}
+
+#[test]
+fn status_view_groups_untracked_files_by_directory() {
+    let fixture = TestRepo::new("status-view-untracked");
+    fixture.commit_file("tracked.txt", "line\n", "Initial commit");
+    fixture.write("build/a.o", "a\n").write("build/b.o", "b\n");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 20);
+    split_view::show_status_and_diff(&mut ui.siv);
+    ui.render();
+    assert!(ui.shows("?? + build/ (2 files)"), "{}", ui.screen());
+    assert!(!ui.shows("a.o"), "{}", ui.screen());
+
+    ui.press(vec![Key::Enter]);
+    assert!(ui.shows("?? - build/ (2 files)"), "{}", ui.screen());
+    assert!(ui.shows("??   a.o"), "{}", ui.screen());
+
+    ui.press(vec!['a']);
+    assert!(ui.shows("A  build/a.o"), "{}", ui.screen());
+}
+
+#[test]
+fn diff_pane_loads_more_of_a_long_diff_when_scrolled() {
+    let fixture = TestRepo::new("streamed-diff");
+    let lines: String = (0..2000).map(|n| format!("line {:04}\n", n)).collect();
+    fixture.commit_file("long.txt", &lines, "Add a long file");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 20);
+    split_view::show_log_and_diff(&mut ui.siv);
+    assert!(ui.wait_for("+line 0000"), "{}", ui.screen());
+
+    // Each jump to the end brings in another page.
+    ui.press(vec![Key::Tab]);
+    for _ in 0..100 {
+        if ui.shows("+line 1999") {
+            break;
+        }
+        ui.press(vec![Key::End]);
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(ui.shows("+line 1999"), "{}", ui.screen());
+}
+
+#[test]
+fn refresh_reloads_the_status_view() {
+    let fixture = TestRepo::new("status-view-refresh");
+    fixture.commit_file("tracked.txt", "line\n", "Initial commit");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 20);
+    split_view::show_status_and_diff(&mut ui.siv);
+    ui.render();
+    assert!(ui.shows("There are no uncommitted changes."), "{}", ui.screen());
+
+    fixture.write("tracked.txt", "changed\n");
+    super::refresh_views(&mut ui.siv);
+    ui.render();
+    assert!(ui.shows(" M tracked.txt"), "{}", ui.screen());
+}
+
+#[test]
+fn compare_view_toggles_between_three_dot_and_two_dot() {
+    let fixture = TestRepo::new("compare-view");
+    fixture.commit_file("base.txt", "base\n", "Base");
+    fixture.branch("feature");
+    fixture.commit_file("master.txt", "on master\n", "On master");
+    fixture.checkout("feature");
+    fixture.commit_file("feature.txt", "on feature\n", "On feature");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 30);
+    compare_view::show(&mut ui.siv);
+    ui.press("master...feature".chars()).press(vec![Key::Enter]);
+    assert!(ui.wait_for("+on feature"), "{}", ui.screen());
+    assert!(ui.shows("changes made on 'feature' since it forked from 'master'"), "{}", ui.screen());
+    assert!(!ui.shows("-on master"), "{}", ui.screen());
+
+    ui.press(vec!['t']);
+    assert!(ui.wait_for("-on master"), "{}", ui.screen());
+    assert!(ui.shows("+on feature"), "{}", ui.screen());
+    assert!(ui.shows("master..feature: the difference between the trees"), "{}", ui.screen());
+}
+
+#[test]
+fn index_view_moves_files_and_hunks_between_staged_and_unstaged() {
+    let fixture = TestRepo::new("index-view");
+    fixture.commit_file("tracked.txt", "old line\n", "Initial commit");
+    fixture.write("tracked.txt", "new line\n");
+    open(&fixture);
+
+    let mut ui = TestUi::new(120, 20);
+    index_view::show(&mut ui.siv);
+    assert!(ui.wait_for("+new line"), "{}", ui.screen());
+    assert!(ui.shows("Staged (0)") && ui.shows("Unstaged (1)"), "{}", ui.screen());
+    assert!(ui.shows(" M tracked.txt"), "{}", ui.screen());
+
+    ui.press(vec![Key::Enter]);
+    assert!(ui.shows("Staged (1)") && ui.shows("Unstaged (0)"), "{}", ui.screen());
+    assert!(ui.shows("M  tracked.txt"), "{}", ui.screen());
+    assert!(ui.wait_for("tracked.txt (staged)"), "{}", ui.screen());
+    assert!(ui.wait_for("+new line"), "{}", ui.screen());
+
+    // Unstage the file's only hunk from the diff pane.
+    ui.press(vec![Key::Tab]).press(vec!['u']);
+    assert!(ui.shows("Staged (0)") && ui.shows("Unstaged (1)"), "{}", ui.screen());
+}
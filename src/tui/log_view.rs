@@ -0,0 +1,557 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable};
+use cursive::view::SizeConstraint;
+use cursive::views::{BoxView, Checkbox, Dialog, EditView, IdView, LinearLayout, OnEventView, TextView};
+use git2::Oid;
+use forge::WebTarget;
+use config;
+use dates::{self, DateStyle};
+use export::Table;
+use history::{self, LogEntry, LogFilter, LogSort};
+use identity;
+use large_repo;
+use refs::{self, Decorations, RefLabel};
+use review_marks::ReviewState;
+use rewrite;
+use session::{self, LogColumns};
+use status;
+use text_width::{self, Ellipsis};
+use super::fuzzy_filter::FilterView;
+use super::virtual_list::VirtualList;
+
+const HEADER_ID: &str = "log_view_header";
+const LIST_ID: &str = "log_view_list";
+const REFS_ID: &str = "log_view_refs";
+const REFS_PANEL_ID: &str = "log_view_refs_panel";
+const COLUMN_IDS: [(&str, &str); 4] = [
+    ("log_column_hash", "Hash"),
+    ("log_column_date", "Date"),
+    ("log_column_author", "Author"),
+    ("log_column_refs", "Branches and tags"),
+];
+const FILTER_AUTHOR_ID: &str = "log_filter_author";
+const FILTER_SINCE_ID: &str = "log_filter_since";
+const FILTER_UNTIL_ID: &str = "log_filter_until";
+const FILTER_PATHS_ID: &str = "log_filter_paths";
+
+/// The maximum number of commits loaded into the view at once, unless this
+/// is a large repository or the user has asked for more.
+pub const LOAD_LIMIT: usize = 1000;
+
+/// How much the refs panel grows or shrinks per key press.
+const REFS_PANEL_STEP: usize = 5;
+
+type RefsPanel = BoxView<IdView<FilterView<RefLabel>>>;
+
+thread_local! {
+    static FILTER: RefCell<LogFilter> = RefCell::new(LogFilter::default());
+    // The commit the log is loaded from. None means HEAD.
+    static ANCHOR: RefCell<Option<Oid>> = RefCell::new(None);
+    // Set when the user asks for more commits than the default limit.
+    static LIMIT: Cell<Option<usize>> = Cell::new(None);
+    // Set when the user asks for untracked files to be scanned in a large repository.
+    static SCAN_UNTRACKED: Cell<bool> = Cell::new(false);
+}
+
+/// Shows the log view for the current repository, or refreshes it if it
+/// is already showing.
+pub fn show(siv: &mut Cursive) {
+    if siv.find_id::<VirtualList<LogEntry>>(LIST_ID).is_none() {
+        // The rows are made when the log is loaded, by `refresh`.
+        let mut list = VirtualList::new(|_: &LogEntry| String::new());
+        list.set_on_submit(|s, entry: &LogEntry| super::show_commit(s, entry.oid));
+
+        // The labels are truncated to the panel's width by `refresh`.
+        let mut refs_panel = FilterView::new(|label: &RefLabel| label.to_string());
+        refs_panel.set_on_submit(|s, label: &RefLabel| goto_commit(s, label.target));
+
+        // Side by side panels are hard to follow with a screen reader.
+        let refs_width = if super::screen_reader::is_enabled() { 0 } else { session::current().log_view.refs_panel_width };
+        let refs_panel = BoxView::new(SizeConstraint::Fixed(refs_width), SizeConstraint::Full,
+                                      refs_panel.with_id(REFS_ID));
+
+        let layout = LinearLayout::vertical()
+            .child(TextView::new("").with_id(HEADER_ID))
+            .child(LinearLayout::horizontal()
+                .child(refs_panel.with_id(REFS_PANEL_ID))
+                .child(list.with_id(LIST_ID).full_screen()));
+
+        let view = OnEventView::new(layout)
+            .on_event('f', show_filter_dialog)
+            .on_event('g', prompt_goto)
+            .on_event('o', open_selected_in_browser)
+            .on_event('b', prompt_merge_base)
+            .on_event('c', show_branches_containing)
+            .on_event('w', reword_selected)
+            .on_event('s', |s| {
+                if let Some(oid) = selected_commit(s) {
+                    super::split_dialog::show(s, oid);
+                }
+            })
+            .on_event('r', |s| {
+                if let Some(oid) = selected_commit(s) {
+                    super::review_view::mark(s, oid);
+                }
+            })
+            .on_event('R', super::review_view::export_pending)
+            .on_event('p', |s| {
+                if let Some(oid) = selected_commit(s) {
+                    super::backport_view::show_with(s, &oid.to_string());
+                }
+            })
+            .on_event('v', show_columns_dialog)
+            .on_event('t', toggle_sort)
+            .on_event('[', |s| resize_refs_panel(s, -(REFS_PANEL_STEP as isize)))
+            .on_event(']', |s| resize_refs_panel(s, REFS_PANEL_STEP as isize))
+            .on_event('m', load_more)
+            .on_event('e', export_log)
+            .on_event('E', export_refs)
+            .on_event('u', |s| {
+                SCAN_UNTRACKED.with(|u| u.set(true));
+                refresh(s);
+            })
+            .on_event('q', |s| { s.pop_layer(); });
+
+        super::navigation::open(siv, "Log", view, true);
+    }
+
+    refresh(siv);
+}
+
+/// Reloads the commits in the log view using the current filter, if the view
+/// is open. In a large repository fewer commits are loaded, they are not
+/// decorated with refs and untracked files are not scanned unless the user
+/// asks.
+pub fn refresh(siv: &mut Cursive) {
+    if siv.find_id::<TextView>(HEADER_ID).is_none() {
+        return;
+    }
+
+    let filter = FILTER.with(|f| f.borrow().clone());
+    let anchor = ANCHOR.with(|a| *a.borrow());
+    let scan_untracked = SCAN_UNTRACKED.with(|u| u.get());
+    let large_config = config::current().large_repo.clone();
+    let layout = session::current().log_view.clone();
+    let profiles = config::current().identities.clone();
+
+    let loaded = super::with_current_repo(siv, |repo| -> Result<_, ::git2::Error> {
+        let large = large_repo::is_large(repo, &large_config);
+        let default_limit = if large { large_config.log_limit } else { LOAD_LIMIT };
+        let limit = LIMIT.with(|l| l.get()).unwrap_or(default_limit);
+        let status = if repo.is_bare() {
+            "Bare repository".to_string()
+        } else {
+            status::summary(repo, !large || scan_untracked)?.description()
+        };
+
+        Ok((history::load(repo, anchor, &filter, layout.sort, limit, &super::identity_mailmap(repo))?, refs::list_refs(repo)?,
+            super::ahead_behind_description(repo), status, large, limit, history::unborn_branch(repo),
+            identity::description(repo, &profiles)))
+    });
+
+    let (entries, ref_labels, ahead_behind, status, large, limit, unborn, committer) = match loaded {
+        Some(Ok(loaded)) => loaded,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Load log", Err(e)),
+        None => return,
+    };
+
+    let start = match anchor {
+        Some(oid) => format!("from {:.7}", oid.to_string()),
+        None => "from HEAD".to_string(),
+    };
+    let more = if entries.len() >= limit { " (more available)" } else { "" };
+    let mode = if large { "Large repository mode. " } else { "" };
+    let order = match layout.sort {
+        LogSort::Time => "by date",
+        LogSort::Topological => "in topological order",
+    };
+    let commits = match unborn {
+        Some(ref branch) if anchor.is_none() => format!("No commits yet on '{}'", branch),
+        _ => format!("{} commits {} {}{}", entries.len(), start, order, more),
+    };
+    let header = format!("{}\n{}. {}. {}. {}{}{}\n\
+                          [f] filter  [g] go to ref  [o] open in browser  [b] merge base with  [c] branches containing  \
+                          [w] reword  [s] split  [p] backport  [r] review  [R] export review comments  [v] columns  [t] sort  [ ] refs width  [m] load more  [u] scan untracked  [e] export  [E] export refs  [q] close",
+                         super::navigation::breadcrumb(siv), commits, filter.description(), status, ahead_behind, committer, mode);
+    siv.call_on_id(HEADER_ID, |v: &mut TextView| v.set_content(header));
+    let decorations = if large { Decorations::new() } else { refs::decorations(&ref_labels) };
+    let columns = layout.columns.clone();
+    let reviews = super::review_view::states(siv);
+    siv.call_on_id(LIST_ID, move |v: &mut VirtualList<LogEntry>| {
+        v.set_label(move |entry: &LogEntry| format_entry(entry, &decorations, &reviews, &columns));
+        v.set_items(entries);
+    });
+    // Leave room for the scroll bar.
+    let label_width = layout.refs_panel_width.saturating_sub(2);
+    let ellipsis = config::current().display.path_ellipsis;
+    siv.call_on_id(REFS_ID, move |v: &mut FilterView<RefLabel>| {
+        v.set_label(move |label: &RefLabel| text_width::truncate(&label.to_string(), label_width, ellipsis));
+        v.set_items(ref_labels);
+    });
+}
+
+/// Exports the commits in the log, as filtered, with every column.
+fn export_log(siv: &mut Cursive) {
+    let entries = siv.call_on_id(LIST_ID, |v: &mut VirtualList<LogEntry>| v.items().to_vec()).unwrap_or_default();
+    let decorations = super::with_current_repo(siv, |repo| refs::list_refs(repo))
+        .and_then(Result::ok)
+        .map(|labels| refs::decorations(&labels))
+        .unwrap_or_default();
+    let config = config::current();
+
+    let mut table = Table::new(vec!["commit", "date", "author", "refs", "summary"]);
+    for entry in entries {
+        let labels = decorations.get(&entry.oid).map(|labels| {
+            labels.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", ")
+        });
+        let date = dates::format(&entry.time, DateStyle::Iso, &config.dates);
+        table.add_row(vec![entry.oid.to_string(), date, entry.author, labels.unwrap_or_default(), entry.summary]);
+    }
+    super::export_table(siv, "log.csv", table);
+}
+
+/// Exports the refs in the refs panel, as filtered.
+fn export_refs(siv: &mut Cursive) {
+    let labels = siv.call_on_id(REFS_ID, |v: &mut FilterView<RefLabel>| v.shown()).unwrap_or_default();
+    let mut table = Table::new(vec!["kind", "name", "commit"]);
+    for label in labels {
+        table.add_row(vec![label.kind.to_string(), label.name, label.target.to_string()]);
+    }
+    super::export_table(siv, "refs.csv", table);
+}
+
+/// Asks for a revision expression and moves the selection in the log view to
+/// the commit it names. If that commit is not currently loaded, because it
+/// is beyond the load limit, filtered out or not reachable from HEAD, the
+/// log is reloaded starting from it.
+pub fn prompt_goto(siv: &mut Cursive) {
+    super::dialogs::prompt(siv, "Go to Ref", "", |s, spec| {
+        let oid = match super::with_current_repo(s, |repo| history::resolve_commit(repo, spec)) {
+            Some(Ok(oid)) => oid,
+            Some(Err(e)) => return super::report_result::<::git2::Error>(s, "Go to ref", Err(e)),
+            None => return,
+        };
+
+        goto_commit(s, oid);
+    });
+}
+
+/// Selects `oid` in the log view, re-anchoring the log there if necessary.
+pub fn goto_commit(siv: &mut Cursive, oid: Oid) {
+    if !select_commit(siv, oid) {
+        info!("Commit {} is not loaded, re-anchoring the log view", oid);
+        ANCHOR.with(|a| *a.borrow_mut() = Some(oid));
+        refresh(siv);
+        if !select_commit(siv, oid) {
+            siv.add_layer(Dialog::info(format!("Commit {} is hidden by the current filter.", oid)));
+        }
+    }
+}
+
+/// Doubles the number of commits loaded, keeping the current selection.
+fn load_more(siv: &mut Cursive) {
+    let loaded = siv.call_on_id(LIST_ID, |v: &mut VirtualList<LogEntry>| v.len()).unwrap_or(0);
+    let selected = selected_commit(siv);
+    LIMIT.with(|l| l.set(Some(loaded.max(1) * 2)));
+    refresh(siv);
+    if let Some(oid) = selected {
+        select_commit(siv, oid);
+    }
+}
+
+/// Opens the forge's web page for the selected commit.
+fn open_selected_in_browser(siv: &mut Cursive) {
+    if let Some(oid) = selected_commit(siv) {
+        super::open_in_browser(siv, WebTarget::Commit(oid.to_string()));
+    }
+}
+
+/// Asks for a revision expression and shows the merge base of it and the
+/// selected commit, offering to go to it.
+fn prompt_merge_base(siv: &mut Cursive) {
+    let selected = match selected_commit(siv) {
+        Some(oid) => oid,
+        None => return,
+    };
+
+    super::dialogs::prompt(siv, "Show Merge Base With", "HEAD", move |s, spec| {
+        let base = super::with_current_repo(s, |repo| -> Result<_, ::git2::Error> {
+            let other = history::resolve_commit(repo, spec)?;
+            super::with_commit_graph(repo, |graph| graph.merge_base(repo, selected, other))
+        });
+
+        let base = match base {
+            Some(Ok(base)) => base,
+            Some(Err(e)) => return super::report_result::<::git2::Error>(s, "Find merge base", Err(e)),
+            None => return,
+        };
+
+        match base {
+            Some(base) => s.add_layer(Dialog::text(format!(
+                    "The merge base of {:.7} and '{}' is {}.", selected.to_string(), spec, base))
+                .title("Merge Base")
+                .button("Go To", move |s| {
+                    s.pop_layer();
+                    goto_commit(s, base);
+                })
+                .dismiss_button("Close")),
+            None => s.add_layer(Dialog::info(format!(
+                "{:.7} and '{}' have no history in common.", selected.to_string(), spec))),
+        }
+    });
+}
+
+/// Lists the local and remote-tracking branches that contain the selected
+/// commit. Choosing one goes to its tip.
+fn show_branches_containing(siv: &mut Cursive) {
+    let selected = match selected_commit(siv) {
+        Some(oid) => oid,
+        None => return,
+    };
+
+    let branches = super::with_current_repo(siv, |repo| -> Result<_, ::git2::Error> {
+        let labels = refs::list_refs(repo)?;
+        super::with_commit_graph(repo, |graph| graph.branches_containing(repo, selected, &labels))
+    });
+
+    let branches = match branches {
+        Some(Ok(branches)) => branches,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Find branches containing commit", Err(e)),
+        None => return,
+    };
+
+    if branches.is_empty() {
+        return siv.add_layer(Dialog::info(format!("No branch contains {:.7}.", selected.to_string())));
+    }
+
+    let width = branches.iter().map(|label| text_width::width(&label.to_string())).max().unwrap_or(0);
+    let mut list = FilterView::new(|label: &RefLabel| label.to_string()).always_filtering();
+    list.set_items(branches);
+    list.set_on_submit(|s, label: &RefLabel| {
+        s.pop_layer();
+        goto_commit(s, label.target);
+    });
+
+    siv.add_layer(Dialog::around(list.fixed_width((width + 2).max(30).min(100)).max_height(21))
+        .title(format!("Branches Containing {:.7}", selected.to_string()))
+        .dismiss_button("Close"));
+}
+
+/// Lets the user edit the message of the selected commit, then rewrites the
+/// current branch with the new message. Commits that have been pushed are
+/// refused.
+fn reword_selected(siv: &mut Cursive) {
+    let selected = match selected_commit(siv) {
+        Some(oid) => oid,
+        None => return,
+    };
+
+    let message = super::with_current_repo(siv, |repo| -> Result<_, ::git2::Error> {
+        super::with_commit_graph(repo, |graph| rewrite::check_unpushed(repo, graph, selected))?;
+        let commit = repo.find_commit(selected)?;
+        let message = String::from_utf8_lossy(commit.message_bytes()).into_owned();
+        Ok(message)
+    });
+
+    let message = match message {
+        Some(Ok(message)) => message,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Reword commit", Err(e)),
+        None => return,
+    };
+
+    let title = format!("Reword {:.7}", selected.to_string());
+    super::dialogs::edit_text(siv, &title, &message, move |s, message| {
+        let reworded = super::with_current_repo(s, |repo| {
+            super::with_commit_graph(repo, |graph| rewrite::reword(repo, graph, selected, message))
+        });
+        match reworded {
+            Some(Ok(oid)) => {
+                super::report_result::<::git2::Error>(s, "Reword commit", Ok(format!(
+                    "Reworded {:.7}; it is now {:.7}.", selected.to_string(), oid.to_string())));
+                refresh(s);
+                goto_commit(s, oid);
+            },
+            Some(Err(e)) => super::report_result::<::git2::Error>(s, "Reword commit", Err(e)),
+            None => {},
+        }
+    });
+}
+
+fn selected_commit(siv: &mut Cursive) -> Option<Oid> {
+    siv.call_on_id(LIST_ID, |v: &mut VirtualList<LogEntry>| v.selection().map(|entry| entry.oid))
+        .and_then(|oid| oid)
+}
+
+/// Selects `oid` in the list if it is loaded, returning false if it isn't.
+fn select_commit(siv: &mut Cursive, oid: Oid) -> bool {
+    siv.call_on_id(LIST_ID, |v: &mut VirtualList<LogEntry>| {
+        match v.find(|entry| entry.oid == oid) {
+            Some(i) => {
+                v.set_selection(i);
+                true
+            },
+            None => false,
+        }
+    }).unwrap_or(false)
+}
+
+/// Marked commits get their review indicator at the start of the line; when
+/// any commit is marked the others get a space, to keep the columns lined up.
+fn format_entry(entry: &LogEntry, decorations: &Decorations, reviews: &HashMap<Oid, ReviewState>, columns: &LogColumns) -> String {
+    if super::screen_reader::is_enabled() {
+        return describe_entry(entry, decorations, reviews, columns);
+    }
+
+    let mut line = String::new();
+    if !reviews.is_empty() {
+        line.push(reviews.get(&entry.oid).map_or(' ', |state| state.indicator()));
+        line.push(' ');
+    }
+    if columns.hash {
+        line.push_str(&format!("{:.7} ", entry.oid.to_string()));
+    }
+    if columns.date {
+        let config = config::current();
+        line.push_str(&format!("{} ", dates::format(&entry.time, config.dates.log, &config.dates)));
+    }
+    if columns.author {
+        line.push_str(&text_width::fit(&entry.author, 20, Ellipsis::End));
+        line.push(' ');
+    }
+    if columns.refs {
+        if let Some(labels) = decorations.get(&entry.oid) {
+            line.push_str(&format!("{} ", refs::format_decorations(labels)));
+        }
+    }
+    line.push_str(&entry.summary);
+    line
+}
+
+/// Writes out a log entry as labelled fields, for screen readers.
+fn describe_entry(entry: &LogEntry, decorations: &Decorations, reviews: &HashMap<Oid, ReviewState>, columns: &LogColumns) -> String {
+    let mut fields = Vec::new();
+    if let Some(state) = reviews.get(&entry.oid) {
+        fields.push(state.description().to_string());
+    }
+    if columns.hash {
+        fields.push(format!("Commit {:.7}", entry.oid.to_string()));
+    }
+    if columns.date {
+        let config = config::current();
+        fields.push(format!("Date {}", dates::format(&entry.time, config.dates.log, &config.dates)));
+    }
+    if columns.author {
+        fields.push(format!("Author {}", entry.author));
+    }
+    if columns.refs {
+        if let Some(labels) = decorations.get(&entry.oid) {
+            fields.push(format!("Refs {}", refs::format_decorations(labels)));
+        }
+    }
+    fields.push(format!("Summary {}", entry.summary));
+    fields.join(". ")
+}
+
+/// Lets the user choose which columns are shown for each commit. The choice
+/// is saved in the session file.
+fn show_columns_dialog(siv: &mut Cursive) {
+    let columns = session::current().log_view.columns.clone();
+    let checked = [columns.hash, columns.date, columns.author, columns.refs];
+
+    let mut layout = LinearLayout::vertical();
+    for (&(id, label), &checked) in COLUMN_IDS.iter().zip(checked.iter()) {
+        let mut checkbox = Checkbox::new();
+        checkbox.set_checked(checked);
+        layout.add_child(LinearLayout::horizontal()
+            .child(checkbox.with_id(id))
+            .child(TextView::new(format!(" {}", label))));
+    }
+
+    siv.add_layer(Dialog::around(layout)
+        .title("Log Columns")
+        .button("Apply", |s| {
+            let mut checked = COLUMN_IDS.iter()
+                .map(|&(id, _)| s.call_on_id(id, |v: &mut Checkbox| v.is_checked()).unwrap_or(true));
+            let columns = LogColumns {
+                hash: checked.next().unwrap_or(true),
+                date: checked.next().unwrap_or(true),
+                author: checked.next().unwrap_or(true),
+                refs: checked.next().unwrap_or(true),
+            };
+            s.pop_layer();
+            session::update(|session| session.log_view.columns = columns);
+            refresh(s);
+        })
+        .dismiss_button("Cancel"));
+}
+
+/// Switches between date and topological order, keeping the selection.
+fn toggle_sort(siv: &mut Cursive) {
+    let selected = selected_commit(siv);
+    session::update(|session| {
+        session.log_view.sort = match session.log_view.sort {
+            LogSort::Time => LogSort::Topological,
+            LogSort::Topological => LogSort::Time,
+        };
+    });
+    refresh(siv);
+    if let Some(oid) = selected {
+        select_commit(siv, oid);
+    }
+}
+
+/// Grows or shrinks the refs panel by `delta` columns. Shrinking it to zero
+/// hides it.
+fn resize_refs_panel(siv: &mut Cursive, delta: isize) {
+    let old = session::current().log_view.refs_panel_width;
+    let width = (old as isize + delta).max(0).min(80) as usize;
+    session::update(|session| session.log_view.refs_panel_width = width);
+    siv.call_on_id(REFS_PANEL_ID, |v: &mut RefsPanel| v.set_width(SizeConstraint::Fixed(width)));
+}
+
+fn show_filter_dialog(siv: &mut Cursive) {
+    let filter = FILTER.with(|f| f.borrow().clone());
+    let date_text = |d: Option<::chrono::NaiveDate>| d.map(|d| d.to_string()).unwrap_or_default();
+
+    let field = |label: &str, id: &str, content: String| {
+        LinearLayout::horizontal()
+            .child(TextView::new(format!("{:<8}", label)))
+            .child(EditView::new().content(content).with_id(id).min_width(40))
+    };
+
+    let layout = LinearLayout::vertical()
+        .child(field("Author", FILTER_AUTHOR_ID, filter.author.clone().unwrap_or_default()))
+        .child(field("Since", FILTER_SINCE_ID, date_text(filter.since)))
+        .child(field("Until", FILTER_UNTIL_ID, date_text(filter.until)))
+        .child(field("Paths", FILTER_PATHS_ID, filter.paths.join(" ")))
+        .child(TextView::new("Dates are YYYY-MM-DD. Paths are space-separated globs."));
+
+    siv.add_layer(Dialog::around(layout)
+        .title("Filter Log")
+        .button("Apply", |s| {
+            let text = |s: &mut Cursive, id: &str| {
+                s.call_on_id(id, |v: &mut EditView| v.get_content().to_string()).unwrap_or_default()
+            };
+            let author = text(s, FILTER_AUTHOR_ID);
+            let since = text(s, FILTER_SINCE_ID);
+            let until = text(s, FILTER_UNTIL_ID);
+            let paths = text(s, FILTER_PATHS_ID);
+
+            match LogFilter::parse(&author, &since, &until, &paths) {
+                Ok(filter) => {
+                    s.pop_layer();
+                    FILTER.with(|f| *f.borrow_mut() = filter);
+                    refresh(s);
+                },
+                Err(msg) => s.add_layer(Dialog::info(msg)),
+            }
+        })
+        .button("Clear", |s| {
+            s.pop_layer();
+            FILTER.with(|f| *f.borrow_mut() = LogFilter::default());
+            refresh(s);
+        })
+        .dismiss_button("Cancel"));
+}
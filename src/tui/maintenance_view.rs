@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{Checkbox, Dialog, LinearLayout, TextView};
+use git2::Time;
+use config;
+use dates::{self, DateStyle};
+use error::OafError;
+use maintenance::{self, Report, Task};
+use super::notifications::{self, Level};
+use super::progress_dialog;
+
+fn checkbox_id(task: Task) -> String {
+    format!("maintenance_{:?}", task)
+}
+
+/// Shows the size of the current repository's object database and when
+/// maintenance last ran, with a checkbox for each task to run now.
+pub fn show(siv: &mut Cursive) {
+    let found = super::with_current_repo(siv, |repo| (repo.path().to_path_buf(), maintenance::last_run(repo)));
+    let (path, last_run) = match found {
+        Some(found) => found,
+        None => return,
+    };
+
+    let config = config::current();
+    let last_run = match last_run {
+        Some(seconds) => dates::format(&Time::new(seconds, 0), DateStyle::Relative, &config.dates),
+        None => "never".to_string(),
+    };
+    let schedule = match config.maintenance.interval_days {
+        0 => "no schedule".to_string(),
+        days => format!("scheduled every {} days", days),
+    };
+
+    let stats = maintenance::measure(&path);
+    let mut layout = LinearLayout::vertical()
+        .child(TextView::new(format!("{}\n\nLast run: {} ({}).\n", stats, last_run, schedule)));
+    if let Some(hint) = maintenance::pack_refs_hint(stats.loose_refs, config.maintenance.loose_refs_hint) {
+        layout.add_child(TextView::new(format!("{}\n", hint)));
+    }
+    for &task in Task::all() {
+        let mut checkbox = Checkbox::new();
+        checkbox.set_checked(config.maintenance.tasks.contains(&task));
+        layout.add_child(LinearLayout::horizontal()
+            .child(checkbox.with_id(checkbox_id(task)))
+            .child(TextView::new(format!(" {}", task.description()))));
+    }
+
+    let pack_path = path.clone();
+    siv.add_layer(Dialog::around(layout.max_width(90))
+        .title("Maintenance")
+        .button("Pack Refs", move |s| {
+            s.pop_layer();
+            run(s, pack_path.clone(), vec![Task::PackRefs]);
+        })
+        .button("Run", move |s| {
+            let tasks: Vec<Task> = Task::all().iter().cloned()
+                .filter(|&task| s.call_on_id(&checkbox_id(task), |c: &mut Checkbox| c.is_checked()).unwrap_or(false))
+                .collect();
+            if tasks.is_empty() {
+                return s.add_layer(Dialog::info("Choose at least one task."));
+            }
+            s.pop_layer();
+            run(s, path.clone(), tasks);
+        })
+        .dismiss_button("Close"));
+}
+
+fn run(siv: &mut Cursive, path: PathBuf, tasks: Vec<Task>) {
+    progress_dialog::run(siv, "Maintenance", move |cancel, progress| -> Result<Report, OafError> {
+        maintenance::run(&path, &tasks, cancel, progress)
+    }, |s, result| match result {
+        Ok(report) => show_report(s, &report),
+        Err(e) => progress_dialog::report(s, "Maintenance", Err(e)),
+    });
+}
+
+fn show_report(siv: &mut Cursive, report: &Report) {
+    let title = if report.succeeded() { "Maintenance Done" } else { "Maintenance - Some Tasks Failed" };
+    siv.add_layer(Dialog::around(TextView::new(report.to_string()).scrollable().max_height(20).max_width(100))
+        .title(title)
+        .dismiss_button("Close"));
+}
+
+/// Suggests packing the refs of a repository that has just been opened, if
+/// it has a great many loose ones.
+pub fn hint_if_needed(siv: &mut Cursive, git_dir: &Path) {
+    let threshold = config::current().maintenance.loose_refs_hint;
+    if let Some(hint) = maintenance::pack_refs_hint(maintenance::loose_ref_count(git_dir), threshold) {
+        let name = git_dir.parent().unwrap_or(git_dir).display().to_string();
+        notifications::notify(siv, Level::Info, format!("'{}': {}", name, hint));
+    }
+}
+
+/// Reports a scheduled run: quietly if it went well, as a warning if not.
+pub fn on_scheduled_report(siv: &mut Cursive, path: &Path, result: Result<Report, OafError>) {
+    let name = path.parent().unwrap_or(path).display().to_string();
+    match result {
+        Ok(ref report) if report.succeeded() => {
+            notifications::notify(siv, Level::Info, format!("Scheduled maintenance of '{}': {}", name, report.summary()))
+        },
+        Ok(report) => {
+            let failed: Vec<&str> = report.tasks.iter().filter(|t| t.1.is_err()).map(|t| t.0.description()).collect();
+            notifications::notify(siv, Level::Warning, format!("Scheduled maintenance of '{}' failed: {}.", name, failed.join(", ")))
+        },
+        Err(e) => notifications::notify(siv, Level::Warning, format!("Scheduled maintenance of '{}' failed: {}", name, e)),
+    }
+}
@@ -1,3 +1,7 @@
+use std::cell::RefCell;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use cursive::Cursive;
 //use cursive::align::HAlign;
 //use cursive::traits::*;
@@ -7,16 +11,183 @@ use cursive::Cursive;
 //use cursive::utils::span::SpannedString;
 use cursive::menu::{MenuItem, MenuTree};
 use cursive::event::{Event, Key};
-use repositories::{Repositories, RepositoryExtensions};
+use cursive::views::Dialog;
+use git2::Repository;
+use repositories::{self, Repositories, RepositoryExtensions};
 use mru_list::MruList;
+use network;
 use paths;
+use activity::{self, ActivityScope};
+use autofetch::{self, FetchReport};
+use commit;
+use commit_graph::CommitGraph;
+use config;
+use default_branch;
+use discard;
+use error::OafError;
+use export::{self, Table};
+use error_report::IntoErrorReport;
+use forge::{self, WebTarget};
+use history;
+use ignore_rules;
+use mailmap::Mailmap;
+use maintenance;
+use recent_commits::RecentCommits;
+use refresh;
+use remotes;
+use review_marks::ReviewMarks;
+use rewrite;
+use session;
+use signatures::SignatureCache;
+use signing;
+use self::notifications::Level;
 
-pub fn run_cursive(repos: Repositories) {
+mod backend;
+mod backport_view;
+mod backups_view;
+mod changelog_view;
+mod compare_view;
+#[cfg(all(unix, feature = "control-socket"))]
+mod control;
+mod details;
+mod dialogs;
+mod diff_view;
+mod diffstat_view;
+mod error_dialog;
+mod file_revisions_view;
+mod fuzzy_filter;
+mod gitignore_view;
+mod global_search_view;
+mod identity_view;
+mod in_progress_view;
+mod index_view;
+mod inspect_view;
+#[cfg(test)]
+mod integration_tests;
+mod log_view;
+mod maintenance_view;
+mod navigation;
+mod new_repo_dialog;
+mod notifications;
+mod owners_view;
+mod overview;
+mod palette;
+mod patch_stack_view;
+mod progress_dialog;
+#[cfg(feature = "forge-api")]
+mod pull_requests_view;
+mod push_review_view;
+mod range_diff_view;
+mod recent_commits_view;
+mod ref_editor_view;
+mod release_view;
+mod remotes_view;
+mod repo_tabs;
+mod review_view;
+mod sandbox_view;
+mod screen_reader;
+mod settings_problems;
+mod signing_check;
+mod split_dialog;
+mod split_pane;
+mod sparse_view;
+mod split_view;
+mod sync_view;
+mod terminal;
+#[cfg(test)]
+mod test_backend;
+mod trash_view;
+mod upstream_dialog;
+mod virtual_list;
+
+pub use self::backend::Backend;
+
+/// The number of threads used to open repositories at startup.
+const OPEN_WORKERS: usize = 4;
+
+// Cursive callbacks are plain functions with no way to pass our own state
+// into them, so the open repositories live here for the duration of the UI.
+thread_local! {
+    static REPOS: RefCell<Option<Repositories>> = RefCell::new(None);
+    static SIGNATURES: RefCell<SignatureCache> = RefCell::new(SignatureCache::new());
+    static SHOW_RAW_IDENTITIES: Cell<bool> = Cell::new(false);
+    // Ahead/behind counts from the most recent autofetch, and how far behind
+    // the default branch HEAD is, keyed by .git directory.
+    static AHEAD_BEHIND: RefCell<HashMap<PathBuf, (Option<(usize, usize)>, Option<(String, usize)>)>> = RefCell::new(HashMap::new());
+    // Commit graphs, with their cached query results, keyed by .git directory.
+    static GRAPHS: RefCell<HashMap<PathBuf, CommitGraph>> = RefCell::new(HashMap::new());
+    // The commits whose details were shown. Only loaded by `run_cursive`, so
+    // that tests leave the real file alone.
+    static RECENT_COMMITS: RefCell<Option<RecentCommits>> = RefCell::new(None);
+    // The review marks on commits, loaded like the recent commits.
+    static REVIEW_MARKS: RefCell<Option<ReviewMarks>> = RefCell::new(None);
+    // The watch for changes made outside oaf, once started.
+    static WATCHER: RefCell<Option<refresh::Watcher>> = RefCell::new(None);
+    // The background fetch of the open repositories, once started.
+    static AUTOFETCHER: RefCell<Option<autofetch::Autofetcher>> = RefCell::new(None);
+    // The scheduled maintenance of the open repositories, once started.
+    static MAINTENANCE: RefCell<Option<maintenance::Scheduler>> = RefCell::new(None);
+}
+
+/// How the UI is run, from the command line and the settings.
+pub struct Options {
+    pub backend: Backend,
+    /// Render for screen readers.
+    pub screen_reader: bool,
+    /// The mistakes found in the settings files, shown once the UI is up.
+    pub problems: Vec<config::Problem>,
+    /// Apply changes to the config file as they are saved.
+    pub watch_config: bool,
+    /// Where to listen for requests from other programs, if anywhere. Only
+    /// used when built with the control-socket feature.
+    #[cfg_attr(not(all(unix, feature = "control-socket")), allow(dead_code))]
+    pub control_socket: Option<PathBuf>,
+}
+
+pub fn run_cursive(repos: Repositories, options: Options) {
     // If we managed to open at least 1, display it, else show the opening view.
-    let mut siv = Cursive::default();
+    let mut siv = options.backend.create();
+    screen_reader::set_enabled(options.screen_reader);
+    palette::set_depth(config::current().display.colours);
+    if options.screen_reader {
+        let theme = screen_reader::plain_theme(siv.current_theme());
+        siv.set_theme(theme);
+    }
     create_menu_bar(&mut siv, &repos.mru);
-   
+    let pending = repos.pending().to_vec();
+    REPOS.with(|r| *r.borrow_mut() = Some(repos));
+    RECENT_COMMITS.with(|r| {
+        *r.borrow_mut() = Some(RecentCommits::load(::PATHS.recent_commits_file(), ::chrono::Local::now().timestamp()))
+    });
+    REVIEW_MARKS.with(|r| *r.borrow_mut() = Some(ReviewMarks::load(::PATHS.reviews_file())));
+    repo_tabs::show(&mut siv);
+    settings_problems::show(&mut siv, &options.problems);
+    let review_error = REVIEW_MARKS.with(|r| r.borrow().as_ref().and_then(|m| m.load_error().map(|e| e.to_string())));
+    if let Some(error) = review_error {
+        notifications::notify(&mut siv, Level::Warning, error);
+    }
+    open_pending(&siv, pending);
+    terminal::handle_signals(&siv);
+    terminal::hand_off_for_signing(&siv);
+    if options.watch_config {
+        start_config_watch(&siv);
+    }
+    #[cfg(all(unix, feature = "control-socket"))]
+    {
+        if let Some(ref path) = options.control_socket {
+            control::start(&mut siv, path);
+        }
+    }
+
     siv.run();
+    signing::set_terminal_handoff(None);
+    save_state();
+    #[cfg(all(unix, feature = "control-socket"))]
+    {
+        if let Some(ref path) = options.control_socket {
+            control::stop(path);
+        }
+    }
 
     // let mut select = SelectView::new().h_align(HAlign::Left);
     // for (i, repo) in repos.iter().enumerate() {
@@ -34,6 +205,243 @@ pub fn run_cursive(repos: Repositories) {
     // );
 }
 
+/// Sees that the terminal is restored and the UI state saved if oaf panics.
+pub fn install_panic_hook() {
+    terminal::install_panic_hook(save_state_after_panic);
+}
+
+/// As `save_state`, when the UI thread has panicked. Anything that was
+/// borrowed when the panic happened is skipped, since borrowing it again
+/// would panic inside the panic.
+fn save_state_after_panic() {
+    session::try_flush();
+    REPOS.with(|r| match r.try_borrow_mut() {
+        Ok(mut repos) => if let Some(Err(e)) = repos.as_mut().map(|repos| repos.save_mru()) {
+            warn!("Error writing the MRU file after a panic. Error = {}", e);
+        },
+        Err(_) => warn!("The repositories are in use, not writing the MRU file after a panic"),
+    });
+    RECENT_COMMITS.with(|r| match r.try_borrow() {
+        Ok(recent) => if let Some(Err(e)) = recent.as_ref().map(|recent| recent.save()) {
+            warn!("Error writing the recent commits file after a panic. Error = {}", e);
+        },
+        Err(_) => warn!("The recent commits are in use, not writing them after a panic"),
+    });
+}
+
+/// Writes out the files that record the UI state, as oaf exits.
+fn save_state() {
+    session::flush();
+    with_repos(|repos| {
+        if let Err(e) = repos.save_mru() {
+            warn!("Error writing to MRU file '{}', ignoring. Error = {}", repos.mru.filename().display(), e);
+        }
+    });
+    RECENT_COMMITS.with(|r| {
+        if let Some(Err(e)) = r.borrow().as_ref().map(|recent| recent.save()) {
+            warn!("Error writing the recent commits file, ignoring. Error = {}", e);
+        }
+    });
+}
+
+/// Opens the repositories given on the command line in the background, so
+/// that the UI appears straight away. Each one gets a tab as it is opened;
+/// the background work starts once they are all done.
+fn open_pending(siv: &Cursive, pending: Vec<PathBuf>) {
+    if pending.is_empty() {
+        return start_background_work(siv);
+    }
+
+    let sink = siv.cb_sink().clone();
+    repositories::open_in_background(pending, OPEN_WORKERS, move |path, result| {
+        let _ = sink.send(Box::new(move |s: &mut Cursive| on_repository_opened(s, path, result)));
+    });
+}
+
+fn on_repository_opened(siv: &mut Cursive, path: PathBuf, result: Result<Repository, OafError>) {
+    let git_dir = result.as_ref().ok().map(|repo| repo.path().to_path_buf());
+    let outcome = with_repos(|repos| {
+        let opened = repos.finish_open(&path, result).map(|_| ()).and_then(|_| repos.save_mru());
+        (opened, repos.pending().is_empty())
+    });
+    let (opened, done) = match outcome {
+        Some(outcome) => outcome,
+        None => return,
+    };
+
+    match (opened, git_dir) {
+        (Err(e), _) => error_dialog::show(siv, e.into_report("Open repository")),
+        (Ok(()), Some(git_dir)) => maintenance_view::hint_if_needed(siv, &git_dir),
+        (Ok(()), None) => {},
+    }
+    repo_tabs::refresh(siv);
+    if done {
+        start_background_work(siv);
+    }
+}
+
+/// Starts the watch for outside changes, autofetch and scheduled maintenance
+/// of the open repositories.
+fn start_background_work(siv: &Cursive) {
+    start_repo_watch(siv);
+    start_autofetch(siv);
+    start_maintenance(siv);
+}
+
+/// Starts fetching the open repositories in the background, if the user has
+/// turned autofetch on. Reports are passed back to the UI thread.
+fn start_autofetch(siv: &Cursive) {
+    let paths = with_repos(|repos| repos.iter().map(|r| r.path().to_path_buf()).collect()).unwrap_or_default();
+    let sink = siv.cb_sink().clone();
+    let autofetcher = autofetch::spawn(paths, config::current().autofetch_minutes, move |report| {
+        let _ = sink.send(Box::new(move |s: &mut Cursive| on_autofetch_report(s, report)));
+    });
+    AUTOFETCHER.with(|a| *a.borrow_mut() = Some(autofetcher));
+}
+
+/// Starts running the maintenance tasks on the open repositories when they
+/// are due, if the user has set a schedule. Reports are passed back to the UI
+/// thread.
+fn start_maintenance(siv: &Cursive) {
+    let paths = with_repos(|repos| repos.iter().map(|r| r.path().to_path_buf()).collect()).unwrap_or_default();
+    let sink = siv.cb_sink().clone();
+    let scheduler = maintenance::spawn(paths, move |path, result| {
+        let _ = sink.send(Box::new(move |s: &mut Cursive| maintenance_view::on_scheduled_report(s, &path, result)));
+    });
+    MAINTENANCE.with(|m| *m.borrow_mut() = Some(scheduler));
+}
+
+/// Starts watching the open repositories for changes made outside oaf, such
+/// as by an editor or a build. The views are refreshed once the changes to
+/// the current repository settle down.
+fn start_repo_watch(siv: &Cursive) {
+    let paths = with_repos(|repos| repos.iter().map(|r| r.path().to_path_buf()).collect()).unwrap_or_default();
+    let sink = siv.cb_sink().clone();
+    let watcher = refresh::watch(paths, move |path| {
+        let _ = sink.send(Box::new(move |s: &mut Cursive| on_repository_changed(s, &path)));
+    });
+    WATCHER.with(|w| *w.borrow_mut() = Some(watcher));
+}
+
+/// Adds any repositories opened since the background work started to the
+/// watch, autofetch and maintenance schedule. Until they have started there
+/// is nothing to do, since they start with every open one.
+fn add_open_repositories_to_background_work() {
+    let paths = with_repos(|repos| repos.iter().map(|r| r.path().to_path_buf()).collect::<Vec<_>>()).unwrap_or_default();
+    WATCHER.with(|w| {
+        if let Some(ref watcher) = *w.borrow() {
+            for path in &paths {
+                watcher.add(path);
+            }
+        }
+    });
+    AUTOFETCHER.with(|a| {
+        if let Some(ref autofetcher) = *a.borrow() {
+            for path in &paths {
+                autofetcher.add(path);
+            }
+        }
+    });
+    MAINTENANCE.with(|m| {
+        if let Some(ref scheduler) = *m.borrow() {
+            for path in &paths {
+                scheduler.add(path);
+            }
+        }
+    });
+}
+
+fn on_repository_changed(siv: &mut Cursive, path: &Path) {
+    let current = REPOS.with(|r| r.borrow().as_ref().and_then(|repos| repos.current()).map(|repo| repo.path() == path));
+    if current == Some(true) {
+        refresh_views(siv);
+    }
+}
+
+/// Reloads every open view from the current repository.
+fn refresh_views(siv: &mut Cursive) {
+    repo_tabs::refresh(siv);
+    log_view::refresh(siv);
+    split_view::refresh(siv);
+    index_view::refresh(siv);
+    sync_view::refresh(siv);
+}
+
+fn start_config_watch(siv: &Cursive) {
+    let sink = siv.cb_sink().clone();
+    config::watch(::PATHS.config_file().to_path_buf(), move |config, problems| {
+        let _ = sink.send(Box::new(move |s: &mut Cursive| on_config_changed(s, config, problems)));
+    });
+}
+
+/// Applies a changed config file. Most settings are read each time they are
+/// used, so setting the new config is enough; the colours and offline mode
+/// are applied here, and the user is told about any that need a restart.
+fn on_config_changed(siv: &mut Cursive, new: config::Config, problems: Vec<config::Problem>) {
+    let old = config::current();
+    let restart = config::needs_restart(&old, &new);
+    if new.display.colours != old.display.colours {
+        palette::set_depth(new.display.colours);
+    }
+    if new.offline != old.offline {
+        network::set_offline(new.offline);
+    }
+    config::set(new);
+    log_view::refresh(siv);
+
+    if restart.is_empty() {
+        notifications::notify(siv, Level::Info, "Reloaded the settings.");
+    } else {
+        notifications::notify(siv, Level::Info, format!(
+            "Reloaded the settings. These take effect when oaf is restarted: {}.", restart.join(", ")));
+    }
+    settings_problems::show(siv, &problems);
+}
+
+fn on_autofetch_report(siv: &mut Cursive, report: FetchReport) {
+    let name = report.path.parent().unwrap_or(&report.path).display().to_string();
+
+    if let Some((ref error, retry_in)) = report.error {
+        let message = format!("Autofetch of '{}' failed, retrying in {} minutes: {}", name, (retry_in.as_secs() + 59) / 60, error);
+        return notifications::notify(siv, Level::Warning, message);
+    }
+
+    match (report.ahead_behind, report.behind_default.clone()) {
+        (None, None) => AHEAD_BEHIND.with(|a| a.borrow_mut().remove(&report.path)),
+        counts => AHEAD_BEHIND.with(|a| a.borrow_mut().insert(report.path.clone(), counts)),
+    };
+
+    if let Some(base) = report.upstream_rewritten {
+        let base = match base {
+            Some(oid) => format!("it shares history with the old upstream up to {:.7}", oid.to_string()),
+            None => "it shares no history with the old upstream".to_string(),
+        };
+        notifications::notify(siv, Level::Warning, format!("Upstream of '{}' was force-pushed; {}.", name, base));
+    } else if report.upstream_changed {
+        let (ahead, behind) = report.ahead_behind.unwrap_or((0, 0));
+        notifications::notify(siv, Level::Info, format!(
+            "Upstream of '{}' changed: {} new commits, now {} ahead and {} behind.", name, report.new_commits, ahead, behind));
+    } else if report.new_commits > 0 {
+        notifications::notify(siv, Level::Info, format!("Autofetch found {} new commits in '{}'.", report.new_commits, name));
+    }
+    sync_view::refresh(siv);
+}
+
+/// Describes how far the current branch of `repo` is ahead of and behind its
+/// upstream, and behind the default branch, as of the last autofetch.
+/// Returns an empty string if unknown.
+fn ahead_behind_description(repo: &Repository) -> String {
+    let (upstream, default) = AHEAD_BEHIND.with(|a| a.borrow().get(repo.path()).cloned()).unwrap_or((None, None));
+    let upstream = match upstream {
+        Some((ahead, behind)) => format!("{} ahead, {} behind upstream. ", ahead, behind),
+        None => String::new(),
+    };
+    match default {
+        Some((base, behind)) => format!("{}{} behind {}. ", upstream, behind, base),
+        None => upstream,
+    }
+}
+
 // fn make_menu_leaf(label: &str, callback: F)
 //     where F: 'static + FnMut()
 // {
@@ -61,6 +469,16 @@ fn create_menu_bar(siv: &mut Cursive, mru: &MruList) {
     siv.menubar().add_subtree("Edit", edit_menu);
     let view_menu = create_view_menu(siv);
     siv.menubar().add_subtree("View", view_menu);
+    let repository_menu = create_repository_menu(siv);
+    siv.menubar().add_subtree("Repository", repository_menu);
+    let remote_menu = create_remote_menu(siv);
+    siv.menubar().add_subtree("Remote", remote_menu);
+    let working_tree_menu = create_working_tree_menu(siv);
+    siv.menubar().add_subtree("Working Tree", working_tree_menu);
+    let branch_menu = create_branch_menu(siv);
+    siv.menubar().add_subtree("Branch", branch_menu);
+    let tags_menu = create_tags_menu(siv);
+    siv.menubar().add_subtree("Tags", tags_menu);
 
     siv.set_autohide_menu(false);
     siv.add_global_callback(Key::F10, |s| s.select_menubar());
@@ -78,6 +496,8 @@ fn create_file_menu(siv: &mut Cursive, mru: &MruList) -> MenuTree {
     menu.add_leaf("Open...     A-o", cb_file_open);
     siv.add_global_callback(Event::AltChar('o'), cb_file_open);
     menu.add_leaf("Clone...", cb_file_clone);
+    menu.add_leaf("Recent...   A-r", cb_file_recent);
+    siv.add_global_callback(Event::AltChar('r'), cb_file_recent);
 
     if mru.len() > 0 {
         let mut recent_submenu = MenuTree::new();
@@ -143,21 +563,200 @@ fn create_view_menu(siv: &mut Cursive) -> MenuTree {
     siv.add_global_callback(Event::CtrlChar('m'), cb_view_main);
     menu.add_leaf("Log View       C-l", cb_view_log);
     siv.add_global_callback(Event::CtrlChar('l'), cb_view_log);
+    menu.add_leaf("Go to Ref...   A-g", cb_view_goto_ref);
+    siv.add_global_callback(Event::AltChar('g'), cb_view_goto_ref);
+    menu.add_leaf("Back           A-Left", navigation::back);
+    siv.add_global_callback(Event::Alt(Key::Left), navigation::back);
+    menu.add_leaf("Forward        A-Right", navigation::forward);
+    siv.add_global_callback(Event::Alt(Key::Right), navigation::forward);
+    menu.add_leaf("Log and Diff", split_view::show_log_and_diff);
+    menu.add_leaf("Status and Diff", split_view::show_status_and_diff);
+    menu.add_delimiter();
+    menu.add_leaf("Show Commit...", cb_view_show_commit);
+    menu.add_leaf("Recently Viewed Commits...", recent_commits_view::show);
+    menu.add_leaf("Toggle Raw Identities", cb_view_toggle_raw_identities);
+    menu.add_leaf("Activity...", cb_view_activity);
+    menu.add_leaf("Search All Repositories...", global_search_view::show);
+    menu.add_leaf("Range Diff...", range_diff_view::show);
+    menu.add_leaf("Changelog...", changelog_view::show);
+    menu.add_leaf("Compare...", compare_view::show);
+    menu.add_leaf("Diffstat of Uncommitted Changes...", diffstat_view::show);
+    menu.add_leaf("Incoming and Outgoing...", sync_view::show);
+    menu.add_leaf("Notifications...  A-h", notifications::show_history);
+    siv.add_global_callback(Event::AltChar('h'), notifications::show_history);
+
+    menu
+}
+
+fn create_repository_menu(_siv: &mut Cursive) -> MenuTree {
+    let mut menu = MenuTree::new();
+
+    menu.add_leaf("Inspect Object...", inspect_view::show);
+    menu.add_leaf("Maintenance...", maintenance_view::show);
+    menu.add_leaf("Refs...", ref_editor_view::show);
+
+    menu
+}
+
+fn create_remote_menu(siv: &mut Cursive) -> MenuTree {
+    let mut menu = MenuTree::new();
+
+    menu.add_leaf("Fetch...       A-f", cb_remote_fetch);
+    siv.add_global_callback(Event::AltChar('f'), cb_remote_fetch);
+    menu.add_leaf("Push           A-p", cb_remote_push);
+    siv.add_global_callback(Event::AltChar('p'), cb_remote_push);
+    menu.add_leaf("Push to Several Remotes...", cb_remote_push_several);
+    menu.add_delimiter();
+    menu.add_leaf("Delete Remote Branch...", cb_remote_delete_branch);
+    menu.add_leaf("Prune...", cb_remote_prune);
+    menu.add_leaf("Update Default Branch...", cb_remote_update_default_branch);
+    menu.add_leaf("Remotes...", remotes_view::show);
+    menu.add_delimiter();
+    menu.add_leaf("Open in Browser...", cb_remote_open_in_browser);
+    menu.add_delimiter();
+    menu.add_leaf("Toggle Offline Mode", cb_remote_toggle_offline);
+    #[cfg(feature = "forge-api")]
+    menu.add_leaf("Pull Requests...", pull_requests_view::show);
+
+    menu
+}
+
+fn create_working_tree_menu(siv: &mut Cursive) -> MenuTree {
+    let mut menu = MenuTree::new();
+
+    menu.add_leaf("Commit...", cb_working_tree_commit);
+    menu.add_leaf("Commit All Tracked...  A-c", cb_working_tree_commit_all_tracked);
+    siv.add_global_callback(Event::AltChar('c'), cb_working_tree_commit_all_tracked);
+    menu.add_leaf("Identity...", identity_view::show);
+    menu.add_leaf("Operation in Progress...", in_progress_view::show_from_menu);
+    menu.add_delimiter();
+    menu.add_leaf("Discard Changes...", cb_working_tree_discard);
+    menu.add_leaf("Delete Untracked Files...", cb_working_tree_delete_untracked);
+    menu.add_leaf("Why Is It Ignored?...", cb_working_tree_why_ignored);
+    menu.add_leaf("Add .gitignore Template...", gitignore_view::show);
+    menu.add_leaf("File Info...", cb_working_tree_file_info);
+    menu.add_leaf("File at Revision...", file_revisions_view::show);
+    menu.add_delimiter();
+    menu.add_leaf("Trash...", trash_view::show);
+    menu.add_leaf("Sparse Checkout...", sparse_view::show);
+
+    menu
+}
+
+fn create_branch_menu(_siv: &mut Cursive) -> MenuTree {
+    let mut menu = MenuTree::new();
+
+    menu.add_leaf("Squash onto Base...", cb_branch_squash);
+    menu.add_leaf("Patch Stack...", patch_stack_view::show);
+    menu.add_leaf("Backport Commits...", backport_view::show);
+    menu.add_leaf("Merge in Sandbox...", sandbox_view::merge);
+    menu.add_leaf("Rebase in Sandbox...", sandbox_view::rebase);
+    menu.add_delimiter();
+    menu.add_leaf("Backups...", backups_view::show);
+    menu.add_leaf("Clean Up Backups...", backups_view::clean_up);
+
+    menu
+}
+
+fn create_tags_menu(_siv: &mut Cursive) -> MenuTree {
+    let mut menu = MenuTree::new();
+
+    menu.add_leaf("Show Tag...", cb_tags_show);
+    menu.add_leaf("Create Release...", release_view::show);
+    menu.add_delimiter();
+    menu.add_leaf("Push Tag...", cb_tags_push_tag);
+    menu.add_leaf("Push All Tags...", cb_tags_push_all);
 
     menu
 }
 
+/// Runs `f` against the current repository. If no repositories are open
+/// the user is told so and `f` is not called.
+fn with_current_repo<F, R>(siv: &mut Cursive, f: F) -> Option<R>
+    where F: FnOnce(&Repository) -> R
+{
+    let result = REPOS.with(|r| {
+        r.borrow().as_ref().and_then(|repos| repos.current()).map(f)
+    });
+
+    if result.is_none() {
+        notifications::notify(siv, Level::Warning, "No repository is open.");
+    }
+
+    result
+}
+
+/// Runs `f` against the commit graph of `repo`, loading it the first time
+/// it is needed. The graph is kept for the rest of the session.
+fn with_commit_graph<F, R>(repo: &Repository, f: F) -> R
+    where F: FnOnce(&mut CommitGraph) -> R
+{
+    GRAPHS.with(|g| {
+        let mut graphs = g.borrow_mut();
+        let graph = graphs.entry(repo.path().to_path_buf()).or_insert_with(|| CommitGraph::new(repo));
+        f(graph)
+    })
+}
+
+/// Runs `f` against the set of open repositories.
+fn with_repos<F, R>(f: F) -> Option<R>
+    where F: FnOnce(&mut Repositories) -> R
+{
+    REPOS.with(|r| r.borrow_mut().as_mut().map(f))
+}
+
+/// Asks where to export `table`, the content of a view, and writes it there.
+/// The extension of the file chooses the format.
+fn export_table(siv: &mut Cursive, default_file: &str, table: Table) {
+    dialogs::prompt(siv, "Export to a .txt, .csv or .json File", default_file, move |s, file| {
+        let path = paths::expand_tilde(file);
+        let result = export::write(&path, &table)
+            .map(|format| format!("Exported {} rows to '{}' as {}.", table.rows.len(), path.display(), format));
+        report_result(s, "Export", result);
+    });
+}
+
+/// Reports the outcome of `operation`: success as a notification, failure
+/// in an error dialog with the details of what went wrong.
+fn report_result<E>(siv: &mut Cursive, operation: &str, result: Result<String, E>)
+    where E: IntoErrorReport
+{
+    match result {
+        Ok(msg) => notifications::notify(siv, Level::Info, msg),
+        Err(e) => error_dialog::show(siv, e.into_report(operation)),
+    }
+}
+
 // Callbacks for each menu item.
-fn cb_file_new(_siv: &mut Cursive) {
+fn cb_file_new(siv: &mut Cursive) {
     info!("cb_file_new invoked...");
+    new_repo_dialog::show(siv);
 }
 
 fn cb_file_open(_siv: &mut Cursive) {
     info!("cb_file_open invoked...");
 }
 
-fn cb_file_clone(_siv: &mut Cursive) {
+/// Lets the user pick a recently used repository to open.
+fn cb_file_recent(siv: &mut Cursive) {
+    let recent = with_repos(|repos| repos.mru.iter().map(|p| paths::to_canon(p).display().to_string()).collect())
+        .unwrap_or_default();
+
+    dialogs::choose(siv, "Open Recent Repository", recent, |s, choice| {
+        let path = PathBuf::from(choice);
+        let result = repositories::open_repository(&path);
+        let opened = with_repos(|repos| repos.finish_open(&path, result).map(|_| ()).and_then(|_| repos.save_mru()));
+        if let Some(Err(e)) = opened {
+            error_dialog::show(s, e.into_report("Open repository"));
+        }
+        add_open_repositories_to_background_work();
+        repo_tabs::refresh(s);
+    });
+}
+
+fn cb_file_clone(siv: &mut Cursive) {
     info!("cb_file_clone invoked...");
+    new_repo_dialog::clone(siv);
 }
 
 fn cb_file_exit(_siv: &mut Cursive) {
@@ -189,22 +788,738 @@ fn cb_edit_select_all(_siv: &mut Cursive) {
     info!("cb_edit_select_all invoked...");
 }
 
-fn cb_view_refresh(_siv: &mut Cursive) {
+fn cb_view_refresh(siv: &mut Cursive) {
     info!("cb_view_refresh invoked...");
+    refresh_views(siv);
 }
 
-fn cb_view_next_repo(_siv: &mut Cursive) {
+fn cb_view_next_repo(siv: &mut Cursive) {
     info!("cb_view_next_repo invoked...");
+    with_repos(|repos| repos.select_next());
+    repo_tabs::refresh(siv);
 }
 
-fn cb_view_previous_repo(_siv: &mut Cursive) {
+fn cb_view_previous_repo(siv: &mut Cursive) {
     info!("cb_view_previous_repo invoked...");
+    with_repos(|repos| repos.select_previous());
+    repo_tabs::refresh(siv);
 }
 
 fn cb_view_main(_siv: &mut Cursive) {
     info!("cb_view_main invoked...");
 }
 
-fn cb_view_log(_siv: &mut Cursive) {
+fn cb_view_log(siv: &mut Cursive) {
     info!("cb_view_log invoked...");
+    log_view::show(siv);
+}
+
+fn cb_remote_fetch(siv: &mut Cursive) {
+    info!("cb_remote_fetch invoked...");
+
+    choose_remote(siv, "Fetch From", |s, remote| {
+        let remote = remote.to_string();
+        let modes = remotes::TagFetch::all().iter().map(|t| t.description().to_string()).collect();
+        dialogs::choose(s, "Fetch Tags", modes, move |s, mode| {
+            let tags = *remotes::TagFetch::all().iter().find(|t| t.description() == mode).unwrap();
+            let path = match with_current_repo(s, |repo| repo.path().to_path_buf()) {
+                Some(path) => path,
+                None => return,
+            };
+            let remote = remote.clone();
+            let title = format!("Fetch from '{}'", remote);
+            progress_dialog::run(s, &title, move |cancel, progress| -> Result<String, OafError> {
+                let repo = Repository::open(&path)?;
+                let fetch = || remotes::fetch_cancellable(&repo, &remote, tags, cancel, |p| progress(p.to_string()));
+                let fetched = network::with_retries(&config::current().retry, cancel, fetch, |retry| progress(retry.to_string())).map(|n| match n {
+                    0 => format!("Fetched from '{}', no new commits.", remote),
+                    1 => format!("Fetched from '{}', 1 new commit.", remote),
+                    n => format!("Fetched from '{}', {} new commits.", remote, n),
+                })?;
+                progress("Checking the default branch...".to_string());
+                match default_branch::detect(&repo, &remote) {
+                    Ok(detected) => Ok(match detected.advice() {
+                        Some(advice) => format!("{} {}", fetched, advice),
+                        None => fetched,
+                    }),
+                    Err(e) => {
+                        warn!("Cannot find the default branch of '{}', ignoring. Error = {}", remote, e);
+                        Ok(fetched)
+                    },
+                }
+            }, |s, result| {
+                progress_dialog::report(s, "Fetch", result);
+                sync_view::refresh(s);
+            });
+        });
+    });
+}
+
+fn cb_remote_push(siv: &mut Cursive) {
+    info!("cb_remote_push invoked...");
+
+    let state = with_current_repo(siv, |repo| {
+        let branch = remotes::current_branch_name(repo)?;
+        let upstream = remotes::upstream_of(repo, &branch);
+        let default = remotes::default_upstream(repo, &branch);
+        Some((branch, upstream, default, remotes::remote_names(repo)))
+    });
+
+    match state {
+        None => {},
+        Some(None) => siv.add_layer(Dialog::info("HEAD is not on a branch, there is nothing to push.")),
+        Some(Some((branch, Some(upstream), _, _))) => push_to_upstream(siv, &branch, &upstream, false),
+        Some(Some((_, None, None, _))) => siv.add_layer(Dialog::info("The repository has no remotes to push to.")),
+        Some(Some((branch, None, Some(default), remote_names))) => {
+            let local_branch = branch.clone();
+            upstream_dialog::show(siv, &branch, &remote_names, &default, move |s, upstream| {
+                push_to_upstream(s, &local_branch, &upstream, true);
+            });
+        }
+    }
+}
+
+/// Pushes `branch` once the user has reviewed what will be sent and, if they
+/// want to see that, who owns the files it changes.
+fn push_to_upstream(siv: &mut Cursive, branch: &str, upstream: &remotes::UpstreamSpec, set_upstream: bool) {
+    let (local_branch, spec) = (branch.to_string(), upstream.clone());
+    push_review_view::show(siv, branch, upstream, move |s| {
+        let changed_files = |repo: &Repository| {
+            remotes::outgoing_commits(repo, &local_branch, &spec).and_then(|commits| remotes::files_changed_by(repo, &commits))
+        };
+        let (local_branch, spec) = (local_branch.clone(), spec.clone());
+        owners_view::confirm(s, "Push", changed_files, move |s| push_now(s, &local_branch, &spec, set_upstream));
+    });
+}
+
+/// Pushes in the background, retrying if the connection times out or drops.
+fn push_now(siv: &mut Cursive, branch: &str, upstream: &remotes::UpstreamSpec, set_upstream: bool) {
+    let path = match with_current_repo(siv, |repo| repo.path().to_path_buf()) {
+        Some(path) => path,
+        None => return,
+    };
+    let (branch, upstream) = (branch.to_string(), upstream.clone());
+    let title = format!("Push to '{}/{}'", upstream.remote, upstream.branch);
+    progress_dialog::run(siv, &title, move |cancel, progress| -> Result<String, OafError> {
+        let repo = Repository::open(&path)?;
+        progress(format!("Pushing '{}'...", branch));
+        network::with_retries(&config::current().retry, cancel,
+                              || remotes::push_branch(&repo, &branch, &upstream),
+                              |retry| progress(retry.to_string()))?;
+        if set_upstream {
+            remotes::set_upstream(&repo, &branch, &upstream)?;
+        }
+        Ok(format!("Pushed '{}' to '{}/{}'.", branch, upstream.remote, upstream.branch))
+    }, |s, result| progress_dialog::report(s, "Push", result));
+}
+
+/// Runs `push` in the background, retrying it as `push_now` does, and
+/// reports `done` once it succeeds.
+fn push_in_background<F>(siv: &mut Cursive, title: &str, operation: &'static str, done: String, push: F)
+    where F: Fn(&Repository) -> Result<(), OafError> + Send + 'static
+{
+    let path = match with_current_repo(siv, |repo| repo.path().to_path_buf()) {
+        Some(path) => path,
+        None => return,
+    };
+    let starting = format!("{}...", title);
+    progress_dialog::run(siv, title, move |cancel, progress| -> Result<String, OafError> {
+        let repo = Repository::open(&path)?;
+        progress(starting);
+        network::with_retries(&config::current().retry, cancel, || push(&repo), |retry| progress(retry.to_string()))?;
+        Ok(done)
+    }, move |s, result| progress_dialog::report(s, operation, result));
+}
+
+/// Pushes the current branch to each of the remotes in the repository's
+/// 'oaf.pushRemotes' setting, once the user has reviewed what each push will
+/// send, then sums up how each push went.
+fn cb_remote_push_several(siv: &mut Cursive) {
+    info!("cb_remote_push_several invoked...");
+
+    let state = with_current_repo(siv, |repo| {
+        (remotes::current_branch_name(repo), remotes::push_targets(repo), repo.path().to_path_buf())
+    });
+    let (branch, targets, path) = match state {
+        Some((Some(branch), targets, path)) => (branch, targets, path),
+        Some((None, _, _)) => return siv.add_layer(Dialog::info("HEAD is not on a branch, there is nothing to push.")),
+        None => return,
+    };
+    if targets.is_empty() {
+        return siv.add_layer(Dialog::info(
+            "There are no remotes to push to. List them in the repository's git config setting 'oaf.pushRemotes', \
+             e.g. 'git config oaf.pushRemotes \"origin mirror\"'."));
+    }
+
+    let (local_branch, remotes) = (branch.clone(), targets.clone());
+    push_review_view::show_several(siv, &local_branch, &remotes, move |s| {
+        let (branch, targets, path) = (branch.clone(), targets.clone(), path.clone());
+        progress_dialog::run(s, "Push to Several Remotes", move |cancel, progress| -> Result<String, OafError> {
+            let repo = Repository::open(&path)?;
+            let mut results = Vec::new();
+            for (i, remote) in targets.iter().enumerate() {
+                progress(format!("Pushing to '{}' ({} of {})...", remote, i + 1, targets.len()));
+                let upstream = remotes::UpstreamSpec::new(remote.as_str(), branch.as_str());
+                let result = network::with_retries(&config::current().retry, cancel,
+                                                   || remotes::push_branch(&repo, &branch, &upstream),
+                                                   |retry| progress(format!("'{}': {}", remote, retry)));
+                let cancelled = cancel.is_cancelled();
+                results.push((remote.clone(), result));
+                if cancelled {
+                    break;
+                }
+            }
+            Ok(remotes::describe_push_results(&branch, &results))
+        }, |s, result| match result {
+            Ok(summary) => s.add_layer(Dialog::info(summary).title("Push to Several Remotes")),
+            Err(e) => progress_dialog::report(s, "Push", Err(e)),
+        });
+    });
+}
+
+fn cb_remote_delete_branch(siv: &mut Cursive) {
+    info!("cb_remote_delete_branch invoked...");
+
+    let branches = match with_current_repo(siv, remotes::remote_tracking_branches) {
+        Some(Ok(branches)) => branches,
+        Some(Err(e)) => return report_result::<::git2::Error>(siv, "List remote branches", Err(e)),
+        None => return,
+    };
+
+    dialogs::choose(siv, "Delete Remote Branch", branches, |s, tracking_name| {
+        let parts = with_current_repo(s, |repo| remotes::split_tracking_name(repo, tracking_name));
+        let (remote, branch) = match parts {
+            Some(Some(parts)) => parts,
+            _ => return,
+        };
+
+        let question = format!("Delete the branch '{}' on the remote '{}'?\nThis cannot be undone.", branch, remote);
+        dialogs::confirm(s, "Delete Remote Branch", question, move |s| {
+            let (remote, branch) = (remote.clone(), branch.clone());
+            let title = format!("Delete '{}' on '{}'", branch, remote);
+            let done = format!("Deleted '{}' on '{}'.", branch, remote);
+            push_in_background(s, &title, "Delete remote branch", done,
+                               move |repo| remotes::delete_remote_branch(repo, &remote, &branch));
+        });
+    });
+}
+
+/// Asks the remote what its default branch is and records it as
+/// '<remote>/HEAD', so that it is used as the default base.
+fn cb_remote_update_default_branch(siv: &mut Cursive) {
+    info!("cb_remote_update_default_branch invoked...");
+
+    choose_remote(siv, "Update Default Branch of", |s, remote| {
+        let path = match with_current_repo(s, |repo| repo.path().to_path_buf()) {
+            Some(path) => path,
+            None => return,
+        };
+        let remote = remote.to_string();
+        let title = format!("Default branch of '{}'", remote);
+        progress_dialog::run(s, &title, move |_, _| -> Result<String, OafError> {
+            let repo = Repository::open(&path)?;
+            let detected = default_branch::detect(&repo, &remote)?;
+            match detected.actual {
+                None => Ok(format!("'{}' has no default branch.", remote)),
+                Some(ref actual) if !detected.changed() => Ok(format!("The default branch of '{}' is still '{}'.", remote, actual)),
+                Some(ref actual) => {
+                    default_branch::update(&repo, &remote, actual)?;
+                    Ok(format!("The default branch of '{}' is now recorded as '{}'.", remote, actual))
+                },
+            }
+        }, |s, result| {
+            progress_dialog::report(s, "Update default branch", result);
+            log_view::refresh(s);
+        });
+    });
+}
+
+/// Asks each remote, in the background, which of its remote-tracking
+/// branches no longer exist on it, then offers to delete them. A remote that
+/// cannot be asked is skipped and reported rather than stopping the others.
+fn cb_remote_prune(siv: &mut Cursive) {
+    info!("cb_remote_prune invoked...");
+
+    let path = match with_current_repo(siv, |repo| repo.path().to_path_buf()) {
+        Some(path) => path,
+        None => return,
+    };
+
+    progress_dialog::run(siv, "Find Stale Refs", move |cancel, progress| -> Result<(Vec<String>, Vec<String>), OafError> {
+        let repo = Repository::open(&path)?;
+        let names = remotes::remote_names(&repo);
+        let (mut stale, mut skipped) = (Vec::new(), Vec::new());
+        for (i, remote) in names.iter().enumerate() {
+            cancel.check()?;
+            progress(format!("Asking '{}' for its refs ({} of {})...", remote, i + 1, names.len()));
+            match remotes::stale_tracking_refs(&repo, remote) {
+                Ok(refs) => stale.extend(refs),
+                Err(e) => {
+                    warn!("Cannot find the stale refs of '{}': {}", remote, e);
+                    skipped.push(format!("{}: {}", remote, e));
+                },
+            }
+        }
+        Ok((stale, skipped))
+    }, |s, result| match result {
+        Ok((stale, skipped)) => confirm_prune(s, stale, &skipped),
+        Err(e) => progress_dialog::report(s, "Find stale refs", Err(e)),
+    });
+}
+
+fn confirm_prune(siv: &mut Cursive, stale: Vec<String>, skipped: &[String]) {
+    let skipped = if skipped.is_empty() {
+        String::new()
+    } else {
+        format!("\n\nThese remotes could not be asked, so were skipped:\n\n{}", skipped.join("\n"))
+    };
+
+    if stale.is_empty() {
+        siv.add_layer(Dialog::info(format!("There are no stale remote-tracking branches.{}", skipped)));
+        return;
+    }
+
+    let question = format!("The following refs no longer exist on their remote and will be removed:\n\n{}{}",
+                           stale.join("\n"), skipped);
+    dialogs::confirm(siv, "Prune", question, move |s| {
+        let result = with_current_repo(s, |repo| {
+            remotes::prune_refs(repo, &stale).map(|_| format!("Pruned {} refs.", stale.len()))
+        });
+        if let Some(result) = result {
+            report_result(s, "Prune refs", result);
+        }
+    });
+}
+
+fn cb_view_goto_ref(siv: &mut Cursive) {
+    info!("cb_view_goto_ref invoked...");
+    log_view::show(siv);
+    log_view::prompt_goto(siv);
+}
+
+fn cb_view_show_commit(siv: &mut Cursive) {
+    info!("cb_view_show_commit invoked...");
+
+    dialogs::prompt(siv, "Show Commit", "HEAD", |s, spec| {
+        let commit = with_current_repo(s, |repo| history::resolve_commit(repo, spec));
+
+        match commit {
+            Some(Ok(oid)) => show_commit(s, oid),
+            Some(Err(e)) => report_result::<::git2::Error>(s, "Show commit", Err(e)),
+            None => {},
+        }
+    });
+}
+
+/// Shows the details dialog for a commit in the current repository.
+fn show_commit(siv: &mut Cursive, oid: ::git2::Oid) {
+    REPOS.with(|r| {
+        if let Some(repo) = r.borrow().as_ref().and_then(|repos| repos.current()) {
+            let mailmap = identity_mailmap(repo);
+            details::show_commit(siv, repo, oid, &mailmap);
+            record_recent_commit(repo, oid);
+        }
+    });
+}
+
+fn record_recent_commit(repo: &Repository, oid: ::git2::Oid) {
+    let summary = repo.find_commit(oid).ok().and_then(|c| c.summary().map(|s| s.to_string())).unwrap_or_default();
+    RECENT_COMMITS.with(|r| {
+        if let Some(ref mut recent) = *r.borrow_mut() {
+            recent.record(repo.path(), oid, &summary, ::chrono::Local::now().timestamp());
+        }
+    });
+}
+
+/// Makes the repository at `path` current, opening it first if need be.
+/// Returns false, having reported why, if it cannot be opened.
+fn select_or_open(siv: &mut Cursive, path: &Path) -> bool {
+    let selected = with_repos(|repos| -> Result<(), OafError> {
+        if repos.select(path) {
+            return Ok(());
+        }
+        let opened = repositories::open_repository(path);
+        repos.finish_open(path, opened).map(|_| ()).and_then(|_| repos.save_mru())?;
+        repos.select(path);
+        Ok(())
+    });
+    match selected {
+        Some(Ok(())) => {
+            add_open_repositories_to_background_work();
+            repo_tabs::refresh(siv);
+            true
+        },
+        Some(Err(e)) => {
+            error_dialog::show(siv, e.into_report("Open repository"));
+            false
+        },
+        None => false,
+    }
+}
+
+fn show_file_info(siv: &mut Cursive, path: &Path) {
+    REPOS.with(|r| {
+        if let Some(repo) = r.borrow().as_ref().and_then(|repos| repos.current()) {
+            details::show_file(siv, repo, path);
+        }
+    });
+}
+
+/// Returns the mailmap to use when displaying identities. When the user has
+/// asked to see raw identities this is an empty mailmap, which maps nothing.
+fn identity_mailmap(repo: &Repository) -> Mailmap {
+    if SHOW_RAW_IDENTITIES.with(|r| r.get()) {
+        Mailmap::default()
+    } else {
+        Mailmap::load(repo)
+    }
+}
+
+fn cb_view_toggle_raw_identities(siv: &mut Cursive) {
+    info!("cb_view_toggle_raw_identities invoked...");
+
+    let raw = SHOW_RAW_IDENTITIES.with(|r| {
+        r.set(!r.get());
+        r.get()
+    });
+
+    let msg = if raw {
+        "Identities will be shown exactly as recorded in commits."
+    } else {
+        "Identities will be mapped using .mailmap."
+    };
+    log_view::refresh(siv);
+    siv.add_layer(Dialog::info(msg));
+}
+
+fn cb_view_activity(siv: &mut Cursive) {
+    info!("cb_view_activity invoked...");
+
+    let scopes = vec!["Current branch".to_string(), "All branches and tags".to_string()];
+    dialogs::choose(siv, "Activity", scopes, |s, choice| {
+        let scope = if choice.starts_with("Current") { ActivityScope::CurrentBranch } else { ActivityScope::AllRefs };
+        let weeks = 52;
+        let today = ::chrono::Local::today().naive_local();
+        let since = today - ::chrono::Duration::weeks(weeks as i64);
+
+        let (path, mailmap) = match with_current_repo(s, |repo| (repo.path().to_path_buf(), identity_mailmap(repo))) {
+            Some(current) => current,
+            None => return,
+        };
+        let title = format!("Activity - {}", choice);
+        progress_dialog::run(s, "Counting Commits", move |cancel, _| -> Result<String, OafError> {
+            let repo = Repository::open(&path)?;
+            activity::gather(&repo, scope, since, &mailmap, cancel).map(|activity| activity::render(&activity, today, weeks))
+        }, move |s, text| match text {
+            Ok(text) => s.add_layer(Dialog::text(text).title(title).dismiss_button("Close")),
+            Err(e) => progress_dialog::report(s, "Show activity", Err(e)),
+        });
+    });
+}
+
+fn cb_tags_show(siv: &mut Cursive) {
+    info!("cb_tags_show invoked...");
+
+    let tags = match with_current_repo(siv, remotes::tag_names) {
+        Some(Ok(tags)) => tags,
+        Some(Err(e)) => return report_result::<::git2::Error>(siv, "List tags", Err(e)),
+        None => return,
+    };
+
+    dialogs::choose(siv, "Show Tag", tags, |s, tag| {
+        REPOS.with(|r| {
+            if let Some(repo) = r.borrow().as_ref().and_then(|repos| repos.current()) {
+                let mailmap = identity_mailmap(repo);
+                details::show_tag(s, repo, tag, &mailmap);
+            }
+        });
+    });
+}
+
+fn cb_tags_push_tag(siv: &mut Cursive) {
+    info!("cb_tags_push_tag invoked...");
+
+    let tags = match with_current_repo(siv, remotes::tag_names) {
+        Some(Ok(tags)) => tags,
+        Some(Err(e)) => return report_result::<::git2::Error>(siv, "List tags", Err(e)),
+        None => return,
+    };
+
+    dialogs::choose(siv, "Push Tag", tags, |s, tag| {
+        let tag = tag.to_string();
+        choose_remote(s, "Push Tag To", move |s, remote| {
+            let (remote, tag) = (remote.to_string(), tag.clone());
+            let title = format!("Push Tag '{}' to '{}'", tag, remote);
+            let done = format!("Pushed tag '{}' to '{}'.", tag, remote);
+            push_in_background(s, &title, "Push tag", done, move |repo| remotes::push_tag(repo, &remote, &tag));
+        });
+    });
+}
+
+fn cb_tags_push_all(siv: &mut Cursive) {
+    info!("cb_tags_push_all invoked...");
+
+    choose_remote(siv, "Push All Tags To", |s, remote| {
+        let remote = remote.to_string();
+        let title = format!("Push All Tags to '{}'", remote);
+        let done = format!("Pushed all tags to '{}'.", remote);
+        push_in_background(s, &title, "Push all tags", done, move |repo| remotes::push_all_tags(repo, &remote));
+    });
+}
+
+fn cb_working_tree_discard(siv: &mut Cursive) {
+    info!("cb_working_tree_discard invoked...");
+
+    let files = match with_current_repo(siv, discard::modified_files) {
+        Some(Ok(files)) => files,
+        Some(Err(e)) => return report_result::<OafError>(siv, "List modified files", Err(e)),
+        None => return,
+    };
+
+    let names = files.iter().map(|f| f.display().to_string()).collect();
+    dialogs::choose(siv, "Discard Changes To", names, |s, file| {
+        let result = with_current_repo(s, |repo| {
+            discard::discard_changes(repo, ::PATHS.trash_dir(), Path::new(file))
+                .map(|_| format!("Discarded the changes to '{}'. The old file is in the trash.", file))
+        });
+        if let Some(result) = result {
+            report_result(s, "Discard changes", result);
+        }
+    });
+}
+
+fn cb_working_tree_delete_untracked(siv: &mut Cursive) {
+    info!("cb_working_tree_delete_untracked invoked...");
+
+    let count = match with_current_repo(siv, discard::untracked_files) {
+        Some(Ok(files)) => files.len(),
+        Some(Err(e)) => return report_result::<OafError>(siv, "List untracked files", Err(e)),
+        None => return,
+    };
+
+    if count == 0 {
+        return notifications::notify(siv, Level::Info, "There are no untracked files.");
+    }
+
+    let text = format!("Move {} untracked files to the trash?", count);
+    dialogs::confirm(siv, "Delete Untracked Files", text, |s| {
+        let result = with_current_repo(s, |repo| {
+            discard::delete_untracked(repo, ::PATHS.trash_dir())
+                .map(|n| format!("Moved {} untracked files to the trash.", n))
+        });
+        if let Some(result) = result {
+            report_result(s, "Delete untracked files", result);
+        }
+    });
+}
+
+fn cb_working_tree_commit(siv: &mut Cursive) {
+    info!("cb_working_tree_commit invoked...");
+
+    if config::current().commit_all_tracked {
+        return cb_working_tree_commit_all_tracked(siv);
+    }
+    if !identity_view::apply(siv) {
+        return;
+    }
+    owners_view::confirm(siv, "Commit", commit::staged_files, |s| {
+        signing_check::confirm(s, |s, title| {
+            dialogs::edit_text(s, title, "", |s, message| {
+                let result = with_current_repo(s, |repo| {
+                    commit::commit_index(repo, message).map(|oid| format!("Committed {:.7}.", oid.to_string()))
+                });
+                if let Some(result) = result {
+                    report_result(s, "Commit", result);
+                    log_view::refresh(s);
+                }
+            });
+        });
+    });
+}
+
+/// Commits the changes to all tracked files, staged or not. Nothing is
+/// staged until the message has been entered, so cancelling changes nothing.
+fn cb_working_tree_commit_all_tracked(siv: &mut Cursive) {
+    info!("cb_working_tree_commit_all_tracked invoked...");
+
+    if !identity_view::apply(siv) {
+        return;
+    }
+    owners_view::confirm(siv, "Commit", commit::all_tracked_changes, |s| {
+        signing_check::confirm(s, |s, title| {
+            dialogs::edit_text(s, &format!("{} (all tracked changes)", title), "", |s, message| {
+                let result = with_current_repo(s, |repo| {
+                    commit::commit_all_tracked(repo, message).map(|oid| format!("Committed {:.7}.", oid.to_string()))
+                });
+                if let Some(result) = result {
+                    report_result(s, "Commit", result);
+                    refresh_views(s);
+                }
+            });
+        });
+    });
+}
+
+fn cb_working_tree_why_ignored(siv: &mut Cursive) {
+    info!("cb_working_tree_why_ignored invoked...");
+
+    dialogs::prompt(siv, "Why Is It Ignored? (path)", "", |s, path| {
+        let path = Path::new(path.trim());
+        let explanation = with_current_repo(s, |repo| match ignore_rules::check(repo, path) {
+            Some(found) => found.description(path),
+            None if repo.is_path_ignored(path).unwrap_or(false) =>
+                format!("'{}' is ignored, but oaf cannot find the rule that ignores it.", path.display()),
+            None => format!("'{}' is not ignored; no rule matches it.", path.display()),
+        });
+        if let Some(explanation) = explanation {
+            s.add_layer(Dialog::info(explanation).title("Why Is It Ignored?"));
+        }
+    });
+}
+
+fn cb_working_tree_file_info(siv: &mut Cursive) {
+    info!("cb_working_tree_file_info invoked...");
+
+    dialogs::prompt(siv, "File Info (path)", "", |s, path| show_file_info(s, Path::new(path.trim())));
+}
+
+fn cb_branch_squash(siv: &mut Cursive) {
+    info!("cb_branch_squash invoked...");
+
+    let default_base = match with_current_repo(siv, |repo| {
+        remotes::current_branch_name(repo)
+            .and_then(|branch| remotes::upstream_of(repo, &branch))
+            .map(|upstream| format!("{}/{}", upstream.remote, upstream.branch))
+    }) {
+        Some(base) => base.unwrap_or_else(|| "master".to_string()),
+        None => return,
+    };
+
+    dialogs::prompt(siv, "Squash Branch onto Base", &default_base, |s, spec| {
+        let loaded = with_current_repo(s, |repo| -> Result<_, ::git2::Error> {
+            let base = history::resolve_commit(repo, spec)?;
+            Ok((base, rewrite::squash_message(repo, base)?))
+        });
+
+        let (base, message) = match loaded {
+            Some(Ok(loaded)) => loaded,
+            Some(Err(e)) => return report_result::<::git2::Error>(s, "Load branch for squash", Err(e)),
+            None => return,
+        };
+
+        let title = format!("Message for Branch Squashed onto {}", spec);
+        dialogs::edit_text(s, &title, &message, move |s, message| {
+            let result = with_current_repo(s, |repo| {
+                rewrite::squash_branch(repo, base, message).map(|squashed| format!(
+                    "Squashed the branch into {:.7}. The original is kept in backup '{}'.",
+                    squashed.commit.to_string(), squashed.backup))
+            });
+            if let Some(result) = result {
+                report_result(s, "Squash branch", result);
+                log_view::refresh(s);
+            }
+        });
+    });
+}
+
+fn cb_remote_toggle_offline(siv: &mut Cursive) {
+    info!("cb_remote_toggle_offline invoked...");
+
+    let offline = !network::is_offline();
+    network::set_offline(offline);
+    let message = if offline { "Offline mode is on, network operations are disabled." } else { "Offline mode is off." };
+    notifications::notify(siv, Level::Info, message);
+}
+
+fn cb_remote_open_in_browser(siv: &mut Cursive) {
+    info!("cb_remote_open_in_browser invoked...");
+
+    let head = match with_current_repo(siv, |repo| {
+        let branch = remotes::current_branch_name(repo);
+        repo.head().and_then(|h| h.peel_to_commit()).map(|c| (branch, c.id().to_string()))
+    }) {
+        Some(Ok(head)) => head,
+        Some(Err(e)) => return report_result::<::git2::Error>(siv, "Read HEAD", Err(e)),
+        None => return,
+    };
+
+    let (branch, commit) = head;
+    let mut choices = Vec::new();
+    if let Some(ref branch) = branch {
+        choices.push(format!("Branch '{}'", branch));
+    }
+    choices.push(format!("Commit {:.7}", commit));
+    choices.push("File...".to_string());
+
+    dialogs::choose(siv, "Open in Browser", choices, move |s, choice| {
+        if choice.starts_with("Branch") {
+            open_in_browser(s, WebTarget::Branch(branch.clone().unwrap_or_default()));
+        } else if choice.starts_with("Commit") {
+            open_in_browser(s, WebTarget::Commit(commit.clone()));
+        } else {
+            let rev = branch.clone().unwrap_or_else(|| commit.clone());
+            dialogs::prompt(s, "Open File in Browser", "", move |s, path| {
+                let path = path.trim().trim_start_matches('/').to_string();
+                open_in_browser(s, WebTarget::File { rev: rev.clone(), path });
+            });
+        }
+    });
+}
+
+/// Opens the forge's web page for `target` on one of the current repository's
+/// remotes, asking which remote to use if there is more than one.
+fn open_in_browser(siv: &mut Cursive, target: WebTarget) {
+    choose_remote(siv, "Open in Browser Using", move |s, remote| {
+        let url = match with_current_repo(s, |repo| remotes::remote_url(repo, remote)) {
+            Some(Ok(url)) => url,
+            Some(Err(e)) => return report_result::<::git2::Error>(s, "Read remote URL", Err(e)),
+            None => return,
+        };
+
+        match forge::web_url(&config::current().forges, &url, &target) {
+            Some(web_url) => {
+                if let Err(e) = forge::open_in_browser(&web_url) {
+                    report_result::<::std::io::Error>(s, "Open in browser", Err(e));
+                }
+            },
+            None => notifications::notify(s, Level::Warning, format!(
+                "Don't know how to make a web URL for '{}'. Add a forge template to the config file.", url)),
+        }
+    });
+}
+
+/// Asks the user to pick one of the current repository's remotes. If there is
+/// only one remote it is used without asking.
+fn choose_remote<F>(siv: &mut Cursive, title: &str, on_choose: F)
+    where F: Fn(&mut Cursive, &str) + 'static
+{
+    let names = match with_current_repo(siv, remotes::remote_names) {
+        Some(names) => names,
+        None => return,
+    };
+
+    if names.len() == 1 {
+        on_choose(siv, &names[0]);
+    } else {
+        dialogs::choose(siv, title, names, on_choose);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_backend::TestUi;
+
+    #[test]
+    fn menu_bar_opens_with_f10() {
+        let mut ui = TestUi::new(100, 30);
+        create_menu_bar(&mut ui.siv, &MruList::new("/nonexistent/mru", 5));
+        ui.render();
+        for menu in &["File", "Edit", "View", "Repository", "Remote", "Working Tree", "Branch", "Tags"] {
+            assert!(ui.lines()[0].contains(menu), "{}", ui.screen());
+        }
+
+        ui.press(vec![Key::F10, Key::Enter]);
+        assert!(ui.shows("Open..."), "{}", ui.screen());
+    }
 }
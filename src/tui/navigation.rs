@@ -0,0 +1,129 @@
+use std::cell::RefCell;
+use cursive::Cursive;
+use cursive::view::{IntoBoxedView, View};
+use repositories::RepositoryExtensions;
+use super::notifications;
+
+/// A view opened through `open`, remembered so that it can be named in the
+/// breadcrumb trail.
+struct Crumb {
+    name: String,
+    // The layer the view occupies; layers above the top of the stack have
+    // been closed.
+    depth: usize,
+    fullscreen: bool,
+}
+
+// Views closed by going back, most recent last, kept whole so that going
+// forward again restores them exactly, scroll positions and all. Only layers
+// opened through `open` are closed by going back; message boxes, toasts and
+// the repository tabs underneath everything are left alone.
+thread_local! {
+    static TRAIL: RefCell<Vec<Crumb>> = RefCell::new(Vec::new());
+    static FORWARD: RefCell<Vec<(Crumb, Box<dyn View>)>> = RefCell::new(Vec::new());
+}
+
+/// Adds `view` as a new layer and records it in the navigation history
+/// under `name`. Like following a link in a browser, this forgets the views
+/// that could have been gone forward to.
+pub fn open<V: IntoBoxedView>(siv: &mut Cursive, name: &str, view: V, fullscreen: bool) {
+    let depth = prune(siv);
+    FORWARD.with(|f| f.borrow_mut().clear());
+    TRAIL.with(|t| t.borrow_mut().push(Crumb { name: name.to_string(), depth, fullscreen }));
+
+    if fullscreen {
+        siv.add_fullscreen_layer(view);
+    } else {
+        siv.add_layer(view);
+    }
+}
+
+/// Closes the top layer, keeping it so that `forward` can bring it back. Does
+/// nothing unless the top layer was opened through `open`.
+pub fn back(siv: &mut Cursive) {
+    info!("navigation::back invoked...");
+    let depth = prune(siv);
+    let crumb = TRAIL.with(|t| {
+        let mut trail = t.borrow_mut();
+        if depth > 0 && trail.last().map_or(false, |c| c.depth == depth - 1) { trail.pop() } else { None }
+    });
+    let crumb = match crumb {
+        Some(crumb) => crumb,
+        None => return,
+    };
+
+    // A toast is the only layer that can be above the crumb's.
+    notifications::dismiss(siv);
+    if let Some(view) = siv.pop_layer() {
+        FORWARD.with(|f| f.borrow_mut().push((crumb, view)));
+    }
+}
+
+/// Reopens the layer most recently closed by `back`.
+pub fn forward(siv: &mut Cursive) {
+    info!("navigation::forward invoked...");
+    let (mut crumb, view) = match FORWARD.with(|f| f.borrow_mut().pop()) {
+        Some(next) => next,
+        None => return,
+    };
+
+    crumb.depth = prune(siv);
+    if crumb.fullscreen {
+        siv.add_fullscreen_layer(view);
+    } else {
+        siv.add_layer(view);
+    }
+    TRAIL.with(|t| t.borrow_mut().push(crumb));
+}
+
+/// Describes where the user is, e.g. 'oaf > Log > Commit 1a2b3c4', starting
+/// from the current repository.
+pub fn breadcrumb(siv: &mut Cursive) -> String {
+    prune(siv);
+    let repo = super::with_current_repo(siv, |repo| repo.display_name()).unwrap_or_default();
+    TRAIL.with(|t| {
+        let trail = t.borrow();
+        ::std::iter::once(repo.as_str())
+            .chain(trail.iter().map(|c| c.name.as_str()))
+            .collect::<Vec<_>>()
+            .join(" > ")
+    })
+}
+
+/// Forgets the crumbs for layers that have since been closed, which views
+/// do by popping themselves. Returns the number of layers, not counting a
+/// toast, which comes and goes by itself.
+fn prune(siv: &mut Cursive) -> usize {
+    let toasts = if notifications::has_toast(siv) { 1 } else { 0 };
+    let depth = siv.screen().layer_sizes().len() - toasts;
+    TRAIL.with(|t| t.borrow_mut().retain(|c| c.depth < depth));
+    depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursive::views::TextView;
+    use super::super::notifications::Level;
+    use super::super::test_backend::TestUi;
+
+    #[test]
+    fn back_only_closes_views_that_were_opened() {
+        let mut ui = TestUi::new(60, 10);
+        ui.siv.add_fullscreen_layer(TextView::new("repository tabs"));
+        back(&mut ui.siv);
+        assert!(ui.render().shows("repository tabs"), "{}", ui.screen());
+
+        open(&mut ui.siv, "Details", TextView::new("commit details"), false);
+        notifications::notify(&mut ui.siv, Level::Info, "toast");
+        back(&mut ui.siv);
+        assert!(!ui.render().shows("commit details"), "{}", ui.screen());
+        forward(&mut ui.siv);
+        assert!(ui.render().shows("commit details"), "{}", ui.screen());
+
+        back(&mut ui.siv);
+        back(&mut ui.siv);
+        assert!(ui.render().shows("repository tabs"), "{}", ui.screen());
+        assert_eq!(ui.siv.screen().layer_sizes().len(), 1);
+    }
+}
@@ -0,0 +1,178 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{Checkbox, Dialog, EditView, LinearLayout, SelectView, TextView};
+use config;
+use error::OafError;
+use error_report::IntoErrorReport;
+use network;
+use paths;
+use remotes;
+use repo_setup;
+use repositories;
+use scaffold::{self, Scaffold, Template, TemplateKind};
+use super::notifications::{self, Level};
+use super::progress_dialog;
+
+const PATH_ID: &str = "new_repo_path";
+const BRANCH_ID: &str = "new_repo_branch";
+const COMMIT_ID: &str = "new_repo_commit";
+
+/// Asks where to create a repository and what to start it with: the initial
+/// branch, a README, .gitignore and LICENSE from the templates, and whether
+/// to commit them. The new repository is then opened.
+pub fn show(siv: &mut Cursive) {
+    let config = config::current();
+    let template_dir = config.new_repo.template_dir.as_ref().map(paths::expand_tilde);
+
+    let mut layout = LinearLayout::vertical()
+        .child(TextView::new("Directory:"))
+        .child(EditView::new().with_id(PATH_ID).min_width(50))
+        .child(LinearLayout::horizontal()
+            .child(TextView::new("Initial branch: "))
+            .child(EditView::new().content(config.new_repo.initial_branch.clone()).with_id(BRANCH_ID).min_width(20)));
+
+    for &kind in TemplateKind::all() {
+        let mut select = SelectView::<Option<Template>>::new().popup();
+        select.add_item("(none)", None);
+        for template in scaffold::templates(kind, template_dir.as_ref().map(|d| d.as_path())) {
+            select.add_item(template.name.clone(), Some(template));
+        }
+        layout.add_child(LinearLayout::horizontal()
+            .child(TextView::new(format!("{:<15}", format!("{}:", kind.file_name()))))
+            .child(select.with_id(select_id(kind))));
+    }
+
+    let mut commit = Checkbox::new();
+    commit.set_checked(true);
+    layout.add_child(LinearLayout::horizontal()
+        .child(commit.with_id(COMMIT_ID))
+        .child(TextView::new(" Commit the files")));
+
+    siv.add_layer(Dialog::around(layout)
+        .title("New Repository")
+        .button("Create", create)
+        .dismiss_button("Cancel"));
+}
+
+fn select_id(kind: TemplateKind) -> &'static str {
+    match kind {
+        TemplateKind::Readme => "new_repo_readme",
+        TemplateKind::Gitignore => "new_repo_gitignore",
+        TemplateKind::License => "new_repo_license",
+    }
+}
+
+fn create(siv: &mut Cursive) {
+    let path = siv.call_on_id(PATH_ID, |v: &mut EditView| v.get_content().trim().to_string()).unwrap_or_default();
+    let initial_branch = siv.call_on_id(BRANCH_ID, |v: &mut EditView| v.get_content().trim().to_string()).unwrap_or_default();
+    let commit = siv.call_on_id(COMMIT_ID, |v: &mut Checkbox| v.is_checked()).unwrap_or(false);
+    if path.is_empty() || initial_branch.is_empty() {
+        return siv.add_layer(Dialog::info("Enter a directory and an initial branch name."));
+    }
+
+    let mut files = Vec::new();
+    for &kind in TemplateKind::all() {
+        let chosen = siv.call_on_id(select_id(kind), |v: &mut SelectView<Option<Template>>| v.selection());
+        if let Some(Some(template)) = chosen.and_then(|c| c).map(|t| (*t).clone()) {
+            files.push((kind, template));
+        }
+    }
+
+    let path = paths::expand_tilde(PathBuf::from(path));
+    let author = ::git2::Config::open_default().and_then(|c| c.get_string("user.name")).unwrap_or_default();
+    let scaffold = Scaffold { initial_branch, files, author };
+    let (repo, written) = match scaffold::create(&path, &scaffold) {
+        Ok(created) => created,
+        Err(e) => return super::report_result::<OafError>(siv, "Create repository", Err(e)),
+    };
+
+    siv.pop_layer();
+    if commit {
+        let committed = repo.signature().and_then(|signature| scaffold::initial_commit(&repo, &written, &signature));
+        if let Err(e) = committed {
+            super::report_result::<::git2::Error>(siv, "Make initial commit", Err(e));
+        }
+    }
+
+    open_new(siv, &path);
+}
+
+/// Asks for the URL of a repository and the directory to clone it into, then
+/// clones and opens it.
+pub fn clone(siv: &mut Cursive) {
+    super::dialogs::prompt(siv, "Clone Repository URL", "", |s, url| {
+        let url = url.to_string();
+        let initial = env::current_dir().unwrap_or_default().join(directory_for(&url));
+        super::dialogs::prompt(s, "Clone Into Directory", &initial.display().to_string(), move |s, path| {
+            let (url, path) = (url.clone(), paths::expand_tilde(PathBuf::from(path)));
+            let title = format!("Clone '{}'", url);
+            progress_dialog::run(s, &title, move |cancel, progress| -> Result<PathBuf, OafError> {
+                // A failed clone removes what it wrote, so it can simply be
+                // tried again.
+                let clone = || remotes::clone_cancellable(&url, &path, cancel, |p| progress(p.to_string()));
+                network::with_retries(&config::current().retry, cancel, clone, |retry| progress(retry.to_string()))?;
+                Ok(path)
+            }, |s, result| match result {
+                Ok(path) => open_new(s, &path),
+                Err(e) => progress_dialog::report(s, "Clone", Err(e)),
+            });
+        });
+    });
+}
+
+/// The directory git would clone `url` into, e.g. 'widgets' for
+/// 'https://github.com/acme/widgets.git'.
+fn directory_for(url: &str) -> String {
+    let name = url.trim_end_matches('/').rsplit(|c| c == '/' || c == ':').next().unwrap_or("");
+    name.trim_end_matches(".git").to_string()
+}
+
+/// Opens a repository that has just been created or cloned, applying the
+/// 'new_repo' setup to it first.
+fn open_new(siv: &mut Cursive, path: &Path) {
+    let opened = repositories::open_repository(path);
+    if let Ok(ref repo) = opened {
+        let config = config::current();
+        let reports = repo_setup::run(repo, &config.new_repo, &config.identities);
+        report_setup(siv, &reports);
+    }
+
+    let opened = super::with_repos(|repos| repos.finish_open(path, opened).map(|_| ()).and_then(|_| repos.save_mru()));
+    if let Some(Err(e)) = opened {
+        super::error_dialog::show(siv, e.into_report("Open repository"));
+    }
+    super::add_open_repositories_to_background_work();
+    super::repo_tabs::refresh(siv);
+}
+
+/// Shows the steps of the setup if any failed, otherwise just notes what
+/// was done.
+fn report_setup(siv: &mut Cursive, reports: &[repo_setup::StepReport]) {
+    if reports.is_empty() {
+        return;
+    }
+    if reports.iter().all(|r| r.result.is_ok()) {
+        let steps: Vec<String> = reports.iter().map(|r| r.step.to_string()).collect();
+        return notifications::notify(siv, Level::Info, format!("Set up the new repository: {}.", steps.join(", ")));
+    }
+
+    let text = reports.iter().map(|r| r.to_string()).collect::<Vec<_>>().join("\n");
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(20).max_width(100))
+        .title("Repository Setup Incomplete")
+        .dismiss_button("Close"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directory_for_takes_the_last_part_of_the_url() {
+        assert_eq!(directory_for("https://github.com/acme/widgets.git"), "widgets");
+        assert_eq!(directory_for("git@github.com:acme/widgets.git"), "widgets");
+        assert_eq!(directory_for("/srv/git/widgets/"), "widgets");
+        assert_eq!(directory_for("host:widgets"), "widgets");
+    }
+}
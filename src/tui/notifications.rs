@@ -0,0 +1,181 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+use chrono::{DateTime, Local};
+use cursive::Cursive;
+use cursive::event::{Event, EventResult};
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::view::{Position, ViewWrapper};
+use cursive::views::{BoxView, Dialog, Panel, TextView};
+use text_width;
+
+const TOAST_ID: &str = "notification_toast";
+const TOAST_WIDTH: usize = 50;
+/// The number of notifications kept for the history panel.
+const HISTORY_LIMIT: usize = 200;
+
+/// How serious a notification is. Errors stay on screen for longer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn duration(&self) -> Duration {
+        match *self {
+            Level::Info => Duration::from_secs(4),
+            Level::Warning | Level::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Level::Info => write!(f, "info"),
+            Level::Warning => write!(f, "warning"),
+            Level::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+    pub time: DateTime<Local>,
+}
+
+thread_local! {
+    static HISTORY: RefCell<VecDeque<Notification>> = RefCell::new(VecDeque::new());
+    // Incremented for each toast, so that an expiry timer only removes the
+    // toast it was started for and not one that has replaced it since.
+    static GENERATION: Cell<u64> = Cell::new(0);
+}
+
+/// Tells the user about something without interrupting them. The message is
+/// logged, recorded in the history and shown briefly in a toast in the
+/// bottom right corner of the screen. Any key press dismisses the toast and
+/// is then handled as normal.
+pub fn notify<S: Into<String>>(siv: &mut Cursive, level: Level, message: S) {
+    let message = message.into();
+    match level {
+        Level::Info => info!("Notification: {}", message),
+        Level::Warning => warn!("Notification: {}", message),
+        Level::Error => error!("Notification: {}", message),
+    }
+
+    record(level, message.clone());
+    show_toast(siv, level, &message);
+}
+
+/// Adds a message to the history without showing a toast, for things that
+/// the user has already been told about some other way.
+pub fn record(level: Level, message: String) {
+    HISTORY.with(|h| {
+        let mut history = h.borrow_mut();
+        if history.len() == HISTORY_LIMIT {
+            history.pop_front();
+        }
+        history.push_back(Notification { level, message, time: Local::now() });
+    });
+}
+
+/// Shows the notifications received so far, newest first.
+pub fn show_history(siv: &mut Cursive) {
+    let text = HISTORY.with(|h| {
+        h.borrow().iter().rev()
+            .map(|n| format!("{} {:<7} {}", n.time.format("%H:%M:%S"), n.level.to_string(), n.message))
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+
+    let text = if text.is_empty() { "There have been no notifications.".to_string() } else { text };
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(30).max_width(100))
+        .title("Notifications")
+        .button("Clear", |s| {
+            HISTORY.with(|h| h.borrow_mut().clear());
+            s.pop_layer();
+        })
+        .dismiss_button("Close"));
+}
+
+fn show_toast(siv: &mut Cursive, level: Level, message: &str) {
+    remove_toast(siv);
+    let generation = GENERATION.with(|g| { g.set(g.get() + 1); g.get() });
+
+    let text = format!("[{}] {}", level, message);
+    let inner_width = TOAST_WIDTH - 2;
+    let lines = (text_width::width(&text) + inner_width - 1) / inner_width;
+    let screen = siv.screen_size();
+    let x = screen.x.saturating_sub(TOAST_WIDTH + 1);
+    let y = screen.y.saturating_sub(lines + 3);
+
+    let toast = Toast { view: Panel::new(TextView::new(text)).fixed_width(TOAST_WIDTH) };
+    siv.screen_mut().add_transparent_layer_at(Position::absolute((x, y)), toast.with_id(TOAST_ID));
+
+    let sink = siv.cb_sink().clone();
+    let duration = level.duration();
+    thread::spawn(move || {
+        thread::sleep(duration);
+        let _ = sink.send(Box::new(move |s: &mut Cursive| {
+            if GENERATION.with(|g| g.get()) == generation {
+                remove_toast(s);
+            }
+        }));
+    });
+}
+
+/// Whether a toast is showing. It is a layer of its own.
+pub fn has_toast(siv: &mut Cursive) -> bool {
+    siv.screen_mut().find_layer_from_id(TOAST_ID).is_some()
+}
+
+/// Removes the toast, if one is showing, before it would expire.
+pub fn dismiss(siv: &mut Cursive) {
+    remove_toast(siv);
+}
+
+fn remove_toast(siv: &mut Cursive) {
+    let screen = siv.screen_mut();
+    if let Some(position) = screen.find_layer_from_id(TOAST_ID) {
+        screen.move_to_front(position);
+        screen.pop_layer();
+    }
+}
+
+/// A view that displays a message but passes key presses and mouse clicks on
+/// to the views below it, removing itself first.
+struct Toast {
+    view: BoxView<Panel<TextView>>,
+}
+
+impl ViewWrapper for Toast {
+    type V = BoxView<Panel<TextView>>;
+
+    fn with_view<F, R>(&self, f: F) -> Option<R>
+        where F: FnOnce(&Self::V) -> R
+    {
+        Some(f(&self.view))
+    }
+
+    fn with_view_mut<F, R>(&mut self, f: F) -> Option<R>
+        where F: FnOnce(&mut Self::V) -> R
+    {
+        Some(f(&mut self.view))
+    }
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::WindowResize | Event::Refresh => EventResult::Ignored,
+            event => EventResult::with_cb(move |s| {
+                remove_toast(s);
+                s.on_event(event.clone());
+            }),
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! A one column overview beside a long diff or file, showing where in it
+//! the changes and search hits are. Each row of the column stands for an
+//! equal share of the lines; the column can be clicked, and `jump` moves
+//! between the marked places.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use cursive::Printer;
+use cursive::direction::Direction;
+use cursive::event::{AnyCb, Event, EventResult, MouseButton, MouseEvent};
+use cursive::theme::{ColorStyle, PaletteColor};
+use cursive::vec::Vec2;
+use cursive::view::{Selector, View};
+use cursive::views::ScrollView;
+use super::palette;
+
+/// How many rows are left showing above a line that is jumped to.
+const CONTEXT_ROWS: usize = 3;
+
+/// What a line is marked as. Where several marks share a row of the
+/// overview, the one that comes last here is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Mark {
+    Added,
+    Removed,
+    Hit,
+}
+
+impl Mark {
+    fn symbol(self) -> &'static str {
+        match self {
+            Mark::Added => "+",
+            Mark::Removed => "-",
+            Mark::Hit => "*",
+        }
+    }
+}
+
+/// The marked lines of a text, which may still be growing.
+#[derive(Debug, Clone, Default)]
+pub struct Marks {
+    lines: usize,
+    marks: BTreeMap<usize, Mark>,
+    /// The lines where hunks start, if the text is a patch. Jumps then go
+    /// from hunk to hunk rather than between runs of marked lines.
+    hunks: Vec<usize>,
+    /// The lines that stand for others left out, such as the notes of
+    /// unchanged lines between hunks.
+    gaps: Vec<usize>,
+    /// True once all the lines have been added.
+    pub complete: bool,
+    /// The line at the top of the view, as of its last layout.
+    top: usize,
+}
+
+/// Marks shared between a view and the overview beside it.
+pub type SharedMarks = Arc<Mutex<Marks>>;
+
+impl Marks {
+    /// Forgets all the lines, ready for a new text.
+    pub fn clear(&mut self) {
+        *self = Marks::default();
+    }
+
+    /// Adds a line to the end of the text.
+    pub fn push(&mut self, mark: Option<Mark>) {
+        if let Some(mark) = mark {
+            self.marks.insert(self.lines, mark);
+        }
+        self.lines += 1;
+    }
+
+    /// Adds a hunk header to the end of the text.
+    pub fn push_hunk(&mut self) {
+        self.hunks.push(self.lines);
+        self.push(None);
+    }
+
+    /// Adds a line standing for others that were left out.
+    pub fn push_gap(&mut self) {
+        self.gaps.push(self.lines);
+        self.push(None);
+    }
+
+    /// The first gap at or below the top of the view: which gap it is,
+    /// counting from 0, and its line.
+    pub fn next_gap(&self) -> Option<(usize, usize)> {
+        self.gaps.iter().position(|&line| line >= self.top).map(|i| (i, self.gaps[i]))
+    }
+
+    /// The mark to show in each of `height` rows. A text shorter than the
+    /// overview gets a row per line.
+    pub fn rows(&self, height: usize) -> Vec<Option<Mark>> {
+        let mut rows = vec![None; height];
+        let scale = self.lines.max(height);
+        for (&line, &mark) in &self.marks {
+            let row = &mut rows[line * height / scale];
+            *row = (*row).max(Some(mark));
+        }
+        rows
+    }
+
+    /// The first line that the row at `row` stands for.
+    pub fn line_at_row(&self, row: usize, height: usize) -> usize {
+        (row * self.lines.max(height) / height.max(1)).min(self.lines.saturating_sub(1))
+    }
+
+    /// The next place to jump to from `line`.
+    pub fn next(&self, line: usize) -> Option<usize> {
+        let stops = self.stops();
+        stops.get(self.passed(&stops, line)).cloned()
+    }
+
+    /// The place before the one that `line` is at.
+    pub fn previous(&self, line: usize) -> Option<usize> {
+        let stops = self.stops();
+        self.passed(&stops, line).checked_sub(2).map(|i| stops[i])
+    }
+
+    /// Which hunk the view is at, e.g. 'hunk 3 of 17', with a '+' if more
+    /// may be to come. None if the text has no hunks.
+    pub fn hunk_counter(&self) -> Option<String> {
+        let current = self.current_hunk()?;
+        let more = if self.complete { "" } else { "+" };
+        Some(format!("hunk {} of {}{}", current + 1, self.hunks.len(), more))
+    }
+
+    /// Which hunk the view is at, counting from 0. None if the text has no
+    /// hunks.
+    pub fn current_hunk(&self) -> Option<usize> {
+        if self.hunks.is_empty() {
+            return None;
+        }
+        Some(self.passed(&self.hunks, current_line(self.top)) - 1)
+    }
+
+    /// How many of `stops` are at or above `line`. The header of the first
+    /// file in a patch is taken as part of its first hunk.
+    fn passed(&self, stops: &[usize], line: usize) -> usize {
+        let passed = stops.iter().take_while(|&&stop| stop <= line).count();
+        if self.hunks.is_empty() { passed } else { passed.max(1) }
+    }
+
+    /// The hunks, or else the start of each run of marked lines.
+    fn stops(&self) -> Vec<usize> {
+        if !self.hunks.is_empty() {
+            return self.hunks.clone();
+        }
+        self.marks.keys().cloned().filter(|&line| line == 0 || !self.marks.contains_key(&(line - 1))).collect()
+    }
+}
+
+/// The line that counts as where the view is when `top` is at the top: the
+/// line jumped to, if it was a jump. At the very top it is the first line,
+/// so that a mark a line or two down can still be jumped to.
+fn current_line(top: usize) -> usize {
+    if top == 0 { 0 } else { top + CONTEXT_ROWS }
+}
+
+/// A scrolling view with the overview column at its right edge, beside
+/// the scroll bar. Clicking a row of the overview scrolls there.
+pub struct Overview<V> {
+    scroll: ScrollView<V>,
+    marks: SharedMarks,
+    size: Vec2,
+    /// The line last jumped to, and the top of the view it left, for when
+    /// the line is too near the top for `current_line` to work it out.
+    jumped: Option<(usize, usize)>,
+}
+
+impl<V: View> Overview<V> {
+    pub fn new(view: V, marks: SharedMarks) -> Self {
+        Overview { scroll: ScrollView::new(view), marks, size: Vec2::zero(), jumped: None }
+    }
+
+    /// Replaces the marks, for a new text.
+    pub fn set_marks(&mut self, marks: Marks) {
+        *self.marks.lock().unwrap() = marks;
+        self.jumped = None;
+    }
+
+    /// Scrolls to show `line`, with a few lines above it. Rows are taken to
+    /// be lines, so in a text with wrapped lines this lands a little early.
+    pub fn scroll_to(&mut self, line: usize) {
+        self.scroll.set_offset((0, line.saturating_sub(CONTEXT_ROWS)));
+    }
+
+    /// Scrolls to the next, or previous, hunk or marked place.
+    pub fn jump(&mut self, forward: bool) {
+        let top = self.scroll.content_viewport().top();
+        let current = match self.jumped {
+            Some((line, jumped_top)) if jumped_top == top => line,
+            _ => current_line(top),
+        };
+        let target = {
+            let marks = self.marks.lock().unwrap();
+            if forward { marks.next(current) } else { marks.previous(current) }
+        };
+        if let Some(line) = target {
+            self.scroll_to(line);
+            self.jumped = Some((line, line.saturating_sub(CONTEXT_ROWS)));
+        }
+    }
+
+    fn content_size(&self, size: Vec2) -> Vec2 {
+        Vec2::new(size.x.saturating_sub(1), size.y)
+    }
+}
+
+impl<V: View> View for Overview<V> {
+    fn draw(&self, printer: &Printer) {
+        self.scroll.draw(&printer.cropped(self.content_size(printer.size)));
+
+        let palette = palette::current();
+        let column = printer.offset((printer.size.x.saturating_sub(1), 0));
+        let rows = self.marks.lock().unwrap().rows(printer.size.y);
+        for (y, mark) in rows.into_iter().enumerate() {
+            let mark = match mark {
+                Some(mark) => mark,
+                None => continue,
+            };
+            let colour = match mark {
+                Mark::Added => palette.added,
+                Mark::Removed => palette.removed,
+                Mark::Hit => palette.notice,
+            };
+            column.with_color(ColorStyle::new(colour, PaletteColor::View), |printer| printer.print((0, y), mark.symbol()));
+        }
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        self.size = size;
+        let content = self.content_size(size);
+        self.scroll.layout(content);
+        self.marks.lock().unwrap().top = self.scroll.content_viewport().top();
+    }
+
+    fn needs_relayout(&self) -> bool {
+        self.scroll.needs_relayout()
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        let content = self.content_size(constraint);
+        self.scroll.required_size(content) + (1, 0)
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        if let Event::Mouse { event: MouseEvent::Press(MouseButton::Left), position, offset } = event {
+            if position.x == offset.x + self.size.x.saturating_sub(1) && position.y >= offset.y {
+                let line = self.marks.lock().unwrap().line_at_row(position.y - offset.y, self.size.y);
+                self.scroll_to(line);
+                return EventResult::Consumed(None);
+            }
+        }
+        self.scroll.on_event(event)
+    }
+
+    fn call_on_any<'a>(&mut self, selector: &Selector, callback: AnyCb<'a>) {
+        self.scroll.call_on_any(selector, callback);
+    }
+
+    fn focus_view(&mut self, selector: &Selector) -> Result<(), ()> {
+        self.scroll.focus_view(selector)
+    }
+
+    fn take_focus(&mut self, source: Direction) -> bool {
+        self.scroll.take_focus(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marks(lines: &[Option<Mark>]) -> Marks {
+        let mut marks = Marks::default();
+        for &mark in lines {
+            marks.push(mark);
+        }
+        marks
+    }
+
+    #[test]
+    fn rows_show_the_strongest_mark_in_their_share_of_the_lines() {
+        let mut lines = vec![None; 100];
+        lines[5] = Some(Mark::Added);
+        lines[6] = Some(Mark::Removed);
+        lines[99] = Some(Mark::Hit);
+        let marks = marks(&lines);
+
+        let rows = marks.rows(10);
+        assert_eq!(rows[0], Some(Mark::Removed));
+        assert_eq!(rows[9], Some(Mark::Hit));
+        assert_eq!(rows.iter().filter(|r| r.is_some()).count(), 2);
+        assert_eq!(marks.line_at_row(9, 10), 90);
+    }
+
+    #[test]
+    fn a_short_text_gets_a_row_per_line() {
+        let marks = marks(&[None, Some(Mark::Added), None]);
+        assert_eq!(marks.rows(5), vec![None, Some(Mark::Added), None, None, None]);
+        assert_eq!(marks.line_at_row(1, 5), 1);
+        assert_eq!(marks.line_at_row(4, 5), 2);
+    }
+
+    #[test]
+    fn jumps_go_to_the_start_of_each_run_of_marks() {
+        let marks = marks(&[None, Some(Mark::Added), Some(Mark::Added), None, Some(Mark::Removed)]);
+        assert_eq!(marks.next(0), Some(1));
+        assert_eq!(marks.next(1), Some(4));
+        assert_eq!(marks.next(4), None);
+        assert_eq!(marks.previous(4), Some(1));
+        assert_eq!(marks.previous(1), None);
+    }
+
+    #[test]
+    fn patches_jump_from_hunk_to_hunk() {
+        let mut marks = Marks::default();
+        marks.push(None);
+        marks.push_hunk();
+        for _ in 0..10 {
+            marks.push(Some(Mark::Added));
+            marks.push(Some(Mark::Removed));
+            marks.push(None);
+        }
+        marks.push_hunk();
+        marks.push(Some(Mark::Added));
+
+        // The first hunk is reached from the top.
+        assert_eq!(marks.next(0), Some(32));
+        assert_eq!(marks.next(1), Some(32));
+        assert_eq!(marks.previous(32), Some(1));
+        assert_eq!(marks.hunk_counter(), Some("hunk 1 of 2+".to_string()));
+
+        marks.top = 29;
+        marks.complete = true;
+        assert_eq!(marks.hunk_counter(), Some("hunk 2 of 2".to_string()));
+        assert_eq!(marks.current_hunk(), Some(1));
+        assert_eq!(self::marks(&[Some(Mark::Hit)]).hunk_counter(), None);
+    }
+
+    #[test]
+    fn the_next_gap_is_the_first_at_or_below_the_top() {
+        let mut marks = Marks::default();
+        assert_eq!(marks.next_gap(), None);
+        marks.push_gap();
+        marks.push_hunk();
+        marks.push(Some(Mark::Added));
+        marks.push_gap();
+        assert_eq!(marks.next_gap(), Some((0, 0)));
+
+        marks.top = 1;
+        assert_eq!(marks.next_gap(), Some((1, 3)));
+        marks.top = 4;
+        assert_eq!(marks.next_gap(), None);
+    }
+
+    #[test]
+    fn a_mark_near_the_top_is_jumped_to_from_the_top() {
+        let marks = marks(&[None, Some(Mark::Hit), None, None, None, None, Some(Mark::Hit)]);
+        assert_eq!(marks.next(current_line(0)), Some(1));
+        // Jumping there leaves the view at the top, so the line jumped to
+        // is remembered for the next jump.
+        assert_eq!(marks.next(1), Some(6));
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, TextView};
+use git2::Repository;
+use codeowners::CodeOwners;
+use config;
+
+/// If the 'show_owners' setting is on and the repository has a CODEOWNERS
+/// file, shows the owners of the files `action` (a commit or push) changes,
+/// as listed by `changed_files`, so the user knows who will need to review
+/// it. Otherwise the action goes ahead straight away.
+pub fn confirm<C, F>(siv: &mut Cursive, action: &str, changed_files: C, on_continue: F)
+    where C: FnOnce(&Repository) -> Result<Vec<PathBuf>, ::git2::Error>,
+          F: Fn(&mut Cursive) + 'static
+{
+    if !config::current().show_owners {
+        return on_continue(siv);
+    }
+    let found = super::with_current_repo(siv, |repo| match repo.workdir().and_then(CodeOwners::load) {
+        Some(owners) => changed_files(repo).map(|files| Some((owners, files))),
+        None => Ok(None),
+    });
+    let (owners, files) = match found {
+        Some(Ok(Some((owners, ref files)))) if !files.is_empty() => (owners, files.clone()),
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "List changed files", Err(e)),
+        _ => return on_continue(siv),
+    };
+
+    let summary = owners.summarise(&files);
+    let text = format!("Owners of the {} files changed, from {}:\n\n{}", files.len(), owners.source.display(), summary.description());
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(24).max_width(100))
+        .title(format!("Review Owners - {}", action))
+        .button(action, move |s| {
+            s.pop_layer();
+            on_continue(s);
+        })
+        .dismiss_button("Cancel"));
+}
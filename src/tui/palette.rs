@@ -0,0 +1,128 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use cursive::theme::{BaseColor, Color};
+use cursive::utils::markup::StyledString;
+use colours::ColourDepth;
+use config;
+use highlight::{self, Highlighter, Rgb};
+
+/// The colours used for diffs and notices, in shades the terminal can show.
+/// With only the basic colours, added and removed lines are the standard
+/// green and red, which every terminal shows distinctly; with more colours
+/// softer shades are used that are easier on the eye but still far apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub added: Color,
+    pub removed: Color,
+    pub hunk: Color,
+    /// For messages among the text, e.g. that a diff was cut off.
+    pub notice: Color,
+}
+
+impl Palette {
+    pub fn for_depth(depth: ColourDepth) -> Palette {
+        match depth {
+            ColourDepth::Auto | ColourDepth::Basic => Palette {
+                added: Color::Dark(BaseColor::Green),
+                removed: Color::Dark(BaseColor::Red),
+                hunk: Color::Dark(BaseColor::Cyan),
+                notice: Color::Dark(BaseColor::Yellow),
+            },
+            // Each component is 0 to 5, in the 6x6x6 colour cube.
+            ColourDepth::Extended => Palette {
+                added: Color::RgbLowRes(1, 4, 1),
+                removed: Color::RgbLowRes(4, 1, 1),
+                hunk: Color::RgbLowRes(1, 3, 4),
+                notice: Color::RgbLowRes(4, 3, 0),
+            },
+            ColourDepth::TrueColour => Palette {
+                added: Color::Rgb(0x50, 0xc8, 0x78),
+                removed: Color::Rgb(0xe6, 0x5a, 0x5a),
+                hunk: Color::Rgb(0x5a, 0xaa, 0xdc),
+                notice: Color::Rgb(0xdc, 0xb4, 0x3c),
+            },
+        }
+    }
+}
+
+// The depth, as an index into DEPTHS.
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+const DEPTHS: [ColourDepth; 3] = [ColourDepth::Basic, ColourDepth::Extended, ColourDepth::TrueColour];
+
+/// Chooses the palette for `depth`, resolving Auto from the environment.
+pub fn set_depth(depth: ColourDepth) {
+    let depth = depth.resolve();
+    info!("Using the {:?} colour palette", depth);
+    let index = DEPTHS.iter().position(|d| *d == depth).unwrap_or(0);
+    DEPTH.store(index, Ordering::SeqCst);
+}
+
+/// The palette for the terminal, as chosen by `set_depth`.
+pub fn current() -> Palette {
+    Palette::for_depth(DEPTHS[DEPTH.load(Ordering::SeqCst)])
+}
+
+
+/// A highlighter for the file at `path`, if it is turned on. The themes'
+/// shades cannot be shown with only the basic colours, so then there is
+/// none.
+pub fn highlighter(path: &Path) -> Option<Highlighter> {
+    match DEPTHS[DEPTH.load(Ordering::SeqCst)] {
+        ColourDepth::Basic | ColourDepth::Auto => None,
+        _ => highlight::for_path(path, &config::current().highlighting),
+    }
+}
+
+/// Appends `line` to `text`, highlighted if there is a highlighter.
+pub fn append_highlighted(text: &mut StyledString, highlighter: Option<&mut Highlighter>, line: &str) {
+    let highlighter = match highlighter {
+        Some(highlighter) => highlighter,
+        None => return text.append_plain(line),
+    };
+    for (rgb, run) in highlighter.line(line) {
+        match rgb.and_then(syntax_colour) {
+            Some(colour) => text.append_styled(run, colour),
+            None => text.append_plain(run),
+        }
+    }
+}
+
+/// Appends a line of a diff: `prefix`, e.g. '+', in `colour`, then `code`
+/// highlighted, or in `colour` too if there is no highlighter.
+pub fn append_change(text: &mut StyledString, highlighter: Option<&mut Highlighter>, prefix: &str, colour: Option<Color>, code: &str) {
+    let styled = |text: &mut StyledString, s: &str| match colour {
+        Some(colour) => text.append_styled(s, colour),
+        None => text.append_plain(s),
+    };
+    styled(text, prefix);
+    match highlighter {
+        Some(highlighter) => append_highlighted(text, Some(highlighter), code),
+        None => styled(text, code),
+    }
+    text.append_plain("\n");
+}
+
+/// The nearest colour to `rgb` that the terminal can show.
+fn syntax_colour(rgb: Rgb) -> Option<Color> {
+    let (r, g, b) = rgb;
+    match DEPTHS[DEPTH.load(Ordering::SeqCst)] {
+        ColourDepth::Basic | ColourDepth::Auto => None,
+        ColourDepth::Extended => Some(Color::RgbLowRes(cube(r), cube(g), cube(b))),
+        ColourDepth::TrueColour => Some(Color::Rgb(r, g, b)),
+    }
+}
+
+/// The 0 to 5 step of the 6x6x6 colour cube nearest to `component`.
+fn cube(component: u8) -> u8 {
+    ((u16::from(component) * 5 + 127) / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn components_map_to_the_nearest_step_of_the_cube() {
+        assert_eq!((cube(0), cube(25), cube(26), cube(128), cube(229), cube(255)), (0, 0, 1, 3, 4, 5));
+    }
+}
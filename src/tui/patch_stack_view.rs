@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, TextView};
+use git2::Repository;
+use error::OafError;
+use history;
+use patch_stack::{self, Rebuilt, Stack};
+use remotes;
+
+/// Shows the patch stack of the current branch, with buttons to reorder,
+/// refresh and export it. If the branch has no stack, offers to make one.
+pub fn show(siv: &mut Cursive) {
+    let loaded = super::with_current_repo(siv, |repo| -> Result<_, ::git2::Error> {
+        match patch_stack::current(repo)? {
+            Some(stack) => Ok(Some((patch_stack::order_text(repo, &stack)?, stack))),
+            None => Ok(None),
+        }
+    });
+    let (text, stack) = match loaded {
+        Some(Ok(Some(loaded))) => loaded,
+        Some(Ok(None)) => return create(siv),
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Load patch stack", Err(e)),
+        None => return,
+    };
+
+    let title = format!("Patch Stack of '{}' on {:.7}", stack.branch, stack.base.to_string());
+    let (reorder_stack, export_stack) = (stack.clone(), stack.clone());
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(20).max_width(100))
+        .title(title)
+        .button("Reorder", move |s| { s.pop_layer(); reorder(s, &reorder_stack); })
+        .button("Refresh", |s| { s.pop_layer(); refresh(s); })
+        .button("Export", move |s| { s.pop_layer(); export(s, &export_stack); })
+        .button("Make Again", |s| { s.pop_layer(); create(s); })
+        .dismiss_button("Close"));
+}
+
+/// The upstream of the current branch, the usual base for its patches.
+fn default_base(siv: &mut Cursive) -> Option<String> {
+    super::with_current_repo(siv, |repo| {
+        remotes::current_branch_name(repo)
+            .and_then(|branch| remotes::upstream_of(repo, &branch))
+            .map(|upstream| format!("{}/{}", upstream.remote, upstream.branch))
+            .unwrap_or_else(|| "master".to_string())
+    })
+}
+
+fn create(siv: &mut Cursive) {
+    let default_base = match default_base(siv) {
+        Some(base) => base,
+        None => return,
+    };
+
+    super::dialogs::prompt(siv, "Make Patch Stack on Base", &default_base, |s, spec| {
+        let result = super::with_current_repo(s, |repo| -> Result<Stack, OafError> {
+            patch_stack::create(repo, history::resolve_commit(repo, spec)?)
+        });
+        match result {
+            Some(Ok(_)) => show(s),
+            Some(Err(e)) => super::report_result::<OafError>(s, "Make patch stack", Err(e)),
+            None => {},
+        }
+    });
+}
+
+/// Lets the user move the lines for the patches around, then reapplies them
+/// in the new order.
+fn reorder(siv: &mut Cursive, stack: &Stack) {
+    let text = match super::with_current_repo(siv, |repo| patch_stack::order_text(repo, stack)) {
+        Some(Ok(text)) => text,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Load patch stack", Err(e)),
+        None => return,
+    };
+
+    let patches = stack.patches.clone();
+    super::dialogs::edit_text(siv, "Reorder Patches, Bottom First", &text, move |s, text| {
+        let order = match patch_stack::parse_order(&patches, text) {
+            Ok(order) => order,
+            Err(problem) => return s.add_layer(Dialog::info(problem).title("Invalid Order")),
+        };
+        let result = super::with_current_repo(s, |repo| patch_stack::reorder(repo, &order));
+        finish(s, "Reorder patches", result);
+    });
+}
+
+fn refresh(siv: &mut Cursive) {
+    let default_base = match default_base(siv) {
+        Some(base) => base,
+        None => return,
+    };
+
+    super::dialogs::prompt(siv, "Refresh Patches onto New Base", &default_base, |s, spec| {
+        let result = super::with_current_repo(s, |repo| -> Result<Rebuilt, OafError> {
+            patch_stack::refresh(repo, history::resolve_commit(repo, spec)?)
+        });
+        finish(s, "Refresh patches", result);
+    });
+}
+
+fn finish(siv: &mut Cursive, operation: &str, result: Option<Result<Rebuilt, OafError>>) {
+    let result = match result {
+        Some(result) => result.map(|rebuilt| describe(&rebuilt)),
+        None => return,
+    };
+    super::report_result(siv, operation, result);
+    super::log_view::refresh(siv);
+}
+
+fn describe(rebuilt: &Rebuilt) -> String {
+    let mut text = format!("'{}' now has {} patches.", rebuilt.stack.branch, rebuilt.stack.patches.len());
+    if !rebuilt.dropped.is_empty() {
+        text.push_str(&format!(" {} were dropped because the base already has them.", rebuilt.dropped.len()));
+    }
+    text.push_str(&format!(" The original is kept in backup '{}'.", rebuilt.backup));
+    text
+}
+
+fn export(siv: &mut Cursive, stack: &Stack) {
+    let default_dir = match super::with_current_repo(siv, default_export_dir) {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    let stack = stack.clone();
+    super::dialogs::prompt(siv, "Export Patches to Directory", &default_dir.to_string_lossy(), move |s, dir| {
+        let dir = PathBuf::from(dir.trim());
+        let result = super::with_current_repo(s, |repo| {
+            patch_stack::export(repo, &stack, &dir)
+                .map(|written| format!("Wrote {} patches and a series file to {}.", written.len() - 1, dir.display()))
+        });
+        if let Some(result) = result {
+            super::report_result(s, "Export patches", result);
+        }
+    });
+}
+
+/// Inside the .git directory, so the exported files don't show up as
+/// untracked changes.
+fn default_export_dir(repo: &Repository) -> PathBuf {
+    repo.path().join("patches")
+}
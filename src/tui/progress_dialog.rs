@@ -0,0 +1,72 @@
+use std::thread;
+use cursive::Cursive;
+use cursive::event::Key;
+use cursive::traits::{Boxable, Identifiable};
+use cursive::views::{Dialog, OnEventView, TextView};
+use cancel::Cancel;
+use error::OafError;
+use super::notifications::{self, Level};
+
+const DIALOG_ID: &str = "progress_dialog";
+const TEXT_ID: &str = "progress_dialog_text";
+
+/// Runs `job` on a background thread, showing a dialog with the latest
+/// message it passes to its progress function. Esc or the Cancel button sets
+/// the job's `Cancel`; the dialog stays up until the job notices and stops.
+/// Then the dialog is closed and `on_done` is called on the UI thread with
+/// what the job returned.
+pub fn run<J, T, D>(siv: &mut Cursive, title: &str, job: J, on_done: D)
+    where J: FnOnce(&Cancel, &dyn Fn(String)) -> T + Send + 'static,
+          T: Send + 'static,
+          D: FnOnce(&mut Cursive, T) + Send + 'static
+{
+    let cancel = Cancel::new();
+    let (button_cancel, esc_cancel) = (cancel.clone(), cancel.clone());
+    let dialog = Dialog::around(TextView::new("Starting...").with_id(TEXT_ID).min_width(50))
+        .title(title)
+        .button("Cancel", move |s| request_cancel(s, &button_cancel));
+    let view = OnEventView::new(dialog).on_event(Key::Esc, move |s| request_cancel(s, &esc_cancel));
+    siv.add_layer(view.with_id(DIALOG_ID));
+
+    let sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let progress_sink = sink.clone();
+        let progress_cancel = cancel.clone();
+        let progress = move |message: String| {
+            // Keep saying that we are cancelling rather than how far we got.
+            if !progress_cancel.is_cancelled() {
+                let _ = progress_sink.send(Box::new(move |s: &mut Cursive| {
+                    s.call_on_id(TEXT_ID, |v: &mut TextView| v.set_content(message));
+                }));
+            }
+        };
+
+        let result = job(&cancel, &progress);
+        let _ = sink.send(Box::new(move |s: &mut Cursive| {
+            close(s);
+            on_done(s, result);
+        }));
+    });
+}
+
+/// Reports the outcome of a job run by `run`. Cancelling is not a failure, so
+/// it gets a notification rather than an error dialog.
+pub fn report(siv: &mut Cursive, operation: &str, result: Result<String, OafError>) {
+    match result {
+        Err(OafError::Cancelled) => notifications::notify(siv, Level::Info, format!("{} cancelled.", operation)),
+        result => super::report_result(siv, operation, result),
+    }
+}
+
+fn request_cancel(siv: &mut Cursive, cancel: &Cancel) {
+    cancel.cancel();
+    siv.call_on_id(TEXT_ID, |v: &mut TextView| v.set_content("Cancelling..."));
+}
+
+fn close(siv: &mut Cursive) {
+    let screen = siv.screen_mut();
+    if let Some(position) = screen.find_layer_from_id(DIALOG_ID) {
+        screen.move_to_front(position);
+        screen.pop_layer();
+    }
+}
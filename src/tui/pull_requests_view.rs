@@ -0,0 +1,69 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, SelectView};
+use config;
+use error::OafError;
+use forge;
+use pull_requests::{self, ForgeKind, PullRequest};
+use remotes;
+
+/// Lists the open pull requests on one of the current repository's remotes.
+/// Choosing one fetches its head into a local branch and checks it out.
+pub fn show(siv: &mut Cursive) {
+    super::choose_remote(siv, "Pull Requests For", |s, remote| {
+        let url = match super::with_current_repo(s, |repo| remotes::remote_url(repo, remote)) {
+            Some(Ok(url)) => url,
+            Some(Err(e)) => return super::report_result::<::git2::Error>(s, "Read remote URL", Err(e)),
+            None => return,
+        };
+
+        let location = match forge::parse_remote_url(&url) {
+            Some(location) => location,
+            None => return s.add_layer(Dialog::info(format!("'{}' is not a forge URL.", url))),
+        };
+
+        let kind = match ForgeKind::from_host(&location.host) {
+            Some(kind) => kind,
+            None => return s.add_layer(Dialog::info(format!("Pull requests are not supported for '{}'.", location.host))),
+        };
+
+        let token = match config::current().api_tokens.get(&location.host) {
+            Some(token) => token.clone(),
+            None => return s.add_layer(Dialog::info(format!(
+                "No API token for '{}'. Add one to the [api_tokens] section of the config file.", location.host))),
+        };
+
+        let requests = match pull_requests::list(&location, &token) {
+            Ok(requests) => requests,
+            Err(e) => return super::report_result::<String>(s, "List pull requests", Err(e)),
+        };
+
+        if requests.is_empty() {
+            return s.add_layer(Dialog::info(format!("{} has no open pull requests.", location.path)));
+        }
+
+        let remote = remote.to_string();
+        let mut list = SelectView::<u64>::new();
+        for pr in &requests {
+            list.add_item(format_request(pr), pr.number);
+        }
+        list.set_on_submit(move |s, number: &u64| {
+            s.pop_layer();
+            let result = super::with_current_repo(s, |repo| {
+                pull_requests::checkout(repo, &remote, kind, *number)
+                    .map(|branch| format!("Checked out pull request {} as '{}'.", number, branch))
+            });
+            if let Some(result) = result {
+                super::report_result::<OafError>(s, "Check out pull request", result);
+            }
+        });
+
+        s.add_layer(Dialog::around(list.scrollable().max_height(20))
+            .title(format!("Open Pull Requests - {}", location.path))
+            .dismiss_button("Close"));
+    });
+}
+
+fn format_request(pr: &PullRequest) -> String {
+    format!("#{:<5} {:<8} {} ({}, {})", pr.number, pr.ci_status.to_string(), pr.title, pr.author, pr.branch)
+}
@@ -0,0 +1,70 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, TextView};
+use config;
+use push_review::{self, Warning};
+use remotes::UpstreamSpec;
+
+/// Shows what pushing `branch` to `upstream` will send, with any warnings,
+/// and calls `on_confirm` if the user goes ahead.
+pub fn show<F>(siv: &mut Cursive, branch: &str, upstream: &UpstreamSpec, on_confirm: F)
+    where F: Fn(&mut Cursive) + 'static
+{
+    let protected = config::current().protected_branches.clone();
+    let review = match super::with_current_repo(siv, |repo| push_review::review(repo, branch, upstream, &protected)) {
+        Some(Ok(review)) => review,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Review push", Err(e)),
+        None => return,
+    };
+
+    if review.commits.is_empty() && !review.warnings.contains(&Warning::NewBranch) {
+        let text = format!("'{}/{}' already has every commit on '{}'.", upstream.remote, upstream.branch, branch);
+        return siv.add_layer(Dialog::info(text).title("Nothing to Push"));
+    }
+
+    let force = review.warnings.iter().any(|w| match *w {
+        Warning::ForcePush { .. } => true,
+        _ => false,
+    });
+    let title = match (force, review.warnings.is_empty()) {
+        (true, _) => "Review Push - Force Push",
+        (false, true) => "Review Push",
+        (false, false) => "Review Push - Warnings",
+    };
+    siv.add_layer(Dialog::around(TextView::new(review.description()).scrollable().max_height(24).max_width(100))
+        .title(title)
+        .button("Push", move |s| {
+            s.pop_layer();
+            on_confirm(s);
+        })
+        .dismiss_button("Cancel"));
+}
+
+/// As `show`, for pushing `branch` to the branch of the same name on each of
+/// `remotes`: shows what each push will send and its warnings together, and
+/// calls `on_confirm` if the user goes ahead with all of them.
+pub fn show_several<F>(siv: &mut Cursive, branch: &str, remotes: &[String], on_confirm: F)
+    where F: Fn(&mut Cursive) + 'static
+{
+    let protected = config::current().protected_branches.clone();
+    let reviews = super::with_current_repo(siv, |repo| {
+        remotes.iter()
+            .map(|remote| push_review::review(repo, branch, &UpstreamSpec::new(remote.as_str(), branch), &protected))
+            .collect::<Result<Vec<_>, _>>()
+    });
+    let reviews = match reviews {
+        Some(Ok(reviews)) => reviews,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Review push", Err(e)),
+        None => return,
+    };
+
+    let text = reviews.iter().map(|r| r.description()).collect::<Vec<_>>().join("\n");
+    let title = if reviews.iter().all(|r| r.warnings.is_empty()) { "Push to Several Remotes" } else { "Push to Several Remotes - Warnings" };
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(24).max_width(100))
+        .title(title)
+        .button("Push", move |s| {
+            s.pop_layer();
+            on_confirm(s);
+        })
+        .dismiss_button("Cancel"));
+}
@@ -0,0 +1,95 @@
+use std::rc::Rc;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::utils::markup::StyledString;
+use cursive::views::{Dialog, LinearLayout, SelectView, TextView};
+use history;
+use range_diff::{self, DiffLine, RangeDiffEntry};
+use super::palette;
+
+const PATCH_ID: &str = "range_diff_patch";
+
+/// Asks for the old and new versions of a branch, e.g. 'feature@{1}...feature'
+/// after a force-push, and shows how the commits in each version correspond.
+/// Both series start from the merge base of the two versions. The changelog
+/// of the commits in NEW but not OLD is a button away.
+pub fn show(siv: &mut Cursive) {
+    super::dialogs::prompt(siv, "Range Diff (OLD...NEW)", "", |s, spec| {
+        let (old, new) = match range_diff::parse_spec(spec) {
+            Some(revisions) => revisions,
+            None => return s.add_layer(Dialog::info("Enter two revisions, as 'OLD...NEW' or 'OLD NEW'.")),
+        };
+
+        let entries = super::with_current_repo(s, |repo| -> Result<_, ::git2::Error> {
+            let old_tip = history::resolve_commit(repo, old)?;
+            let new_tip = history::resolve_commit(repo, new)?;
+            let base = super::with_commit_graph(repo, |graph| graph.merge_base(repo, old_tip, new_tip))?
+                .ok_or_else(|| ::git2::Error::from_str("The two revisions have no history in common."))?;
+            range_diff::range_diff(repo, base, old_tip, base, new_tip)
+        });
+
+        let entries = match entries {
+            Some(Ok(entries)) => entries,
+            Some(Err(e)) => return super::report_result::<::git2::Error>(s, "Range diff", Err(e)),
+            None => return,
+        };
+
+        if entries.is_empty() {
+            return s.add_layer(Dialog::info(format!("'{}' and '{}' have no commits of their own.", old, new)));
+        }
+
+        show_entries(s, old, new, entries);
+    });
+}
+
+fn show_entries(siv: &mut Cursive, old: &str, new: &str, entries: Vec<RangeDiffEntry>) {
+    let title = format!("{}...{}", old, new);
+    let entries = Rc::new(entries);
+    let mut list = SelectView::<usize>::new();
+    for (i, entry) in entries.iter().enumerate() {
+        list.add_item(entry.description(), i);
+    }
+
+    let for_select = entries.clone();
+    list.set_on_select(move |s, i: &usize| {
+        let text = patch_text(&for_select[*i]);
+        s.call_on_id(PATCH_ID, |v: &mut TextView| v.set_content(text));
+    });
+
+    let layout = LinearLayout::vertical()
+        .child(list.scrollable().max_height(10))
+        .child(TextView::new(patch_text(&entries[0])).with_id(PATCH_ID).scrollable().max_height(20));
+
+    let view = Dialog::around(layout.max_width(120))
+        .title(format!("Range Diff - {}", title))
+        .button("Changelog", {
+            let (old, new) = (old.to_string(), new.to_string());
+            move |s| super::changelog_view::show_range(s, &old, &new)
+        })
+        .dismiss_button("Close");
+    super::navigation::open(siv, &format!("Range Diff {}", title), view, false);
+}
+
+/// Shows how a modified commit's patch changed, in the style of
+/// 'git range-diff': removed lines in red, added lines in green.
+fn patch_text(entry: &RangeDiffEntry) -> StyledString {
+    if entry.patch_diff.is_empty() {
+        let message = if entry.old.is_some() && entry.new.is_some() {
+            "The patch is unchanged."
+        } else {
+            "This commit is only in one version."
+        };
+        return StyledString::plain(message);
+    }
+
+    let palette = palette::current();
+    let mut text = StyledString::new();
+    for line in &entry.patch_diff {
+        match *line {
+            DiffLine::Same(ref l) => text.append_plain(format!(" {}\n", l)),
+            DiffLine::Removed(ref l) => text.append_styled(format!("-{}\n", l), palette.removed),
+            DiffLine::Added(ref l) => text.append_styled(format!("+{}\n", l), palette.added),
+        }
+    }
+    text
+}
@@ -0,0 +1,60 @@
+use std::path::{Path, PathBuf};
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, SelectView};
+use git2::Oid;
+use paths;
+use recent_commits::RecentCommit;
+use text_width::{self, Ellipsis};
+
+/// Lists the commits whose details were shown, this session's first, then
+/// those from earlier sessions. Choosing one opens its repository, if need
+/// be, and selects it in the log view.
+pub fn show(siv: &mut Cursive) {
+    let sections = super::RECENT_COMMITS.with(|r| r.borrow().as_ref().map(|recent| {
+        let list = |commits: Vec<&RecentCommit>| commits.into_iter().cloned().collect::<Vec<_>>();
+        (list(recent.this_session()), list(recent.earlier()))
+    }));
+    let (session, earlier) = match sections {
+        Some((ref session, ref earlier)) if session.is_empty() && earlier.is_empty() =>
+            return siv.add_layer(Dialog::info("No commits have been viewed yet.")),
+        Some(sections) => sections,
+        None => return,
+    };
+
+    let mut list = SelectView::<Option<(PathBuf, Oid)>>::new();
+    for &(heading, ref commits) in &[("This session", &session), ("Earlier", &earlier)] {
+        if commits.is_empty() {
+            continue;
+        }
+        list.add_item(heading, None);
+        for commit in commits.iter() {
+            let label = format!("    {} {:.7} {}", repo_name(&commit.repo), commit.oid.to_string(), commit.summary);
+            list.add_item(text_width::fit(&label, 100, Ellipsis::End), Some((commit.repo.clone(), commit.oid)));
+        }
+    }
+    list.set_on_submit(|s, item: &Option<(PathBuf, Oid)>| {
+        if let Some((ref repo, oid)) = *item {
+            s.pop_layer();
+            if super::select_or_open(s, repo) {
+                super::log_view::show(s);
+                super::log_view::goto_commit(s, oid);
+            }
+        }
+    });
+
+    siv.add_layer(Dialog::around(list.scrollable().max_height(25))
+        .title("Recently Viewed Commits")
+        .dismiss_button("Close"));
+}
+
+/// The working tree directory's name, or the .git directory's path for a
+/// bare repository.
+fn repo_name(git_dir: &Path) -> String {
+    match git_dir.file_name() {
+        Some(name) if name == ".git" => git_dir.parent().and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        _ => paths::compress_tilde(git_dir).display().to_string(),
+    }
+}
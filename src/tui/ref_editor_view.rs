@@ -0,0 +1,121 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{Dialog, SelectView};
+use ref_editor::{self, NoBackup};
+
+const LIST_ID: &str = "ref_editor_list";
+
+/// Lists every ref by its full name, including hidden ones such as backups,
+/// with buttons to create, repoint and delete them.
+pub fn show(siv: &mut Cursive) {
+    let refs = match super::with_current_repo(siv, ref_editor::list) {
+        Some(Ok(refs)) => refs,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "List refs", Err(e)),
+        None => return,
+    };
+
+    let mut list = SelectView::<String>::new();
+    for raw in &refs {
+        list.add_item(raw.to_string(), raw.name.clone());
+    }
+
+    siv.add_layer(Dialog::around(list.with_id(LIST_ID).scrollable().max_height(25).max_width(120))
+        .title(format!("Refs ({})", refs.len()))
+        .button("Create...", create)
+        .button("Point At...", point_at_selected)
+        .button("Delete...", delete_selected)
+        .button("Inspect", |s| {
+            if let Some(name) = selected(s) {
+                super::inspect_view::show_with(s, &name);
+            }
+        })
+        .dismiss_button("Close"));
+}
+
+fn selected(siv: &mut Cursive) -> Option<String> {
+    siv.call_on_id(LIST_ID, |v: &mut SelectView<String>| v.selection()).and_then(|s| s).map(|name| (*name).clone())
+}
+
+/// Closes the list and opens it again, to show a change.
+fn reshow(siv: &mut Cursive) {
+    siv.pop_layer();
+    show(siv);
+    super::refresh_views(siv);
+}
+
+fn create(siv: &mut Cursive) {
+    super::dialogs::prompt(siv, "New Ref (full name, e.g. 'refs/heads/rescue')", "refs/heads/", |s, name| {
+        let name = name.to_string();
+        super::dialogs::prompt(s, &format!("Point '{}' At (id or revspec)", name), "HEAD", move |s, spec| {
+            let result = super::with_current_repo(s, |repo| {
+                ref_editor::create(repo, &name, spec).map(|oid| format!("Created '{}' at {:.7}.", name, oid.to_string()))
+            });
+            if let Some(result) = result {
+                reshow(s);
+                super::report_result(s, "Create ref", result);
+            }
+        });
+    });
+}
+
+fn point_at_selected(siv: &mut Cursive) {
+    let name = match selected(siv) {
+        Some(name) => name,
+        None => return,
+    };
+
+    super::dialogs::prompt(siv, &format!("Point '{}' At (id or revspec)", name), "", move |s, spec| {
+        let (name, spec) = (name.clone(), spec.to_string());
+        let text = format!("Point '{}' at '{}'?\n\nThe working tree is not updated, even if '{}' is checked out. {}",
+                           name, spec, name, loss_warning(s, &name, "anything only reachable from its old target"));
+        let expected = name.clone();
+        super::dialogs::confirm_typed(s, "Point Ref At", text, &expected, move |s| {
+            let result = super::with_current_repo(s, |repo| {
+                ref_editor::point_at(repo, &name, &spec).map(|backup| done(&format!("Pointed '{}' at '{}'", name, spec), backup))
+            });
+            if let Some(result) = result {
+                reshow(s);
+                super::report_result(s, "Point ref", result);
+            }
+        });
+    });
+}
+
+fn delete_selected(siv: &mut Cursive) {
+    let name = match selected(siv) {
+        Some(name) => name,
+        None => return,
+    };
+
+    let text = format!("Delete the ref '{}'?\n\n{}", name, loss_warning(siv, &name, "commits only reachable from it"));
+    let expected = name.clone();
+    super::dialogs::confirm_typed(siv, "Delete Ref", text, &expected, move |s| {
+        let result = super::with_current_repo(s, |repo| {
+            ref_editor::delete(repo, &name).map(|backup| done(&format!("Deleted '{}'", name), backup))
+        });
+        if let Some(result) = result {
+            reshow(s);
+            super::report_result(s, "Delete ref", result);
+        }
+    });
+}
+
+/// Says what may be lost by changing the ref `name`, and whether a backup
+/// is made first. `at_risk` is what may be lost, e.g. 'commits only
+/// reachable from it'.
+fn loss_warning(siv: &mut Cursive, name: &str, at_risk: &str) -> String {
+    match super::with_current_repo(siv, |repo| ref_editor::no_backup(repo, name)) {
+        Some(Ok(Some(NoBackup::IsBackup))) =>
+            format!("No backup will be made, since it is a backup itself, so {} may be lost.", at_risk),
+        Some(Ok(Some(NoBackup::Symbolic))) =>
+            "No backup will be made, since it is a symbolic ref; the ref it points at is left as it is.".to_string(),
+        _ => format!("A backup is made first; {} may be lost once the backup is deleted.", at_risk),
+    }
+}
+
+fn done(what: &str, backup: Option<String>) -> String {
+    match backup {
+        Some(backup) => format!("{}; the old target is kept in backup '{}'.", what, backup),
+        None => format!("{}.", what),
+    }
+}
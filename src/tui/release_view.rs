@@ -0,0 +1,83 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable};
+use cursive::views::{Checkbox, Dialog, EditView, LinearLayout, TextArea, TextView};
+use config;
+use release;
+use remotes;
+
+const TAG_ID: &str = "release_tag";
+const SIGN_ID: &str = "release_sign";
+const MESSAGE_ID: &str = "release_message";
+
+/// Guides the user through releasing HEAD: suggests the next version from
+/// the Conventional Commits since the last release tag, shows the changelog
+/// as the tag's message, creates the tag and offers to push it.
+pub fn show(siv: &mut Cursive) {
+    let config = config::current();
+    let result = super::with_current_repo(siv, |repo| {
+        release::plan(repo, &config.changelog).map(|plan| (plan, release::signs_tags(repo)))
+    });
+    let (plan, sign) = match result {
+        Some(Ok(result)) => result,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Plan release", Err(e)),
+        None => return,
+    };
+
+    let summary = match plan.previous {
+        Some(ref p) if plan.commits == 0 => return siv.add_layer(Dialog::info(format!("There are no commits since '{}'.", p.tag))),
+        Some(ref p) => format!("{} commits since '{}'; suggesting {} because {}.", plan.commits, p.tag, plan.tag, plan.bump.reason()),
+        None => format!("There are no release tags yet; suggesting {} for the first release.", plan.tag),
+    };
+
+    let mut checkbox = Checkbox::new();
+    checkbox.set_checked(sign);
+    let layout = LinearLayout::vertical()
+        .child(TextView::new(summary))
+        .child(TextView::new("\nTag:"))
+        .child(EditView::new().content(plan.tag.clone()).with_id(TAG_ID).min_width(30))
+        .child(LinearLayout::horizontal()
+            .child(checkbox.with_id(SIGN_ID))
+            .child(TextView::new(" Sign the tag with gpg")))
+        .child(TextView::new("\nMessage:"))
+        .child(TextArea::new().content(plan.message.clone()).with_id(MESSAGE_ID).min_width(72).min_height(12));
+
+    siv.add_layer(Dialog::around(layout.max_width(100))
+        .title("Create Release")
+        .button("Create", create)
+        .dismiss_button("Cancel"));
+}
+
+fn create(siv: &mut Cursive) {
+    let tag = siv.call_on_id(TAG_ID, |v: &mut EditView| v.get_content().trim().to_string()).unwrap_or_default();
+    let sign = siv.call_on_id(SIGN_ID, |v: &mut Checkbox| v.is_checked()).unwrap_or(false);
+    let message = siv.call_on_id(MESSAGE_ID, |v: &mut TextArea| v.get_content().to_string()).unwrap_or_default();
+    if tag.is_empty() {
+        return siv.add_layer(Dialog::info("Enter a name for the tag."));
+    }
+
+    let result = super::with_current_repo(siv, |repo| release::create_tag(repo, &tag, &message, sign));
+    match result {
+        Some(Ok(_)) => {
+            siv.pop_layer();
+            super::log_view::refresh(siv);
+            offer_push(siv, tag);
+        },
+        Some(Err(e)) => super::report_result::<::git2::Error>(siv, "Create release tag", Err(e)),
+        None => {},
+    }
+}
+
+fn offer_push(siv: &mut Cursive, tag: String) {
+    let question = format!("Created the tag '{}'. Push it now?", tag);
+    super::dialogs::confirm(siv, "Push Release Tag", question, move |s| {
+        let tag = tag.clone();
+        super::choose_remote(s, "Push Tag To", move |s, remote| {
+            let result = super::with_current_repo(s, |repo| {
+                remotes::push_tag(repo, remote, &tag).map(|_| format!("Pushed tag '{}' to '{}'.", tag, remote))
+            });
+            if let Some(result) = result {
+                super::report_result(s, "Push tag", result);
+            }
+        });
+    });
+}
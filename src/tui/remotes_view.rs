@@ -0,0 +1,129 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, TextView};
+use git2::{Direction, Repository};
+use error::OafError;
+use refspecs::{self, Refspec};
+use remotes;
+use super::notifications::{self, Level};
+use super::progress_dialog;
+
+/// Asks which remote to look at, then shows its URL and refspecs with
+/// buttons to edit them.
+pub fn show(siv: &mut Cursive) {
+    super::choose_remote(siv, "Remotes", |s, remote| show_remote(s, remote));
+}
+
+fn show_remote(siv: &mut Cursive, remote: &str) {
+    let loaded = super::with_current_repo(siv, |repo| -> Result<_, ::git2::Error> {
+        Ok((remotes::remote_url(repo, remote)?,
+            refspecs::load(repo, remote, Direction::Fetch)?,
+            refspecs::load(repo, remote, Direction::Push)?))
+    });
+    let (url, fetch, push) = match loaded {
+        Some(Ok(loaded)) => loaded,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Load remote", Err(e)),
+        None => return,
+    };
+
+    let list = |specs: &[String], none: &str| if specs.is_empty() { format!("  {}", none) } else { format!("  {}", specs.join("\n  ")) };
+    let text = format!("URL: {}\n\nFetch refspecs:\n{}\n\nPush refspecs:\n{}", url,
+                       list(&fetch, "(none)"), list(&push, "(none, so push uses push.default)"));
+    let (fetch_remote, push_remote) = (remote.to_string(), remote.to_string());
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(20))
+        .title(format!("Remote '{}'", remote))
+        .button("Edit Fetch", move |s| { s.pop_layer(); edit(s, &fetch_remote, Direction::Fetch, &fetch); })
+        .button("Edit Push", move |s| { s.pop_layer(); edit(s, &push_remote, Direction::Push, &push); })
+        .dismiss_button("Close"));
+}
+
+/// Lets the user edit the refspecs, one per line. They are checked, and the
+/// refs they would match are shown before they are saved.
+fn edit(siv: &mut Cursive, remote: &str, direction: Direction, current: &[String]) {
+    let title = format!("{} Refspecs of '{}', One per Line", kind(direction), remote);
+    let remote = remote.to_string();
+    super::dialogs::edit_text(siv, &title, &current.join("\n"), move |s, text| {
+        match refspecs::parse_lines(text, direction) {
+            Ok(specs) => confirm(s, &remote, direction, specs),
+            Err(problems) => s.add_layer(Dialog::info(problems.join("\n")).title("Invalid Refspecs")),
+        }
+    });
+}
+
+/// Shows which refs the refspecs would match, then saves them if the user
+/// agrees. A fetch is previewed against the remote's refs, which are listed
+/// in the background since that needs the network.
+fn confirm(siv: &mut Cursive, remote: &str, direction: Direction, specs: Vec<Refspec>) {
+    let remote = remote.to_string();
+    match direction {
+        Direction::Fetch => {
+            let path = match super::with_current_repo(siv, |repo| repo.path().to_path_buf()) {
+                Some(path) => path,
+                None => return,
+            };
+            let title = format!("List the Refs of '{}'", remote);
+            let job_remote = remote.clone();
+            progress_dialog::run(siv, &title, move |cancel, progress| -> Result<Vec<String>, OafError> {
+                progress(format!("Asking '{}' for its refs...", job_remote));
+                let names = remotes::remote_heads(&Repository::open(&path)?, &job_remote)?;
+                cancel.check()?;
+                Ok(names)
+            }, move |s, names| match names {
+                Err(OafError::Cancelled) => notifications::notify(s, Level::Info, "Saving the refspecs cancelled."),
+                names => show_preview(s, &remote, direction, specs, names),
+            });
+        },
+        Direction::Push => {
+            if let Some(names) = super::with_current_repo(siv, local_ref_names) {
+                show_preview(siv, &remote, direction, specs, names);
+            }
+        },
+    }
+}
+
+fn show_preview(siv: &mut Cursive, remote: &str, direction: Direction, specs: Vec<Refspec>, names: Result<Vec<String>, OafError>) {
+    let preview = match names {
+        Ok(ref names) if specs.is_empty() => format!("With no refspecs nothing will be matched among {} refs.", names.len()),
+        Ok(names) => {
+            let matches = refspecs::preview(&specs, &names);
+            let lines: Vec<String> = matches.iter().map(|&(ref src, ref dst)| format!("  {} -> {}", src, dst)).collect();
+            format!("{} of {} refs would be matched:\n{}", matches.len(), names.len(), lines.join("\n"))
+        },
+        Err(e) => format!("The refs cannot be previewed: {}", e),
+    };
+
+    let text = format!("{}\n\n{}", specs.iter().map(|s| s.to_string()).collect::<Vec<_>>().join("\n"), preview);
+    let remote = remote.to_string();
+    siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(20).max_width(100))
+        .title(format!("Save {} Refspecs", kind(direction)))
+        .button("Save", move |s| {
+            s.pop_layer();
+            let result = super::with_current_repo(s, |repo| {
+                refspecs::save(repo, &remote, direction, &specs)
+                    .map(|_| format!("Saved {} {} refspecs for '{}'.", specs.len(), kind(direction).to_lowercase(), remote))
+            });
+            if let Some(result) = result {
+                super::report_result(s, "Save refspecs", result);
+                show_remote(s, &remote);
+            }
+        })
+        .dismiss_button("Cancel"));
+}
+
+/// The local refs, which push refspecs are matched against.
+fn local_ref_names(repo: &Repository) -> Result<Vec<String>, OafError> {
+    let mut names = Vec::new();
+    for reference in repo.references()? {
+        if let Some(name) = reference?.name() {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+fn kind(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Fetch => "Fetch",
+        Direction::Push => "Push",
+    }
+}
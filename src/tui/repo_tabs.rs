@@ -0,0 +1,58 @@
+use cursive::Cursive;
+use cursive::theme::Effect;
+use cursive::traits::{Boxable, Identifiable};
+use cursive::utils::markup::StyledString;
+use cursive::views::{LinearLayout, TextView};
+use paths;
+use repositories::RepositoryExtensions;
+
+const TABS_ID: &str = "repo_tabs";
+const BANNER_ID: &str = "repo_tabs_banner";
+
+/// Adds the bottom layer, which has a tab for each open repository with the
+/// current one highlighted. Directories that are still being opened have a
+/// placeholder tab until they are ready.
+pub fn show(siv: &mut Cursive) {
+    let layout = LinearLayout::vertical()
+        .child(TextView::new("").with_id(TABS_ID))
+        .child(TextView::new("").with_id(BANNER_ID))
+        .child(TextView::new("\nC-n and C-p switch repository. C-l shows the log.").full_screen());
+
+    siv.add_fullscreen_layer(layout);
+    refresh(siv);
+}
+
+/// Updates the tabs after a repository has been opened or made current, and
+/// the banner that warns of a merge, rebase or similar left in progress in
+/// the current repository.
+pub fn refresh(siv: &mut Cursive) {
+    let tabs = super::with_repos(|repos| {
+        let mut tabs = StyledString::new();
+        let current = repos.current().map(|r| r.path().to_path_buf());
+        for repo in repos.iter() {
+            let label = format!(" {} ", repo.display_name());
+            if current.as_ref().map(|p| p.as_path()) == Some(repo.path()) {
+                tabs.append_styled(label, Effect::Reverse);
+            } else {
+                tabs.append_plain(label);
+            }
+            tabs.append_plain("|");
+        }
+        for path in repos.pending() {
+            tabs.append_plain(format!(" {} (opening...) |", paths::compress_tilde(path).display()));
+        }
+        tabs
+    });
+
+    if let Some(tabs) = tabs {
+        siv.call_on_id(TABS_ID, |v: &mut TextView| v.set_content(tabs));
+    }
+
+    let banner = match super::in_progress_view::current(siv) {
+        Some(state) => StyledString::styled(
+            format!("\n{} Working Tree > Operation in Progress to continue or abort it.", state.banner()),
+            Effect::Bold),
+        None => StyledString::new(),
+    };
+    siv.call_on_id(BANNER_ID, |v: &mut TextView| v.set_content(banner));
+}
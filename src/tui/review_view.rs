@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use cursive::Cursive;
+use git2::Oid;
+use paths;
+use review_marks::{self, ReviewState};
+
+const CLEAR: &str = "Clear mark";
+
+/// The review state of each marked commit in the current repository.
+pub fn states(siv: &mut Cursive) -> HashMap<Oid, ReviewState> {
+    super::with_current_repo(siv, |repo| {
+        super::REVIEW_MARKS.with(|r| r.borrow().as_ref().map(|marks| marks.states(repo.path())))
+    }).and_then(|states| states).unwrap_or_default()
+}
+
+/// Asks how to mark the commit `oid`, then for a comment on it, starting from
+/// the comment already there.
+pub fn mark(siv: &mut Cursive, oid: Oid) {
+    let existing = super::with_current_repo(siv, |repo| {
+        super::REVIEW_MARKS.with(|r| r.borrow().as_ref().and_then(|marks| marks.get(repo.path(), oid)).cloned())
+    }).and_then(|mark| mark);
+
+    let mut choices: Vec<String> = ReviewState::all().iter().map(|s| s.description().to_string()).collect();
+    if existing.is_some() {
+        choices.push(CLEAR.to_string());
+    }
+    let title = format!("Review {:.7}", oid.to_string());
+    super::dialogs::choose(siv, &title, choices, move |s, choice| {
+        if choice == CLEAR {
+            return clear(s, oid);
+        }
+        let state = *ReviewState::all().iter().find(|st| st.description() == choice).unwrap();
+        let comment = existing.as_ref().map(|m| m.comment.clone()).unwrap_or_default();
+        let title = format!("{} {:.7}: Comment", state.description(), oid.to_string());
+        super::dialogs::edit_text(s, &title, &comment, move |s, comment| save(s, oid, state, comment));
+    });
+}
+
+fn save(siv: &mut Cursive, oid: Oid, state: ReviewState, comment: &str) {
+    let saved = super::with_current_repo(siv, |repo| -> io::Result<()> {
+        let summary = repo.find_commit(oid).ok().and_then(|c| c.summary().map(|s| s.to_string())).unwrap_or_default();
+        super::REVIEW_MARKS.with(|r| match *r.borrow_mut() {
+            Some(ref mut marks) => marks.set(repo.path(), oid, &summary, state, comment, ::chrono::Local::now().timestamp()),
+            None => Ok(()),
+        })
+    });
+    if let Some(saved) = saved {
+        super::report_result(siv, "Save review mark",
+                             saved.map(|_| format!("Marked {:.7} as {}.", oid.to_string(), state.description().to_lowercase())));
+        super::log_view::refresh(siv);
+    }
+}
+
+fn clear(siv: &mut Cursive, oid: Oid) {
+    let cleared = super::with_current_repo(siv, |repo| {
+        super::REVIEW_MARKS.with(|r| match *r.borrow_mut() {
+            Some(ref mut marks) => marks.clear(repo.path(), oid),
+            None => Ok(false),
+        })
+    });
+    if let Some(cleared) = cleared {
+        super::report_result(siv, "Clear review mark", cleared.map(|_| format!("Cleared the mark on {:.7}.", oid.to_string())));
+        super::log_view::refresh(siv);
+    }
+}
+
+/// Writes the comments on the current repository's commits that need work
+/// to a Markdown file.
+pub fn export_pending(siv: &mut Cursive) {
+    let text = super::with_current_repo(siv, |repo| {
+        super::REVIEW_MARKS.with(|r| r.borrow().as_ref().map(|marks| {
+            let pending = marks.pending(repo.path());
+            (pending.len(), review_marks::format_pending(&pending))
+        }))
+    }).and_then(|text| text);
+
+    let (count, text) = match text {
+        Some((0, _)) => return super::report_result::<io::Error>(siv, "Export review comments",
+                                                                  Ok("No commits are marked as needing work.".to_string())),
+        Some(text) => text,
+        None => return,
+    };
+
+    super::dialogs::prompt(siv, "Export Review Comments to File", "review.md", move |s, file| {
+        let path = paths::expand_tilde(file);
+        let result = fs::write(&path, &text)
+            .map(|_| format!("Exported comments on {} commits to '{}'.", count, path.display()));
+        super::report_result(s, "Export review comments", result);
+    });
+}
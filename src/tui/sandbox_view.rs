@@ -0,0 +1,64 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, TextView};
+use error::OafError;
+use remotes;
+use sandbox::{self, Operation, Outcome};
+use super::notifications::{self, Level};
+
+/// Asks what to merge into the current branch, then merges it in a sandbox.
+pub fn merge(siv: &mut Cursive) {
+    start(siv, "Merge into Branch in Sandbox", Operation::Merge);
+}
+
+/// Asks what to rebase the current branch onto, then rebases it in a
+/// sandbox.
+pub fn rebase(siv: &mut Cursive) {
+    start(siv, "Rebase Branch in Sandbox", Operation::Rebase);
+}
+
+/// Prompts for the revision to merge or rebase onto, offering the branch's
+/// upstream.
+fn start(siv: &mut Cursive, title: &str, make: fn(String) -> Operation) {
+    let found = super::with_current_repo(siv, |repo| {
+        remotes::current_branch_name(repo).map(|branch| {
+            let upstream = remotes::upstream_of(repo, &branch).map(|u| format!("{}/{}", u.remote, u.branch));
+            (branch, upstream)
+        })
+    });
+    let (branch, upstream) = match found {
+        Some(Some(found)) => found,
+        Some(None) => return siv.add_layer(Dialog::info("HEAD is detached; check out a branch first.")),
+        None => return,
+    };
+
+    super::dialogs::prompt(siv, title, &upstream.unwrap_or_default(), move |s, rev| {
+        let operation = make(rev.trim().to_string());
+        let result = super::with_current_repo(s, |repo| sandbox::run(repo, &branch, &operation));
+        match result {
+            Some(Ok(outcome)) => show_outcome(s, &branch, &operation, outcome),
+            Some(Err(e)) => super::report_result::<OafError>(s, "Sandboxed operation", Err(e)),
+            None => {},
+        }
+    });
+}
+
+fn show_outcome(siv: &mut Cursive, branch: &str, operation: &Operation, outcome: Outcome) {
+    match outcome {
+        Outcome::Done { new_tip, backup } => {
+            let message = format!("Finished the {} on '{}', now at {:.7}. The original is kept in backup '{}'.",
+                                  operation, branch, new_tip.to_string(), backup);
+            notifications::notify(siv, Level::Info, message);
+            super::refresh_views(siv);
+        },
+        Outcome::Unchanged => siv.add_layer(Dialog::info(format!("The {} made no change to '{}'.", operation, branch))),
+        Outcome::Conflicts { paths, output } => {
+            let paths: Vec<String> = paths.iter().map(|p| format!("  {}", p.display())).collect();
+            let text = format!("The {} stopped with conflicts, so '{}' and the working tree have not been changed.\n\n\
+                                Conflicted files:\n{}\n\n{}", operation, branch, paths.join("\n"), output.trim());
+            siv.add_layer(Dialog::around(TextView::new(text).scrollable().max_height(20).max_width(100))
+                .title("Conflicts")
+                .dismiss_button("Close"));
+        },
+    }
+}
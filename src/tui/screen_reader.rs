@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use cursive::theme::{BorderStyle, Theme};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the UI is rendered for screen readers: no borders, shadows or
+/// side by side panes, and list items written out as labelled sentences
+/// rather than aligned columns.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+pub fn set_enabled(enabled: bool) {
+    info!("Screen reader mode is now {}", if enabled { "on" } else { "off" });
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Turns off the box-drawing parts of `theme`.
+pub fn plain_theme(theme: &Theme) -> Theme {
+    let mut theme = theme.clone();
+    theme.borders = BorderStyle::None;
+    theme.shadow = false;
+    theme
+}
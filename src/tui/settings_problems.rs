@@ -0,0 +1,60 @@
+use std::path::Path;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Scrollable};
+use cursive::views::{Dialog, TextView};
+use config::Problem;
+use super::notifications::{self, Level};
+
+/// Shows the mistakes found in the settings files as oaf started, grouped by
+/// file, so the user can fix them all at once. They are also added to the
+/// notification history.
+pub fn show(siv: &mut Cursive, problems: &[Problem]) {
+    if problems.is_empty() {
+        return;
+    }
+
+    for problem in problems {
+        notifications::record(Level::Warning, format!("{}: {}", problem.file.display(), problem));
+    }
+
+    siv.add_layer(Dialog::around(TextView::new(describe(problems)).scrollable().max_height(20).max_width(80))
+        .title("Problems With Your Settings")
+        .dismiss_button("Close"));
+}
+
+fn describe(problems: &[Problem]) -> String {
+    let mut text = String::new();
+    let mut file: Option<&Path> = None;
+    for problem in problems {
+        if file != Some(&problem.file) {
+            if file.is_some() {
+                text.push('\n');
+            }
+            text.push_str(&format!("In '{}':\n", problem.file.display()));
+            file = Some(&problem.file);
+        }
+        text.push_str(&format!("  {}\n", problem));
+    }
+    text.push_str("\nThese settings are ignored until they are fixed.");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_problems_by_file() {
+        let config = Path::new("/cfg/config.toml");
+        let logging = Path::new("/cfg/logging.toml");
+        let problems = vec![
+            Problem::new(config, Some("display.colours".to_string()), "bad colours"),
+            Problem::new(config, None, "bad file"),
+            Problem::new(logging, None, "bad appender"),
+        ];
+
+        assert_eq!(describe(&problems), "In '/cfg/config.toml':\n  display.colours: bad colours\n  bad file\n\n\
+                                         In '/cfg/logging.toml':\n  bad appender\n\n\
+                                         These settings are ignored until they are fixed.");
+    }
+}
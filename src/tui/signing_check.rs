@@ -0,0 +1,45 @@
+//! The check made before committing that the policy on signed commits is
+//! met.
+
+use cursive::Cursive;
+use cursive::views::Dialog;
+use config;
+use signing::{self, Enforcement, PolicyCheck, SigningSetup};
+use super::dialogs;
+
+/// Checks the policy on signed commits for the branch being committed on,
+/// then calls `on_continue` with the title for the commit message editor,
+/// which names the key the commit will be signed with. If the branch needs
+/// signed commits and they will not be signed the commit is refused, or the
+/// user is warned and asked whether to go on, as 'signing.enforcement' says.
+pub fn confirm<F>(siv: &mut Cursive, on_continue: F)
+    where F: Fn(&mut Cursive, &str) + 'static
+{
+    let policy = config::current().signing.clone();
+    let found = super::with_current_repo(siv, |repo| SigningSetup::load(repo).map(|setup| {
+        let check = signing::check_policy(repo, &setup, &policy.required_branches);
+        (setup, check)
+    }));
+    let (setup, check) = match found {
+        Some(Ok(found)) => found,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Check commit signing", Err(e)),
+        None => return,
+    };
+
+    let title = if setup.sign_commits {
+        format!("Commit Message - signed with {}", setup.key_description())
+    } else {
+        "Commit Message".to_string()
+    };
+    match check {
+        PolicyCheck::Unmet { branch, problem } => {
+            let text = format!("Commits on '{}' must be signed, but {}", branch, problem);
+            match policy.enforcement {
+                Enforcement::Refuse => siv.add_layer(Dialog::info(text).title("Commit Refused")),
+                Enforcement::Warn => dialogs::confirm(siv, "Unsigned Commit", format!("{}\n\nCommit anyway?", text),
+                                                      move |s| on_continue(s, &title)),
+            }
+        },
+        PolicyCheck::Met { .. } | PolicyCheck::NotRequired => on_continue(siv, &title),
+    }
+}
@@ -0,0 +1,78 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable};
+use cursive::views::{Checkbox, Dialog, LinearLayout, TextArea, TextView};
+use sparse::{self, SparseCheckout};
+
+const CONE_ID: &str = "sparse_cone";
+const PATTERNS_ID: &str = "sparse_patterns";
+
+/// Shows the sparse checkout patterns of the current repository for editing,
+/// with buttons to save and apply them, reapply them or turn the sparse
+/// checkout off.
+pub fn show(siv: &mut Cursive) {
+    let sparse = match super::with_current_repo(siv, |repo| SparseCheckout::load(repo)) {
+        Some(sparse) => sparse,
+        None => return,
+    };
+
+    let enabled = sparse.is_some();
+    let sparse = sparse.unwrap_or(SparseCheckout { cone: true, patterns: Vec::new() });
+    let summary = if enabled {
+        "This repository has a sparse checkout. In cone mode list directories, one per line; \
+         otherwise list patterns as in .gitignore."
+    } else {
+        "This repository does not have a sparse checkout. List the directories to keep in the \
+         working tree, one per line, then save to start one."
+    };
+
+    let mut cone = Checkbox::new();
+    cone.set_checked(sparse.cone);
+    let layout = LinearLayout::vertical()
+        .child(TextView::new(summary))
+        .child(LinearLayout::horizontal()
+            .child(cone.with_id(CONE_ID))
+            .child(TextView::new(" Cone mode (directories only)")))
+        .child(TextView::new("\nPatterns:"))
+        .child(TextArea::new().content(sparse.edit_text()).with_id(PATTERNS_ID).min_width(60).min_height(10));
+
+    let mut dialog = Dialog::around(layout.max_width(80))
+        .title("Sparse Checkout")
+        .button("Save and Apply", save);
+    if enabled {
+        dialog = dialog
+            .button("Reapply", move |s| {
+                let sparse = sparse.clone();
+                finish(s, "Reapply sparse checkout", move |repo| sparse::apply(repo, Some(&sparse)));
+            })
+            .button("Disable", |s| {
+                super::dialogs::confirm(s, "Disable Sparse Checkout", "Put every file back in the working tree?", |s| {
+                    finish(s, "Disable sparse checkout", sparse::disable);
+                });
+            });
+    }
+    siv.add_layer(dialog.dismiss_button("Close"));
+}
+
+fn save(siv: &mut Cursive) {
+    let cone = siv.call_on_id(CONE_ID, |v: &mut Checkbox| v.is_checked()).unwrap_or(true);
+    let text = siv.call_on_id(PATTERNS_ID, |v: &mut TextArea| v.get_content().to_string()).unwrap_or_default();
+    let sparse = sparse::from_edit_text(&text, cone);
+    if sparse.patterns.is_empty() {
+        return siv.add_layer(Dialog::info("Enter at least one pattern."));
+    }
+    finish(siv, "Save sparse checkout", move |repo| sparse::save(repo, &sparse));
+}
+
+/// Runs `action`, closes the dialog if it worked and reports what it did.
+fn finish<F>(siv: &mut Cursive, operation: &str, action: F)
+    where F: FnOnce(&::git2::Repository) -> Result<sparse::Applied, ::error::OafError>
+{
+    match super::with_current_repo(siv, action) {
+        Some(Ok(applied)) => {
+            siv.pop_layer();
+            super::report_result::<::error::OafError>(siv, operation, Ok(applied.description()));
+        },
+        Some(Err(e)) => super::report_result::<::error::OafError>(siv, operation, Err(e)),
+        None => {},
+    }
+}
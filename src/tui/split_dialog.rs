@@ -0,0 +1,160 @@
+use std::cell::RefCell;
+use std::path::PathBuf;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{Checkbox, Dialog, EditView, LinearLayout, TextView};
+use git2::Oid;
+use rewrite::{self, SplitPart};
+
+const MESSAGE_ID: &str = "split_message";
+
+/// The split being put together. Files are chosen for one new commit at a
+/// time; whatever is left at the end goes into a final commit.
+struct SplitState {
+    commit: Oid,
+    original_message: String,
+    remaining: Vec<PathBuf>,
+    parts: Vec<SplitPart>,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<SplitState>> = RefCell::new(None);
+}
+
+/// Starts the split assistant for `commit`, which divides it by file rather
+/// than by hunk. Pushed commits are refused before anything else happens.
+pub fn show(siv: &mut Cursive, commit: Oid) {
+    let loaded = super::with_current_repo(siv, |repo| -> Result<_, ::git2::Error> {
+        super::with_commit_graph(repo, |graph| rewrite::check_unpushed(repo, graph, commit))?;
+        rewrite::head_branch(repo)?;
+        let message = String::from_utf8_lossy(repo.find_commit(commit)?.message_bytes()).into_owned();
+        Ok((rewrite::changed_paths(repo, commit)?, message))
+    });
+
+    let (changed, original_message) = match loaded {
+        Some(Ok(loaded)) => loaded,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Load commit for split", Err(e)),
+        None => return,
+    };
+
+    if changed.len() < 2 {
+        return siv.add_layer(Dialog::info("Only commits that change at least two files can be split."));
+    }
+
+    STATE.with(|s| *s.borrow_mut() = Some(SplitState { commit, original_message, remaining: changed, parts: Vec::new() }));
+    show_next_part(siv);
+}
+
+/// Shows the files not yet assigned to a commit, with a checkbox each, and a
+/// message for the next commit.
+fn show_next_part(siv: &mut Cursive) {
+    let (commit, remaining, done) = match STATE.with(|s| {
+        s.borrow().as_ref().map(|st| (st.commit, st.remaining.clone(), st.parts.len()))
+    }) {
+        Some(state) => state,
+        None => return,
+    };
+
+    let mut files = LinearLayout::vertical();
+    for (i, path) in remaining.iter().enumerate() {
+        files.add_child(LinearLayout::horizontal()
+            .child(Checkbox::new().with_id(checkbox_id(i)))
+            .child(TextView::new(format!(" {}", path.display()))));
+    }
+
+    let layout = LinearLayout::vertical()
+        .child(TextView::new(format!(
+            "Choose the files for commit {} of the split. Files left over at the end go into a \
+             final commit with the original message. Files cannot be divided: all the changes to \
+             a file go into the same commit.", done + 1)))
+        .child(files.scrollable().max_height(15))
+        .child(TextView::new("\nMessage:"))
+        .child(EditView::new().with_id(MESSAGE_ID).min_width(60));
+
+    siv.add_layer(Dialog::around(layout.max_width(100))
+        .title(format!("Split {:.7}", commit.to_string()))
+        .button("Next Commit", |s| {
+            if add_part(s) {
+                s.pop_layer();
+                show_next_part(s);
+            }
+        })
+        .button("Finish", |s| {
+            if add_part(s) {
+                s.pop_layer();
+                finish(s);
+            }
+        })
+        .button("Cancel", |s| {
+            STATE.with(|st| *st.borrow_mut() = None);
+            s.pop_layer();
+        }));
+}
+
+/// Records the checked files as the next part. Returns false, after telling
+/// the user, if the part is not valid. Nothing checked is allowed when there
+/// are already parts, so that the user can finish without adding another.
+fn add_part(siv: &mut Cursive) -> bool {
+    let count = STATE.with(|s| s.borrow().as_ref().map_or(0, |st| st.remaining.len()));
+    let checked: Vec<usize> = (0..count)
+        .filter(|&i| siv.call_on_id(&checkbox_id(i), |c: &mut Checkbox| c.is_checked()).unwrap_or(false))
+        .collect();
+    let message = siv.call_on_id(MESSAGE_ID, |v: &mut EditView| v.get_content().to_string()).unwrap_or_default();
+
+    let error = STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        let state = state.as_mut()?;
+        if checked.is_empty() {
+            return if state.parts.is_empty() { Some("Choose some files for the first commit.") } else { None };
+        }
+        if rewrite::clean_message(&message).is_none() {
+            return Some("Enter a message for the commit.");
+        }
+
+        let paths: Vec<PathBuf> = checked.iter().map(|&i| state.remaining[i].clone()).collect();
+        state.remaining = state.remaining.iter().filter(|p| !paths.contains(p)).cloned().collect();
+        state.parts.push(SplitPart { paths, message });
+        None
+    });
+
+    match error {
+        Some(error) => {
+            siv.add_layer(Dialog::info(error));
+            false
+        },
+        None => true,
+    }
+}
+
+fn finish(siv: &mut Cursive) {
+    let state = match STATE.with(|s| s.borrow_mut().take()) {
+        Some(state) => state,
+        None => return,
+    };
+
+    let preview: Vec<String> = state.parts.iter()
+        .map(|p| format!("{} ({} files)", p.message.lines().next().unwrap_or(""), p.paths.len()))
+        .chain(if state.remaining.is_empty() {
+            None
+        } else {
+            Some(format!("{} ({} files)", state.original_message.lines().next().unwrap_or(""), state.remaining.len()))
+        })
+        .collect();
+
+    let text = format!("Replace {:.7} with these commits and rewrite the commits after it?\n\n{}",
+                       state.commit.to_string(), preview.join("\n"));
+    super::dialogs::confirm(siv, "Split Commit", text, move |s| {
+        let result = super::with_current_repo(s, |repo| {
+            super::with_commit_graph(repo, |graph| rewrite::split(repo, graph, state.commit, &state.parts))
+                .map(|commits| format!("Split {:.7} into {} commits.", state.commit.to_string(), commits.len()))
+        });
+        if let Some(result) = result {
+            super::report_result::<::git2::Error>(s, "Split commit", result);
+            super::log_view::refresh(s);
+        }
+    });
+}
+
+fn checkbox_id(index: usize) -> String {
+    format!("split_file_{}", index)
+}
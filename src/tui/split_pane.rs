@@ -0,0 +1,216 @@
+use cursive::Printer;
+use cursive::direction::{Direction, Orientation};
+use cursive::event::{AnyCb, Event, EventResult, Key};
+use cursive::vec::Vec2;
+use cursive::view::{Selector, View};
+
+/// The smallest share of the space either pane can be given, as a percentage.
+pub const MIN_PERCENT: usize = 10;
+
+/// Two views side by side, or one above the other, with a line between
+/// them. The first view gets `percent` of the space and the second the rest,
+/// so the split keeps its proportions when the terminal is resized. Tab and
+/// Shift-Tab move the focus between the views. The focused view can be
+/// zoomed to fill the whole space, hiding the other until it is unzoomed.
+pub struct SplitPane {
+    orientation: Orientation,
+    percent: usize,
+    first: Box<dyn View>,
+    second: Box<dyn View>,
+    second_focused: bool,
+    zoomed: bool,
+    // The length of the first pane along the main axis, as of the last layout.
+    first_len: usize,
+}
+
+impl SplitPane {
+    pub fn new<A: View, B: View>(orientation: Orientation, percent: usize, first: A, second: B) -> Self {
+        SplitPane {
+            orientation,
+            percent: clamp_percent(percent),
+            first: Box::new(first),
+            second: Box::new(second),
+            second_focused: false,
+            zoomed: false,
+            first_len: 0,
+        }
+    }
+
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    pub fn percent(&self) -> usize {
+        self.percent
+    }
+
+    /// Moves the splitter, keeping both panes at least `MIN_PERCENT` wide.
+    pub fn set_percent(&mut self, percent: usize) {
+        self.percent = clamp_percent(percent);
+    }
+
+    /// Zooms the focused view to fill the whole space, or puts the split
+    /// back as it was.
+    pub fn toggle_zoom(&mut self) {
+        self.zoomed = !self.zoomed;
+    }
+
+    fn second_offset(&self) -> Vec2 {
+        if self.zoomed {
+            Vec2::zero()
+        } else {
+            self.orientation.make_vec(self.first_len + 1, 0)
+        }
+    }
+
+    fn focused_view(&self) -> &dyn View {
+        if self.second_focused { &*self.second } else { &*self.first }
+    }
+
+    fn switch_focus(&mut self) -> EventResult {
+        let taken = if self.second_focused {
+            self.first.take_focus(Direction::front())
+        } else {
+            self.second.take_focus(Direction::back())
+        };
+
+        if taken {
+            self.second_focused = !self.second_focused;
+            EventResult::Consumed(None)
+        } else {
+            EventResult::Ignored
+        }
+    }
+}
+
+fn clamp_percent(percent: usize) -> usize {
+    percent.max(MIN_PERCENT).min(100 - MIN_PERCENT)
+}
+
+impl View for SplitPane {
+    fn draw(&self, printer: &Printer) {
+        if self.zoomed {
+            return self.focused_view().draw(printer);
+        }
+
+        let main = *printer.size.get(self.orientation);
+        let cross = *printer.size.get(self.orientation.swap());
+        let second_len = main.saturating_sub(self.first_len + 1);
+
+        self.first.draw(&printer.cropped(self.orientation.make_vec(self.first_len, cross))
+            .focused(!self.second_focused));
+
+        let splitter = self.orientation.make_vec(self.first_len, 0);
+        let plain = super::screen_reader::is_enabled();
+        match self.orientation {
+            Orientation::Horizontal => printer.print_vline(splitter, cross, if plain { "|" } else { "│" }),
+            Orientation::Vertical => printer.print_hline(splitter, cross, if plain { "-" } else { "─" }),
+        }
+
+        self.second.draw(&printer.offset(self.second_offset())
+            .cropped(self.orientation.make_vec(second_len, cross))
+            .focused(self.second_focused));
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        if self.zoomed {
+            // Tab can switch the focus while zoomed, so both views are laid
+            // out to fill the space.
+            self.first.layout(size);
+            self.second.layout(size);
+            return;
+        }
+
+        let main = *size.get(self.orientation);
+        let cross = *size.get(self.orientation.swap());
+        let available = main.saturating_sub(1);
+        self.first_len = available * self.percent / 100;
+        let second_len = available - self.first_len;
+
+        self.first.layout(self.orientation.make_vec(self.first_len, cross));
+        self.second.layout(self.orientation.make_vec(second_len, cross));
+    }
+
+    fn needs_relayout(&self) -> bool {
+        true
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        constraint
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Key(Key::Tab) | Event::Shift(Key::Tab) => return self.switch_focus(),
+            _ => {},
+        }
+
+        if self.second_focused {
+            let offset = self.second_offset();
+            self.second.on_event(event.relativized(offset))
+        } else {
+            self.first.on_event(event)
+        }
+    }
+
+    fn call_on_any<'a>(&mut self, selector: &Selector, mut callback: AnyCb<'a>) {
+        self.first.call_on_any(selector, Box::new(|any| callback(any)));
+        self.second.call_on_any(selector, Box::new(|any| callback(any)));
+    }
+
+    fn focus_view(&mut self, selector: &Selector) -> Result<(), ()> {
+        if self.first.focus_view(selector).is_ok() {
+            self.second_focused = false;
+            Ok(())
+        } else if self.second.focus_view(selector).is_ok() {
+            self.second_focused = true;
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    fn take_focus(&mut self, source: Direction) -> bool {
+        if self.first.take_focus(source) {
+            self.second_focused = false;
+            true
+        } else if self.second.take_focus(source) {
+            self.second_focused = true;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursive::event::Key;
+    use cursive::traits::Boxable;
+    use cursive::views::{EditView, TextView};
+    use super::super::test_backend::TestUi;
+
+    #[test]
+    fn panes_are_sized_by_percent() {
+        let mut ui = TestUi::new(40, 5);
+        ui.siv.add_fullscreen_layer(SplitPane::new(Orientation::Horizontal, 25,
+                                                   TextView::new("left"), TextView::new("right")).full_screen());
+        ui.render();
+        let line: Vec<char> = ui.lines()[0].chars().collect();
+        assert!(line.starts_with(&['l', 'e', 'f', 't']), "{}", ui.screen());
+        assert_eq!(line.iter().position(|&c| c == 'r'), Some(10), "{}", ui.screen());
+    }
+
+    #[test]
+    fn tab_moves_the_focus_to_the_other_pane() {
+        let mut ui = TestUi::new(40, 5);
+        ui.siv.add_fullscreen_layer(SplitPane::new(Orientation::Vertical, 50,
+                                                   EditView::new(), EditView::new()).full_screen());
+        ui.press(vec![Event::Char('a'), Event::Key(Key::Tab), Event::Char('b')]);
+        let lines = ui.lines();
+        let a = lines.iter().position(|l| l.contains('a')).expect("first pane");
+        let b = lines.iter().position(|l| l.contains('b')).expect("second pane");
+        assert!(a < b, "{}", ui.screen());
+    }
+}
@@ -0,0 +1,546 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use cursive::Cursive;
+use cursive::direction::Orientation;
+use cursive::traits::{Boxable, Identifiable};
+use cursive::utils::markup::StyledString;
+use cursive::view::View;
+use cursive::views::{Dialog, IdView, LinearLayout, OnEventView, TextView};
+use git2::{Oid, Repository, Status};
+use config;
+use discard;
+use file_content;
+use export::Table;
+use history::{self, LogEntry, LogFilter};
+use large_repo;
+use patch::{self, DiffLimits};
+use session::{self, SplitOrientation, StatusLayout};
+use short_path::PathShortener;
+use sparse::SparseCheckout;
+use staging;
+use status::{self, StatusRow, StatusTree};
+use text_width;
+use untracked;
+use super::diff_view::{self, DiffSource, DiffTitle, DiffView};
+use super::overview::Overview;
+use super::split_pane::SplitPane;
+use super::fuzzy_filter::FilterView;
+use super::virtual_list::VirtualList;
+
+const SPLIT_ID: &str = "split_view_pane";
+const DIFF_ID: &str = "split_view_diff";
+const DIFF_OVERVIEW_ID: &str = "split_view_diff_overview";
+const DIFF_TITLE_ID: &str = "split_view_diff_title";
+const STATUS_ID: &str = "split_view_status";
+const OUTSIDE_SPARSE: &str = " (outside sparse checkout)";
+const MIXED_EOL: &str = " (mixed line endings)";
+
+/// How far the splitter moves per key press, as a percentage of the screen.
+const SPLITTER_STEP: usize = 5;
+
+/// Shows the log beside the diff of the selected commit.
+pub fn show_log_and_diff(siv: &mut Cursive) {
+    let sort = session::current().log_view.sort;
+    let large_config = config::current().large_repo.clone();
+    let entries = super::with_current_repo(siv, |repo| {
+        let limit = if large_repo::is_large(repo, &large_config) { large_config.log_limit } else { super::log_view::LOAD_LIMIT };
+        history::load(repo, None, &LogFilter::default(), sort, limit, &super::identity_mailmap(repo))
+    });
+    let entries = match entries {
+        Some(Ok(entries)) => entries,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Load log", Err(e)),
+        None => return,
+    };
+
+    let first = entries.first().map(|entry| entry.oid);
+    let mut list = VirtualList::new(|entry: &LogEntry| format!("{:.7} {}", entry.oid.to_string(), entry.summary));
+    list.set_items(entries);
+    list.set_on_select(|s, entry: &LogEntry| show_commit_diff(s, entry.oid));
+    list.set_on_submit(|s, entry: &LogEntry| super::show_commit(s, entry.oid));
+
+    show(siv, "Log and Diff", "", list);
+    if let Some(oid) = first {
+        show_commit_diff(siv, oid);
+    }
+}
+
+/// Shows the status view, unless a merge, rebase or similar is in progress,
+/// when the operation is shown instead so that it can be continued or
+/// aborted.
+pub fn show_status_and_diff(siv: &mut Cursive) {
+    if !super::in_progress_view::show(siv) {
+        show_status(siv);
+    }
+}
+
+/// Shows the changed files in the working tree beside the diff of the
+/// selected file. Untracked files are grouped by directory; Enter expands or
+/// collapses a directory, and the files in it can be staged, ignored or
+/// deleted together. If the index layout was chosen, the staged and
+/// unstaged files are listed separately instead.
+pub fn show_status(siv: &mut Cursive) {
+    if session::current().status_layout == StatusLayout::Index {
+        return super::index_view::show(siv);
+    }
+    let (tree, mixed_eol) = match load_status(siv) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let rows = tree.rows(&HashSet::new());
+    let first = rows.first().cloned();
+
+    // Files outside a sparse checkout are marked, since they are not
+    // expected in the working tree, as are files whose lines do not all end
+    // the same way.
+    let sparse = super::with_current_repo(siv, SparseCheckout::load).and_then(|s| s);
+    let notes = move |row: &StatusRow| {
+        let mut notes = String::new();
+        if let StatusRow::File { ref path, .. } = *row {
+            if sparse.as_ref().map_or(false, |sparse| !sparse.includes(path)) {
+                notes.push_str(OUTSIDE_SPARSE);
+            }
+            if STATUS.with(|st| st.borrow().mixed_eol.contains(path)) {
+                notes.push_str(MIXED_EOL);
+            }
+        }
+        notes
+    };
+    let fit_notes = notes.clone();
+
+    let screen_reader = super::screen_reader::is_enabled();
+    let mut list = FilterView::new(move |row: &StatusRow| format!("{}{}", row_label(row, screen_reader), notes(row)));
+    if !screen_reader {
+        // Take directories out of the middle of long paths, rather than
+        // cutting off the end.
+        let shortener = PathShortener::new(rows.iter().map(|row| row.path().display().to_string()));
+        list.set_fit(move |row: &StatusRow, width| match *row {
+            StatusRow::File { ref path, status, depth: 0 } => {
+                let code = status::short_code(status);
+                let note = fit_notes(row);
+                let path_width = width.saturating_sub(text_width::width(&code) + text_width::width(&note) + 1);
+                format!("{} {}{}", code, shortener.shorten(&path.display().to_string(), path_width), note)
+            },
+            _ => format!("{}{}", row_label(row, false), fit_notes(row)),
+        });
+    }
+    STATUS.with(|st| *st.borrow_mut() = StatusState { tree, expanded: HashSet::new(), mixed_eol });
+    list.set_items(rows);
+    list.set_on_select(|s, row: &StatusRow| show_row(s, row));
+    list.set_on_submit(|s, row: &StatusRow| toggle_dir(s, row));
+    let list = OnEventView::new(list.with_id(STATUS_ID))
+        .on_event('a', stage_untracked)
+        .on_event('i', ignore_untracked)
+        .on_event('d', delete_untracked)
+        .on_event('e', export_status)
+        .on_event('f', show_file_info)
+        .on_event('h', show_file_revisions)
+        .on_event('s', super::diffstat_view::show)
+        .on_event('l', |s| switch_status_layout(s, StatusLayout::Index));
+
+    show(siv, "Status and Diff",
+         "  [Enter] expand  [a] stage  [i] ignore  [d] delete untracked  [f] file info  [h] history  [s] diffstat  [e] export  [l] staged/unstaged",
+         list);
+    match first {
+        Some(row) => show_row(siv, &row),
+        None => set_diff(siv, StyledString::plain("There are no uncommitted changes.")),
+    }
+}
+
+/// The status shown in the status view, which of its directories of
+/// untracked files are expanded and which files have mixed line endings.
+#[derive(Default)]
+struct StatusState {
+    tree: StatusTree,
+    expanded: HashSet<PathBuf>,
+    mixed_eol: HashSet<PathBuf>,
+}
+
+thread_local! {
+    static STATUS: RefCell<StatusState> = RefCell::new(StatusState::default());
+    /// The context around each change in the diff pane, which goes back to
+    /// git's usual when another diff is chosen.
+    static CONTEXT_LINES: Cell<u32> = Cell::new(patch::DEFAULT_CONTEXT_LINES);
+}
+
+/// Loads the changed files and finds those with mixed line endings. In a
+/// large repository the files are not read.
+fn load_status(siv: &mut Cursive) -> Option<(StatusTree, HashSet<PathBuf>)> {
+    let large_config = config::current().large_repo.clone();
+    let max_bytes = config::current().limits.diff_limits().max_file_bytes;
+    let files = super::with_current_repo(siv, |repo| {
+        let large = large_repo::is_large(repo, &large_config);
+        status::changed_files(repo, !large).map(|files| {
+            let mixed_eol = if large { HashSet::new() } else { mixed_line_endings(repo, &files, max_bytes) };
+            (files, mixed_eol)
+        })
+    });
+    match files {
+        Some(Ok((files, mixed_eol))) => Some((StatusTree::new(files), mixed_eol)),
+        Some(Err(e)) => { super::report_result::<::git2::Error>(siv, "Load working tree status", Err(e)); None },
+        None => None,
+    }
+}
+
+/// The files in the working tree whose lines do not all end the same way.
+/// Binary files and files over `max_bytes` are skipped.
+fn mixed_line_endings(repo: &Repository, files: &[(PathBuf, Status)], max_bytes: Option<u64>) -> HashSet<PathBuf> {
+    let workdir = match repo.workdir() {
+        Some(workdir) => workdir,
+        None => return HashSet::new(),
+    };
+    let config = config::current();
+    files.iter()
+        .filter(|&&(ref path, _)| match file_content::read(&workdir.join(path), max_bytes, file_content::fallback_for(&config.encoding, path)) {
+            Ok(Some(text)) => text.line_endings.is_mixed(),
+            _ => false,
+        })
+        .map(|&(ref path, _)| path.clone())
+        .collect()
+}
+
+fn row_label(row: &StatusRow, screen_reader: bool) -> String {
+    let name = |path: &Path, depth: usize| if depth == 0 {
+        path.display().to_string()
+    } else {
+        path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned())
+    };
+
+    match *row {
+        StatusRow::File { ref path, status, .. } if screen_reader => format!("{}: {}", path.display(), status::describe(status)),
+        StatusRow::File { ref path, status, depth } => format!("{} {}{}", status::short_code(status), "  ".repeat(depth), name(path, depth)),
+        StatusRow::Dir { ref path, files, expanded, .. } if screen_reader => {
+            format!("{}/: {} untracked files, {}", path.display(), files, if expanded { "expanded" } else { "collapsed" })
+        },
+        StatusRow::Dir { ref path, files, depth, expanded } => {
+            format!("?? {}{} {}/ ({} files)", "  ".repeat(depth), if expanded { "-" } else { "+" }, name(path, depth), files)
+        },
+    }
+}
+
+fn show_row(siv: &mut Cursive, row: &StatusRow) {
+    match *row {
+        StatusRow::File { ref path, .. } => show_file_diff(siv, path.clone()),
+        StatusRow::Dir { ref path, files, .. } => {
+            let listing = STATUS.with(|st| st.borrow().tree.untracked_files(row));
+            let mut text = format!("{} untracked files in {}/:\n\n", files, path.display());
+            for file in listing {
+                text.push_str(&format!("{}\n", file.display()));
+            }
+            set_diff(siv, StyledString::plain(text));
+        },
+    }
+}
+
+fn toggle_dir(siv: &mut Cursive, row: &StatusRow) {
+    if let StatusRow::Dir { ref path, expanded, .. } = *row {
+        STATUS.with(|st| {
+            let mut st = st.borrow_mut();
+            if expanded {
+                st.expanded.retain(|p| !p.starts_with(path));
+            } else {
+                st.expanded.insert(path.clone());
+            }
+        });
+        update_rows(siv);
+    }
+}
+
+/// Shows the rows of the status again, after a directory is expanded or
+/// collapsed or the status is reloaded.
+fn update_rows(siv: &mut Cursive) {
+    let rows = STATUS.with(|st| {
+        let st = st.borrow();
+        st.tree.rows(&st.expanded)
+    });
+    siv.call_on_id(STATUS_ID, |v: &mut FilterView<StatusRow>| v.set_items(rows));
+}
+
+/// Closes the status view and opens it again laid out as `layout`, which
+/// is remembered for next time.
+pub fn switch_status_layout(siv: &mut Cursive, layout: StatusLayout) {
+    session::update(|session| session.status_layout = layout);
+    siv.pop_layer();
+    show_status(siv);
+}
+
+/// Reloads the status in the status view, if it is open.
+pub fn refresh(siv: &mut Cursive) {
+    if siv.find_id::<FilterView<StatusRow>>(STATUS_ID).is_some() {
+        reload_status(siv);
+    }
+}
+
+/// Loads the status again, after files have been staged or deleted.
+fn reload_status(siv: &mut Cursive) {
+    if let Some((tree, mixed_eol)) = load_status(siv) {
+        STATUS.with(|st| {
+            let mut st = st.borrow_mut();
+            st.tree = tree;
+            st.mixed_eol = mixed_eol;
+        });
+        update_rows(siv);
+    }
+}
+
+/// The selected row, if it is untracked, and the untracked files it stands
+/// for.
+fn selected_untracked(siv: &mut Cursive) -> Option<(StatusRow, Vec<PathBuf>)> {
+    let row = siv.call_on_id(STATUS_ID, |v: &mut FilterView<StatusRow>| v.selection())??;
+    if !row.is_untracked() {
+        siv.add_layer(Dialog::info("Select an untracked file or directory."));
+        return None;
+    }
+    let files = STATUS.with(|st| st.borrow().tree.untracked_files(&row));
+    Some((row, files))
+}
+
+fn stage_untracked(siv: &mut Cursive) {
+    let (row, files) = match selected_untracked(siv) {
+        Some(selected) => selected,
+        None => return,
+    };
+    let result = super::with_current_repo(siv, |repo| {
+        untracked::stage(repo, &files).map(|_| format!("Staged {} files from '{}'.", files.len(), row.path().display()))
+    });
+    if let Some(result) = result {
+        super::report_result(siv, "Stage untracked files", result);
+        reload_status(siv);
+    }
+}
+
+fn ignore_untracked(siv: &mut Cursive) {
+    let (row, _) = match selected_untracked(siv) {
+        Some(selected) => selected,
+        None => return,
+    };
+    let is_dir = match row { StatusRow::Dir { .. } => true, StatusRow::File { .. } => false };
+    let result = super::with_current_repo(siv, |repo| {
+        untracked::ignore(repo, row.path(), is_dir).map(|_| format!("Added '{}' to .gitignore.", row.path().display()))
+    });
+    if let Some(result) = result {
+        super::report_result(siv, "Ignore untracked files", result);
+        reload_status(siv);
+    }
+}
+
+fn delete_untracked(siv: &mut Cursive) {
+    let (row, files) = match selected_untracked(siv) {
+        Some(selected) => selected,
+        None => return,
+    };
+    let text = format!("Move {} untracked files in '{}' to the trash?", files.len(), row.path().display());
+    super::dialogs::confirm(siv, "Delete Untracked Files", text, move |s| {
+        let result = super::with_current_repo(s, |repo| {
+            discard::delete_files(repo, ::PATHS.trash_dir(), &files)
+                .map(|n| format!("Moved {} untracked files to the trash.", n))
+        });
+        if let Some(result) = result {
+            super::report_result(s, "Delete untracked files", result);
+            reload_status(s);
+        }
+    });
+}
+
+/// Exports the changed files, as filtered. The files in a directory of
+/// untracked files are exported whether it is expanded or not.
+fn show_file_info(siv: &mut Cursive) {
+    match siv.call_on_id(STATUS_ID, |v: &mut FilterView<StatusRow>| v.selection()) {
+        Some(Some(StatusRow::File { path, .. })) => super::show_file_info(siv, &path),
+        Some(Some(StatusRow::Dir { .. })) => siv.add_layer(Dialog::info("Select a file.")),
+        _ => {},
+    }
+}
+
+/// Shows the selected file as it is at HEAD, from where its earlier
+/// revisions can be stepped through.
+fn show_file_revisions(siv: &mut Cursive) {
+    let path = match siv.call_on_id(STATUS_ID, |v: &mut FilterView<StatusRow>| v.selection()) {
+        Some(Some(StatusRow::File { path, .. })) => path,
+        Some(Some(StatusRow::Dir { .. })) => return siv.add_layer(Dialog::info("Select a file.")),
+        _ => return,
+    };
+    match super::with_current_repo(siv, |repo| history::head_commit(repo).map(|head| head.map(|c| c.id()))) {
+        Some(Ok(Some(head))) => super::file_revisions_view::show_file(siv, &path, head),
+        Some(Ok(None)) => siv.add_layer(Dialog::info("There are no commits yet.")),
+        Some(Err(e)) => super::report_result::<::git2::Error>(siv, "File history", Err(e)),
+        None => {},
+    }
+}
+
+fn export_status(siv: &mut Cursive) {
+    let rows = siv.call_on_id(STATUS_ID, |v: &mut FilterView<StatusRow>| v.shown()).unwrap_or_default();
+    let mut files = Vec::new();
+    for row in rows {
+        match row {
+            StatusRow::File { path, status, .. } => files.push((path, status)),
+            StatusRow::Dir { .. } => {
+                let untracked = STATUS.with(|st| st.borrow().tree.untracked_files(&row));
+                files.extend(untracked.into_iter().map(|path| (path, Status::WT_NEW)));
+            },
+        }
+    }
+
+    let mut table = Table::new(vec!["status", "path", "description"]);
+    for (path, status) in files {
+        table.add_row(vec![status::short_code(status).trim().to_string(), path.display().to_string(), status::describe(status)]);
+    }
+    super::export_table(siv, "status.csv", table);
+}
+
+/// Adds a full screen layer with `first` in one pane and the diff in the
+/// other, laid out as last saved in the session. `keys` describes the keys
+/// `first` handles, for the header.
+pub fn show<V: View>(siv: &mut Cursive, title: &str, keys: &str, first: V) {
+    let layout = session::current().split.clone();
+    // One pane above the other reads in a sensible order with a screen reader.
+    let split = if super::screen_reader::is_enabled() { SplitOrientation::Vertical } else { layout.orientation };
+    let diff_view = DiffView::new();
+    let marks = diff_view.marks();
+    let diff = LinearLayout::vertical()
+        .child(DiffTitle::new(marks.clone()).with_id(DIFF_TITLE_ID))
+        .child(Overview::new(diff_view.with_id(DIFF_ID), marks).with_id(DIFF_OVERVIEW_ID));
+    let pane = SplitPane::new(orientation(split), layout.percent, first, diff);
+
+    let header = format!("{} > {}\n[Tab] switch pane  [z] zoom  [<] [>] move splitter  [[] []] previous/next hunk  [+] [-] context  [x] expand unchanged  [o] rotate{}  [q] close",
+                         super::navigation::breadcrumb(siv), title, keys);
+    let view = OnEventView::new(LinearLayout::vertical()
+            .child(TextView::new(header))
+            .child(pane.with_id(SPLIT_ID).full_screen()))
+        .on_event('<', |s| move_splitter(s, -(SPLITTER_STEP as isize)))
+        .on_event('>', |s| move_splitter(s, SPLITTER_STEP as isize))
+        .on_event('[', |s| jump_to_hunk(s, false))
+        .on_event(']', |s| jump_to_hunk(s, true))
+        .on_event('o', rotate)
+        .on_event('L', load_in_full)
+        .on_event('s', |s| move_hunk(s, true))
+        .on_event('u', |s| move_hunk(s, false))
+        .on_event('+', |s| change_context(s, true))
+        .on_event('-', |s| change_context(s, false))
+        .on_event('x', |s| { s.call_on_id(DIFF_ID, |v: &mut DiffView| v.expand_gap()); })
+        .on_event('z', |s| { s.call_on_id(SPLIT_ID, |v: &mut SplitPane| v.toggle_zoom()); })
+        .on_event('q', |s| { s.pop_layer(); });
+
+    super::navigation::open(siv, title, view, true);
+}
+
+fn orientation(orientation: SplitOrientation) -> Orientation {
+    match orientation {
+        SplitOrientation::Horizontal => Orientation::Horizontal,
+        SplitOrientation::Vertical => Orientation::Vertical,
+    }
+}
+
+fn move_splitter(siv: &mut Cursive, delta: isize) {
+    let percent = siv.call_on_id(SPLIT_ID, |v: &mut SplitPane| {
+        v.set_percent((v.percent() as isize + delta).max(0) as usize);
+        v.percent()
+    });
+
+    if let Some(percent) = percent {
+        session::update(|session| session.split.percent = percent);
+    }
+}
+
+/// Scrolls the diff to the next, or previous, hunk.
+fn jump_to_hunk(siv: &mut Cursive, forward: bool) {
+    siv.call_on_id(DIFF_OVERVIEW_ID, |v: &mut Overview<IdView<DiffView>>| v.jump(forward));
+}
+
+/// Switches between side by side and one above the other.
+fn rotate(siv: &mut Cursive) {
+    let rotated = match session::current().split.orientation {
+        SplitOrientation::Horizontal => SplitOrientation::Vertical,
+        SplitOrientation::Vertical => SplitOrientation::Horizontal,
+    };
+
+    siv.call_on_id(SPLIT_ID, |v: &mut SplitPane| v.set_orientation(orientation(rotated)));
+    session::update(|session| session.split.orientation = rotated);
+}
+
+fn show_commit_diff(siv: &mut Cursive, oid: Oid) {
+    show_source(siv, DiffSource::Commit(oid));
+}
+
+fn show_file_diff(siv: &mut Cursive, path: PathBuf) {
+    show_source(siv, DiffSource::File(path));
+}
+
+/// Shows the diff of `source` in the diff pane, with git's usual context.
+pub fn show_source(siv: &mut Cursive, source: DiffSource) {
+    CONTEXT_LINES.with(|c| c.set(patch::DEFAULT_CONTEXT_LINES));
+    show_diff(siv, source, config::current().limits.diff_limits());
+}
+
+/// Stages, or unstages, the hunk at the top of the diff pane, if it is
+/// showing the unstaged, or staged, changes to a file. The diff is shown
+/// again afterwards, without the hunk.
+fn move_hunk(siv: &mut Cursive, stage: bool) {
+    let current = siv.call_on_id(DIFF_ID, |v: &mut DiffView| {
+        let hunk = v.marks().lock().unwrap().current_hunk();
+        v.source().cloned().and_then(|source| hunk.map(|hunk| (source, hunk)))
+    });
+    let (source, hunk) = match current {
+        Some(Some(current)) => current,
+        _ => return,
+    };
+    let context = CONTEXT_LINES.with(|c| c.get());
+    let result = match (stage, &source) {
+        (true, &DiffSource::Unstaged(ref path)) => super::with_current_repo(siv, |repo| {
+            staging::stage_hunk(repo, path, hunk, context).map(|_| format!("Staged hunk {} of '{}'.", hunk + 1, path.display()))
+        }),
+        (false, &DiffSource::Staged(ref path)) => super::with_current_repo(siv, |repo| {
+            staging::unstage_hunk(repo, path, hunk, context).map(|_| format!("Unstaged hunk {} of '{}'.", hunk + 1, path.display()))
+        }),
+        _ => return,
+    };
+
+    if let Some(result) = result {
+        let moved = result.is_ok();
+        super::report_result(siv, if stage { "Stage hunk" } else { "Unstage hunk" }, result);
+        if moved {
+            super::refresh_views(siv);
+            show_diff(siv, source, config::current().limits.diff_limits());
+        }
+    }
+}
+
+/// Loads the diff shown in the pane again, ignoring the limits.
+fn load_in_full(siv: &mut Cursive) {
+    let source = siv.call_on_id(DIFF_ID, |v: &mut DiffView| v.source().cloned());
+    if let Some(Some(source)) = source {
+        show_diff(siv, source, DiffLimits::unlimited());
+    }
+}
+
+/// Shows more, or less, of the unchanged lines around each change in the
+/// diff.
+fn change_context(siv: &mut Cursive, more: bool) {
+    let source = match siv.call_on_id(DIFF_ID, |v: &mut DiffView| v.source().cloned()) {
+        Some(Some(source)) => source,
+        _ => return,
+    };
+    CONTEXT_LINES.with(|c| c.set(diff_view::step_context(c.get(), more)));
+    show_diff(siv, source, config::current().limits.diff_limits());
+}
+
+/// Starts streaming the diff of `source` into the diff pane.
+fn show_diff(siv: &mut Cursive, source: DiffSource, limits: DiffLimits) {
+    let repo_path = match super::with_current_repo(siv, |repo| repo.path().to_path_buf()) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let limits = DiffLimits { context_lines: CONTEXT_LINES.with(|c| c.get()), ..limits };
+    let sink = siv.cb_sink().clone();
+    let wake = move || { let _ = sink.send(Box::new(|_: &mut Cursive| {})); };
+    let title = Some(source.clone());
+    siv.call_on_id(DIFF_TITLE_ID, |v: &mut DiffTitle| v.set_source(title));
+    siv.call_on_id(DIFF_ID, |v: &mut DiffView| v.show(repo_path, source, limits, wake));
+}
+
+/// Shows a message in the diff pane instead of a diff.
+pub fn set_diff(siv: &mut Cursive, text: StyledString) {
+    siv.call_on_id(DIFF_TITLE_ID, |v: &mut DiffTitle| v.set_source(None));
+    siv.call_on_id(DIFF_ID, |v: &mut DiffView| v.set_text(text));
+}
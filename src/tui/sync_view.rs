@@ -0,0 +1,100 @@
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{LinearLayout, OnEventView, Panel, SelectView, TextView};
+use git2::{Oid, Repository};
+use history::{self, LogEntry};
+use remotes;
+
+const HEADER_ID: &str = "sync_view_header";
+const INCOMING_ID: &str = "sync_view_incoming";
+const OUTGOING_ID: &str = "sync_view_outgoing";
+
+/// The commits to show in the two panels.
+struct Sync {
+    description: String,
+    incoming: Vec<LogEntry>,
+    outgoing: Vec<LogEntry>,
+}
+
+/// Shows the commits on the upstream branch that are not on the current
+/// branch (incoming) beside those on the current branch that have not been
+/// pushed (outgoing). The panels are updated after each fetch while the view
+/// is open.
+pub fn show(siv: &mut Cursive) {
+    if siv.find_id::<TextView>(HEADER_ID).is_none() {
+        let panel = |title: &str, id: &'static str| {
+            let mut list = SelectView::<Oid>::new();
+            list.set_on_submit(|s, oid: &Oid| super::show_commit(s, *oid));
+            let list = OnEventView::new(list.with_id(id).scrollable())
+                .on_event('l', move |s| show_in_log(s, id));
+            Panel::new(LinearLayout::vertical()
+                .child(TextView::new(title))
+                .child(list))
+                .full_screen()
+        };
+
+        let view = OnEventView::new(LinearLayout::vertical()
+                .child(TextView::new("").with_id(HEADER_ID))
+                .child(LinearLayout::horizontal()
+                    .child(panel("Incoming (on the upstream, not here)", INCOMING_ID))
+                    .child(panel("Outgoing (here, not pushed)", OUTGOING_ID))))
+            .on_event('q', |s| { s.pop_layer(); });
+        super::navigation::open(siv, "Incoming and Outgoing", view, true);
+    }
+    refresh(siv);
+}
+
+/// Reloads the panels, if the view is open.
+pub fn refresh(siv: &mut Cursive) {
+    if siv.find_id::<TextView>(HEADER_ID).is_none() {
+        return;
+    }
+
+    let sync = match super::with_current_repo(siv, load) {
+        Some(Ok(sync)) => sync,
+        Some(Err(e)) => return super::report_result::<::git2::Error>(siv, "Load incoming and outgoing commits", Err(e)),
+        None => return,
+    };
+
+    let header = format!("{}\n{}\n[Enter] show commit  [l] show in log  [q] close",
+                         super::navigation::breadcrumb(siv), sync.description);
+    siv.call_on_id(HEADER_ID, |v: &mut TextView| v.set_content(header));
+    fill(siv, INCOMING_ID, sync.incoming);
+    fill(siv, OUTGOING_ID, sync.outgoing);
+}
+
+fn load(repo: &Repository) -> Result<Sync, ::git2::Error> {
+    let branch = match remotes::current_branch_name(repo) {
+        Some(branch) => branch,
+        None => return Ok(Sync { description: "HEAD is not on a branch.".to_string(), incoming: Vec::new(), outgoing: Vec::new() }),
+    };
+    let upstream = match remotes::upstream_of(repo, &branch) {
+        Some(upstream) => upstream,
+        None => return Ok(Sync { description: format!("'{}' has no upstream.", branch), incoming: Vec::new(), outgoing: Vec::new() }),
+    };
+
+    let mailmap = super::identity_mailmap(repo);
+    let incoming = history::entries(repo, &remotes::incoming_commits(repo, &branch, &upstream)?, &mailmap)?;
+    let outgoing = history::entries(repo, &remotes::outgoing_commits(repo, &branch, &upstream)?, &mailmap)?;
+    let description = format!("'{}' and '{}/{}': {} incoming, {} outgoing, as of the last fetch.",
+                              branch, upstream.remote, upstream.branch, incoming.len(), outgoing.len());
+    Ok(Sync { description, incoming, outgoing })
+}
+
+fn fill(siv: &mut Cursive, id: &str, entries: Vec<LogEntry>) {
+    siv.call_on_id(id, |v: &mut SelectView<Oid>| {
+        v.clear();
+        for entry in entries {
+            v.add_item(format!("{:.7} {} ({})", entry.oid.to_string(), entry.summary, entry.author), entry.oid);
+        }
+    });
+}
+
+/// Opens the log view at the commit selected in the panel `id`.
+fn show_in_log(siv: &mut Cursive, id: &str) {
+    let oid = siv.call_on_id(id, |v: &mut SelectView<Oid>| v.selection().map(|oid| *oid)).and_then(|o| o);
+    if let Some(oid) = oid {
+        super::log_view::show(siv);
+        super::log_view::goto_commit(siv, oid);
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::{self, Write};
+use std::panic;
+#[cfg(unix)]
+use std::process::{self, Command, Stdio};
+use std::thread;
+use cursive::Cursive;
+#[cfg(unix)]
+use signing::{self, TerminalHandoff};
+#[cfg(unix)]
+use libc::{self, SIGINT, SIGTERM, SIGTSTP};
+#[cfg(unix)]
+use signal_hook::iterator::Signals;
+
+/// The mouse reporting modes cursive turns on: clicks, drags, and the
+/// urxvt and SGR encodings of positions.
+#[cfg(unix)]
+const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+/// Makes sure a panic leaves the terminal usable. The hook runs before the
+/// stack unwinds, while cursive still has the terminal in raw mode on the
+/// alternate screen, so without this the panic message is wiped out when the
+/// screen is torn down and the shell is left unusable. `save_state` is
+/// called first, since a panic on the main thread ends oaf without the
+/// usual saving on the way out. Panics on background threads do not end the
+/// program, so they are only logged rather than printed over the UI.
+pub fn install_panic_hook(save_state: fn()) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        error!("oaf panicked: {}", info);
+        if thread::current().name() == Some("main") {
+            save_state();
+            restore();
+            default_hook(info);
+        }
+    }));
+}
+
+/// Handles the signals that would otherwise kill or stop oaf with the
+/// terminal still in cursive's hands. SIGINT and SIGTERM ask the UI to quit,
+/// so that it shuts down normally and saves its files; if the UI does not
+/// respond and the signal comes again, oaf restores the terminal and exits
+/// at once. SIGTSTP (Ctrl-Z) gives the terminal back to the shell while oaf
+/// is stopped and takes it again on 'fg'.
+#[cfg(unix)]
+pub fn handle_signals(siv: &Cursive) {
+    let signals = match Signals::new(&[SIGINT, SIGTERM, SIGTSTP]) {
+        Ok(signals) => signals,
+        Err(e) => return warn!("Cannot install signal handlers, ignoring. Error = {}", e),
+    };
+
+    let sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let mut quit_requested = false;
+        for signal in signals.forever() {
+            if signal == SIGTSTP {
+                suspend();
+                let _ = sink.send(Box::new(|s: &mut Cursive| s.clear()));
+            } else if quit_requested {
+                warn!("Received signal {} again, exiting immediately", signal);
+                restore();
+                process::exit(128 + signal);
+            } else {
+                info!("Received signal {}, quitting", signal);
+                quit_requested = true;
+                let _ = sink.send(Box::new(|s: &mut Cursive| s.quit()));
+            }
+        }
+    });
+}
+
+/// On Windows the console turns Ctrl-C into a key press while cursive has
+/// it, and there is no Ctrl-Z, so there is nothing to do.
+#[cfg(not(unix))]
+pub fn handle_signals(_siv: &Cursive) {}
+
+/// Puts the terminal back as the shell expects it: mouse reporting off, the
+/// cursor visible, the normal screen showing and line editing and echo on.
+/// Windows consoles understand the escape sequences but have no stty; their
+/// input mode is restored by the backend.
+pub fn restore() {
+    print!("{}\x1b[0m\x1b[?25h\x1b[?1049l", MOUSE_OFF);
+    let _ = io::stdout().flush();
+    #[cfg(unix)]
+    let _ = Command::new("stty").arg("sane").stdin(Stdio::inherit()).status();
+}
+
+/// Stops the process with the terminal restored, returning once the shell
+/// has continued it, with the terminal set up for cursive again.
+#[cfg(unix)]
+fn suspend() {
+    info!("Suspending");
+    restore();
+    unsafe {
+        libc::raise(libc::SIGSTOP);
+    }
+
+    info!("Resuming");
+    take_back();
+}
+
+/// Sets the terminal up for cursive again after `restore`. The caller must
+/// have cursive clear the screen so that it is all redrawn.
+#[cfg(unix)]
+fn take_back() {
+    let _ = Command::new("stty").args(&["-icanon", "-echo"]).stdin(Stdio::inherit()).status();
+    print!("\x1b[?1049h\x1b[?25l{}", MOUSE_ON);
+    let _ = io::stdout().flush();
+}
+
+/// Lets gpg have the terminal when it needs to ask for a passphrase while
+/// signing, so that pinentry-curses can draw its prompt on the normal screen
+/// rather than over the UI, which is redrawn afterwards. With the termion
+/// backend, cursive's input thread keeps reading the terminal, so some key
+/// presses may go to oaf rather than pinentry; a graphical pinentry or the
+/// ncurses backend avoids that.
+#[cfg(unix)]
+pub fn hand_off_for_signing(siv: &Cursive) {
+    let sink = siv.cb_sink().clone();
+    signing::set_terminal_handoff(Some(TerminalHandoff {
+        release: Box::new(|| {
+            info!("Releasing the terminal for gpg");
+            restore();
+            println!("oaf: gpg is signing; enter the passphrase if it asks for one.");
+        }),
+        reclaim: Box::new(move || {
+            info!("Taking the terminal back from gpg");
+            take_back();
+            let _ = sink.send(Box::new(|s: &mut Cursive| s.clear()));
+        }),
+    }));
+}
+
+/// On Windows gpg's pinentry is a separate window, so the terminal is never
+/// needed.
+#[cfg(not(unix))]
+pub fn hand_off_for_signing(_siv: &Cursive) {}
@@ -0,0 +1,165 @@
+//! A cursive backend for tests. It records what is drawn in a grid of
+//! characters instead of writing to a terminal, so that views can be driven
+//! with events and what they show checked, without a terminal.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crossbeam_channel::{Receiver, Sender};
+use cursive::Cursive;
+use cursive::backend::{self, InputRequest};
+use cursive::event::Event;
+use cursive::theme::{Color, ColorPair, Effect};
+use cursive::vec::Vec2;
+use unicode_width::UnicodeWidthChar;
+
+type Grid = Rc<RefCell<Vec<Vec<char>>>>;
+
+struct Backend {
+    size: Vec2,
+    grid: Grid,
+}
+
+impl backend::Backend for Backend {
+    fn finish(&mut self) {}
+
+    fn refresh(&mut self) {}
+
+    fn has_colors(&self) -> bool {
+        false
+    }
+
+    fn screen_size(&self) -> Vec2 {
+        self.size
+    }
+
+    // Input comes from `TestUi::press`, which hands events straight to
+    // cursive. All this has to do is stop the event loop from waiting:
+    // a blocking request gets a refresh and a peek gets nothing.
+    fn start_input_thread(&mut self, event_sink: Sender<Option<Event>>, input_requests: Receiver<InputRequest>) {
+        thread::spawn(move || {
+            for request in input_requests {
+                match request {
+                    InputRequest::Block => event_sink.send(Some(Event::Refresh)),
+                    InputRequest::Peek => event_sink.send(None),
+                }
+            }
+        });
+    }
+
+    fn print_at(&self, pos: Vec2, text: &str) {
+        let mut grid = self.grid.borrow_mut();
+        let row = match grid.get_mut(pos.y) {
+            Some(row) => row,
+            None => return,
+        };
+
+        let mut x = pos.x;
+        for c in text.chars() {
+            if x >= row.len() {
+                break;
+            }
+            row[x] = c;
+            x += c.width().unwrap_or(0).max(1);
+        }
+    }
+
+    fn clear(&self, _: Color) {
+        for row in self.grid.borrow_mut().iter_mut() {
+            for c in row.iter_mut() {
+                *c = ' ';
+            }
+        }
+    }
+
+    fn set_color(&self, colors: ColorPair) -> ColorPair {
+        colors
+    }
+
+    fn set_effect(&self, _: Effect) {}
+
+    fn unset_effect(&self, _: Effect) {}
+}
+
+/// A Cursive instance on the test backend, with helpers to send it keys and
+/// read back the screen.
+pub struct TestUi {
+    pub siv: Cursive,
+    grid: Grid,
+}
+
+impl TestUi {
+    pub fn new(width: usize, height: usize) -> Self {
+        let grid: Grid = Rc::new(RefCell::new(vec![vec![' '; width]; height]));
+        let backend_grid = grid.clone();
+        let siv = Cursive::new(move || Box::new(Backend { size: Vec2::new(width, height), grid: backend_grid }));
+        TestUi { siv, grid }
+    }
+
+    /// Lays out and draws the screen, and runs any callbacks that have been
+    /// queued, e.g. by background threads.
+    pub fn render(&mut self) -> &mut Self {
+        self.siv.step();
+        self.siv.step();
+        self
+    }
+
+    /// Keeps drawing until `text` is on the screen, for views that are
+    /// filled in by a background thread. Gives up after a few seconds.
+    pub fn wait_for(&mut self, text: &str) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !self.shows(text) {
+            if Instant::now() > deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+            self.render();
+        }
+        true
+    }
+
+    /// Sends each event to the UI in turn, then draws the result.
+    pub fn press<I, E>(&mut self, events: I) -> &mut Self
+        where I: IntoIterator<Item = E>, E: Into<Event>
+    {
+        for event in events {
+            self.siv.on_event(event.into());
+        }
+        self.render()
+    }
+
+    /// The screen, one string per row with trailing spaces removed.
+    pub fn lines(&self) -> Vec<String> {
+        self.grid.borrow().iter()
+            .map(|row| row.iter().collect::<String>().trim_end().to_string())
+            .collect()
+    }
+
+    pub fn screen(&self) -> String {
+        self.lines().join("\n")
+    }
+
+    pub fn shows(&self, text: &str) -> bool {
+        self.lines().iter().any(|line| line.contains(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursive::event::Key;
+    use cursive::views::{Dialog, TextView};
+
+    #[test]
+    fn records_what_is_drawn() {
+        let mut ui = TestUi::new(40, 10);
+        ui.siv.add_layer(Dialog::around(TextView::new("Hello")).title("Greeting").dismiss_button("Close"));
+        ui.render();
+        assert!(ui.shows("Greeting"));
+        assert!(ui.shows("Hello"));
+
+        ui.press(vec![Key::Enter]);
+        assert!(!ui.shows("Hello"));
+    }
+}
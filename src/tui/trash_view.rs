@@ -0,0 +1,63 @@
+use std::io;
+use cursive::Cursive;
+use cursive::traits::{Boxable, Identifiable, Scrollable};
+use cursive::views::{Dialog, SelectView};
+use text_width::{self, Ellipsis};
+use trash::{self, TrashEntry};
+
+const LIST_ID: &str = "trash_list";
+
+/// Lists the files oaf has moved to the trash. Choosing one puts it back.
+pub fn show(siv: &mut Cursive) {
+    let entries = match trash::list(::PATHS.trash_dir()) {
+        Ok(entries) => entries,
+        Err(e) => return super::report_result::<io::Error>(siv, "List trash", Err(e)),
+    };
+
+    if entries.is_empty() {
+        return siv.add_layer(Dialog::info("The trash is empty."));
+    }
+
+    let mut list = SelectView::<TrashEntry>::new();
+    for entry in entries {
+        let label = format!("{}  {} {}", entry.trashed, text_width::fit(&entry.reason, 16, Ellipsis::End),
+                            entry.original_path().display());
+        list.add_item(label, entry);
+    }
+    list.set_on_submit(|s, entry: &TrashEntry| restore(s, entry.clone()));
+
+    siv.add_layer(Dialog::around(list.with_id(LIST_ID).scrollable().max_height(20))
+        .title("Trash")
+        .button("Restore", |s| {
+            let selected = s.call_on_id(LIST_ID, |v: &mut SelectView<TrashEntry>| v.selection());
+            if let Some(Some(entry)) = selected {
+                restore(s, (*entry).clone());
+            }
+        })
+        .button("Empty Trash", |s| {
+            super::dialogs::confirm(s, "Empty Trash", "Permanently delete everything in the trash?", |s| {
+                s.pop_layer();
+                let result = trash::empty(::PATHS.trash_dir()).map(|n| format!("Deleted {} files from the trash.", n));
+                super::report_result(s, "Empty trash", result);
+            });
+        })
+        .dismiss_button("Close"));
+}
+
+/// Restores `entry`, asking first if that would replace a file.
+fn restore(siv: &mut Cursive, entry: TrashEntry) {
+    let target = entry.original_path();
+    let do_restore = move |s: &mut Cursive, overwrite: bool| {
+        s.pop_layer();
+        let result = trash::restore(::PATHS.trash_dir(), &entry, overwrite)
+            .map(|_| format!("Restored '{}'.", entry.original_path().display()));
+        super::report_result(s, "Restore from trash", result);
+    };
+
+    if target.exists() {
+        let text = format!("'{}' exists. Replace it? The current file will be moved to the trash.", target.display());
+        super::dialogs::confirm(siv, "Restore", text, move |s| do_restore(s, true));
+    } else {
+        do_restore(siv, false);
+    }
+}
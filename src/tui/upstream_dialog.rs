@@ -0,0 +1,47 @@
+use cursive::Cursive;
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, SelectView, TextView};
+use remotes::UpstreamSpec;
+
+const REMOTE_ID: &str = "upstream_dialog_remote";
+const BRANCH_ID: &str = "upstream_dialog_branch";
+
+/// Shows a dialog asking the user where `local_branch` should be pushed to.
+/// The remote and branch name are pre-filled from `default`. When the user
+/// accepts, the dialog is closed and `on_accept` is called with their choice.
+pub fn show<F>(siv: &mut Cursive, local_branch: &str, remotes: &[String], default: &UpstreamSpec, on_accept: F)
+    where F: Fn(&mut Cursive, UpstreamSpec) + 'static
+{
+    let mut remote_select = SelectView::new().popup();
+    for (i, remote) in remotes.iter().enumerate() {
+        remote_select.add_item(remote.clone(), remote.clone());
+        if *remote == default.remote {
+            remote_select.set_selection(i);
+        }
+    }
+
+    let layout = LinearLayout::vertical()
+        .child(TextView::new(format!("The branch '{}' has no upstream. Push it to:", local_branch)))
+        .child(LinearLayout::horizontal()
+            .child(TextView::new("Remote: "))
+            .child(remote_select.with_id(REMOTE_ID)))
+        .child(LinearLayout::horizontal()
+            .child(TextView::new("Branch: "))
+            .child(EditView::new().content(default.branch.clone()).with_id(BRANCH_ID).min_width(30)));
+
+    siv.add_layer(Dialog::around(layout)
+        .title("Set Upstream")
+        .button("Push", move |s| {
+            let remote = s.call_on_id(REMOTE_ID, |v: &mut SelectView<String>| v.selection());
+            let branch = s.call_on_id(BRANCH_ID, |v: &mut EditView| v.get_content());
+
+            match (remote.and_then(|r| r), branch) {
+                (Some(remote), Some(branch)) if !branch.trim().is_empty() => {
+                    s.pop_layer();
+                    on_accept(s, UpstreamSpec::new(remote.as_str(), branch.trim()));
+                },
+                _ => s.add_layer(Dialog::info("Please choose a remote and enter a branch name.")),
+            }
+        })
+        .dismiss_button("Cancel"));
+}
@@ -0,0 +1,362 @@
+use std::rc::Rc;
+use cursive::{Cursive, Printer};
+use cursive::direction::Direction;
+use cursive::event::{Callback, Event, EventResult, Key, MouseButton, MouseEvent};
+use cursive::theme::{ColorStyle, Effect};
+use cursive::vec::Vec2;
+use cursive::view::View;
+use text_width::{self, Ellipsis};
+
+/// A selectable list that only formats the rows that are on screen, for lists
+/// of log entries or files that may run to hundreds of thousands of items.
+/// A SelectView turns every item into a label up front and asks for a line
+/// per item from its ScrollView; this keeps the items as they are, asks for
+/// whatever space it is given and scrolls itself.
+pub struct VirtualList<T> {
+    items: Vec<T>,
+    label: Box<dyn Fn(&T) -> String>,
+    /// Gives the char indices of the label to emphasise, e.g. those that
+    /// matched a filter.
+    highlight: Option<Box<dyn Fn(&T) -> Vec<usize>>>,
+    /// Makes a row that is too wide fit in the given number of columns.
+    fit: Option<Box<dyn Fn(&T, usize) -> String>>,
+    selected: usize,
+    /// The index of the first row on screen.
+    top: usize,
+    /// The number of rows on screen, as of the last layout.
+    height: usize,
+    /// Whether the last row on screen shows the whole of the selected row,
+    /// because it has been shortened to fit.
+    footer: bool,
+    on_select: Option<Rc<dyn Fn(&mut Cursive, &T)>>,
+    on_submit: Option<Rc<dyn Fn(&mut Cursive, &T)>>,
+}
+
+impl<T: Clone + 'static> VirtualList<T> {
+    /// Creates an empty list whose rows are made by `label`.
+    pub fn new<F>(label: F) -> Self
+        where F: Fn(&T) -> String + 'static
+    {
+        VirtualList {
+            items: Vec::new(),
+            label: Box::new(label),
+            highlight: None,
+            fit: None,
+            selected: 0,
+            top: 0,
+            height: 1,
+            footer: false,
+            on_select: None,
+            on_submit: None,
+        }
+    }
+
+    /// Replaces the function that makes the rows, e.g. when the columns
+    /// to show have changed.
+    pub fn set_label<F>(&mut self, label: F)
+        where F: Fn(&T) -> String + 'static
+    {
+        self.label = Box::new(label);
+    }
+
+    /// Sets the function that picks out the characters of each row to
+    /// emphasise.
+    pub fn set_highlight<F>(&mut self, highlight: F)
+        where F: Fn(&T) -> Vec<usize> + 'static
+    {
+        self.highlight = Some(Box::new(highlight));
+    }
+
+    /// Sets how a row that is too wide for the list is shortened, given the
+    /// columns there are. While the selected row is shortened, the whole of
+    /// it is shown at the bottom of the list.
+    pub fn set_fit<F>(&mut self, fit: F)
+        where F: Fn(&T, usize) -> String + 'static
+    {
+        self.fit = Some(Box::new(fit));
+    }
+
+    /// Replaces the items, keeping the selected row where possible.
+    pub fn set_items(&mut self, items: Vec<T>) {
+        self.items = items;
+        self.selected = self.selected.min(self.items.len().saturating_sub(1));
+        self.scroll_to_selection();
+    }
+
+    /// Called when the selection moves, with the newly selected item.
+    pub fn set_on_select<F>(&mut self, cb: F)
+        where F: Fn(&mut Cursive, &T) + 'static
+    {
+        self.on_select = Some(Rc::new(cb));
+    }
+
+    /// Called when Enter is pressed, or an item is clicked, with that item.
+    pub fn set_on_submit<F>(&mut self, cb: F)
+        where F: Fn(&mut Cursive, &T) + 'static
+    {
+        self.on_submit = Some(Rc::new(cb));
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn selection(&self) -> Option<&T> {
+        self.items.get(self.selected)
+    }
+
+    /// Selects the item at `index` and scrolls it into view. Returns the
+    /// on_select callback to run, if there is one.
+    pub fn set_selection(&mut self, index: usize) -> Callback {
+        self.selected = index.min(self.items.len().saturating_sub(1));
+        self.scroll_to_selection();
+        match (self.on_select.clone(), self.selection().cloned()) {
+            (Some(cb), Some(item)) => Callback::from_fn(move |s| cb(s, &item)),
+            _ => Callback::dummy(),
+        }
+    }
+
+    /// The index of the first item for which `predicate` is true.
+    pub fn find<P>(&self, predicate: P) -> Option<usize>
+        where P: Fn(&T) -> bool
+    {
+        self.items.iter().position(predicate)
+    }
+
+    fn scroll_to_selection(&mut self) {
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + self.height {
+            self.top = self.selected + 1 - self.height;
+        }
+        self.top = self.top.min(self.items.len().saturating_sub(self.height));
+    }
+
+    fn move_selection(&mut self, delta: isize) -> EventResult {
+        if self.items.is_empty() {
+            return EventResult::Ignored;
+        }
+
+        let index = (self.selected as isize + delta).max(0) as usize;
+        let index = index.min(self.items.len() - 1);
+        if index == self.selected {
+            return EventResult::Ignored;
+        }
+        EventResult::Consumed(Some(self.set_selection(index)))
+    }
+
+    fn submit(&self) -> EventResult {
+        match (self.on_submit.clone(), self.selection().cloned()) {
+            (Some(cb), Some(item)) => EventResult::with_cb(move |s| cb(s, &item)),
+            _ => EventResult::Ignored,
+        }
+    }
+
+    /// Whether the selected row is too wide to show in full.
+    fn selection_is_shortened(&self, width: usize) -> bool {
+        match (&self.fit, self.selection()) {
+            (&Some(_), Some(item)) => text_width::width(&(self.label)(item)) > width,
+            _ => false,
+        }
+    }
+
+    /// The row under a mouse position, if it is over an item.
+    fn row_at(&self, position: Vec2, offset: Vec2) -> Option<usize> {
+        let position = position.checked_sub(offset)?;
+        let index = self.top + position.y;
+        if position.y < self.height && index < self.items.len() { Some(index) } else { None }
+    }
+}
+
+impl<T: Clone + 'static> View for VirtualList<T> {
+    fn draw(&self, printer: &Printer) {
+        let scrollbar = self.items.len() > self.height;
+        let width = if scrollbar { printer.size.x.saturating_sub(1) } else { printer.size.x };
+
+        let end = (self.top + self.height).min(self.items.len());
+        for (row, index) in (self.top..end).enumerate() {
+            printer.offset((0, row)).with_selection(index == self.selected, |printer| {
+                let item = &self.items[index];
+                let label = (self.label)(item);
+                printer.print_hline((0, 0), width, " ");
+                match self.fit {
+                    Some(ref fit) if text_width::width(&label) > width => printer.print((0, 0), &fit(item, width)),
+                    _ => {
+                        printer.print((0, 0), &label);
+                        if let Some(ref highlight) = self.highlight {
+                            draw_highlights(printer, &label, &highlight(item));
+                        }
+                    },
+                }
+            });
+        }
+
+        if let (true, Some(item)) = (self.footer, self.selection()) {
+            let label = text_width::truncate(&(self.label)(item), printer.size.x, Ellipsis::Start);
+            printer.with_color(ColorStyle::secondary(), |printer| printer.print((0, self.height), &label));
+        }
+
+        if scrollbar && printer.size.x > 0 {
+            let x = printer.size.x - 1;
+            let thumb_height = (self.height * self.height / self.items.len()).max(1);
+            let max_top = self.items.len() - self.height;
+            let thumb_top = (self.height - thumb_height) * self.top / max_top.max(1);
+            printer.print_vline((x, 0), self.height, "|");
+            printer.with_color(ColorStyle::highlight(), |printer| {
+                printer.print_vline((x, thumb_top), thumb_height, " ");
+            });
+        }
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        let scrollbar = self.items.len() > size.y;
+        let width = if scrollbar { size.x.saturating_sub(1) } else { size.x };
+        self.footer = size.y > 1 && self.selection_is_shortened(width);
+        self.height = if self.footer { size.y - 1 } else { size.y.max(1) };
+        self.scroll_to_selection();
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        // Take whatever is offered, as working out the widest row would mean
+        // formatting every item.
+        let footer = if self.selection_is_shortened(constraint.x) { 1 } else { 0 };
+        Vec2::new(constraint.x, constraint.y.min(self.items.len().max(1) + footer))
+    }
+
+    fn take_focus(&mut self, _: Direction) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        let page = self.height.saturating_sub(1).max(1) as isize;
+        match event {
+            Event::Key(Key::Up) => self.move_selection(-1),
+            Event::Key(Key::Down) => self.move_selection(1),
+            Event::Key(Key::PageUp) => self.move_selection(-page),
+            Event::Key(Key::PageDown) => self.move_selection(page),
+            Event::Key(Key::Home) => self.move_selection(-(self.selected as isize)),
+            Event::Key(Key::End) => {
+                let last = self.items.len() as isize - 1;
+                let delta = last - self.selected as isize;
+                self.move_selection(delta)
+            },
+            Event::Key(Key::Enter) => self.submit(),
+            Event::Mouse { event: MouseEvent::WheelUp, .. } => {
+                self.top = self.top.saturating_sub(3);
+                EventResult::Consumed(None)
+            },
+            Event::Mouse { event: MouseEvent::WheelDown, .. } => {
+                self.top = (self.top + 3).min(self.items.len().saturating_sub(self.height));
+                EventResult::Consumed(None)
+            },
+            Event::Mouse { event: MouseEvent::Press(_), position, offset } => {
+                match self.row_at(position, offset) {
+                    Some(index) if index != self.selected => EventResult::Consumed(Some(self.set_selection(index))),
+                    Some(_) => EventResult::Consumed(None),
+                    None => EventResult::Ignored,
+                }
+            },
+            Event::Mouse { event: MouseEvent::Release(MouseButton::Left), position, offset } => {
+                match self.row_at(position, offset) {
+                    Some(index) if index == self.selected => self.submit(),
+                    _ => EventResult::Ignored,
+                }
+            },
+            _ => EventResult::Ignored,
+        }
+    }
+}
+
+/// Draws the chars of `label` at `positions` again, emphasised.
+fn draw_highlights(printer: &Printer, label: &str, positions: &[usize]) {
+    let mut x = 0;
+    let mut positions = positions.iter().peekable();
+    for (i, c) in label.chars().enumerate() {
+        if positions.peek() == Some(&&i) {
+            positions.next();
+            printer.with_effect(Effect::Underline, |printer| printer.print((x, 0), &c.to_string()));
+        }
+        x += text_width::width(&c.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn numbers(n: usize) -> VirtualList<usize> {
+        let mut list = VirtualList::new(|n: &usize| format!("Item {}", n));
+        list.set_items((0..n).collect());
+        list.layout(Vec2::new(20, 10));
+        list
+    }
+
+    #[test]
+    fn only_formats_the_rows_on_screen() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let mut list = numbers(100_000);
+        list.set_label(move |n: &usize| { counter.set(counter.get() + 1); n.to_string() });
+
+        let mut ui = ::tui::test_backend::TestUi::new(20, 10);
+        ui.siv.add_fullscreen_layer(list);
+        ui.render();
+        assert!(ui.shows("9"));
+        assert!(!ui.shows("10"));
+        // Once per row, for each of the steps in render.
+        assert!(calls.get() <= 20, "formatted {} rows", calls.get());
+    }
+
+    #[test]
+    fn moving_the_selection_scrolls_the_list() {
+        let mut list = numbers(100);
+        list.on_event(Event::Key(Key::PageDown));
+        assert_eq!(list.selected, 9);
+        assert_eq!(list.top, 0);
+
+        list.on_event(Event::Key(Key::Down));
+        assert_eq!(list.top, 1);
+
+        list.on_event(Event::Key(Key::End));
+        assert_eq!(list.selection(), Some(&99));
+        assert_eq!(list.top, 90);
+
+        list.on_event(Event::Key(Key::Home));
+        assert_eq!((list.selected, list.top), (0, 0));
+    }
+
+    #[test]
+    fn shortened_rows_show_in_full_at_the_bottom_when_selected() {
+        let mut list = VirtualList::new(|s: &String| s.clone());
+        list.set_fit(|s: &String, width| ::short_path::shorten(s, width));
+        list.set_items(vec!["src/tui/views/log/main.rs".to_string(), "README.md".to_string()]);
+
+        let mut ui = ::tui::test_backend::TestUi::new(20, 5);
+        ui.siv.add_fullscreen_layer(list);
+        ui.render();
+        assert_eq!(ui.lines()[0], "…/views/log/main.rs");
+        assert_eq!(ui.lines()[2], "…i/views/log/main.rs");
+
+        ui.press(vec![Key::Down]);
+        assert_eq!(ui.lines()[1], "README.md");
+        assert_eq!(ui.lines()[2], "");
+    }
+
+    #[test]
+    fn replacing_the_items_keeps_the_selection_in_range() {
+        let mut list = numbers(100);
+        list.set_selection(50);
+        list.set_items((0..20).collect());
+        assert_eq!(list.selected, 19);
+        assert_eq!(list.top, 10);
+
+        list.set_items(Vec::new());
+        assert_eq!(list.selection(), None);
+        assert!(!list.on_event(Event::Key(Key::Down)).is_consumed());
+    }
+}
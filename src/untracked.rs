@@ -0,0 +1,48 @@
+//! Actions on the untracked files in the working tree, which the status
+//! view applies to a whole directory at once.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use git2::{Error, Repository};
+use error::OafError;
+
+/// Stages the given files, as 'git add' does.
+pub fn stage(repo: &Repository, paths: &[PathBuf]) -> Result<(), OafError> {
+    let mut index = repo.index().map_err(OafError::Index)?;
+    for path in paths {
+        index.add_path(path).map_err(OafError::Index)?;
+    }
+    index.write().map_err(OafError::Index)
+}
+
+/// Adds `path` to the '.gitignore' at the root of the working tree, anchored
+/// there so that only that file or directory is ignored.
+pub fn ignore(repo: &Repository, path: &Path, is_dir: bool) -> Result<(), OafError> {
+    let workdir = repo.workdir().ok_or_else(|| OafError::Index(Error::from_str("A bare repository has no working tree.")))?;
+    let gitignore = workdir.join(".gitignore");
+    let needs_newline = fs::read(&gitignore).map(|c| !c.is_empty() && !c.ends_with(b"\n")).unwrap_or(false);
+
+    let mut file = OpenOptions::new().create(true).append(true).open(&gitignore)?;
+    if needs_newline {
+        writeln!(file)?;
+    }
+    writeln!(file, "{}", ignore_pattern(path, is_dir))?;
+    Ok(())
+}
+
+fn ignore_pattern(path: &Path, is_dir: bool) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    format!("/{}{}", path.trim_end_matches('/'), if is_dir { "/" } else { "" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignore_patterns_are_anchored() {
+        assert_eq!(ignore_pattern(Path::new("build/out"), true), "/build/out/");
+        assert_eq!(ignore_pattern(Path::new("notes.txt"), false), "/notes.txt");
+    }
+}